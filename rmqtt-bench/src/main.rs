@@ -0,0 +1,279 @@
+#![deny(unsafe_code)]
+
+use std::cell::Cell;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ntex::time::Seconds;
+use ntex::util::{ByteString, Bytes, Ready};
+use ntex_mqtt::v3::{self, codec::Publish as PublishV3, codec::SubscribeReturnCode};
+
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, Clone)]
+#[structopt(name = "rmqtt-bench", about = "MQTT v3.1.1 benchmark tool of rmqtt")]
+enum Mode {
+    ///Only connect the clients
+    Conn {
+        #[structopt(flatten)]
+        common: Common,
+    },
+    ///Publish messages
+    Pub {
+        #[structopt(flatten)]
+        common: Common,
+        ///Topic, %i is replaced with the client number
+        #[structopt(short, long, default_value = "bench/%i")]
+        topic: String,
+        ///QoS of the messages, 0 or 1
+        #[structopt(short, long, default_value = "0", possible_values = &["0", "1"])]
+        qos: u8,
+        #[structopt(short, long)]
+        retain: bool,
+        ///Payload size in bytes
+        #[structopt(short, long, default_value = "256")]
+        size: usize,
+        ///Interval between the messages of a client, in milliseconds
+        #[structopt(short = "I", long, default_value = "1000")]
+        pub_interval: u64,
+        ///Messages per client, 0 means unlimited
+        #[structopt(short = "L", long, default_value = "0")]
+        limit: usize,
+    },
+    ///Subscribe to a topic and count the received messages
+    Sub {
+        #[structopt(flatten)]
+        common: Common,
+        ///Topic filter, %i is replaced with the client number
+        #[structopt(short, long, default_value = "bench/#")]
+        topic: String,
+        #[structopt(short, long, default_value = "0", possible_values = &["0", "1", "2"])]
+        qos: u8,
+    },
+}
+
+#[derive(StructOpt, Debug, Clone)]
+struct Common {
+    ///Broker address
+    #[structopt(long, default_value = "127.0.0.1:1883")]
+    host: String,
+    ///Number of connections
+    #[structopt(short, long, default_value = "100")]
+    count: usize,
+    ///Ramp-up, interval between two connections, in milliseconds
+    #[structopt(short, long, default_value = "10")]
+    interval: u64,
+    ///Client identifier prefix, the client number is appended
+    #[structopt(long, default_value = "rmqtt-bench")]
+    prefix: String,
+    #[structopt(short, long)]
+    username: Option<String>,
+    #[structopt(short = "P", long)]
+    password: Option<String>,
+    ///Keep alive, in seconds
+    #[structopt(short, long, default_value = "300")]
+    keepalive: u16,
+}
+
+#[derive(Default)]
+struct Stats {
+    connected: Cell<u64>,
+    failed: Cell<u64>,
+    sent: Cell<u64>,
+    received: Cell<u64>,
+    //Sum of the latency of the received messages that carry a send timestamp, in milliseconds
+    latency_sum: Cell<u64>,
+    latency_count: Cell<u64>,
+}
+
+impl Stats {
+    #[inline]
+    fn incr(c: &Cell<u64>, n: u64) {
+        c.set(c.get() + n);
+    }
+
+    fn on_message(&self, payload: &Bytes) {
+        Self::incr(&self.received, 1);
+        if payload.len() >= 8 {
+            let mut ts = [0u8; 8];
+            ts.copy_from_slice(&payload[..8]);
+            let latency = timestamp_millis() - i64::from_be_bytes(ts);
+            if (0..60_000).contains(&latency) {
+                Self::incr(&self.latency_sum, latency as u64);
+                Self::incr(&self.latency_count, 1);
+            }
+        }
+    }
+
+    fn latency_avg(&self) -> f64 {
+        if self.latency_count.get() == 0 {
+            0.0
+        } else {
+            self.latency_sum.get() as f64 / self.latency_count.get() as f64
+        }
+    }
+}
+
+#[ntex::main]
+async fn main() {
+    let mode = Mode::from_args();
+    let common = match &mode {
+        Mode::Conn { common } | Mode::Pub { common, .. } | Mode::Sub { common, .. } => common.clone(),
+    };
+    let addr = match common.host.to_socket_addrs().map(|mut addrs| addrs.next()) {
+        Ok(Some(addr)) => addr,
+        _ => {
+            eprintln!("invalid broker address: {}", common.host);
+            std::process::exit(1);
+        }
+    };
+
+    let stats = Rc::new(Stats::default());
+    ntex::rt::spawn(report(stats.clone()));
+    ntex::rt::spawn(ramp_up(mode, common.clone(), addr, stats.clone()));
+
+    let _ = tokio::signal::ctrl_c().await;
+    println!(
+        "total, connected: {}, failed: {}, sent: {}, received: {}, latency avg: {:.2}ms",
+        stats.connected.get(),
+        stats.failed.get(),
+        stats.sent.get(),
+        stats.received.get(),
+        stats.latency_avg()
+    );
+}
+
+async fn ramp_up(mode: Mode, common: Common, addr: SocketAddr, stats: Rc<Stats>) {
+    for no in 0..common.count {
+        if no > 0 {
+            ntex::time::sleep(Duration::from_millis(common.interval)).await;
+        }
+        ntex::rt::spawn(run_client(mode.clone(), common.clone(), addr, no, stats.clone()));
+    }
+}
+
+async fn run_client(mode: Mode, common: Common, addr: SocketAddr, no: usize, stats: Rc<Stats>) {
+    let mut builder = v3::client::MqttConnector::new(addr)
+        .client_id(ByteString::from(format!("{}-{}", common.prefix, no)))
+        .keep_alive(Seconds(common.keepalive))
+        .clean_session();
+    if let Some(username) = common.username.as_ref() {
+        builder = builder.username(username.clone());
+    }
+    if let Some(password) = common.password.as_ref() {
+        builder = builder.password(Bytes::from(password.clone()));
+    }
+
+    let c = match builder.connect().await {
+        Ok(c) => c,
+        Err(e) => {
+            Stats::incr(&stats.failed, 1);
+            eprintln!("client {} connect error, {:?}", no, e);
+            return;
+        }
+    };
+    Stats::incr(&stats.connected, 1);
+
+    let sink = c.sink();
+    let ev_stats = stats.clone();
+    ntex::rt::spawn(async move {
+        let _ = c
+            .start(move |control: v3::client::ControlMessage<()>| match control {
+                v3::client::ControlMessage::Publish(publish) => {
+                    ev_stats.on_message(&publish.packet().payload);
+                    Ready::Ok(publish.ack())
+                }
+                v3::client::ControlMessage::Error(msg) => Ready::Ok(msg.ack()),
+                v3::client::ControlMessage::ProtocolError(msg) => Ready::Ok(msg.ack()),
+                v3::client::ControlMessage::PeerGone(msg) => {
+                    ev_stats.connected.set(ev_stats.connected.get().saturating_sub(1));
+                    Ready::Ok(msg.ack())
+                }
+                v3::client::ControlMessage::Closed(msg) => Ready::Ok(msg.ack()),
+            })
+            .await;
+    });
+
+    match mode {
+        Mode::Conn { .. } => {}
+        Mode::Sub { topic, qos, .. } => {
+            let topic_filter = ByteString::from(topic.replace("%i", &no.to_string()));
+            match sink.subscribe().topic_filter(topic_filter, to_qos(qos)).send().await {
+                Ok(rets) if !rets.iter().any(|r| matches!(r, SubscribeReturnCode::Failure)) => {}
+                Ok(_) => eprintln!("client {} subscribe failure", no),
+                Err(e) => eprintln!("client {} subscribe error, {:?}", no, e),
+            }
+        }
+        Mode::Pub { topic, qos, retain, size, pub_interval, limit, .. } => {
+            let topic = ByteString::from(topic.replace("%i", &no.to_string()));
+            let qos = to_qos(qos);
+            let mut n = 0;
+            while limit == 0 || n < limit {
+                if !sink.is_open() {
+                    break;
+                }
+                let p = PublishV3 {
+                    dup: false,
+                    retain,
+                    qos,
+                    topic: topic.clone(),
+                    packet_id: None,
+                    payload: payload(size),
+                };
+                let res = if matches!(qos, v3::QoS::AtMostOnce) {
+                    sink.publish_pkt(p).send_at_most_once().map_err(|e| format!("{:?}", e))
+                } else {
+                    sink.publish_pkt(p).send_at_least_once().await.map_err(|e| format!("{:?}", e))
+                };
+                match res {
+                    Ok(()) => Stats::incr(&stats.sent, 1),
+                    Err(e) => eprintln!("client {} publish error, {}", no, e),
+                }
+                n += 1;
+                ntex::time::sleep(Duration::from_millis(pub_interval)).await;
+            }
+        }
+    }
+}
+
+//The payload starts with the send timestamp, so that subscribers can measure the latency
+fn payload(size: usize) -> Bytes {
+    let mut payload = vec![b'x'; size];
+    if size >= 8 {
+        payload[..8].copy_from_slice(&timestamp_millis().to_be_bytes());
+    }
+    Bytes::from(payload)
+}
+
+#[inline]
+fn timestamp_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default()
+}
+
+#[inline]
+fn to_qos(qos: u8) -> v3::QoS {
+    match qos {
+        0 => v3::QoS::AtMostOnce,
+        1 => v3::QoS::AtLeastOnce,
+        _ => v3::QoS::ExactlyOnce,
+    }
+}
+
+//Prints the connection count and the message rates every second
+async fn report(stats: Rc<Stats>) {
+    let (mut sent, mut received) = (0, 0);
+    loop {
+        ntex::time::sleep(Duration::from_secs(1)).await;
+        println!(
+            "connected: {}, failed: {}, sent: {}/s, received: {}/s, latency avg: {:.2}ms",
+            stats.connected.get(),
+            stats.failed.get(),
+            stats.sent.get() - sent,
+            stats.received.get() - received,
+            stats.latency_avg()
+        );
+        sent = stats.sent.get();
+        received = stats.received.get();
+    }
+}
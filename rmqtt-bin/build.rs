@@ -26,10 +26,22 @@ fn plugins(decoded: &toml::Value) {
             let name = cfg.get("name").and_then(|v| v.as_str()).unwrap_or(id);
             let default_startup = cfg.get("default_startup").and_then(|v| v.as_bool()).unwrap_or(false);
             let immutable = cfg.get("immutable").and_then(|v| v.as_bool()).unwrap_or(false);
+            let phase = cfg.get("phase").and_then(|v| v.as_str()).map(|phase| match phase {
+                "pre-storage" => "PreStorage",
+                "post-storage" => "PostStorage",
+                "post-listeners" => "PostListeners",
+                _ => panic!("{} unknown plugin phase: {}", id, phase),
+            });
             println!(
-                "plugin_id: {}, default_startup: {}, immutable: {}, name: {}",
-                plugin_id, default_startup, immutable, name
+                "plugin_id: {}, default_startup: {}, immutable: {}, phase: {:?}, name: {}",
+                plugin_id, default_startup, immutable, phase, name
             );
+            if let Some(phase) = phase {
+                inits.push(format!(
+                    "    rmqtt::Runtime::instance().plugins.set_phase(r#\"{}\"#, rmqtt::plugin::Phase::{});",
+                    name, phase
+                ));
+            }
             // Use the extracted data to generate Rust code and add it to the inits vector
             inits.push(format!(
                 "    {}::register(rmqtt::Runtime::instance(), r#\"{}\"#, {} || default_startups.contains(&String::from(r#\"{}\"#)), {}).await.map_err(|e| format!(r#\"Failed to register '{}' plug-in, {{}} \"#, e.to_string()))?;",
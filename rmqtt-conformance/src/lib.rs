@@ -0,0 +1,186 @@
+//!Raw MQTT client for the conformance tests. Packets are encoded by hand so that the tests control
+//!every byte sent to the broker, including malformed packets.
+//!
+//!The tests of each test binary share one in-process broker, run them with `cargo test -p rmqtt-conformance`.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use rmqtt::testing::TestBroker;
+
+pub mod packet;
+
+pub const CONNECT: u8 = 1;
+pub const CONNACK: u8 = 2;
+pub const PUBLISH: u8 = 3;
+pub const PUBACK: u8 = 4;
+pub const PUBREC: u8 = 5;
+pub const PUBREL: u8 = 6;
+pub const PUBCOMP: u8 = 7;
+pub const SUBSCRIBE: u8 = 8;
+pub const SUBACK: u8 = 9;
+pub const UNSUBSCRIBE: u8 = 10;
+pub const UNSUBACK: u8 = 11;
+pub const PINGREQ: u8 = 12;
+pub const PINGRESP: u8 = 13;
+pub const DISCONNECT: u8 = 14;
+
+const RECV_TIMEOUT: Duration = Duration::from_secs(3);
+
+///Address of the broker shared by the tests of a test binary
+pub fn broker() -> SocketAddr {
+    TestBroker::start().expect("test broker start failed").addr()
+}
+
+///Control packet, the body is the variable header and the payload
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub typ: u8,
+    pub flags: u8,
+    pub body: Bytes,
+}
+
+impl Packet {
+    #[inline]
+    pub fn packet_id(&self) -> u16 {
+        u16::from_be_bytes([self.body[0], self.body[1]])
+    }
+
+    ///Decodes a PUBLISH packet
+    pub fn publish(&self, v5: bool) -> Publish {
+        assert_eq!(self.typ, PUBLISH, "not a PUBLISH packet: {:?}", self);
+        let qos = (self.flags >> 1) & 0b11;
+        let mut body = self.body.clone();
+        let topic = packet::get_string(&mut body);
+        let packet_id = if qos > 0 { Some(body.get_u16()) } else { None };
+        let user_properties = if v5 { packet::get_properties(&mut body) } else { Vec::new() };
+        Publish { topic, qos, retain: self.flags & 1 == 1, packet_id, payload: body, user_properties }
+    }
+
+    ///Return codes of a SUBACK packet
+    pub fn suback_codes(&self, v5: bool) -> Vec<u8> {
+        assert_eq!(self.typ, SUBACK, "not a SUBACK packet: {:?}", self);
+        let mut body = self.body.slice(2..);
+        if v5 {
+            packet::get_properties(&mut body);
+        }
+        body.to_vec()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Publish {
+    pub topic: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub packet_id: Option<u16>,
+    pub payload: Bytes,
+    pub user_properties: Vec<(String, String)>,
+}
+
+pub struct Client {
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl Client {
+    pub async fn connect(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self { stream: TcpStream::connect(addr).await?, buf: BytesMut::with_capacity(1024) })
+    }
+
+    ///Connects and completes a MQTT 3.1.1 handshake, returns the client and the CONNACK
+    pub async fn handshake(addr: SocketAddr, client_id: &str, clean_session: bool) -> (Self, Packet) {
+        let mut c = Self::connect(addr).await.expect("tcp connect failed");
+        c.send(&packet::connect(client_id, clean_session, 60)).await.expect("send CONNECT failed");
+        let connack = c.expect(CONNACK).await;
+        assert_eq!(connack.body[1], 0, "connection refused: {:?}", connack);
+        (c, connack)
+    }
+
+    ///Connects and completes a MQTT 5.0 handshake, returns the client and the CONNACK
+    pub async fn handshake_v5(
+        addr: SocketAddr,
+        client_id: &str,
+        clean_start: bool,
+        expiry: u32,
+    ) -> (Self, Packet) {
+        let mut c = Self::connect(addr).await.expect("tcp connect failed");
+        c.send(&packet::connect_v5(client_id, clean_start, expiry)).await.expect("send CONNECT failed");
+        let connack = c.expect(CONNACK).await;
+        assert_eq!(connack.body[1], 0, "connection refused: {:?}", connack);
+        (c, connack)
+    }
+
+    #[inline]
+    pub async fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.write_all(data).await
+    }
+
+    ///Next packet, None if the connection is closed by the broker
+    pub async fn recv(&mut self) -> io::Result<Option<Packet>> {
+        tokio::time::timeout(RECV_TIMEOUT, self.read_packet())
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "receive packet timeout"))?
+    }
+
+    async fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            if let Some(p) = packet::decode(&mut self.buf)? {
+                return Ok(Some(p));
+            }
+            match self.stream.read_buf(&mut self.buf).await {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    ///Receives the next packet and checks its type
+    pub async fn expect(&mut self, typ: u8) -> Packet {
+        match self.recv().await {
+            Ok(Some(p)) if p.typ == typ => p,
+            res => panic!("expected packet type {}, got {:?}", typ, res),
+        }
+    }
+
+    ///Whether no packet arrives within `timeout`
+    pub async fn nothing_within(&mut self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.read_packet()).await.is_err()
+    }
+
+    ///Whether the broker closes the connection, packets received before closing are ignored
+    pub async fn is_closed(&mut self) -> bool {
+        loop {
+            match self.recv().await {
+                Ok(None) => return true,
+                Ok(Some(_)) => {}
+                Err(e) => return e.kind() != io::ErrorKind::TimedOut,
+            }
+        }
+    }
+
+    pub async fn subscribe(&mut self, packet_id: u16, topic_filter: &str, qos: u8) -> Vec<u8> {
+        self.send(&packet::subscribe(packet_id, &[(topic_filter, qos)]))
+            .await
+            .expect("send SUBSCRIBE failed");
+        let suback = self.expect(SUBACK).await;
+        assert_eq!(suback.packet_id(), packet_id);
+        suback.suback_codes(false)
+    }
+
+    pub async fn subscribe_v5(&mut self, packet_id: u16, topic_filter: &str, options: u8) -> Vec<u8> {
+        self.send(&packet::subscribe_v5(packet_id, &[(topic_filter, options)]))
+            .await
+            .expect("send SUBSCRIBE failed");
+        let suback = self.expect(SUBACK).await;
+        assert_eq!(suback.packet_id(), packet_id);
+        suback.suback_codes(true)
+    }
+}
@@ -0,0 +1,287 @@
+//!Encoders of the packets sent by the tests and the decoding helpers of the packets received
+
+use std::io;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use super::{
+    Packet, CONNECT, DISCONNECT, PINGREQ, PUBACK, PUBCOMP, PUBLISH, PUBREC, PUBREL, SUBSCRIBE, UNSUBSCRIBE,
+};
+
+const PROP_SESSION_EXPIRY_INTERVAL: u8 = 0x11;
+const PROP_USER_PROPERTY: u8 = 0x26;
+
+///MQTT 3.1.1 CONNECT
+#[inline]
+pub fn connect(client_id: &str, clean_session: bool, keep_alive: u16) -> Bytes {
+    connect_with(4, client_id, if clean_session { 0b10 } else { 0 }, keep_alive, None, &[])
+}
+
+///MQTT 3.1.1 CONNECT with a will message, QoS 0, not retained
+#[inline]
+pub fn connect_with_will(client_id: &str, will_topic: &str, will_payload: &[u8]) -> Bytes {
+    connect_with(4, client_id, 0b110, 60, Some((will_topic, will_payload)), &[])
+}
+
+///MQTT 5.0 CONNECT
+pub fn connect_v5(client_id: &str, clean_start: bool, session_expiry_interval: u32) -> Bytes {
+    let mut props = BytesMut::new();
+    if session_expiry_interval > 0 {
+        props.put_u8(PROP_SESSION_EXPIRY_INTERVAL);
+        props.put_u32(session_expiry_interval);
+    }
+    connect_with(5, client_id, if clean_start { 0b10 } else { 0 }, 60, None, &props)
+}
+
+///CONNECT with the given protocol level and flags, `props` is only encoded for level 5
+pub fn connect_with(
+    level: u8,
+    client_id: &str,
+    flags: u8,
+    keep_alive: u16,
+    will: Option<(&str, &[u8])>,
+    props: &[u8],
+) -> Bytes {
+    let mut body = BytesMut::new();
+    put_string(&mut body, "MQTT");
+    body.put_u8(level);
+    body.put_u8(flags);
+    body.put_u16(keep_alive);
+    if level == 5 {
+        put_properties(&mut body, props);
+    }
+    put_string(&mut body, client_id);
+    if let Some((topic, payload)) = will {
+        if level == 5 {
+            put_properties(&mut body, &[]);
+        }
+        put_string(&mut body, topic);
+        put_binary(&mut body, payload);
+    }
+    packet(CONNECT << 4, &body)
+}
+
+///MQTT 3.1.1 PUBLISH, `packet_id` is required for QoS 1 and 2
+#[inline]
+pub fn publish(topic: &str, payload: &[u8], qos: u8, retain: bool, packet_id: Option<u16>) -> Bytes {
+    publish_with(topic.as_bytes(), payload, qos, retain, packet_id, None)
+}
+
+///MQTT 5.0 PUBLISH with user properties
+pub fn publish_v5(
+    topic: &str,
+    payload: &[u8],
+    qos: u8,
+    retain: bool,
+    packet_id: Option<u16>,
+    user_properties: &[(&str, &str)],
+) -> Bytes {
+    let mut props = BytesMut::new();
+    for (k, v) in user_properties {
+        props.put_u8(PROP_USER_PROPERTY);
+        put_string(&mut props, k);
+        put_string(&mut props, v);
+    }
+    publish_with(topic.as_bytes(), payload, qos, retain, packet_id, Some(&props))
+}
+
+///PUBLISH with a raw topic, which may be malformed
+pub fn publish_with(
+    topic: &[u8],
+    payload: &[u8],
+    qos: u8,
+    retain: bool,
+    packet_id: Option<u16>,
+    props: Option<&[u8]>,
+) -> Bytes {
+    let mut body = BytesMut::new();
+    put_binary(&mut body, topic);
+    if let Some(packet_id) = packet_id {
+        body.put_u16(packet_id);
+    }
+    if let Some(props) = props {
+        put_properties(&mut body, props);
+    }
+    body.put_slice(payload);
+    packet(PUBLISH << 4 | qos << 1 | retain as u8, &body)
+}
+
+#[inline]
+pub fn puback(packet_id: u16) -> Bytes {
+    packet(PUBACK << 4, &packet_id.to_be_bytes())
+}
+
+#[inline]
+pub fn pubrec(packet_id: u16) -> Bytes {
+    packet(PUBREC << 4, &packet_id.to_be_bytes())
+}
+
+#[inline]
+pub fn pubrel(packet_id: u16) -> Bytes {
+    packet(PUBREL << 4 | 0b10, &packet_id.to_be_bytes())
+}
+
+#[inline]
+pub fn pubcomp(packet_id: u16) -> Bytes {
+    packet(PUBCOMP << 4, &packet_id.to_be_bytes())
+}
+
+///MQTT 3.1.1 SUBSCRIBE, topic filters with the requested QoS
+pub fn subscribe(packet_id: u16, topic_filters: &[(&str, u8)]) -> Bytes {
+    let mut body = BytesMut::new();
+    body.put_u16(packet_id);
+    for (tf, qos) in topic_filters {
+        put_string(&mut body, tf);
+        body.put_u8(*qos);
+    }
+    packet(SUBSCRIBE << 4 | 0b10, &body)
+}
+
+///MQTT 5.0 SUBSCRIBE, topic filters with the subscription options byte: QoS in bits 0-1, No Local
+///in bit 2, Retain As Published in bit 3 and Retain Handling in bits 4-5
+pub fn subscribe_v5(packet_id: u16, topic_filters: &[(&str, u8)]) -> Bytes {
+    let mut body = BytesMut::new();
+    body.put_u16(packet_id);
+    put_properties(&mut body, &[]);
+    for (tf, opts) in topic_filters {
+        put_string(&mut body, tf);
+        body.put_u8(*opts);
+    }
+    packet(SUBSCRIBE << 4 | 0b10, &body)
+}
+
+///MQTT 3.1.1 UNSUBSCRIBE
+pub fn unsubscribe(packet_id: u16, topic_filters: &[&str]) -> Bytes {
+    let mut body = BytesMut::new();
+    body.put_u16(packet_id);
+    for tf in topic_filters {
+        put_string(&mut body, tf);
+    }
+    packet(UNSUBSCRIBE << 4 | 0b10, &body)
+}
+
+#[inline]
+pub fn pingreq() -> Bytes {
+    packet(PINGREQ << 4, &[])
+}
+
+#[inline]
+pub fn disconnect() -> Bytes {
+    packet(DISCONNECT << 4, &[])
+}
+
+fn packet(first_byte: u8, body: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(body.len() + 5);
+    buf.put_u8(first_byte);
+    put_var_int(&mut buf, body.len());
+    buf.put_slice(body);
+    buf.freeze()
+}
+
+#[inline]
+fn put_string(buf: &mut BytesMut, s: &str) {
+    put_binary(buf, s.as_bytes())
+}
+
+#[inline]
+fn put_binary(buf: &mut BytesMut, data: &[u8]) {
+    buf.put_u16(data.len() as u16);
+    buf.put_slice(data);
+}
+
+#[inline]
+fn put_properties(buf: &mut BytesMut, props: &[u8]) {
+    put_var_int(buf, props.len());
+    buf.put_slice(props);
+}
+
+fn put_var_int(buf: &mut BytesMut, mut val: usize) {
+    loop {
+        let mut byte = (val % 128) as u8;
+        val /= 128;
+        if val > 0 {
+            byte |= 0x80;
+        }
+        buf.put_u8(byte);
+        if val == 0 {
+            break;
+        }
+    }
+}
+
+fn get_var_int(buf: &mut impl Buf) -> Option<usize> {
+    let mut val = 0;
+    for i in 0..4 {
+        if !buf.has_remaining() {
+            return None;
+        }
+        let byte = buf.get_u8();
+        val |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(val);
+        }
+    }
+    None
+}
+
+#[inline]
+pub(crate) fn get_string(buf: &mut Bytes) -> String {
+    let len = buf.get_u16() as usize;
+    String::from_utf8(buf.split_to(len).to_vec()).expect("invalid UTF-8 string")
+}
+
+///Skips the properties, returns the user properties
+pub(crate) fn get_properties(buf: &mut Bytes) -> Vec<(String, String)> {
+    let len = get_var_int(buf).expect("malformed properties length");
+    let mut props = buf.split_to(len);
+    let mut user_properties = Vec::new();
+    while props.has_remaining() {
+        match props.get_u8() {
+            PROP_USER_PROPERTY => {
+                let k = get_string(&mut props);
+                let v = get_string(&mut props);
+                user_properties.push((k, v));
+            }
+            //Payload Format Indicator, Maximum QoS, Retain Available and the other byte properties
+            0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => props.advance(1),
+            //Receive Maximum, Topic Alias Maximum, Topic Alias, Server Keep Alive
+            0x13 | 0x21 | 0x22 | 0x23 => props.advance(2),
+            //Message Expiry Interval, Session Expiry Interval, Maximum Packet Size and Will Delay Interval
+            0x02 | 0x11 | 0x18 | 0x27 => props.advance(4),
+            //Subscription Identifier
+            0x0B => {
+                get_var_int(&mut props);
+            }
+            //Strings and binary data
+            _ => {
+                let len = props.get_u16() as usize;
+                props.advance(len);
+            }
+        }
+    }
+    user_properties
+}
+
+///Decodes a packet from the buffer, None if the buffer does not hold a whole packet yet
+pub(crate) fn decode(buf: &mut BytesMut) -> io::Result<Option<Packet>> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let mut header = &buf[1..];
+    let before = header.len();
+    let len = match get_var_int(&mut header) {
+        Some(len) => len,
+        None if before >= 4 => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed remaining length"))
+        }
+        None => return Ok(None),
+    };
+    let header_len = 1 + before - header.len();
+    if buf.len() < header_len + len {
+        return Ok(None);
+    }
+    let first_byte = buf[0];
+    let mut packet = buf.split_to(header_len + len);
+    packet.advance(header_len);
+    Ok(Some(Packet { typ: first_byte >> 4, flags: first_byte & 0x0f, body: packet.freeze() }))
+}
@@ -0,0 +1,248 @@
+//!MQTT 3.1.1 normative statements, see https://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html
+
+use std::time::Duration;
+
+use rmqtt_conformance::{
+    broker, packet, Client, CONNACK, PINGRESP, PUBACK, PUBCOMP, PUBLISH, PUBREC, PUBREL, SUBACK, UNSUBACK,
+};
+
+const QUIET: Duration = Duration::from_millis(500);
+
+//[MQTT-3.1.0-1] The first packet sent from the client to the server MUST be a CONNECT packet
+#[tokio::test]
+async fn first_packet_must_be_connect() {
+    let mut c = Client::connect(broker()).await.unwrap();
+    c.send(&packet::pingreq()).await.unwrap();
+    assert!(c.is_closed().await);
+}
+
+//[MQTT-3.1.0-2] A second CONNECT packet is a protocol violation, the server MUST disconnect the client
+#[tokio::test]
+async fn second_connect_is_protocol_violation() {
+    let (mut c, _) = Client::handshake(broker(), "c311-second-connect", true).await;
+    c.send(&packet::connect("c311-second-connect", true, 60)).await.unwrap();
+    assert!(c.is_closed().await);
+}
+
+//[MQTT-3.1.3-8] A zero-byte ClientId with CleanSession 0 MUST be rejected with return code 0x02
+#[tokio::test]
+async fn empty_client_id_requires_clean_session() {
+    let mut c = Client::connect(broker()).await.unwrap();
+    c.send(&packet::connect("", false, 60)).await.unwrap();
+    let connack = c.expect(CONNACK).await;
+    assert_eq!(connack.body[1], 0x02);
+    assert!(c.is_closed().await);
+}
+
+//[MQTT-3.1.3-6] A zero-byte ClientId with CleanSession 1 is accepted, the server assigns an identifier
+#[tokio::test]
+async fn empty_client_id_with_clean_session() {
+    Client::handshake(broker(), "", true).await;
+}
+
+//[MQTT-3.2.2-1] With CleanSession 1 the Session Present flag MUST be 0
+//[MQTT-3.2.2-2] With CleanSession 0 and a stored session the Session Present flag MUST be 1
+//[MQTT-3.1.2-4] The subscriptions and the QoS 1 messages of a persistent session are kept while disconnected
+#[tokio::test]
+async fn persistent_session() {
+    let addr = broker();
+    let (mut sub, connack) = Client::handshake(addr, "c311-persistent", true).await;
+    assert_eq!(connack.body[0], 0);
+    sub.send(&packet::disconnect()).await.unwrap();
+
+    let (mut sub, connack) = Client::handshake(addr, "c311-persistent", false).await;
+    assert_eq!(connack.body[0], 0);
+    assert_eq!(sub.subscribe(1, "c311/persistent", 1).await, vec![1]);
+    sub.send(&packet::disconnect()).await.unwrap();
+    assert!(sub.is_closed().await);
+
+    let (mut publisher, _) = Client::handshake(addr, "c311-persistent-pub", true).await;
+    publisher.send(&packet::publish("c311/persistent", b"queued", 1, false, Some(1))).await.unwrap();
+    publisher.expect(PUBACK).await;
+
+    let (mut sub, connack) = Client::handshake(addr, "c311-persistent", false).await;
+    assert_eq!(connack.body[0], 1);
+    let p = sub.expect(PUBLISH).await.publish(false);
+    assert_eq!((p.topic.as_str(), p.qos, &p.payload[..]), ("c311/persistent", 1, &b"queued"[..]));
+    sub.send(&packet::puback(p.packet_id.unwrap())).await.unwrap();
+    sub.send(&packet::disconnect()).await.unwrap();
+
+    //clean the session
+    let (mut sub, connack) = Client::handshake(addr, "c311-persistent", true).await;
+    assert_eq!(connack.body[0], 0);
+    sub.send(&packet::disconnect()).await.unwrap();
+}
+
+//[MQTT-3.1.4-2] A second connection with the same ClientId disconnects the existing client
+#[tokio::test]
+async fn client_takeover() {
+    let addr = broker();
+    let (mut first, _) = Client::handshake(addr, "c311-takeover", true).await;
+    let (_second, _) = Client::handshake(addr, "c311-takeover", true).await;
+    assert!(first.is_closed().await);
+}
+
+//[MQTT-4.3.2-2] The receiver of a QoS 1 PUBLISH MUST respond with a PUBACK containing its packet identifier
+#[tokio::test]
+async fn qos1_flow() {
+    let (mut c, _) = Client::handshake(broker(), "c311-qos1", true).await;
+    c.send(&packet::publish("c311/qos1", b"1", 1, false, Some(7))).await.unwrap();
+    assert_eq!(c.expect(PUBACK).await.packet_id(), 7);
+}
+
+//[MQTT-4.3.3-2] The receiver of a QoS 2 PUBLISH MUST respond with a PUBREC
+//[MQTT-4.3.3-11] and MUST respond to a PUBREL with a PUBCOMP containing the same packet identifier
+#[tokio::test]
+async fn qos2_flow() {
+    let addr = broker();
+    let (mut sub, _) = Client::handshake(addr, "c311-qos2-sub", true).await;
+    assert_eq!(sub.subscribe(1, "c311/qos2", 2).await, vec![2]);
+
+    let (mut c, _) = Client::handshake(addr, "c311-qos2", true).await;
+    c.send(&packet::publish("c311/qos2", b"2", 2, false, Some(9))).await.unwrap();
+    assert_eq!(c.expect(PUBREC).await.packet_id(), 9);
+    c.send(&packet::pubrel(9)).await.unwrap();
+    assert_eq!(c.expect(PUBCOMP).await.packet_id(), 9);
+
+    //exactly once to the subscriber
+    let p = sub.expect(PUBLISH).await.publish(false);
+    assert_eq!(p.qos, 2);
+    let packet_id = p.packet_id.unwrap();
+    sub.send(&packet::pubrec(packet_id)).await.unwrap();
+    assert_eq!(sub.expect(PUBREL).await.packet_id(), packet_id);
+    sub.send(&packet::pubcomp(packet_id)).await.unwrap();
+    assert!(sub.nothing_within(QUIET).await);
+}
+
+//[MQTT-3.8.4-6] The delivered QoS is the minimum of the published QoS and the granted QoS
+//[MQTT-3.9.3-1] The return codes of a SUBACK are in the order of the topic filters of the SUBSCRIBE
+#[tokio::test]
+async fn downgrade_qos() {
+    let addr = broker();
+    let (mut sub, _) = Client::handshake(addr, "c311-downgrade", true).await;
+    sub.send(&packet::subscribe(3, &[("c311/downgrade/0", 0), ("c311/downgrade/1", 1)])).await.unwrap();
+    let suback = sub.expect(SUBACK).await;
+    assert_eq!(suback.suback_codes(false), vec![0, 1]);
+
+    let (mut publisher, _) = Client::handshake(addr, "c311-downgrade-pub", true).await;
+    publisher.send(&packet::publish("c311/downgrade/0", b"0", 1, false, Some(1))).await.unwrap();
+    publisher.expect(PUBACK).await;
+    assert_eq!(sub.expect(PUBLISH).await.publish(false).qos, 0);
+}
+
+//[MQTT-4.7.1-2] "c311/retain/#" also matches "c311/retain"
+//[MQTT-3.3.1-6] A new subscription receives the retained message of a matching topic, with RETAIN 1
+//[MQTT-3.3.1-9] Messages forwarded to existing subscriptions have RETAIN 0
+//[MQTT-3.3.1-10] A retained message with a zero-byte payload removes the retained message
+#[tokio::test]
+async fn retained_messages() {
+    let addr = broker();
+    let (mut live, _) = Client::handshake(addr, "c311-retain-live", true).await;
+    live.subscribe(1, "c311/retain", 0).await;
+
+    let (mut publisher, _) = Client::handshake(addr, "c311-retain-pub", true).await;
+    publisher.send(&packet::publish("c311/retain", b"last", 1, true, Some(1))).await.unwrap();
+    publisher.expect(PUBACK).await;
+    assert!(!live.expect(PUBLISH).await.publish(false).retain);
+
+    let (mut late, _) = Client::handshake(addr, "c311-retain-late", true).await;
+    late.subscribe(1, "c311/retain/#", 0).await;
+    let p = late.expect(PUBLISH).await.publish(false);
+    assert!(p.retain);
+    assert_eq!(&p.payload[..], b"last");
+
+    publisher.send(&packet::publish("c311/retain", b"", 1, true, Some(2))).await.unwrap();
+    publisher.expect(PUBACK).await;
+    let (mut later, _) = Client::handshake(addr, "c311-retain-later", true).await;
+    later.subscribe(1, "c311/retain", 0).await;
+    assert!(later.nothing_within(QUIET).await);
+}
+
+//[MQTT-4.7.1-2] The multi-level wildcard also matches the parent level
+//[MQTT-4.7.1-3] The single-level wildcard matches exactly one level
+//[MQTT-4.7.2-1] Filters starting with a wildcard do not match topics starting with $
+#[tokio::test]
+async fn wildcards() {
+    let addr = broker();
+    let (mut sub, _) = Client::handshake(addr, "c311-wildcards", true).await;
+    sub.subscribe(1, "c311/sport/tennis/#", 0).await;
+    sub.subscribe(2, "c311/+/player1", 0).await;
+    sub.subscribe(3, "#", 0).await;
+    sub.subscribe(4, "+/c311/sys", 0).await;
+
+    let topics =
+        ["c311/sport/tennis", "c311/sport/player1", "c311/sport/tennis/player1/ranking", "$c311/sys"];
+    let (mut publisher, _) = Client::handshake(addr, "c311-wildcards-pub", true).await;
+    for topic in topics {
+        publisher.send(&packet::publish(topic, b"w", 1, false, Some(1))).await.unwrap();
+        publisher.expect(PUBACK).await;
+    }
+
+    //"#" also receives the messages of the other tests
+    let mut received = Vec::new();
+    while let Ok(Ok(Some(p))) = tokio::time::timeout(QUIET, sub.recv()).await {
+        let p = p.publish(false);
+        if topics.contains(&p.topic.as_str()) {
+            received.push(p.topic);
+        }
+    }
+    assert!(received.iter().any(|t| t == "c311/sport/tennis"));
+    assert!(received.iter().any(|t| t == "c311/sport/player1"));
+    assert!(received.iter().any(|t| t == "c311/sport/tennis/player1/ranking"));
+    assert!(!received.iter().any(|t| t.starts_with('$')));
+}
+
+//[MQTT-3.10.4-4] The server MUST respond to an UNSUBSCRIBE with an UNSUBACK with the same packet identifier
+//[MQTT-3.10.4-2] and stop adding messages for delivery to the client
+#[tokio::test]
+async fn unsubscribe() {
+    let addr = broker();
+    let (mut sub, _) = Client::handshake(addr, "c311-unsub", true).await;
+    sub.subscribe(1, "c311/unsub", 0).await;
+    sub.send(&packet::unsubscribe(5, &["c311/unsub"])).await.unwrap();
+    assert_eq!(sub.expect(UNSUBACK).await.packet_id(), 5);
+
+    let (mut publisher, _) = Client::handshake(addr, "c311-unsub-pub", true).await;
+    publisher.send(&packet::publish("c311/unsub", b"u", 1, false, Some(1))).await.unwrap();
+    publisher.expect(PUBACK).await;
+    assert!(sub.nothing_within(QUIET).await);
+}
+
+//[MQTT-3.12.4-1] The server MUST send a PINGRESP in response to a PINGREQ
+#[tokio::test]
+async fn ping() {
+    let (mut c, _) = Client::handshake(broker(), "c311-ping", true).await;
+    c.send(&packet::pingreq()).await.unwrap();
+    c.expect(PINGRESP).await;
+}
+
+//[MQTT-1.5.3-1] A topic that is not well-formed UTF-8 MUST close the network connection
+#[tokio::test]
+async fn topic_must_be_utf8() {
+    let (mut c, _) = Client::handshake(broker(), "c311-utf8", true).await;
+    c.send(&packet::publish_with(b"c311/\xff\xfe", b"x", 0, false, None, None)).await.unwrap();
+    assert!(c.is_closed().await);
+}
+
+//[MQTT-3.1.2-8] The will message MUST be published when the network connection is closed without DISCONNECT
+//[MQTT-3.1.2-10] and MUST be removed when a DISCONNECT is received
+#[tokio::test]
+async fn will_message() {
+    let addr = broker();
+    let (mut sub, _) = Client::handshake(addr, "c311-will-sub", true).await;
+    sub.subscribe(1, "c311/will/+", 0).await;
+
+    let mut graceful = Client::connect(addr).await.unwrap();
+    graceful.send(&packet::connect_with_will("c311-will-1", "c311/will/1", b"gone")).await.unwrap();
+    graceful.expect(CONNACK).await;
+    graceful.send(&packet::disconnect()).await.unwrap();
+    assert!(graceful.is_closed().await);
+    assert!(sub.nothing_within(QUIET).await);
+
+    let mut dropped = Client::connect(addr).await.unwrap();
+    dropped.send(&packet::connect_with_will("c311-will-2", "c311/will/2", b"gone")).await.unwrap();
+    dropped.expect(CONNACK).await;
+    drop(dropped);
+    let p = sub.expect(PUBLISH).await.publish(false);
+    assert_eq!((p.topic.as_str(), &p.payload[..]), ("c311/will/2", &b"gone"[..]));
+}
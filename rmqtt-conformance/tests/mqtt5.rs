@@ -0,0 +1,108 @@
+//!MQTT 5.0 normative statements, see https://docs.oasis-open.org/mqtt/mqtt/v5.0/os/mqtt-v5.0-os.html
+
+use std::time::Duration;
+
+use rmqtt_conformance::{broker, packet, Client, PINGRESP, PUBACK, PUBLISH, SUBACK};
+
+const QUIET: Duration = Duration::from_millis(500);
+
+const NO_LOCAL: u8 = 0b0100;
+const RETAIN_AS_PUBLISHED: u8 = 0b1000;
+const RETAIN_HANDLING_NONE: u8 = 0b10_0000;
+
+//[MQTT-3.2.2-2] With Clean Start 1 the Session Present flag MUST be 0
+//[MQTT-3.2.2-3] With Clean Start 0 and a stored session the Session Present flag MUST be 1
+#[tokio::test]
+async fn session_present() {
+    let addr = broker();
+    let (mut c, connack) = Client::handshake_v5(addr, "c5-session", true, 60).await;
+    assert_eq!(connack.body[0], 0);
+    c.subscribe_v5(1, "c5/session", 1).await;
+    c.send(&packet::disconnect()).await.unwrap();
+    assert!(c.is_closed().await);
+
+    let (mut c, connack) = Client::handshake_v5(addr, "c5-session", false, 60).await;
+    assert_eq!(connack.body[0], 1);
+    c.send(&packet::disconnect()).await.unwrap();
+
+    let (_, connack) = Client::handshake_v5(addr, "c5-session", true, 0).await;
+    assert_eq!(connack.body[0], 0);
+}
+
+//[MQTT-3.3.2-17] The server MUST forward all User Properties unaltered and in order
+#[tokio::test]
+async fn user_properties_forwarded() {
+    let addr = broker();
+    let (mut sub, _) = Client::handshake_v5(addr, "c5-props-sub", true, 0).await;
+    sub.subscribe_v5(1, "c5/props", 1).await;
+
+    let (mut publisher, _) = Client::handshake_v5(addr, "c5-props-pub", true, 0).await;
+    let props = [("b", "2"), ("a", "1"), ("a", "3")];
+    publisher.send(&packet::publish_v5("c5/props", b"p", 1, false, Some(1), &props)).await.unwrap();
+    publisher.expect(PUBACK).await;
+
+    let p = sub.expect(PUBLISH).await.publish(true);
+    let received = p.user_properties.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect::<Vec<_>>();
+    assert_eq!(received, props);
+}
+
+//[MQTT-3.8.3-3] With No Local set, messages MUST NOT be forwarded to the publisher's own ClientID
+#[tokio::test]
+async fn no_local() {
+    let (mut c, _) = Client::handshake_v5(broker(), "c5-no-local", true, 0).await;
+    c.subscribe_v5(1, "c5/no-local", 1 | NO_LOCAL).await;
+    c.send(&packet::publish_v5("c5/no-local", b"n", 1, false, Some(2), &[])).await.unwrap();
+    c.expect(PUBACK).await;
+    assert!(c.nothing_within(QUIET).await);
+}
+
+//[MQTT-3.3.1-12] Without Retain As Published the RETAIN flag of forwarded messages MUST be 0
+//[MQTT-3.3.1-13] With Retain As Published it MUST be the RETAIN flag of the received PUBLISH
+#[tokio::test]
+async fn retain_as_published() {
+    let addr = broker();
+    let (mut plain, _) = Client::handshake_v5(addr, "c5-rap-plain", true, 0).await;
+    plain.subscribe_v5(1, "c5/rap", 0).await;
+    let (mut rap, _) = Client::handshake_v5(addr, "c5-rap", true, 0).await;
+    rap.subscribe_v5(1, "c5/rap", RETAIN_AS_PUBLISHED).await;
+
+    let (mut publisher, _) = Client::handshake_v5(addr, "c5-rap-pub", true, 0).await;
+    publisher.send(&packet::publish_v5("c5/rap", b"r", 1, true, Some(1), &[])).await.unwrap();
+    publisher.expect(PUBACK).await;
+    assert!(!plain.expect(PUBLISH).await.publish(true).retain);
+    assert!(rap.expect(PUBLISH).await.publish(true).retain);
+
+    publisher.send(&packet::publish_v5("c5/rap", b"", 1, true, Some(2), &[])).await.unwrap();
+    publisher.expect(PUBACK).await;
+}
+
+//[MQTT-3.3.1-11] With Retain Handling 2 retained messages MUST NOT be sent on subscribe
+#[tokio::test]
+async fn retain_handling_none() {
+    let addr = broker();
+    let (mut publisher, _) = Client::handshake_v5(addr, "c5-rh-pub", true, 0).await;
+    publisher.send(&packet::publish_v5("c5/rh", b"r", 1, true, Some(1), &[])).await.unwrap();
+    publisher.expect(PUBACK).await;
+
+    let (mut sub, _) = Client::handshake_v5(addr, "c5-rh", true, 0).await;
+    sub.subscribe_v5(1, "c5/rh", RETAIN_HANDLING_NONE).await;
+    assert!(sub.nothing_within(QUIET).await);
+
+    let (mut control, _) = Client::handshake_v5(addr, "c5-rh-control", true, 0).await;
+    control.subscribe_v5(1, "c5/rh", 0).await;
+    assert!(control.expect(PUBLISH).await.publish(true).retain);
+
+    publisher.send(&packet::publish_v5("c5/rh", b"", 1, true, Some(2), &[])).await.unwrap();
+    publisher.expect(PUBACK).await;
+}
+
+//A malformed topic filter is refused with reason code 0x8F (Topic Filter invalid) in its SUBACK entry,
+//the other filters of the same SUBSCRIBE are still granted
+#[tokio::test]
+async fn invalid_topic_filter() {
+    let (mut c, _) = Client::handshake_v5(broker(), "c5-invalid-filter", true, 0).await;
+    c.send(&packet::subscribe_v5(1, &[("c5/#/invalid", 1), ("c5/valid", 1)])).await.unwrap();
+    assert_eq!(c.expect(SUBACK).await.suback_codes(true), vec![0x8F, 1]);
+    c.send(&packet::pingreq()).await.unwrap();
+    c.expect(PINGRESP).await;
+}
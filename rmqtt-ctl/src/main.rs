@@ -0,0 +1,348 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use reqwest::Method;
+use serde_json::{json, Value};
+use structopt::StructOpt;
+
+mod table;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "rmqtt-ctl", about = "Command line administration tool of rmqtt")]
+struct Options {
+    ///Address of the rmqtt-http-api plugin
+    #[structopt(long, default_value = "http://127.0.0.1:6060")]
+    url: String,
+
+    ///Bearer token, the http_bearer_token of the rmqtt-http-api plugin
+    #[structopt(long)]
+    token: Option<String>,
+
+    ///Output format, table or json
+    #[structopt(short, long, default_value = "table")]
+    output: Output,
+
+    #[structopt(subcommand)]
+    cmd: Command,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Output {
+    Table,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Output::Table),
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output format: {}, expected table or json", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    ///Basic information of the nodes in the cluster
+    Brokers { node: Option<u64> },
+    ///Status of the nodes in the cluster
+    Nodes { node: Option<u64> },
+    ///Statistics, summed over the cluster with --sum
+    Stats {
+        node: Option<u64>,
+        #[structopt(long)]
+        sum: bool,
+    },
+    ///Metrics, summed over the cluster with --sum
+    Metrics {
+        node: Option<u64>,
+        #[structopt(long)]
+        sum: bool,
+    },
+    ///List, inspect and kick clients
+    Clients(ClientsCommand),
+    ///Search subscriptions
+    Subscriptions(SubscriptionsCommand),
+    ///Routes, all or those matching a topic
+    Routes {
+        topic: Option<String>,
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+    ///Retained messages of the node matching a topic filter
+    Retained {
+        #[structopt(default_value = "#")]
+        topic_filter: String,
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+    ///Manage plugins
+    Plugins(PluginsCommand),
+    ///Publish a message
+    Publish {
+        topic: String,
+        payload: String,
+        #[structopt(short, long, default_value = "0")]
+        qos: u8,
+        #[structopt(short, long)]
+        retain: bool,
+        ///Client identifier of the publisher
+        #[structopt(long, default_value = "system")]
+        clientid: String,
+        ///Payload encoding, plain or base64
+        #[structopt(long, default_value = "plain")]
+        encoding: String,
+    },
+    ///Reload the broker configuration of a node
+    ReloadConfig { node: u64 },
+}
+
+#[derive(StructOpt, Debug)]
+enum ClientsCommand {
+    ///Search clients
+    List {
+        #[structopt(long)]
+        clientid: Option<String>,
+        #[structopt(long)]
+        username: Option<String>,
+        #[structopt(long)]
+        ip_address: Option<String>,
+        #[structopt(long)]
+        connected: Option<bool>,
+        ///Substring of the client identifier
+        #[structopt(long)]
+        like_clientid: Option<String>,
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+    ///Information of a client
+    Show { clientid: String },
+    ///Disconnect a client
+    Kick { clientid: String },
+    ///Whether a client is online
+    Online { clientid: String },
+}
+
+#[derive(StructOpt, Debug)]
+enum SubscriptionsCommand {
+    ///Search subscriptions
+    List {
+        #[structopt(long)]
+        clientid: Option<String>,
+        #[structopt(long)]
+        topic: Option<String>,
+        ///Subscriptions matching this topic
+        #[structopt(long)]
+        match_topic: Option<String>,
+        #[structopt(long)]
+        qos: Option<u8>,
+        ///Shared subscription group
+        #[structopt(long)]
+        share: Option<String>,
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+    ///Subscriptions of a client
+    Show { clientid: String },
+}
+
+#[derive(StructOpt, Debug)]
+enum PluginsCommand {
+    ///Plugins of the cluster, or of a node
+    List { node: Option<u64> },
+    ///Information of a plugin
+    Show { node: u64, plugin: String },
+    ///Configuration of a plugin
+    Config { node: u64, plugin: String },
+    ///Reload the configuration of a plugin
+    Reload { node: u64, plugin: String },
+    ///Load and start a plugin
+    Load { node: u64, plugin: String },
+    ///Stop and unload a plugin
+    Unload { node: u64, plugin: String },
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    fn new(url: &str, token: Option<String>) -> Self {
+        Self { http: reqwest::Client::new(), url: format!("{}/api/v1", url.trim_end_matches('/')), token }
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, Option<String>)]) -> Result<Value> {
+        let query = query.iter().filter_map(|(k, v)| v.as_ref().map(|v| (*k, v))).collect::<Vec<_>>();
+        self.request(Method::GET, path, &query, None).await
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &String)],
+        body: Option<Value>,
+    ) -> Result<Value> {
+        let mut req = self.http.request(method, format!("{}{}", self.url, path)).query(query);
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        let resp = req.send().await?;
+        let status = resp.status();
+        let body = resp.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!("{}, {}", status, body));
+        }
+        if body.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&body).unwrap_or(Value::String(body)))
+    }
+}
+
+#[inline]
+fn opt<T: ToString>(v: &Option<T>) -> Option<String> {
+    v.as_ref().map(|v| v.to_string())
+}
+
+#[inline]
+fn node_path(base: &str, node: Option<u64>) -> String {
+    match node {
+        Some(node) => format!("{}/{}", base, node),
+        None => base.into(),
+    }
+}
+
+async fn execute(c: &Client, cmd: Command) -> Result<Value> {
+    match cmd {
+        Command::Brokers { node } => c.get(&node_path("/brokers", node), &[]).await,
+        Command::Nodes { node } => c.get(&node_path("/nodes", node), &[]).await,
+        Command::Stats { sum: true, .. } => c.get("/stats/sum", &[]).await,
+        Command::Stats { node, .. } => c.get(&node_path("/stats", node), &[]).await,
+        Command::Metrics { sum: true, .. } => c.get("/metrics/sum", &[]).await,
+        Command::Metrics { node, .. } => c.get(&node_path("/metrics", node), &[]).await,
+        Command::Clients(ClientsCommand::List {
+            clientid,
+            username,
+            ip_address,
+            connected,
+            like_clientid,
+            limit,
+        }) => {
+            let query = [
+                ("clientid", clientid),
+                ("username", username),
+                ("ip_address", ip_address),
+                ("connected", opt(&connected)),
+                ("_like_clientid", like_clientid),
+                ("_limit", opt(&limit)),
+            ];
+            c.get("/clients", &query).await
+        }
+        Command::Clients(ClientsCommand::Show { clientid }) => {
+            c.get(&format!("/clients/{}", encode(&clientid)), &[]).await
+        }
+        Command::Clients(ClientsCommand::Kick { clientid }) => {
+            c.request(Method::DELETE, &format!("/clients/{}", encode(&clientid)), &[], None).await
+        }
+        Command::Clients(ClientsCommand::Online { clientid }) => {
+            c.get(&format!("/clients/{}/online", encode(&clientid)), &[]).await
+        }
+        Command::Subscriptions(SubscriptionsCommand::List {
+            clientid,
+            topic,
+            match_topic,
+            qos,
+            share,
+            limit,
+        }) => {
+            let query = [
+                ("clientid", clientid),
+                ("topic", topic),
+                ("_match_topic", match_topic),
+                ("qos", opt(&qos)),
+                ("share", share),
+                ("_limit", opt(&limit)),
+            ];
+            c.get("/subscriptions", &query).await
+        }
+        Command::Subscriptions(SubscriptionsCommand::Show { clientid }) => {
+            c.get(&format!("/subscriptions/{}", encode(&clientid)), &[]).await
+        }
+        Command::Routes { topic: Some(topic), .. } => {
+            c.get(&format!("/routes/{}", encode(&topic)), &[]).await
+        }
+        Command::Routes { topic: None, limit } => c.get("/routes", &[("_limit", opt(&limit))]).await,
+        Command::Retained { topic_filter, limit } => {
+            c.get("/retains", &[("topic", Some(topic_filter)), ("_limit", opt(&limit))]).await
+        }
+        Command::Plugins(PluginsCommand::List { node }) => c.get(&node_path("/plugins", node), &[]).await,
+        Command::Plugins(PluginsCommand::Show { node, plugin }) => {
+            c.get(&format!("/plugins/{}/{}", node, plugin), &[]).await
+        }
+        Command::Plugins(PluginsCommand::Config { node, plugin }) => {
+            c.get(&format!("/plugins/{}/{}/config", node, plugin), &[]).await
+        }
+        Command::Plugins(PluginsCommand::Reload { node, plugin }) => {
+            c.request(Method::PUT, &format!("/plugins/{}/{}/config/reload", node, plugin), &[], None).await
+        }
+        Command::Plugins(PluginsCommand::Load { node, plugin }) => {
+            c.request(Method::PUT, &format!("/plugins/{}/{}/load", node, plugin), &[], None).await
+        }
+        Command::Plugins(PluginsCommand::Unload { node, plugin }) => {
+            c.request(Method::PUT, &format!("/plugins/{}/{}/unload", node, plugin), &[], None).await
+        }
+        Command::Publish { topic, payload, qos, retain, clientid, encoding } => {
+            let body = json!({
+                "topic": topic,
+                "payload": payload,
+                "qos": qos,
+                "retain": retain,
+                "clientid": clientid,
+                "encoding": encoding,
+            });
+            c.request(Method::POST, "/mqtt/publish", &[], Some(body)).await
+        }
+        Command::ReloadConfig { node } => {
+            c.request(Method::PUT, &format!("/nodes/{}/config/reload", node), &[], None).await
+        }
+    }
+}
+
+//Percent-encodes a path segment, client identifiers and topics may contain '/', '+' and '#'
+fn encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let opts = Options::from_args();
+    let client = Client::new(&opts.url, opts.token);
+    match execute(&client, opts.cmd).await {
+        Ok(Value::Null) => {}
+        Ok(res) => match opts.output {
+            Output::Json => println!("{}", serde_json::to_string_pretty(&res).unwrap_or_default()),
+            Output::Table => table::print(&res),
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use serde_json::{Map, Value};
+
+///Prints an array of objects as a table with a column per key, an object as key/value rows
+pub(crate) fn print(value: &Value) {
+    match value {
+        Value::Array(rows) if rows.iter().all(|r| r.is_object()) && !rows.is_empty() => {
+            let mut columns: Vec<&str> = Vec::new();
+            for row in rows.iter().filter_map(|r| r.as_object()) {
+                for key in row.keys() {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+            let cells = rows
+                .iter()
+                .filter_map(|r| r.as_object())
+                .map(|row| columns.iter().map(|c| cell(row.get(*c))).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            render(&columns.iter().map(|c| c.to_uppercase()).collect::<Vec<_>>(), &cells);
+        }
+        Value::Object(obj) => render(&["KEY".into(), "VALUE".into()], &key_values(obj)),
+        Value::Array(items) => {
+            for item in items {
+                println!("{}", cell(Some(item)));
+            }
+        }
+        _ => println!("{}", cell(Some(value))),
+    }
+}
+
+#[inline]
+fn key_values(obj: &Map<String, Value>) -> Vec<Vec<String>> {
+    obj.iter().map(|(k, v)| vec![k.clone(), cell(Some(v))]).collect()
+}
+
+#[inline]
+fn cell(v: Option<&Value>) -> String {
+    match v {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn render(header: &[String], rows: &[Vec<String>]) {
+    let mut widths = header.iter().map(|h| h.chars().count()).collect::<Vec<_>>();
+    for row in rows {
+        for (w, c) in widths.iter_mut().zip(row.iter()) {
+            *w = (*w).max(c.chars().count());
+        }
+    }
+    let line = |cells: &[String]| {
+        let line = cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(c, w)| format!("{:<width$}", c, width = *w))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    line(header);
+    for row in rows {
+        line(row);
+    }
+}
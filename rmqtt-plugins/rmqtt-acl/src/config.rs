@@ -4,13 +4,15 @@ use std::sync::Arc;
 use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::{self, Serialize};
 
+use chrono_tz::Tz;
 use rmqtt::broker::hook::Priority;
 use rmqtt::broker::topic::TopicTree;
+use rmqtt::chrono::{self, DateTime, Datelike, FixedOffset, NaiveTime, Utc, Weekday};
 use rmqtt::{
     ahash, dashmap, log,
     serde_json::{self, Value},
     tokio::sync::RwLock,
-    Id,
+    Id, IpCidr,
 };
 use rmqtt::{ClientId, MqttError, Password, Result, Superuser, Topic, UserName};
 
@@ -94,6 +96,7 @@ pub struct Rule {
     pub users: Vec<User>,
     pub control: Control,
     pub topics: Topics,
+    pub schedule: Option<Schedule>,
 }
 
 impl Rule {
@@ -117,6 +120,12 @@ impl Rule {
         protocol: Option<u8>,
         allow: bool,
     ) -> (bool, Superuser) {
+        //Outside of its schedule the rule is skipped, the next rules are checked
+        if let Some(schedule) = &self.schedule {
+            if !schedule.is_active(Utc::now()) {
+                return (false, false);
+            }
+        }
         let mut superuser: Superuser = false;
         for user in &self.users {
             let (hit, _superuser) = user.hit(id, password, protocol, allow);
@@ -139,15 +148,19 @@ impl std::convert::TryFrom<&serde_json::Value> for Rule {
             let user_cfg = cfg_items.get(1).ok_or_else(|| MqttError::from(err_msg))?;
             let control_cfg = cfg_items.get(2);
             let topics_cfg = cfg_items.get(3);
+            let schedule_cfg = cfg_items.get(4);
 
             let access = Access::try_from(access_cfg)?;
             let users = users_try_from(user_cfg, access)?;
             let control = Control::try_from(control_cfg)?;
             let topics = Topics::try_from(topics_cfg)?;
-            if topics_cfg.is_some() && matches!(control, Control::Connect) {
+            //An empty topic list is allowed, so that a connect rule can have a schedule
+            let has_topics = topics_cfg.and_then(|t| t.as_array()).map(|t| !t.is_empty()).unwrap_or(true);
+            if topics_cfg.is_some() && has_topics && matches!(control, Control::Connect) {
                 log::warn!("ACL Rule config, the third column of a quadruple is Connect, but the fourth column is not empty! topics config is {:?}", topics_cfg);
             }
-            Ok(Rule { access, users, control, topics })
+            let schedule = schedule_cfg.map(Schedule::try_from).transpose()?;
+            Ok(Rule { access, users, control, topics, schedule })
         } else {
             Err(MqttError::from(err_msg))
         }
@@ -164,7 +177,7 @@ pub enum Access {
 pub enum User {
     Username(UserName, Option<Password>, Superuser),
     Clientid(ClientId),
-    Ipaddr(String),
+    Ipaddr(IpCidr),
     Protocol(u8), //MQTT Protocol Ver, 3=MQTT 3.1, 4=MQTT 3.11, 5=MQTT 5.0
     All,
 }
@@ -195,7 +208,7 @@ impl User {
             User::Clientid(clientid) => (id.client_id == clientid, false),
             User::Ipaddr(ipaddr) => {
                 if let Some(remote_addr) = id.remote_addr {
-                    (ipaddr == remote_addr.ip().to_string().as_str(), false) //@TODO Consider using integer representation of IP addresses
+                    (ipaddr.contains(remote_addr.ip()), false)
                 } else {
                     (false, false)
                 }
@@ -301,7 +314,7 @@ fn users_try_from(user_cfg: &Value, access: Access) -> Result<Vec<User>> {
             }
 
             if let Some(ipaddr) = ipaddr {
-                users.push(User::Ipaddr(String::from(ipaddr)));
+                users.push(User::Ipaddr(ipaddr.parse::<IpCidr>()?));
             }
 
             if let Some(mqtt_protocol) = mqtt_protocol {
@@ -382,3 +395,105 @@ impl std::convert::TryFrom<Option<&serde_json::Value>> for Topics {
         })
     }
 }
+
+///When a rule is in effect, all the given constraints must be met:
+///
+///- `start`, `end`: RFC 3339 times, e.g. "2024-06-01T02:00:00+08:00", `end` is excluded
+///- `days`: weekdays, e.g. ["sat", "sun"]
+///- `time`: daily window, e.g. "02:00-04:00", a window such as "22:00-02:00" ends on the next day and
+///  belongs to the day it starts on
+///- `timezone`: IANA name of the timezone of `days` and `time`, e.g. "Europe/Berlin", the local
+///  timezone of the broker by default
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    start: Option<DateTime<FixedOffset>>,
+    end: Option<DateTime<FixedOffset>>,
+    days: Option<Vec<Weekday>>,
+    time: Option<(NaiveTime, NaiveTime)>,
+    timezone: Option<Tz>,
+}
+
+impl Schedule {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        if self.start.map(|start| now < start).unwrap_or_default() {
+            return false;
+        }
+        if self.end.map(|end| now >= end).unwrap_or_default() {
+            return false;
+        }
+        let (weekday, time) = match &self.timezone {
+            Some(tz) => {
+                let now = now.with_timezone(tz);
+                (now.weekday(), now.time())
+            }
+            None => {
+                let now = now.with_timezone(&chrono::Local);
+                (now.weekday(), now.time())
+            }
+        };
+        let day = match self.time {
+            None => weekday,
+            Some((from, to)) if from <= to => {
+                if time < from || time >= to {
+                    return false;
+                }
+                weekday
+            }
+            Some((from, to)) => {
+                if time >= from {
+                    weekday
+                } else if time < to {
+                    weekday.pred()
+                } else {
+                    return false;
+                }
+            }
+        };
+        self.days.as_ref().map(|days| days.contains(&day)).unwrap_or(true)
+    }
+}
+
+impl std::convert::TryFrom<&serde_json::Value> for Schedule {
+    type Error = MqttError;
+    #[inline]
+    fn try_from(schedule_cfg: &serde_json::Value) -> Result<Self, Self::Error> {
+        let err = |e: &dyn std::fmt::Display| {
+            MqttError::from(format!("ACL Rule config error, schedule config is {:?}, {}", schedule_cfg, e))
+        };
+        let map = schedule_cfg.as_object().ok_or_else(|| err(&"it is not a table"))?;
+        let str_of = |name: &str| match map.get(name) {
+            None => Ok(None),
+            Some(Value::String(v)) => Ok(Some(v.as_str())),
+            Some(_) => Err(err(&format!("{} is not a string", name))),
+        };
+        let datetime = |name: &str| {
+            str_of(name)?.map(|v| DateTime::parse_from_rfc3339(v).map_err(|e| err(&e))).transpose()
+        };
+        let time_of_day = |v: &str| NaiveTime::parse_from_str(v.trim(), "%H:%M").map_err(|e| err(&e));
+
+        let start = datetime("start")?;
+        let end = datetime("end")?;
+        let days = match map.get("days") {
+            None => None,
+            Some(Value::Array(days)) => Some(
+                days.iter()
+                    .map(|day| {
+                        day.as_str()
+                            .ok_or_else(|| err(&"days are not strings"))?
+                            .parse::<Weekday>()
+                            .map_err(|_| err(&format!("invalid weekday {}", day)))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Some(_) => return Err(err(&"days is not an array")),
+        };
+        let time = str_of("time")?
+            .map(|time| {
+                let (from, to) = time.split_once('-').ok_or_else(|| err(&"time is not HH:MM-HH:MM"))?;
+                Ok::<_, MqttError>((time_of_day(from)?, time_of_day(to)?))
+            })
+            .transpose()?;
+        let timezone = str_of("timezone")?.map(|tz| tz.parse::<Tz>().map_err(|e| err(&e))).transpose()?;
+        Ok(Schedule { start, end, days, time, timezone })
+    }
+}
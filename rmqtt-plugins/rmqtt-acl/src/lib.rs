@@ -15,7 +15,7 @@ use rmqtt::{
     tokio::{self, sync::RwLock},
 };
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::types::{AuthResult, PublishAclResult, SubscribeAckReason, SubscribeAclResult, Topic},
     plugin::{PackageInfo, Plugin},
     register, Result, Runtime,
@@ -151,7 +151,7 @@ impl Handler for AclHandler {
                     Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword))
                         | Some(HookResult::AuthResult(AuthResult::NotAuthorized))
                 ) {
-                    return (false, acc);
+                    return (Proceed::Stop, acc);
                 }
 
                 for rule in self.cfg.read().await.rules() {
@@ -163,25 +163,25 @@ impl Handler for AclHandler {
                     let (hit, superuser) = rule.hit(
                         connect_info.id(),
                         connect_info.password(),
-                        Some(connect_info.proto_ver()),
+                        Some(connect_info.protocol_version()),
                         allow,
                     );
                     if hit {
                         log::debug!("{:?} ClientAuthenticate, rule: {:?}", connect_info.id(), rule);
                         return if allow {
-                            (false, Some(HookResult::AuthResult(AuthResult::Allow(superuser))))
+                            (Proceed::Stop, Some(HookResult::AuthResult(AuthResult::Allow(superuser, None))))
                         } else {
-                            (false, Some(HookResult::AuthResult(AuthResult::NotAuthorized)))
+                            (Proceed::Stop, Some(HookResult::AuthResult(AuthResult::NotAuthorized)))
                         };
                     }
                 }
-                return (false, Some(HookResult::AuthResult(AuthResult::NotAuthorized)));
+                return (Proceed::Stop, Some(HookResult::AuthResult(AuthResult::NotAuthorized)));
             }
 
             Parameter::ClientSubscribeCheckAcl(session, subscribe) => {
                 if let Some(HookResult::SubscribeAclResult(acl_result)) = &acc {
                     if acl_result.failure() {
-                        return (false, acc);
+                        return (Proceed::Stop, acc);
                     }
                 }
                 let topic =
@@ -209,7 +209,7 @@ impl Handler for AclHandler {
                     );
                     return if allow {
                         (
-                            false,
+                            Proceed::Stop,
                             Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_success(
                                 subscribe.opts.qos(),
                                 None,
@@ -217,7 +217,7 @@ impl Handler for AclHandler {
                         )
                     } else {
                         (
-                            false,
+                            Proceed::Stop,
                             Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_failure(
                                 SubscribeAckReason::NotAuthorized,
                             ))),
@@ -225,7 +225,7 @@ impl Handler for AclHandler {
                     };
                 }
                 return (
-                    false,
+                    Proceed::Stop,
                     Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_failure(
                         SubscribeAckReason::NotAuthorized,
                     ))),
@@ -234,7 +234,7 @@ impl Handler for AclHandler {
 
             Parameter::MessagePublishCheckAcl(session, publish) => {
                 if let Some(HookResult::PublishAclResult(PublishAclResult::Rejected(_))) = &acc {
-                    return (false, acc);
+                    return (Proceed::Stop, acc);
                 }
                 let topic_str = publish.topic();
                 let topic = Topic::from_str(topic_str).unwrap_or_else(|_| Topic::from(Vec::new()));
@@ -260,10 +260,10 @@ impl Handler for AclHandler {
                         topic_str
                     );
                     return if allow {
-                        (false, Some(HookResult::PublishAclResult(PublishAclResult::Allow)))
+                        (Proceed::Stop, Some(HookResult::PublishAclResult(PublishAclResult::Allow)))
                     } else {
                         (
-                            false,
+                            Proceed::Stop,
                             Some(HookResult::PublishAclResult(PublishAclResult::Rejected(
                                 disconnect_if_pub_rejected,
                             ))),
@@ -271,7 +271,7 @@ impl Handler for AclHandler {
                     };
                 }
                 return (
-                    false,
+                    Proceed::Stop,
                     Some(HookResult::PublishAclResult(PublishAclResult::Rejected(
                         disconnect_if_pub_rejected,
                     ))),
@@ -281,6 +281,6 @@ impl Handler for AclHandler {
                 log::error!("parameter is: {:?}", param);
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
@@ -29,6 +29,9 @@ pub struct PluginConfig {
 
     #[serde(default = "PluginConfig::http_timeout_default", deserialize_with = "deserialize_duration")]
     pub http_timeout: Duration,
+    ///Maximum number of HTTP requests sent at once, 0 means unlimited
+    #[serde(default)]
+    pub http_max_concurrency: usize,
     #[serde(
         default,
         serialize_with = "PluginConfig::serialize_http_headers",
@@ -5,6 +5,8 @@ extern crate serde;
 #[macro_use]
 extern crate rmqtt_macros;
 
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -20,12 +22,12 @@ use rmqtt::ntex::util::ByteString;
 use rmqtt::reqwest::Response;
 use rmqtt::{ahash, async_trait, chrono, log, once_cell::sync::Lazy, reqwest, serde_json, tokio, Id};
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::types::{
         AuthResult, Password, PublishAclResult, SubscribeAckReason, SubscribeAclResult, Superuser,
     },
-    plugin::{PackageInfo, Plugin},
-    register, MqttError, Result, Runtime, TopicName,
+    plugin::{CacheCounter, LatencyRecorder, PackageInfo, Plugin, PluginMetrics, RequestCoalescer},
+    register, MqttError, Result, Runtime, SessionAttrs, TopicName,
 };
 
 mod config;
@@ -34,6 +36,8 @@ type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
 
 const CACHEABLE: &str = "X-Cache";
 const SUPERUSER: &str = "X-Superuser";
+//"X-Limits-Max-Inflight: 16" is returned as the session attribute "limits.max_inflight"
+const LIMITS_PREFIX: &str = "x-limits-";
 
 const CACHE_KEY: &str = "ACL-CACHE-MAP";
 
@@ -57,6 +61,9 @@ impl ResponseResult {
 }
 
 type Cacheable = Option<i64>;
+type Limits = Option<SessionAttrs>;
+//Identical requests in flight share one HTTP request, keyed by the method, URL and parameters
+type Coalescer = RequestCoalescer<String, std::result::Result<(ResponseResult, Limits, Cacheable), String>>;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Copy)]
 enum ACLType {
@@ -80,6 +87,8 @@ struct AuthHttpPlugin {
     runtime: &'static Runtime,
     register: Box<dyn Register>,
     cfg: Arc<RwLock<PluginConfig>>,
+    metrics: Arc<Metrics>,
+    coalescer: Arc<Coalescer>,
 }
 
 impl AuthHttpPlugin {
@@ -89,7 +98,8 @@ impl AuthHttpPlugin {
         let cfg = Arc::new(RwLock::new(runtime.settings.plugins.load_config::<PluginConfig>(&name)?));
         log::debug!("{} AuthHttpPlugin cfg: {:?}", name, cfg.read().await);
         let register = runtime.extends.hook_mgr().await.register();
-        Ok(Self { runtime, register, cfg })
+        let coalescer = Arc::new(Coalescer::new(cfg.read().await.http_max_concurrency));
+        Ok(Self { runtime, register, cfg, metrics: Arc::new(Metrics::default()), coalescer })
     }
 }
 
@@ -99,14 +109,30 @@ impl Plugin for AuthHttpPlugin {
     async fn init(&mut self) -> Result<()> {
         log::info!("{} init", self.name());
         let cfg = &self.cfg;
+        let metrics = &self.metrics;
+        let coalescer = &self.coalescer;
 
         let priority = cfg.read().await.priority;
-        self.register.add_priority(Type::ClientAuthenticate, priority, Box::new(AuthHandler::new(cfg))).await;
         self.register
-            .add_priority(Type::ClientSubscribeCheckAcl, priority, Box::new(AuthHandler::new(cfg)))
+            .add_priority(
+                Type::ClientAuthenticate,
+                priority,
+                Box::new(AuthHandler::new(cfg, metrics, coalescer)),
+            )
             .await;
         self.register
-            .add_priority(Type::MessagePublishCheckAcl, priority, Box::new(AuthHandler::new(cfg)))
+            .add_priority(
+                Type::ClientSubscribeCheckAcl,
+                priority,
+                Box::new(AuthHandler::new(cfg, metrics, coalescer)),
+            )
+            .await;
+        self.register
+            .add_priority(
+                Type::MessagePublishCheckAcl,
+                priority,
+                Box::new(AuthHandler::new(cfg, metrics, coalescer)),
+            )
             .await;
 
         Ok(())
@@ -120,6 +146,7 @@ impl Plugin for AuthHttpPlugin {
     #[inline]
     async fn load_config(&mut self) -> Result<()> {
         let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        self.coalescer.set_max_concurrency(new_cfg.http_max_concurrency);
         *self.cfg.write().await = new_cfg;
         log::debug!("load_config ok,  {:?}", self.cfg);
         Ok(())
@@ -141,20 +168,38 @@ impl Plugin for AuthHttpPlugin {
 
     #[inline]
     async fn attrs(&self) -> serde_json::Value {
-        serde_json::json!({})
+        PluginMetrics {
+            queue_depth: Some(self.coalescer.waiting()),
+            cache: Some(self.metrics.acl_cache.metrics()),
+            latency: Some(self.metrics.latency.metrics()),
+            errors: Some(self.metrics.errors.load(Ordering::Relaxed)),
+            ..Default::default()
+        }
+        .attrs(serde_json::json!({ "coalesced_requests": self.coalescer.coalesced() }))
     }
 }
 
+#[derive(Default)]
+struct Metrics {
+    //publish ACL results cached in the session
+    acl_cache: CacheCounter,
+    //HTTP requests
+    latency: LatencyRecorder,
+    errors: AtomicU64,
+}
+
 struct AuthHandler {
     cfg: Arc<RwLock<PluginConfig>>,
+    metrics: Arc<Metrics>,
+    coalescer: Arc<Coalescer>,
 }
 
 impl AuthHandler {
-    fn new(cfg: &Arc<RwLock<PluginConfig>>) -> Self {
-        Self { cfg: cfg.clone() }
+    fn new(cfg: &Arc<RwLock<PluginConfig>>, metrics: &Arc<Metrics>, coalescer: &Arc<Coalescer>) -> Self {
+        Self { cfg: cfg.clone(), metrics: metrics.clone(), coalescer: coalescer.clone() }
     }
 
-    async fn response_result(resp: Response) -> Result<(ResponseResult, Superuser, Cacheable)> {
+    async fn response_result(resp: Response) -> Result<(ResponseResult, Limits, Cacheable)> {
         if resp.status().is_success() {
             let superuser = resp.headers().contains_key(SUPERUSER);
             let cache_timeout = if let Some(tm) = resp.headers().get(CACHEABLE).and_then(|v| v.to_str().ok())
@@ -170,10 +215,38 @@ impl AuthHandler {
                 None
             };
             log::debug!("Cache timeout is {:?}", cache_timeout);
+            let limits = Self::limits(resp.headers());
             let body = resp.text().await.map_err(|e| MqttError::Msg(e.to_string()))?;
-            Ok((ResponseResult::from(body.as_str(), superuser), superuser, cache_timeout))
+            Ok((ResponseResult::from(body.as_str(), superuser), limits, cache_timeout))
+        } else {
+            Ok((ResponseResult::Ignore, None, None))
+        }
+    }
+
+    //Per-client limits, see the `limits.*` session attributes of the broker
+    fn limits(headers: &HeaderMap) -> Limits {
+        let mut attrs = SessionAttrs::new();
+        for (name, value) in headers.iter() {
+            if let Some(name) = name.as_str().strip_prefix(LIMITS_PREFIX) {
+                let value = match value.to_str() {
+                    Ok(value) => value,
+                    Err(e) => {
+                        log::warn!("Parse {} error, {:?}", name, e);
+                        continue;
+                    }
+                };
+                let key = format!("limits.{}", name.replace('-', "_"));
+                if let Ok(v) = value.parse::<i64>() {
+                    attrs.insert(key, v);
+                } else {
+                    attrs.insert(key, value);
+                }
+            }
+        }
+        if attrs.is_empty() {
+            None
         } else {
-            Ok((ResponseResult::Ignore, false, None))
+            Some(attrs)
         }
     }
 
@@ -182,7 +255,7 @@ impl AuthHandler {
         body: &T,
         headers: HeaderMap,
         timeout: Duration,
-    ) -> Result<(ResponseResult, Superuser, Cacheable)> {
+    ) -> Result<(ResponseResult, Limits, Cacheable)> {
         log::debug!("http_get_request, timeout: {:?}, url: {}", timeout, url);
         match HTTP_CLIENT
             .as_ref()?
@@ -208,7 +281,7 @@ impl AuthHandler {
         body: &T,
         headers: HeaderMap,
         timeout: Duration,
-    ) -> Result<(ResponseResult, Superuser, Cacheable)> {
+    ) -> Result<(ResponseResult, Limits, Cacheable)> {
         log::debug!("http_form_request, method: {:?}, timeout: {:?}, url: {}", method, timeout, url);
         match HTTP_CLIENT
             .as_ref()?
@@ -234,7 +307,7 @@ impl AuthHandler {
         body: &T,
         headers: HeaderMap,
         timeout: Duration,
-    ) -> Result<(ResponseResult, Superuser, Cacheable)> {
+    ) -> Result<(ResponseResult, Limits, Cacheable)> {
         log::debug!("http_json_request, method: {:?}, timeout: {:?}, url: {}", method, timeout, url);
         match HTTP_CLIENT
             .as_ref()?
@@ -265,7 +338,7 @@ impl AuthHandler {
             if let Some(p) = password { ByteString::try_from(p.clone())? } else { ByteString::default() };
         let client_id = id.client_id.as_ref();
         let username = id.username.as_ref().map(|n| n.as_ref()).unwrap_or("");
-        let remote_addr = id.remote_addr.map(|addr| addr.ip().to_string()).unwrap_or_default();
+        let remote_addr = id.remote_ip().map(|ip| ip.to_string()).unwrap_or_default();
         for v in params.values_mut() {
             *v = v.replace("%u", username);
             *v = v.replace("%c", client_id);
@@ -293,7 +366,7 @@ impl AuthHandler {
         password: Option<&Password>,
         protocol: Option<u8>,
         sub_or_pub: Option<(ACLType, &TopicName)>,
-    ) -> Result<(ResponseResult, Cacheable)> {
+    ) -> Result<(ResponseResult, Limits, Cacheable)> {
         log::debug!("{:?} req_cfg.url.path(): {:?}", id, req_cfg.url.path());
         let (headers, timeout) = {
             let cfg = self.cfg.read().await;
@@ -310,42 +383,62 @@ impl AuthHandler {
             (headers, cfg.http_timeout)
         };
 
-        let (auth_result, superuser, cacheable) = if req_cfg.is_get() {
-            let body = &mut req_cfg.params;
-            Self::replaces(body, id, password, protocol, sub_or_pub)?;
-            Self::http_get_request(req_cfg.url, body, headers, timeout).await?
-        } else if req_cfg.json_body() {
-            let body = &mut req_cfg.params;
-            Self::replaces(body, id, password, protocol, sub_or_pub)?;
-            Self::http_json_request(req_cfg.url, req_cfg.method, body, headers, timeout).await?
-        } else {
-            //form body
-            let body = &mut req_cfg.params;
-            Self::replaces(body, id, password, protocol, sub_or_pub)?;
-            Self::http_form_request(req_cfg.url, req_cfg.method, body, headers, timeout).await?
-        };
-        log::debug!("auth_result: {:?}, superuser: {}, cacheable: {:?}", auth_result, superuser, cacheable);
-        Ok((auth_result, cacheable))
+        let (is_get, json_body) = (req_cfg.is_get(), req_cfg.json_body());
+        let body = &mut req_cfg.params;
+        Self::replaces(body, id, password, protocol, sub_or_pub)?;
+        let (url, method) = (req_cfg.url, req_cfg.method);
+        let key = format!("{} {} {:?}", method, url, body.iter().collect::<BTreeMap<_, _>>());
+        let metrics = &self.metrics;
+        let res = self
+            .coalescer
+            .call(key, async move {
+                let res = metrics
+                    .latency
+                    .time(async move {
+                        if is_get {
+                            Self::http_get_request(url, body, headers, timeout).await
+                        } else if json_body {
+                            Self::http_json_request(url, method, body, headers, timeout).await
+                        } else {
+                            //form body
+                            Self::http_form_request(url, method, body, headers, timeout).await
+                        }
+                    })
+                    .await;
+                if res.is_err() {
+                    metrics.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                res.map_err(|e| e.to_string())
+            })
+            .await;
+        let (auth_result, limits, cacheable) = res.map_err(MqttError::Msg)?;
+        log::debug!("auth_result: {:?}, limits: {:?}, cacheable: {:?}", auth_result, limits, cacheable);
+        Ok((auth_result, limits, cacheable))
     }
 
-    async fn auth(&self, id: &Id, password: Option<&Password>, protocol: Option<u8>) -> ResponseResult {
+    async fn auth(
+        &self,
+        id: &Id,
+        password: Option<&Password>,
+        protocol: Option<u8>,
+    ) -> (ResponseResult, Limits) {
         if let Some(req) = { self.cfg.read().await.http_auth_req.clone() } {
             match self.request(id, req, password, protocol, None).await {
-                Ok((auth_res, _)) => {
+                Ok((auth_res, limits, _)) => {
                     log::debug!("auth result: {:?}", auth_res);
-                    auth_res
+                    (auth_res, limits)
                 }
                 Err(e) => {
                     log::warn!("{:?} auth error, {:?}", id, e);
                     if self.cfg.read().await.deny_if_error {
-                        ResponseResult::Deny
+                        (ResponseResult::Deny, None)
                     } else {
-                        ResponseResult::Ignore
+                        (ResponseResult::Ignore, None)
                     }
                 }
             }
         } else {
-            ResponseResult::Ignore
+            (ResponseResult::Ignore, None)
         }
     }
 
@@ -357,9 +450,9 @@ impl AuthHandler {
     ) -> (ResponseResult, Cacheable) {
         if let Some(req) = { self.cfg.read().await.http_acl_req.clone() } {
             match self.request(id, req, None, protocol, sub_or_pub).await {
-                Ok(acl_res) => {
+                Ok((acl_res, _, cacheable)) => {
                     log::debug!("acl result: {:?}", acl_res);
-                    acl_res
+                    (acl_res, cacheable)
                 }
                 Err(e) => {
                     log::warn!("{:?} acl error, {:?}", id, e);
@@ -387,27 +480,27 @@ impl Handler for AuthHandler {
                     Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword))
                         | Some(HookResult::AuthResult(AuthResult::NotAuthorized))
                 ) {
-                    return (false, acc);
+                    return (Proceed::Stop, acc);
                 }
 
                 return match self
-                    .auth(connect_info.id(), connect_info.password(), Some(connect_info.proto_ver()))
+                    .auth(connect_info.id(), connect_info.password(), Some(connect_info.protocol_version()))
                     .await
                 {
-                    ResponseResult::Allow(superuser) => {
-                        (false, Some(HookResult::AuthResult(AuthResult::Allow(superuser))))
+                    (ResponseResult::Allow(superuser), limits) => {
+                        (Proceed::Stop, Some(HookResult::AuthResult(AuthResult::Allow(superuser, limits))))
                     }
-                    ResponseResult::Deny => {
-                        (false, Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword)))
+                    (ResponseResult::Deny, _) => {
+                        (Proceed::Stop, Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword)))
                     }
-                    ResponseResult::Ignore => (true, None),
+                    (ResponseResult::Ignore, _) => (Proceed::Continue, None),
                 };
             }
 
             Parameter::ClientSubscribeCheckAcl(session, subscribe) => {
                 if let Some(HookResult::SubscribeAclResult(acl_result)) = &acc {
                     if acl_result.failure() {
-                        return (false, acc);
+                        return (Proceed::Stop, acc);
                     }
                 }
 
@@ -421,26 +514,26 @@ impl Handler for AuthHandler {
                     .await;
                 return match acl_res {
                     ResponseResult::Allow(_) => (
-                        false,
+                        Proceed::Stop,
                         Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_success(
                             subscribe.opts.qos(),
                             None,
                         ))),
                     ),
                     ResponseResult::Deny => (
-                        false,
+                        Proceed::Stop,
                         Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_failure(
                             SubscribeAckReason::NotAuthorized,
                         ))),
                     ),
-                    ResponseResult::Ignore => (true, None),
+                    ResponseResult::Ignore => (Proceed::Continue, None),
                 };
             }
 
             Parameter::MessagePublishCheckAcl(session, publish) => {
                 log::debug!("MessagePublishCheckAcl");
                 if let Some(HookResult::PublishAclResult(PublishAclResult::Rejected(_))) = &acc {
-                    return (false, acc);
+                    return (Proceed::Stop, acc);
                 }
 
                 let acl_res = if let Some((acl_res, expire)) = session
@@ -460,8 +553,10 @@ impl Handler for AuthHandler {
                 };
 
                 let acl_res = if let Some(acl_res) = acl_res {
+                    self.metrics.acl_cache.hit();
                     acl_res
                 } else {
+                    self.metrics.acl_cache.miss();
                     //ResponseResult, Cacheable
                     let (acl_res, cacheable) = self
                         .acl(
@@ -486,22 +581,22 @@ impl Handler for AuthHandler {
 
                 return match acl_res {
                     ResponseResult::Allow(_) => {
-                        (false, Some(HookResult::PublishAclResult(PublishAclResult::Allow)))
+                        (Proceed::Stop, Some(HookResult::PublishAclResult(PublishAclResult::Allow)))
                     }
                     ResponseResult::Deny => (
-                        false,
+                        Proceed::Stop,
                         Some(HookResult::PublishAclResult(PublishAclResult::Rejected(
                             self.cfg.read().await.disconnect_if_pub_rejected,
                         ))),
                     ),
-                    ResponseResult::Ignore => (true, None),
+                    ResponseResult::Ignore => (Proceed::Continue, None),
                 };
             }
             _ => {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
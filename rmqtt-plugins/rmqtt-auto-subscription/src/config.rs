@@ -1,3 +1,4 @@
+use regex::Regex;
 use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::{self, Serialize};
 
@@ -49,13 +50,22 @@ impl PluginConfig {
                         RetainHandling::NoAtSubscribe => 3,
                     })
                     .unwrap_or(0);
-                json!({
+                let mut item_json = json!({
                     "topic_filter": topic_filter,
                     "qos": qos,
                     "no_local": no_local,
                     "retain_as_published": retain_as_published,
                     "retain_handling": retain_handling,
-                })
+                });
+                if let Some(obj) = item_json.as_object_mut() {
+                    if let Some(re) = &item.clientid_regex {
+                        obj.insert("clientid_regex".into(), serde_json::Value::String(re.to_string()));
+                    }
+                    if let Some(re) = &item.username_regex {
+                        obj.insert("username_regex".into(), serde_json::Value::String(re.to_string()));
+                    }
+                }
+                item_json
             })
             .collect::<Vec<_>>()
             .serialize(s)
@@ -109,10 +119,24 @@ impl PluginConfig {
                         .map_err(de::Error::custom)?;
                     let has_clientid_placeholder = topic_filter.contains("${clientid}");
                     let has_username_placeholder = topic_filter.contains("${username}");
+                    let clientid_regex = objs
+                        .get("clientid_regex")
+                        .and_then(|re| re.as_str())
+                        .map(Regex::new)
+                        .transpose()
+                        .map_err(de::Error::custom)?;
+                    let username_regex = objs
+                        .get("username_regex")
+                        .and_then(|re| re.as_str())
+                        .map(Regex::new)
+                        .transpose()
+                        .map_err(de::Error::custom)?;
                     subscribes.push(SubscribeItem {
                         sub,
                         has_clientid_placeholder,
                         has_username_placeholder,
+                        clientid_regex,
+                        username_regex,
                     });
                 }
             }
@@ -131,4 +155,26 @@ pub struct SubscribeItem {
     pub sub: Subscribe,
     pub has_clientid_placeholder: bool,
     pub has_username_placeholder: bool,
+    pub clientid_regex: Option<Regex>,
+    pub username_regex: Option<Regex>,
+}
+
+impl SubscribeItem {
+    ///Returns true if the client matches the clientid and username patterns of this item,
+    ///an item without patterns applies to all clients.
+    #[inline]
+    pub fn is_match(&self, client_id: &str, username: Option<&str>) -> bool {
+        if let Some(re) = &self.clientid_regex {
+            if !re.is_match(client_id) {
+                return false;
+            }
+        }
+        if let Some(re) = &self.username_regex {
+            match username {
+                Some(username) if re.is_match(username) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
 }
@@ -99,6 +99,9 @@ impl AutoSubscription for &'static XAutoSubscription {
     #[inline]
     async fn subscribe(&self, id: &Id, msg_tx: &Tx) -> Result<()> {
         for item in self.cfg.read().await.subscribes.iter() {
+            if !item.is_match(&id.client_id, id.username.as_deref()) {
+                continue;
+            }
             let (tx, rx) = oneshot::channel();
             let mut sub = item.sub.clone();
             if item.has_clientid_placeholder {
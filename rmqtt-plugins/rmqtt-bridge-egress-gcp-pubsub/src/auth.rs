@@ -0,0 +1,76 @@
+use std::sync::Mutex;
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+
+use rmqtt::{anyhow::anyhow, serde_json, timestamp_secs, MqttError, Result, Timestamp};
+
+//Pub/Sub accepts JWTs signed by the service account itself, no OAuth token exchange is needed
+const AUDIENCE: &str = "https://pubsub.googleapis.com/";
+const TOKEN_LIFETIME: Timestamp = 3600;
+//A new token is signed this long before the current one expires
+const TOKEN_REFRESH_MARGIN: Timestamp = 300;
+
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key_id: String,
+    private_key: String,
+    #[serde(default)]
+    project_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    sub: &'a str,
+    aud: &'a str,
+    iat: Timestamp,
+    exp: Timestamp,
+}
+
+pub(crate) struct ServiceAccount {
+    client_email: String,
+    private_key_id: String,
+    key: EncodingKey,
+    pub(crate) project_id: Option<String>,
+    token: Mutex<Option<(String, Timestamp)>>,
+}
+
+impl ServiceAccount {
+    pub(crate) fn from_file(path: &str) -> Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| MqttError::from(format!("read credentials file {} error, {:?}", path, e)))?;
+        let sa_key: ServiceAccountKey = serde_json::from_slice(&data)?;
+        let key = EncodingKey::from_rsa_pem(sa_key.private_key.as_bytes()).map_err(|e| anyhow!(e))?;
+        Ok(Self {
+            client_email: sa_key.client_email,
+            private_key_id: sa_key.private_key_id,
+            key,
+            project_id: sa_key.project_id,
+            token: Mutex::new(None),
+        })
+    }
+
+    ///The cached JWT, a new one is signed when it is about to expire
+    pub(crate) fn token(&self) -> Result<String> {
+        let now = timestamp_secs();
+        let mut token = self.token.lock().map_err(|e| anyhow!(e.to_string()))?;
+        if let Some((jwt, exp)) = token.as_ref() {
+            if *exp - TOKEN_REFRESH_MARGIN > now {
+                return Ok(jwt.clone());
+            }
+        }
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(self.private_key_id.clone());
+        let claims = Claims {
+            iss: &self.client_email,
+            sub: &self.client_email,
+            aud: AUDIENCE,
+            iat: now,
+            exp: now + TOKEN_LIFETIME,
+        };
+        let jwt = encode(&header, &claims, &self.key).map_err(|e| anyhow!(e))?;
+        token.replace((jwt.clone(), now + TOKEN_LIFETIME));
+        Ok(jwt)
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rmqtt::{
+    anyhow::anyhow,
+    base64::{engine::general_purpose, Engine as _},
+    bytestring::ByteString,
+    log, reqwest, tokio,
+    tokio::sync::mpsc::{self, error::TrySendError},
+    tokio::sync::RwLock,
+    DashMap,
+};
+use rmqtt::{
+    broker::topic::{TopicTree, VecToTopic},
+    From, HashMap, MqttError, Publish, QoSEx, Result, Topic,
+};
+
+use crate::auth::ServiceAccount;
+use crate::config::{Bridge, Entry, LimitExceeded, PluginConfig};
+
+//Pub/Sub refuses publish requests over 10MB, payloads grow by a third when base64 encoded
+const MAX_BATCH_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Command {
+    Start,
+    Close,
+}
+
+enum Message {
+    Publish(From, Publish),
+    Close,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PubsubMessage {
+    data: String,
+    attributes: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ordering_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PublishRequest<'a> {
+    messages: &'a [PubsubMessage],
+}
+
+#[derive(Default)]
+pub(crate) struct Stats {
+    pub(crate) published: AtomicUsize,
+    pub(crate) failed: AtomicUsize,
+    pub(crate) dropped: AtomicUsize,
+}
+
+#[derive(Clone)]
+pub struct Producer {
+    pub(crate) cfg: Arc<Bridge>,
+    pub(crate) stats: Arc<Stats>,
+    tx: mpsc::Sender<Message>,
+}
+
+impl Producer {
+    pub(crate) fn from(
+        http: reqwest::Client,
+        sa: Arc<ServiceAccount>,
+        project_id: String,
+        cfg: Arc<Bridge>,
+        cfg_entry: Entry,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(cfg.flow_control.max_outstanding_messages.max(1));
+        let stats = Arc::new(Stats::default());
+        let publisher = Publisher { http, sa, project_id, cfg: cfg.clone(), cfg_entry, stats: stats.clone() };
+        tokio::spawn(publisher.start(rx));
+        Producer { cfg, stats, tx }
+    }
+
+    ///Messages waiting to be published
+    #[inline]
+    pub(crate) fn outstanding(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    #[inline]
+    pub(crate) async fn send(&self, f: &From, p: &Publish) -> Result<()> {
+        let msg = Message::Publish(f.clone(), p.clone());
+        match self.cfg.flow_control.limit_exceeded {
+            LimitExceeded::Block => {
+                self.tx.send(msg).await.map_err(|_| MqttError::from("publisher is closed"))?;
+            }
+            LimitExceeded::Drop => match self.tx.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.stats.dropped.fetch_add(1, Ordering::SeqCst);
+                    return Err(MqttError::from(format!(
+                        "{} max_outstanding_messages exceeded, message dropped, topic: {}",
+                        self.cfg.name, p.topic
+                    )));
+                }
+                Err(TrySendError::Closed(_)) => return Err(MqttError::from("publisher is closed")),
+            },
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn close(&self) {
+        if let Err(e) = self.tx.send(Message::Close).await {
+            log::error!("{:?}", e.to_string());
+        }
+    }
+}
+
+///Publishes the messages of an entry one batch at a time, which keeps the order of messages
+///with the same ordering key
+struct Publisher {
+    http: reqwest::Client,
+    sa: Arc<ServiceAccount>,
+    project_id: String,
+    cfg: Arc<Bridge>,
+    cfg_entry: Entry,
+    stats: Arc<Stats>,
+}
+
+impl Publisher {
+    async fn start(self, mut rx: mpsc::Receiver<Message>) {
+        let batch_size = self.cfg.batch_size.max(1);
+        let mut closed = false;
+        while !closed {
+            let mut batch: BTreeMap<String, Vec<PubsubMessage>> = BTreeMap::new();
+            let mut batch_len = 0;
+            let mut batch_bytes = 0;
+            let mut next = rx.recv().await;
+            loop {
+                match next {
+                    Some(Message::Publish(f, p)) => {
+                        batch_len += 1;
+                        batch_bytes += p.payload.len();
+                        let topic = self.cfg_entry.remote.make_topic(&self.project_id, &p.topic);
+                        batch.entry(topic).or_default().push(self.to_message(&f, &p));
+                    }
+                    Some(Message::Close) | None => {
+                        closed = true;
+                        break;
+                    }
+                }
+                if batch_len >= batch_size || batch_bytes >= MAX_BATCH_BYTES {
+                    break;
+                }
+                next = match rx.try_recv() {
+                    Ok(msg) => Some(msg),
+                    Err(_) => break,
+                };
+            }
+            for (topic, messages) in batch {
+                self.publish(&topic, &messages).await;
+            }
+        }
+        log::info!("{} exit pubsub publisher, topic: {}", self.cfg.name, self.cfg_entry.remote.topic.0);
+    }
+
+    fn to_message(&self, f: &From, p: &Publish) -> PubsubMessage {
+        let remote = &self.cfg_entry.remote;
+        let mut attributes = HashMap::default();
+        if remote.forward_all_from {
+            attributes.insert("from_type".into(), f.typ().as_str().into());
+            attributes.insert("from_node".into(), f.node().to_string());
+            if let Some(addr) = f.remote_addr {
+                attributes.insert("from_ipaddress".into(), addr.to_string());
+            }
+            attributes.insert("from_clientid".into(), f.client_id.to_string());
+            attributes.insert("from_username".into(), f.username_ref().into());
+        }
+        if remote.forward_all_publish {
+            attributes.insert("dup".into(), p.dup().to_string());
+            attributes.insert("retain".into(), p.retain().to_string());
+            attributes.insert("qos".into(), p.qos().value().to_string());
+            if let Some(packet_id) = p.packet_id() {
+                attributes.insert("packet_id".into(), packet_id.to_string());
+            }
+        }
+        attributes.insert("topic".into(), p.topic().to_string());
+        PubsubMessage {
+            data: general_purpose::STANDARD.encode(p.payload()),
+            attributes,
+            ordering_key: remote.make_ordering_key(f, p),
+        }
+    }
+
+    async fn publish(&self, topic: &str, messages: &[PubsubMessage]) {
+        let url = format!("{}/v1/{}:publish", self.cfg.endpoint.trim_end_matches('/'), topic);
+        let mut attempts = 0;
+        loop {
+            match self.try_publish(&url, messages).await {
+                Ok(()) => {
+                    self.stats.published.fetch_add(messages.len(), Ordering::SeqCst);
+                    log::debug!("{} published {} messages to {}", self.cfg.name, messages.len(), topic);
+                    return;
+                }
+                Err((e, retryable)) if retryable && attempts < self.cfg.max_retries => {
+                    attempts += 1;
+                    log::warn!("{} publish to {} error, retry {}, {}", self.cfg.name, topic, attempts, e);
+                    tokio::time::sleep(self.cfg.retry_interval).await;
+                }
+                Err((e, _)) => {
+                    self.stats.failed.fetch_add(messages.len(), Ordering::SeqCst);
+                    log::error!(
+                        "{} publish to {} error, {} messages dropped, {}",
+                        self.cfg.name,
+                        topic,
+                        messages.len(),
+                        e
+                    );
+                    return;
+                }
+            }
+        }
+    }
+
+    ///On error, whether the request may succeed if it is sent again
+    async fn try_publish(
+        &self,
+        url: &str,
+        messages: &[PubsubMessage],
+    ) -> std::result::Result<(), (MqttError, bool)> {
+        let token = self.sa.token().map_err(|e| (e, false))?;
+        let resp = self
+            .http
+            .post(url)
+            .bearer_auth(token)
+            .json(&PublishRequest { messages })
+            .send()
+            .await
+            .map_err(|e| (MqttError::from(anyhow!(e)), true))?;
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = resp.text().await.unwrap_or_default();
+        let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        Err((MqttError::from(format!("{}, {}", status, body)), retryable))
+    }
+}
+
+pub(crate) type BridgeName = ByteString;
+type SourceKey = (BridgeName, EntryIndex);
+
+type EntryIndex = usize;
+
+#[derive(Clone)]
+pub(crate) struct BridgeManager {
+    cfg: Arc<RwLock<PluginConfig>>,
+    sinks: Arc<DashMap<SourceKey, Producer>>,
+    topics: Arc<RwLock<TopicTree<(BridgeName, EntryIndex)>>>,
+}
+
+impl BridgeManager {
+    pub async fn new(cfg: Arc<RwLock<PluginConfig>>) -> Self {
+        Self { cfg, sinks: Arc::new(DashMap::default()), topics: Arc::new(RwLock::new(TopicTree::default())) }
+    }
+
+    pub async fn start(&mut self) -> Result<()> {
+        let mut topics = self.topics.write().await;
+        let bridges = self.cfg.read().await.bridges.clone();
+        let mut bridge_names: HashSet<&str> = HashSet::default();
+        for b_cfg in &bridges {
+            if !b_cfg.enable {
+                continue;
+            }
+            if bridge_names.contains(&b_cfg.name as &str) {
+                return Err(MqttError::from(format!("The bridge name already exists! {:?}", b_cfg.name)));
+            }
+
+            let sa = Arc::new(ServiceAccount::from_file(&b_cfg.credentials_file)?);
+            let project_id = b_cfg.project_id.clone().or_else(|| sa.project_id.clone()).ok_or_else(|| {
+                MqttError::from(format!("project_id is not configured, bridge: {}", b_cfg.name))
+            })?;
+            let http = reqwest::Client::builder()
+                .timeout(b_cfg.request_timeout)
+                .build()
+                .map_err(|e| MqttError::from(anyhow!(e)))?;
+            let cfg = Arc::new(b_cfg.clone());
+
+            bridge_names.insert(&b_cfg.name);
+            for (entry_idx, entry) in b_cfg.entries.iter().enumerate() {
+                if entry.remote.topic.0.is_empty() {
+                    return Err(MqttError::from(format!(
+                        "remote.topic is not configured, bridge: {}, entry: {}",
+                        b_cfg.name, entry_idx
+                    )));
+                }
+                log::debug!("entry.local.topic_filter: {}", entry.local.topic_filter);
+                topics.insert(
+                    &Topic::from_str(entry.local.topic_filter.as_str())?,
+                    (b_cfg.name.clone(), entry_idx),
+                );
+
+                let producer =
+                    Producer::from(http.clone(), sa.clone(), project_id.clone(), cfg.clone(), entry.clone());
+                self.sinks.insert((b_cfg.name.clone(), entry_idx), producer);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn stop(&mut self) {
+        for entry in self.sinks.iter() {
+            let ((bridge_name, entry_idx), producer) = entry.pair();
+            log::debug!("stop bridge_name: {:?}, entry_idx: {:?}", bridge_name, entry_idx);
+            producer.close().await;
+        }
+        self.sinks.clear();
+        *self.topics.write().await = TopicTree::default();
+    }
+
+    pub(crate) fn sinks(&self) -> &DashMap<SourceKey, Producer> {
+        &self.sinks
+    }
+
+    #[inline]
+    pub(crate) async fn send(&self, f: &From, p: &Publish) -> Result<()> {
+        let topic = Topic::from_str(&p.topic)?;
+        for (topic_filter, bridge_infos) in { self.topics.read().await.matches(&topic) }.iter() {
+            let topic_filter = topic_filter.to_topic_filter();
+            log::debug!("topic_filter: {:?}", topic_filter);
+            log::debug!("bridge_infos: {:?}", bridge_infos);
+            for (name, entry_idx) in bridge_infos {
+                //The map entry is not held while waiting for room in the queue
+                let producer = self.sinks.get(&(name.clone(), *entry_idx)).map(|p| p.value().clone());
+                if let Some(producer) = producer {
+                    if let Err(e) = producer.send(f, p).await {
+                        log::warn!("{}", e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use serde::de::{Deserialize, Deserializer};
+
+use rmqtt::{settings::deserialize_duration, From, Publish, Result};
+
+use crate::bridge::BridgeName;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(default)]
+    pub bridges: Vec<Bridge>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct Bridge {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default)]
+    pub name: BridgeName,
+
+    ///Service account key file (JSON), the JWTs used for authentication are signed with its private key
+    pub credentials_file: String,
+    ///Defaults to the project of the service account
+    #[serde(default)]
+    pub project_id: Option<String>,
+    ///Regional endpoints, e.g. "https://us-east1-pubsub.googleapis.com", keep ordered messages in one region
+    #[serde(default = "Bridge::endpoint_default")]
+    pub endpoint: String,
+    #[serde(default = "Bridge::request_timeout_default", deserialize_with = "deserialize_duration")]
+    pub request_timeout: Duration,
+
+    ///Maximum number of messages in one publish request
+    #[serde(default = "Bridge::batch_size_default")]
+    pub batch_size: usize,
+    ///Attempts of a publish request that failed, 0 means no retry
+    #[serde(default = "Bridge::max_retries_default")]
+    pub max_retries: usize,
+    #[serde(default = "Bridge::retry_interval_default", deserialize_with = "deserialize_duration")]
+    pub retry_interval: Duration,
+
+    #[serde(default)]
+    pub flow_control: FlowControl,
+
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+}
+
+impl Bridge {
+    fn endpoint_default() -> String {
+        "https://pubsub.googleapis.com".into()
+    }
+
+    fn request_timeout_default() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn batch_size_default() -> usize {
+        100
+    }
+
+    fn max_retries_default() -> usize {
+        3
+    }
+
+    fn retry_interval_default() -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FlowControl {
+    ///Maximum number of messages of an entry waiting to be published
+    #[serde(default = "FlowControl::max_outstanding_messages_default")]
+    pub max_outstanding_messages: usize,
+    #[serde(default)]
+    pub limit_exceeded: LimitExceeded,
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        Self {
+            max_outstanding_messages: FlowControl::max_outstanding_messages_default(),
+            limit_exceeded: LimitExceeded::default(),
+        }
+    }
+}
+
+impl FlowControl {
+    fn max_outstanding_messages_default() -> usize {
+        10_000
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitExceeded {
+    ///The publishing client waits until there is room, the backpressure reaches the broker
+    #[default]
+    Block,
+    ///The message is not forwarded
+    Drop,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct Entry {
+    #[serde(default)]
+    pub local: Local,
+
+    #[serde(default)]
+    pub remote: Remote,
+}
+
+type HasPattern = bool; //${local.topic}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct Remote {
+    ///Topic id, or the full name "projects/{project}/topics/{topic}"
+    #[serde(default, deserialize_with = "Remote::deserialize_topic")]
+    pub topic: (String, HasPattern),
+    ///Template with ${client_id}, ${username} and ${local.topic}, messages with the same ordering key
+    ///are delivered in order to subscriptions that have message ordering enabled
+    #[serde(default)]
+    pub ordering_key: Option<String>,
+    #[serde(default)]
+    pub forward_all_from: bool,
+    #[serde(default)]
+    pub forward_all_publish: bool,
+}
+
+impl Remote {
+    #[inline]
+    pub fn make_topic(&self, project_id: &str, local_topic: &str) -> String {
+        let topic = if self.topic.1 {
+            self.topic.0.replace("${local.topic}", local_topic.replace('/', "-").as_str())
+        } else {
+            self.topic.0.clone()
+        };
+        if topic.starts_with("projects/") {
+            topic
+        } else {
+            format!("projects/{}/topics/{}", project_id, topic)
+        }
+    }
+
+    #[inline]
+    pub fn make_ordering_key(&self, f: &From, p: &Publish) -> Option<String> {
+        self.ordering_key.as_ref().map(|key| {
+            key.replace("${client_id}", &f.client_id)
+                .replace("${username}", f.username_ref())
+                .replace("${local.topic}", &p.topic)
+        })
+    }
+
+    pub fn deserialize_topic<'de, D>(deserializer: D) -> Result<(String, HasPattern), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let topic = String::deserialize(deserializer)?;
+        let has_pattern = topic.contains("${local.topic}");
+        Ok((topic, has_pattern))
+    }
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct Local {
+    #[serde(default)]
+    pub topic_filter: String,
+}
@@ -7,9 +7,11 @@ use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{FutureProducer, FutureRecord};
 
 use rmqtt::anyhow::anyhow;
+use rmqtt::bytes::Bytes;
 use rmqtt::bytestring::ByteString;
 use rmqtt::rust_box::task_exec_queue::SpawnExt;
 use rmqtt::{
+    broker::compression::COMPRESSION_MARKER,
     broker::topic::{TopicTree, VecToTopic},
     timestamp_millis, timestamp_secs, From, MqttError, NodeId, Publish, QoSEx, Result, Topic,
 };
@@ -97,8 +99,15 @@ impl Producer {
             .insert(Header { key: "time", value: Some(itoa::Buffer::new().format(timestamp_millis())) });
         headers = headers.insert(Header { key: "topic", value: Some(p.topic().as_str()) });
 
+        let payload = if self.cfg.compression.is_none() {
+            p.payload().clone()
+        } else {
+            headers = headers
+                .insert(Header { key: COMPRESSION_MARKER, value: Some(self.cfg.compression.as_str()) });
+            Bytes::from(self.cfg.compression.compress(p.payload(), self.cfg.compression_level)?)
+        };
+
         let topic = self.cfg_entry.remote.make_topic(&p.topic);
-        let payload = p.payload().clone();
         let queue_timeout = self.cfg_entry.remote.queue_timeout;
         let partition = self.cfg_entry.remote.partition;
         let name = self.cfg.name.clone();
@@ -4,7 +4,7 @@ use rmqtt::bytestring::ByteString;
 
 use serde::de::{Deserialize, Deserializer};
 
-use rmqtt::{settings::deserialize_duration, HashMap, Result};
+use rmqtt::{broker::compression::Compression, settings::deserialize_duration, HashMap, Result};
 
 use crate::bridge::BridgeName;
 
@@ -42,6 +42,12 @@ pub struct Bridge {
     #[serde(default)]
     pub properties: HashMap<String, String>,
 
+    ///Payload compression, the algorithm is passed in the "rmqtt-compression" header
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+
     #[serde(default)]
     pub entries: Vec<Entry>,
 }
@@ -13,7 +13,7 @@ use rmqtt::{
     tokio::sync::RwLock,
 };
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     plugin::{PackageInfo, Plugin},
     register, Result, Runtime,
 };
@@ -165,6 +165,6 @@ impl Handler for HookHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use ntex::connect::rustls::Connector as TlsConnector;
+use ntex::connect::Address;
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore};
+
+use rmqtt::{MqttError, Result};
+
+use crate::config::Bridge;
+
+//AWS IoT Core message broker limits
+const MAX_TOPIC_SIZE: usize = 256;
+const MAX_TOPIC_SLASHES: usize = 7;
+const MAX_PAYLOAD_SIZE: usize = 128 * 1024;
+
+const ALPN_MQTT: &[u8] = b"x-amzn-mqtt-ca";
+
+///Bridge preset for AWS IoT Core, the connection is made with mutual TLS to the ATS endpoint of the account
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct AwsIot {
+    ///ATS endpoint, e.g. "xxxxxxxxxxxxxx-ats.iot.us-east-1.amazonaws.com"
+    pub endpoint: String,
+    ///8883, or 443 with ALPN
+    #[serde(default = "AwsIot::port_default")]
+    pub port: u16,
+    ///Device certificate, PEM file
+    pub cert: String,
+    ///Private key of the device certificate, PEM file
+    pub key: String,
+    ///Root CA, PEM file, the bundled web PKI roots, which include the Amazon Root CAs, are used by default
+    #[serde(default)]
+    pub ca: Option<String>,
+    ///Thing name for the ${aws.thing}, ${aws.shadow} and ${aws.jobs} placeholders of remote topics
+    #[serde(default)]
+    pub thing_name: Option<String>,
+}
+
+impl AwsIot {
+    fn port_default() -> u16 {
+        8883
+    }
+
+    ///Applies the preset to the bridge: the server address, no username/password, and the AWS
+    ///namespaces in the remote topics
+    pub(crate) fn apply(&self, b_cfg: &mut Bridge) -> Result<()> {
+        if self.endpoint.is_empty() {
+            return Err(MqttError::from(format!(
+                "aws_iot.endpoint is not configured, bridge: {}",
+                b_cfg.name
+            )));
+        }
+        b_cfg.server = format!("{}:{}", self.endpoint, self.port);
+        b_cfg.username = None;
+        b_cfg.password = None;
+        for entry in b_cfg.entries.iter_mut() {
+            entry.remote.topic.0 = self.map_topic(&entry.remote.topic.0)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn map_topic(&self, topic: &str) -> Result<String> {
+        if !topic.contains("${aws.") {
+            return Ok(topic.into());
+        }
+        let thing_name = self
+            .thing_name
+            .as_deref()
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| MqttError::from(format!("aws_iot.thing_name is required by {}", topic)))?;
+        Ok(topic
+            .replace("${aws.shadow}", &format!("$aws/things/{}/shadow", thing_name))
+            .replace("${aws.jobs}", &format!("$aws/things/{}/jobs", thing_name))
+            .replace("${aws.thing}", thing_name))
+    }
+
+    ///Messages that AWS IoT Core would refuse, and disconnect the client for, are not sent
+    #[inline]
+    pub(crate) fn check_limits(&self, topic: &str, payload_len: usize) -> Result<()> {
+        if topic.len() > MAX_TOPIC_SIZE {
+            return Err(MqttError::from(format!("topic exceeds {} bytes, {}", MAX_TOPIC_SIZE, topic)));
+        }
+        if topic.matches('/').count() > MAX_TOPIC_SLASHES {
+            return Err(MqttError::from(format!(
+                "topic has more than {} slashes, {}",
+                MAX_TOPIC_SLASHES, topic
+            )));
+        }
+        if payload_len > MAX_PAYLOAD_SIZE {
+            return Err(MqttError::from(format!(
+                "payload of {} bytes exceeds {} bytes, topic: {}",
+                payload_len, MAX_PAYLOAD_SIZE, topic
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn tls_connector<A: Address>(&self) -> Result<TlsConnector<A>> {
+        let certs = read_certs(&self.cert)?;
+        let key = read_key(&self.key)?;
+
+        let mut roots = RootCertStore::empty();
+        if let Some(ca) = self.ca.as_ref() {
+            for cert in read_certs(ca)? {
+                roots.add(&cert).map_err(|e| MqttError::from(format!("invalid CA {}, {:?}", ca, e)))?;
+            }
+        } else {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+            }));
+        }
+
+        let mut tls_cfg = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(certs, key)
+            .map_err(|e| MqttError::from(format!("invalid certificate or key, {:?}", e)))?;
+        if self.port == 443 {
+            tls_cfg.alpn_protocols = vec![ALPN_MQTT.to_vec()];
+        }
+        Ok(TlsConnector::new(tls_cfg))
+    }
+}
+
+fn read_certs(path: &str) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+    if certs.is_empty() {
+        return Err(MqttError::from(format!("no certificate found in {}", path)));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &str) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(MqttError::from(format!("no private key found in {}", path)))
+}
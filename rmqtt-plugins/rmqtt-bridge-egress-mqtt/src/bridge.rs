@@ -1,6 +1,7 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ntex_mqtt::v3::codec::Publish as PublishV3;
 use ntex_mqtt::v5::codec::Publish as PublishV5;
@@ -10,10 +11,12 @@ use rmqtt::bytestring::ByteString;
 use rmqtt::futures::channel::mpsc;
 use rmqtt::futures::SinkExt;
 use rmqtt::{
+    broker::compression::COMPRESSION_MARKER,
     broker::topic::{TopicTree, VecToTopic},
+    broker::translate::forward_properties,
     rand, ClientId, From, MqttError, NodeId, Publish, PublishProperties, Result, Topic,
 };
-use rmqtt::{log, tokio::sync::RwLock, DashMap};
+use rmqtt::{log, plugin::BridgeMetrics, plugin::BridgeRecorder, tokio::sync::RwLock, DashMap};
 
 use rmqtt::ntex_mqtt::types::{MQTT_LEVEL_31, MQTT_LEVEL_311, MQTT_LEVEL_5};
 
@@ -32,17 +35,32 @@ pub enum Command {
 pub struct CommandMailbox {
     pub(crate) cfg: Arc<Bridge>,
     pub(crate) client_id: ClientId,
+    pub(crate) recorder: Arc<BridgeRecorder>,
     cmd_tx: mpsc::Sender<Command>,
 }
 
 impl CommandMailbox {
-    pub(crate) fn new(cfg: Arc<Bridge>, client_id: ClientId, cmd_tx: mpsc::Sender<Command>) -> Self {
-        CommandMailbox { cfg, client_id, cmd_tx }
+    pub(crate) fn new(
+        cfg: Arc<Bridge>,
+        client_id: ClientId,
+        recorder: Arc<BridgeRecorder>,
+        cmd_tx: mpsc::Sender<Command>,
+    ) -> Self {
+        CommandMailbox { cfg, client_id, recorder, cmd_tx }
     }
 
     #[inline]
     pub(crate) async fn send(&self, cmd: Command) -> Result<()> {
-        self.cmd_tx.clone().send(cmd).await.map_err(|e| anyhow!(e))?;
+        let is_publish = matches!(cmd, Command::Publish(_));
+        if is_publish {
+            self.recorder.enqueued();
+        }
+        if let Err(e) = self.cmd_tx.clone().send(cmd).await {
+            if is_publish {
+                self.recorder.dequeued();
+            }
+            return Err(anyhow!(e).into());
+        }
         Ok(())
     }
 
@@ -58,6 +76,24 @@ pub enum BridgePublish {
     V5(PublishV5),
 }
 
+impl BridgePublish {
+    #[inline]
+    fn topic(&self) -> &str {
+        match self {
+            BridgePublish::V3(p) => &p.topic,
+            BridgePublish::V5(p) => &p.topic,
+        }
+    }
+
+    #[inline]
+    fn payload_len(&self) -> usize {
+        match self {
+            BridgePublish::V3(p) => p.payload.len(),
+            BridgePublish::V5(p) => p.payload.len(),
+        }
+    }
+}
+
 pub(crate) type BridgeName = ByteString;
 type SourceKey = (BridgeName, EntryIndex);
 
@@ -91,10 +127,22 @@ impl BridgeManager {
             if !b_cfg.enable {
                 continue;
             }
+            let mut b_cfg = b_cfg.clone();
+            if let Some(aws_iot) = b_cfg.aws_iot.clone() {
+                aws_iot.apply(&mut b_cfg)?;
+            }
+            let b_cfg = &b_cfg;
             if bridge_names.contains(&b_cfg.name as &str) {
                 return Err(MqttError::from(format!("The bridge name already exists! {:?}", b_cfg.name)));
             }
 
+            if !b_cfg.compression.is_none() && b_cfg.mqtt_ver.level() != MQTT_LEVEL_5 {
+                return Err(MqttError::from(format!(
+                    "Payload compression requires MQTT 5.0, bridge: {:?}",
+                    b_cfg.name
+                )));
+            }
+
             bridge_names.insert(&b_cfg.name);
             for (entry_idx, entry) in b_cfg.entries.iter().enumerate() {
                 log::debug!("entry.local.topic_filter: {}", entry.local.topic_filter);
@@ -155,6 +203,37 @@ impl BridgeManager {
         &self.sinks
     }
 
+    ///Metrics of the bridges, merged over the clients of each bridge
+    pub(crate) fn metrics(&self) -> BTreeMap<String, BridgeMetrics> {
+        let mut bridges: BTreeMap<String, BridgeMetrics> = BTreeMap::new();
+        for entry in self.sinks.iter() {
+            let ((bridge_name, _), mailboxs) = entry.pair();
+            let metrics = bridges.entry(bridge_name.to_string()).or_default();
+            for mailbox in mailboxs {
+                metrics.merge(&mailbox.recorder.metrics());
+            }
+        }
+        bridges
+    }
+
+    ///The bridges whose forwarding lag exceeds `lag_alarm_threshold`, with the lag and the threshold
+    pub(crate) fn lagging(&self) -> Vec<(BridgeName, Duration, Duration)> {
+        let mut lagging: BTreeMap<BridgeName, (Duration, Duration)> = BTreeMap::new();
+        for entry in self.sinks.iter() {
+            let ((bridge_name, _), mailboxs) = entry.pair();
+            for mailbox in mailboxs {
+                let threshold = mailbox.cfg.lag_alarm_threshold;
+                let lag = mailbox.recorder.lag();
+                if threshold.is_zero() || lag <= threshold {
+                    continue;
+                }
+                let (max, _) = lagging.entry(bridge_name.clone()).or_insert((lag, threshold));
+                *max = (*max).max(lag);
+            }
+        }
+        lagging.into_iter().map(|(name, (lag, threshold))| (name, lag, threshold)).collect()
+    }
+
     #[inline]
     pub(crate) async fn send(&self, _f: &From, p: &Publish) -> Result<()> {
         let topic = Topic::from_str(&p.topic)?;
@@ -173,30 +252,33 @@ impl BridgeManager {
                             log::error!("unreachable!(), entry_idx: {}", *entry_idx);
                             continue;
                         };
-                        match *mqtt_ver {
-                            MQTT_LEVEL_311 => {
-                                if let Err(e) = mailbox
-                                    .send(Command::Publish(BridgePublish::V3(self.to_v3_publish(entry, p))))
-                                    .await
-                                {
-                                    log::warn!("{}", e);
-                                }
-                            }
-                            MQTT_LEVEL_5 => {
-                                if let Err(e) = mailbox
-                                    .send(Command::Publish(BridgePublish::V5(self.to_v5_publish(entry, p))))
-                                    .await
-                                {
+                        let publish = match *mqtt_ver {
+                            MQTT_LEVEL_311 => BridgePublish::V3(self.to_v3_publish(entry, p)),
+                            MQTT_LEVEL_5 => match self.to_v5_publish(&mailbox.cfg, entry, p) {
+                                Ok(p) => BridgePublish::V5(p),
+                                Err(e) => {
                                     log::warn!("{}", e);
+                                    continue;
                                 }
-                            }
+                            },
                             MQTT_LEVEL_31 => {
-                                log::warn!("Connection to MQTT 3.1 broker not implemented!")
+                                log::warn!("Connection to MQTT 3.1 broker not implemented!");
+                                continue;
                             }
                             _ => {
-                                log::error!("Wrong MQTT version, {}", *mqtt_ver)
+                                log::error!("Wrong MQTT version, {}", *mqtt_ver);
+                                continue;
+                            }
+                        };
+                        if let Some(aws_iot) = mailbox.cfg.aws_iot.as_ref() {
+                            if let Err(e) = aws_iot.check_limits(publish.topic(), publish.payload_len()) {
+                                log::warn!("{} message not sent, {}", name, e);
+                                continue;
                             }
                         }
+                        if let Err(e) = mailbox.send(Command::Publish(publish)).await {
+                            log::warn!("{}", e);
+                        }
                     }
                 }
             }
@@ -217,16 +299,26 @@ impl BridgeManager {
     }
 
     #[inline]
-    fn to_v5_publish(&self, cfg_entry: &Entry, p: &Publish) -> PublishV5 {
-        PublishV5 {
+    fn to_v5_publish(&self, b_cfg: &Bridge, cfg_entry: &Entry, p: &Publish) -> Result<PublishV5> {
+        let mut properties = to_properties(&forward_properties(p.properties.clone()));
+        let payload = if b_cfg.compression.is_none() {
+            p.payload.to_vec() //@TODO ...
+        } else {
+            properties.user_properties.push((
+                ntex::util::ByteString::from_static(COMPRESSION_MARKER),
+                ntex::util::ByteString::from_static(b_cfg.compression.as_str()),
+            ));
+            b_cfg.compression.compress(&p.payload, b_cfg.compression_level)?
+        };
+        Ok(PublishV5 {
             dup: false,
             retain: cfg_entry.remote.make_retain(p.retain),
             qos: cfg_entry.remote.make_qos(p.qos),
             topic: cfg_entry.remote.make_topic(&p.topic),
             packet_id: None,
-            payload: ntex::util::Bytes::from(p.payload.to_vec()), //@TODO ...
-            properties: to_properties(&p.properties),
-        }
+            payload: ntex::util::Bytes::from(payload),
+            properties,
+        })
     }
 }
 
@@ -14,15 +14,18 @@ use rmqtt::{
     anyhow,
     base64::{engine::general_purpose, Engine as _},
     ntex_mqtt::types::{Protocol, MQTT_LEVEL_31, MQTT_LEVEL_311, MQTT_LEVEL_5},
+    rand,
     serde_json::{self, Map, Value},
 };
 
 use rmqtt::serde_json::json;
 use rmqtt::{
+    broker::compression::Compression,
     settings::{deserialize_duration, to_duration, Bytesize},
     MqttError, Result,
 };
 
+use crate::aws::AwsIot;
 use crate::bridge::BridgeName;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -41,6 +44,9 @@ pub struct Bridge {
     pub client_id_prefix: String,
     #[serde(default)]
     pub server: String,
+    ///Servers tried in order when `server` can not be connected
+    #[serde(default)]
+    pub failover_servers: Vec<String>,
     #[serde(default)]
     pub username: Option<String>,
     #[serde(default)]
@@ -56,6 +62,10 @@ pub struct Bridge {
     pub reconnect_interval: Duration,
     #[serde(default = "Bridge::message_channel_capacity_default")]
     pub message_channel_capacity: usize,
+    ///The plug-in reports degraded health, raising a plug-in alarm, when the oldest message waiting
+    ///to be forwarded is older than this, 0 disables the alarm
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub lag_alarm_threshold: Duration,
     #[serde(default = "Bridge::mqtt_ver_default", deserialize_with = "Bridge::deserialize_mqtt_ver")]
     pub mqtt_ver: Protocol,
     #[serde(default)]
@@ -63,6 +73,16 @@ pub struct Bridge {
     #[serde(default)]
     pub v5: MoreV5,
 
+    ///Payload compression, the algorithm is passed in a user property, so MQTT 5.0 is required
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub compression_level: Option<i32>,
+
+    ///AWS IoT Core preset, replaces server, username and password
+    #[serde(default)]
+    pub aws_iot: Option<AwsIot>,
+
     #[serde(default)]
     pub entries: Vec<Entry>,
 }
@@ -92,6 +112,23 @@ impl Bridge {
         Protocol::MQTT(MQTT_LEVEL_311)
     }
 
+    ///The server followed by the failover servers
+    #[inline]
+    pub fn endpoints(&self) -> Vec<String> {
+        std::iter::once(&self.server).chain(self.failover_servers.iter()).cloned().collect()
+    }
+
+    ///Delay before reconnecting, AWS IoT bridges add a random jitter of up to one interval so that
+    ///the clients of a bridge do not reconnect all at once
+    #[inline]
+    pub fn reconnect_delay(&self) -> Duration {
+        if self.aws_iot.is_some() {
+            self.reconnect_interval + self.reconnect_interval.mul_f64(rand::random::<f64>())
+        } else {
+            self.reconnect_interval
+        }
+    }
+
     #[inline]
     pub fn deserialize_mqtt_ver<'de, D>(deserializer: D) -> Result<Protocol, D::Error>
     where
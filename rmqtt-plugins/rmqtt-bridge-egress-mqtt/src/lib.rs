@@ -13,8 +13,8 @@ use rmqtt::{
     tokio::sync::RwLock,
 };
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
-    plugin::{PackageInfo, Plugin},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
+    plugin::{Health, PackageInfo, Plugin, PluginMetrics},
     register, Result, Runtime,
 };
 use std::ops::Deref;
@@ -23,6 +23,7 @@ use std::sync::Arc;
 use bridge::{BridgeManager, Command};
 use config::PluginConfig;
 
+mod aws;
 mod bridge;
 mod config;
 mod v4;
@@ -132,9 +133,28 @@ impl Plugin for BridgeMqttEgressPlugin {
                     .collect::<Vec<_>>()
             })
             .collect::<Vec<serde_json::Value>>();
-        json!({
+        let metrics = PluginMetrics { bridges: Some(self.bridge_mgr.metrics()), ..Default::default() };
+        metrics.attrs(json!({
             "bridges": bridges
-        })
+        }))
+    }
+
+    #[inline]
+    async fn health(&self) -> Health {
+        let lagging = self.bridge_mgr.lagging();
+        if lagging.is_empty() {
+            return Health::healthy();
+        }
+        let reason = lagging
+            .iter()
+            .map(|(name, lag, threshold)| format!("{} lag {:?} exceeds {:?}", name, lag, threshold))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let details = lagging
+            .iter()
+            .map(|(name, lag, _)| (name.to_string(), json!(lag.as_millis() as u64)))
+            .collect::<serde_json::Map<_, _>>();
+        Health::degraded(reason).details(json!({ "lag_ms": details }))
     }
 }
 
@@ -162,6 +182,6 @@ impl Handler for HookHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
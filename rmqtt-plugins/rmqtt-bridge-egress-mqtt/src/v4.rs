@@ -1,10 +1,12 @@
-use std::cell::RefCell;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::cell::{Cell, RefCell};
+use std::net::SocketAddr;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use ntex::connect::Connector;
+use ntex::connect::{Address, Connect, ConnectError, Connector};
+use ntex::io::{Filter, Io};
+use ntex::service::Service;
 use ntex::time::Seconds;
 use ntex::util::ByteString;
 use ntex::util::Bytes;
@@ -13,10 +15,12 @@ use ntex_mqtt::{self, v3};
 
 use rmqtt::ntex_mqtt::types::MQTT_LEVEL_311;
 
+use rmqtt::broker::resolver::Resolver;
 use rmqtt::futures::channel::mpsc;
 use rmqtt::futures::StreamExt;
 use rmqtt::log;
-use rmqtt::{ClientId, MqttError, NodeId, Result};
+use rmqtt::plugin::BridgeRecorder;
+use rmqtt::{ClientId, NodeId, Result};
 
 use crate::bridge::{BridgePublish, Command, CommandMailbox};
 use crate::config::Bridge;
@@ -24,8 +28,9 @@ use crate::config::Bridge;
 #[derive(Clone)]
 pub struct Client {
     pub(crate) cfg: Arc<Bridge>,
-    pub(crate) server_addr: SocketAddr,
+    pub(crate) server_addr: Rc<Cell<Option<SocketAddr>>>,
     pub(crate) client_id: ClientId,
+    pub(crate) recorder: Arc<BridgeRecorder>,
     closed: Rc<AtomicBool>,
     sink: Rc<RefCell<Option<v3::MqttSink>>>,
 }
@@ -56,44 +61,52 @@ impl Client {
         let client_id =
             format!("{}:{}:egress:{}:{}:{}", cfg.client_id_prefix, cfg.name, node_id, entry_idx, client_no);
 
-        let server_addr = cfg.server.to_socket_addrs()?.next().ok_or_else(|| MqttError::from("None"))?;
-
         let client = Self {
             cfg: Arc::new(cfg),
-            server_addr,
+            server_addr: Rc::new(Cell::new(None)),
             client_id: ClientId::from(client_id),
+            recorder: Arc::new(BridgeRecorder::default()),
             closed: Rc::new(AtomicBool::new(false)),
             sink: Rc::new(RefCell::new(None)),
         };
 
-        let mut builder = v3::client::MqttConnector::new(client.server_addr)
-            .client_id(ByteString::from(client.client_id.as_ref()))
-            .keep_alive(Seconds(client.cfg.keepalive.as_secs() as u16))
-            .handshake_timeout(Seconds(client.cfg.connect_timeout.as_secs() as u16));
+        if client.cfg.mqtt_ver.level() == MQTT_LEVEL_311 {
+            if let Some(aws_iot) = client.cfg.aws_iot.as_ref() {
+                //The TLS connection needs the host name of the endpoint
+                let builder = client.builder(client.cfg.server.clone()).connector(aws_iot.tls_connector()?);
+                ntex::rt::spawn(client.clone().start(builder));
+            } else {
+                ntex::rt::spawn(client.clone().start_with_failover());
+            }
+            ntex::rt::spawn(client.clone().cmd_loop(cmd_rx));
+        } else {
+            unreachable!()
+        }
+
+        Ok(CommandMailbox::new(client.cfg.clone(), client.client_id, client.recorder, cmd_tx))
+    }
+
+    fn builder<A: Address + Clone>(&self, addr: A) -> v3::client::MqttConnector<A, Connector<A>> {
+        let mut builder = v3::client::MqttConnector::new(addr)
+            .client_id(ByteString::from(self.client_id.as_ref()))
+            .keep_alive(Seconds(self.cfg.keepalive.as_secs() as u16))
+            .handshake_timeout(Seconds(self.cfg.connect_timeout.as_secs() as u16));
 
-        if let Some(username) = client.cfg.username.as_ref() {
+        if let Some(username) = self.cfg.username.as_ref() {
             builder = builder.username(username.clone());
         }
-        if let Some(password) = client.cfg.password.as_ref() {
+        if let Some(password) = self.cfg.password.as_ref() {
             builder = builder.password(Bytes::from(password.clone()));
         }
 
-        if client.cfg.mqtt_ver.level() == MQTT_LEVEL_311 {
-            if client.cfg.v4.clean_session {
-                builder = builder.clean_session()
-            };
-
-            if let Some(last_will) = client.cfg.v4.last_will.as_ref() {
-                builder = builder.last_will(last_will.clone());
-            }
+        if self.cfg.v4.clean_session {
+            builder = builder.clean_session()
+        };
 
-            ntex::rt::spawn(client.clone().start(builder));
-            ntex::rt::spawn(client.clone().cmd_loop(cmd_rx));
-        } else {
-            unreachable!()
+        if let Some(last_will) = self.cfg.v4.last_will.as_ref() {
+            builder = builder.last_will(last_will.clone());
         }
-
-        Ok(CommandMailbox::new(client.cfg.clone(), client.client_id, cmd_tx))
+        builder
     }
 
     async fn cmd_loop(self, mut cmd_rx: mpsc::Receiver<Command>) {
@@ -110,17 +123,29 @@ impl Client {
                 }
                 Some(Command::Publish(BridgePublish::V3(p))) => {
                     log::debug!("{} Command::Publish, {:?}", self.client_id, p);
+                    self.recorder.dequeued();
                     let sink = self.sink.borrow().as_ref().cloned();
                     if let Some(sink) = sink {
                         if matches!(p.qos, ntex_mqtt::QoS::AtMostOnce) {
-                            if let Err(e) = sink.publish_pkt(p).send_at_most_once() {
-                                log::warn!("{}", e);
+                            match sink.publish_pkt(p).send_at_most_once() {
+                                Ok(()) => self.recorder.forwarded(),
+                                Err(e) => {
+                                    log::warn!("{}", e);
+                                    self.recorder.error(e);
+                                }
+                            }
+                        } else {
+                            match sink.publish_pkt(p).send_at_least_once().await {
+                                Ok(_) => self.recorder.forwarded(),
+                                Err(e) => {
+                                    log::warn!("{}", e);
+                                    self.recorder.error(e);
+                                }
                             }
-                        } else if let Err(e) = sink.publish_pkt(p).send_at_least_once().await {
-                            log::warn!("{}", e);
                         }
                     } else {
                         log::error!("mqtt sink is None");
+                        self.recorder.error("not connected, message dropped");
                     }
                 }
                 Some(Command::Publish(BridgePublish::V5(_))) => {
@@ -130,10 +155,15 @@ impl Client {
         }
     }
 
-    async fn start(self, builder: v3::client::MqttConnector<SocketAddr, Connector<SocketAddr>>) {
+    async fn start<A, T, F>(self, builder: v3::client::MqttConnector<A, T>)
+    where
+        A: Address + Clone,
+        T: Service<Connect<A>, Response = Io<F>, Error = ConnectError>,
+        F: Filter,
+    {
         let client = self;
-        let sleep_interval = client.cfg.reconnect_interval;
         loop {
+            client.recorder.connecting();
             match builder.connect().await {
                 Ok(c) => {
                     log::info!("{} Successfully connected to {:?}", client.client_id, client.cfg.server);
@@ -142,21 +172,72 @@ impl Client {
                     client.sink.replace(Some(sink.clone()));
 
                     //client event loop
+                    client.recorder.connected();
                     client.clone().ev_loop(c).await;
+                    client.recorder.disconnected();
                 }
                 Err(e) => {
                     log::warn!("{} Connect to {:?} fail, {:?}", client.client_id, client.cfg.server, e);
+                    client.recorder.error(format!("{:?}", e));
                 }
             }
             if client.is_closed() {
                 break;
             } else {
-                ntex::time::sleep(sleep_interval).await;
+                ntex::time::sleep(client.cfg.reconnect_delay()).await;
             }
         }
         log::info!("{} Exit 'rmqtt-bridge-ingress-mqtt' client", client.client_id);
     }
 
+    //The server and the failover servers are resolved again before each connect, so the bridge follows
+    //address changes of the remote broker
+    async fn start_with_failover(self) {
+        let client = self;
+        loop {
+            client.recorder.connecting();
+            match client.dial().await {
+                Ok(addr) => match client.builder(addr).connect().await {
+                    Ok(c) => {
+                        log::info!("{} Successfully connected to {:?}", client.client_id, addr);
+
+                        let sink = c.sink();
+                        client.sink.replace(Some(sink.clone()));
+
+                        //client event loop
+                        client.recorder.connected();
+                        client.clone().ev_loop(c).await;
+                        client.recorder.disconnected();
+                    }
+                    Err(e) => {
+                        log::warn!("{} Connect to {:?} fail, {:?}", client.client_id, addr, e);
+                        client.recorder.error(format!("{:?}", e));
+                    }
+                },
+                Err(e) => {
+                    log::warn!("{} Connect to {:?} fail, {}", client.client_id, client.cfg.server, e);
+                    client.recorder.error(e);
+                }
+            }
+            if client.is_closed() {
+                break;
+            } else {
+                ntex::time::sleep(client.cfg.reconnect_delay()).await;
+            }
+        }
+        log::info!("{} Exit 'rmqtt-bridge-egress-mqtt' client", client.client_id);
+    }
+
+    async fn dial(&self) -> Result<SocketAddr> {
+        let endpoints = self.cfg.endpoints();
+        let (idx, addr) = Resolver::instance().dial(&endpoints, self.cfg.connect_timeout).await?;
+        if idx > 0 {
+            log::info!("{} failover to {}, {:?}", self.client_id, endpoints[idx], addr);
+        }
+        self.server_addr.set(Some(addr));
+        Ok(addr)
+    }
+
     // async fn subscribe(
     //     self,
     //     sink: v3::MqttSink,
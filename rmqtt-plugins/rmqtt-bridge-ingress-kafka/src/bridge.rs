@@ -16,12 +16,12 @@ use rdkafka::message::{BorrowedMessage, Headers, Message};
 use rdkafka::topic_partition_list::TopicPartitionList;
 
 use rmqtt::{
-    anyhow::anyhow, bytes::Bytes, bytestring::ByteString, log, tokio, tokio::sync::mpsc, tokio::sync::RwLock,
-    DashMap, UserProperties,
+    anyhow::anyhow, broker::compression::decompress_publish, bytes::Bytes, bytestring::ByteString, log,
+    tokio, tokio::sync::mpsc, tokio::sync::RwLock, DashMap, UserProperties,
 };
 use rmqtt::{
-    timestamp_millis, ClientId, From, Id, MqttError, NodeId, Publish, PublishProperties, QoS, Result,
-    Runtime, SessionState, UserName,
+    ClientId, From, Id, MqttError, NodeId, Publish, PublishProperties, QoS, Result, Runtime, SessionState,
+    UserName,
 };
 
 use crate::config::{Bridge, Entry, PluginConfig, MESSAGE_KEY, PARTITION_UNASSIGNED};
@@ -334,18 +334,18 @@ impl Consumer {
             from_username,
         ));
 
-        let properties = PublishProperties::from(user_properties);
-        let p = Publish {
-            dup: false,
-            retain: entry.local.make_retain(retain),
-            qos: entry.local.make_qos(qos),
-            topic: entry.local.make_topic(key),
-            packet_id: None,
-            payload,
-            properties,
-            delay_interval: None,
-            create_time: timestamp_millis(),
-        };
+        let mut p = Publish::builder()
+            .retain(entry.local.make_retain(retain))
+            .qos(entry.local.make_qos(qos))
+            .topic(entry.local.make_topic(key))
+            .payload(payload)
+            .properties(PublishProperties::from(user_properties))
+            .build();
+
+        if let Err(e) = decompress_publish(&mut p, cfg.decompression_limit.as_usize()) {
+            log::warn!("{}/{} decompress payload error, topic: {}, {:?}", name, client_id, p.topic, e);
+            return;
+        }
 
         on_message.fire((from, p, cfg.retain_available, cfg.storage_available, cfg.expiry_interval));
     }
@@ -6,7 +6,10 @@ use serde::ser::{Serialize, Serializer};
 
 use rdkafka::topic_partition_list::Offset;
 
-use rmqtt::{settings::deserialize_duration, HashMap, QoS, Result, TopicName};
+use rmqtt::{
+    settings::{deserialize_duration, Bytesize},
+    HashMap, QoS, Result, TopicName,
+};
 
 use crate::bridge::BridgeName;
 
@@ -42,6 +45,10 @@ pub struct Bridge {
 
     #[serde(default = "Bridge::expiry_interval_default", deserialize_with = "deserialize_duration")]
     pub expiry_interval: Duration,
+
+    ///Maximum size of a payload decompressed by the bridge
+    #[serde(default = "Bridge::decompression_limit_default")]
+    pub decompression_limit: Bytesize,
 }
 
 impl Bridge {
@@ -59,6 +66,11 @@ impl Bridge {
     fn expiry_interval_default() -> Duration {
         Duration::from_secs(300)
     }
+
+    #[inline]
+    fn decompression_limit_default() -> Bytesize {
+        Bytesize::from(16 * 1024 * 1024)
+    }
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -8,9 +9,12 @@ use ntex_mqtt::v3::codec::Publish as PublishV3;
 use ntex_mqtt::v5::codec::Publish as PublishV5;
 
 use rmqtt::anyhow::anyhow;
+use rmqtt::broker::compression::decompress_publish;
+use rmqtt::broker::translate::forward_properties;
 use rmqtt::bytestring::ByteString;
 use rmqtt::futures::channel::mpsc;
 use rmqtt::futures::SinkExt;
+use rmqtt::plugin::{BridgeMetrics, BridgeRecorder};
 use rmqtt::{bytes::Bytes, log, timestamp_millis, tokio::sync::RwLock, ClientId, DashMap, UserName};
 use rmqtt::{From, Id, NodeId, Publish, PublishProperties, Result, Runtime, SessionState, UserProperties};
 
@@ -29,12 +33,17 @@ pub enum Command {
 #[derive(Clone)]
 pub struct CommandMailbox {
     pub(crate) client_id: ClientId,
+    pub(crate) recorder: Arc<BridgeRecorder>,
     cmd_tx: mpsc::Sender<Command>,
 }
 
 impl CommandMailbox {
-    pub(crate) fn new(client_id: ClientId, cmd_tx: mpsc::Sender<Command>) -> Self {
-        CommandMailbox { client_id, cmd_tx }
+    pub(crate) fn new(
+        client_id: ClientId,
+        recorder: Arc<BridgeRecorder>,
+        cmd_tx: mpsc::Sender<Command>,
+    ) -> Self {
+        CommandMailbox { client_id, recorder, cmd_tx }
     }
 
     #[inline]
@@ -89,6 +98,13 @@ impl BridgeClient {
             BridgeClient::V5(c) => c.username.clone(),
         }
     }
+
+    fn recorder(&self) -> &BridgeRecorder {
+        match self {
+            BridgeClient::V4(c) => &c.recorder,
+            BridgeClient::V5(c) => &c.recorder,
+        }
+    }
 }
 
 pub type OnMessageEvent =
@@ -198,6 +214,16 @@ impl BridgeManager {
     pub(crate) fn sources(&self) -> &DashMap<SourceKey, CommandMailbox> {
         &self.sources
     }
+
+    ///Metrics of the bridges, merged over the clients of each bridge
+    pub(crate) fn metrics(&self) -> BTreeMap<String, BridgeMetrics> {
+        let mut bridges: BTreeMap<String, BridgeMetrics> = BTreeMap::new();
+        for entry in self.sources.iter() {
+            let ((bridge_name, _, _), mailbox) = entry.pair();
+            bridges.entry(bridge_name.clone()).or_default().merge(&mailbox.recorder.metrics());
+        }
+        bridges
+    }
 }
 
 async fn send_publish(
@@ -214,9 +240,11 @@ async fn send_publish(
         Some(c.username()),
     ));
     log::debug!("from {:?}, message: {:?}", from, p);
+    //the publish tasks are started in the order the messages were received
+    c.recorder().dequeued();
     let cfg = c.cfg();
     let entry = if let Some(entry) = cfg.entries.get(c.entry_idx()) { entry } else { unreachable!() };
-    let msg = match p {
+    let mut msg = match p {
         BridgePublish::V3(p) => Publish {
             dup: false,
             retain: entry.local.make_retain(p.retain),
@@ -235,7 +263,7 @@ async fn send_publish(
             topic: entry.local.make_topic(p.topic.as_str()),
             packet_id: None,
             payload: Bytes::from(p.payload.to_vec()), //@TODO ...
-            properties: to_properties(p.properties),  //@TODO ...
+            properties: forward_properties(to_properties(p.properties)), //@TODO ...
             delay_interval: None,
             create_time: timestamp_millis(),
         },
@@ -243,6 +271,12 @@ async fn send_publish(
 
     log::debug!("msg: {:?}", msg);
 
+    if let Err(e) = decompress_publish(&mut msg, cfg.decompression_limit.as_usize()) {
+        log::warn!("{} decompress payload error, topic: {}, {:?}", cfg.name, msg.topic, e);
+        c.recorder().error(format!("decompress payload error, {:?}", e));
+        return;
+    }
+
     let expiry_interval = msg
         .properties
         .message_expiry_interval
@@ -263,6 +297,9 @@ async fn send_publish(
             .await
     {
         log::warn!("{:?}", e);
+        c.recorder().error(format!("{:?}", e));
+    } else {
+        c.recorder().forwarded();
     }
 }
 
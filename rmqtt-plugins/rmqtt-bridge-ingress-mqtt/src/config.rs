@@ -39,6 +39,9 @@ pub struct Bridge {
     pub client_id_prefix: String,
     #[serde(default)]
     pub server: String,
+    ///Servers tried in order when `server` can not be connected
+    #[serde(default)]
+    pub failover_servers: Vec<String>,
     #[serde(default)]
     pub username: Option<String>,
     #[serde(default)]
@@ -59,6 +62,9 @@ pub struct Bridge {
     pub storage_available: bool,
     #[serde(default = "Bridge::expiry_interval_default", deserialize_with = "deserialize_duration")]
     pub expiry_interval: Duration,
+    ///Maximum size of a payload decompressed by the bridge
+    #[serde(default = "Bridge::decompression_limit_default")]
+    pub decompression_limit: Bytesize,
 
     #[serde(default = "Bridge::mqtt_ver_default", deserialize_with = "Bridge::deserialize_mqtt_ver")]
     pub mqtt_ver: Protocol,
@@ -92,6 +98,12 @@ impl Bridge {
         Protocol::MQTT(MQTT_LEVEL_311)
     }
 
+    ///The server followed by the failover servers
+    #[inline]
+    pub fn endpoints(&self) -> Vec<String> {
+        std::iter::once(&self.server).chain(self.failover_servers.iter()).cloned().collect()
+    }
+
     fn retain_available_default() -> bool {
         false
     }
@@ -104,6 +116,10 @@ impl Bridge {
         Duration::from_secs(300)
     }
 
+    fn decompression_limit_default() -> Bytesize {
+        Bytesize::from(16 * 1024 * 1024)
+    }
+
     #[inline]
     pub fn deserialize_mqtt_ver<'de, D>(deserializer: D) -> Result<Protocol, D::Error>
     where
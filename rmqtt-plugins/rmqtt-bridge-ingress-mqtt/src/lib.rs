@@ -14,7 +14,7 @@ use rmqtt::{
 };
 use rmqtt::{
     broker::hook::Register,
-    plugin::{PackageInfo, Plugin},
+    plugin::{PackageInfo, Plugin, PluginMetrics},
     register, Result, Runtime,
 };
 use std::ops::Deref;
@@ -124,8 +124,9 @@ impl Plugin for BridgeMqttIngressPlugin {
                 })
             })
             .collect::<Vec<serde_json::Value>>();
-        json!({
+        let metrics = PluginMetrics { bridges: Some(self.bridge_mgr.metrics()), ..Default::default() };
+        metrics.attrs(json!({
             "bridges": bridges
-        })
+        }))
     }
 }
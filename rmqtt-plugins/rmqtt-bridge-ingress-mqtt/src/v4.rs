@@ -1,7 +1,8 @@
-use std::cell::RefCell;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::cell::{Cell, RefCell};
+use std::net::SocketAddr;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use ntex::connect::Connector;
@@ -16,10 +17,12 @@ use ntex_mqtt::{self, v3};
 
 use rmqtt::ntex_mqtt::types::MQTT_LEVEL_311;
 
+use rmqtt::broker::resolver::Resolver;
 use rmqtt::futures::channel::mpsc;
 use rmqtt::futures::StreamExt;
 use rmqtt::log;
-use rmqtt::{ClientId, MqttError, NodeId, Result, UserName};
+use rmqtt::plugin::BridgeRecorder;
+use rmqtt::{ClientId, NodeId, Result, UserName};
 
 use crate::bridge::{BridgeClient, BridgePublish, Command, CommandMailbox, OnMessageEvent};
 use crate::config::Bridge;
@@ -27,10 +30,11 @@ use crate::config::Bridge;
 #[derive(Clone)]
 pub struct Client {
     pub(crate) cfg: Rc<Bridge>,
-    pub(crate) server_addr: SocketAddr,
+    pub(crate) server_addr: Rc<Cell<Option<SocketAddr>>>,
     pub(crate) entry_idx: usize,
     pub(crate) client_id: ClientId,
     pub(crate) username: UserName,
+    pub(crate) recorder: Arc<BridgeRecorder>,
     closed: Rc<AtomicBool>,
     sink: Rc<RefCell<Option<v3::MqttSink>>>,
     on_message: OnMessageEvent,
@@ -64,47 +68,49 @@ impl Client {
             format!("{}:{}:ingress:{}:{}:{}", cfg.client_id_prefix, cfg.name, node_id, entry_idx, client_no);
         let username = cfg.username.clone().unwrap_or("undefined".into());
 
-        let server_addr = cfg.server.to_socket_addrs()?.next().ok_or_else(|| MqttError::from("None"))?;
-
         let client = Self {
             cfg: Rc::new(cfg),
-            server_addr,
+            server_addr: Rc::new(Cell::new(None)),
             entry_idx,
             client_id: ClientId::from(client_id),
             username: UserName::from(username),
+            recorder: Arc::new(BridgeRecorder::default()),
             closed: Rc::new(AtomicBool::new(false)),
             sink: Rc::new(RefCell::new(None)),
             on_message,
         };
 
-        let mut builder = v3::client::MqttConnector::new(client.server_addr)
-            .client_id(ByteString::from(client.client_id.as_ref()))
-            .keep_alive(Seconds(client.cfg.keepalive.as_secs() as u16))
-            .handshake_timeout(Seconds(client.cfg.connect_timeout.as_secs() as u16));
+        if client.cfg.mqtt_ver.level() == MQTT_LEVEL_311 {
+            ntex::rt::spawn(client.clone().start());
+            ntex::rt::spawn(client.clone().cmd_loop(cmd_rx));
+        } else {
+            unreachable!()
+        }
 
-        if let Some(username) = client.cfg.username.as_ref() {
+        Ok(CommandMailbox::new(client.client_id, client.recorder, cmd_tx))
+    }
+
+    fn builder(&self, addr: SocketAddr) -> v3::client::MqttConnector<SocketAddr, Connector<SocketAddr>> {
+        let mut builder = v3::client::MqttConnector::new(addr)
+            .client_id(ByteString::from(self.client_id.as_ref()))
+            .keep_alive(Seconds(self.cfg.keepalive.as_secs() as u16))
+            .handshake_timeout(Seconds(self.cfg.connect_timeout.as_secs() as u16));
+
+        if let Some(username) = self.cfg.username.as_ref() {
             builder = builder.username(username.clone());
         }
-        if let Some(password) = client.cfg.password.as_ref() {
+        if let Some(password) = self.cfg.password.as_ref() {
             builder = builder.password(Bytes::from(password.clone()));
         }
 
-        if client.cfg.mqtt_ver.level() == MQTT_LEVEL_311 {
-            if client.cfg.v4.clean_session {
-                builder = builder.clean_session()
-            };
-
-            if let Some(last_will) = client.cfg.v4.last_will.as_ref() {
-                builder = builder.last_will(last_will.clone());
-            }
+        if self.cfg.v4.clean_session {
+            builder = builder.clean_session()
+        };
 
-            ntex::rt::spawn(client.clone().start(builder));
-            ntex::rt::spawn(client.clone().cmd_loop(cmd_rx));
-        } else {
-            unreachable!()
+        if let Some(last_will) = self.cfg.v4.last_will.as_ref() {
+            builder = builder.last_will(last_will.clone());
         }
-
-        Ok(CommandMailbox::new(client.client_id, cmd_tx))
+        builder
     }
 
     async fn cmd_loop(self, mut cmd_rx: mpsc::Receiver<Command>) {
@@ -123,13 +129,28 @@ impl Client {
         }
     }
 
-    async fn start(self, builder: v3::client::MqttConnector<SocketAddr, Connector<SocketAddr>>) {
+    //Each reconnect dials the endpoints again, a remote broker that moved to another address is found once
+    //the cached DNS records expire
+    async fn start(self) {
         let client = self;
         let sleep_interval = client.cfg.reconnect_interval;
         loop {
-            match builder.connect().await {
+            client.recorder.connecting();
+            let addr = match client.dial().await {
+                Ok(addr) => addr,
+                Err(e) => {
+                    log::warn!("{} Connect to {:?} fail, {}", client.client_id, client.cfg.server, e);
+                    client.recorder.error(e);
+                    if client.is_closed() {
+                        break;
+                    }
+                    ntex::time::sleep(sleep_interval).await;
+                    continue;
+                }
+            };
+            match client.builder(addr).connect().await {
                 Ok(c) => {
-                    log::info!("{} Successfully connected to {:?}", client.client_id, client.cfg.server);
+                    log::info!("{} Successfully connected to {:?}", client.client_id, addr);
 
                     let sink = c.sink();
                     client.sink.replace(Some(sink.clone()));
@@ -147,10 +168,13 @@ impl Client {
                     }
 
                     //client event loop
+                    client.recorder.connected();
                     client.clone().ev_loop(c).await;
+                    client.recorder.disconnected();
                 }
                 Err(e) => {
-                    log::warn!("{} Connect to {:?} fail, {:?}", client.client_id, client.cfg.server, e);
+                    log::warn!("{} Connect to {:?} fail, {:?}", client.client_id, addr, e);
+                    client.recorder.error(format!("{:?}", e));
                 }
             }
             if client.is_closed() {
@@ -162,6 +186,16 @@ impl Client {
         log::info!("{} Exit 'rmqtt-bridge-ingress-mqtt' client", client.client_id);
     }
 
+    async fn dial(&self) -> Result<SocketAddr> {
+        let endpoints = self.cfg.endpoints();
+        let (idx, addr) = Resolver::instance().dial(&endpoints, self.cfg.connect_timeout).await?;
+        if idx > 0 {
+            log::info!("{} failover to {}, {:?}", self.client_id, endpoints[idx], addr);
+        }
+        self.server_addr.set(Some(addr));
+        Ok(addr)
+    }
+
     async fn subscribe(
         self,
         sink: v3::MqttSink,
@@ -200,9 +234,10 @@ impl Client {
             .start(move |control: v3::client::ControlMessage<()>| match control {
                 v3::client::ControlMessage::Publish(publish) => {
                     log::debug!("{} publish: {:?}", self.client_id, publish);
+                    self.recorder.enqueued();
                     self.on_message.fire((
                         BridgeClient::V4(self.clone()),
-                        Some(self.server_addr),
+                        self.server_addr.get(),
                         None,
                         BridgePublish::V3(publish.packet().clone()),
                     ));
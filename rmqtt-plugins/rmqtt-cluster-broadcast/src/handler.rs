@@ -2,7 +2,7 @@ use rmqtt::broker::{Router, Shared};
 use rmqtt::{async_trait::async_trait, log};
 use rmqtt::{
     broker::{
-        hook::{Handler, HookResult, Parameter, ReturnType},
+        hook::{Handler, HookResult, Parameter, Proceed, ReturnType},
         types::{From, Publish, SubRelationsMap, SubscriptionClientIds},
     },
     grpc::{Message, MessageReply},
@@ -29,14 +29,14 @@ impl Handler for HookHandler {
             Parameter::GrpcMessageReceived(typ, msg) => {
                 log::debug!("GrpcMessageReceived, type: {}, msg: {:?}", typ, msg);
                 if self.shared.message_type != *typ {
-                    return (true, acc);
+                    return (Proceed::Continue, acc);
                 }
                 match msg {
                     Message::Forwards(from, publish) => {
                         let (shared_subs, subs_size) = forwards(from.clone(), publish.clone()).await;
                         let new_acc =
                             HookResult::GrpcMessageReply(Ok(MessageReply::Forwards(shared_subs, subs_size)));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::ForwardsTo(from, publish, sub_rels) => {
                         if let Err(droppeds) =
@@ -44,7 +44,7 @@ impl Handler for HookHandler {
                         {
                             hook_message_dropped(droppeds).await;
                         }
-                        return (false, acc);
+                        return (Proceed::Stop, acc);
                     }
                     Message::Kick(id, clean_start, clear_subscriptions, is_admin) => {
                         let entry = self.shared.inner().entry(id.clone());
@@ -64,21 +64,21 @@ impl Handler for HookHandler {
                                 HookResult::GrpcMessageReply(Err(e))
                             }
                         };
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::NumberOfClients => {
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::NumberOfClients(
                             //self.shared.inner().clients().await,
                             Runtime::instance().stats.connections.count() as usize,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::NumberOfSessions => {
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::NumberOfSessions(
                             //self.shared.inner().sessions().await,
                             Runtime::instance().stats.sessions.count() as usize,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::GetRetains(_topic_filter) => {
                         unreachable!()
@@ -92,13 +92,13 @@ impl Handler for HookHandler {
                                 .is_online(Runtime::instance().node.id(), clientid)
                                 .await,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::SubscriptionsSearch(q) => {
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::SubscriptionsSearch(
                             self.shared.inner()._query_subscriptions(q).await,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::SubscriptionsGet(clientid) => {
                         let id = Id::from(Runtime::instance().node.id(), clientid.clone());
@@ -106,13 +106,13 @@ impl Handler for HookHandler {
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::SubscriptionsGet(
                             entry.subscriptions().await,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::RoutesGet(limit) => {
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::RoutesGet(
                             self.router._inner().gets(*limit).await,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::RoutesGetBy(topic) => {
                         let routes = match self.router._inner()._get_routes(topic).await {
@@ -120,12 +120,17 @@ impl Handler for HookHandler {
                             Err(e) => Err(e),
                         };
                         let new_acc = HookResult::GrpcMessageReply(routes);
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     Message::SessionStatus(clientid) => {
                         let status = self.shared.inner().session_status(clientid).await;
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::SessionStatus(status)));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
+                    }
+                    Message::UsernameSessions(username) => {
+                        let ids = self.shared.inner().username_sessions(username);
+                        let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::UsernameSessions(ids)));
+                        return (Proceed::Stop, Some(new_acc));
                     }
 
                     _ => {
@@ -137,7 +142,7 @@ impl Handler for HookHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
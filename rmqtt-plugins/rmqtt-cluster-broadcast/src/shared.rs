@@ -1,4 +1,5 @@
 use std::convert::From as _f;
+use std::time::Duration;
 
 use once_cell::sync::OnceCell;
 
@@ -9,10 +10,10 @@ use rmqtt::{
         default::DefaultShared,
         session::{Session, SessionOfflineInfo},
         types::{
-            ClientId, From, Id, IsAdmin, IsOnline, NodeId, Publish, Reason, SessionStatus, SharedGroup,
-            SharedGroupType, SubRelations, SubRelationsMap, SubsSearchParams, SubsSearchResult, Subscribe,
-            SubscribeReturn, SubscriptionClientIds, SubscriptionIdentifier, SubscriptionOptions, To,
-            TopicFilter, Tx, Unsubscribe,
+            ClientId, ClusterTotals, From, Id, IsAdmin, IsOnline, NodeId, Publish, Reason, SessionStatus,
+            SharedGroup, SharedGroupType, SubRelations, SubRelationsMap, SubsSearchParams, SubsSearchResult,
+            Subscribe, SubscribeReturn, SubscriptionClientIds, SubscriptionIdentifier, SubscriptionOptions,
+            To, TopicFilter, Tx, Unsubscribe, UserName,
         },
         Entry, Shared,
     },
@@ -43,6 +44,11 @@ impl Entry for ClusterLockEntry {
         Ok(Box::new(ClusterLockEntry::new(self.inner.try_lock().await?, self.cluster_shared)))
     }
 
+    #[inline]
+    async fn lock(&self, timeout: Duration) -> Result<Box<dyn Entry>> {
+        Ok(Box::new(ClusterLockEntry::new(self.inner.lock(timeout).await?, self.cluster_shared)))
+    }
+
     #[inline]
     fn id(&self) -> Id {
         self.inner.id()
@@ -376,11 +382,12 @@ impl Shared for &'static ClusterShared {
             //shared subscription choice
             let mut node_shared_subs: HashMap<NodeId, SubRelations> = HashMap::default();
             for (topic_filter, sub_groups) in shared_sub_groups.iter_mut() {
-                for (_group, subs) in sub_groups.iter_mut() {
+                for (group, subs) in sub_groups.iter_mut() {
                     if let Some((idx, _is_online)) =
                         Runtime::instance().extends.shared_subscription().await.choice(subs).await
                     {
                         let (node_id, client_id, opts, sub_ids, _is_online) = subs.remove(idx);
+                        Runtime::instance().stats.shared_group_dispatched(group, node_id == this_node_id);
                         node_shared_subs.entry(node_id).or_default().push((
                             topic_filter.clone(),
                             client_id,
@@ -523,6 +530,49 @@ impl Shared for &'static ClusterShared {
         self.inner.subscriptions_count().await
     }
 
+    #[inline]
+    async fn username_sessions_all(&self, username: &str) -> Vec<Id> {
+        let mut ids = self.inner.username_sessions(username);
+        let replys = MessageBroadcaster::new(
+            self.grpc_clients.clone(),
+            self.message_type,
+            Message::UsernameSessions(UserName::from(username)),
+        )
+        .join_all()
+        .await;
+        for (node_id, reply) in replys {
+            match reply {
+                Ok(MessageReply::UsernameSessions(node_ids)) => ids.extend(node_ids),
+                Ok(reply) => {
+                    log::warn!("username_sessions_all, node({}), unexpected reply: {:?}", node_id, reply)
+                }
+                Err(e) => log::warn!("username_sessions_all, node({}), error: {:?}", node_id, e),
+            }
+        }
+        ids
+    }
+
+    #[inline]
+    async fn cluster_totals(&self) -> ClusterTotals {
+        let mut totals = self.inner.cluster_totals().await;
+        let (clients, sessions) = futures::future::join(
+            MessageBroadcaster::new(self.grpc_clients.clone(), self.message_type, Message::NumberOfClients)
+                .join_all(),
+            MessageBroadcaster::new(self.grpc_clients.clone(), self.message_type, Message::NumberOfSessions)
+                .join_all(),
+        )
+        .await;
+        for (node_id, reply) in clients.into_iter().chain(sessions) {
+            match reply {
+                Ok(MessageReply::NumberOfClients(n)) => totals.connections += n,
+                Ok(MessageReply::NumberOfSessions(n)) => totals.sessions += n,
+                Ok(reply) => log::warn!("cluster_totals, node({}), unexpected reply: {:?}", node_id, reply),
+                Err(e) => log::warn!("cluster_totals, node({}), error: {:?}", node_id, e),
+            }
+        }
+        totals
+    }
+
     #[inline]
     fn get_grpc_clients(&self) -> GrpcClients {
         self.grpc_clients.clone()
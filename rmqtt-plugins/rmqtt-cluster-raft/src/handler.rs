@@ -4,7 +4,7 @@ use rmqtt::broker::Shared;
 use rmqtt::rust_box::task_exec_queue::SpawnExt;
 use rmqtt::{async_trait::async_trait, log, tokio, MqttError};
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, ReturnType},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, ReturnType},
     grpc::{Message as GrpcMessage, MessageReply},
     Id, Runtime,
 };
@@ -107,7 +107,7 @@ impl Handler for HookHandler {
             Parameter::GrpcMessageReceived(typ, msg) => {
                 log::debug!("GrpcMessageReceived, type: {}, msg: {:?}", typ, msg);
                 if self.shared.message_type != *typ {
-                    return (true, acc);
+                    return (Proceed::Continue, acc);
                 }
                 match msg {
                     GrpcMessage::ForwardsTo(from, publish, sub_rels) => {
@@ -116,7 +116,7 @@ impl Handler for HookHandler {
                         {
                             hook_message_dropped(droppeds).await;
                         }
-                        return (false, acc);
+                        return (Proceed::Stop, acc);
                     }
                     GrpcMessage::Kick(id, clean_start, clear_subscriptions, is_admin) => {
                         let mut entry = self.shared.inner().entry(id.clone());
@@ -125,7 +125,7 @@ impl Handler for HookHandler {
                             Ok(None) => HookResult::GrpcMessageReply(Ok(MessageReply::Kick(None))),
                             Err(e) => HookResult::GrpcMessageReply(Err(e)),
                         };
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     GrpcMessage::GetRetains(topic_filter) => {
                         log::debug!("[GrpcMessage::GetRetains] topic_filter: {:?}", topic_filter);
@@ -137,7 +137,24 @@ impl Handler for HookHandler {
                         let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::SubscriptionsGet(
                             entry.subscriptions().await,
                         )));
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
+                    }
+                    GrpcMessage::UsernameSessions(username) => {
+                        let ids = self.shared.inner().username_sessions(username);
+                        let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::UsernameSessions(ids)));
+                        return (Proceed::Stop, Some(new_acc));
+                    }
+                    GrpcMessage::NumberOfClients => {
+                        let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::NumberOfClients(
+                            Runtime::instance().stats.connections.count() as usize,
+                        )));
+                        return (Proceed::Stop, Some(new_acc));
+                    }
+                    GrpcMessage::NumberOfSessions => {
+                        let new_acc = HookResult::GrpcMessageReply(Ok(MessageReply::NumberOfSessions(
+                            Runtime::instance().stats.sessions.count() as usize,
+                        )));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     GrpcMessage::Data(data) => {
                         let new_acc = match RaftGrpcMessage::decode(data) {
@@ -164,7 +181,7 @@ impl Handler for HookHandler {
                                 }
                             }
                         };
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     _ => {
                         log::error!("unimplemented, {:?}", param)
@@ -175,6 +192,6 @@ impl Handler for HookHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
@@ -149,8 +149,8 @@ impl ClusterPlugin {
                 } else {
                     //The other nodes are leader.
                     let actual_leader_info = find_actual_leader(&raft, peer_addrs, 60).await?;
-                    let (actual_leader_id, actual_leader_addr) =
-                        actual_leader_info.ok_or_else(|| MqttError::from("Leader does not exist"))?;
+                    let (actual_leader_id, actual_leader_addr) = actual_leader_info
+                        .ok_or_else(|| MqttError::Cluster("Leader does not exist".into()))?;
                     if actual_leader_id != leader_info.id {
                         return Err(MqttError::from(format!(
                             "Not the expected Leader, the expected one is {:?}",
@@ -270,7 +270,7 @@ impl Plugin for ClusterPlugin {
         let status = raft_mailbox.status().await.map_err(anyhow::Error::new)?;
         log::info!("raft status: {:?}", status);
         if !status.is_started() {
-            return Err(MqttError::from("Raft cluster status is abnormal"));
+            return Err(MqttError::Cluster("Raft cluster status is abnormal".into()));
         }
 
         let ping = message::Message::Ping.encode()?;
@@ -295,7 +295,7 @@ impl Plugin for ClusterPlugin {
             sleep(Duration::from_millis(500)).await;
         }
 
-        Err(MqttError::from("Raft cluster status is unavailable"))
+        Err(MqttError::Cluster("Raft cluster status is unavailable".into()))
     }
 
     #[inline]
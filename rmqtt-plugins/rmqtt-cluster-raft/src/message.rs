@@ -30,6 +30,9 @@ impl<'a> Message<'a> {
     }
 }
 
+//Reply error of HandshakeTryLock while another handshake of the client id holds the lock
+pub(crate) const HANDSHAKE_TRY_LOCK_FAILED: &str = "Handshake try lock failed";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum MessageReply {
     Error(String),
@@ -26,7 +26,7 @@ use rmqtt::{
 use crate::task_exec_queue;
 
 use super::config::{retry, BACKOFF_STRATEGY};
-use super::message::{Message, MessageReply};
+use super::message::{Message, MessageReply, HANDSHAKE_TRY_LOCK_FAILED};
 
 type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
 type DashMap<K, V> = dashmap::DashMap<K, V, ahash::RandomState>;
@@ -250,7 +250,7 @@ impl Store for &'static ClusterRouter {
                 return if try_lock_ok {
                     Ok(MessageReply::HandshakeTryLock(prev_id).encode().map_err(|_e| Error::Unknown)?)
                 } else {
-                    Ok(MessageReply::Error("Handshake try lock failed".into())
+                    Ok(MessageReply::Error(HANDSHAKE_TRY_LOCK_FAILED.into())
                         .encode()
                         .map_err(|_e| Error::Unknown)?)
                 };
@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 use std::convert::From as _;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use rmqtt::{
     anyhow, anyhow::Error, async_trait::async_trait, futures, futures::future::FutureExt, log,
@@ -11,9 +11,9 @@ use rmqtt::{
         default::DefaultShared,
         session::{Session, SessionOfflineInfo},
         types::{
-            From, Id, IsAdmin, NodeId, NodeName, Publish, Reason, SessionStatus, SubRelations,
+            ClusterTotals, From, Id, IsAdmin, NodeId, NodeName, Publish, Reason, SessionStatus, SubRelations,
             SubRelationsMap, SubsSearchParams, SubsSearchResult, Subscribe, SubscribeReturn,
-            SubscriptionClientIds, To, Tx, Unsubscribe,
+            SubscriptionClientIds, To, Tx, Unsubscribe, UserName,
         },
         Entry, Router, Shared,
     },
@@ -22,9 +22,10 @@ use rmqtt::{
     MqttError, Result, Runtime,
 };
 
+use super::config::{retry, ExponentialBackoffBuilder};
 use super::message::{
     get_client_node_id, Message as RaftMessage, MessageReply as RaftMessageReply, RaftGrpcMessage,
-    RaftGrpcMessageReply,
+    RaftGrpcMessageReply, HANDSHAKE_TRY_LOCK_FAILED,
 };
 use super::{task_exec_queue, ClusterRouter, GrpcClients, HashMap, MessageSender, NodeGrpcClient};
 
@@ -43,12 +44,10 @@ impl ClusterLockEntry {
     ) -> Self {
         Self { inner, cluster_shared, prev_node_id }
     }
-}
 
-#[async_trait]
-impl Entry for ClusterLockEntry {
+    //The handshake lock of the client id across the cluster, returns the node of the previous session
     #[inline]
-    async fn try_lock(&self) -> Result<Box<dyn Entry>> {
+    async fn handshake_try_lock(&self) -> Result<Option<NodeId>> {
         let msg = RaftMessage::HandshakeTryLock { id: self.id() }.encode()?;
         let raft_mailbox = self.cluster_shared.router.raft_mailbox().await;
         let reply = raft_mailbox.send_proposal(msg).await.map_err(anyhow::Error::new)?;
@@ -69,9 +68,48 @@ impl Entry for ClusterLockEntry {
                 _ => unreachable!(),
             }
         }
+        Ok(prev_node_id)
+    }
+
+    //Waits up to `timeout` for the handshake lock of the client id across the cluster, it is held by
+    //another handshake until that handshake completes
+    #[inline]
+    async fn handshake_lock(&self, timeout: Duration) -> Result<Option<NodeId>> {
+        let backoff = ExponentialBackoffBuilder::new()
+            .with_initial_interval(Duration::from_millis(10))
+            .with_max_interval(Duration::from_millis(500))
+            .with_max_elapsed_time(Some(timeout))
+            .build();
+        retry(backoff, || async {
+            self.handshake_try_lock().await.map_err(|e| {
+                if matches!(&e, MqttError::Msg(msg) if msg == HANDSHAKE_TRY_LOCK_FAILED) {
+                    backoff::Error::transient(e)
+                } else {
+                    backoff::Error::permanent(e)
+                }
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Entry for ClusterLockEntry {
+    #[inline]
+    async fn try_lock(&self) -> Result<Box<dyn Entry>> {
+        let prev_node_id = self.handshake_try_lock().await?;
         Ok(Box::new(ClusterLockEntry::new(self.inner.try_lock().await?, self.cluster_shared, prev_node_id)))
     }
 
+    #[inline]
+    async fn lock(&self, timeout: Duration) -> Result<Box<dyn Entry>> {
+        //The local lock first, so only one handshake of this node waits for the cluster lock
+        let start = Instant::now();
+        let inner = self.inner.lock(timeout).await?;
+        let prev_node_id = self.handshake_lock(timeout.saturating_sub(start.elapsed())).await?;
+        Ok(Box::new(ClusterLockEntry::new(inner, self.cluster_shared, prev_node_id)))
+    }
+
     #[inline]
     fn id(&self) -> Id {
         self.inner.id()
@@ -342,6 +380,7 @@ impl Shared for &'static ClusterShared {
 
         //let subs_size: SubscriptionSize = relations_map.values().map(|subs| subs.len()).sum();
         let sub_client_ids = self.inner()._collect_subscription_client_ids(&relations_map);
+        Runtime::instance().stats.shared_groups_dispatched(&relations_map);
 
         let mut errs = Vec::new();
 
@@ -467,6 +506,49 @@ impl Shared for &'static ClusterShared {
     async fn subscriptions_count(&self) -> usize {
         self.inner.subscriptions_count().await
     }
+
+    #[inline]
+    async fn username_sessions_all(&self, username: &str) -> Vec<Id> {
+        let mut ids = self.inner.username_sessions(username);
+        let replys = MessageBroadcaster::new(
+            self.grpc_clients.clone(),
+            self.message_type,
+            Message::UsernameSessions(UserName::from(username)),
+        )
+        .join_all()
+        .await;
+        for (node_id, reply) in replys {
+            match reply {
+                Ok(MessageReply::UsernameSessions(node_ids)) => ids.extend(node_ids),
+                Ok(reply) => {
+                    log::warn!("username_sessions_all, node({}), unexpected reply: {:?}", node_id, reply)
+                }
+                Err(e) => log::warn!("username_sessions_all, node({}), error: {:?}", node_id, e),
+            }
+        }
+        ids
+    }
+
+    #[inline]
+    async fn cluster_totals(&self) -> ClusterTotals {
+        let mut totals = self.inner.cluster_totals().await;
+        let (clients, sessions) = futures::future::join(
+            MessageBroadcaster::new(self.grpc_clients.clone(), self.message_type, Message::NumberOfClients)
+                .join_all(),
+            MessageBroadcaster::new(self.grpc_clients.clone(), self.message_type, Message::NumberOfSessions)
+                .join_all(),
+        )
+        .await;
+        for (node_id, reply) in clients.into_iter().chain(sessions) {
+            match reply {
+                Ok(MessageReply::NumberOfClients(n)) => totals.connections += n,
+                Ok(MessageReply::NumberOfSessions(n)) => totals.sessions += n,
+                Ok(reply) => log::warn!("cluster_totals, node({}), unexpected reply: {:?}", node_id, reply),
+                Err(e) => log::warn!("cluster_totals, node({}), error: {:?}", node_id, e),
+            }
+        }
+        totals
+    }
     #[inline]
     fn get_grpc_clients(&self) -> GrpcClients {
         self.grpc_clients.clone()
@@ -4,9 +4,9 @@
 extern crate rmqtt_macros;
 
 use rmqtt::broker::hook::Priority;
-use rmqtt::{async_trait::async_trait, log, FromType};
+use rmqtt::{async_trait::async_trait, log, FromType, QoS, Reason};
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::metrics::Metrics,
     plugin::{PackageInfo, Plugin},
     register, Result, Runtime,
@@ -129,7 +129,7 @@ impl Handler for CounterHandler {
             Parameter::ClientAuthenticate(_) => {
                 self.metrics.client_authenticate_inc();
             }
-            Parameter::ClientConnack(connect_info, reason) => {
+            Parameter::ClientConnack(connect_info, reason, _) => {
                 self.metrics.client_connack_inc();
                 match reason.success_or_auth_error() {
                     (true, _) => {}
@@ -163,7 +163,7 @@ impl Handler for CounterHandler {
                 self.metrics.client_unsubscribe_inc();
             }
 
-            Parameter::SessionCreated(_session) => {
+            Parameter::SessionCreated(_session, _clean_start) => {
                 self.metrics.session_created_inc();
             }
             Parameter::SessionTerminated(_session, _r) => {
@@ -179,14 +179,13 @@ impl Handler for CounterHandler {
             Parameter::MessagePublishCheckAcl(_session, _p) => {
                 self.metrics.client_publish_check_acl_inc();
             }
-            Parameter::MessagePublish(_session, from, _p) => {
-                // self.metrics.messages_received_inc();  //@TODO ... elaboration
-                // match p.qos{
-                //     QoS::AtMostOnce => self.metrics.messages_received_qos0_inc(),
-                //     QoS::AtLeastOnce => self.metrics.messages_received_qos1_inc(),
-                //     QoS::ExactlyOnce => self.metrics.messages_received_qos2_inc(),
-                // }
+            Parameter::MessagePublish(_session, from, p) => {
                 self.metrics.messages_publish_inc();
+                match p.qos {
+                    QoS::AtMostOnce => self.metrics.messages_publish_qos0_inc(),
+                    QoS::AtLeastOnce => self.metrics.messages_publish_qos1_inc(),
+                    QoS::ExactlyOnce => self.metrics.messages_publish_qos2_inc(),
+                }
                 match from.typ() {
                     FromType::Custom => self.metrics.messages_publish_custom_inc(),
                     FromType::Admin => self.metrics.messages_publish_admin_inc(),
@@ -200,6 +199,11 @@ impl Handler for CounterHandler {
                 if p.retain {
                     self.metrics.messages_delivered_retain_inc()
                 }
+                match p.qos {
+                    QoS::AtMostOnce => self.metrics.messages_delivered_qos0_inc(),
+                    QoS::AtLeastOnce => self.metrics.messages_delivered_qos1_inc(),
+                    QoS::ExactlyOnce => self.metrics.messages_delivered_qos2_inc(),
+                }
                 match from.typ() {
                     FromType::Custom => self.metrics.messages_delivered_custom_inc(),
                     FromType::Admin => self.metrics.messages_delivered_admin_inc(),
@@ -221,8 +225,16 @@ impl Handler for CounterHandler {
                     FromType::Bridge => self.metrics.messages_acked_bridge_inc(),
                 }
             }
-            Parameter::MessageDropped(_to, _from, _p, _r) => {
-                self.metrics.messages_dropped_inc(); //@TODO ... elaboration
+            Parameter::MessageDropped(_to, _from, _p, r) => {
+                self.metrics.messages_dropped_inc();
+                match r {
+                    Reason::MessageExpiration => self.metrics.messages_dropped_expired_inc(),
+                    Reason::MessageQueueFull => self.metrics.messages_dropped_queue_full_inc(),
+                    Reason::PublishRefused | Reason::DelayedPublishRefused => {
+                        self.metrics.messages_dropped_refused_inc()
+                    }
+                    _ => {}
+                }
             }
             Parameter::MessageNonsubscribed(from) => {
                 self.metrics.messages_nonsubscribed_inc();
@@ -239,6 +251,6 @@ impl Handler for CounterHandler {
                 log::error!("parameter is: {:?}", param);
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
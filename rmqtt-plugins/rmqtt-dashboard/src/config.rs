@@ -0,0 +1,91 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rmqtt::serde_json;
+use rmqtt::{
+    settings::{deserialize_addr, deserialize_duration},
+    MqttError, Result,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(default = "PluginConfig::workers_default")]
+    pub workers: usize,
+
+    #[serde(default = "PluginConfig::http_laddr_default", deserialize_with = "deserialize_addr")]
+    pub http_laddr: SocketAddr,
+
+    #[serde(default = "PluginConfig::username_default")]
+    pub username: String,
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+
+    #[serde(default = "PluginConfig::api_url_default")]
+    pub api_url: String,
+    #[serde(default, skip_serializing)]
+    pub api_bearer_token: Option<String>,
+    #[serde(default = "PluginConfig::api_timeout_default", deserialize_with = "deserialize_duration")]
+    pub api_timeout: Duration,
+
+    #[serde(default = "PluginConfig::event_buffer_size_default")]
+    pub event_buffer_size: usize,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn workers_default() -> usize {
+        1
+    }
+
+    #[inline]
+    fn http_laddr_default() -> SocketAddr {
+        ([127, 0, 0, 1], 6080).into()
+    }
+
+    #[inline]
+    fn username_default() -> String {
+        "admin".into()
+    }
+
+    #[inline]
+    fn api_url_default() -> String {
+        "http://127.0.0.1:6060/api/v1".into()
+    }
+
+    #[inline]
+    fn api_timeout_default() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    #[inline]
+    fn event_buffer_size_default() -> usize {
+        1000
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    ///The dashboard adds `api_bearer_token` to the requests it forwards, so it must not be served
+    ///without a login
+    #[inline]
+    pub fn validate(&self) -> Result<()> {
+        if self.api_bearer_token.is_some() && self.password.is_none() {
+            return Err(MqttError::from(
+                "api_bearer_token is set without a password, the management API would be open to everyone",
+            ));
+        }
+        Ok(())
+    }
+
+    ///Whether the web server has to be restarted for the new configuration, the login is also only read
+    ///when the server starts
+    #[inline]
+    pub fn restart_enable(&self, other: &Self) -> bool {
+        self.workers != other.workers
+            || self.http_laddr != other.http_laddr
+            || self.username != other.username
+            || self.password != other.password
+    }
+}
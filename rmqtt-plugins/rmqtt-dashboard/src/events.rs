@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use rmqtt::{
+    async_trait::async_trait,
+    serde_json::{self, json},
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Proceed, ReturnType},
+    broker::types::timestamp_millis,
+};
+
+///Recent client and subscription events of this node, read by the live event view
+pub struct EventLog {
+    inner: Mutex<Events>,
+}
+
+struct Events {
+    seq: u64,
+    cap: usize,
+    items: VecDeque<serde_json::Value>,
+}
+
+impl EventLog {
+    pub fn new(cap: usize) -> Self {
+        Self { inner: Mutex::new(Events { seq: 0, cap, items: VecDeque::new() }) }
+    }
+
+    #[inline]
+    pub fn set_capacity(&self, cap: usize) {
+        let mut inner = self.inner();
+        inner.cap = cap;
+        while inner.items.len() > cap {
+            inner.items.pop_front();
+        }
+    }
+
+    ///Adds an event, the oldest event is removed when the buffer is full
+    pub fn push(&self, event: &str, mut body: serde_json::Value) {
+        let mut inner = self.inner();
+        if inner.cap == 0 {
+            return;
+        }
+        inner.seq += 1;
+        if let Some(obj) = body.as_object_mut() {
+            obj.insert("seq".into(), json!(inner.seq));
+            obj.insert("event".into(), json!(event));
+            obj.insert("time".into(), json!(timestamp_millis()));
+        }
+        if inner.items.len() >= inner.cap {
+            inner.items.pop_front();
+        }
+        inner.items.push_back(body);
+    }
+
+    ///The events with a sequence number greater than `seq`, at most `limit` of the newest ones
+    pub fn after(&self, seq: u64, limit: usize) -> Vec<serde_json::Value> {
+        let inner = self.inner();
+        let newer = inner
+            .items
+            .iter()
+            .rev()
+            .take_while(|e| e.get("seq").and_then(|s| s.as_u64()).map(|s| s > seq).unwrap_or_default())
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        newer.into_iter().rev().collect()
+    }
+
+    #[inline]
+    fn inner(&self) -> std::sync::MutexGuard<'_, Events> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub(crate) struct EventHandler {
+    events: Arc<EventLog>,
+}
+
+impl EventHandler {
+    pub(crate) fn new(events: &Arc<EventLog>) -> Self {
+        Self { events: events.clone() }
+    }
+}
+
+#[async_trait]
+impl Handler for EventHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        let body = match param {
+            Parameter::ClientConnected(session) => Some(json!({
+                "clientid": session.id.client_id,
+                "username": session.id.username_ref(),
+                "ipaddress": session.id.remote_addr,
+            })),
+            Parameter::ClientDisconnected(session, reason) => Some(json!({
+                "clientid": session.id.client_id,
+                "username": session.id.username_ref(),
+                "ipaddress": session.id.remote_addr,
+                "reason": reason.to_string(),
+            })),
+            Parameter::SessionSubscribed(session, subscribe) => Some(json!({
+                "clientid": session.id.client_id,
+                "username": session.id.username_ref(),
+                "topic": subscribe.topic_filter,
+                "opts": subscribe.opts.to_json(),
+            })),
+            Parameter::SessionUnsubscribed(session, unsubscribed) => Some(json!({
+                "clientid": session.id.client_id,
+                "username": session.id.username_ref(),
+                "topic": unsubscribed.topic_filter,
+            })),
+            _ => None,
+        };
+        if let Some(body) = body {
+            self.events.push(param.get_type().as_str(), body);
+        }
+        (Proceed::Continue, acc)
+    }
+}
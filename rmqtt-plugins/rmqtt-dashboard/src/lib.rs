@@ -0,0 +1,138 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::sync::Arc;
+
+use config::PluginConfig;
+use events::{EventHandler, EventLog};
+use rmqtt::{
+    async_trait::async_trait,
+    log, serde_json,
+    tokio::{self, sync::oneshot, sync::RwLock},
+};
+use rmqtt::{
+    broker::hook::{Register, Type},
+    plugin::{PackageInfo, Plugin},
+    register, Result, Runtime,
+};
+
+mod config;
+mod events;
+mod server;
+
+type ShutdownTX = oneshot::Sender<()>;
+type PluginConfigType = Arc<RwLock<PluginConfig>>;
+
+register!(DashboardPlugin::new);
+
+#[derive(Plugin)]
+struct DashboardPlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: PluginConfigType,
+    events: Arc<EventLog>,
+    shutdown_tx: Option<ShutdownTX>,
+}
+
+impl DashboardPlugin {
+    #[inline]
+    async fn new<S: Into<String>>(runtime: &'static Runtime, name: S) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config_default::<PluginConfig>(&name)?;
+        log::debug!("{} DashboardPlugin cfg: {:?}", name, cfg);
+        let events = Arc::new(EventLog::new(cfg.event_buffer_size));
+        let cfg = Arc::new(RwLock::new(cfg));
+        let register = runtime.extends.hook_mgr().await.register();
+        Ok(Self { runtime, register, cfg, events, shutdown_tx: None })
+    }
+
+    async fn serve(cfg: PluginConfigType, events: Arc<EventLog>) -> ShutdownTX {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let (workers, http_laddr) = {
+            let cfg = cfg.read().await;
+            (cfg.workers, cfg.http_laddr)
+        };
+        let _child = std::thread::Builder::new().name("dashboard".to_string()).spawn(move || {
+            let runner = async move {
+                if let Err(e) = server::listen_and_serve(http_laddr, cfg, events, shutdown_rx).await {
+                    log::error!("{:?}", e);
+                }
+            };
+
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .worker_threads(workers)
+                .thread_name("dashboard-worker")
+                .build()
+                .expect("tokio runtime build failed");
+            rt.block_on(runner);
+            log::info!("Exit Dashboard, ..., http://{:?}", http_laddr);
+        });
+        shutdown_tx
+    }
+
+    #[inline]
+    fn shutdown(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            if let Err(e) = tx.send(()) {
+                log::warn!("shutdown_tx send fail, {:?}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for DashboardPlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        self.register.add(Type::ClientConnected, Box::new(EventHandler::new(&self.events))).await;
+        self.register.add(Type::ClientDisconnected, Box::new(EventHandler::new(&self.events))).await;
+        self.register.add(Type::SessionSubscribed, Box::new(EventHandler::new(&self.events))).await;
+        self.register.add(Type::SessionUnsubscribed, Box::new(EventHandler::new(&self.events))).await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        new_cfg.validate()?;
+        self.events.set_capacity(new_cfg.event_buffer_size);
+        let restart = self.cfg.read().await.restart_enable(&new_cfg);
+        *self.cfg.write().await = new_cfg;
+        if restart && self.shutdown_tx.is_some() {
+            self.shutdown();
+            self.shutdown_tx = Some(Self::serve(self.cfg.clone(), self.events.clone()).await);
+        }
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.cfg.read().await.validate()?;
+        self.register.start().await;
+        if self.shutdown_tx.is_none() {
+            self.shutdown_tx = Some(Self::serve(self.cfg.clone(), self.events.clone()).await);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        self.shutdown();
+        Ok(true)
+    }
+}
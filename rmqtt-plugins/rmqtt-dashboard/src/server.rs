@@ -0,0 +1,205 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use salvo::conn::tcp::TcpAcceptor;
+use salvo::http::header::{HeaderValue, CONTENT_TYPE, HOST, ORIGIN, WWW_AUTHENTICATE};
+use salvo::http::Method;
+use salvo::prelude::*;
+
+use rmqtt::{
+    anyhow::anyhow,
+    base64::prelude::{Engine, BASE64_STANDARD},
+    log,
+    once_cell::sync::Lazy,
+    reqwest,
+    tokio::{self, sync::oneshot},
+};
+use rmqtt::{MqttError, Result};
+
+use super::events::EventLog;
+use super::PluginConfigType;
+
+const INDEX_HTML: &str = include_str!("../assets/index.html");
+//events returned by one poll of the live event view
+const MAX_EVENTS_PER_POLL: usize = 500;
+
+static HTTP_CLIENT: Lazy<Result<reqwest::Client>> =
+    Lazy::new(|| reqwest::Client::builder().build().map_err(|e| MqttError::from(anyhow!(e))));
+
+struct BasicAuthValidator {
+    authorization: String,
+}
+
+impl BasicAuthValidator {
+    fn new(username: &str, password: &str) -> Self {
+        let credentials = BASE64_STANDARD.encode(format!("{}:{}", username, password));
+        Self { authorization: format!("Basic {}", credentials) }
+    }
+}
+
+#[async_trait]
+impl Handler for BasicAuthValidator {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        let authorized = req
+            .headers()
+            .get("authorization")
+            .is_some_and(|auth| constant_time_eq(auth.as_bytes(), self.authorization.as_bytes()));
+        if authorized {
+            ctrl.call_next(req, depot, res).await;
+        } else {
+            res.status_code(StatusCode::UNAUTHORIZED);
+            res.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic realm=\"rmqtt-dashboard\""));
+            ctrl.skip_rest()
+        }
+    }
+}
+
+#[inline]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+//Browsers resend the basic authentication credentials with requests that other sites trigger, so requests
+//that change state must come from a page of the dashboard itself
+struct OriginValidator;
+
+#[async_trait]
+impl Handler for OriginValidator {
+    async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+        if matches!(*req.method(), Method::GET | Method::HEAD) || Self::same_origin(req) {
+            ctrl.call_next(req, depot, res).await;
+        } else {
+            res.render(StatusError::forbidden().detail("cross-origin request"));
+            ctrl.skip_rest()
+        }
+    }
+}
+
+impl OriginValidator {
+    fn same_origin(req: &Request) -> bool {
+        let origin = req.headers().get(ORIGIN).and_then(|o| o.to_str().ok());
+        let host = req.headers().get(HOST).and_then(|h| h.to_str().ok());
+        match (origin, host) {
+            (Some(origin), Some(host)) => {
+                origin.split_once("://").map(|(_, origin_host)| origin_host == host).unwrap_or_default()
+            }
+            _ => false,
+        }
+    }
+}
+
+fn route(cfg: PluginConfigType, events: Arc<EventLog>, password: Option<String>, username: &str) -> Router {
+    let mut router = Router::new().hoop(affix::inject(cfg).inject(events));
+    if let Some(password) = password {
+        router = router.hoop(BasicAuthValidator::new(username, &password));
+    }
+    router
+        .hoop(OriginValidator)
+        .get(index)
+        .push(Router::with_path("events").get(get_events))
+        .push(Router::with_path("api/v1/<**rest>").goal(api_proxy))
+}
+
+pub(crate) async fn listen_and_serve(
+    laddr: SocketAddr,
+    cfg: PluginConfigType,
+    events: Arc<EventLog>,
+    rx: oneshot::Receiver<()>,
+) -> Result<()> {
+    let (username, password) = {
+        let cfg = cfg.read().await;
+        (cfg.username.clone(), cfg.password.clone())
+    };
+    log::info!("Dashboard Listening on {}", laddr);
+
+    let listen = rmqtt::tokio::net::TcpListener::from_std(rmqtt::grpc::server::Server::bind(
+        laddr, 128, true, false,
+    )?)?;
+
+    let acceptor = TcpAcceptor::try_from(listen)?;
+    let server = Server::new(acceptor);
+    let handler = server.handle();
+    tokio::task::spawn(async move {
+        rx.await.ok();
+        handler.stop_graceful(None);
+    });
+    server.try_serve(route(cfg, events, password, &username)).await?;
+    Ok(())
+}
+
+#[inline]
+fn get_cfg(depot: &mut Depot) -> Result<&PluginConfigType, salvo::Error> {
+    depot.obtain::<PluginConfigType>().map_err(|_| {
+        salvo::Error::Io(std::io::Error::new(ErrorKind::NotFound, anyhow!("plugin config is not injected")))
+    })
+}
+
+#[handler]
+async fn index(res: &mut Response) {
+    res.render(Text::Html(INDEX_HTML));
+}
+
+#[handler]
+async fn get_events(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let after = req.query::<u64>("after").unwrap_or_default();
+    match depot.obtain::<Arc<EventLog>>() {
+        Ok(events) => res.render(Json(events.after(after, MAX_EVENTS_PER_POLL))),
+        Err(_) => res.render(StatusError::internal_server_error()),
+    }
+}
+
+//Forwards the request to the management API, the bearer token of the API is added here so it is
+//never sent to the browser
+#[handler]
+async fn api_proxy(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
+    let (api_url, token, timeout) = {
+        let cfg = get_cfg(depot)?.read().await;
+        (cfg.api_url.clone(), cfg.api_bearer_token.clone(), cfg.api_timeout)
+    };
+    let path = req.param::<String>("rest").unwrap_or_default();
+    let url = match req.uri().query() {
+        Some(query) => format!("{}/{}?{}", api_url.trim_end_matches('/'), path, query),
+        None => format!("{}/{}", api_url.trim_end_matches('/'), path),
+    };
+
+    match forward(req, url, token, timeout).await {
+        Ok((status, content_type, body)) => {
+            res.status_code(StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY));
+            if let Some(content_type) = content_type.and_then(|c| HeaderValue::from_str(&c).ok()) {
+                res.headers_mut().insert(CONTENT_TYPE, content_type);
+            }
+            res.write_body(body)?;
+        }
+        Err(e) => {
+            log::warn!("management API request error, {}", e);
+            res.render(StatusError::bad_gateway().detail(e.to_string()));
+        }
+    }
+    Ok(())
+}
+
+async fn forward(
+    req: &mut Request,
+    url: String,
+    token: Option<String>,
+    timeout: std::time::Duration,
+) -> Result<(u16, Option<String>, Vec<u8>)> {
+    let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+        .map_err(|e| MqttError::from(anyhow!(e)))?;
+    let body = req.payload().await.map(|b| b.to_vec()).unwrap_or_default();
+    let mut builder = HTTP_CLIENT.as_ref()?.request(method, url).timeout(timeout);
+    if let Some(content_type) = req.headers().get(CONTENT_TYPE).and_then(|c| c.to_str().ok()) {
+        builder = builder.header(reqwest::header::CONTENT_TYPE, content_type);
+    }
+    if let Some(token) = token {
+        builder = builder.bearer_auth(token);
+    }
+    let resp = builder.body(body).send().await.map_err(|e| MqttError::from(anyhow!(e)))?;
+    let status = resp.status().as_u16();
+    let content_type =
+        resp.headers().get(reqwest::header::CONTENT_TYPE).and_then(|c| c.to_str().ok()).map(String::from);
+    let body = resp.bytes().await.map_err(|e| MqttError::from(anyhow!(e)))?;
+    Ok((status, content_type, body.to_vec()))
+}
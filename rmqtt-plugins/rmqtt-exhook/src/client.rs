@@ -0,0 +1,138 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use tonic::transport::{Channel, Endpoint};
+
+use rmqtt::{ahash, anyhow, log, tokio::sync::RwLock};
+use rmqtt::{broker::hook::Type, broker::topic::TopicTree, MqttError, Result, Runtime, Topic};
+
+use crate::config::{hook_type, PluginConfig};
+use crate::pb::{self, hook_provider_client::HookProviderClient};
+
+type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
+
+///Topic filters of a hook, None means all topics
+type HookTopics = Option<Arc<TopicTree<()>>>;
+
+struct Provider {
+    client: HookProviderClient<Channel>,
+    hooks: HashMap<Type, HookTopics>,
+}
+
+pub(crate) struct ExHookClient {
+    cfg: Arc<RwLock<PluginConfig>>,
+    provider: RwLock<Option<Provider>>,
+}
+
+impl ExHookClient {
+    #[inline]
+    pub(crate) fn new(cfg: Arc<RwLock<PluginConfig>>) -> Self {
+        Self { cfg, provider: RwLock::new(None) }
+    }
+
+    #[inline]
+    pub(crate) async fn is_loaded(&self) -> bool {
+        self.provider.read().await.is_some()
+    }
+
+    ///Whether the hook is requested by the provider and the topic matches its topic filters
+    #[inline]
+    pub(crate) async fn is_hooked(&self, typ: Type, topic: Option<&str>) -> bool {
+        let provider = self.provider.read().await;
+        let topics = if let Some(topics) = provider.as_ref().and_then(|p| p.hooks.get(&typ)) {
+            topics
+        } else {
+            return false;
+        };
+        match (topics, topic) {
+            (Some(tree), Some(topic)) => Topic::from_str(topic).map(|t| tree.is_match(&t)).unwrap_or(false),
+            _ => true,
+        }
+    }
+
+    ///Connects to the external server and loads the hooks it is interested in
+    pub(crate) async fn load(&self) -> Result<()> {
+        let (url, timeout) = {
+            let cfg = self.cfg.read().await;
+            (cfg.url.clone(), cfg.request_timeout)
+        };
+        let endpoint = Endpoint::from_shared(url)
+            .map_err(anyhow::Error::new)?
+            .connect_timeout(timeout)
+            .timeout(timeout);
+        let channel = endpoint.connect().await.map_err(anyhow::Error::new)?;
+        let mut client = HookProviderClient::new(channel);
+        let resp = client
+            .on_provider_loaded(pb::ProviderLoadedRequest { broker: Some(broker_info().await) })
+            .await
+            .map_err(anyhow::Error::new)?
+            .into_inner();
+
+        let mut hooks = HashMap::default();
+        {
+            let cfg = self.cfg.read().await;
+            for spec in resp.hooks {
+                let typ = if let Some(typ) = hook_type(&spec.name) {
+                    typ
+                } else {
+                    log::warn!("exhook, unsupported hook: {}", spec.name);
+                    continue;
+                };
+                if !cfg.is_enabled(&spec.name) {
+                    continue;
+                }
+                let topics = if spec.topics.is_empty() {
+                    None
+                } else {
+                    let mut tree = TopicTree::default();
+                    for tf in spec.topics.iter() {
+                        tree.insert(&Topic::from_str(tf)?, ());
+                    }
+                    Some(Arc::new(tree))
+                };
+                hooks.insert(typ, topics);
+            }
+        }
+        log::info!("exhook, provider loaded, hooks: {:?}", hooks.keys().collect::<Vec<_>>());
+        self.provider.write().await.replace(Provider { client, hooks });
+        Ok(())
+    }
+
+    pub(crate) async fn unload(&self) {
+        if let Some(mut provider) = self.provider.write().await.take() {
+            let req = pb::ProviderUnloadedRequest { broker: Some(broker_info().await) };
+            if let Err(e) = provider.client.on_provider_unloaded(req).await {
+                log::warn!("exhook, provider unloaded error, {:?}", e);
+            }
+        }
+    }
+
+    pub(crate) async fn call(&self, req: pb::HookRequest) -> Result<pb::ValuedResponse> {
+        let mut client = if let Some(provider) = self.provider.read().await.as_ref() {
+            provider.client.clone()
+        } else {
+            return Err(MqttError::from("exhook, provider is not loaded"));
+        };
+        match client.on_hook(req).await {
+            Ok(resp) => Ok(resp.into_inner()),
+            Err(status) => {
+                //The connection is broken, reload the provider on the next reconnect
+                if status.code() == tonic::Code::Unavailable {
+                    self.provider.write().await.take();
+                }
+                Err(MqttError::from(format!("exhook, request error, {}", status)))
+            }
+        }
+    }
+}
+
+#[inline]
+pub(crate) async fn broker_info() -> pb::BrokerInfo {
+    let info = Runtime::instance().node.broker_info().await;
+    pb::BrokerInfo {
+        version: info.version,
+        node_id: info.node_id,
+        node_name: info.node_name,
+        sysdescr: info.sysdescr,
+    }
+}
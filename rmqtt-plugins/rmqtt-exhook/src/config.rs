@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use serde::de::{self, Deserialize, Deserializer};
+
+use rmqtt::broker::hook::Type;
+use rmqtt::serde_json;
+use rmqtt::settings::deserialize_duration;
+use rmqtt::Result;
+
+use crate::HOOKS;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(default = "PluginConfig::url_default")]
+    pub url: String,
+
+    #[serde(default = "PluginConfig::request_timeout_default", deserialize_with = "deserialize_duration")]
+    pub request_timeout: Duration,
+
+    #[serde(default = "PluginConfig::auto_reconnect_default", deserialize_with = "deserialize_duration")]
+    pub auto_reconnect: Duration,
+
+    #[serde(default)]
+    pub failed_action: FailedAction,
+
+    #[serde(default, deserialize_with = "PluginConfig::deserialize_hooks")]
+    pub hooks: Vec<String>,
+
+    ///Number of workers sending the notification hooks, whose response is ignored
+    #[serde(default = "PluginConfig::notify_workers_default")]
+    pub notify_workers: usize,
+
+    ///Notifications waiting to be sent, they are dropped while the queue is full
+    #[serde(default = "PluginConfig::notify_queue_size_default")]
+    pub notify_queue_size: usize,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn url_default() -> String {
+        "http://127.0.0.1:9000".into()
+    }
+
+    #[inline]
+    fn request_timeout_default() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    #[inline]
+    fn auto_reconnect_default() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    #[inline]
+    fn notify_workers_default() -> usize {
+        16
+    }
+
+    #[inline]
+    fn notify_queue_size_default() -> usize {
+        10_000
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    ///Whether the hook is enabled, an empty list enables all hooks requested by the provider
+    #[inline]
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.hooks.is_empty() || self.hooks.iter().any(|h| h == name)
+    }
+
+    #[inline]
+    fn deserialize_hooks<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hooks = Vec::<String>::deserialize(deserializer)?;
+        for h in hooks.iter() {
+            if hook_type(h).is_none() {
+                return Err(de::Error::custom(format!("unsupported hook, {}", h)));
+            }
+        }
+        Ok(hooks)
+    }
+}
+
+///Action taken when the request to the external server fails
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailedAction {
+    ///Continue with the next handler
+    #[default]
+    Ignore,
+    ///Deny authentication and authorization, other hooks are ignored
+    Deny,
+}
+
+#[inline]
+pub fn hook_type(name: &str) -> Option<Type> {
+    HOOKS.iter().find(|(n, _)| *n == name).map(|(_, t)| *t)
+}
+
+#[inline]
+pub fn hook_name(typ: Type) -> Option<&'static str> {
+    HOOKS.iter().find(|(_, t)| *t == typ).map(|(n, _)| *n)
+}
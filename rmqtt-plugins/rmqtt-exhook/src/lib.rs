@@ -0,0 +1,507 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmqtt::{
+    async_trait::async_trait,
+    bytes::Bytes,
+    log,
+    serde_json::{self, json},
+    tokio::spawn,
+    tokio::sync::mpsc::{self, error::TrySendError},
+    tokio::sync::{Mutex, RwLock},
+    tokio::time::sleep,
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
+    broker::types::{
+        AuthResult, ConnectInfo, From, Id, PublishAclResult, QoSEx, SubscribeAckReason, SubscribeAclResult,
+    },
+    plugin::{PackageInfo, Plugin},
+    register, Publish, QoS, Result, Runtime, TopicFilter, TopicName,
+};
+
+use client::ExHookClient;
+use config::{hook_name, FailedAction, PluginConfig};
+
+mod client;
+mod config;
+
+mod pb {
+    #![allow(clippy::all)]
+    tonic::include_proto!("rmqtt.exhook.v1");
+}
+
+///Hooks that can be forwarded to the external server
+pub(crate) const HOOKS: &[(&str, Type)] = &[
+    ("client_connect", Type::ClientConnect),
+    ("client_connack", Type::ClientConnack),
+    ("client_authenticate", Type::ClientAuthenticate),
+    ("client_connected", Type::ClientConnected),
+    ("client_disconnected", Type::ClientDisconnected),
+    ("client_subscribe", Type::ClientSubscribe),
+    ("client_unsubscribe", Type::ClientUnsubscribe),
+    ("client_subscribe_check_acl", Type::ClientSubscribeCheckAcl),
+    ("client_keepalive_timeout", Type::ClientKeepaliveTimeout),
+    ("client_alive", Type::ClientAlive),
+    ("session_created", Type::SessionCreated),
+    ("session_resumed", Type::SessionResumed),
+    ("session_terminated", Type::SessionTerminated),
+    ("session_subscribed", Type::SessionSubscribed),
+    ("session_unsubscribed", Type::SessionUnsubscribed),
+    ("message_publish_check_acl", Type::MessagePublishCheckAcl),
+    ("message_publish", Type::MessagePublish),
+    ("message_delivered", Type::MessageDelivered),
+    ("message_acked", Type::MessageAcked),
+    ("message_dropped", Type::MessageDropped),
+];
+
+type NotifySender = mpsc::Sender<pb::HookRequest>;
+
+register!(ExHookPlugin::new);
+
+#[derive(Plugin)]
+struct ExHookPlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: Arc<RwLock<PluginConfig>>,
+    client: Arc<ExHookClient>,
+    running: Arc<AtomicBool>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl ExHookPlugin {
+    #[inline]
+    async fn new<N: Into<String>>(runtime: &'static Runtime, name: N) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config::<PluginConfig>(&name)?;
+        log::info!("{} ExHookPlugin cfg: {:?}", name, cfg);
+        let cfg = Arc::new(RwLock::new(cfg));
+        let register = runtime.extends.hook_mgr().await.register();
+        let client = Arc::new(ExHookClient::new(cfg.clone()));
+        Ok(Self {
+            runtime,
+            register,
+            cfg,
+            client,
+            running: Arc::new(AtomicBool::new(false)),
+            dropped: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    ///Notifications are sent by a fixed number of workers from a bounded queue
+    fn start_notifiers(client: Arc<ExHookClient>, workers: usize, queue_size: usize) -> NotifySender {
+        let (tx, rx) = mpsc::channel::<pb::HookRequest>(queue_size.max(1));
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..workers.max(1) {
+            let client = client.clone();
+            let rx = rx.clone();
+            spawn(async move {
+                loop {
+                    let req = rx.lock().await.recv().await;
+                    match req {
+                        Some(req) => {
+                            if let Err(e) = client.call(req).await {
+                                log::warn!("{:?}", e);
+                            }
+                        }
+                        None => break,
+                    }
+                }
+            });
+        }
+        tx
+    }
+
+    ///Loads the provider, and reloads it at the auto_reconnect interval while it is unavailable
+    fn start_reconnect(cfg: Arc<RwLock<PluginConfig>>, client: Arc<ExHookClient>, running: Arc<AtomicBool>) {
+        spawn(async move {
+            while running.load(Ordering::SeqCst) {
+                if !client.is_loaded().await {
+                    if let Err(e) = client.load().await {
+                        log::warn!("exhook, failed to load the provider, {:?}", e);
+                    }
+                }
+                let auto_reconnect = cfg.read().await.auto_reconnect;
+                if auto_reconnect.is_zero() {
+                    break;
+                }
+                sleep(auto_reconnect.max(Duration::from_secs(1))).await;
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl Plugin for ExHookPlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        let (workers, queue_size) = {
+            let cfg = self.cfg.read().await;
+            (cfg.notify_workers, cfg.notify_queue_size)
+        };
+        let notify_tx = Self::start_notifiers(self.client.clone(), workers, queue_size);
+        for (_, typ) in HOOKS {
+            self.register
+                .add(*typ, Box::new(ExHookHandler::new(&self.cfg, &self.client, &notify_tx, &self.dropped)))
+                .await;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        *self.cfg.write().await = new_cfg;
+        //Reload the provider with the new configuration
+        self.client.unload().await;
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.register.start().await;
+        self.running.store(true, Ordering::SeqCst);
+        Self::start_reconnect(self.cfg.clone(), self.client.clone(), self.running.clone());
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        self.running.store(false, Ordering::SeqCst);
+        self.client.unload().await;
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        json!({
+            "loaded": self.client.is_loaded().await,
+            "dropped": self.dropped.load(Ordering::SeqCst),
+        })
+    }
+}
+
+struct ExHookHandler {
+    cfg: Arc<RwLock<PluginConfig>>,
+    client: Arc<ExHookClient>,
+    notify_tx: NotifySender,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl ExHookHandler {
+    fn new(
+        cfg: &Arc<RwLock<PluginConfig>>,
+        client: &Arc<ExHookClient>,
+        notify_tx: &NotifySender,
+        dropped: &Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            client: client.clone(),
+            notify_tx: notify_tx.clone(),
+            dropped: dropped.clone(),
+        }
+    }
+
+    #[inline]
+    async fn request(&self, name: &str, param: &Parameter<'_>) -> pb::HookRequest {
+        let mut req = pb::HookRequest { name: name.into(), ..Default::default() };
+        match param {
+            Parameter::ClientConnect(c) | Parameter::ClientAuthenticate(c) => {
+                req.client = Some(client_info(c.id()));
+                req.conninfo = Some(conn_info(c, matches!(param, Parameter::ClientAuthenticate(_))));
+            }
+            Parameter::ClientConnack(c, r, _) => {
+                req.client = Some(client_info(c.id()));
+                req.conninfo = Some(conn_info(c, false));
+                req.reason = r.reason().to_string();
+            }
+            Parameter::ClientConnected(s) => {
+                req.client = Some(client_info(&s.id));
+                req.conninfo = s.connect_info().await.ok().map(|c| conn_info(&c, false));
+            }
+            Parameter::ClientDisconnected(s, r) | Parameter::SessionTerminated(s, r) => {
+                req.client = Some(client_info(&s.id));
+                req.reason = r.to_string();
+            }
+            Parameter::ClientKeepaliveTimeout(s)
+            | Parameter::ClientAlive(s, _)
+            | Parameter::SessionResumed(s, _, _) => {
+                req.client = Some(client_info(&s.id));
+            }
+            Parameter::ClientSubscribe(s, sub) | Parameter::ClientSubscribeCheckAcl(s, sub) => {
+                req.client = Some(client_info(&s.id));
+                req.topic_filters = vec![topic_filter(&sub.topic_filter, sub.opts.qos_value())];
+            }
+            Parameter::SessionSubscribed(s, sub) => {
+                req.client = Some(client_info(&s.id));
+                req.topic_filters = vec![topic_filter(&sub.topic_filter, sub.opts.qos_value())];
+            }
+            Parameter::ClientUnsubscribe(s, unsub) => {
+                req.client = Some(client_info(&s.id));
+                req.topic_filters = vec![topic_filter(&unsub.topic_filter, 0)];
+            }
+            Parameter::SessionUnsubscribed(s, unsub) => {
+                req.client = Some(client_info(&s.id));
+                req.topic_filters = vec![topic_filter(&unsub.topic_filter, 0)];
+            }
+            Parameter::SessionCreated(s, clean_start) => {
+                req.client = Some(client_info(&s.id));
+                req.clean_start = *clean_start;
+            }
+            Parameter::MessagePublishCheckAcl(s, p) => {
+                req.client = Some(client_info(&s.id));
+                req.message = Some(message(&From::from_custom(s.id.clone()), p));
+            }
+            Parameter::MessagePublish(s, f, p) => {
+                req.client = s.map(|s| client_info(&s.id));
+                req.message = Some(message(f, p));
+            }
+            Parameter::MessageDelivered(s, f, p) | Parameter::MessageAcked(s, f, p) => {
+                req.client = Some(client_info(&s.id));
+                req.message = Some(message(f, p));
+            }
+            Parameter::MessageDropped(to, f, p, r) => {
+                req.client = to.as_ref().map(client_info);
+                req.message = Some(message(f, p));
+                req.reason = r.to_string();
+            }
+            _ => {}
+        }
+        req
+    }
+
+    #[inline]
+    fn proceed(resp: &pb::ValuedResponse) -> Proceed {
+        if resp.r#type() == pb::valued_response::ResponsedType::StopAndReturn {
+            Proceed::Stop
+        } else {
+            Proceed::Continue
+        }
+    }
+
+    #[inline]
+    fn bool_result(resp: &pb::ValuedResponse) -> Option<bool> {
+        match resp.value {
+            Some(pb::valued_response::Value::BoolResult(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    ///Result of the valued hooks when the request fails
+    #[inline]
+    async fn failed(&self, param: &Parameter<'_>, acc: Option<HookResult>) -> ReturnType {
+        if matches!(self.cfg.read().await.failed_action, FailedAction::Ignore) {
+            return (Proceed::Continue, acc);
+        }
+        match param {
+            Parameter::ClientAuthenticate(_) => {
+                (Proceed::Stop, Some(HookResult::AuthResult(AuthResult::NotAuthorized)))
+            }
+            Parameter::ClientSubscribeCheckAcl(_, _) => (
+                Proceed::Stop,
+                Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_failure(
+                    SubscribeAckReason::NotAuthorized,
+                ))),
+            ),
+            Parameter::MessagePublishCheckAcl(_, _) => {
+                (Proceed::Stop, Some(HookResult::PublishAclResult(PublishAclResult::Rejected(false))))
+            }
+            _ => (Proceed::Continue, acc),
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for ExHookHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        let typ = param.get_type();
+        let name = if let Some(name) = hook_name(typ) {
+            name
+        } else {
+            return (Proceed::Continue, acc);
+        };
+
+        let topic = match param {
+            Parameter::MessagePublishCheckAcl(_, p)
+            | Parameter::MessagePublish(_, _, p)
+            | Parameter::MessageDelivered(_, _, p)
+            | Parameter::MessageAcked(_, _, p) => Some(&*p.topic),
+            Parameter::MessageDropped(_, _, p, _) => Some(&*p.topic),
+            _ => None,
+        };
+        if !self.client.is_hooked(typ, topic).await {
+            return (Proceed::Continue, acc);
+        }
+
+        if let Parameter::ClientAuthenticate(_) = param {
+            if matches!(
+                acc,
+                Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword))
+                    | Some(HookResult::AuthResult(AuthResult::NotAuthorized))
+            ) {
+                return (Proceed::Stop, acc);
+            }
+        }
+
+        let mut req = self.request(name, param).await;
+        req.broker = Some(client::broker_info().await);
+
+        match param {
+            Parameter::ClientAuthenticate(_)
+            | Parameter::ClientSubscribeCheckAcl(_, _)
+            | Parameter::MessagePublishCheckAcl(_, _)
+            | Parameter::MessagePublish(_, _, _) => {}
+            _ => {
+                //Notification only, the response is ignored
+                match self.notify_tx.try_send(req) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        self.dropped.fetch_add(1, Ordering::SeqCst);
+                        log::debug!("exhook, the notification queue is full, {} dropped", name);
+                    }
+                    Err(TrySendError::Closed(_)) => {
+                        log::warn!("exhook, the notification workers are stopped")
+                    }
+                }
+                return (Proceed::Continue, acc);
+            }
+        }
+
+        let resp = match self.client.call(req).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                log::warn!("{:?}", e);
+                return self.failed(param, acc).await;
+            }
+        };
+        if resp.r#type() == pb::valued_response::ResponsedType::Ignore {
+            return (Proceed::Continue, acc);
+        }
+        let proceed = Self::proceed(&resp);
+
+        match param {
+            Parameter::ClientAuthenticate(_) => match Self::bool_result(&resp) {
+                Some(true) => (proceed, Some(HookResult::AuthResult(AuthResult::Allow(false, None)))),
+                Some(false) => (proceed, Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword))),
+                None => (proceed, acc),
+            },
+            Parameter::ClientSubscribeCheckAcl(_, sub) => match Self::bool_result(&resp) {
+                Some(true) => (
+                    proceed,
+                    Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_success(
+                        sub.opts.qos(),
+                        None,
+                    ))),
+                ),
+                Some(false) => (
+                    proceed,
+                    Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_failure(
+                        SubscribeAckReason::NotAuthorized,
+                    ))),
+                ),
+                None => (proceed, acc),
+            },
+            Parameter::MessagePublishCheckAcl(_, _) => match Self::bool_result(&resp) {
+                Some(true) => (proceed, Some(HookResult::PublishAclResult(PublishAclResult::Allow))),
+                Some(false) => {
+                    (proceed, Some(HookResult::PublishAclResult(PublishAclResult::Rejected(false))))
+                }
+                None => (proceed, acc),
+            },
+            Parameter::MessagePublish(_, _, p) => match resp.value {
+                Some(pb::valued_response::Value::Message(msg)) => {
+                    (proceed, Some(HookResult::Publish(modified_publish(p, msg))))
+                }
+                _ => (proceed, acc),
+            },
+            _ => (proceed, acc),
+        }
+    }
+}
+
+#[inline]
+fn client_info(id: &Id) -> pb::ClientInfo {
+    pb::ClientInfo {
+        node: id.node(),
+        clientid: id.client_id.to_string(),
+        username: id.username_ref().into(),
+        ipaddress: id.remote_addr.map(|addr| addr.to_string()).unwrap_or_default(),
+    }
+}
+
+#[inline]
+fn conn_info(c: &ConnectInfo, with_password: bool) -> pb::ConnInfo {
+    pb::ConnInfo {
+        proto_ver: c.proto_ver() as u32,
+        keepalive: c.keep_alive() as u32,
+        clean_start: c.clean_start(),
+        password: if with_password {
+            c.password().map(|p| p.to_vec()).unwrap_or_default()
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+#[inline]
+fn topic_filter(tf: &TopicFilter, qos: u8) -> pb::TopicFilter {
+    pb::TopicFilter { name: tf.to_string(), qos: qos as u32 }
+}
+
+#[inline]
+fn message(from: &From, p: &Publish) -> pb::Message {
+    pb::Message {
+        node: from.node(),
+        from: from.client_id.to_string(),
+        username: from.username_ref().into(),
+        qos: p.qos.value() as u32,
+        topic: p.topic.to_string(),
+        payload: p.payload.to_vec(),
+        retain: p.retain,
+        properties: p
+            .user_properties()
+            .iter()
+            .map(|(k, v)| pb::Property { key: k.to_string(), value: v.to_string() })
+            .collect(),
+        timestamp: p.create_time,
+    }
+}
+
+///Applies the message returned by the external server to the publish
+#[inline]
+fn modified_publish(p: &Publish, msg: pb::Message) -> Publish {
+    let mut p = p.clone();
+    if !msg.topic.is_empty() {
+        p.set_topic(TopicName::from(msg.topic));
+    }
+    p.set_payload(Bytes::from(msg.payload));
+    p.set_qos(match msg.qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    });
+    p.retain = msg.retain;
+    p.set_user_properties(
+        msg.properties.into_iter().map(|prop| (prop.key.into(), prop.value.into())).collect(),
+    );
+    p
+}
@@ -0,0 +1,22 @@
+use rmqtt::serde_json;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(default)]
+    pub country_db: Option<String>,
+    #[serde(default)]
+    pub asn_db: Option<String>,
+    #[serde(default = "PluginConfig::attr_prefix_default")]
+    pub attr_prefix: String,
+}
+
+impl PluginConfig {
+    fn attr_prefix_default() -> String {
+        "geo_".into()
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!(self)
+    }
+}
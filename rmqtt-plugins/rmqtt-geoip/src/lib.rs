@@ -0,0 +1,162 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use maxminddb::{geoip2, Reader};
+
+use config::PluginConfig;
+use rmqtt::{async_trait::async_trait, log, serde_json, tokio::sync::RwLock};
+use rmqtt::{
+    broker::default::DefaultClientEnricher,
+    broker::ClientEnricher,
+    plugin::{PackageInfo, Plugin},
+    register, ConnectInfo, MqttError, Result, Runtime, SessionAttrs,
+};
+
+mod config;
+
+register!(GeoIpPlugin::new);
+
+#[derive(Plugin)]
+struct GeoIpPlugin {
+    runtime: &'static Runtime,
+    cfg: Arc<RwLock<PluginConfig>>,
+    geoip: Arc<RwLock<GeoIp>>,
+}
+
+impl GeoIpPlugin {
+    #[inline]
+    async fn new<S: Into<String>>(runtime: &'static Runtime, name: S) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config_default::<PluginConfig>(&name)?;
+        log::info!("{} GeoIpPlugin cfg: {:?}", name, cfg);
+        let geoip = Arc::new(RwLock::new(GeoIp::open(&cfg)?));
+        Ok(Self { runtime, cfg: Arc::new(RwLock::new(cfg)), geoip })
+    }
+}
+
+#[async_trait]
+impl Plugin for GeoIpPlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        Ok(self.cfg.read().await.to_json())
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config_default::<PluginConfig>(self.name())?;
+        *self.geoip.write().await = GeoIp::open(&new_cfg)?;
+        *self.cfg.write().await = new_cfg;
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        *self.runtime.extends.client_enricher_mut().await =
+            Box::new(GeoIpEnricher { geoip: self.geoip.clone() });
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        *self.runtime.extends.client_enricher_mut().await = Box::new(DefaultClientEnricher::instance());
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        let geoip = self.geoip.read().await;
+        serde_json::json!({
+            "country_db": geoip.country.as_ref().map(|r| r.metadata.database_type.clone()),
+            "asn_db": geoip.asn.as_ref().map(|r| r.metadata.database_type.clone()),
+        })
+    }
+}
+
+struct GeoIp {
+    attr_prefix: String,
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    fn open(cfg: &PluginConfig) -> Result<Self> {
+        let open = |path: &Option<String>| {
+            path.as_ref()
+                .map(|path| {
+                    Reader::open_readfile(path)
+                        .map_err(|e| MqttError::Plugin(format!("open GeoIP database {} error, {}", path, e)))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            attr_prefix: cfg.attr_prefix.clone(),
+            country: open(&cfg.country_db)?,
+            asn: open(&cfg.asn_db)?,
+        })
+    }
+
+    ///Addresses not found in the databases, such as private addresses, have no attributes
+    fn lookup(&self, ip: IpAddr) -> SessionAttrs {
+        let mut attrs = SessionAttrs::new();
+        if let Some(reader) = &self.country {
+            match reader.lookup::<geoip2::Country>(ip) {
+                Ok(country) => {
+                    if let Some(iso_code) = country.country.and_then(|c| c.iso_code) {
+                        attrs.insert(format!("{}country", self.attr_prefix), iso_code);
+                    }
+                }
+                Err(e) => log::debug!("{} country lookup failed, {}", ip, e),
+            }
+        }
+        if let Some(reader) = &self.asn {
+            match reader.lookup::<geoip2::Asn>(ip) {
+                Ok(asn) => {
+                    if let Some(number) = asn.autonomous_system_number {
+                        attrs.insert(format!("{}asn", self.attr_prefix), number as i64);
+                    }
+                    if let Some(org) = asn.autonomous_system_organization {
+                        attrs.insert(format!("{}as_org", self.attr_prefix), org);
+                    }
+                }
+                Err(e) => log::debug!("{} ASN lookup failed, {}", ip, e),
+            }
+        }
+        attrs
+    }
+}
+
+struct GeoIpEnricher {
+    geoip: Arc<RwLock<GeoIp>>,
+}
+
+#[async_trait]
+impl ClientEnricher for GeoIpEnricher {
+    #[inline]
+    fn enable(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    async fn enrich(&self, connect_info: &ConnectInfo) -> SessionAttrs {
+        match connect_info.id().remote_ip() {
+            Some(ip) => self.geoip.read().await.lookup(ip),
+            None => SessionAttrs::default(),
+        }
+    }
+}
@@ -0,0 +1,4 @@
+fn main() {
+    let out = std::env::var("OUT_DIR").unwrap();
+    tonic_build::configure().build_client(false).out_dir(out).compile(&["api.proto"], &["proto"]).unwrap();
+}
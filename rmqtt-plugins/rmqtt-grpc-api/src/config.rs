@@ -0,0 +1,72 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rmqtt::serde_json;
+use rmqtt::{
+    settings::{deserialize_addr, deserialize_duration},
+    Result,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(default = "PluginConfig::laddr_default", deserialize_with = "deserialize_addr")]
+    pub laddr: SocketAddr,
+
+    ///Clients must send it in the "authorization: Bearer {token}" metadata, no authentication if not set
+    #[serde(default, skip_serializing)]
+    pub bearer_token: Option<String>,
+
+    #[serde(default = "PluginConfig::max_row_limit_default")]
+    pub max_row_limit: usize,
+
+    ///Messages or events buffered for each stream, they are dropped while the buffer is full
+    #[serde(default = "PluginConfig::stream_buffer_size_default")]
+    pub stream_buffer_size: usize,
+
+    #[serde(default = "PluginConfig::max_streams_default")]
+    pub max_streams: usize,
+
+    #[serde(default)]
+    pub message_retain_available: bool,
+
+    #[serde(default)]
+    pub message_storage_available: bool,
+
+    #[serde(
+        default = "PluginConfig::message_expiry_interval_default",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub message_expiry_interval: Duration,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn laddr_default() -> SocketAddr {
+        ([0, 0, 0, 0], 6070).into()
+    }
+
+    #[inline]
+    fn max_row_limit_default() -> usize {
+        10_000
+    }
+
+    #[inline]
+    fn stream_buffer_size_default() -> usize {
+        10_000
+    }
+
+    #[inline]
+    fn max_streams_default() -> usize {
+        100
+    }
+
+    #[inline]
+    fn message_expiry_interval_default() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+}
@@ -0,0 +1,191 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use rmqtt::{
+    async_trait::async_trait,
+    log,
+    serde_json::{self, json},
+    tokio::spawn,
+    tokio::sync::{oneshot, RwLock},
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
+    plugin::{PackageInfo, Plugin},
+    register, timestamp_millis, Id, Result, Runtime,
+};
+
+use config::PluginConfig;
+use service::Streams;
+
+mod config;
+mod service;
+
+mod pb {
+    #![allow(clippy::all)]
+    tonic::include_proto!("rmqtt.api.v1");
+}
+
+register!(GrpcApiPlugin::new);
+
+#[derive(Plugin)]
+struct GrpcApiPlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: Arc<RwLock<PluginConfig>>,
+    streams: Arc<Streams>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl GrpcApiPlugin {
+    #[inline]
+    async fn new<N: Into<String>>(runtime: &'static Runtime, name: N) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config::<PluginConfig>(&name)?;
+        log::info!("{} GrpcApiPlugin cfg: {:?}", name, cfg);
+        let cfg = Arc::new(RwLock::new(cfg));
+        let register = runtime.extends.hook_mgr().await.register();
+        Ok(Self { runtime, register, cfg, streams: Arc::new(Streams::default()), shutdown_tx: None })
+    }
+
+    async fn serve(&mut self) {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let laddr = self.cfg.read().await.laddr;
+        let cfg = self.cfg.clone();
+        let streams = self.streams.clone();
+        spawn(async move {
+            if let Err(e) = service::listen_and_serve(laddr, cfg, streams, shutdown_rx).await {
+                log::error!("{:?}", e);
+            }
+            log::info!("Exit gRPC API Server, tcp://{:?}", laddr);
+        });
+        self.shutdown_tx = Some(shutdown_tx);
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            if let Err(e) = tx.send(()) {
+                log::warn!("shutdown_tx send fail, {:?}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for GrpcApiPlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        for typ in [
+            Type::MessagePublish,
+            Type::ClientConnected,
+            Type::ClientDisconnected,
+            Type::SessionSubscribed,
+            Type::SessionUnsubscribed,
+        ] {
+            self.register.add(typ, Box::new(StreamHandler::new(&self.streams))).await;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        *self.cfg.write().await = new_cfg;
+        //Restart the server, the listening address or token may have changed
+        if self.shutdown_tx.is_some() {
+            self.shutdown();
+            self.serve().await;
+        }
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.register.start().await;
+        self.serve().await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        self.shutdown();
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        json!({
+            "streams": self.streams.len(),
+            "dropped": self.streams.dropped.load(Ordering::SeqCst),
+        })
+    }
+}
+
+struct StreamHandler {
+    streams: Arc<Streams>,
+}
+
+impl StreamHandler {
+    fn new(streams: &Arc<Streams>) -> Self {
+        Self { streams: streams.clone() }
+    }
+}
+
+#[async_trait]
+impl Handler for StreamHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        match param {
+            Parameter::MessagePublish(_, f, p) => self.streams.publish(f, p),
+            Parameter::ClientConnected(s) => {
+                self.streams.event(event(pb::EventType::Connected, &s.id));
+            }
+            Parameter::ClientDisconnected(s, r) => {
+                let mut ev = event(pb::EventType::Disconnected, &s.id);
+                ev.reason = r.to_string();
+                self.streams.event(ev);
+            }
+            Parameter::SessionSubscribed(s, sub) => {
+                let mut ev = event(pb::EventType::Subscribed, &s.id);
+                ev.topic_filter = sub.topic_filter.to_string();
+                ev.qos = sub.opts.qos_value() as u32;
+                self.streams.event(ev);
+            }
+            Parameter::SessionUnsubscribed(s, unsub) => {
+                let mut ev = event(pb::EventType::Unsubscribed, &s.id);
+                ev.topic_filter = unsub.topic_filter.to_string();
+                self.streams.event(ev);
+            }
+            _ => {}
+        }
+        (Proceed::Continue, acc)
+    }
+}
+
+#[inline]
+fn event(typ: pb::EventType, id: &Id) -> pb::ClientEvent {
+    pb::ClientEvent {
+        r#type: typ as i32,
+        node: id.node(),
+        clientid: id.client_id.to_string(),
+        username: id.username_ref().into(),
+        ipaddress: id.remote_addr.map(|addr| addr.to_string()).unwrap_or_default(),
+        timestamp: timestamp_millis(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,353 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport, Request, Response, Status};
+
+use rmqtt::{
+    broker::topic::TopicTree, ClientId, From, Id, MqttError, Publish, QoS, QoSEx, Result, Runtime,
+    SessionState, SubsSearchParams, SubsSearchResult, Topic, TopicName, UserName, ValidTopicName,
+};
+use rmqtt::{
+    bytes::Bytes,
+    futures, log,
+    tokio::sync::{mpsc, oneshot, RwLock},
+    DashMap,
+};
+
+use crate::config::PluginConfig;
+use crate::pb::{
+    self,
+    api_server::{Api, ApiServer},
+};
+
+type RpcResult<T> = std::result::Result<Response<T>, Status>;
+type StreamId = u64;
+type MessageTx = mpsc::Sender<std::result::Result<pb::Message, Status>>;
+type EventTx = mpsc::Sender<std::result::Result<pb::ClientEvent, Status>>;
+
+///The open Subscribe and ClientEvents streams
+#[derive(Default)]
+pub(crate) struct Streams {
+    next_id: AtomicU64,
+    messages: DashMap<StreamId, (TopicTree<()>, MessageTx)>,
+    events: DashMap<StreamId, (Vec<i32>, EventTx)>,
+    pub(crate) dropped: AtomicUsize,
+}
+
+impl Streams {
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.messages.len() + self.events.len()
+    }
+
+    #[inline]
+    fn next_id(&self) -> StreamId {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    ///Sends the message to the Subscribe streams whose topic filters match its topic
+    pub(crate) fn publish(&self, f: &From, p: &Publish) {
+        if self.messages.is_empty() {
+            return;
+        }
+        let topic = match Topic::from_str(&p.topic) {
+            Ok(t) => t,
+            Err(e) => {
+                log::warn!("grpc-api, topic format error, {:?}", e);
+                return;
+            }
+        };
+        let mut msg = None;
+        let mut closeds = Vec::new();
+        for entry in self.messages.iter() {
+            let (tree, tx) = entry.value();
+            if !tree.is_match(&topic) {
+                continue;
+            }
+            let msg = msg.get_or_insert_with(|| message(f, p));
+            self.try_send(tx, msg.clone(), *entry.key(), &mut closeds);
+        }
+        for id in closeds {
+            self.messages.remove(&id);
+        }
+    }
+
+    ///Sends the event to the ClientEvents streams that asked for its type
+    pub(crate) fn event(&self, ev: pb::ClientEvent) {
+        let mut closeds = Vec::new();
+        for entry in self.events.iter() {
+            let (types, tx) = entry.value();
+            if types.is_empty() || types.contains(&ev.r#type) {
+                self.try_send(tx, ev.clone(), *entry.key(), &mut closeds);
+            }
+        }
+        for id in closeds {
+            self.events.remove(&id);
+        }
+    }
+
+    #[inline]
+    fn try_send<T>(
+        &self,
+        tx: &mpsc::Sender<std::result::Result<T, Status>>,
+        item: T,
+        id: StreamId,
+        closeds: &mut Vec<StreamId>,
+    ) {
+        match tx.try_send(Ok(item)) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => closeds.push(id),
+        }
+    }
+}
+
+pub(crate) struct ApiService {
+    cfg: Arc<RwLock<PluginConfig>>,
+    streams: Arc<Streams>,
+}
+
+impl ApiService {
+    #[inline]
+    async fn check_streams(&self) -> std::result::Result<usize, Status> {
+        let cfg = self.cfg.read().await;
+        if self.streams.len() >= cfg.max_streams {
+            return Err(Status::resource_exhausted("max_streams exceeded"));
+        }
+        Ok(cfg.stream_buffer_size.max(1))
+    }
+}
+
+#[tonic::async_trait]
+impl Api for ApiService {
+    type SubscribeStream = ReceiverStream<std::result::Result<pb::Message, Status>>;
+    type ClientEventsStream = ReceiverStream<std::result::Result<pb::ClientEvent, Status>>;
+
+    async fn publish(&self, req: Request<pb::PublishRequest>) -> RpcResult<pb::PublishResponse> {
+        let remote_addr = req.remote_addr();
+        let req = req.into_inner();
+        if req.topics.is_empty() {
+            return Err(Status::invalid_argument("topics is empty"));
+        }
+        let topics = req
+            .topics
+            .iter()
+            .map(|t| ValidTopicName::new(TopicName::from(t.as_str())))
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let qos = QoS::try_from(req.qos as u8).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let (laddr, retain_available, storage_available, expiry_interval) = {
+            let cfg = self.cfg.read().await;
+            (
+                cfg.laddr,
+                cfg.message_retain_available,
+                cfg.message_storage_available,
+                cfg.message_expiry_interval,
+            )
+        };
+        let expiry_interval = if req.message_expiry_interval > 0 {
+            Duration::from_secs(req.message_expiry_interval as u64)
+        } else {
+            expiry_interval
+        };
+
+        let from = From::from_admin(Id::new(
+            Runtime::instance().node.id(),
+            Some(laddr),
+            remote_addr,
+            ClientId::from(req.clientid),
+            Some(UserName::from("admin")),
+        ));
+        let mut p = Publish::builder().retain(req.retain).qos(qos).payload(Bytes::from(req.payload)).build();
+        p.set_user_properties(
+            req.properties.into_iter().map(|prop| (prop.key.into(), prop.value.into())).collect(),
+        );
+
+        let futs = topics.into_iter().map(|topic| {
+            let from = from.clone();
+            let mut p = p.clone();
+            p.topic = topic.into_inner();
+            async move {
+                //hook, message_publish
+                let p = Runtime::instance()
+                    .extends
+                    .hook_mgr()
+                    .await
+                    .message_publish(None, from.clone(), &p)
+                    .await
+                    .unwrap_or(p);
+                if let Err(e) = SessionState::forwards(
+                    from,
+                    p,
+                    retain_available,
+                    storage_available,
+                    Some(expiry_interval),
+                )
+                .await
+                {
+                    log::warn!("{:?}", e);
+                }
+            }
+        });
+        futures::future::join_all(futs).await;
+        Ok(Response::new(pb::PublishResponse {}))
+    }
+
+    async fn subscribe(&self, req: Request<pb::SubscribeRequest>) -> RpcResult<Self::SubscribeStream> {
+        let req = req.into_inner();
+        if req.topic_filters.is_empty() {
+            return Err(Status::invalid_argument("topic_filters is empty"));
+        }
+        let mut tree = TopicTree::default();
+        for tf in req.topic_filters.iter() {
+            let t = Topic::from_str(tf).map_err(|e| Status::invalid_argument(format!("{:?}", e)))?;
+            tree.insert(&t, ());
+        }
+        let buffer_size = self.check_streams().await?;
+        let (tx, rx) = mpsc::channel(buffer_size);
+        self.streams.messages.insert(self.streams.next_id(), (tree, tx));
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn client_events(
+        &self,
+        req: Request<pb::ClientEventsRequest>,
+    ) -> RpcResult<Self::ClientEventsStream> {
+        let types = req.into_inner().types;
+        let buffer_size = self.check_streams().await?;
+        let (tx, rx) = mpsc::channel(buffer_size);
+        self.streams.events.insert(self.streams.next_id(), (types, tx));
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn get_session(&self, req: Request<pb::GetSessionRequest>) -> RpcResult<pb::Session> {
+        let clientid = req.into_inner().clientid;
+        let shared = Runtime::instance().extends.shared().await;
+        let status = shared
+            .session_status(&clientid)
+            .await
+            .ok_or_else(|| Status::not_found("session does not exist"))?;
+        let max_row_limit = self.cfg.read().await.max_row_limit;
+        let subscriptions = shared
+            .query_subscriptions(SubsSearchParams {
+                _limit: max_row_limit,
+                clientid: Some(clientid),
+                ..Default::default()
+            })
+            .await;
+        Ok(Response::new(pb::Session {
+            node: status.id.node(),
+            clientid: status.id.client_id.to_string(),
+            username: status.id.username_ref().into(),
+            ipaddress: status.id.remote_addr.map(|addr| addr.to_string()).unwrap_or_default(),
+            online: status.online,
+            handshaking: status.handshaking,
+            created_at: status.id.create_time,
+            subscriptions: subscriptions.into_iter().map(subscription).collect(),
+        }))
+    }
+
+    async fn query_subscriptions(
+        &self,
+        req: Request<pb::QuerySubscriptionsRequest>,
+    ) -> RpcResult<pb::QuerySubscriptionsResponse> {
+        let req = req.into_inner();
+        let max_row_limit = self.cfg.read().await.max_row_limit;
+        let limit = if req.limit == 0 { max_row_limit } else { (req.limit as usize).min(max_row_limit) };
+        let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+        let subscriptions = Runtime::instance()
+            .extends
+            .shared()
+            .await
+            .query_subscriptions(SubsSearchParams {
+                _limit: limit,
+                clientid: non_empty(req.clientid),
+                topic: non_empty(req.topic),
+                qos: None,
+                share: non_empty(req.share_group).map(Into::into),
+                _match_topic: non_empty(req.match_topic),
+            })
+            .await;
+        Ok(Response::new(pb::QuerySubscriptionsResponse {
+            subscriptions: subscriptions.into_iter().map(subscription).collect(),
+        }))
+    }
+
+    async fn session_stats(
+        &self,
+        _req: Request<pb::SessionStatsRequest>,
+    ) -> RpcResult<pb::SessionStatsResponse> {
+        let shared = Runtime::instance().extends.shared().await;
+        Ok(Response::new(pb::SessionStatsResponse {
+            sessions: shared.sessions_count() as u64,
+            client_states: shared.client_states_count().await as u64,
+            subscriptions: shared.subscriptions_count().await as u64,
+        }))
+    }
+}
+
+pub(crate) async fn listen_and_serve(
+    laddr: SocketAddr,
+    cfg: Arc<RwLock<PluginConfig>>,
+    streams: Arc<Streams>,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> Result<()> {
+    let expected = cfg.read().await.bearer_token.as_ref().map(|token| format!("Bearer {}", token));
+    let check_auth = move |req: Request<()>| {
+        let expected = if let Some(expected) = expected.as_ref() {
+            expected
+        } else {
+            return Ok(req);
+        };
+        match req.metadata().get("authorization").and_then(|t| t.to_str().ok()) {
+            Some(t) if t == expected => Ok(req),
+            _ => Err(Status::unauthenticated("No valid auth token")),
+        }
+    };
+    log::info!("gRPC API server is listening on tcp://{:?}", laddr);
+    transport::Server::builder()
+        .add_service(ApiServer::with_interceptor(ApiService { cfg, streams }, check_auth))
+        .serve_with_shutdown(laddr, async {
+            let _ = shutdown_rx.await;
+        })
+        .await
+        .map_err(|e| MqttError::from(format!("gRPC API server error, {:?}", e)))?;
+    Ok(())
+}
+
+#[inline]
+fn message(from: &From, p: &Publish) -> pb::Message {
+    pb::Message {
+        node: from.node(),
+        from: from.client_id.to_string(),
+        username: from.username_ref().into(),
+        qos: p.qos.value() as u32,
+        topic: p.topic.to_string(),
+        payload: p.payload.to_vec(),
+        retain: p.retain,
+        properties: p
+            .user_properties()
+            .iter()
+            .map(|(k, v)| pb::Property { key: k.to_string(), value: v.to_string() })
+            .collect(),
+        timestamp: p.create_time,
+    }
+}
+
+#[inline]
+fn subscription(s: SubsSearchResult) -> pb::Subscription {
+    pb::Subscription {
+        node: s.node_id,
+        clientid: s.clientid.to_string(),
+        topic_filter: s.topic.to_string(),
+        qos: s.opts.qos_value() as u32,
+        share_group: s.opts.shared_group().map(|g| g.to_string()).unwrap_or_default(),
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::convert::From as _;
 use std::io::ErrorKind;
 use std::net::SocketAddr;
@@ -23,13 +24,17 @@ use rmqtt::{
         client::NodeGrpcClient, Message as GrpcMessage, MessageBroadcaster, MessageReply as GrpcMessageReply,
         MessageSender, MessageType,
     },
-    node::NodeStatus,
-    timestamp_millis, ClientId, From, Id, MqttError, Publish, PublishProperties, QoS, Result, Runtime,
-    SessionState, SubsSearchParams, TopicFilter, TopicName, UserName,
+    logger::{self, LogLevelOptions, LogLevels},
+    node::{MaintenanceOptions, MaintenanceStatus, NodeStatus},
+    plugin::PluginMetrics,
+    settings::ReloadReport,
+    ClientId, From, Id, MqttError, Publish, QoS, Result, Runtime, SessionState, SubsSearchParams,
+    TopicFilter, TopicName, UserName, ValidTopicName,
 };
 
 use super::types::{
-    ClientSearchParams, Message, MessageReply, PublishParams, SubscribeParams, UnsubscribeParams,
+    ClientSearchParams, Message, MessageReply, PublishParams, RoutingTableFormat, RoutingTableParams,
+    SessionExport, SessionImportParams, SubscribeParams, UnsubscribeParams, ROUTING_TABLE_CSV_HEADER,
 };
 use super::PluginConfigType;
 use super::{clients, plugin, subs};
@@ -63,31 +68,68 @@ fn route(cfg: PluginConfigType, token: Option<String>) -> Router {
     router
         .get(list_apis)
         .push(Router::with_path("brokers").get(get_brokers).push(Router::with_path("<id>").get(get_brokers)))
-        .push(Router::with_path("nodes").get(get_nodes).push(Router::with_path("<id>").get(get_nodes)))
+        .push(
+            Router::with_path("nodes")
+                .get(get_nodes)
+                .push(Router::with_path("<id>").get(get_nodes))
+                .push(Router::with_path("<id>/config/reload").put(node_config_reload))
+                .push(
+                    Router::with_path("<id>/maintenance")
+                        .get(node_maintenance_status)
+                        .put(node_maintenance_start)
+                        .delete(node_maintenance_stop),
+                )
+                .push(
+                    Router::with_path("<id>/log/level")
+                        .get(node_log_levels)
+                        .put(node_log_level_set)
+                        .delete(node_log_level_reset),
+                ),
+        )
         .push(Router::with_path("health/check").get(check_health))
         .push(
             Router::with_path("clients").get(search_clients).push(
                 Router::with_path("<clientid>")
                     .get(get_client)
                     .delete(kick_client)
-                    .push(Router::with_path("online").get(check_online)),
+                    .push(Router::with_path("online").get(check_online))
+                    .push(Router::with_path("presence").get(get_client_presence))
+                    .push(
+                        Router::with_path("will")
+                            .get(get_client_will)
+                            .post(trigger_client_will)
+                            .delete(clear_client_will),
+                    ),
             ),
         )
+        .push(
+            Router::with_path("sessions")
+                .push(Router::with_path("import").post(import_session))
+                .push(Router::with_path("<clientid>/export").post(export_session)),
+        )
         .push(
             Router::with_path("subscriptions")
                 .get(query_subscriptions)
                 .push(Router::with_path("<clientid>").get(get_client_subscriptions)),
         )
         .push(Router::with_path("routes").get(get_routes).push(Router::with_path("<topic>").get(get_route)))
+        .push(Router::with_path("routing_table").get(get_routing_table))
+        .push(Router::with_path("retains").get(get_retains))
         .push(
             Router::with_path("mqtt")
                 .push(Router::with_path("publish").post(publish))
                 .push(Router::with_path("subscribe").post(subscribe))
                 .push(Router::with_path("unsubscribe").post(unsubscribe)),
         )
+        .push(Router::with_path("webhook/test").post(test_webhook))
         .push(
             Router::with_path("plugins")
                 .get(all_plugins)
+                .push(
+                    Router::with_path("metrics")
+                        .get(plugins_metrics)
+                        .push(Router::with_path("prometheus").get(plugins_metrics_prometheus)),
+                )
                 .push(Router::with_path("<node>").get(node_plugins))
                 .push(Router::with_path("<node>/<plugin>").get(node_plugin_info))
                 .push(Router::with_path("<node>/<plugin>/config").get(node_plugin_config))
@@ -99,7 +141,7 @@ fn route(cfg: PluginConfigType, token: Option<String>) -> Router {
             Router::with_path("stats")
                 .get(get_stats)
                 .push(Router::with_path("sum").get(get_stats_sum))
-                .push(Router::with_path("<id>").get(get_stats)),
+                .push(Router::with_path("<id>").get(get_stats).delete(reset_stats)),
         )
         .push(
             Router::with_path("metrics")
@@ -150,6 +192,48 @@ async fn list_apis(res: &mut Response) {
             "path": "/nodes/{node}",
             "descr": "Returns the status of the node"
         },
+        {
+            "name": "node_config_reload",
+            "method": "PUT",
+            "path": "/nodes/{node}/config/reload",
+            "descr": "Reload the broker configuration of the node"
+        },
+        {
+            "name": "node_maintenance_status",
+            "method": "GET",
+            "path": "/nodes/{node}/maintenance",
+            "descr": "Return the maintenance progress of the node, null if it is not in maintenance"
+        },
+        {
+            "name": "node_maintenance_start",
+            "method": "PUT",
+            "path": "/nodes/{node}/maintenance",
+            "descr": "Put the node into maintenance now or at a scheduled time, new connections are refused and the connected clients are disconnected or kept"
+        },
+        {
+            "name": "node_maintenance_stop",
+            "method": "DELETE",
+            "path": "/nodes/{node}/maintenance",
+            "descr": "Take the node out of maintenance"
+        },
+        {
+            "name": "node_log_levels",
+            "method": "GET",
+            "path": "/nodes/{node}/log/level",
+            "descr": "Return the log level of the node and the overrides set at runtime"
+        },
+        {
+            "name": "node_log_level_set",
+            "method": "PUT",
+            "path": "/nodes/{node}/log/level",
+            "descr": "Change the log level of the node, for all targets or a module, optionally for a duration"
+        },
+        {
+            "name": "node_log_level_reset",
+            "method": "DELETE",
+            "path": "/nodes/{node}/log/level",
+            "descr": "Remove the log level overrides of the node"
+        },
         {
             "name": "check_health",
             "method": "GET",
@@ -180,6 +264,42 @@ async fn list_apis(res: &mut Response) {
             "path": "/clients/{clientid}/online",
             "descr": "Check a client whether online from the cluster"
         },
+        {
+            "name": "get_client_presence",
+            "method": "GET",
+            "path": "/clients/{clientid}/presence",
+            "descr": "Get the presence record of a client, kept by the rmqtt-presence plugin"
+        },
+        {
+            "name": "get_client_will",
+            "method": "GET",
+            "path": "/clients/{clientid}/will",
+            "descr": "Get the pending last will of a client connected to this node"
+        },
+        {
+            "name": "trigger_client_will",
+            "method": "POST",
+            "path": "/clients/{clientid}/will",
+            "descr": "Publish the last will of a client connected to this node, the client stays connected"
+        },
+        {
+            "name": "clear_client_will",
+            "method": "DELETE",
+            "path": "/clients/{clientid}/will",
+            "descr": "Discard the last will of a client connected to this node"
+        },
+        {
+            "name": "export_session",
+            "method": "POST",
+            "path": "/sessions/{clientid}/export",
+            "descr": "Take the session of a client out of the cluster and return its state, the client is disconnected"
+        },
+        {
+            "name": "import_session",
+            "method": "POST",
+            "path": "/sessions/import",
+            "descr": "Create an offline session on this node from the state returned by export_session"
+        },
 
         {
             "name": "query_subscriptions",
@@ -206,6 +326,18 @@ async fn list_apis(res: &mut Response) {
             "path": "/routes/{topic}",
             "descr": "Get routing information from the cluster"
         },
+        {
+            "name": "get_routing_table",
+            "method": "GET",
+            "path": "/routing_table",
+            "descr": "Dump the routing table, topic filters and their subscribers, as JSON or CSV"
+        },
+        {
+            "name": "get_retains",
+            "method": "GET",
+            "path": "/retains",
+            "descr": "Get the retained messages matching a topic filter"
+        },
 
         {
             "name": "publish",
@@ -225,6 +357,12 @@ async fn list_apis(res: &mut Response) {
             "path": "/mqtt/unsubscribe",
             "descr": "Unsubscribe"
         },
+        {
+            "name": "test_webhook",
+            "method": "POST",
+            "path": "/webhook/test",
+            "descr": "Test the rules of the rmqtt-web-hook plugin against a sample event, nothing is sent"
+        },
 
         {
             "name": "all_plugins",
@@ -232,6 +370,12 @@ async fn list_apis(res: &mut Response) {
             "path": "/plugins/",
             "descr": "Returns information of all plugins in the cluster"
         },
+        {
+            "name": "plugins_metrics",
+            "method": "GET",
+            "path": "/plugins/metrics",
+            "descr": "Returns the operational metrics of the plugins, aggregated over the cluster"
+        },
         {
             "name": "node_plugins",
             "method": "GET",
@@ -281,6 +425,12 @@ async fn list_apis(res: &mut Response) {
             "path": "/stats/sum",
             "descr": "Summarize all statistics information from the cluster"
         },
+        {
+            "name": "reset_stats",
+            "method": "DELETE",
+            "path": "/stats/{node}",
+            "descr": "Reset the cumulative statistics and the peaks of the node"
+        },
 
         {
             "name": "get_metrics",
@@ -615,26 +765,61 @@ async fn _search_clients(
 }
 
 #[handler]
-async fn kick_client(req: &mut Request, res: &mut Response) {
+async fn kick_client(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
     let clientid = req.param::<String>("clientid");
     if let Some(clientid) = clientid {
-        let mut entry = Runtime::instance()
-            .extends
-            .shared()
-            .await
-            .entry(Id::from(Runtime::instance().node.id(), ClientId::from(clientid)));
-        let s = entry.session();
-        if let Some(s) = s {
-            match entry.kick(true, true, true).await {
-                Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
-                Ok(_) => res.render(Json(s.id.to_json())),
+        match _kick_client(message_type, &clientid).await {
+            Ok(Some(id)) => res.render(Json(id.to_json())),
+            Ok(None) => {
+                res.status_code(StatusCode::NOT_FOUND);
             }
-        } else {
-            res.status_code(StatusCode::NOT_FOUND);
+            Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
         }
     } else {
         res.render(StatusError::bad_request())
     }
+    Ok(())
+}
+
+async fn _kick_client(message_type: MessageType, clientid: &str) -> Result<Option<Id>> {
+    if let Some(id) = clients::kick(clientid).await? {
+        return Ok(Some(id));
+    }
+    _select_client_reply(message_type, Message::ClientKick { clientid }, |reply| match reply {
+        MessageReply::ClientKick(id) => id,
+        _ => unreachable!(),
+    })
+    .await
+}
+
+//Asks the other nodes in the cluster, the reply of the node where the client is found is returned
+async fn _select_client_reply<R, F>(message_type: MessageType, msg: Message<'_>, f: F) -> Result<Option<R>>
+where
+    R: std::any::Any + Send + Sync,
+    F: Fn(MessageReply) -> Option<R> + Send + Sync,
+{
+    let grpc_clients = Runtime::instance().extends.shared().await.get_grpc_clients();
+    if grpc_clients.is_empty() {
+        return Ok(None);
+    }
+
+    let check_result = |reply: GrpcMessageReply| match reply {
+        GrpcMessageReply::Data(res) => f(MessageReply::decode(&res)?).ok_or(MqttError::None),
+        GrpcMessageReply::Error(e) => Err(MqttError::Msg(e)),
+        _ => unreachable!(),
+    };
+
+    let msg = msg.encode()?;
+    match MessageBroadcaster::new(grpc_clients, message_type, GrpcMessage::Data(msg))
+        .select_ok(check_result)
+        .await
+    {
+        Ok(reply) => Ok(Some(reply)),
+        Err(MqttError::None) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
 #[handler]
@@ -654,6 +839,203 @@ async fn check_online(req: &mut Request, res: &mut Response) {
     }
 }
 
+#[handler]
+async fn get_client_presence(req: &mut Request, res: &mut Response) {
+    let clientid = req.param::<String>("clientid");
+    if let Some(clientid) = clientid {
+        //The presence records are kept by the rmqtt-presence plugin
+        match Runtime::instance().plugins.send("rmqtt-presence", json!({ "clientid": clientid })).await {
+            Ok(serde_json::Value::Null) => {
+                res.status_code(StatusCode::NOT_FOUND);
+            }
+            Ok(presence) => res.render(Json(presence)),
+            Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+        }
+    } else {
+        res.render(StatusError::bad_request())
+    }
+}
+
+#[handler]
+async fn test_webhook(req: &mut Request, res: &mut Response) {
+    let event = match req.parse_json::<serde_json::Value>().await {
+        Ok(event) => event,
+        Err(e) => {
+            res.render(StatusError::bad_request().detail(e.to_string()));
+            return;
+        }
+    };
+    //The rules are evaluated by the rmqtt-web-hook plugin of this node
+    match Runtime::instance().plugins.send("rmqtt-web-hook", event).await {
+        Ok(actions) => res.render(Json(actions)),
+        Err(e) => res.render(StatusError::bad_request().detail(e.to_string())),
+    }
+}
+
+#[handler]
+async fn get_client_will(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let clientid = req.param::<String>("clientid");
+    if let Some(clientid) = clientid {
+        match _get_client_will(message_type, &clientid).await {
+            Ok(Some(last_will)) => res.render(Json(last_will)),
+            Ok(None) => {
+                res.status_code(StatusCode::NOT_FOUND);
+            }
+            Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+        }
+    } else {
+        res.render(StatusError::bad_request())
+    }
+    Ok(())
+}
+
+async fn _get_client_will(message_type: MessageType, clientid: &str) -> Result<Option<serde_json::Value>> {
+    if let Some(last_will) = clients::last_will(clientid).await {
+        return Ok(Some(last_will));
+    }
+    let last_will =
+        _select_client_reply(message_type, Message::ClientLastWill { clientid }, |reply| match reply {
+            MessageReply::ClientLastWill(last_will) => last_will,
+            _ => unreachable!(),
+        })
+        .await?;
+    match last_will {
+        Some(last_will) => Ok(Some(serde_json::from_slice(&last_will).map_err(anyhow::Error::new)?)),
+        None => Ok(None),
+    }
+}
+
+#[handler]
+async fn trigger_client_will(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let clientid = req.param::<String>("clientid");
+    if let Some(clientid) = clientid {
+        match _trigger_client_will(message_type, &clientid).await {
+            Ok(Some(id)) => res.render(Json(id.to_json())),
+            Ok(None) => {
+                res.status_code(StatusCode::NOT_FOUND);
+            }
+            Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+        }
+    } else {
+        res.render(StatusError::bad_request())
+    }
+    Ok(())
+}
+
+async fn _trigger_client_will(message_type: MessageType, clientid: &str) -> Result<Option<Id>> {
+    if let Some(id) = clients::last_will_trigger(clientid).await? {
+        return Ok(Some(id));
+    }
+    _select_client_reply(message_type, Message::ClientLastWillTrigger { clientid }, |reply| match reply {
+        MessageReply::ClientLastWillTrigger(id) => id,
+        _ => unreachable!(),
+    })
+    .await
+}
+
+#[handler]
+async fn clear_client_will(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let clientid = req.param::<String>("clientid");
+    if let Some(clientid) = clientid {
+        match _clear_client_will(message_type, &clientid).await {
+            Ok(Some(id)) => res.render(Json(id.to_json())),
+            Ok(None) => {
+                res.status_code(StatusCode::NOT_FOUND);
+            }
+            Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+        }
+    } else {
+        res.render(StatusError::bad_request())
+    }
+    Ok(())
+}
+
+async fn _clear_client_will(message_type: MessageType, clientid: &str) -> Result<Option<Id>> {
+    if let Some(id) = clients::last_will_clear(clientid).await {
+        return Ok(Some(id));
+    }
+    _select_client_reply(message_type, Message::ClientLastWillClear { clientid }, |reply| match reply {
+        MessageReply::ClientLastWillClear(id) => id,
+        _ => unreachable!(),
+    })
+    .await
+}
+
+#[handler]
+async fn export_session(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let clientid = req.param::<String>("clientid");
+    if let Some(clientid) = clientid {
+        let export = _export_session(message_type, &clientid).await;
+        match export.and_then(|e| e.map(|e| e.to_json()).transpose()) {
+            Ok(Some(export)) => res.render(Json(export)),
+            Ok(None) => {
+                res.status_code(StatusCode::NOT_FOUND);
+            }
+            Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+        }
+    } else {
+        res.render(StatusError::bad_request())
+    }
+    Ok(())
+}
+
+async fn _export_session(message_type: MessageType, clientid: &str) -> Result<Option<SessionExport>> {
+    if let Some(export) = clients::session_export(clientid).await? {
+        return Ok(Some(export));
+    }
+    _select_client_reply(message_type, Message::SessionExport { clientid }, |reply| match reply {
+        MessageReply::SessionExport(export) => export,
+        _ => unreachable!(),
+    })
+    .await
+}
+
+#[handler]
+async fn import_session(req: &mut Request, res: &mut Response) {
+    let params = match req.parse_json::<SessionImportParams>().await {
+        Ok(p) => p,
+        Err(e) => {
+            res.render(StatusError::bad_request().detail(e.to_string()));
+            return;
+        }
+    };
+    let export = match SessionExport::decode(&params.data) {
+        Ok(export) => export,
+        Err(e) => {
+            res.render(StatusError::bad_request().detail(e.to_string()));
+            return;
+        }
+    };
+    match clients::session_import(export).await {
+        Ok(id) => res.render(Json(id.to_json())),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+}
+
 #[handler]
 async fn query_subscriptions(
     req: &mut Request,
@@ -737,6 +1119,70 @@ async fn get_route(req: &mut Request, res: &mut Response) {
     }
 }
 
+#[handler]
+async fn get_routing_table(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let max_row_limit = cfg.read().await.max_row_limit;
+    let mut q = match req.parse_queries::<RoutingTableParams>() {
+        Ok(q) => q,
+        Err(e) => {
+            res.render(StatusError::bad_request().detail(e.to_string()));
+            return Ok(());
+        }
+    };
+    if q._limit == 0 || q._limit > max_row_limit {
+        q._limit = max_row_limit;
+    }
+    let table = subs::routing_table(&q).await;
+    match q.format {
+        RoutingTableFormat::Json => {
+            res.render(Json(table.iter().map(|e| e.to_json()).collect::<Vec<serde_json::Value>>()))
+        }
+        RoutingTableFormat::Csv => {
+            let mut csv = String::from(ROUTING_TABLE_CSV_HEADER);
+            for e in table.iter() {
+                e.write_csv(&mut csv);
+            }
+            res.render(Text::Csv(csv))
+        }
+    }
+    Ok(())
+}
+
+#[handler]
+async fn get_retains(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let max_row_limit = cfg.read().await.max_row_limit;
+    let limit = req.query::<usize>("_limit").map(|l| l.min(max_row_limit)).unwrap_or(max_row_limit);
+    let topic_filter = TopicFilter::from(req.query::<String>("topic").unwrap_or_else(|| "#".into()));
+    let retains = match Runtime::instance().extends.retain().await.get(&topic_filter).await {
+        Ok(retains) => retains,
+        Err(e) => {
+            res.render(StatusError::service_unavailable().detail(e.to_string()));
+            return Ok(());
+        }
+    };
+    let retains = retains
+        .into_iter()
+        .take(limit)
+        .map(|(topic, retain)| {
+            json!({
+                "topic": topic,
+                "qos": retain.publish.qos.value(),
+                "payload": BASE64_STANDARD.encode(retain.publish.payload.as_ref()),
+                "clientid": retain.from.id.client_id,
+                "create_time": retain.publish.create_time,
+            })
+        })
+        .collect::<Vec<_>>();
+    res.render(Json(retains));
+    Ok(())
+}
+
 #[handler]
 async fn publish(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
     let cfg = get_cfg(depot)?;
@@ -792,6 +1238,7 @@ async fn _publish(
     if topics.is_empty() {
         return Err(MqttError::Msg("topics or topic is empty".into()));
     }
+    let topics = topics.into_iter().map(ValidTopicName::new).collect::<Result<Vec<_>>>()?;
     let qos = QoS::try_from(params.qos).map_err(|e| anyhow::Error::msg(e.to_string()))?;
     let encoding = params.encoding.to_ascii_lowercase();
     let payload = if encoding == "plain" {
@@ -809,17 +1256,7 @@ async fn _publish(
         params.clientid,
         Some(UserName::from("admin")),
     ));
-    let p = Publish {
-        dup: false,
-        retain: params.retain,
-        qos,
-        topic: "".into(),
-        packet_id: None,
-        payload,
-        properties: PublishProperties::default(),
-        delay_interval: None,
-        create_time: timestamp_millis(),
-    };
+    let p = Publish::builder().retain(params.retain).qos(qos).payload(payload).build();
 
     let message_expiry_interval = params
         .properties
@@ -834,7 +1271,7 @@ async fn _publish(
     for topic in topics {
         let from = from.clone();
         let mut p1 = p.clone();
-        p1.topic = topic;
+        p1.topic = topic.into_inner();
 
         let fut = async move {
             //hook, message_publish
@@ -1065,6 +1502,140 @@ async fn _all_plugins(message_type: MessageType) -> Result<Vec<serde_json::Value
     Ok(pluginss)
 }
 
+#[handler]
+async fn plugins_metrics(depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+
+    match _all_plugins(message_type).await {
+        Ok(pluginss) => res.render(Json(_plugins_metrics(pluginss))),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+//Sums the metrics reported in the attrs of the plugins, per plugin, the nodes that failed to reply
+//are left out
+fn _plugins_metrics(pluginss: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
+    let mut metricss: BTreeMap<String, (PluginMetrics, Vec<serde_json::Value>)> = BTreeMap::new();
+    for node_plugins in pluginss {
+        let plugins = if let Some(plugins) = node_plugins.get("plugins").and_then(|p| p.as_array()) {
+            plugins
+        } else {
+            continue;
+        };
+        for p in plugins {
+            let metrics = if let Some(metrics) = p.get("attrs").and_then(PluginMetrics::from_attrs) {
+                metrics
+            } else {
+                continue;
+            };
+            let name = p.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+            let (sum, nodes) = metricss.entry(name.into()).or_default();
+            sum.merge(&metrics);
+            nodes.push(json!({ "node": node_plugins.get("node"), "metrics": metrics }));
+        }
+    }
+    metricss
+        .into_iter()
+        .map(|(name, (sum, nodes))| json!({ "name": name, "metrics": sum, "nodes": nodes }))
+        .collect()
+}
+
+#[handler]
+async fn plugins_metrics_prometheus(depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+
+    match _all_plugins(message_type).await {
+        Ok(pluginss) => res.render(Text::Plain(_plugins_metrics_prometheus(&_plugins_metrics(pluginss)))),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+//name, type and help of the metric families of the plugins
+const PROMETHEUS_FAMILIES: &[(&str, &str, &str)] = &[
+    ("rmqtt_plugin_queue_depth", "gauge", "Messages or requests waiting to be processed"),
+    ("rmqtt_plugin_messages", "gauge", "Messages held by the plugin"),
+    ("rmqtt_plugin_errors_total", "counter", "Failed requests or dropped messages"),
+    ("rmqtt_plugin_cache_hits_total", "counter", "Cache hits"),
+    ("rmqtt_plugin_cache_misses_total", "counter", "Cache misses"),
+    ("rmqtt_plugin_requests_total", "counter", "Backend requests"),
+    ("rmqtt_plugin_latency_avg_ms", "gauge", "Average backend latency, in milliseconds"),
+    ("rmqtt_plugin_latency_max_ms", "gauge", "Maximum backend latency, in milliseconds"),
+    ("rmqtt_bridge_clients", "gauge", "Clients of the bridge"),
+    ("rmqtt_bridge_connected", "gauge", "Clients of the bridge connected to the remote system"),
+    ("rmqtt_bridge_reconnects_total", "counter", "Reconnects of the bridge clients"),
+    ("rmqtt_bridge_messages_total", "counter", "Messages forwarded by the bridge"),
+    ("rmqtt_bridge_lag_seconds", "gauge", "Age of the oldest message waiting to be forwarded"),
+];
+
+//Renders the metrics of the plugins on each node in the Prometheus text exposition format
+fn _plugins_metrics_prometheus(metricss: &[serde_json::Value]) -> String {
+    let mut samples: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    let mut add = |name, labels: &str, value: String| {
+        samples.entry(name).or_default().push(format!("{}{{{}}} {}", name, labels, value));
+    };
+    for plugin in metricss {
+        let plugin_name = plugin.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+        let nodes = plugin.get("nodes").and_then(|n| n.as_array()).map(|n| n.as_slice()).unwrap_or_default();
+        for node in nodes {
+            let metrics = if let Some(metrics) =
+                node.get("metrics").and_then(|m| serde_json::from_value::<PluginMetrics>(m.clone()).ok())
+            {
+                metrics
+            } else {
+                continue;
+            };
+            let node_id = node.get("node").map(|n| n.to_string()).unwrap_or_default();
+            let labels = format!("plugin=\"{}\",node=\"{}\"", label_value(plugin_name), node_id);
+            if let Some(v) = metrics.queue_depth {
+                add("rmqtt_plugin_queue_depth", &labels, v.to_string());
+            }
+            if let Some(v) = metrics.messages {
+                add("rmqtt_plugin_messages", &labels, v.to_string());
+            }
+            if let Some(v) = metrics.errors {
+                add("rmqtt_plugin_errors_total", &labels, v.to_string());
+            }
+            if let Some(cache) = metrics.cache {
+                add("rmqtt_plugin_cache_hits_total", &labels, cache.hits.to_string());
+                add("rmqtt_plugin_cache_misses_total", &labels, cache.misses.to_string());
+            }
+            if let Some(latency) = metrics.latency {
+                add("rmqtt_plugin_requests_total", &labels, latency.count.to_string());
+                add("rmqtt_plugin_latency_avg_ms", &labels, latency.avg_ms.to_string());
+                add("rmqtt_plugin_latency_max_ms", &labels, latency.max_ms.to_string());
+            }
+            for (bridge_name, bridge) in metrics.bridges.iter().flatten() {
+                let labels = format!("{},bridge=\"{}\"", labels, label_value(bridge_name));
+                add("rmqtt_bridge_clients", &labels, bridge.clients.to_string());
+                add("rmqtt_bridge_connected", &labels, bridge.connected.to_string());
+                add("rmqtt_bridge_reconnects_total", &labels, bridge.reconnects.to_string());
+                add("rmqtt_bridge_messages_total", &labels, bridge.messages.to_string());
+                add("rmqtt_bridge_lag_seconds", &labels, (bridge.lag_ms as f64 / 1000.0).to_string());
+            }
+        }
+    }
+    let mut text = String::new();
+    for (name, typ, help) in PROMETHEUS_FAMILIES {
+        if let Some(samples) = samples.get(name) {
+            text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, typ));
+            for sample in samples {
+                text.push_str(sample);
+                text.push('\n');
+            }
+        }
+    }
+    text
+}
+
+#[inline]
+fn label_value(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
 #[handler]
 async fn node_plugins(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
     let cfg = get_cfg(depot)?;
@@ -1250,6 +1821,255 @@ async fn _node_plugin_config_reload(node_id: NodeId, name: &str, message_type: M
     }
 }
 
+#[handler]
+async fn node_config_reload(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+
+    match _node_config_reload(node_id, message_type).await {
+        Ok(report) => res.render(Json(report)),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+async fn _node_config_reload(node_id: NodeId, message_type: MessageType) -> Result<ReloadReport> {
+    if node_id == Runtime::instance().node.id() {
+        Runtime::instance().reload_config().await
+    } else {
+        let c = get_grpc_client(node_id).await?;
+        let msg = Message::ReloadConfig.encode()?;
+        let reply = MessageSender::new(c, message_type, GrpcMessage::Data(msg)).send().await?;
+        match reply {
+            GrpcMessageReply::Data(msg) => match MessageReply::decode(&msg)? {
+                MessageReply::ReloadConfig(report) => Ok(report),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[handler]
+async fn node_maintenance_status(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+
+    match _node_maintenance(node_id, Message::MaintenanceStatus, message_type).await {
+        Ok(status) => res.render(Json(status.map(|s| s.to_json()))),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+#[handler]
+async fn node_maintenance_start(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+    let opts = match req.parse_json::<MaintenanceOptions>().await {
+        Ok(opts) => opts,
+        Err(e) => {
+            res.render(StatusError::bad_request().detail(e.to_string()));
+            return Ok(());
+        }
+    };
+
+    match _node_maintenance(node_id, Message::MaintenanceStart(opts), message_type).await {
+        Ok(status) => res.render(Json(status.map(|s| s.to_json()))),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+#[handler]
+async fn node_maintenance_stop(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+
+    let reply = if node_id == Runtime::instance().node.id() {
+        Ok(Runtime::instance().node.maintenance_stop())
+    } else {
+        _node_maintenance_stop_on_other_node(node_id, message_type).await
+    };
+    match reply {
+        Ok(stopped) => res.render(Json(stopped)),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+async fn _node_maintenance(
+    node_id: NodeId,
+    msg: Message<'_>,
+    message_type: MessageType,
+) -> Result<Option<MaintenanceStatus>> {
+    if node_id == Runtime::instance().node.id() {
+        match msg {
+            Message::MaintenanceStart(opts) => Runtime::instance().node.maintenance_start(opts).map(Some),
+            _ => Ok(Runtime::instance().node.maintenance_status()),
+        }
+    } else {
+        let c = get_grpc_client(node_id).await?;
+        let reply = MessageSender::new(c, message_type, GrpcMessage::Data(msg.encode()?)).send().await?;
+        match reply {
+            GrpcMessageReply::Data(msg) => match MessageReply::decode(&msg)? {
+                MessageReply::MaintenanceStatus(status) => Ok(status),
+                _ => unreachable!(),
+            },
+            GrpcMessageReply::Error(e) => Err(MqttError::from(e)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+async fn _node_maintenance_stop_on_other_node(node_id: NodeId, message_type: MessageType) -> Result<bool> {
+    let c = get_grpc_client(node_id).await?;
+    let msg = Message::MaintenanceStop.encode()?;
+    let reply = MessageSender::new(c, message_type, GrpcMessage::Data(msg)).send().await?;
+    match reply {
+        GrpcMessageReply::Data(msg) => match MessageReply::decode(&msg)? {
+            MessageReply::MaintenanceStop(stopped) => Ok(stopped),
+            _ => unreachable!(),
+        },
+        GrpcMessageReply::Error(e) => Err(MqttError::from(e)),
+        _ => unreachable!(),
+    }
+}
+
+#[handler]
+async fn node_log_levels(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+
+    match _node_log_level(node_id, Message::LogLevels, message_type).await {
+        Ok(levels) => res.render(Json(levels)),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+#[handler]
+async fn node_log_level_set(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+    let opts = match req.parse_json::<LogLevelOptions>().await {
+        Ok(opts) => opts,
+        Err(e) => {
+            res.render(StatusError::bad_request().detail(e.to_string()));
+            return Ok(());
+        }
+    };
+
+    match _node_log_level(node_id, Message::LogLevelSet(opts), message_type).await {
+        Ok(levels) => res.render(Json(levels)),
+        Err(e) => res.render(StatusError::bad_request().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+#[handler]
+async fn node_log_level_reset(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+
+    match _node_log_level(node_id, Message::LogLevelReset, message_type).await {
+        Ok(levels) => res.render(Json(levels)),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+async fn _node_log_level(node_id: NodeId, msg: Message<'_>, message_type: MessageType) -> Result<LogLevels> {
+    if node_id == Runtime::instance().node.id() {
+        match msg {
+            Message::LogLevelSet(opts) => logger::set_target_level(&opts),
+            Message::LogLevelReset => Ok(logger::reset_levels()),
+            _ => Ok(logger::levels()),
+        }
+    } else {
+        let c = get_grpc_client(node_id).await?;
+        let reply = MessageSender::new(c, message_type, GrpcMessage::Data(msg.encode()?)).send().await?;
+        match reply {
+            GrpcMessageReply::Data(msg) => match MessageReply::decode(&msg)? {
+                MessageReply::LogLevels(levels) => Ok(levels),
+                _ => unreachable!(),
+            },
+            GrpcMessageReply::Error(e) => Err(MqttError::from(e)),
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[handler]
 async fn node_plugin_load(
     req: &mut Request,
@@ -1424,6 +2244,43 @@ async fn get_stats(req: &mut Request, depot: &mut Depot, res: &mut Response) ->
     Ok(())
 }
 
+#[handler]
+async fn reset_stats(req: &mut Request, depot: &mut Depot, res: &mut Response) -> Result<(), salvo::Error> {
+    let cfg = get_cfg(depot)?;
+    let message_type = cfg.read().await.message_type;
+    let node_id = if let Some(node_id) = req.param::<NodeId>("id") {
+        node_id
+    } else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+
+    let reply = if node_id == Runtime::instance().node.id() {
+        Runtime::instance().stats.reset().await
+    } else {
+        _reset_stats_on_other_node(node_id, message_type).await
+    };
+    match reply {
+        Ok(()) => res.render(Json(true)),
+        Err(e) => res.render(StatusError::service_unavailable().detail(e.to_string())),
+    }
+    Ok(())
+}
+
+async fn _reset_stats_on_other_node(node_id: NodeId, message_type: MessageType) -> Result<()> {
+    let c = get_grpc_client(node_id).await?;
+    let msg = Message::StatsReset.encode()?;
+    let reply = MessageSender::new(c, message_type, GrpcMessage::Data(msg)).send().await?;
+    match reply {
+        GrpcMessageReply::Data(msg) => match MessageReply::decode(&msg)? {
+            MessageReply::StatsReset => Ok(()),
+            _ => unreachable!(),
+        },
+        GrpcMessageReply::Error(e) => Err(MqttError::from(e)),
+        _ => unreachable!(),
+    }
+}
+
 #[inline]
 async fn _get_stats_one(message_type: MessageType, id: NodeId) -> Result<Option<serde_json::Value>> {
     if id == Runtime::instance().node.id() {
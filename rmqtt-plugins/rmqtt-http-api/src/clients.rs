@@ -1,10 +1,19 @@
 use rmqtt::{
-    broker::Entry, log, tokio, ClientId, ConnectInfo, Id, Result, Runtime, Session, TimestampMillis,
+    anyhow, broker::types::Message, broker::Entry, log, tokio, tokio::sync::oneshot, ClientId, ConnectInfo,
+    DisconnectInfo, Id, MqttError, Result, Runtime, Session, SessionState, SessionSubMap, SessionSubs,
+    TimestampMillis,
 };
-use rmqtt::{chrono, futures, serde_json};
+use rmqtt::{
+    chrono, futures, futures::channel::mpsc, futures::StreamExt, once_cell::sync::OnceCell, serde_json,
+};
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use super::types::{ClientSearchParams as SearchParams, ClientSearchResult as SearchResult};
+use super::types::{
+    ClientRuntime, ClientSearchParams as SearchParams, ClientSearchResult as SearchResult, InflightDetail,
+    SessionExport,
+};
 
 pub(crate) async fn get(clientid: &str) -> Option<SearchResult> {
     let shared = Runtime::instance().extends.shared().await;
@@ -14,7 +23,190 @@ pub(crate) async fn get(clientid: &str) -> Option<SearchResult> {
     let id = Id::from(Runtime::instance().node.id(), ClientId::from(clientid));
     let peer = shared.entry(id);
     let s = peer.session()?;
-    Some(build_result(Some(s)).await)
+    let mut result = build_result(Some(s.clone())).await;
+    if result.connected {
+        result.runtime = Some(build_runtime(&s).await);
+    }
+    Some(result)
+}
+
+#[inline]
+async fn entry(clientid: &str) -> Box<dyn Entry> {
+    Runtime::instance()
+        .extends
+        .shared()
+        .await
+        .entry(Id::from(Runtime::instance().node.id(), ClientId::from(clientid)))
+}
+
+pub(crate) async fn kick(clientid: &str) -> Result<Option<Id>> {
+    let mut entry = entry(clientid).await;
+    if let Some(s) = entry.session() {
+        entry.kick(true, true, true).await?;
+        Ok(Some(s.id))
+    } else {
+        Ok(None)
+    }
+}
+
+pub(crate) async fn last_will(clientid: &str) -> Option<serde_json::Value> {
+    entry(clientid).await.session()?.last_will().await
+}
+
+//The will is published by the session itself, so it goes through the same hooks and ACL as on disconnect
+pub(crate) async fn last_will_trigger(clientid: &str) -> Result<Option<Id>> {
+    let entry = entry(clientid).await;
+    let has_will = match entry.session() {
+        Some(s) => s.last_will().await.is_some(),
+        None => false,
+    };
+    match entry.tx() {
+        Some(tx) if has_will && entry.is_connected().await => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            tx.unbounded_send(Message::TriggerLastWill(reply_tx)).map_err(anyhow::Error::new)?;
+            reply_rx.await.map_err(anyhow::Error::new)??;
+            Ok(Some(entry.id()))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub(crate) async fn last_will_clear(clientid: &str) -> Option<Id> {
+    match entry(clientid).await.session() {
+        Some(s) if s.last_will().await.is_some() => {
+            s.last_will_clear();
+            Some(s.id)
+        }
+        _ => None,
+    }
+}
+
+//The session is taken out of this node like on a takeover, the client is disconnected if it is connected
+pub(crate) async fn session_export(clientid: &str) -> Result<Option<SessionExport>> {
+    let mut entry = entry(clientid).await;
+    let s = match entry.session() {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let conn_info = s.connect_info().await?.as_ref().clone();
+    let attrs = s.attrs.read().await.clone();
+    let superuser = s.superuser().await.unwrap_or_default();
+    let offline_info = match entry.kick(false, false, true).await? {
+        Some(offline_info) => offline_info,
+        None => return Ok(None),
+    };
+    let router = Runtime::instance().extends.router().await;
+    for (topic_filter, _) in offline_info.subscriptions.iter() {
+        if let Err(e) = router.remove(topic_filter, offline_info.id.clone()).await {
+            log::warn!("{:?} session export, remove route {}, {:?}", offline_info.id, topic_filter, e);
+        }
+    }
+    Ok(Some(SessionExport { offline_info, conn_info, attrs, superuser }))
+}
+
+//The session is created offline on this node, it is resumed when the client connects without clean start
+pub(crate) async fn session_import(export: SessionExport) -> Result<Id> {
+    let SessionExport { mut offline_info, conn_info, attrs, superuser } = export;
+    let exported_id = offline_info.id.clone();
+    if Runtime::instance().extends.shared().await.exist(&exported_id.client_id) {
+        return Err(MqttError::Msg(format!("client {} already exists", exported_id.client_id)));
+    }
+    let listen_cfg = exported_id
+        .local_addr
+        .and_then(|addr| Runtime::instance().settings.listeners.get(addr.port()))
+        .ok_or_else(|| {
+            MqttError::Msg(format!("listener is not found, local addr is {:?}", exported_id.local_addr))
+        })?;
+    let id = Id::new(
+        Runtime::instance().node.id(),
+        exported_id.local_addr,
+        exported_id.remote_addr,
+        exported_id.client_id.clone(),
+        exported_id.username.clone(),
+    );
+
+    let conn_info = Arc::new(conn_info);
+    let fitter = Runtime::instance().extends.fitter_mgr().await.create(
+        conn_info.clone(),
+        id.clone(),
+        listen_cfg.clone(),
+    );
+    let session_expiry_interval = fitter.session_expiry_interval(None);
+    if session_expiry_interval.is_zero() {
+        return Err(MqttError::Msg("the session expires when the client disconnects".into()));
+    }
+    let max_inflight = fitter.max_inflight();
+    let max_mqueue_len = fitter.max_mqueue_len();
+    let now = chrono::Local::now().timestamp_millis();
+    let subs = offline_info.subscriptions.iter().cloned().collect::<SessionSubMap>();
+    let session = Session::new(
+        id.clone(),
+        max_mqueue_len,
+        listen_cfg,
+        fitter,
+        max_inflight,
+        offline_info.created_at,
+        conn_info,
+        false,
+        superuser,
+        attrs,
+        false,
+        now,
+        SessionSubs::from(subs),
+        Some(DisconnectInfo::new(now)),
+        None,
+    )
+    .await?;
+
+    let router = Runtime::instance().extends.router().await;
+    for (topic_filter, opts) in offline_info.subscriptions.drain(..) {
+        router.add(&topic_filter, id.clone(), opts).await?;
+    }
+    drop(router);
+    for item in offline_info.offline_messages.drain(..) {
+        if let Err((f, p)) = session.deliver_queue().push(item) {
+            log::warn!("{:?} session import, deliver queue is full, from: {:?}, publish: {:?}", id, f, p);
+        }
+    }
+    for item in offline_info.inflight_messages.drain(..) {
+        session.inflight_win().write().await.push_back(item);
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    session_importer()
+        .unbounded_send((session, session_expiry_interval, reply_tx))
+        .map_err(anyhow::Error::new)?;
+    reply_rx.await.map_err(anyhow::Error::new)??;
+    Ok(id)
+}
+
+type SessionImport = (Session, Duration, oneshot::Sender<Result<()>>);
+
+//The event loop of an offline session is a local task, imported sessions are started on this thread
+fn session_importer() -> &'static mpsc::UnboundedSender<SessionImport> {
+    static INSTANCE: OnceCell<mpsc::UnboundedSender<SessionImport>> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        let (tx, mut rx) = mpsc::unbounded::<SessionImport>();
+        std::thread::spawn(move || {
+            let local_rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("tokio runtime build failed");
+            let local_set = tokio::task::LocalSet::new();
+            local_set.block_on(&local_rt, async {
+                while let Some((session, session_expiry_interval, reply_tx)) = rx.next().await {
+                    let _ = reply_tx.send(offline_start(session, session_expiry_interval).await);
+                }
+            });
+        });
+        tx
+    })
+}
+
+async fn offline_start(session: Session, session_expiry_interval: Duration) -> Result<()> {
+    let (state, msg_tx) = SessionState::offline_restart(session.clone(), session_expiry_interval).await?;
+    let mut entry = Runtime::instance().extends.shared().await.entry(state.id.clone());
+    entry.set(session, msg_tx).await
 }
 
 pub(crate) async fn search(q: &SearchParams) -> Vec<SearchResult> {
@@ -61,12 +253,10 @@ async fn build_result(s: Option<Session>) -> SearchResult {
     let created_at = s.created_at().await.map(|at| at / 1000).unwrap_or_default();
     let subscriptions_cnt = if let Ok(subs) = s.subscriptions().await { subs.len().await } else { 0 };
     let extra_attrs = s.extra_attrs.read().await.len();
+    let attrs = s.attrs.read().await.clone();
 
     let connect_info = s.connect_info().await.ok();
-    let last_will = connect_info
-        .as_ref()
-        .and_then(|conn_info| conn_info.last_will().map(|lw| lw.to_json()))
-        .unwrap_or(serde_json::Value::Null);
+    let last_will = s.last_will().await.unwrap_or(serde_json::Value::Null);
     let keepalive = connect_info.as_ref().map(|c| c.keep_alive()).unwrap_or_default();
     let clean_start = connect_info.as_ref().map(|c| c.clean_start()).unwrap_or_default();
     let protocol = connect_info.as_ref().map(|c| c.proto_ver()).unwrap_or_default();
@@ -77,8 +267,8 @@ async fn build_result(s: Option<Session>) -> SearchResult {
         username: id.username(),
         superuser: s.superuser().await.unwrap_or_default(),
         proto_ver: protocol,
-        ip_address: id.remote_addr.map(|addr| addr.ip().to_string()),
-        port: id.remote_addr.map(|addr| addr.port()),
+        ip_address: id.remote_ip().map(|ip| ip.to_string()),
+        port: id.remote_port(),
         connected,
         connected_at,
         disconnected_at,
@@ -91,13 +281,51 @@ async fn build_result(s: Option<Session>) -> SearchResult {
         subscriptions_cnt,
         max_subscriptions: s.listen_cfg().max_subscriptions,
         extra_attrs,
+        attrs,
         last_will,
 
         inflight,
         max_inflight: s.listen_cfg().max_inflight.get(),
 
         mqueue_len: s.deliver_queue().len(),
+        mqueue_priority_len: s.deliver_queue().priority_len(),
         max_mqueue: s.listen_cfg().max_mqueue_len,
+
+        runtime: None,
+    }
+}
+
+async fn build_runtime(s: &Session) -> ClientRuntime {
+    let now = chrono::Local::now().timestamp_millis();
+    let inflight_win = s.inflight_win().read().await;
+    let retry_interval = inflight_win.retry_interval();
+    let next_retry_in = |update_time: TimestampMillis| {
+        if retry_interval > 0 {
+            Some((update_time + retry_interval - now).max(0))
+        } else {
+            None
+        }
+    };
+    let inflight = inflight_win
+        .iter()
+        .map(|(packet_id, m)| InflightDetail {
+            packet_id: *packet_id,
+            qos: m.publish.qos().value(),
+            status: format!("{:?}", m.status),
+            topic: m.publish.topic.clone(),
+            dup: m.publish.dup(),
+            age: now - m.update_time,
+            next_retry_in: next_retry_in(m.update_time),
+        })
+        .collect::<Vec<_>>();
+    ClientRuntime {
+        next_retry_in: inflight.first().and_then(|m| m.next_retry_in),
+        inflight,
+        retry_interval,
+        mqueue_len: s.deliver_queue().len(),
+        mqueue_bytes: s.deliver_queue().bytes(),
+        last_received_at: s.last_received_at(),
+        last_delivered_at: s.last_delivered_at(),
     }
 }
 
@@ -135,12 +363,9 @@ async fn _filtering(q: &SearchParams, entry: &dyn Entry) -> Result<bool> {
     }
 
     if let Some(ip_address) = &q.ip_address {
-        if let Some(remote_addr) = id.remote_addr {
-            if remote_addr.ip().to_string() != *ip_address {
-                return Ok(false);
-            }
-        } else {
-            return Ok(false);
+        match (id.remote_ip(), ip_address.parse::<IpAddr>()) {
+            (Some(ip), Ok(q_ip)) if ip == q_ip => {}
+            _ => return Ok(false),
         }
     }
 
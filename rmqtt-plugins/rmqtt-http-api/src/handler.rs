@@ -1,8 +1,8 @@
 use rmqtt::{async_trait::async_trait, log};
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, ReturnType},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, ReturnType},
     grpc::{Message as GrpcMessage, MessageReply as GrpcMessageReply, MessageType},
-    Runtime,
+    logger, Runtime,
 };
 
 use super::clients;
@@ -27,7 +27,7 @@ impl Handler for HookHandler {
             Parameter::GrpcMessageReceived(typ, msg) => {
                 log::debug!("GrpcMessageReceived, type: {}, msg: {:?}", typ, msg);
                 if self.message_type != *typ {
-                    return (true, acc);
+                    return (Proceed::Continue, acc);
                 }
                 match msg {
                     GrpcMessage::Data(data) => {
@@ -101,6 +101,64 @@ impl Handler for HookHandler {
                                     ))),
                                 }
                             }
+                            Ok(Message::ClientKick { clientid }) => match clients::kick(clientid)
+                                .await
+                                .and_then(|id| MessageReply::ClientKick(id).encode())
+                            {
+                                Ok(ress) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress))),
+                                Err(e) => {
+                                    HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(e.to_string())))
+                                }
+                            },
+                            Ok(Message::ClientLastWill { clientid }) => {
+                                let last_will =
+                                    clients::last_will(clientid).await.map(|w| w.to_string().into_bytes());
+                                match MessageReply::ClientLastWill(last_will).encode() {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::ClientLastWillTrigger { clientid }) => {
+                                match clients::last_will_trigger(clientid)
+                                    .await
+                                    .and_then(|id| MessageReply::ClientLastWillTrigger(id).encode())
+                                {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::ClientLastWillClear { clientid }) => {
+                                let id = clients::last_will_clear(clientid).await;
+                                match MessageReply::ClientLastWillClear(id).encode() {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::SessionExport { clientid }) => {
+                                match clients::session_export(clientid)
+                                    .await
+                                    .and_then(|export| MessageReply::SessionExport(export).encode())
+                                {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
                             Ok(Message::Subscribe(params)) =>
                             {
                                 #[allow(clippy::mutable_key_type)]
@@ -211,6 +269,104 @@ impl Handler for HookHandler {
                                     ))),
                                 }
                             }
+                            Ok(Message::ReloadConfig) => {
+                                match Runtime::instance().reload_config().await {
+                                    Ok(report) => match MessageReply::ReloadConfig(report).encode() {
+                                        Ok(ress) => {
+                                            HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                        }
+                                        Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                            e.to_string(),
+                                        ))),
+                                    },
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::MaintenanceStart(opts)) => {
+                                let reply =
+                                    Runtime::instance().node.maintenance_start(opts).and_then(|status| {
+                                        MessageReply::MaintenanceStatus(Some(status)).encode()
+                                    });
+                                match reply {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::StatsReset) => {
+                                let reply = Runtime::instance()
+                                    .stats
+                                    .reset()
+                                    .await
+                                    .and_then(|_| MessageReply::StatsReset.encode());
+                                match reply {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::MaintenanceStop) => {
+                                let stopped = Runtime::instance().node.maintenance_stop();
+                                match MessageReply::MaintenanceStop(stopped).encode() {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::MaintenanceStatus) => {
+                                let status = Runtime::instance().node.maintenance_status();
+                                match MessageReply::MaintenanceStatus(status).encode() {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::LogLevels) => {
+                                match MessageReply::LogLevels(logger::levels()).encode() {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::LogLevelSet(opts)) => {
+                                let reply = logger::set_target_level(&opts)
+                                    .and_then(|levels| MessageReply::LogLevels(levels).encode());
+                                match reply {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
+                            Ok(Message::LogLevelReset) => {
+                                match MessageReply::LogLevels(logger::reset_levels()).encode() {
+                                    Ok(ress) => {
+                                        HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Data(ress)))
+                                    }
+                                    Err(e) => HookResult::GrpcMessageReply(Ok(GrpcMessageReply::Error(
+                                        e.to_string(),
+                                    ))),
+                                }
+                            }
                             Ok(Message::UnloadPlugin { name }) => {
                                 match Runtime::instance().plugins.stop(name).await {
                                     Ok(ok) => match MessageReply::UnloadPlugin(ok).encode() {
@@ -227,7 +383,7 @@ impl Handler for HookHandler {
                                 }
                             }
                         };
-                        return (false, Some(new_acc));
+                        return (Proceed::Stop, Some(new_acc));
                     }
                     _ => {
                         log::error!("unimplemented, {:?}", param)
@@ -238,6 +394,6 @@ impl Handler for HookHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
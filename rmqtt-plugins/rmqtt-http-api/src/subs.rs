@@ -3,7 +3,7 @@ use rmqtt::{
     Id, Message as MqttMessage, MqttError, QoSEx, Result, Runtime, Subscribe, TopicFilter, Unsubscribe,
 };
 
-use super::types::{SubscribeParams, UnsubscribeParams};
+use super::types::{RoutingTableEntry, RoutingTableParams, SubscribeParams, UnsubscribeParams};
 
 #[inline]
 pub(crate) async fn subscribe(params: SubscribeParams) -> Result<HashMap<TopicFilter, Result<bool>>> {
@@ -62,3 +62,31 @@ pub(crate) async fn unsubscribe(params: UnsubscribeParams) -> Result<()> {
     reply_rx.await.map_err(anyhow::Error::new)??;
     Ok(())
 }
+
+//Relations of the router of this node, sorted by topic filter. `_limit` is the number of subscribers.
+pub(crate) async fn routing_table(q: &RoutingTableParams) -> Vec<RoutingTableEntry> {
+    let router = Runtime::instance().extends.router().await;
+    let mut count = 0;
+    let mut table = Vec::new();
+    for entry in router.relations().iter() {
+        if count >= q._limit {
+            break;
+        }
+        let topic_filter = entry.key();
+        if let Some(prefix) = &q.prefix {
+            if !topic_filter.starts_with(prefix.as_str()) {
+                continue;
+            }
+        }
+        let mut subscribers = entry
+            .iter()
+            .take(q._limit - count)
+            .map(|(clientid, (id, opts))| (clientid.clone(), id.node_id, opts.clone()))
+            .collect::<Vec<_>>();
+        subscribers.sort_by(|(c1, n1, _), (c2, n2, _)| (n1, c1).cmp(&(n2, c2)));
+        count += subscribers.len();
+        table.push(RoutingTableEntry { topic_filter: topic_filter.clone(), subscribers });
+    }
+    table.sort_by(|e1, e2| e1.topic_filter.cmp(&e2.topic_filter));
+    table
+}
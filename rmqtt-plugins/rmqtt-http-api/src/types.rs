@@ -2,23 +2,32 @@ use serde::de::{self, Deserialize};
 use serde::ser::{self, Serialize};
 use std::time::Duration;
 
+use rmqtt::base64::prelude::{Engine, BASE64_STANDARD};
+use rmqtt::broker::session::SessionOfflineInfo;
 use rmqtt::chrono::LocalResult;
-use rmqtt::node::{BrokerInfo, NodeInfo, NodeStatus};
+use rmqtt::logger::{LogLevelOptions, LogLevels};
+use rmqtt::node::{BrokerInfo, MaintenanceOptions, MaintenanceStatus, NodeInfo, NodeStatus};
 use rmqtt::plugin::PluginInfo;
-use rmqtt::settings::{deserialize_datetime_option, serialize_datetime_option};
+use rmqtt::settings::{deserialize_datetime_option, serialize_datetime_option, ReloadReport};
 use rmqtt::{anyhow, bincode, chrono, serde_json, HashMap, MqttError, QoS};
 use rmqtt::{metrics::Metrics, stats::Stats};
-use rmqtt::{ClientId, NodeId, Timestamp, TopicFilter, TopicName, UserName};
-use rmqtt::{PublishProperties, Result};
+use rmqtt::{ClientId, Id, NodeId, Timestamp, TopicFilter, TopicName, UserName};
+use rmqtt::{ConnectInfo, PublishProperties, Result, SessionAttrs, SubscriptionOptions};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Message<'a> {
     BrokerInfo,
     NodeInfo,
     StatsInfo,
+    StatsReset,
     MetricsInfo,
     ClientSearch(Box<ClientSearchParams>),
     ClientGet { clientid: &'a str },
+    ClientKick { clientid: &'a str },
+    ClientLastWill { clientid: &'a str },
+    ClientLastWillTrigger { clientid: &'a str },
+    ClientLastWillClear { clientid: &'a str },
+    SessionExport { clientid: &'a str },
     Subscribe(SubscribeParams),
     Unsubscribe(UnsubscribeParams),
     GetPlugins,
@@ -27,6 +36,13 @@ pub enum Message<'a> {
     ReloadPluginConfig { name: &'a str },
     LoadPlugin { name: &'a str },
     UnloadPlugin { name: &'a str },
+    ReloadConfig,
+    MaintenanceStart(MaintenanceOptions),
+    MaintenanceStop,
+    MaintenanceStatus,
+    LogLevels,
+    LogLevelSet(LogLevelOptions),
+    LogLevelReset,
 }
 
 impl<'a> Message<'a> {
@@ -45,9 +61,15 @@ pub enum MessageReply {
     BrokerInfo(BrokerInfo),
     NodeInfo(NodeInfo),
     StatsInfo(NodeStatus, Box<Stats>),
+    StatsReset,
     MetricsInfo(Metrics),
     ClientSearch(Vec<ClientSearchResult>),
     ClientGet(Option<ClientSearchResult>),
+    ClientKick(Option<Id>),
+    ClientLastWill(Option<Vec<u8>>),
+    ClientLastWillTrigger(Option<Id>),
+    ClientLastWillClear(Option<Id>),
+    SessionExport(Option<SessionExport>),
     Subscribe(HashMap<TopicFilter, (bool, Option<String>)>),
     Unsubscribe,
     GetPlugins(Vec<PluginInfo>),
@@ -56,6 +78,10 @@ pub enum MessageReply {
     ReloadPluginConfig,
     LoadPlugin,
     UnloadPlugin(bool),
+    ReloadConfig(ReloadReport),
+    MaintenanceStatus(Option<MaintenanceStatus>),
+    MaintenanceStop(bool),
+    LogLevels(LogLevels),
 }
 
 impl MessageReply {
@@ -138,6 +164,7 @@ pub struct ClientSearchResult {
     pub subscriptions_cnt: usize,
     pub max_subscriptions: usize,
     pub extra_attrs: usize,
+    pub attrs: SessionAttrs,
     #[serde(
         default,
         serialize_with = "ClientSearchResult::serialize_last_will",
@@ -160,6 +187,9 @@ pub struct ClientSearchResult {
     //     pub send_msg:0,	//Number of sent PUBLISH packets
     //     pub resend_msg:0, //Resent message data
     //     pub ackeds:0,  //Number of Acked received
+
+    //only in the detail of a single client
+    pub runtime: Option<ClientRuntime>,
 }
 
 impl ClientSearchResult {
@@ -181,7 +211,7 @@ impl ClientSearchResult {
 
     #[inline]
     pub fn to_json(&self) -> serde_json::Value {
-        let data = serde_json::json!({
+        let mut data = serde_json::json!({
             "node_id": self.node_id,
             "clientid": self.clientid,
             "username": self.username,
@@ -201,6 +231,7 @@ impl ClientSearchResult {
             "subscriptions_cnt": self.subscriptions_cnt,
             "max_subscriptions": self.max_subscriptions,
             "extra_attrs": self.extra_attrs,
+            "attrs": self.attrs.to_json(),
             "last_will": self.last_will,
 
             "inflight": self.inflight,
@@ -221,10 +252,75 @@ impl ClientSearchResult {
             // "ackeds": 0,  //Number of Acked received
 
         });
+        if let (Some(runtime), Some(obj)) = (self.runtime.as_ref(), data.as_object_mut()) {
+            obj.insert("runtime".into(), runtime.to_json());
+        }
         data
     }
 }
 
+///Runtime state of a connected session, to debug QoS 1/2 flows that do not complete, times are in
+///milliseconds
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct ClientRuntime {
+    pub inflight: Vec<InflightDetail>,
+    //0 means inflight messages are not retried
+    pub retry_interval: i64,
+    //until the first inflight message is retried
+    pub next_retry_in: Option<i64>,
+    pub mqueue_len: usize,
+    //queued messages in the high priority lane, included in mqueue_len
+    pub mqueue_priority_len: usize,
+    //payload bytes of the queued messages
+    pub mqueue_bytes: usize,
+    pub last_received_at: i64,
+    pub last_delivered_at: i64,
+}
+
+impl ClientRuntime {
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "inflight": self.inflight.iter().map(|m| m.to_json()).collect::<Vec<_>>(),
+            "retry_interval": self.retry_interval,
+            "next_retry_in": self.next_retry_in,
+            "mqueue_len": self.mqueue_len,
+            "mqueue_priority_len": self.mqueue_priority_len,
+            "mqueue_bytes": self.mqueue_bytes,
+            "last_received_at": format_timestamp(self.last_received_at / 1000),
+            "last_delivered_at": format_timestamp(self.last_delivered_at / 1000),
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct InflightDetail {
+    pub packet_id: u16,
+    pub qos: u8,
+    //UnAck, UnReceived or UnComplete, the acknowledgement the broker is waiting for
+    pub status: String,
+    pub topic: TopicName,
+    pub dup: bool,
+    //since it was sent or its status last changed
+    pub age: i64,
+    pub next_retry_in: Option<i64>,
+}
+
+impl InflightDetail {
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "packet_id": self.packet_id,
+            "qos": self.qos,
+            "status": self.status,
+            "topic": self.topic,
+            "dup": self.dup,
+            "age": self.age,
+            "next_retry_in": self.next_retry_in,
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct PublishParams {
     //For topic and topics, with at least one of them specified
@@ -313,6 +409,141 @@ pub struct UnsubscribeParams {
     pub clientid: ClientId,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingTableFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RoutingTableParams {
+    #[serde(default)]
+    pub _limit: usize,
+    //Only topic filters starting with the prefix
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub format: RoutingTableFormat,
+}
+
+pub const ROUTING_TABLE_CSV_HEADER: &str =
+    "topic_filter,clientid,node_id,qos,group,limit_subs,no_local,retain_as_published,retain_handling,id\n";
+
+///A topic filter of the routing table and its subscribers
+#[derive(Debug, Clone)]
+pub struct RoutingTableEntry {
+    pub topic_filter: TopicFilter,
+    pub subscribers: Vec<(ClientId, NodeId, SubscriptionOptions)>,
+}
+
+impl RoutingTableEntry {
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Value {
+        let subscribers = self
+            .subscribers
+            .iter()
+            .map(|(clientid, node_id, opts)| {
+                serde_json::json!({
+                    "clientid": clientid,
+                    "node_id": node_id,
+                    "opts": opts.to_json(),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "topic_filter": self.topic_filter,
+            "subscribers": subscribers,
+        })
+    }
+
+    ///One record per subscriber, the columns are those of `ROUTING_TABLE_CSV_HEADER`
+    pub fn write_csv(&self, out: &mut String) {
+        fn field<T: ToString>(v: Option<T>) -> String {
+            v.map(|v| v.to_string()).unwrap_or_default()
+        }
+        let topic_filter = csv_escape(&self.topic_filter);
+        for (clientid, node_id, opts) in &self.subscribers {
+            let retain_handling = match opts {
+                SubscriptionOptions::V3(_) => None,
+                SubscriptionOptions::V5(opts) => Some(opts.retain_handling_value()),
+            };
+            let record = [
+                topic_filter.clone(),
+                csv_escape(clientid),
+                node_id.to_string(),
+                opts.qos_value().to_string(),
+                csv_escape(&field(opts.shared_group())),
+                field(opts.limit_subs()),
+                field(opts.no_local()),
+                field(opts.retain_as_published()),
+                field(retain_handling),
+                field(opts.subscription_identifier()),
+            ];
+            out.push_str(&record.join(","));
+            out.push('\n');
+        }
+    }
+}
+
+#[inline]
+fn csv_escape(v: &str) -> String {
+    if v.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", v.replace('"', "\"\""))
+    } else {
+        v.to_owned()
+    }
+}
+
+const SESSION_EXPORT_VERSION: u32 = 1;
+
+///State of a persistent session taken out of a broker, to be imported on another node or broker
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SessionExport {
+    pub offline_info: SessionOfflineInfo,
+    pub conn_info: ConnectInfo,
+    pub attrs: SessionAttrs,
+    pub superuser: bool,
+}
+
+impl SessionExport {
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::json!({
+            "clientid": self.offline_info.id.client_id,
+            "username": self.offline_info.id.username(),
+            "subscriptions": self.offline_info.subscriptions.len(),
+            "offline_messages": self.offline_info.offline_messages.len(),
+            "inflight_messages": self.offline_info.inflight_messages.len(),
+            "data": self.encode()?,
+        }))
+    }
+
+    ///Base64 of the format version followed by the bincode of the session
+    #[inline]
+    pub fn encode(&self) -> Result<String> {
+        let data = bincode::serialize(&(SESSION_EXPORT_VERSION, self)).map_err(anyhow::Error::new)?;
+        Ok(BASE64_STANDARD.encode(data))
+    }
+
+    #[inline]
+    pub fn decode(data: &str) -> Result<Self> {
+        let data = BASE64_STANDARD.decode(data.trim()).map_err(anyhow::Error::new)?;
+        let (version, export) =
+            bincode::deserialize::<(u32, SessionExport)>(&data).map_err(anyhow::Error::new)?;
+        if version != SESSION_EXPORT_VERSION {
+            return Err(MqttError::Msg(format!("unsupported session export version {}", version)));
+        }
+        Ok(export)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SessionImportParams {
+    //"data" of the export
+    pub data: String,
+}
+
 #[inline]
 fn format_timestamp(t: i64) -> String {
     if t <= 0 {
@@ -0,0 +1,122 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::sync::Arc;
+
+use config::PluginConfig;
+use rmqtt::{async_trait::async_trait, log, serde_json, MqttError};
+use rmqtt::{
+    broker::default::DefaultKvStore,
+    broker::KvStore,
+    plugin::{PackageInfo, Plugin},
+    register, Result, Runtime,
+};
+use rmqtt_storage::{init_db, DefaultStorageDB, Map, StorageType};
+
+mod config;
+
+register!(KvStorePlugin::new);
+
+#[derive(Plugin)]
+struct KvStorePlugin {
+    runtime: &'static Runtime,
+    cfg: Arc<PluginConfig>,
+    storage_db: DefaultStorageDB,
+}
+
+impl KvStorePlugin {
+    #[inline]
+    async fn new<S: Into<String>>(runtime: &'static Runtime, name: S) -> Result<Self> {
+        let name = name.into();
+        let mut cfg = runtime.settings.plugins.load_config_default::<PluginConfig>(&name)?;
+        match cfg.storage.typ {
+            StorageType::Sled => {
+                cfg.storage.sled.path =
+                    cfg.storage.sled.path.replace("{node}", &format!("{}", runtime.node.id()));
+            }
+            StorageType::Redis => {
+                cfg.storage.redis.prefix =
+                    cfg.storage.redis.prefix.replace("{node}", &format!("{}", runtime.node.id()));
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(MqttError::from("unsupported storage type")),
+        }
+        log::info!("{} KvStorePlugin cfg: {:?}", name, cfg);
+
+        let storage_db = init_db(&cfg.storage).await?;
+        Ok(Self { runtime, cfg: Arc::new(cfg), storage_db })
+    }
+}
+
+#[async_trait]
+impl Plugin for KvStorePlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        Ok(self.cfg.to_json())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        *self.runtime.extends.kv_store_mut().await = Box::new(StorageKvStore { db: self.storage_db.clone() });
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        *self.runtime.extends.kv_store_mut().await = Box::new(DefaultKvStore::instance());
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        let storage_info = self.storage_db.info().await.unwrap_or_default();
+        serde_json::json!({
+            "storage_info": storage_info,
+        })
+    }
+}
+
+///Each namespace is stored as a map of the storage db
+struct StorageKvStore {
+    db: DefaultStorageDB,
+}
+
+#[async_trait]
+impl KvStore for StorageKvStore {
+    #[inline]
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let m = self.db.map(namespace, None).await?;
+        Ok(m.get::<_, Vec<u8>>(key).await?)
+    }
+
+    #[inline]
+    async fn set(&self, namespace: &str, key: &str, val: Vec<u8>) -> Result<()> {
+        let m = self.db.map(namespace, None).await?;
+        m.insert(key, &val).await?;
+        Ok(())
+    }
+
+    #[inline]
+    async fn remove(&self, namespace: &str, key: &str) -> Result<()> {
+        let m = self.db.map(namespace, None).await?;
+        m.remove(key).await?;
+        Ok(())
+    }
+
+    #[inline]
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
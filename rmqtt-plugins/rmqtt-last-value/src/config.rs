@@ -0,0 +1,88 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize};
+
+use rmqtt::serde_json;
+use rmqtt::{broker::topic::TopicTree, Result, Topic};
+
+type Topics = Arc<TopicTree<()>>;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(
+        default = "PluginConfig::topics_default",
+        serialize_with = "PluginConfig::serialize_topics",
+        deserialize_with = "PluginConfig::deserialize_topics"
+    )]
+    pub topics: (Topics, Vec<String>),
+
+    #[serde(default = "PluginConfig::max_topics_default")]
+    pub max_topics: usize,
+
+    #[serde(default = "PluginConfig::query_topic_default")]
+    pub query_topic: String,
+
+    #[serde(default = "PluginConfig::reply_topic_default")]
+    pub reply_topic: String,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn topics_default() -> (Topics, Vec<String>) {
+        let mut tree = TopicTree::default();
+        if let Ok(t) = Topic::from_str("#") {
+            tree.insert(&t, ());
+        }
+        (Arc::new(tree), vec!["#".into()])
+    }
+
+    #[inline]
+    fn max_topics_default() -> usize {
+        100_000
+    }
+
+    #[inline]
+    fn query_topic_default() -> String {
+        "$lastvalue/query".into()
+    }
+
+    #[inline]
+    fn reply_topic_default() -> String {
+        "$lastvalue/reply/${clientid}".into()
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    ///Whether the last value of the topic should be cached
+    #[inline]
+    pub fn is_cached(&self, topic: &Topic) -> bool {
+        self.topics.0.is_match(topic)
+    }
+
+    #[inline]
+    fn serialize_topics<S>(topics: &(Topics, Vec<String>), s: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        topics.1.serialize(s)
+    }
+
+    #[inline]
+    fn deserialize_topics<'de, D>(deserializer: D) -> std::result::Result<(Topics, Vec<String>), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let topics = Vec::<String>::deserialize(deserializer)?;
+        let mut tree = TopicTree::default();
+        for topic_filter in topics.iter() {
+            let t = Topic::from_str(topic_filter).map_err(|e| de::Error::custom(format!("{:?}", e)))?;
+            tree.insert(&t, ());
+        }
+        Ok((Arc::new(tree), topics))
+    }
+}
@@ -0,0 +1,264 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use rmqtt::{
+    async_trait::async_trait,
+    base64::prelude::{Engine, BASE64_STANDARD},
+    log,
+    serde_json::{self, json},
+    tokio::spawn,
+    tokio::sync::RwLock,
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
+    broker::retain::RetainTree,
+    broker::types::{From, Id, QoSEx},
+    plugin::{PackageInfo, Plugin},
+    register, timestamp_millis, ClientId, MqttError, Publish, PublishAclResult, Result, Runtime, Session,
+    SessionState, Subscribe, SubscriptionOptions, TimestampMillis, Topic, TopicFilter, TopicName, UserName,
+};
+
+use config::PluginConfig;
+
+mod config;
+
+type Cache = Arc<RwLock<LastValues>>;
+
+register!(LastValuePlugin::new);
+
+#[derive(Plugin)]
+struct LastValuePlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: Arc<RwLock<PluginConfig>>,
+    cache: Cache,
+}
+
+impl LastValuePlugin {
+    #[inline]
+    async fn new<N: Into<String>>(runtime: &'static Runtime, name: N) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config::<PluginConfig>(&name)?;
+        let cfg = Arc::new(RwLock::new(cfg));
+        log::info!("{} LastValuePlugin cfg: {:?}", name, cfg.read().await);
+        let register = runtime.extends.hook_mgr().await.register();
+        let cache = Arc::new(RwLock::new(LastValues::default()));
+        Ok(Self { runtime, register, cfg, cache })
+    }
+}
+
+#[async_trait]
+impl Plugin for LastValuePlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        //Executed last, after topic rewriting and other modifications
+        self.register
+            .add_priority(Type::MessagePublish, 0, Box::new(LastValueHandler::new(&self.cfg, &self.cache)))
+            .await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        *self.cfg.write().await = new_cfg;
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.register.start().await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        *self.cache.write().await = LastValues::default();
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        json!({
+            "topics": self.cache.read().await.count,
+        })
+    }
+
+    ///Query the last values, message format: {"topic_filter": "foo/#"}
+    #[inline]
+    async fn send(&self, msg: serde_json::Value) -> Result<serde_json::Value> {
+        let topic_filter = msg
+            .get("topic_filter")
+            .and_then(|tf| tf.as_str())
+            .ok_or_else(|| MqttError::from("topic_filter is required"))?;
+        Ok(serde_json::Value::Array(self.cache.read().await.query(topic_filter)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LastValue {
+    from: From,
+    publish: Publish,
+    update_time: TimestampMillis,
+}
+
+#[derive(Default)]
+struct LastValues {
+    tree: RetainTree<LastValue>,
+    count: usize,
+}
+
+impl LastValues {
+    #[inline]
+    fn update(&mut self, topic: &Topic, from: &From, publish: &Publish, max_topics: usize) {
+        let existed = self.tree.remove(topic).is_some();
+        if existed {
+            self.count -= 1;
+        }
+        //An empty payload clears the cached value
+        if publish.payload.is_empty() {
+            return;
+        }
+        if max_topics > 0 && self.count >= max_topics {
+            log::warn!("last value cache is full, max_topics: {}, topic: {}", max_topics, publish.topic);
+            return;
+        }
+        let lv = LastValue { from: from.clone(), publish: publish.clone(), update_time: timestamp_millis() };
+        self.tree.insert(topic, lv);
+        self.count += 1;
+    }
+
+    #[inline]
+    fn query(&self, topic_filter: &str) -> Result<Vec<serde_json::Value>> {
+        let tf = Topic::from_str(topic_filter)?;
+        Ok(self
+            .tree
+            .matches(&tf)
+            .into_iter()
+            .map(|(topic, lv)| {
+                json!({
+                    "topic": topic.to_string(),
+                    "qos": lv.publish.qos.value(),
+                    "payload": BASE64_STANDARD.encode(&lv.publish.payload),
+                    "clientid": lv.from.client_id,
+                    "username": lv.from.username_ref(),
+                    "pts": lv.publish.create_time,
+                    "ts": lv.update_time,
+                })
+            })
+            .collect())
+    }
+}
+
+struct LastValueHandler {
+    cfg: Arc<RwLock<PluginConfig>>,
+    cache: Cache,
+}
+
+impl LastValueHandler {
+    fn new(cfg: &Arc<RwLock<PluginConfig>>, cache: &Cache) -> Self {
+        Self { cfg: cfg.clone(), cache: cache.clone() }
+    }
+
+    #[inline]
+    async fn reply(&self, s: &Session, query: &Publish) -> Result<()> {
+        let id = &s.id;
+        let topic_filter = std::str::from_utf8(&query.payload).map_err(|e| MqttError::from(e.to_string()))?;
+        let topic_filter = topic_filter.trim();
+        let hook_mgr = Runtime::instance().extends.hook_mgr().await;
+
+        //Only the values the client could receive by subscribing to the topic filter
+        let sub =
+            Subscribe { topic_filter: TopicFilter::from(topic_filter), opts: SubscriptionOptions::default() };
+        let allowed = match hook_mgr.client_subscribe_check_acl(s, &sub).await {
+            Some(acl_result) => acl_result.success().is_some(),
+            None => !(s.listen_cfg().wildcard_subscription.multi_level_acl && topic_filter.contains('#')),
+        };
+        if !allowed {
+            return Err(MqttError::from(format!("query of {} is not authorized", topic_filter)));
+        }
+        let values = self.cache.read().await.query(topic_filter)?;
+
+        let reply_topic = if let Some(response_topic) = query.response_topic() {
+            response_topic.clone()
+        } else {
+            TopicName::from(self.cfg.read().await.reply_topic.replace("${clientid}", &id.client_id))
+        };
+
+        let mut p = Publish::builder()
+            .qos(query.qos)
+            .topic(reply_topic)
+            .payload(serde_json::to_vec(&values)?)
+            .build();
+        p.set_correlation_data(query.correlation_data().cloned());
+
+        //The reply is sent by the system, a response topic chosen by the client must pass its publish ACL
+        if query.response_topic().is_some() {
+            if let PublishAclResult::Rejected(_) = hook_mgr.message_publish_check_acl(s, &p).await {
+                return Err(MqttError::from(format!("reply to {} is not authorized", p.topic)));
+            }
+        }
+
+        let from = From::from_system(Id::new(
+            id.node_id,
+            None,
+            None,
+            ClientId::from_static("system"),
+            Some(UserName::from("system")),
+        ));
+        spawn(async move {
+            if let Err(e) = SessionState::forwards(from, p, false, false, None).await {
+                log::warn!("last value reply error, {:?}", e);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Handler for LastValueHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        if let Parameter::MessagePublish(s, from, publish) = param {
+            //The message may have been modified by the previous handlers
+            let publish = if let Some(HookResult::Publish(p)) = &acc { p } else { *publish };
+
+            let (is_query, max_topics, is_cached) = {
+                let cfg = self.cfg.read().await;
+                let is_cached = !publish.topic.starts_with('$')
+                    && Topic::from_str(&publish.topic).map(|t| cfg.is_cached(&t)).unwrap_or_default();
+                (publish.topic == cfg.query_topic, cfg.max_topics, is_cached)
+            };
+
+            if is_query {
+                if let Some(s) = s {
+                    if let Err(e) = self.reply(s, publish).await {
+                        log::warn!("{:?} last value query error, {:?}", s.id, e);
+                    }
+                }
+            } else if is_cached {
+                match Topic::from_str(&publish.topic) {
+                    Ok(t) => self.cache.write().await.update(&t, from, publish, max_topics),
+                    Err(e) => log::warn!("{:?} topic format error, {:?}", from.id, e),
+                }
+            }
+        }
+        (Proceed::Continue, acc)
+    }
+}
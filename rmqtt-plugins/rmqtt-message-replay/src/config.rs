@@ -0,0 +1,117 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize};
+
+use rmqtt::serde_json;
+use rmqtt::{
+    broker::topic::TopicTree,
+    settings::{deserialize_duration, Bytesize},
+    Result, Topic,
+};
+
+type Topics = Arc<TopicTree<()>>;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    #[serde(default = "PluginConfig::storage_path_default")]
+    pub storage_path: String,
+
+    #[serde(
+        default,
+        serialize_with = "PluginConfig::serialize_topics",
+        deserialize_with = "PluginConfig::deserialize_topics"
+    )]
+    pub topics: (Topics, Vec<String>),
+
+    #[serde(default = "PluginConfig::segment_size_default")]
+    pub segment_size: Bytesize,
+
+    #[serde(default = "PluginConfig::retention_time_default", deserialize_with = "deserialize_duration")]
+    pub retention_time: Duration,
+
+    #[serde(default = "PluginConfig::retention_size_default")]
+    pub retention_size: Bytesize,
+
+    #[serde(default = "PluginConfig::cleanup_interval_default", deserialize_with = "deserialize_duration")]
+    pub cleanup_interval: Duration,
+
+    #[serde(default = "PluginConfig::max_replay_messages_default")]
+    pub max_replay_messages: usize,
+
+    ///Messages waiting to be written to the log, they are dropped while the queue is full
+    #[serde(default = "PluginConfig::write_queue_size_default")]
+    pub write_queue_size: usize,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn storage_path_default() -> String {
+        "/var/log/rmqtt/.cache/replay/{node}".into()
+    }
+
+    #[inline]
+    fn segment_size_default() -> Bytesize {
+        Bytesize::from(64 * 1024 * 1024)
+    }
+
+    #[inline]
+    fn retention_time_default() -> Duration {
+        Duration::from_secs(60 * 60 * 24)
+    }
+
+    #[inline]
+    fn retention_size_default() -> Bytesize {
+        Bytesize::from(1024 * 1024 * 1024)
+    }
+
+    #[inline]
+    fn cleanup_interval_default() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    #[inline]
+    fn max_replay_messages_default() -> usize {
+        1000
+    }
+
+    #[inline]
+    fn write_queue_size_default() -> usize {
+        100_000
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    ///Whether messages published to the topic are written to the log
+    #[inline]
+    pub fn is_logged(&self, topic: &Topic) -> bool {
+        self.topics.0.is_match(topic)
+    }
+
+    #[inline]
+    fn serialize_topics<S>(topics: &(Topics, Vec<String>), s: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        topics.1.serialize(s)
+    }
+
+    #[inline]
+    fn deserialize_topics<'de, D>(deserializer: D) -> std::result::Result<(Topics, Vec<String>), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let topics = Vec::<String>::deserialize(deserializer)?;
+        let mut tree = TopicTree::default();
+        for topic_filter in topics.iter() {
+            let t = Topic::from_str(topic_filter).map_err(|e| de::Error::custom(format!("{:?}", e)))?;
+            tree.insert(&t, ());
+        }
+        Ok((Arc::new(tree), topics))
+    }
+}
@@ -0,0 +1,365 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rmqtt::{
+    async_trait::async_trait,
+    base64::prelude::{Engine, BASE64_STANDARD},
+    crossbeam::channel::{self, RecvTimeoutError, Sender, TrySendError},
+    dashmap::DashMap,
+    log,
+    serde_json::{self, json},
+    tokio::{spawn, sync::RwLock, task::spawn_blocking},
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Priority, Proceed, Register, ReturnType, Type},
+    broker::topic::TopicTree,
+    broker::types::{QoSEx, SubscribeAckReason, SubscribeAclResult},
+    plugin::{PackageInfo, Plugin},
+    register,
+    settings::try_to_duration,
+    timestamp_millis, ClientId, Id, MqttError, QoS, Result, Runtime, TimestampMillis, Topic, TopicFilter,
+};
+
+use config::PluginConfig;
+use segment::{MessageLog, Record};
+
+mod config;
+mod segment;
+
+///Subscribe to `$replay/{Duration}/{TopicFilter}` to receive the messages of the last `Duration`
+const REPLAY_PREFIX: &str = "$replay/";
+
+//A subscribe is handled at once, at most one replay subscription of a client is pending,
+//it is removed when the subscription is done or the session is terminated
+type PendingReplays = Arc<DashMap<ClientId, (Id, TopicFilter, TimestampMillis)>>;
+
+register!(MessageReplayPlugin::new);
+
+#[derive(Plugin)]
+struct MessageReplayPlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: Arc<RwLock<PluginConfig>>,
+    msg_log: MessageLog,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl MessageReplayPlugin {
+    #[inline]
+    async fn new<N: Into<String>>(runtime: &'static Runtime, name: N) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config::<PluginConfig>(&name)?;
+        log::info!("{} MessageReplayPlugin cfg: {:?}", name, cfg);
+        let dir = PathBuf::from(cfg.storage_path.replace("{node}", &format!("{}", runtime.node.id())));
+        let msg_log = MessageLog::open(
+            dir,
+            cfg.segment_size.as_u64(),
+            cfg.retention_time,
+            cfg.retention_size.as_u64(),
+        )?;
+        let cfg = Arc::new(RwLock::new(cfg));
+        let register = runtime.extends.hook_mgr().await.register();
+        Ok(Self { runtime, register, cfg, msg_log, dropped: Arc::new(AtomicUsize::new(0)) })
+    }
+
+    fn start_writer(msg_log: MessageLog, cleanup_interval: Duration, queue_size: usize) -> Sender<Record> {
+        let (tx, rx) = channel::bounded::<Record>(queue_size);
+        std::thread::spawn(move || {
+            let mut last_cleanup = Instant::now();
+            loop {
+                match rx.recv_timeout(Duration::from_secs(1)) {
+                    Ok(rec) => {
+                        if let Err(e) = msg_log.append(&rec) {
+                            log::warn!("append message log error, {:?}", e);
+                        }
+                        if !rx.is_empty() {
+                            continue;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if let Err(e) = msg_log.flush() {
+                    log::warn!("flush message log error, {:?}", e);
+                }
+                if last_cleanup.elapsed() >= cleanup_interval {
+                    last_cleanup = Instant::now();
+                    match msg_log.cleanup() {
+                        Ok(removeds) if removeds > 0 => log::info!("removed {} expired segments", removeds),
+                        Ok(_) => {}
+                        Err(e) => log::warn!("cleanup message log error, {:?}", e),
+                    }
+                }
+            }
+            let _ = msg_log.flush();
+        });
+        tx
+    }
+}
+
+#[async_trait]
+impl Plugin for MessageReplayPlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        let (cleanup_interval, write_queue_size) = {
+            let cfg = self.cfg.read().await;
+            (cfg.cleanup_interval, cfg.write_queue_size)
+        };
+        let tx = Self::start_writer(self.msg_log.clone(), cleanup_interval, write_queue_size);
+        let pendings = PendingReplays::default();
+        let handler = || -> Box<dyn Handler> {
+            Box::new(MessageReplayHandler::new(&self.cfg, &self.msg_log, &tx, &pendings, &self.dropped))
+        };
+        //Executed last, after topic rewriting and other modifications
+        self.register.add_priority(Type::MessagePublish, 0, handler()).await;
+        self.register.add(Type::ClientSubscribe, handler()).await;
+        self.register.add_priority(Type::ClientSubscribeCheckAcl, Priority::MAX, handler()).await;
+        self.register.add(Type::SessionSubscribed, handler()).await;
+        self.register.add(Type::SessionTerminated, handler()).await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.register.start().await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        json!({
+            "dropped": self.dropped.load(Ordering::SeqCst),
+        })
+    }
+
+    ///Query logged messages, message format: {"topic_filter": "foo/#", "since": "10m", "limit": 100}
+    #[inline]
+    async fn send(&self, msg: serde_json::Value) -> Result<serde_json::Value> {
+        let topic_filter = msg
+            .get("topic_filter")
+            .and_then(|tf| tf.as_str())
+            .ok_or_else(|| MqttError::from("topic_filter is required"))?;
+        let since = match msg.get("since").and_then(|s| s.as_str()) {
+            Some(since) => since_timestamp(since)?,
+            None => timestamp_millis(),
+        };
+        let limit = msg
+            .get("limit")
+            .and_then(|l| l.as_u64())
+            .map(|l| l as usize)
+            .unwrap_or(self.cfg.read().await.max_replay_messages);
+        let recs = load(self.msg_log.clone(), topic_filter, since, limit).await?;
+        Ok(serde_json::Value::Array(
+            recs.into_iter()
+                .map(|rec| {
+                    json!({
+                        "topic": rec.publish.topic,
+                        "qos": rec.publish.qos.value(),
+                        "payload": BASE64_STANDARD.encode(&rec.publish.payload),
+                        "clientid": rec.from.client_id,
+                        "username": rec.from.username_ref(),
+                        "ts": rec.ts,
+                    })
+                })
+                .collect(),
+        ))
+    }
+}
+
+///The time `since` ago, such as "10m"
+fn since_timestamp(since: &str) -> Result<TimestampMillis> {
+    let since =
+        TimestampMillis::try_from(try_to_duration(since)?.as_millis()).unwrap_or(TimestampMillis::MAX);
+    Ok(timestamp_millis().saturating_sub(since))
+}
+
+///Loads the latest `limit` records matching the topic filter, written at or after `since`
+async fn load(
+    msg_log: MessageLog,
+    topic_filter: &str,
+    since: TimestampMillis,
+    limit: usize,
+) -> Result<Vec<Record>> {
+    let mut filter = TopicTree::default();
+    filter.insert(&Topic::from_str(topic_filter)?, ());
+    spawn_blocking(move || {
+        let mut recs = VecDeque::new();
+        msg_log.read(since, |rec| {
+            if Topic::from_str(&rec.publish.topic).map(|t| filter.is_match(&t)).unwrap_or_default() {
+                if recs.len() >= limit {
+                    recs.pop_front();
+                }
+                recs.push_back(rec);
+            }
+        })?;
+        Ok(recs.into_iter().collect())
+    })
+    .await?
+}
+
+struct MessageReplayHandler {
+    cfg: Arc<RwLock<PluginConfig>>,
+    msg_log: MessageLog,
+    tx: Sender<Record>,
+    pendings: PendingReplays,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl MessageReplayHandler {
+    fn new(
+        cfg: &Arc<RwLock<PluginConfig>>,
+        msg_log: &MessageLog,
+        tx: &Sender<Record>,
+        pendings: &PendingReplays,
+        dropped: &Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            msg_log: msg_log.clone(),
+            tx: tx.clone(),
+            pendings: pendings.clone(),
+            dropped: dropped.clone(),
+        }
+    }
+
+    #[inline]
+    async fn replay(
+        msg_log: MessageLog,
+        id: Id,
+        topic_filter: TopicFilter,
+        qos: QoS,
+        since: TimestampMillis,
+        limit: usize,
+    ) {
+        let recs = match load(msg_log, &topic_filter, since, limit).await {
+            Ok(recs) => recs,
+            Err(e) => {
+                log::warn!("{:?} load replay messages error, {:?}", id, e);
+                return;
+            }
+        };
+        log::debug!("{:?} replay {} messages, topic_filter: {}", id, recs.len(), topic_filter);
+        for Record { from, mut publish, .. } in recs {
+            publish.dup = false;
+            publish.retain = false;
+            publish.qos = publish.qos.less_value(qos);
+            publish.packet_id = None;
+            if let Err((from, p, reason)) =
+                Runtime::instance().extends.shared().await.entry(id.clone()).publish(from, publish).await
+            {
+                Runtime::instance()
+                    .extends
+                    .hook_mgr()
+                    .await
+                    .message_dropped(Some(id.clone()), from, p, reason)
+                    .await;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for MessageReplayHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        match param {
+            Parameter::MessagePublish(_s, from, publish) => {
+                //The message may have been modified by the previous handlers
+                let publish = if let Some(HookResult::Publish(p)) = &acc { p } else { *publish };
+                if publish.topic.starts_with('$') {
+                    return (Proceed::Continue, acc);
+                }
+                let logged = match Topic::from_str(&publish.topic) {
+                    Ok(t) => self.cfg.read().await.is_logged(&t),
+                    Err(_) => false,
+                };
+                if logged {
+                    let rec = Record { ts: timestamp_millis(), from: from.clone(), publish: publish.clone() };
+                    match self.tx.try_send(rec) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            self.dropped.fetch_add(1, Ordering::SeqCst);
+                            log::debug!("{:?} message log write queue is full, message dropped", from.id);
+                        }
+                        Err(TrySendError::Disconnected(_)) => {
+                            log::warn!("{:?} write message log error, the writer is stopped", from.id);
+                        }
+                    }
+                }
+            }
+            Parameter::ClientSubscribe(s, subscribe) => {
+                if let Some(rest) = subscribe.topic_filter.strip_prefix(REPLAY_PREFIX) {
+                    let since = rest.split_once('/').map(|(since, tf)| (since_timestamp(since), tf));
+                    match since {
+                        Some((Ok(since), topic_filter)) => {
+                            let topic_filter = TopicFilter::from(topic_filter);
+                            self.pendings
+                                .insert(s.id.client_id.clone(), (s.id.clone(), topic_filter.clone(), since));
+                            return (Proceed::Continue, Some(HookResult::TopicFilter(Some(topic_filter))));
+                        }
+                        //the topic filter is kept and refused in ClientSubscribeCheckAcl
+                        Some((Err(e), _)) => log::info!("{:?} {}, {}", s.id, e, subscribe.topic_filter),
+                        None => {}
+                    }
+                }
+            }
+            Parameter::ClientSubscribeCheckAcl(_s, subscribe) => {
+                if subscribe.topic_filter.starts_with(REPLAY_PREFIX) {
+                    return (
+                        Proceed::Stop,
+                        Some(HookResult::SubscribeAclResult(SubscribeAclResult::new_failure(
+                            SubscribeAckReason::TopicFilterInvalid,
+                        ))),
+                    );
+                }
+            }
+            Parameter::SessionTerminated(s, _) => {
+                self.pendings.remove_if(&s.id.client_id, |_, (id, _, _)| *id == s.id);
+            }
+            Parameter::SessionSubscribed(s, subscribe) => {
+                if let Some((_, (_, _, since))) = self
+                    .pendings
+                    .remove_if(&s.id.client_id, |_, (id, tf, _)| *id == s.id && *tf == subscribe.topic_filter)
+                {
+                    let limit = self.cfg.read().await.max_replay_messages;
+                    spawn(Self::replay(
+                        self.msg_log.clone(),
+                        s.id.clone(),
+                        subscribe.topic_filter.clone(),
+                        subscribe.opts.qos(),
+                        since,
+                        limit,
+                    ));
+                }
+            }
+            _ => {
+                log::error!("unimplemented, {:?}", param)
+            }
+        }
+        (Proceed::Continue, acc)
+    }
+}
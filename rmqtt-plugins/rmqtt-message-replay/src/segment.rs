@@ -0,0 +1,210 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use rmqtt::{
+    anyhow::anyhow, bincode, log, timestamp_millis, From, MqttError, Publish, Result, TimestampMillis,
+};
+
+const SEGMENT_EXT: &str = "seg";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Record {
+    pub ts: TimestampMillis,
+    pub from: From,
+    pub publish: Publish,
+}
+
+struct Segment {
+    base_ts: TimestampMillis,
+    last_ts: TimestampMillis,
+    path: PathBuf,
+    size: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    segments: Vec<Segment>,
+    writer: Option<BufWriter<File>>,
+}
+
+///Append-only message log, split into segment files named by the timestamp of their first record.
+///Each record is stored as a little-endian u32 length followed by the bincode encoded `Record`.
+#[derive(Clone)]
+pub(crate) struct MessageLog {
+    dir: PathBuf,
+    segment_size: u64,
+    retention_time: Duration,
+    retention_size: u64,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MessageLog {
+    pub(crate) fn open(
+        dir: PathBuf,
+        segment_size: u64,
+        retention_time: Duration,
+        retention_size: u64,
+    ) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXT) {
+                continue;
+            }
+            let base_ts = match path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                Some(base_ts) => base_ts,
+                None => {
+                    log::warn!("ignore unrecognized segment file, {:?}", path);
+                    continue;
+                }
+            };
+            let meta = fs::metadata(&path)?;
+            let last_ts = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as TimestampMillis)
+                .unwrap_or(base_ts);
+            segments.push(Segment { base_ts, last_ts, path, size: meta.len() });
+        }
+        segments.sort_by_key(|s| s.base_ts);
+        log::info!("message log opened, dir: {:?}, segments: {}", dir, segments.len());
+        Ok(Self {
+            dir,
+            segment_size,
+            retention_time,
+            retention_size,
+            inner: Arc::new(Mutex::new(Inner { segments, writer: None })),
+        })
+    }
+
+    #[inline]
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Inner>> {
+        self.inner.lock().map_err(|e| MqttError::from(e.to_string()))
+    }
+
+    pub(crate) fn append(&self, rec: &Record) -> Result<()> {
+        let data = bincode::serialize(rec).map_err(|e| anyhow!(e))?;
+        let mut inner = self.lock()?;
+
+        let full = inner.segments.last().map(|s| s.size >= self.segment_size).unwrap_or(true);
+        if inner.writer.is_none() || full {
+            //Always start a new segment after startup, existing segments are never appended
+            if let Some(mut w) = inner.writer.take() {
+                w.flush()?;
+            }
+            let path = self.dir.join(format!("{:020}.{}", rec.ts, SEGMENT_EXT));
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            inner.writer = Some(BufWriter::new(file));
+            inner.segments.push(Segment { base_ts: rec.ts, last_ts: rec.ts, path, size: 0 });
+        }
+
+        if let Some(w) = inner.writer.as_mut() {
+            w.write_all(&(data.len() as u32).to_le_bytes())?;
+            w.write_all(&data)?;
+        }
+        if let Some(s) = inner.segments.last_mut() {
+            s.size += 4 + data.len() as u64;
+            s.last_ts = rec.ts;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush(&self) -> Result<()> {
+        if let Some(w) = self.lock()?.writer.as_mut() {
+            w.flush()?;
+        }
+        Ok(())
+    }
+
+    ///Reads all records written at or after `since`, in write order.
+    pub(crate) fn read<F>(&self, since: TimestampMillis, mut f: F) -> Result<()>
+    where
+        F: FnMut(Record),
+    {
+        let paths = {
+            let mut inner = self.lock()?;
+            if let Some(w) = inner.writer.as_mut() {
+                w.flush()?;
+            }
+            inner.segments.iter().filter(|s| s.last_ts >= since).map(|s| s.path.clone()).collect::<Vec<_>>()
+        };
+
+        for path in paths {
+            let (mut reader, mut remaining) =
+                match File::open(&path).and_then(|f| Ok((f.metadata()?.len(), f))) {
+                    Ok((len, file)) => (BufReader::new(file), len),
+                    //Removed by the cleanup
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+            let mut len_buf = [0u8; 4];
+            loop {
+                match reader.read_exact(&mut len_buf) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e.into()),
+                }
+                remaining = remaining.saturating_sub(len_buf.len() as u64);
+                let len = u32::from_le_bytes(len_buf) as u64;
+                //A corrupt length, the rest of the segment is skipped
+                if len > remaining {
+                    log::warn!(
+                        "invalid record length {}, {} bytes remaining, segment: {:?}",
+                        len,
+                        remaining,
+                        path
+                    );
+                    break;
+                }
+                remaining -= len;
+                let mut data = vec![0u8; len as usize];
+                match reader.read_exact(&mut data) {
+                    Ok(()) => {}
+                    //Truncated record, the end of a segment that was not closed normally
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e.into()),
+                }
+                match bincode::deserialize::<Record>(&data) {
+                    Ok(rec) if rec.ts >= since => f(rec),
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::warn!("decode record error, {:?}, segment: {:?}", e, path);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Removes segments older than the retention time and the oldest segments exceeding the retention size.
+    pub(crate) fn cleanup(&self) -> Result<usize> {
+        let mut inner = self.lock()?;
+        let expired_ts = timestamp_millis() - self.retention_time.as_millis() as TimestampMillis;
+        let mut total_size: u64 = inner.segments.iter().map(|s| s.size).sum();
+        let mut removeds = 0;
+        while let Some(s) = inner.segments.first() {
+            let is_current = inner.segments.len() == 1 && inner.writer.is_some();
+            let expired = self.retention_time.as_millis() > 0 && s.last_ts < expired_ts;
+            let oversize = self.retention_size > 0 && total_size > self.retention_size && !is_current;
+            if !expired && !oversize {
+                break;
+            }
+            let s = inner.segments.remove(0);
+            if is_current {
+                inner.writer.take();
+            }
+            total_size -= s.size;
+            if let Err(e) = fs::remove_file(&s.path) {
+                log::warn!("remove segment error, {:?}, {:?}", s.path, e);
+            }
+            removeds += 1;
+        }
+        Ok(removeds)
+    }
+}
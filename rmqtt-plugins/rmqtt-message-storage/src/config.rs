@@ -1,3 +1,4 @@
+use rmqtt::broker::encryption::PayloadCipher;
 use rmqtt::serde_json;
 use rmqtt::settings::Bytesize;
 use serde::de::{self, Deserialize, Deserializer};
@@ -9,6 +10,9 @@ pub struct PluginConfig {
     pub storage: Config,
     #[serde(default = "PluginConfig::cleanup_count_default")]
     pub cleanup_count: usize,
+    //Payload encryption of the stored messages, messages kept in ram are not encrypted.
+    #[serde(default)]
+    pub encryption: PayloadCipher,
 }
 
 impl PluginConfig {
@@ -25,8 +25,8 @@ use rmqtt::{
 };
 
 use rmqtt::{
-    broker::retain::RetainTree, broker::MessageManager, ClientId, From, MqttError, MsgID, Publish, Result,
-    SharedGroup, StoredMessage, Topic, TopicFilter,
+    broker::encryption::PayloadCipher, broker::retain::RetainTree, broker::MessageManager, ClientId, From,
+    MqttError, MsgID, Publish, Result, SharedGroup, StoredMessage, Topic, TopicFilter,
 };
 
 use rmqtt::tokio::runtime::Handle;
@@ -82,6 +82,7 @@ impl StorageMessageManager {
         let messages_received_max =
             StorageMessageManagerInner::storage_new_messages_counter(&storage_db).await?;
         log::info!("messages_received_max: {}", messages_received_max.load(Ordering::SeqCst));
+        let cipher = cfg.encryption.clone();
         let (exec, msg_tx, msg_queue_count) = Self::serve(cfg)?;
 
         let inner = Arc::new(StorageMessageManagerInner {
@@ -93,6 +94,7 @@ impl StorageMessageManager {
             msg_queue_count,
             id_generater,
             should_merge_on_get,
+            cipher,
         });
         Ok(Self { inner, exec })
     }
@@ -230,6 +232,7 @@ pub struct StorageMessageManagerInner {
 
     id_generater: AtomicUsize,
     should_merge_on_get: bool,
+    cipher: PayloadCipher,
 }
 
 impl StorageMessageManagerInner {
@@ -331,7 +334,7 @@ impl StorageMessageManagerInner {
             .storage_save_msg_id()
             .timeout(futures_time::time::Duration::from_millis(5000))
             .await
-            .map_err(|_e| MqttError::from("storage_save_msg_id timeout"))?
+            .map_err(|_e| MqttError::Storage("storage_save_msg_id timeout".into()))?
         {
             log::warn!("save message id error, {:?}", e);
             return Ok(());
@@ -348,6 +351,13 @@ impl StorageMessageManagerInner {
             };
             let expiry_time_at = timestamp_millis() + expiry_interval.as_millis() as i64;
 
+            let publish = match self.cipher.encrypt_publish(publish) {
+                Ok(publish) => publish,
+                Err(e) => {
+                    log::warn!("store to db error, {:?}, msg_id: {:?}", e, msg_id);
+                    continue;
+                }
+            };
             let smsg = StoredMessage { msg_id, from, publish, expiry_time_at };
 
             //received messages
@@ -357,7 +367,7 @@ impl StorageMessageManagerInner {
                 .map(msg_key, Some(expiry_interval.as_millis() as TimestampMillis))
                 .timeout(futures_time::time::Duration::from_millis(5000))
                 .await
-                .map_err(|_e| MqttError::from("storage_db.map timeout"))?
+                .map_err(|_e| MqttError::Storage("storage_db.map timeout".into()))?
             {
                 Ok(map) => map,
                 Err(e) => {
@@ -369,7 +379,7 @@ impl StorageMessageManagerInner {
                 .insert(DATA, &smsg)
                 .timeout(futures_time::time::Duration::from_millis(5000))
                 .await
-                .map_err(|_e| MqttError::from("map.insert timeout"))?
+                .map_err(|_e| MqttError::Storage("map.insert timeout".into()))?
             {
                 log::warn!("store to db error, {:?}, message: {:?}", e, smsg);
                 continue;
@@ -391,7 +401,7 @@ impl StorageMessageManagerInner {
             .storage_messages_counter_add(count)
             .timeout(futures_time::time::Duration::from_millis(5000))
             .await
-            .map_err(|_e| MqttError::from("storage_messages_counter_add timeout"))?
+            .map_err(|_e| MqttError::Storage("storage_messages_counter_add timeout".into()))?
         {
             log::warn!("messages_received_counter add error, {:?}", e);
         }
@@ -410,7 +420,7 @@ impl StorageMessageManagerInner {
                 .insert(Self::make_forwarded_key(&client_id), &opts)
                 .timeout(futures_time::time::Duration::from_millis(5000))
                 .await
-                .map_err(|_e| MqttError::from("_forwardeds insert timeout"))?
+                .map_err(|_e| MqttError::Storage("_forwardeds insert timeout".into()))?
             {
                 log::warn!(
                     "_forwardeds error, client_id: {:?}, msg_map name: {:?}, error: {:?}",
@@ -518,7 +528,13 @@ impl StorageMessageManagerInner {
 
     #[inline]
     async fn _get_message(&self, msg_map: &StorageMap) -> Result<Option<StoredMessage>> {
-        Ok(msg_map.get::<_, StoredMessage>(DATA).await?)
+        match msg_map.get::<_, StoredMessage>(DATA).await? {
+            Some(mut smsg) => {
+                smsg.publish = self.cipher.decrypt_publish(smsg.publish)?;
+                Ok(Some(smsg))
+            }
+            None => Ok(None),
+        }
     }
 }
 
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use jsonschema::JSONSchema;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize};
+
+use rmqtt::broker::hook::Priority;
+use rmqtt::serde_json;
+use rmqtt::{broker::topic::TopicTree, MqttError, Result, Topic};
+
+type Rules = Arc<(TopicTree<usize>, Vec<Rule>)>;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    ///Hook priority, rules are checked before the ACL plugins
+    #[serde(default = "PluginConfig::priority_default")]
+    pub priority: Priority,
+
+    #[serde(
+        default = "PluginConfig::rules_default",
+        serialize_with = "PluginConfig::serialize_rules",
+        deserialize_with = "PluginConfig::deserialize_rules"
+    )]
+    pub rules: (Rules, Vec<RuleConfig>),
+
+    ///Maximum number of topics whose violations are counted, 0 means unlimited
+    #[serde(default = "PluginConfig::max_topics_default")]
+    pub max_topics: usize,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn priority_default() -> Priority {
+        200
+    }
+
+    #[inline]
+    fn rules_default() -> (Rules, Vec<RuleConfig>) {
+        (Arc::new((TopicTree::default(), Vec::new())), Vec::new())
+    }
+
+    #[inline]
+    fn max_topics_default() -> usize {
+        10_000
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    ///Rules whose topic filter matches the topic, in configuration order
+    #[inline]
+    pub fn rules(&self, topic: &Topic) -> Vec<&Rule> {
+        let (tree, rules) = self.rules.0.as_ref();
+        let mut idxs = tree.matches(topic).iter().flat_map(|(_, idxs)| idxs).copied().collect::<Vec<_>>();
+        idxs.sort_unstable();
+        idxs.dedup();
+        idxs.into_iter().filter_map(|idx| rules.get(idx)).collect()
+    }
+
+    #[inline]
+    fn serialize_rules<S>(rules: &(Rules, Vec<RuleConfig>), s: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        rules.1.serialize(s)
+    }
+
+    #[inline]
+    fn deserialize_rules<'de, D>(deserializer: D) -> std::result::Result<(Rules, Vec<RuleConfig>), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let rule_cfgs = Vec::<RuleConfig>::deserialize(deserializer)?;
+        let mut tree = TopicTree::default();
+        let mut rules = Vec::new();
+        for (idx, rule_cfg) in rule_cfgs.iter().enumerate() {
+            let t =
+                Topic::from_str(&rule_cfg.topic_filter).map_err(|e| de::Error::custom(format!("{:?}", e)))?;
+            tree.insert(&t, idx);
+            rules.push(Rule::try_from(rule_cfg).map_err(de::Error::custom)?);
+        }
+        Ok((Arc::new((tree, rules)), rule_cfgs))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    ///Well-formed JSON
+    Json,
+    ///JSON valid against the schema of `schema_file`
+    JsonSchema,
+    ///Well-formed CBOR, a single data item
+    Cbor,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    ///The message is refused and dropped, it goes to the dead-letter topic if one is configured
+    #[default]
+    Reject,
+    ///The message is refused and the client is disconnected
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RuleConfig {
+    pub topic_filter: String,
+    pub format: Format,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_file: Option<String>,
+    #[serde(default)]
+    pub action: Action,
+}
+
+pub struct Rule {
+    pub topic_filter: String,
+    pub format: Format,
+    pub action: Action,
+    schema: Option<JSONSchema>,
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rule {{ topic_filter: {}, format: {:?}, action: {:?} }}",
+            self.topic_filter, self.format, self.action
+        )
+    }
+}
+
+impl TryFrom<&RuleConfig> for Rule {
+    type Error = MqttError;
+
+    #[inline]
+    fn try_from(cfg: &RuleConfig) -> Result<Self, Self::Error> {
+        let schema = match (cfg.format, cfg.schema_file.as_ref()) {
+            (Format::JsonSchema, Some(schema_file)) => {
+                let schema = std::fs::read(schema_file).map_err(|e| {
+                    MqttError::from(format!("read schema file {} error, {:?}", schema_file, e))
+                })?;
+                let schema = serde_json::from_slice::<serde_json::Value>(&schema)?;
+                let schema = JSONSchema::compile(&schema)
+                    .map_err(|e| MqttError::from(format!("invalid schema {}, {}", schema_file, e)))?;
+                Some(schema)
+            }
+            (Format::JsonSchema, None) => {
+                return Err(MqttError::from(format!(
+                    "schema_file is required by the json_schema rule of {}",
+                    cfg.topic_filter
+                )))
+            }
+            _ => None,
+        };
+        Ok(Self { topic_filter: cfg.topic_filter.clone(), format: cfg.format, action: cfg.action, schema })
+    }
+}
+
+impl Rule {
+    ///Checks the payload, the error describes the first violation
+    #[inline]
+    pub fn validate(&self, payload: &[u8]) -> std::result::Result<(), String> {
+        match self.format {
+            Format::Json => {
+                serde_json::from_slice::<serde_json::Value>(payload).map(|_| ()).map_err(|e| e.to_string())
+            }
+            Format::JsonSchema => {
+                let instance =
+                    serde_json::from_slice::<serde_json::Value>(payload).map_err(|e| e.to_string())?;
+                if let Some(schema) = &self.schema {
+                    if let Err(mut errs) = schema.validate(&instance) {
+                        return Err(errs.next().map(|e| e.to_string()).unwrap_or_default());
+                    }
+                }
+                Ok(())
+            }
+            Format::Cbor => {
+                let mut reader = payload;
+                ciborium::de::from_reader::<ciborium::value::Value, _>(&mut reader)
+                    .map_err(|e| e.to_string())?;
+                if reader.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("{} trailing bytes after the CBOR data item", reader.len()))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rmqtt::{
+    async_trait::async_trait,
+    log,
+    serde_json::{self, json},
+    tokio::sync::RwLock,
+    DashMap,
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
+    plugin::{PackageInfo, Plugin},
+    register, PublishAclResult, Result, Runtime, Topic, TopicName,
+};
+
+use config::{Action, PluginConfig};
+
+mod config;
+
+register!(PayloadValidationPlugin::new);
+
+#[derive(Plugin)]
+struct PayloadValidationPlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: Arc<RwLock<PluginConfig>>,
+    violations: Arc<Violations>,
+}
+
+impl PayloadValidationPlugin {
+    #[inline]
+    async fn new<N: Into<String>>(runtime: &'static Runtime, name: N) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config::<PluginConfig>(&name)?;
+        log::info!("{} PayloadValidationPlugin cfg: {:?}", name, cfg);
+        let cfg = Arc::new(RwLock::new(cfg));
+        let register = runtime.extends.hook_mgr().await.register();
+        Ok(Self { runtime, register, cfg, violations: Arc::new(Violations::default()) })
+    }
+}
+
+#[async_trait]
+impl Plugin for PayloadValidationPlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        let priority = self.cfg.read().await.priority;
+        self.register
+            .add_priority(
+                Type::MessagePublishCheckAcl,
+                priority,
+                Box::new(PayloadValidationHandler::new(&self.cfg, &self.violations)),
+            )
+            .await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        *self.cfg.write().await = new_cfg;
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.register.start().await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        Ok(true)
+    }
+
+    #[inline]
+    async fn attrs(&self) -> serde_json::Value {
+        self.violations.to_json()
+    }
+}
+
+///Number of invalid messages, in total and per topic
+#[derive(Default)]
+struct Violations {
+    total: AtomicUsize,
+    topics: DashMap<TopicName, usize>,
+}
+
+impl Violations {
+    #[inline]
+    fn inc(&self, topic: &TopicName, max_topics: usize) {
+        self.total.fetch_add(1, Ordering::SeqCst);
+        if let Some(mut count) = self.topics.get_mut(topic) {
+            *count += 1;
+        } else if max_topics == 0 || self.topics.len() < max_topics {
+            *self.topics.entry(topic.clone()).or_default() += 1;
+        }
+    }
+
+    #[inline]
+    fn to_json(&self) -> serde_json::Value {
+        let topics = self
+            .topics
+            .iter()
+            .map(|entry| (entry.key().to_string(), json!(*entry.value())))
+            .collect::<serde_json::Map<_, _>>();
+        json!({
+            "violations": self.total.load(Ordering::SeqCst),
+            "topics": topics,
+        })
+    }
+}
+
+struct PayloadValidationHandler {
+    cfg: Arc<RwLock<PluginConfig>>,
+    violations: Arc<Violations>,
+}
+
+impl PayloadValidationHandler {
+    fn new(cfg: &Arc<RwLock<PluginConfig>>, violations: &Arc<Violations>) -> Self {
+        Self { cfg: cfg.clone(), violations: violations.clone() }
+    }
+}
+
+#[async_trait]
+impl Handler for PayloadValidationHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        if let Parameter::MessagePublishCheckAcl(s, publish) = param {
+            if let Some(HookResult::PublishAclResult(PublishAclResult::Rejected(_))) = &acc {
+                return (Proceed::Stop, acc);
+            }
+
+            let t = match Topic::from_str(&publish.topic) {
+                Ok(t) => t,
+                Err(e) => {
+                    log::warn!("{:?} topic format error, {:?}", s.id, e);
+                    return (Proceed::Continue, acc);
+                }
+            };
+
+            let cfg = self.cfg.read().await;
+            for rule in cfg.rules(&t) {
+                if let Err(reason) = rule.validate(&publish.payload) {
+                    log::info!(
+                        "{:?} invalid payload, topic: {}, rule: {}, reason: {}",
+                        s.id,
+                        publish.topic,
+                        rule.topic_filter,
+                        reason
+                    );
+                    self.violations.inc(&publish.topic, cfg.max_topics);
+                    let disconnect = rule.action == Action::Disconnect;
+                    return (
+                        Proceed::Stop,
+                        Some(HookResult::PublishAclResult(PublishAclResult::Rejected(disconnect))),
+                    );
+                }
+            }
+        }
+        (Proceed::Continue, acc)
+    }
+}
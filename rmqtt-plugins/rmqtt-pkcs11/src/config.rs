@@ -0,0 +1,18 @@
+use rmqtt::serde_json;
+
+//Not Debug, so that the PIN does not end up in the logs
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    ///Path of the PKCS#11 module of the token
+    pub module: String,
+    ///User PIN, not needed by tokens with a protected authentication path such as a PIN pad
+    #[serde(default, skip_serializing)]
+    pub pin: Option<String>,
+}
+
+impl PluginConfig {
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!(self)
+    }
+}
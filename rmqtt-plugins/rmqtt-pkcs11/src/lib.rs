@@ -0,0 +1,343 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::error::{Error as Pkcs11Error, RvError};
+use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsPssParams};
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+
+use config::PluginConfig;
+use rmqtt::rustls::{
+    sign::{Signer, SigningKey},
+    SignatureAlgorithm, SignatureScheme,
+};
+use rmqtt::{async_trait::async_trait, log, rustls, serde_json};
+use rmqtt::{
+    broker::default::DefaultKeyLoader,
+    broker::KeyLoader,
+    plugin::{PackageInfo, Plugin},
+    register, MqttError, Result, Runtime,
+};
+
+mod config;
+
+register!(Pkcs11Plugin::new);
+
+#[derive(Plugin)]
+struct Pkcs11Plugin {
+    runtime: &'static Runtime,
+    cfg: PluginConfig,
+    pkcs11: Pkcs11,
+}
+
+impl Pkcs11Plugin {
+    #[inline]
+    async fn new<S: Into<String>>(runtime: &'static Runtime, name: S) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config::<PluginConfig>(&name)?;
+        log::info!("{} Pkcs11Plugin module: {}", name, cfg.module);
+        let pkcs11 = Pkcs11::new(&cfg.module).map_err(|e| plugin_error("load module", e))?;
+        pkcs11.initialize(CInitializeArgs::OsThreads).map_err(|e| plugin_error("initialize module", e))?;
+        Ok(Self { runtime, cfg, pkcs11 })
+    }
+}
+
+#[async_trait]
+impl Plugin for Pkcs11Plugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        Ok(self.cfg.to_json())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        *self.runtime.extends.key_loader_mut() =
+            Box::new(Pkcs11KeyLoader { pkcs11: self.pkcs11.clone(), pin: self.cfg.pin.clone() });
+        Ok(())
+    }
+
+    //Keys that are already loaded keep their sessions and still sign
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        *self.runtime.extends.key_loader_mut() = Box::new(DefaultKeyLoader::instance());
+        Ok(true)
+    }
+}
+
+#[inline]
+fn plugin_error(action: &str, e: Pkcs11Error) -> MqttError {
+    MqttError::Plugin(format!("PKCS#11 {} error, {}", action, e))
+}
+
+struct Pkcs11KeyLoader {
+    pkcs11: Pkcs11,
+    pin: Option<String>,
+}
+
+impl KeyLoader for Pkcs11KeyLoader {
+    #[inline]
+    fn enable(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn load(&self, key: &str) -> Option<Result<Arc<dyn SigningKey>>> {
+        let uri = key.strip_prefix("pkcs11:")?;
+        Some(KeyUri::parse(uri).and_then(|uri| self.open(&uri)))
+    }
+}
+
+impl Pkcs11KeyLoader {
+    fn open(&self, uri: &KeyUri) -> Result<Arc<dyn SigningKey>> {
+        let mut slot = None;
+        for s in self.pkcs11.get_slots_with_token().map_err(|e| plugin_error("list slots", e))? {
+            let info = self.pkcs11.get_token_info(s).map_err(|e| plugin_error("get token info", e))?;
+            if uri.token.as_deref().map(|token| token == info.label()).unwrap_or(true) {
+                slot = Some(s);
+                break;
+            }
+        }
+        let slot =
+            slot.ok_or_else(|| MqttError::Plugin(format!("PKCS#11 token {:?} is not found", uri.token)))?;
+
+        let session = self.pkcs11.open_ro_session(slot).map_err(|e| plugin_error("open session", e))?;
+        //The login state is shared by all sessions of a token
+        let pin = self.pin.clone().map(AuthPin::new);
+        match session.login(UserType::User, pin.as_ref()) {
+            Ok(()) | Err(Pkcs11Error::Pkcs11(RvError::UserAlreadyLoggedIn, ..)) => {}
+            Err(e) => return Err(plugin_error("login", e)),
+        }
+
+        let mut template = vec![Attribute::Class(ObjectClass::PRIVATE_KEY)];
+        if let Some(object) = &uri.object {
+            template.push(Attribute::Label(object.as_bytes().to_vec()));
+        }
+        if let Some(id) = &uri.id {
+            template.push(Attribute::Id(id.clone()));
+        }
+        let key = session
+            .find_objects(&template)
+            .map_err(|e| plugin_error("find private key", e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| MqttError::Plugin(format!("PKCS#11 private key {:?} is not found", uri.object)))?;
+
+        let attrs = session
+            .get_attributes(key, &[AttributeType::KeyType, AttributeType::EcParams])
+            .map_err(|e| plugin_error("get key attributes", e))?;
+        let key_type = attrs.iter().find_map(|attr| match attr {
+            Attribute::KeyType(key_type) => Some(*key_type),
+            _ => None,
+        });
+        let ec_params = attrs.iter().find_map(|attr| match attr {
+            Attribute::EcParams(params) => Some(params.as_slice()),
+            _ => None,
+        });
+        let kind = match (key_type, ec_params) {
+            (Some(KeyType::RSA), _) => KeyKind::Rsa,
+            (Some(KeyType::EC), Some(EC_P256)) => KeyKind::EcP256,
+            (Some(KeyType::EC), Some(EC_P384)) => KeyKind::EcP384,
+            _ => {
+                return Err(MqttError::Plugin(format!(
+                    "PKCS#11 private key {:?} is not a RSA, P-256 or P-384 key",
+                    uri.object
+                )))
+            }
+        };
+        Ok(Arc::new(Pkcs11SigningKey { session: Arc::new(Mutex::new(session)), key, kind }))
+    }
+}
+
+//DER encoded OIDs of the curves, the CKA_EC_PARAMS of EC keys
+const EC_P256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const EC_P384: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+
+///The path attributes of a PKCS#11 URI (RFC 7512) that select a private key, e.g.
+///"pkcs11:token=rmqtt;object=server" or "pkcs11:token=rmqtt;id=%01"
+#[derive(Debug, Default)]
+struct KeyUri {
+    token: Option<String>,
+    object: Option<String>,
+    id: Option<Vec<u8>>,
+}
+
+impl KeyUri {
+    fn parse(uri: &str) -> Result<Self> {
+        let path = uri.split('?').next().unwrap_or_default();
+        let mut key_uri = KeyUri::default();
+        for attr in path.split(';').filter(|attr| !attr.is_empty()) {
+            let (name, value) = attr
+                .split_once('=')
+                .ok_or_else(|| MqttError::Plugin(format!("invalid PKCS#11 URI attribute {:?}", attr)))?;
+            let value = percent_decode(value)?;
+            match name {
+                "token" => key_uri.token = Some(String::from_utf8_lossy(&value).into_owned()),
+                "object" => key_uri.object = Some(String::from_utf8_lossy(&value).into_owned()),
+                "id" => key_uri.id = Some(value),
+                _ => log::debug!("PKCS#11 URI attribute {:?} is ignored", name),
+            }
+        }
+        Ok(key_uri)
+    }
+}
+
+fn percent_decode(value: &str) -> Result<Vec<u8>> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| MqttError::Plugin(format!("invalid percent encoding in {:?}", value)))?;
+            decoded.push(hex);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum KeyKind {
+    Rsa,
+    EcP256,
+    EcP384,
+}
+
+//Signing takes the session lock, handshakes that sign at the same time are serialized on the token
+struct Pkcs11SigningKey {
+    session: Arc<Mutex<Session>>,
+    key: ObjectHandle,
+    kind: KeyKind,
+}
+
+impl fmt::Debug for Pkcs11SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pkcs11SigningKey").field("key", &self.key).field("kind", &self.kind).finish()
+    }
+}
+
+impl SigningKey for Pkcs11SigningKey {
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        let supported: &[SignatureScheme] = match self.kind {
+            KeyKind::Rsa => &[
+                SignatureScheme::RSA_PSS_SHA256,
+                SignatureScheme::RSA_PSS_SHA384,
+                SignatureScheme::RSA_PSS_SHA512,
+                SignatureScheme::RSA_PKCS1_SHA256,
+                SignatureScheme::RSA_PKCS1_SHA384,
+                SignatureScheme::RSA_PKCS1_SHA512,
+            ],
+            KeyKind::EcP256 => &[SignatureScheme::ECDSA_NISTP256_SHA256],
+            KeyKind::EcP384 => &[SignatureScheme::ECDSA_NISTP384_SHA384],
+        };
+        supported.iter().find(|scheme| offered.contains(scheme)).map(|scheme| {
+            Box::new(Pkcs11Signer { session: self.session.clone(), key: self.key, scheme: *scheme })
+                as Box<dyn Signer>
+        })
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        match self.kind {
+            KeyKind::Rsa => SignatureAlgorithm::RSA,
+            KeyKind::EcP256 | KeyKind::EcP384 => SignatureAlgorithm::ECDSA,
+        }
+    }
+}
+
+struct Pkcs11Signer {
+    session: Arc<Mutex<Session>>,
+    key: ObjectHandle,
+    scheme: SignatureScheme,
+}
+
+impl fmt::Debug for Pkcs11Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pkcs11Signer").field("key", &self.key).field("scheme", &self.scheme).finish()
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    fn sign(&self, message: &[u8]) -> std::result::Result<Vec<u8>, rustls::Error> {
+        let pss = |hash_alg, mgf, s_len: u64| PkcsPssParams { hash_alg, mgf, s_len: s_len.into() };
+        let mechanism = match self.scheme {
+            SignatureScheme::RSA_PSS_SHA256 => {
+                Mechanism::Sha256RsaPkcsPss(pss(MechanismType::SHA256, PkcsMgfType::MGF1_SHA256, 32))
+            }
+            SignatureScheme::RSA_PSS_SHA384 => {
+                Mechanism::Sha384RsaPkcsPss(pss(MechanismType::SHA384, PkcsMgfType::MGF1_SHA384, 48))
+            }
+            SignatureScheme::RSA_PSS_SHA512 => {
+                Mechanism::Sha512RsaPkcsPss(pss(MechanismType::SHA512, PkcsMgfType::MGF1_SHA512, 64))
+            }
+            SignatureScheme::RSA_PKCS1_SHA256 => Mechanism::Sha256RsaPkcs,
+            SignatureScheme::RSA_PKCS1_SHA384 => Mechanism::Sha384RsaPkcs,
+            SignatureScheme::RSA_PKCS1_SHA512 => Mechanism::Sha512RsaPkcs,
+            SignatureScheme::ECDSA_NISTP256_SHA256 => Mechanism::EcdsaSha256,
+            SignatureScheme::ECDSA_NISTP384_SHA384 => Mechanism::EcdsaSha384,
+            scheme => {
+                return Err(rustls::Error::General(format!("unsupported signature scheme {:?}", scheme)))
+            }
+        };
+        let signature = self
+            .session
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .sign(&mechanism, self.key, message)
+            .map_err(|e| rustls::Error::General(format!("PKCS#11 sign error, {}", e)))?;
+        match self.scheme {
+            SignatureScheme::ECDSA_NISTP256_SHA256 | SignatureScheme::ECDSA_NISTP384_SHA384 => {
+                Ok(ecdsa_signature_der(&signature))
+            }
+            _ => Ok(signature),
+        }
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        self.scheme
+    }
+}
+
+//PKCS#11 returns ECDSA signatures as r || s, TLS expects the DER encoded Ecdsa-Sig-Value. For P-256 and
+//P-384 all lengths are below 128, so they fit in a single byte.
+fn ecdsa_signature_der(raw: &[u8]) -> Vec<u8> {
+    fn integer(v: &[u8]) -> Vec<u8> {
+        let start = v.iter().position(|b| *b != 0).unwrap_or(v.len().saturating_sub(1));
+        let v = &v[start..];
+        let pad = v.first().map(|b| b & 0x80 != 0).unwrap_or_default();
+        let mut der = vec![0x02, (v.len() + pad as usize) as u8];
+        if pad {
+            der.push(0);
+        }
+        der.extend_from_slice(v);
+        der
+    }
+    let (r, s) = raw.split_at(raw.len() / 2);
+    let (r, s) = (integer(r), integer(s));
+    let mut der = vec![0x30, (r.len() + s.len()) as u8];
+    der.extend(r);
+    der.extend(s);
+    der
+}
@@ -3,7 +3,7 @@ extern crate rmqtt_macros;
 
 use rmqtt::{async_trait::async_trait, log};
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     plugin::{PackageInfo, Plugin},
     register, Result, Runtime,
 };
@@ -33,6 +33,8 @@ impl Plugin for Template {
         self.register.add(Type::ClientSubscribe, Box::new(HookHandler::new())).await;
         self.register.add(Type::ClientUnsubscribe, Box::new(HookHandler::new())).await;
         self.register.add(Type::MessageDelivered, Box::new(HookHandler::new())).await;
+        self.register.add(Type::MessageAcked, Box::new(HookHandler::new())).await;
+        self.register.add(Type::MessageDropped, Box::new(HookHandler::new())).await;
         self.register.add(Type::MessagePublish, Box::new(HookHandler::new())).await;
         self.register.add_priority(Type::ClientSubscribeCheckAcl, 10, Box::new(HookHandler::new())).await;
         self.register.add_priority(Type::GrpcMessageReceived, 10, Box::new(HookHandler::new())).await;
@@ -67,27 +69,36 @@ impl HookHandler {
 impl Handler for HookHandler {
     async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
         match param {
-            Parameter::ClientConnack(connect_info, r) => {
-                log::debug!("client connack, {:?}, {:?}", connect_info, r);
+            Parameter::ClientConnack(connect_info, r, session_present) => {
+                log::debug!("client connack, {:?}, {:?}, {:?}", connect_info, r, session_present);
             }
             Parameter::ClientSubscribe(s, subscribe) => {
                 log::debug!("{:?} client subscribe, {:?}", s.id, subscribe);
                 //let mut topic_filter = subscribe.topic_filter.clone();
                 //topic_filter.insert(0, Level::Normal("PPP".into()));
-                //return (true, Some(HookResult::TopicFilter(Some(topic_filter))))
+                //return (Proceed::Continue, Some(HookResult::TopicFilter(Some(topic_filter))))
             }
             Parameter::ClientUnsubscribe(s, unsubscribe) => {
                 log::debug!("{:?} client unsubscribe, {:?}", s.id, unsubscribe);
                 //let mut topic_filter = (*unsubscribe).clone();
                 //topic_filter.insert(0, Level::Normal("PPP".into()));
-                //return (true, Some(HookResult::TopicFilter(Some(topic_filter))))
+                //return (Proceed::Continue, Some(HookResult::TopicFilter(Some(topic_filter))))
             }
             Parameter::MessagePublish(s, _f, publish) => {
                 log::debug!("{:?} message publish, {:?}", s.map(|s| &s.id), publish);
+                //let mut publish = (*publish).clone();
+                //publish.add_user_property("x-handled-by".into(), "template".into());
+                //return (Proceed::Continue, Some(HookResult::Publish(publish)))
             }
             Parameter::MessageDelivered(s, f, _publish) => {
                 log::debug!("{:?} MessageDelivered, {:?}", s.id, f);
             }
+            Parameter::MessageAcked(s, f, _publish) => {
+                log::debug!("{:?} MessageAcked, {:?}", s.id, f);
+            }
+            Parameter::MessageDropped(to, f, _publish, reason) => {
+                log::debug!("{:?} MessageDropped, {:?}, reason: {}", to, f, reason);
+            }
             Parameter::ClientSubscribeCheckAcl(s, subscribe) => {
                 log::debug!("{:?} ClientSubscribeCheckAcl, {:?}", s.id, subscribe);
             }
@@ -95,6 +106,6 @@ impl Handler for HookHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
@@ -0,0 +1,52 @@
+use serde::de::{self, Deserialize, Deserializer};
+
+use rmqtt::serde_json;
+use rmqtt::{broker::types::QoS, Result};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PluginConfig {
+    ///Topic template of the retained presence messages, ${clientid} and ${username} can be used,
+    ///presence is not published if not set
+    #[serde(default)]
+    pub publish_topic: Option<String>,
+
+    #[serde(
+        default = "PluginConfig::publish_qos_default",
+        deserialize_with = "PluginConfig::deserialize_publish_qos"
+    )]
+    pub publish_qos: QoS,
+}
+
+impl PluginConfig {
+    #[inline]
+    fn publish_qos_default() -> QoS {
+        QoS::AtLeastOnce
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    #[inline]
+    pub fn make_topic(&self, clientid: &str, username: &str) -> Option<String> {
+        self.publish_topic
+            .as_ref()
+            .filter(|t| !t.is_empty())
+            .map(|t| t.replace("${clientid}", clientid).replace("${username}", username))
+    }
+
+    #[inline]
+    fn deserialize_publish_qos<'de, D>(deserializer: D) -> std::result::Result<QoS, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let qos = match u8::deserialize(deserializer)? {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            _ => return Err(de::Error::custom("QoS configuration error, only values (0,1,2) are supported")),
+        };
+        Ok(qos)
+    }
+}
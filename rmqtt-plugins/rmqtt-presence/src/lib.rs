@@ -0,0 +1,232 @@
+#![deny(unsafe_code)]
+#[macro_use]
+extern crate serde;
+
+#[macro_use]
+extern crate rmqtt_macros;
+
+use std::sync::Arc;
+
+use rmqtt::{
+    async_trait::async_trait,
+    log,
+    serde_json::{self, json},
+    tokio::sync::RwLock,
+};
+use rmqtt::{
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
+    broker::types::{From, Id},
+    plugin::{PackageInfo, Plugin, Store},
+    register, ClientId, MqttError, NodeId, Publish, Result, Runtime, Session, SessionState, TimestampMillis,
+    UserName,
+};
+
+use config::PluginConfig;
+
+mod config;
+
+register!(PresencePlugin::new);
+
+#[derive(Plugin)]
+struct PresencePlugin {
+    runtime: &'static Runtime,
+    register: Box<dyn Register>,
+    cfg: Arc<RwLock<PluginConfig>>,
+    store: Store,
+}
+
+impl PresencePlugin {
+    #[inline]
+    async fn new<N: Into<String>>(runtime: &'static Runtime, name: N) -> Result<Self> {
+        let name = name.into();
+        let cfg = runtime.settings.plugins.load_config_default::<PluginConfig>(&name)?;
+        log::info!("{} PresencePlugin cfg: {:?}", name, cfg);
+        let cfg = Arc::new(RwLock::new(cfg));
+        let register = runtime.extends.hook_mgr().await.register();
+        let store = Store::new(name);
+        Ok(Self { runtime, register, cfg, store })
+    }
+}
+
+#[async_trait]
+impl Plugin for PresencePlugin {
+    #[inline]
+    async fn init(&mut self) -> Result<()> {
+        log::info!("{} init", self.name());
+        self.register
+            .add(Type::ClientConnected, Box::new(PresenceHandler::new(&self.cfg, &self.store)))
+            .await;
+        self.register
+            .add(Type::ClientDisconnected, Box::new(PresenceHandler::new(&self.cfg, &self.store)))
+            .await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn get_config(&self) -> Result<serde_json::Value> {
+        self.cfg.read().await.to_json()
+    }
+
+    #[inline]
+    async fn load_config(&mut self) -> Result<()> {
+        let new_cfg = self.runtime.settings.plugins.load_config::<PluginConfig>(self.name())?;
+        *self.cfg.write().await = new_cfg;
+        log::debug!("load_config ok,  {:?}", self.cfg);
+        Ok(())
+    }
+
+    #[inline]
+    async fn start(&mut self) -> Result<()> {
+        log::info!("{} start", self.name());
+        self.register.start().await;
+        Ok(())
+    }
+
+    #[inline]
+    async fn stop(&mut self) -> Result<bool> {
+        log::info!("{} stop", self.name());
+        self.register.stop().await;
+        Ok(true)
+    }
+
+    ///Query the presence of a client, message format: {"clientid": "foo"}
+    #[inline]
+    async fn send(&self, msg: serde_json::Value) -> Result<serde_json::Value> {
+        let clientid = msg
+            .get("clientid")
+            .and_then(|c| c.as_str())
+            .ok_or_else(|| MqttError::from("clientid is required"))?;
+        match self.store.get::<Presence>(clientid).await? {
+            Some(presence) => Ok(presence.to_json(clientid)),
+            None => Ok(serde_json::Value::Null),
+        }
+    }
+}
+
+///The presence record of a client, kept in the plugin key-value store
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct Presence {
+    online: bool,
+    node: NodeId,
+    username: Option<String>,
+    ipaddress: Option<String>,
+    connected_at: TimestampMillis,
+    disconnected_at: Option<TimestampMillis>,
+    disconnect_reason: Option<String>,
+}
+
+impl Presence {
+    #[inline]
+    fn to_json(&self, clientid: &str) -> serde_json::Value {
+        json!({
+            "clientid": clientid,
+            "online": self.online,
+            "node": self.node,
+            "username": self.username,
+            "ipaddress": self.ipaddress,
+            "connected_at": self.connected_at,
+            "disconnected_at": self.disconnected_at,
+            "disconnect_reason": self.disconnect_reason,
+        })
+    }
+}
+
+struct PresenceHandler {
+    cfg: Arc<RwLock<PluginConfig>>,
+    store: Store,
+}
+
+impl PresenceHandler {
+    fn new(cfg: &Arc<RwLock<PluginConfig>>, store: &Store) -> Self {
+        Self { cfg: cfg.clone(), store: store.clone() }
+    }
+
+    async fn connected(&self, s: &Session) -> Result<Presence> {
+        let presence = Presence {
+            online: true,
+            node: s.id.node(),
+            username: s.id.username.as_ref().map(|u| u.to_string()),
+            ipaddress: s.id.remote_addr.map(|addr| addr.ip().to_string()),
+            connected_at: s.connected_at().await?,
+            disconnected_at: None,
+            disconnect_reason: None,
+        };
+        self.store.set(&s.id.client_id, &presence).await?;
+        Ok(presence)
+    }
+
+    async fn disconnected(&self, s: &Session, reason: String) -> Result<Option<Presence>> {
+        let connected_at = s.connected_at().await?;
+        let mut presence = match self.store.get::<Presence>(&s.id.client_id).await? {
+            //The client has already connected again, this session has been taken over
+            Some(presence) if presence.connected_at > connected_at => return Ok(None),
+            Some(presence) => presence,
+            None => Presence {
+                node: s.id.node(),
+                username: s.id.username.as_ref().map(|u| u.to_string()),
+                ipaddress: s.id.remote_addr.map(|addr| addr.ip().to_string()),
+                connected_at,
+                ..Default::default()
+            },
+        };
+        presence.online = false;
+        presence.disconnected_at = Some(s.disconnected_at().await?);
+        presence.disconnect_reason = Some(reason);
+        self.store.set(&s.id.client_id, &presence).await?;
+        Ok(Some(presence))
+    }
+
+    async fn publish(&self, id: &Id, presence: Presence) {
+        let (topic, qos) = {
+            let cfg = self.cfg.read().await;
+            (cfg.make_topic(&id.client_id, id.username_ref()), cfg.publish_qos)
+        };
+        let topic = if let Some(topic) = topic {
+            topic
+        } else {
+            return;
+        };
+        if topic.contains(['+', '#']) {
+            log::warn!("{:?} presence topic contains wildcards, not published, {}", id, topic);
+            return;
+        }
+        let payload = match serde_json::to_vec(&presence.to_json(&id.client_id)) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::error!("{:?}", e);
+                return;
+            }
+        };
+        let from = From::from_system(Id::new(
+            Runtime::instance().node.id(),
+            None,
+            None,
+            ClientId::from_static("system"),
+            Some(UserName::from("system")),
+        ));
+        let p = Publish::builder().qos(qos).retain(true).topic(topic).payload(payload).build();
+        if let Err(e) = SessionState::forwards(from, p, true, false, None).await {
+            log::warn!("{:?} presence publish error, {:?}", id, e);
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for PresenceHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        let (id, presence) = match param {
+            Parameter::ClientConnected(s) => (&s.id, self.connected(s).await.map(Some)),
+            Parameter::ClientDisconnected(s, r) => (&s.id, self.disconnected(s, r.to_string()).await),
+            _ => {
+                log::error!("unimplemented, {:?}", param);
+                return (Proceed::Continue, acc);
+            }
+        };
+        match presence {
+            Ok(Some(presence)) => self.publish(id, presence).await,
+            Ok(None) => {}
+            Err(e) => log::warn!("{:?} presence update error, {:?}", id, e),
+        }
+        (Proceed::Continue, acc)
+    }
+}
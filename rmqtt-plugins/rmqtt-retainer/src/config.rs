@@ -1,5 +1,6 @@
 use serde::de::{self, Deserialize, Deserializer};
 
+use rmqtt::broker::encryption::PayloadCipher;
 use rmqtt::serde_json;
 use rmqtt::settings::Bytesize;
 use rmqtt::Result;
@@ -19,6 +20,10 @@ pub struct PluginConfig {
     // message server will process the received reserved message as a regular message.
     #[serde(default = "PluginConfig::max_payload_size_default")]
     pub max_payload_size: Bytesize, // = "1MB"
+
+    // Payload encryption of the stored retained messages, messages kept in ram are not encrypted.
+    #[serde(default)]
+    pub encryption: PayloadCipher,
 }
 
 impl PluginConfig {
@@ -21,9 +21,9 @@ use rmqtt::{
     MqttError,
 };
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::RetainStorage,
-    plugin::{PackageInfo, Plugin},
+    plugin::{PackageInfo, Plugin, PluginMetrics},
     register, Result, Runtime,
 };
 use rmqtt_storage::{init_db, StorageType};
@@ -187,7 +187,7 @@ impl Handler for RetainHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
@@ -212,7 +212,8 @@ impl Retainer {
                 let msg_count = r.count().await;
                 let topic_nodes = r.inner.messages.read().await.nodes_size();
                 let topic_values = r.inner.messages.read().await.values_size();
-                json!({
+                let metrics = PluginMetrics { messages: Some(msg_count.max(0) as u64), ..Default::default() };
+                metrics.attrs(json!({
                     "storage_engine": "Ram",
                     "message": {
                         "max": msg_max,
@@ -220,21 +221,26 @@ impl Retainer {
                         "topic_nodes": topic_nodes,
                         "topic_values": topic_values,
                     },
-                })
+                }))
             }
             Retainer::Storage(r) => {
                 let msg_max = r.max().await;
                 let msg_count = r.count().await;
                 let msg_queue_count = r.msg_queue_count.load(Ordering::Relaxed);
                 let storage_info = r.storage_db.info().await.unwrap_or_default();
-                json!({
+                let metrics = PluginMetrics {
+                    queue_depth: Some(msg_queue_count.max(0) as u64),
+                    messages: Some(msg_count.max(0) as u64),
+                    ..Default::default()
+                };
+                metrics.attrs(json!({
                     "storage_info": storage_info,
                     "msg_queue_count": msg_queue_count,
                     "message": {
                         "max": msg_max,
                         "count": msg_count,
                     },
-                })
+                }))
             }
         }
     }
@@ -161,13 +161,13 @@ pub struct RetainerInner {
 impl RetainerInner {
     #[inline]
     async fn _batch_store(&self, msgs: Vec<Msg>) -> Result<()> {
-        let (max_retained_messages, max_payload_size) = {
+        let (max_retained_messages, max_payload_size, cipher) = {
             let cfg = self.cfg.read().await;
-            (cfg.max_retained_messages as usize, *cfg.max_payload_size)
+            (cfg.max_retained_messages as usize, *cfg.max_payload_size, cfg.encryption.clone())
         };
 
         let mut count = 0;
-        for (topic_name, retain, expiry_interval) in msgs {
+        for (topic_name, mut retain, expiry_interval) in msgs {
             let store_topic_name = [RETAIN_MESSAGES_PREFIX, topic_name.as_bytes().as_ref()].concat();
             if retain.publish.payload.is_empty() {
                 //remove retain messagge
@@ -176,7 +176,7 @@ impl RetainerInner {
                     .remove(store_topic_name.as_slice())
                     .timeout(futures_time::time::Duration::from_millis(5000))
                     .await
-                    .map_err(|_e| MqttError::from("storage_db.remove timeout"))?
+                    .map_err(|_e| MqttError::Storage("storage_db.remove timeout".into()))?
                 {
                     log::warn!("remove from db error, remove(..), {:?}, topic_name: {:?}", e, topic_name);
                 };
@@ -206,13 +206,20 @@ impl RetainerInner {
                 let expiry_time_at = expiry_interval_millis
                     .map(|expiry_interval_millis| timestamp_millis() + expiry_interval_millis);
 
+                retain.publish = match cipher.encrypt_publish(retain.publish) {
+                    Ok(publish) => publish,
+                    Err(e) => {
+                        log::warn!("store to db error, {:?}, topic_name: {:?}", e, topic_name);
+                        continue;
+                    }
+                };
                 let smsg: StoredMsg = (retain, expiry_time_at);
                 if let Err(e) = self
                     .storage_db
                     .insert(store_topic_name.as_slice(), &smsg)
                     .timeout(futures_time::time::Duration::from_millis(5000))
                     .await
-                    .map_err(|_e| MqttError::from("storage_db.insert timeout"))?
+                    .map_err(|_e| MqttError::Storage("storage_db.insert timeout".into()))?
                 {
                     log::warn!("store to db error, insert(..), {:?}, message: {:?}", e, smsg);
                     continue;
@@ -234,7 +241,7 @@ impl RetainerInner {
             .storage_messages_max_add(count)
             .timeout(futures_time::time::Duration::from_millis(5000))
             .await
-            .map_err(|_e| MqttError::from("storage_messages_max_add timeout"))?
+            .map_err(|_e| MqttError::Storage("storage_messages_max_add timeout".into()))?
         {
             log::warn!("messages_received_counter add error, {:?}", e);
         }
@@ -342,13 +349,21 @@ impl RetainerInner {
         }
         drop(iter);
 
+        let cipher = self.cfg.read().await.encryption.clone();
         let mut retains = Vec::new();
         for key in matched_topics {
             match db.get::<_, StoredMsg>(key.as_slice()).await {
-                Ok(Some((retain, expiry_time_at))) => {
+                Ok(Some((mut retain, expiry_time_at))) => {
                     let topic_name = TopicName::from(
                         String::from_utf8_lossy(&key[RETAIN_MESSAGES_PREFIX.len()..]).as_ref(),
                     );
+                    retain.publish = match cipher.decrypt_publish(retain.publish) {
+                        Ok(publish) => publish,
+                        Err(e) => {
+                            log::error!("{:?}, topic_name: {:?}", e, topic_name);
+                            continue;
+                        }
+                    };
                     if let Some(expiry_time_at) = expiry_time_at {
                         if expiry_time_at > timestamp_millis() {
                             retains.push((topic_name, retain));
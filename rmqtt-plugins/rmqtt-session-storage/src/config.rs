@@ -1,3 +1,4 @@
+use rmqtt::broker::encryption::PayloadCipher;
 use rmqtt::serde_json;
 
 use rmqtt_storage::Config;
@@ -6,6 +7,9 @@ use rmqtt_storage::Config;
 pub struct PluginConfig {
     #[serde(default)]
     pub storage: Config,
+    ///Payload encryption of the stored offline and inflight messages
+    #[serde(default)]
+    pub encryption: PayloadCipher,
 }
 
 impl PluginConfig {
@@ -23,12 +23,12 @@ use rmqtt::{
 
 use rmqtt::{
     broker::fitter::Fitter,
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::inflight::InflightMessage,
     broker::types::DisconnectInfo,
     plugin::{PackageInfo, Plugin},
-    register, ClientId, From, MqttError, Publish, Result, Runtime, Session, SessionState, SessionSubMap,
-    SessionSubs, TimestampMillis,
+    register, ClientId, From, MqttError, Publish, Result, Runtime, Session, SessionAttrs, SessionState,
+    SessionSubMap, SessionSubs, TimestampMillis,
 };
 
 use rmqtt_storage::{init_db, DefaultStorageDB, List, Map, StorageType};
@@ -97,6 +97,7 @@ impl StoragePlugin {
         log::info!("{:?} load_offline_session_infos ...", self.name());
         let storage_db = self.storage_db.clone();
         let mut iter_storage_db = storage_db.clone();
+        let cipher = &self.cfg.encryption;
         //Load offline session information from the database
         let mut map_iter = iter_storage_db.map_iter().await?;
         while let Some(m) = map_iter.next().await {
@@ -162,7 +163,23 @@ impl StoragePlugin {
                     match m.get::<_, Vec<InflightMessage>>(INFLIGHT_MESSAGES).await {
                         Ok(Some(inflights)) => {
                             log::debug!("inflights len: {:?}", inflights.len());
-                            s_info.inflight_messages = inflights;
+                            s_info.inflight_messages = inflights
+                                .into_iter()
+                                .filter_map(|mut m| match cipher.decrypt_publish(m.publish) {
+                                    Ok(publish) => {
+                                        m.publish = publish;
+                                        Some(m)
+                                    }
+                                    Err(e) => {
+                                        log::warn!(
+                                            "{:?} load offline inflight message error, {:?}",
+                                            id_key,
+                                            e
+                                        );
+                                        None
+                                    }
+                                })
+                                .collect();
                         }
                         Ok(None) => {}
                         Err(e) => {
@@ -188,6 +205,19 @@ impl StoragePlugin {
                     match l.all::<OfflineMessageOptionType>().await {
                         Ok(offline_msgs) => {
                             log::debug!("{:?} offline_msgs len: {}", id_key, offline_msgs.len(),);
+                            let offline_msgs = offline_msgs
+                                .into_iter()
+                                .filter_map(|msg| match msg {
+                                    Some((client_id, from, p)) => match cipher.decrypt_publish(p) {
+                                        Ok(p) => Some(Some((client_id, from, p))),
+                                        Err(e) => {
+                                            log::warn!("{:?} load offline message error, {:?}", id_key, e);
+                                            None
+                                        }
+                                    },
+                                    None => Some(None),
+                                })
+                                .collect();
                             let ok =
                                 self.stored_session_infos.set_offline_messages(id_key.clone(), offline_msgs);
                             log::debug!(
@@ -417,12 +447,19 @@ impl Handler for OfflineMessageHandler {
                     f,
                     p
                 );
+                let p = match self.cfg.encryption.encrypt_publish((*p).clone()) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        log::warn!("{:?} save offline messages error, {:?}", s.id, e);
+                        return (Proceed::Continue, acc);
+                    }
+                };
                 let list_stored_key = make_list_stored_key(s.id.to_string());
                 match self.storage_db.list(list_stored_key.as_ref(), None).await {
                     Ok(offlines_list) => {
                         let res = offlines_list
                             .push_limit::<OfflineMessageOptionType>(
-                                &Some((s.id.client_id.clone(), f.clone(), (*p).clone())),
+                                &Some((s.id.client_id.clone(), f.clone(), p)),
                                 s.listen_cfg().max_mqueue_len,
                                 true,
                             )
@@ -445,9 +482,23 @@ impl Handler for OfflineMessageHandler {
                 );
                 let map_stored_key = make_map_stored_key(s.id.to_string());
                 log::debug!("{:?} map_stored_key: {:?}", s.id, map_stored_key);
+                let inflight_messages = inflight_messages
+                    .iter()
+                    .cloned()
+                    .filter_map(|mut m| match self.cfg.encryption.encrypt_publish(m.publish) {
+                        Ok(publish) => {
+                            m.publish = publish;
+                            Some(m)
+                        }
+                        Err(e) => {
+                            log::warn!("{:?} save offline inflight message error, {:?}", s.id, e);
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>();
                 match self.storage_db.map(map_stored_key.as_ref(), None).await {
                     Ok(m) => {
-                        if let Err(e) = m.insert(INFLIGHT_MESSAGES, inflight_messages).await {
+                        if let Err(e) = m.insert(INFLIGHT_MESSAGES, &inflight_messages).await {
                             log::warn!("{:?} save offline inflight messages error, {:?}", s.id, e)
                         }
                     }
@@ -461,7 +512,7 @@ impl Handler for OfflineMessageHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
@@ -555,6 +606,7 @@ impl StorageHandler {
                     stored.basic.conn_info.clone(),
                     false,
                     false,
+                    SessionAttrs::default(),
                     false,
                     stored.basic.connected_at,
                     subs,
@@ -618,7 +670,7 @@ impl Handler for StorageHandler {
                 log::error!("unimplemented, {:?}", param)
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
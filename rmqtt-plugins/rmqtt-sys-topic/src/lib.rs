@@ -9,7 +9,6 @@ use config::PluginConfig;
 use rmqtt::{
     async_trait::async_trait,
     base64::prelude::{Engine, BASE64_STANDARD},
-    bytes::Bytes,
     chrono, log,
     serde_json::{self, json},
     tokio::spawn,
@@ -17,11 +16,10 @@ use rmqtt::{
     tokio::time::sleep,
 };
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::types::{From, Id, QoSEx},
     plugin::{PackageInfo, Plugin},
-    register, timestamp_millis, ClientId, NodeId, Publish, PublishProperties, QoS, Result, Runtime,
-    SessionState, TopicName, UserName,
+    register, ClientId, NodeId, Publish, QoS, Result, Runtime, SessionState, UserName,
 };
 use std::convert::From as _;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -209,12 +207,13 @@ impl Handler for SystemTopicHandler {
         let now = chrono::Local::now();
         let now_time = now.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
         if let Some((topic, payload)) = match param {
-            Parameter::SessionCreated(session) => {
+            Parameter::SessionCreated(session, clean_start) => {
                 let body = json!({
                     "node": session.id.node(),
                     "ipaddress": session.id.remote_addr,
                     "clientid": session.id.client_id,
                     "username": session.id.username_ref(),
+                    "clean_start": clean_start,
                     "created_at": session.created_at().await.unwrap_or_default(),
                     "time": now_time
                 });
@@ -350,7 +349,7 @@ impl Handler for SystemTopicHandler {
                 expiry_interval,
             ));
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
@@ -374,17 +373,7 @@ async fn sys_publish(
                 Some(UserName::from("system")),
             ));
 
-            let p = Publish {
-                dup: false,
-                retain: false,
-                qos: publish_qos,
-                topic: TopicName::from(topic),
-                packet_id: None,
-                payload: Bytes::from(payload),
-                properties: PublishProperties::default(),
-                delay_interval: None,
-                create_time: timestamp_millis(),
-            };
+            let p = Publish::builder().qos(publish_qos).topic(topic).payload(payload).build();
 
             //hook, message_publish
             let p = Runtime::instance()
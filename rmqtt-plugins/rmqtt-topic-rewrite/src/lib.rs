@@ -10,7 +10,7 @@ use std::sync::Arc;
 
 use rmqtt::{async_trait::async_trait, log, serde_json, tokio::sync::RwLock};
 use rmqtt::{
-    broker::hook::{Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     plugin::{PackageInfo, Plugin},
     register, Publish, Result, Runtime, Session, Topic, TopicFilter, TopicName,
 };
@@ -249,10 +249,10 @@ impl Handler for TopicRewriteHandler {
                 match self.rewrite_publish_topic(s.as_ref().map(|s| *s), p).await {
                     Err(e) => {
                         log::error!("{:?} topic format error, {:?}", s.map(|s| &s.id), e);
-                        return (true, acc);
+                        return (Proceed::Continue, acc);
                     }
                     Ok(Some(p)) => {
-                        return (true, Some(HookResult::Publish(p)));
+                        return (Proceed::Continue, Some(HookResult::Publish(p)));
                     }
                     Ok(None) => {}
                 }
@@ -261,10 +261,10 @@ impl Handler for TopicRewriteHandler {
                 match self.rewrite_subscribe_topic(Some(*s), &sub.topic_filter).await {
                     Err(e) => {
                         log::error!("{} topic_filter format error, {:?}", s.id, e);
-                        return (true, acc);
+                        return (Proceed::Continue, acc);
                     }
                     Ok(Some(tf)) => {
-                        return (true, Some(HookResult::TopicFilter(Some(tf))));
+                        return (Proceed::Continue, Some(HookResult::TopicFilter(Some(tf))));
                     }
                     Ok(None) => {}
                 }
@@ -273,10 +273,10 @@ impl Handler for TopicRewriteHandler {
                 match self.rewrite_subscribe_topic(Some(*s), &unsub.topic_filter).await {
                     Err(e) => {
                         log::error!("{} topic_filter format error, {:?}", s.id, e);
-                        return (true, acc);
+                        return (Proceed::Continue, acc);
                     }
                     Ok(Some(tf)) => {
-                        return (true, Some(HookResult::TopicFilter(Some(tf))));
+                        return (Proceed::Continue, Some(HookResult::TopicFilter(Some(tf))));
                     }
                     Ok(None) => {}
                 }
@@ -285,6 +285,6 @@ impl Handler for TopicRewriteHandler {
                 log::error!("parameter is: {:?}", param);
             }
         }
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
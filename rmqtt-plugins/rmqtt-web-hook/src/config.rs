@@ -14,6 +14,8 @@ use rmqtt::settings::deserialize_duration;
 use rmqtt::{ahash, serde_json, url};
 use rmqtt::{Result, Topic};
 
+use crate::limit::RuleBudget;
+
 type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,6 +44,15 @@ pub struct PluginConfig {
     pub retry_max_elapsed_time: Duration,
     #[serde(default = "PluginConfig::retry_multiplier_default")]
     pub retry_multiplier: f64,
+
+    ///Consecutive failed requests after which the circuit of an http url opens, 0 disables the breaker
+    #[serde(default = "PluginConfig::circuit_breaker_failures_default")]
+    pub circuit_breaker_failures: usize,
+    #[serde(
+        default = "PluginConfig::circuit_breaker_reset_default",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub circuit_breaker_reset: Duration,
 }
 
 impl PluginConfig {
@@ -63,6 +74,12 @@ impl PluginConfig {
     fn retry_multiplier_default() -> f64 {
         2.5
     }
+    fn circuit_breaker_failures_default() -> usize {
+        5
+    }
+    fn circuit_breaker_reset_default() -> Duration {
+        Duration::from_secs(30)
+    }
 
     fn deserialize_rules<'de, D>(deserializer: D) -> std::result::Result<HashMap<Type, Vec<Rule>>, D::Error>
     where
@@ -95,6 +112,26 @@ impl PluginConfig {
         &self.urls
     }
 
+    ///The actions of the rules of the hook type that match the topic, with the urls they are written to
+    pub fn actions(&self, typ: &Type, topic: Option<&Topic>) -> Vec<(&Rule, &[Url])> {
+        let rules = if let Some(rules) = self.rules.get(typ) { rules } else { return Vec::new() };
+        rules
+            .iter()
+            .filter_map(|r| {
+                let is_allowed = match (topic, &r.topics) {
+                    (Some(topic), Some((rule_topics, _))) => rule_topics.is_match(topic),
+                    _ => true,
+                };
+                let urls = if r.urls.is_empty() { self.urls() } else { &r.urls };
+                if is_allowed && !urls.is_empty() {
+                    Some((r, urls.as_slice()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
     #[allow(deprecated)]
     #[inline]
     pub fn merge_urls(&mut self) {
@@ -115,6 +152,17 @@ pub struct Rule {
         serialize_with = "Rule::serialize_topics"
     )]
     pub topics: TopicsType,
+    ///Max evaluations per second, 0 is unlimited
+    #[serde(default)]
+    pub rate_limit: u32,
+    ///What to do with the evaluations over the rate limit
+    #[serde(default)]
+    pub overflow: Overflow,
+    ///Max time an action may take, including the retries, 0 is unlimited
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_latency: Duration,
+    #[serde(skip)]
+    pub budget: Arc<RuleBudget>,
 }
 
 impl Rule {
@@ -149,6 +197,16 @@ impl Rule {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    ///The action is not executed
+    #[default]
+    Drop,
+    ///The action is executed when the budget allows it, the queue of the plugin may back up
+    Delay,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Url {
     pub loc: ByteString,
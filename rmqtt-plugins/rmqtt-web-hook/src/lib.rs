@@ -15,7 +15,8 @@ use backoff::future::retry;
 use backoff::ExponentialBackoff;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
-use crate::config::Url;
+use crate::config::{Overflow, Url};
+use crate::limit::CircuitBreaker;
 use crate::tokio::time;
 use config::PluginConfig;
 use rmqtt::{
@@ -40,14 +41,15 @@ use rmqtt::{
 };
 use rmqtt::{
     broker::error::MqttError,
-    broker::hook::{self, Handler, HookResult, Parameter, Register, ReturnType, Type},
+    broker::hook::{self, Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type},
     broker::stats::Counter,
     broker::types::QoSEx,
-    plugin::{PackageInfo, Plugin},
+    plugin::{Health, PackageInfo, Plugin, PluginMetrics},
     register, Result, Runtime, Topic, TopicFilter,
 };
 
 mod config;
+mod limit;
 
 type HookWriters = Arc<DashMap<ByteString, Arc<RwLock<HookWriter>>>>;
 
@@ -178,6 +180,12 @@ impl Plugin for WebHookPlugin {
                 Box::new(WebHookHandler { tx: tx.clone(), chan_queue_count: chan_queue_count.clone() }),
             )
             .await;
+        self.register
+            .add(
+                Type::SessionResumed,
+                Box::new(WebHookHandler { tx: tx.clone(), chan_queue_count: chan_queue_count.clone() }),
+            )
+            .await;
         self.register
             .add(
                 Type::SessionTerminated,
@@ -221,6 +229,24 @@ impl Plugin for WebHookPlugin {
                 Box::new(WebHookHandler { tx: tx.clone(), chan_queue_count: chan_queue_count.clone() }),
             )
             .await;
+        self.register
+            .add(
+                Type::ClientKeepaliveTimeout,
+                Box::new(WebHookHandler { tx: tx.clone(), chan_queue_count: chan_queue_count.clone() }),
+            )
+            .await;
+        self.register
+            .add(
+                Type::ClientAlive,
+                Box::new(WebHookHandler { tx: tx.clone(), chan_queue_count: chan_queue_count.clone() }),
+            )
+            .await;
+        self.register
+            .add(
+                Type::ClientProtocolViolation,
+                Box::new(WebHookHandler { tx: tx.clone(), chan_queue_count: chan_queue_count.clone() }),
+            )
+            .await;
         self.register
             .add(
                 Type::ClientSubscribe,
@@ -290,6 +316,8 @@ impl Plugin for WebHookPlugin {
         } else {
             *self.cfg.write().await = new_cfg;
         }
+        //the circuit breakers are created again with the new settings
+        breakers().clear();
         log::debug!("load_config ok,  {:?}", self.cfg);
         Ok(())
     }
@@ -312,15 +340,69 @@ impl Plugin for WebHookPlugin {
     async fn attrs(&self) -> serde_json::Value {
         let chan_queue_count = self.chan_queue_count.load(Ordering::SeqCst);
         let exec = &self.exec;
-        json!({
+        let metrics = PluginMetrics {
+            queue_depth: Some((chan_queue_count.max(0) + exec.waiting_count().max(0)) as u64),
+            errors: Some((fails().count().max(0) + drops().count().max(0)) as u64),
+            ..Default::default()
+        };
+        metrics.attrs(json!({
             "chan_queue_count": chan_queue_count,
             "task_exec_queue": {
                 "active_count": exec.active_count(),
                 "waiting_count": exec.waiting_count(),
                 "completed_count": exec.completed_count().await,
                 "failure_count": fails().count(),
-            }
-        })
+            },
+            "dropped_count": drops().count(),
+            "open_circuits": open_circuits(),
+        }))
+    }
+
+    #[inline]
+    async fn health(&self) -> Health {
+        let open = open_circuits();
+        if open.is_empty() {
+            Health::healthy()
+        } else {
+            Health::degraded(format!("circuit open, {}", open.join(", ")))
+        }
+    }
+
+    ///Tests the rules against a sample event without writing anything, returns the actions that would
+    ///fire, with their urls and bodies. Message format:
+    ///{"event": "message_publish", "topic": "foo/bar", "body": {"payload": "aGVsbG8="}}
+    #[inline]
+    async fn send(&self, msg: serde_json::Value) -> Result<serde_json::Value> {
+        let event =
+            msg.get("event").and_then(|e| e.as_str()).ok_or_else(|| MqttError::from("event is required"))?;
+        let topic = msg.get("topic").and_then(|t| t.as_str());
+        let mut body = msg.get("body").cloned().unwrap_or_else(|| json!({}));
+        if let (Some(obj), Some(topic)) = (body.as_object_mut(), topic) {
+            obj.entry("topic").or_insert_with(|| json!(topic));
+        }
+        let topic = topic.map(Topic::from_str).transpose()?;
+
+        let cfg = self.cfg.read().await;
+        //the hook types of the configured rules, an event without rules fires nothing
+        let actions = match cfg.rules.keys().find(|typ| typ.as_str() == event) {
+            Some(typ) => cfg
+                .actions(typ, topic.as_ref())
+                .into_iter()
+                .map(|(rule, urls)| {
+                    let mut body = body.clone();
+                    if let Some(obj) = body.as_object_mut() {
+                        obj.insert("action".into(), json!(rule.action));
+                    }
+                    json!({
+                        "action": rule.action,
+                        "urls": urls.iter().map(|url| url.loc.as_ref()).collect::<Vec<&str>>(),
+                        "body": body,
+                    })
+                })
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        Ok(json!({ "event": event, "actions": actions }))
     }
 }
 
@@ -349,81 +431,56 @@ impl WebHookHandler {
         body: serde_json::Value,
     ) -> Result<()> {
         let topic = if let Some(topic) = topic { Some(Topic::from_str(&topic)?) } else { None };
-        let hook_writes = {
+        let actions = {
             let cfg = cfg.read().await;
-            if let Some(rules) = cfg.rules.get(&typ) {
-                //get action and urls
-                let action_urls = rules.iter().filter_map(|r| {
-                    let is_allowed = if let Some(topic) = &topic {
-                        if let Some((rule_topics, _)) = &r.topics {
-                            rule_topics.is_match(topic)
-                        } else {
-                            true
-                        }
-                    } else {
-                        true
-                    };
-
-                    if is_allowed {
-                        let urls = if r.urls.is_empty() { cfg.urls() } else { &r.urls };
-                        if urls.is_empty() {
-                            None
-                        } else {
-                            Some((&r.action, urls))
+            let mut actions = Vec::new();
+            for (rule, urls) in cfg.actions(&typ, topic.as_ref()) {
+                //the execution budget of the rule
+                let delay = if rule.rate_limit > 0 {
+                    match rule.budget.acquire(rule.rate_limit, rule.overflow == Overflow::Delay) {
+                        Some(delay) => delay,
+                        None => {
+                            drops().current_inc();
+                            log::debug!("action: {}, over the rate limit, dropped", rule.action);
+                            continue;
                         }
-                    } else {
-                        None
                     }
-                });
-
+                } else {
+                    Duration::ZERO
+                };
+                let mut new_body = body.clone();
+                if let Some(obj) = new_body.as_object_mut() {
+                    obj.insert("action".into(), serde_json::Value::String(rule.action.clone()));
+                }
+                let new_body = new_body.arc();
                 //build hook log write futures
-                let mut hook_writes = Vec::new();
-                for (action, urls) in action_urls {
-                    let mut new_body = body.clone();
-                    if let Some(obj) = new_body.as_object_mut() {
-                        obj.insert("action".into(), serde_json::Value::String(action.clone()));
-                    }
-                    if urls.len() == 1 {
-                        log::debug!("action: {}, url: {:?}", action, urls[0]);
-                        hook_writes.push(Self::write(
+                let writes = urls
+                    .iter()
+                    .map(|url| {
+                        log::debug!("action: {}, url: {:?}", rule.action, url);
+                        Self::write(
                             writers.clone(),
                             backoff_strategy.clone(),
-                            urls[0].clone(),
-                            new_body.arc(),
+                            url.clone(),
+                            new_body.clone(),
                             cfg.http_timeout,
-                        ));
-                    } else {
-                        let new_body = new_body.arc();
-                        for url in urls {
-                            log::debug!("action: {}, url: {:?}", action, url);
-                            hook_writes.push(Self::write(
-                                writers.clone(),
-                                backoff_strategy.clone(),
-                                url.clone(),
-                                new_body.clone(),
-                                cfg.http_timeout,
-                            ));
-                        }
-                    }
-                }
-                Some(hook_writes)
-            } else {
-                None
+                            rule.max_latency,
+                            breaker(&cfg, url),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                actions.push((delay, writes));
             }
+            actions
         };
         //send hook_writes
-        if let Some(mut hook_writes) = hook_writes {
-            let c = hook_writes.len();
-            match c {
-                0 => {}
-                1 => {
-                    hook_writes.remove(0).await;
-                }
-                _ => {
-                    let _ = futures::future::join_all(hook_writes).await;
-                }
+        futures::future::join_all(actions.into_iter().map(|(delay, writes)| async move {
+            if !delay.is_zero() {
+                time::sleep(delay).await;
             }
-        }
+            futures::future::join_all(writes).await;
+        }))
+        .await;
 
         Ok(())
     }
@@ -435,6 +492,38 @@ impl WebHookHandler {
         url: Url,
         body: Arc<serde_json::Value>,
         timeout: Duration,
+        max_latency: Duration,
+        breaker: Option<Arc<CircuitBreaker>>,
+    ) {
+        if max_latency.is_zero() {
+            Self::_write(writers, backoff_strategy, url, body, timeout, breaker).await;
+        } else if time::timeout(
+            max_latency,
+            Self::_write(writers, backoff_strategy, url.clone(), body, timeout, breaker.clone()),
+        )
+        .await
+        .is_err()
+        {
+            fails().current_inc();
+            if let Some(breaker) = breaker {
+                breaker.failure();
+            }
+            log::warn!(
+                "send web hook message failure, url: {}, max latency {:?} exceeded",
+                url.loc,
+                max_latency
+            );
+        }
+    }
+
+    #[inline]
+    async fn _write(
+        writers: HookWriters,
+        backoff_strategy: Arc<ExponentialBackoff>,
+        url: Url,
+        body: Arc<serde_json::Value>,
+        timeout: Duration,
+        breaker: Option<Arc<CircuitBreaker>>,
     ) {
         if url.is_file() {
             //is file
@@ -459,7 +548,7 @@ impl WebHookHandler {
             log::debug!("writer.log end ... ");
         } else {
             //is http
-            Self::http_request(backoff_strategy, url, body, timeout).await;
+            Self::http_request(backoff_strategy, url, body, timeout, breaker).await;
         }
     }
 
@@ -468,9 +557,36 @@ impl WebHookHandler {
         url: Url,
         body: Arc<serde_json::Value>,
         timeout: Duration,
+        breaker: Option<Arc<CircuitBreaker>>,
     ) {
+        if let Some(breaker) = &breaker {
+            if !breaker.allow() {
+                drops().current_inc();
+                log::debug!("circuit of {} is open, dropped", url.loc);
+                return;
+            }
+        }
         if let Err(e) = retry(backoff_strategy.as_ref().clone(), || async {
-            Ok(Self::_http_request(&url.loc, body.clone(), timeout).await?)
+            //the retries stop as soon as the circuit opens
+            if let Some(breaker) = &breaker {
+                if !breaker.allow() {
+                    return Err(backoff::Error::permanent(MqttError::from("circuit breaker is open")));
+                }
+            }
+            match Self::_http_request(&url.loc, body.clone(), timeout).await {
+                Ok(()) => {
+                    if let Some(breaker) = &breaker {
+                        breaker.success();
+                    }
+                    Ok(())
+                }
+                Err(e) => {
+                    if let Some(breaker) = &breaker {
+                        breaker.failure();
+                    }
+                    Err(backoff::Error::transient(e))
+                }
+            }
         })
         .await
         {
@@ -514,10 +630,11 @@ impl Handler for WebHookHandler {
                 }
                 Some((None, body))
             }
-            Parameter::ClientConnack(conn_info, conn_ack) => {
+            Parameter::ClientConnack(conn_info, conn_ack, session_present) => {
                 let mut body = conn_info.to_hook_body();
                 if let Some(obj) = body.as_object_mut() {
                     obj.insert("conn_ack".into(), serde_json::Value::String(conn_ack.reason().to_string()));
+                    obj.insert("session_present".into(), serde_json::Value::Bool(session_present.present));
                     obj.insert("time".into(), serde_json::Value::String(now_time));
                 }
                 Some((None, body))
@@ -554,6 +671,42 @@ impl Handler for WebHookHandler {
                 Some((None, body))
             }
 
+            Parameter::ClientKeepaliveTimeout(session) => {
+                let body = json!({
+                    "node": session.id.node(),
+                    "ipaddress": session.id.remote_addr,
+                    "clientid": session.id.client_id,
+                    "username": session.id.username_ref(),
+                    "keepalive": session.connect_info().await.map(|c| c.keep_alive()).unwrap_or_default(),
+                    "time": now_time
+                });
+                Some((None, body))
+            }
+
+            Parameter::ClientAlive(session, ping) => {
+                let body = json!({
+                    "node": session.id.node(),
+                    "ipaddress": session.id.remote_addr,
+                    "clientid": session.id.client_id,
+                    "username": session.id.username_ref(),
+                    "ping": ping,
+                    "time": now_time
+                });
+                Some((None, body))
+            }
+
+            Parameter::ClientProtocolViolation(session, reason) => {
+                let body = json!({
+                    "node": session.id.node(),
+                    "ipaddress": session.id.remote_addr,
+                    "clientid": session.id.client_id,
+                    "username": session.id.username_ref(),
+                    "reason": reason,
+                    "time": now_time
+                });
+                Some((None, body))
+            }
+
             Parameter::ClientSubscribe(session, subscribe) => {
                 let body = json!({
                     "node": session.id.node(),
@@ -605,12 +758,27 @@ impl Handler for WebHookHandler {
                 Some((Some(topic), body))
             }
 
-            Parameter::SessionCreated(session) => {
+            Parameter::SessionCreated(session, clean_start) => {
+                let body = json!({
+                    "node": session.id.node(),
+                    "ipaddress": session.id.remote_addr,
+                    "clientid": session.id.client_id,
+                    "username": session.id.username_ref(),
+                    "clean_start": clean_start,
+                    "created_at": session.created_at().await.unwrap_or_default(),
+                    "time": now_time
+                });
+                Some((None, body))
+            }
+
+            Parameter::SessionResumed(session, subscriptions, messages) => {
                 let body = json!({
                     "node": session.id.node(),
                     "ipaddress": session.id.remote_addr,
                     "clientid": session.id.client_id,
                     "username": session.id.username_ref(),
+                    "subscriptions": subscriptions,
+                    "messages": messages,
                     "created_at": session.created_at().await.unwrap_or_default(),
                     "time": now_time
                 });
@@ -729,7 +897,7 @@ impl Handler for WebHookHandler {
             }
         }
 
-        (true, acc)
+        (Proceed::Continue, acc)
     }
 }
 
@@ -787,3 +955,33 @@ pub(crate) fn fails() -> &'static Counter {
     static INSTANCE: OnceCell<Counter> = OnceCell::new();
     INSTANCE.get_or_init(Counter::new)
 }
+
+//Count of the actions dropped by the rate limits of the rules or by open circuits
+#[inline]
+pub(crate) fn drops() -> &'static Counter {
+    static INSTANCE: OnceCell<Counter> = OnceCell::new();
+    INSTANCE.get_or_init(Counter::new)
+}
+
+//Circuit breakers of the http urls
+#[inline]
+fn breakers() -> &'static DashMap<ByteString, Arc<CircuitBreaker>> {
+    static INSTANCE: OnceCell<DashMap<ByteString, Arc<CircuitBreaker>>> = OnceCell::new();
+    INSTANCE.get_or_init(DashMap::default)
+}
+
+#[inline]
+fn breaker(cfg: &PluginConfig, url: &Url) -> Option<Arc<CircuitBreaker>> {
+    if url.is_file() || cfg.circuit_breaker_failures == 0 {
+        return None;
+    }
+    let breaker = breakers().entry(url.loc.clone()).or_insert_with(|| {
+        Arc::new(CircuitBreaker::new(cfg.circuit_breaker_failures, cfg.circuit_breaker_reset))
+    });
+    Some(breaker.value().clone())
+}
+
+#[inline]
+fn open_circuits() -> Vec<String> {
+    breakers().iter().filter(|e| e.value().is_open()).map(|e| e.key().to_string()).collect()
+}
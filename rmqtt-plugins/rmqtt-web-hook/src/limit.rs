@@ -0,0 +1,106 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+///Execution budget of a rule, a rate limit with a burst of one second worth of evaluations
+#[derive(Debug, Default)]
+pub struct RuleBudget {
+    //theoretical arrival time of the next evaluation
+    tat: Mutex<Option<Instant>>,
+}
+
+impl RuleBudget {
+    ///Takes a slot for one evaluation, `rate` is the number of evaluations per second.
+    ///
+    ///Returns how long the evaluation has to wait for its slot. If `wait` is false, no slot is taken
+    ///and None is returned when the budget is used up.
+    pub fn acquire(&self, rate: u32, wait: bool) -> Option<Duration> {
+        let interval = Duration::from_secs(1) / rate.max(1);
+        let now = Instant::now();
+        let mut tat = self.tat.lock().unwrap_or_else(|e| e.into_inner());
+        let next = tat.map(|t| t.max(now)).unwrap_or(now) + interval;
+        let delay = next.saturating_duration_since(now + Duration::from_secs(1));
+        if delay.is_zero() || wait {
+            *tat = Some(next);
+            Some(delay)
+        } else {
+            None
+        }
+    }
+}
+
+///Circuit breaker of a webhook target.
+///
+///The circuit opens after `failures` consecutive failed requests, while it is open the requests to
+///the target are dropped. After `reset` requests are let through again, the first success closes the
+///circuit and each failure opens it for another `reset`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failures: usize,
+    reset: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: usize,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failures: usize, reset: Duration) -> Self {
+        Self { failures, reset, state: Mutex::new(BreakerState::default()) }
+    }
+
+    ///Whether a request may be sent to the target
+    #[inline]
+    pub fn allow(&self) -> bool {
+        self.state().open_until.map(|until| Instant::now() >= until).unwrap_or(true)
+    }
+
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.state().open_until.is_some()
+    }
+
+    #[inline]
+    pub fn success(&self) {
+        let mut state = self.state();
+        state.failures = 0;
+        state.open_until = None;
+    }
+
+    #[inline]
+    pub fn failure(&self) {
+        let mut state = self.state();
+        state.failures += 1;
+        if state.failures >= self.failures {
+            state.open_until = Some(Instant::now() + self.reset);
+        }
+    }
+
+    #[inline]
+    fn state(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_and_breaker() {
+        let budget = RuleBudget::default();
+        assert!((0..10).all(|_| budget.acquire(10, false) == Some(Duration::ZERO)));
+        assert_eq!(budget.acquire(10, false), None);
+        assert!(budget.acquire(10, true).map(|d| d > Duration::ZERO).unwrap_or_default());
+
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.failure();
+        assert!(breaker.allow());
+        breaker.failure();
+        assert!(breaker.is_open() && !breaker.allow());
+        breaker.success();
+        assert!(!breaker.is_open() && breaker.allow());
+    }
+}
@@ -0,0 +1,50 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+use crate::broker::types::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Banned {
+    ClientId(ClientId),
+    Addr(IpAddr),
+}
+
+//Banned clients of this node and when the ban ends
+static BANNED: Lazy<DashMap<Banned, Instant>> = Lazy::new(DashMap::default);
+
+///Applies the action returned by the ClientProtocolViolation hook to the client
+#[inline]
+pub(crate) fn apply(id: &Id, action: ProtocolViolationAction) {
+    match action {
+        ProtocolViolationAction::Disconnect => {}
+        ProtocolViolationAction::BanClientId(duration) => {
+            log::info!("{:?} client id banned for {:?}", id, duration);
+            ban(Banned::ClientId(id.client_id.clone()), duration);
+        }
+        ProtocolViolationAction::BanAddr(duration) => {
+            if let Some(addr) = id.remote_addr {
+                log::info!("{:?} remote address banned for {:?}", id, duration);
+                ban(Banned::Addr(addr.ip()), duration);
+            }
+        }
+    }
+}
+
+#[inline]
+fn ban(key: Banned, duration: Duration) {
+    BANNED.insert(key, Instant::now() + duration);
+}
+
+///Whether the client id or the remote address of the connecting client is banned
+#[inline]
+pub(crate) fn is_banned(id: &Id) -> bool {
+    if BANNED.is_empty() {
+        return false;
+    }
+    let now = Instant::now();
+    BANNED.retain(|_, until| *until > now);
+    BANNED.contains_key(&Banned::ClientId(id.client_id.clone()))
+        || id.remote_addr.map(|addr| BANNED.contains_key(&Banned::Addr(addr.ip()))).unwrap_or_default()
+}
@@ -0,0 +1,107 @@
+//! Payload compression of bridged messages.
+//!
+//! An egress bridge compresses the payload and marks the message with the [`COMPRESSION_MARKER`]
+//! user property (MQTT 5.0) or header (Kafka) naming the algorithm. An ingress bridge that receives a
+//! marked message decompresses the payload and removes the marker before the message is published
+//! locally, so neither publishers nor subscribers see the compressed form.
+
+use std::io::{Read, Write};
+
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::{MqttError, Publish, Result};
+
+pub const COMPRESSION_MARKER: &str = "rmqtt-compression";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    ///The value of the compression marker
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    #[inline]
+    pub fn from_marker(marker: &str) -> Option<Self> {
+        match marker {
+            "gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        matches!(self, Compression::None)
+    }
+
+    ///Compresses the data, the level is 0-9 for gzip and 1-22 for zstd, each has its own default
+    #[inline]
+    pub fn compress(&self, data: &[u8], level: Option<i32>) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => {
+                let level =
+                    level.map(|level| flate2::Compression::new(level.clamp(0, 9) as u32)).unwrap_or_default();
+                let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), level);
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Compression::Zstd => {
+                Ok(zstd::bulk::compress(data, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?)
+            }
+        }
+    }
+
+    ///Decompresses the data, fails if the decompressed size exceeds `limit` bytes
+    #[inline]
+    pub fn decompress(&self, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::Gzip => {
+                GzDecoder::new(data).take(limit as u64 + 1).read_to_end(&mut out)?;
+            }
+            Compression::Zstd => {
+                zstd::stream::read::Decoder::new(data)?.take(limit as u64 + 1).read_to_end(&mut out)?;
+            }
+        }
+        if out.len() > limit {
+            return Err(MqttError::from(format!("decompressed payload exceeds {} bytes", limit)));
+        }
+        Ok(out)
+    }
+}
+
+///Decompresses the payload of a message received by an ingress bridge if it carries the compression
+///marker, the marker is removed. Messages without the marker are left unchanged
+#[inline]
+pub fn decompress_publish(p: &mut Publish, limit: usize) -> Result<()> {
+    let marker = p
+        .properties
+        .user_properties
+        .iter()
+        .find(|(k, _)| k.as_ref() == COMPRESSION_MARKER)
+        .map(|(_, v)| v.clone());
+    if let Some(marker) = marker {
+        let compression = Compression::from_marker(&marker)
+            .ok_or_else(|| MqttError::from(format!("unsupported payload compression, {}", marker)))?;
+        p.payload = Bytes::from(compression.decompress(&p.payload, limit)?);
+        p.properties.user_properties.retain(|(k, _)| k.as_ref() != COMPRESSION_MARKER);
+    }
+    Ok(())
+}
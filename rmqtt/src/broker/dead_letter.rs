@@ -0,0 +1,89 @@
+use bytestring::ByteString;
+
+use crate::broker::session::SessionState;
+use crate::broker::translate;
+use crate::broker::types::*;
+use crate::Runtime;
+
+#[inline]
+fn dead_letter_topic() -> Option<String> {
+    Runtime::instance().settings.latest_mqtt().dead_letter_topic.clone().filter(|t| !t.is_empty())
+}
+
+///Whether messages that have no subscribers are sent to the dead-letter topic
+#[inline]
+pub(crate) fn nonsubscribed_enable() -> bool {
+    Runtime::instance().settings.latest_mqtt().dead_letter_nonsubscribed && dead_letter_topic().is_some()
+}
+
+//The dead-letter topic or one of its sub-levels, the prefix only matches on a topic level boundary
+#[inline]
+fn is_dead_letter(topic: &str, dl_topic: &str) -> bool {
+    topic
+        .strip_prefix(dl_topic)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/') || dl_topic.ends_with('/'))
+}
+
+///Republish a dropped message to the dead-letter topic, the original topic, the drop reason
+///and the sender/receiver are attached as user properties.
+#[inline]
+pub(crate) fn forward(to: Option<&To>, from: &From, publish: &Publish, reason: &Reason) {
+    let dl_topic = if let Some(dl_topic) = dead_letter_topic() {
+        dl_topic
+    } else {
+        return;
+    };
+
+    //messages from the dead-letter topic itself are never republished
+    if is_dead_letter(&publish.topic, &dl_topic) {
+        return;
+    }
+
+    let mut p = publish.clone();
+    p.dup = false;
+    p.retain = false;
+    p.packet_id = None;
+    p.delay_interval = None;
+    p.properties = translate::forward_properties(p.properties);
+    p.topic = ByteString::from(dl_topic);
+    p.create_time = timestamp_millis();
+
+    let user_props = p.user_properties_mut();
+    user_props.push((ByteString::from_static("dead_letter_reason"), ByteString::from(reason.to_string())));
+    user_props.push((ByteString::from_static("dead_letter_topic"), publish.topic.clone()));
+    user_props.push((ByteString::from_static("dead_letter_from_clientid"), from.client_id.clone()));
+    if let Some(to) = to {
+        user_props.push((ByteString::from_static("dead_letter_to_clientid"), to.client_id.clone()));
+    }
+    user_props
+        .push((ByteString::from_static("dead_letter_time"), ByteString::from(p.create_time.to_string())));
+
+    let from = From::from_system(Id::new(
+        Runtime::instance().node.id(),
+        None,
+        None,
+        ClientId::from_static("system"),
+        Some(UserName::from("system")),
+    ));
+
+    //spawned, a drop may occur while forwarding
+    tokio::spawn(async move {
+        if let Err(e) = SessionState::forwards(from, p, false, false, None).await {
+            log::warn!("dead-letter forward error, {:?}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_dead_letter;
+
+    #[test]
+    fn dead_letter_topic_boundary() {
+        assert!(is_dead_letter("$dead_letter", "$dead_letter"));
+        assert!(is_dead_letter("$dead_letter/a", "$dead_letter"));
+        assert!(is_dead_letter("$dead_letter/a", "$dead_letter/"));
+        assert!(!is_dead_letter("$dead_letter_foo", "$dead_letter"));
+        assert!(!is_dead_letter("a/$dead_letter", "$dead_letter"));
+    }
+}
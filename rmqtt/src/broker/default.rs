@@ -2,11 +2,15 @@ use std::collections::{BTreeMap, BinaryHeap};
 use std::convert::From as _f;
 use std::num::NonZeroU16;
 use std::num::NonZeroU32;
+use std::panic::AssertUnwindSafe;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 #[allow(unused_imports)]
 use bitflags::Flags;
+use bytestring::ByteString;
+use futures::FutureExt;
 use itertools::Itertools;
 use ntex_mqtt::types::{MQTT_LEVEL_31, MQTT_LEVEL_311, MQTT_LEVEL_5};
 use once_cell::sync::OnceCell;
@@ -16,19 +20,25 @@ use tokio::sync::{self, Mutex, OwnedMutexGuard};
 use tokio::time::Duration;
 use uuid::Uuid;
 
+use crate::broker::banned;
+use crate::broker::dead_letter;
+use crate::broker::events;
 use crate::broker::fitter::{Fitter, FitterManager};
-use crate::broker::hook::{Handler, Hook, HookManager, HookResult, Parameter, Priority, Register, Type};
+use crate::broker::hook::{
+    Handler, Hook, HookManager, HookResult, Parameter, Priority, Proceed, Register, ReturnType, Type,
+};
 use crate::broker::inflight::InflightMessage;
 use crate::broker::session::{Session, SessionLike, SessionManager, SessionOfflineInfo};
 use crate::broker::topic::{Topic, VecToTopic};
 use crate::broker::types::*;
+use crate::metrics::Metrics;
 use crate::settings::listener::Listener;
 use crate::stats::Counter;
 use crate::{grpc, MqttError, Result, Runtime, SessionState};
 
 use super::{
-    retain::RetainTree, topic::TopicTree, AutoSubscription, DelayedSender, Entry, RetainStorage, Router,
-    Shared, SharedSubscription,
+    retain::RetainTree, topic::TopicTree, AutoSubscription, CertResolver, ClientEnricher, DelayedSender,
+    Entry, KeyLoader, KvStore, RetainStorage, Router, Shared, SharedSubscription,
 };
 
 type DashSet<V> = dashmap::DashSet<V, ahash::RandomState>;
@@ -45,7 +55,8 @@ impl Drop for LockEntry {
     #[inline]
     fn drop(&mut self) {
         if self._locker.is_some() {
-            let _t = self.shared.lockers.remove(&self.id.client_id);
+            //the locker is kept while other connects wait for it, the map and this guard hold two references
+            let _t = self.shared.lockers.remove_if(&self.id.client_id, |_, l| Arc::strong_count(l) <= 2);
             log::debug!("{:?} LockEntry Drop ..., {}", self.id, _t.is_some());
         }
     }
@@ -130,6 +141,17 @@ impl super::Entry for LockEntry {
         Ok(Box::new(LockEntry::new(self.id.clone(), self.shared, Some(locker))))
     }
 
+    #[inline]
+    async fn lock(&self, timeout: Duration) -> Result<Box<dyn Entry>> {
+        log::debug!("{:?} LockEntry.lock", self.id);
+        let locker =
+            self.shared.lockers.entry(self.id.client_id.clone()).or_insert(Arc::new(Mutex::new(()))).clone();
+        let locker = tokio::time::timeout(timeout, locker.lock_owned()).await.map_err(|_| {
+            MqttError::Msg(format!("another connect of the client id is in progress, waited {:?}", timeout))
+        })?;
+        Ok(Box::new(LockEntry::new(self.id.clone(), self.shared, Some(locker))))
+    }
+
     #[inline]
     fn id(&self) -> Id {
         self.id.clone()
@@ -171,13 +193,21 @@ impl super::Entry for LockEntry {
             is_admin
         );
 
+        //the state of a clean session is not taken over, its subscriptions are cleared
+        let mut prev_clean_session = false;
         if let Some(peer_tx) = self.tx().and_then(|tx| if tx.is_closed() { None } else { Some(tx) }) {
             let (tx, rx) = oneshot::channel();
             if let Ok(()) = peer_tx.unbounded_send(Message::Kick(tx, self.id.clone(), clean_start, is_admin))
             {
                 match tokio::time::timeout(Duration::from_secs(5), rx).await {
-                    Ok(Ok(())) => {
-                        log::debug!("{:?} kicked, from {:?}", self.id, self.session().map(|s| s.id.clone()));
+                    Ok(Ok(ack)) => {
+                        log::debug!(
+                            "{:?} kicked, from {:?}, {:?}",
+                            self.id,
+                            self.session().map(|s| s.id.clone()),
+                            ack
+                        );
+                        prev_clean_session = ack.clean_session;
                     }
                     Ok(Err(e)) => {
                         log::warn!(
@@ -199,8 +229,8 @@ impl super::Entry for LockEntry {
             }
         }
 
-        if let Some((s, _)) = self._remove(clear_subscriptions).await {
-            if clean_start {
+        if let Some((s, _)) = self._remove(clear_subscriptions || prev_clean_session).await {
+            if clean_start || prev_clean_session {
                 Ok(None)
             } else {
                 match s.to_offline_info().await {
@@ -456,6 +486,7 @@ impl Shared for &'static DefaultShared {
         };
 
         let sub_client_ids = self._collect_subscription_client_ids(&relations_map);
+        Runtime::instance().stats.shared_groups_dispatched(&relations_map);
 
         let this_node_id = Runtime::instance().node.id();
         if let Some(relations) = relations_map.remove(&this_node_id) {
@@ -1311,7 +1342,9 @@ impl Fitter for DefaultFitter {
             )));
         }
 
-        if *keep_alive < 6 {
+        if let Some(factor) = self.listen_cfg.keepalive_factor {
+            Ok((*keep_alive as f32 * factor).max(*keep_alive as f32).ceil() as u16)
+        } else if *keep_alive < 6 {
             Ok(*keep_alive + 3)
         } else {
             Ok(((*keep_alive as f32 * self.listen_cfg.keepalive_backoff) * 2.0) as u16)
@@ -1353,7 +1386,7 @@ impl Fitter for DefaultFitter {
             }
         };
 
-        if let Some(Disconnect::V5(d)) = d {
+        let interval = if let Some(Disconnect::V5(d)) = d {
             if let Some(interval_secs) = d.session_expiry_interval_secs {
                 Duration::from_secs(interval_secs as u64)
             } else {
@@ -1361,6 +1394,13 @@ impl Fitter for DefaultFitter {
             }
         } else {
             expiry_interval()
+        };
+
+        let max_interval = self.listen_cfg.max_session_expiry_interval;
+        if !max_interval.is_zero() && interval > max_interval {
+            max_interval
+        } else {
+            interval
         }
     }
 
@@ -1397,11 +1437,41 @@ impl Fitter for DefaultFitter {
 struct HookEntry {
     handler: Box<dyn Handler>,
     enabled: bool,
+    //set after a panic when plugins.disable_panicked_handler is true
+    panicked: AtomicBool,
 }
 
 impl HookEntry {
     fn new(handler: Box<dyn Handler>) -> Self {
-        Self { handler, enabled: false }
+        Self { handler, enabled: false, panicked: AtomicBool::new(false) }
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.enabled && !self.panicked.load(Ordering::Relaxed)
+    }
+
+    ///Calls the handler, a panic is caught and logged instead of unwinding into the connection task.
+    ///The accumulated result is lost on panic, the chain continues with None.
+    #[inline]
+    async fn call(&self, t: Type, p: &Parameter<'_>, acc: Option<HookResult>) -> ReturnType {
+        match AssertUnwindSafe(self.handler.hook(p, acc)).catch_unwind().await {
+            Ok(ret) => ret,
+            Err(e) => {
+                let reason = e
+                    .downcast_ref::<&str>()
+                    .map(|r| r.to_string())
+                    .or_else(|| e.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                log::error!("{:?} handler {} panicked, {}", t, self.handler.name(), reason);
+                Metrics::instance().hook_handler_panic_inc();
+                if Runtime::instance().settings.plugins.disable_panicked_handler {
+                    log::warn!("{:?} handler {} is disabled", t, self.handler.name());
+                    self.panicked.store(true, Ordering::Relaxed);
+                }
+                (Proceed::Continue, None)
+            }
+        }
     }
 }
 
@@ -1436,6 +1506,14 @@ impl DefaultHookManager {
         }
     }
 
+    ///Whether a handler has ever been registered for the hook type, used to skip hooks on hot paths
+    #[inline]
+    fn is_hooked(&self, t: Type) -> bool {
+        self.handlers.contains_key(&t)
+    }
+
+    ///Executes the enabled handlers in descending priority order. The result returned by a handler
+    ///is passed to the next one, Proceed::Stop ends the chain and its result is returned as is.
     #[inline]
     async fn exec<'a>(&'a self, t: Type, p: Parameter<'a>) -> Option<HookResult> {
         let mut acc = None;
@@ -1443,9 +1521,9 @@ impl DefaultHookManager {
         if let Some(type_handlers) = type_handlers {
             let type_handlers = type_handlers.read().await;
             for (_, entry) in type_handlers.iter().rev() {
-                if entry.enabled {
-                    let (proceed, new_acc) = entry.handler.hook(&p, acc).await;
-                    if !proceed {
+                if entry.is_enabled() {
+                    let (proceed, new_acc) = entry.call(t, &p, acc).await;
+                    if proceed.is_stop() {
                         return new_acc;
                     }
                     acc = new_acc;
@@ -1454,6 +1532,61 @@ impl DefaultHookManager {
         }
         acc
     }
+
+    ///Executes the MessagePublish/MessageDelivered handlers, a Publish returned by a handler
+    ///replaces the message passed to the subsequent handlers. For MessageDelivered the packet_id
+    ///and inflight entry are already decided, so the QoS and packet_id of the original are kept.
+    #[inline]
+    async fn exec_publish(
+        &self,
+        t: Type,
+        s: Option<&Session>,
+        from: From,
+        publish: &Publish,
+    ) -> Option<Publish> {
+        let mut acc = None;
+        let mut modified: Option<Publish> = None;
+        let type_handlers = { self.handlers.get(&t).map(|h| (*h.value()).clone()) };
+        if let Some(type_handlers) = type_handlers {
+            let type_handlers = type_handlers.read().await;
+            for (_, entry) in type_handlers.iter().rev() {
+                if !entry.is_enabled() {
+                    continue;
+                }
+                let p = modified.as_ref().unwrap_or(publish);
+                let param = match (t, s) {
+                    (Type::MessageDelivered, Some(s)) => Parameter::MessageDelivered(s, from.clone(), p),
+                    _ => Parameter::MessagePublish(s, from.clone(), p),
+                };
+                let (proceed, new_acc) = entry.call(t, &param, acc).await;
+                drop(param);
+                if let Some(HookResult::Publish(new_p)) = &new_acc {
+                    if new_p.topic.is_empty() || new_p.topic.contains(['+', '#']) {
+                        log::warn!("{:?} invalid topic of the modified message, {:?}", t, new_p.topic);
+                    } else {
+                        let mut new_p = new_p.clone();
+                        if t == Type::MessageDelivered
+                            && (new_p.qos != publish.qos || new_p.packet_id != publish.packet_id)
+                        {
+                            log::warn!(
+                                "{:?} QoS and packet_id of the delivered message can not be modified, {:?}",
+                                t,
+                                new_p
+                            );
+                            new_p.qos = publish.qos;
+                            new_p.packet_id = publish.packet_id;
+                        }
+                        modified = Some(new_p);
+                    }
+                }
+                if proceed.is_stop() {
+                    break;
+                }
+                acc = new_acc;
+            }
+        }
+        modified
+    }
 }
 
 #[async_trait]
@@ -1489,7 +1622,7 @@ impl HookManager for &'static DefaultHookManager {
         &self,
         connect_info: &ConnectInfo,
         allow_anonymous: bool,
-    ) -> (ConnectAckReason, Superuser) {
+    ) -> (ConnectAckReason, Superuser, SessionAttrs) {
         let proto_ver = connect_info.proto_ver();
         let ok = || match proto_ver {
             MQTT_LEVEL_31 => ConnectAckReason::V3(ConnectAckReasonV3::ConnectionAccepted),
@@ -1499,8 +1632,9 @@ impl HookManager for &'static DefaultHookManager {
         };
 
         log::debug!("{:?} username: {:?}", connect_info.id(), connect_info.username());
+        let anonymous = || SessionAttrs::default().with(ATTR_AUTH, AUTH_ANONYMOUS);
         if connect_info.username().is_none() && allow_anonymous {
-            return (ok(), false);
+            return (ok(), false, anonymous());
         }
 
         let result = self.exec(Type::ClientAuthenticate, Parameter::ClientAuthenticate(connect_info)).await;
@@ -1508,11 +1642,13 @@ impl HookManager for &'static DefaultHookManager {
         let (bad_user_or_pass, not_auth) = match result {
             Some(HookResult::AuthResult(AuthResult::BadUsernameOrPassword)) => (true, false),
             Some(HookResult::AuthResult(AuthResult::NotAuthorized)) => (false, true),
-            Some(HookResult::AuthResult(AuthResult::Allow(superuser))) => return (ok(), superuser),
+            Some(HookResult::AuthResult(AuthResult::Allow(superuser, attrs))) => {
+                return (ok(), superuser, attrs.unwrap_or_default().with(ATTR_AUTH, AUTH_AUTHENTICATED))
+            }
             _ => {
                 //or AuthResult::NotFound
                 if allow_anonymous {
-                    return (ok(), false);
+                    return (ok(), false, anonymous());
                 } else {
                     (false, true)
                 }
@@ -1528,6 +1664,7 @@ impl HookManager for &'static DefaultHookManager {
                     _ => ConnectAckReason::V3(ConnectAckReasonV3::BadUserNameOrPassword),
                 },
                 false,
+                SessionAttrs::default(),
             );
         }
 
@@ -1540,10 +1677,28 @@ impl HookManager for &'static DefaultHookManager {
                     _ => ConnectAckReason::V3(ConnectAckReasonV3::NotAuthorized),
                 },
                 false,
+                SessionAttrs::default(),
             );
         }
 
-        (ok(), false)
+        (ok(), false, SessionAttrs::default())
+    }
+
+    #[inline]
+    async fn client_quota_check(&self, connect_info: &ConnectInfo) -> Option<String> {
+        //the cluster totals are only collected if a quota plugin is registered
+        if !self.is_hooked(Type::ClientQuotaCheck) {
+            return None;
+        }
+        let totals = Runtime::instance().extends.shared().await.cluster_totals().await;
+        let result =
+            self.exec(Type::ClientQuotaCheck, Parameter::ClientQuotaCheck(connect_info, totals)).await;
+        log::debug!("{:?} totals: {:?}, result: {:?}", connect_info.id(), totals, result);
+        if let Some(HookResult::QuotaExceeded(reason)) = result {
+            Some(reason)
+        } else {
+            None
+        }
     }
 
     ///When sending mqtt:: connectack message
@@ -1551,30 +1706,75 @@ impl HookManager for &'static DefaultHookManager {
         &self,
         connect_info: &ConnectInfo,
         return_code: ConnectAckReason,
-    ) -> ConnectAckReason {
-        let result =
-            self.exec(Type::ClientConnack, Parameter::ClientConnack(connect_info, &return_code)).await;
+        session_present: SessionPresent,
+    ) -> (ConnectAckReason, SessionPresent) {
+        let result = self
+            .exec(Type::ClientConnack, Parameter::ClientConnack(connect_info, &return_code, session_present))
+            .await;
         log::debug!("{:?} result: {:?}", connect_info.id(), result);
-        if let Some(HookResult::ConnectAckReason(new_return_code)) = result {
-            new_return_code
-        } else {
-            return_code
+        match result {
+            Some(HookResult::ConnectAckReason(new_return_code)) => (new_return_code, session_present),
+            Some(HookResult::SessionPresent(present)) => {
+                if !session_present.override_allowed(present) {
+                    log::warn!(
+                        "{:?} session present override is ignored, no session state is taken over, {:?}",
+                        connect_info.id(),
+                        session_present
+                    );
+                }
+                let new_session_present = session_present.with_override(present);
+                if new_session_present.overridden {
+                    log::info!(
+                        "{:?} session present is overridden, {:?}",
+                        connect_info.id(),
+                        new_session_present
+                    );
+                }
+                (return_code, new_session_present)
+            }
+            _ => (return_code, session_present),
         }
     }
 
     #[inline]
     async fn message_publish(&self, s: Option<&Session>, from: From, publish: &Publish) -> Option<Publish> {
-        let result = self.exec(Type::MessagePublish, Parameter::MessagePublish(s, from, publish)).await;
-        if let Some(HookResult::Publish(publish)) = result {
-            Some(publish)
+        self.exec_publish(Type::MessagePublish, s, from, publish).await
+    }
+
+    #[inline]
+    async fn client_subscribe_check_acl(&self, s: &Session, sub: &Subscribe) -> Option<SubscribeAclResult> {
+        if s.superuser().await.unwrap_or_default() {
+            return Some(SubscribeAclResult::new_success(sub.opts.qos(), None));
+        }
+        let reply =
+            self.exec(Type::ClientSubscribeCheckAcl, Parameter::ClientSubscribeCheckAcl(s, sub)).await;
+        log::debug!("{:?} result: {:?}", s.id, reply);
+        if let Some(HookResult::SubscribeAclResult(r)) = reply {
+            Some(r)
         } else {
             None
         }
     }
 
+    #[inline]
+    async fn message_publish_check_acl(&self, s: &Session, publish: &Publish) -> PublishAclResult {
+        if s.superuser().await.unwrap_or_default() {
+            return PublishAclResult::Allow;
+        }
+        let result =
+            self.exec(Type::MessagePublishCheckAcl, Parameter::MessagePublishCheckAcl(s, publish)).await;
+        log::debug!("{:?} result: {:?}", s.id, result);
+        if let Some(HookResult::PublishAclResult(acl_result)) = result {
+            acl_result
+        } else {
+            PublishAclResult::Allow
+        }
+    }
+
     ///Publish message Dropped
     #[inline]
     async fn message_dropped(&self, to: Option<To>, from: From, publish: Publish, reason: Reason) {
+        dead_letter::forward(to.as_ref(), &from, &publish, &reason);
         let _ = self.exec(Type::MessageDropped, Parameter::MessageDropped(to, from, publish, reason)).await;
     }
 
@@ -1630,6 +1830,13 @@ impl DefaultHookRegister {
 impl Register for DefaultHookRegister {
     #[inline]
     async fn add_priority(&self, typ: Type, priority: Priority, handler: Box<dyn Handler>) {
+        let priority = match Runtime::instance().settings.plugins.hook_priority(handler.name(), typ) {
+            Some(p) => {
+                log::info!("{:?} handler {} priority {} overridden by {}", typ, handler.name(), priority, p);
+                p
+            }
+            None => priority,
+        };
         match self.manager.add(typ, priority, handler).await {
             Ok(id) => {
                 self.type_ids.insert((typ, (priority, id)));
@@ -1667,72 +1874,104 @@ impl DefaultHook {
 #[async_trait]
 impl Hook for DefaultHook {
     #[inline]
-    async fn session_created(&self) {
-        self.manager.exec(Type::SessionCreated, Parameter::SessionCreated(&self.s)).await;
+    async fn session_created(&self, clean_start: CleanStart) {
+        self.manager.exec(Type::SessionCreated, Parameter::SessionCreated(&self.s, clean_start)).await;
+    }
+
+    #[inline]
+    async fn session_resumed(&self, subscriptions: usize, messages: usize) {
+        self.manager
+            .exec(Type::SessionResumed, Parameter::SessionResumed(&self.s, subscriptions, messages))
+            .await;
     }
 
     #[inline]
     async fn client_connected(&self) {
         let _ = self.manager.exec(Type::ClientConnected, Parameter::ClientConnected(&self.s)).await;
+        events::client_connected(&self.s).await;
     }
 
     #[inline]
     async fn client_disconnected(&self, r: Reason) {
+        events::client_disconnected(&self.s, &r).await;
         let _ = self.manager.exec(Type::ClientDisconnected, Parameter::ClientDisconnected(&self.s, r)).await;
     }
 
     #[inline]
-    async fn session_terminated(&self, r: Reason) {
-        let _ = self.manager.exec(Type::SessionTerminated, Parameter::SessionTerminated(&self.s, r)).await;
+    async fn client_keepalive_timeout(&self) {
+        let _ =
+            self.manager.exec(Type::ClientKeepaliveTimeout, Parameter::ClientKeepaliveTimeout(&self.s)).await;
     }
 
     #[inline]
-    async fn client_subscribe_check_acl(&self, sub: &Subscribe) -> Option<SubscribeAclResult> {
-        if self.s.superuser().await.unwrap_or_default() {
-            return Some(SubscribeAclResult::new_success(sub.opts.qos(), None));
-        }
-        let reply = self
-            .manager
-            .exec(Type::ClientSubscribeCheckAcl, Parameter::ClientSubscribeCheckAcl(&self.s, sub))
-            .await;
-        log::debug!("{:?} result: {:?}", self.s.id, reply);
-        if let Some(HookResult::SubscribeAclResult(r)) = reply {
-            Some(r)
-        } else {
-            None
-        }
+    async fn client_alive(&self, ping: IsPing) {
+        let _ = self.manager.exec(Type::ClientAlive, Parameter::ClientAlive(&self.s, ping)).await;
     }
 
     #[inline]
-    async fn message_publish_check_acl(&self, publish: &Publish) -> PublishAclResult {
-        if self.s.superuser().await.unwrap_or_default() {
-            return PublishAclResult::Allow;
-        }
+    async fn client_protocol_violation(&self, reason: ByteString) -> ProtocolViolationAction {
         let result = self
             .manager
-            .exec(Type::MessagePublishCheckAcl, Parameter::MessagePublishCheckAcl(&self.s, publish))
+            .exec(Type::ClientProtocolViolation, Parameter::ClientProtocolViolation(&self.s, reason))
             .await;
         log::debug!("{:?} result: {:?}", self.s.id, result);
-        if let Some(HookResult::PublishAclResult(acl_result)) = result {
-            acl_result
+        let action = if let Some(HookResult::ProtocolViolationAction(action)) = result {
+            action
         } else {
-            PublishAclResult::Allow
+            ProtocolViolationAction::default()
+        };
+        banned::apply(&self.s.id, action);
+        action
+    }
+
+    #[inline]
+    fn is_hooked(&self, typ: Type) -> bool {
+        self.manager.is_hooked(typ)
+    }
+
+    #[inline]
+    async fn packet_received(&self, packet: &Packet) {
+        if self.manager.is_hooked(Type::PacketReceived) {
+            let _ = self.manager.exec(Type::PacketReceived, Parameter::PacketReceived(&self.s, packet)).await;
         }
     }
 
     #[inline]
-    async fn client_subscribe(&self, sub: &Subscribe) -> Option<TopicFilter> {
+    async fn packet_sent(&self, packet: &Packet) {
+        if self.manager.is_hooked(Type::PacketSent) {
+            let _ = self.manager.exec(Type::PacketSent, Parameter::PacketSent(&self.s, packet)).await;
+        }
+    }
+
+    #[inline]
+    async fn session_terminated(&self, r: Reason) {
+        let _ = self.manager.exec(Type::SessionTerminated, Parameter::SessionTerminated(&self.s, r)).await;
+    }
+
+    #[inline]
+    async fn client_subscribe_check_acl(&self, sub: &Subscribe) -> Option<SubscribeAclResult> {
+        self.manager.client_subscribe_check_acl(&self.s, sub).await
+    }
+
+    #[inline]
+    async fn message_publish_check_acl(&self, publish: &Publish) -> PublishAclResult {
+        self.manager.message_publish_check_acl(&self.s, publish).await
+    }
+
+    #[inline]
+    async fn client_subscribe(&self, sub: &Subscribe) -> (Option<TopicFilter>, Option<QoS>) {
         let reply = self.manager.exec(Type::ClientSubscribe, Parameter::ClientSubscribe(&self.s, sub)).await;
         log::debug!("{:?} result: {:?}", self.s.id, reply);
-        if let Some(HookResult::TopicFilter(Some(tf))) = reply {
-            Some(tf)
-        } else {
-            None
+        match reply {
+            Some(HookResult::TopicFilter(tf)) => (tf, None),
+            Some(HookResult::SubscribeQoS(tf, qos)) => (tf, Some(qos)),
+            _ => (None, None),
         }
     }
 
     #[inline]
     async fn session_subscribed(&self, subscribe: Subscribe) {
+        events::session_subscribed(&self.s, &subscribe);
         let _ = self
             .manager
             .exec(Type::SessionSubscribed, Parameter::SessionSubscribed(&self.s, subscribe))
@@ -1753,6 +1992,7 @@ impl Hook for DefaultHook {
 
     #[inline]
     async fn session_unsubscribed(&self, unsubscribe: Unsubscribe) {
+        events::session_unsubscribed(&self.s, &unsubscribe);
         let _ = self
             .manager
             .exec(Type::SessionUnsubscribed, Parameter::SessionUnsubscribed(&self.s, unsubscribe))
@@ -1766,16 +2006,9 @@ impl Hook for DefaultHook {
 
     #[inline]
     async fn message_delivered(&self, from: From, publish: &Publish) -> Option<Publish> {
-        let result = self
-            .manager
-            .exec(Type::MessageDelivered, Parameter::MessageDelivered(&self.s, from, publish))
-            .await;
+        let result = self.manager.exec_publish(Type::MessageDelivered, Some(&self.s), from, publish).await;
         log::debug!("{:?} result: {:?}", self.s.id, result);
-        if let Some(HookResult::Publish(publish)) = result {
-            Some(publish)
-        } else {
-            None
-        }
+        result
     }
 
     #[inline]
@@ -2162,7 +2395,7 @@ impl DelayedSender for &'static DefaultDelayedSender {
         message_expiry_interval: Option<Duration>,
     ) -> Result<Option<(From, Publish)>> {
         let mut msgs = self.msgs.write().await;
-        if msgs.len() < Runtime::instance().settings.mqtt.delayed_publish_max {
+        if msgs.len() < Runtime::instance().settings.latest_mqtt().delayed_publish_max {
             msgs.push(DelayedPublish::new(
                 from,
                 publish,
@@ -2195,3 +2428,72 @@ impl DefaultAutoSubscription {
 
 #[async_trait]
 impl AutoSubscription for &'static DefaultAutoSubscription {}
+
+pub struct DefaultClientEnricher {}
+
+impl DefaultClientEnricher {
+    #[inline]
+    pub fn instance() -> &'static DefaultClientEnricher {
+        static INSTANCE: OnceCell<DefaultClientEnricher> = OnceCell::new();
+        INSTANCE.get_or_init(|| Self {})
+    }
+}
+
+#[async_trait]
+impl ClientEnricher for &'static DefaultClientEnricher {}
+
+pub struct DefaultCertResolver {}
+
+impl DefaultCertResolver {
+    #[inline]
+    pub fn instance() -> &'static DefaultCertResolver {
+        static INSTANCE: OnceCell<DefaultCertResolver> = OnceCell::new();
+        INSTANCE.get_or_init(|| Self {})
+    }
+}
+
+impl CertResolver for &'static DefaultCertResolver {}
+
+pub struct DefaultKeyLoader {}
+
+impl DefaultKeyLoader {
+    #[inline]
+    pub fn instance() -> &'static DefaultKeyLoader {
+        static INSTANCE: OnceCell<DefaultKeyLoader> = OnceCell::new();
+        INSTANCE.get_or_init(|| Self {})
+    }
+}
+
+impl KeyLoader for &'static DefaultKeyLoader {}
+
+pub struct DefaultKvStore {
+    values: DashMap<(String, String), Vec<u8>>,
+}
+
+impl DefaultKvStore {
+    #[inline]
+    pub fn instance() -> &'static DefaultKvStore {
+        static INSTANCE: OnceCell<DefaultKvStore> = OnceCell::new();
+        INSTANCE.get_or_init(|| Self { values: DashMap::default() })
+    }
+}
+
+#[async_trait]
+impl KvStore for &'static DefaultKvStore {
+    #[inline]
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.values.get(&(namespace.to_owned(), key.to_owned())).map(|val| val.value().clone()))
+    }
+
+    #[inline]
+    async fn set(&self, namespace: &str, key: &str, val: Vec<u8>) -> Result<()> {
+        self.values.insert((namespace.to_owned(), key.to_owned()), val);
+        Ok(())
+    }
+
+    #[inline]
+    async fn remove(&self, namespace: &str, key: &str) -> Result<()> {
+        self.values.remove(&(namespace.to_owned(), key.to_owned()));
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Encryption at rest of message payloads.
+//!
+//! The storage plugins encrypt the payload of a message with AES-256-GCM before it is written and
+//! decrypt it when it is read back. An encrypted payload carries the id of its key, so keys can be
+//! rotated: new payloads are encrypted with the first configured key, older ones are still decrypted
+//! as long as their key stays in the list. Payloads without the encryption header were stored before
+//! encryption was enabled and are returned unchanged.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{MqttError, Publish, Result};
+
+const MAGIC: &[u8] = b"RMQE\x01";
+const KEY_ID_LEN: usize = 2;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + KEY_ID_LEN + NONCE_LEN;
+
+pub type KeyId = u16;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EncryptionConfig {
+    ///Encrypt payloads before they are stored. When disabled, payloads that were stored encrypted are
+    ///still decrypted with the configured keys
+    #[serde(default)]
+    pub enable: bool,
+
+    ///The first key encrypts, all of them decrypt
+    #[serde(default)]
+    pub keys: Vec<KeyConfig>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct KeyConfig {
+    pub id: KeyId,
+    ///Base64 encoded 256-bit key, it is never serialized back
+    #[serde(skip_serializing, default)]
+    pub key: String,
+}
+
+impl fmt::Debug for KeyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KeyConfig {{ id: {}, key: *** }}", self.id)
+    }
+}
+
+///Keys built from an [`EncryptionConfig`], it is (de)serialized as the configuration so that plugins can
+///use it directly as a configuration item
+#[derive(Clone, Default)]
+pub struct PayloadCipher {
+    cfg: EncryptionConfig,
+    keys: Arc<HashMap<KeyId, Aes256Gcm>>,
+}
+
+impl fmt::Debug for PayloadCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.cfg.fmt(f)
+    }
+}
+
+impl Serialize for PayloadCipher {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.cfg.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PayloadCipher {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let cfg = EncryptionConfig::deserialize(deserializer)?;
+        PayloadCipher::try_from(&cfg).map_err(de::Error::custom)
+    }
+}
+
+impl TryFrom<&EncryptionConfig> for PayloadCipher {
+    type Error = MqttError;
+
+    #[inline]
+    fn try_from(cfg: &EncryptionConfig) -> Result<Self> {
+        if cfg.enable && cfg.keys.is_empty() {
+            return Err(MqttError::from("encryption is enabled but no key is configured"));
+        }
+        let mut keys = HashMap::default();
+        for key_cfg in cfg.keys.iter() {
+            let key = BASE64_STANDARD
+                .decode(key_cfg.key.as_bytes())
+                .map_err(|e| MqttError::from(format!("invalid encryption key {}, {:?}", key_cfg.id, e)))?;
+            if key.len() != 32 {
+                return Err(MqttError::from(format!(
+                    "invalid encryption key {}, 32 bytes are required, got {}",
+                    key_cfg.id,
+                    key.len()
+                )));
+            }
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+            if keys.insert(key_cfg.id, cipher).is_some() {
+                return Err(MqttError::from(format!("duplicate encryption key id {}", key_cfg.id)));
+            }
+        }
+        Ok(Self { cfg: cfg.clone(), keys: Arc::new(keys) })
+    }
+}
+
+impl PayloadCipher {
+    ///Encrypts with the current key, the data is returned unchanged when encryption is disabled
+    #[inline]
+    pub fn encrypt(&self, data: &Bytes) -> Result<Bytes> {
+        let key_id = match self.cfg.keys.first() {
+            Some(key_cfg) if self.cfg.enable => key_cfg.id,
+            _ => return Ok(data.clone()),
+        };
+        let cipher = self.keys.get(&key_id).ok_or_else(|| MqttError::from("encryption key not found"))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, data.as_ref())
+            .map_err(|e| MqttError::from(format!("payload encryption error, {:?}", e)))?;
+        let mut buf = BytesMut::with_capacity(HEADER_LEN + ciphertext.len());
+        buf.put_slice(MAGIC);
+        buf.put_u16(key_id);
+        buf.put_slice(nonce.as_slice());
+        buf.put_slice(&ciphertext);
+        Ok(buf.freeze())
+    }
+
+    ///Decrypts with the key the data was encrypted with, data without the encryption header is
+    ///returned unchanged
+    #[inline]
+    pub fn decrypt(&self, data: &Bytes) -> Result<Bytes> {
+        if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+            return Ok(data.clone());
+        }
+        let key_id = KeyId::from_be_bytes([data[MAGIC.len()], data[MAGIC.len() + 1]]);
+        let cipher = self
+            .keys
+            .get(&key_id)
+            .ok_or_else(|| MqttError::from(format!("encryption key {} is not configured", key_id)))?;
+        let nonce = Nonce::from_slice(&data[MAGIC.len() + KEY_ID_LEN..HEADER_LEN]);
+        let plaintext = cipher
+            .decrypt(nonce, &data[HEADER_LEN..])
+            .map_err(|e| MqttError::from(format!("payload decryption error, key: {}, {:?}", key_id, e)))?;
+        Ok(Bytes::from(plaintext))
+    }
+
+    #[inline]
+    pub fn encrypt_publish(&self, mut p: Publish) -> Result<Publish> {
+        p.payload = self.encrypt(&p.payload)?;
+        Ok(p)
+    }
+
+    #[inline]
+    pub fn decrypt_publish(&self, mut p: Publish) -> Result<Publish> {
+        p.payload = self.decrypt(&p.payload)?;
+        Ok(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_config(id: KeyId, key: u8) -> KeyConfig {
+        KeyConfig { id, key: BASE64_STANDARD.encode([key; 32]) }
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let cfg = EncryptionConfig { enable: true, keys: vec![key_config(1, 1)] };
+        let cipher = PayloadCipher::try_from(&cfg).unwrap();
+        let data = Bytes::from_static(b"hello");
+        let encrypted = cipher.encrypt(&data).unwrap();
+        assert_ne!(encrypted, data);
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), data);
+        //stored before encryption was enabled
+        assert_eq!(cipher.decrypt(&data).unwrap(), data);
+    }
+
+    #[test]
+    fn rotation() {
+        let old = PayloadCipher::try_from(&EncryptionConfig { enable: true, keys: vec![key_config(1, 1)] })
+            .unwrap();
+        let encrypted = old.encrypt(&Bytes::from_static(b"hello")).unwrap();
+
+        let cfg = EncryptionConfig { enable: true, keys: vec![key_config(2, 2), key_config(1, 1)] };
+        let new = PayloadCipher::try_from(&cfg).unwrap();
+        assert_eq!(new.decrypt(&encrypted).unwrap(), Bytes::from_static(b"hello"));
+        assert_eq!(
+            &new.encrypt(&Bytes::from_static(b"hello")).unwrap()[MAGIC.len()..HEADER_LEN - NONCE_LEN],
+            &[0, 2]
+        );
+
+        let cfg = EncryptionConfig { enable: true, keys: vec![key_config(2, 2)] };
+        let retired = PayloadCipher::try_from(&cfg).unwrap();
+        assert!(retired.decrypt(&encrypted).is_err());
+    }
+}
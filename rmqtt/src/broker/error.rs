@@ -6,7 +6,7 @@ use bytestring::ByteString;
 use config::ConfigError;
 use ntex_mqtt::error::SendPacketError;
 use ntex_mqtt::v5;
-use ntex_mqtt::v5::codec::PublishAckReason;
+use ntex_mqtt::v5::codec::{DisconnectReasonCode, PublishAckReason};
 use ntex_mqtt::TopicError;
 use thiserror::Error;
 use tokio::sync::mpsc::error::SendError;
@@ -72,6 +72,90 @@ pub enum MqttError {
     TryFromIntError(#[from] TryFromIntError),
     #[error("None")]
     None,
+
+    #[error("protocol error, {0}")]
+    Protocol(String),
+    #[error("authentication failed, {0}")]
+    Auth(String),
+    #[error("not authorized, {0}")]
+    Acl(String),
+    #[error("storage error, {0}")]
+    Storage(String),
+    #[error("cluster error, {0}")]
+    Cluster(String),
+    #[error("{0}")]
+    Plugin(String),
+}
+
+///Category of an error, for callers that handle errors by kind rather than by variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Protocol,
+    Auth,
+    Acl,
+    Storage,
+    Cluster,
+    Plugin,
+    Io,
+    Config,
+    Timeout,
+    Other,
+}
+
+impl MqttError {
+    #[inline]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            MqttError::Protocol(_)
+            | MqttError::TopicError(_)
+            | MqttError::Utf8Error(_)
+            | MqttError::TooManySubscriptions
+            | MqttError::TooManyTopicLevels
+            | MqttError::SubscribeLimited(_)
+            | MqttError::PublishAckReason(..) => ErrorKind::Protocol,
+            MqttError::Auth(_) => ErrorKind::Auth,
+            MqttError::Acl(_) => ErrorKind::Acl,
+            MqttError::Storage(_) => ErrorKind::Storage,
+            MqttError::Cluster(_) | MqttError::Grpc(_) => ErrorKind::Cluster,
+            MqttError::Plugin(_) => ErrorKind::Plugin,
+            MqttError::IoError(_) | MqttError::WSError(_) | MqttError::SendPacketError(_) => ErrorKind::Io,
+            MqttError::ConfigError(_) | MqttError::ListenerConfigError | MqttError::AddrParseError(_) => {
+                ErrorKind::Config
+            }
+            MqttError::Timeout(_) => ErrorKind::Timeout,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    ///Reason code of the PUBACK/PUBREC sent to a MQTT 5.0 client when a publish fails with this error
+    #[inline]
+    pub fn publish_ack_reason(&self) -> PublishAckReason {
+        match self {
+            MqttError::PublishAckReason(reason, _) => *reason,
+            MqttError::TopicError(_) | MqttError::TooManyTopicLevels => PublishAckReason::TopicNameInvalid,
+            MqttError::Auth(_) | MqttError::Acl(_) => PublishAckReason::NotAuthorized,
+            MqttError::ServiceUnavailable => PublishAckReason::UnspecifiedError,
+            _ => PublishAckReason::ImplementationSpecificError,
+        }
+    }
+
+    ///Reason code of the DISCONNECT sent to a MQTT 5.0 client when its connection is closed with this error
+    #[inline]
+    pub fn disconnect_reason_code(&self) -> DisconnectReasonCode {
+        match self {
+            MqttError::Protocol(_) => DisconnectReasonCode::ProtocolError,
+            MqttError::Utf8Error(_) => DisconnectReasonCode::MalformedPacket,
+            MqttError::TopicError(_) | MqttError::TooManyTopicLevels => {
+                DisconnectReasonCode::TopicNameInvalid
+            }
+            MqttError::Auth(_) | MqttError::Acl(_) => DisconnectReasonCode::NotAuthorized,
+            MqttError::TooManySubscriptions | MqttError::SubscribeLimited(_) => {
+                DisconnectReasonCode::QuotaExceeded
+            }
+            MqttError::ServiceUnavailable => DisconnectReasonCode::ServerBusy,
+            _ => DisconnectReasonCode::ImplementationSpecificError,
+        }
+    }
 }
 
 impl From<()> for MqttError {
@@ -0,0 +1,117 @@
+use bytestring::ByteString;
+use serde_json::json;
+
+use crate::broker::session::{Session, SessionState};
+use crate::broker::types::*;
+use crate::settings::EventKind;
+use crate::Runtime;
+
+pub const EVENTS_TOPIC_PREFIX: &str = "$events/";
+
+#[inline]
+fn enabled(kind: EventKind) -> bool {
+    Runtime::instance().settings.latest_mqtt().events.contains(&kind)
+}
+
+///Whether the topic is in the `$events/` namespace, which clients may not publish to while events are
+///enabled
+#[inline]
+pub(crate) fn is_events_topic(topic: &str) -> bool {
+    !Runtime::instance().settings.latest_mqtt().events.is_empty() && topic.starts_with(EVENTS_TOPIC_PREFIX)
+}
+
+#[inline]
+pub(crate) async fn client_connected(s: &Session) {
+    if !enabled(EventKind::ClientConnected) {
+        return;
+    }
+    let mut body = s.connect_info().await.map(|c| c.to_hook_body()).unwrap_or_else(|_| base_body(s));
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("connected_at".into(), json!(s.connected_at().await.unwrap_or_default()));
+        obj.insert("session_present".into(), json!(s.session_present().await.unwrap_or_default()));
+    }
+    publish(EventKind::ClientConnected, body);
+}
+
+#[inline]
+pub(crate) async fn client_disconnected(s: &Session, reason: &Reason) {
+    if !enabled(EventKind::ClientDisconnected) {
+        return;
+    }
+    let mut body = base_body(s);
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("disconnected_at".into(), json!(s.disconnected_at().await.unwrap_or_default()));
+        obj.insert("reason".into(), json!(reason.to_string()));
+    }
+    publish(EventKind::ClientDisconnected, body);
+}
+
+#[inline]
+pub(crate) fn session_subscribed(s: &Session, sub: &Subscribe) {
+    if !enabled(EventKind::SessionSubscribed) {
+        return;
+    }
+    let mut body = base_body(s);
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("topic".into(), json!(sub.topic_filter));
+        obj.insert("opts".into(), sub.opts.to_json());
+    }
+    publish(EventKind::SessionSubscribed, body);
+}
+
+#[inline]
+pub(crate) fn session_unsubscribed(s: &Session, unsub: &Unsubscribe) {
+    if !enabled(EventKind::SessionUnsubscribed) {
+        return;
+    }
+    let mut body = base_body(s);
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("topic".into(), json!(unsub.topic_filter));
+    }
+    publish(EventKind::SessionUnsubscribed, body);
+}
+
+#[inline]
+fn base_body(s: &Session) -> serde_json::Value {
+    json!({
+        "node": s.id.node(),
+        "ipaddress": s.id.remote_addr,
+        "clientid": s.id.client_id,
+        "username": s.id.username_ref(),
+    })
+}
+
+///Publishes the event as a QoS 0, non-retained message from the system client
+fn publish(kind: EventKind, mut body: serde_json::Value) {
+    let now = timestamp_millis();
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("event".into(), json!(kind.as_str()));
+        obj.insert("ts".into(), json!(now));
+    }
+    let payload = match serde_json::to_vec(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::error!("{:?}", e);
+            return;
+        }
+    };
+    let p = Publish::builder()
+        .topic(ByteString::from(format!("{}{}", EVENTS_TOPIC_PREFIX, kind.as_str())))
+        .payload(payload)
+        .build();
+
+    let from = From::from_system(Id::new(
+        Runtime::instance().node.id(),
+        None,
+        None,
+        ClientId::from_static("system"),
+        Some(UserName::from("system")),
+    ));
+
+    //spawned, the event hooks are not delayed by the delivery
+    tokio::spawn(async move {
+        if let Err(e) = SessionState::forwards(from, p, false, false, None).await {
+            log::warn!("{} event forward error, {:?}", kind.as_str(), e);
+        }
+    });
+}
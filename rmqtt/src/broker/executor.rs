@@ -99,7 +99,7 @@ pub fn is_busy() -> bool {
     {
         let cached = CACHED.read();
         let (busy, inst) = cached.deref();
-        if inst.elapsed() < Runtime::instance().settings.node.busy.update_interval {
+        if inst.elapsed() < Runtime::instance().settings.latest_node().busy.update_interval {
             return *busy;
         }
     }
@@ -1,11 +1,47 @@
+use bytestring::ByteString;
+
 use crate::broker::inflight::InflightMessage;
 use crate::broker::types::*;
 use crate::{grpc, Result, Session};
 
 pub type Priority = u32;
-pub type Proceed = bool;
 pub type ReturnType = (Proceed, Option<HookResult>);
 
+///Controls the hook chain after a handler returns.
+///
+///Handlers of the same type are executed in descending priority order, each handler receives
+///the result (acc) returned by the previous one.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Proceed {
+    ///Pass the returned result on to the next handler
+    Continue,
+    ///Terminate the chain, the returned result is the final result
+    Stop,
+}
+
+impl Proceed {
+    #[inline]
+    pub fn is_continue(&self) -> bool {
+        matches!(self, Proceed::Continue)
+    }
+
+    #[inline]
+    pub fn is_stop(&self) -> bool {
+        matches!(self, Proceed::Stop)
+    }
+}
+
+impl std::convert::From<bool> for Proceed {
+    #[inline]
+    fn from(proceed: bool) -> Self {
+        if proceed {
+            Proceed::Continue
+        } else {
+            Proceed::Stop
+        }
+    }
+}
+
 #[async_trait]
 pub trait HookManager: Sync + Send {
     fn hook(&self, s: &Session) -> std::rc::Rc<dyn Hook>;
@@ -18,23 +54,39 @@ pub trait HookManager: Sync + Send {
     ///When a connect message is received
     async fn client_connect(&self, connect_info: &ConnectInfo) -> Option<UserProperties>;
 
-    ///authenticate
+    ///authenticate, returns the attributes of the session to be created
     async fn client_authenticate(
         &self,
         connect_info: &ConnectInfo,
         allow_anonymous: bool,
-    ) -> (ConnectAckReason, Superuser);
+    ) -> (ConnectAckReason, Superuser, SessionAttrs);
+
+    ///After authentication, the handlers receive the cluster totals and can refuse the connection,
+    ///returns the reason if the quota is exceeded
+    async fn client_quota_check(&self, connect_info: &ConnectInfo) -> Option<String>;
 
-    ///When sending mqtt:: connectack message
+    ///When sending mqtt:: connectack message, `session_present` is only decided for accepted connections,
+    ///handlers can override the flag sent in the CONNACK, the session itself is not changed
     async fn client_connack(
         &self,
         connect_info: &ConnectInfo,
         return_code: ConnectAckReason,
-    ) -> ConnectAckReason;
+        session_present: SessionPresent,
+    ) -> (ConnectAckReason, SessionPresent);
 
     ///Publish message received
     async fn message_publish(&self, s: Option<&Session>, from: From, publish: &Publish) -> Option<Publish>;
 
+    ///subscribe check acl of the session, for handlers that act on behalf of a client
+    async fn client_subscribe_check_acl(
+        &self,
+        s: &Session,
+        subscribe: &Subscribe,
+    ) -> Option<SubscribeAclResult>;
+
+    ///publish check acl of the session, for handlers that act on behalf of a client
+    async fn message_publish_check_acl(&self, s: &Session, publish: &Publish) -> PublishAclResult;
+
     ///Publish message Dropped
     async fn message_dropped(&self, to: Option<To>, from: From, p: Publish, reason: Reason);
 
@@ -65,12 +117,21 @@ pub trait Register: Sync + Send {
 #[async_trait]
 pub trait Handler: Sync + Send {
     async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType;
+
+    ///Used in logs, defaults to the type name, which starts with the plugin crate name
+    #[inline]
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 #[async_trait]
 pub trait Hook: Sync + Send {
     ///session created
-    async fn session_created(&self);
+    async fn session_created(&self, clean_start: CleanStart);
+
+    ///Persistent session resumed, with the number of restored subscriptions and queued messages
+    async fn session_resumed(&self, subscriptions: usize, messages: usize);
 
     ///After the mqtt:: connectack message is sent, the connection is created successfully
     async fn client_connected(&self);
@@ -78,6 +139,26 @@ pub trait Hook: Sync + Send {
     ///Disconnect message received
     async fn client_disconnected(&self, r: Reason);
 
+    ///The client is dropped because no packet was received within the keepalive period
+    async fn client_keepalive_timeout(&self);
+
+    ///Periodic heartbeat while the client is alive, see listener.client_alive_interval
+    async fn client_alive(&self, ping: IsPing);
+
+    ///Protocol violation reported by the MQTT 5.0 codec after the handshake, such as a second CONNECT
+    ///or a malformed packet. The connection is closed, the handlers can also ban the client.
+    async fn client_protocol_violation(&self, reason: ByteString) -> ProtocolViolationAction;
+
+    ///Whether any handler is registered for the type, so that costly parameters are only built when used
+    fn is_hooked(&self, typ: Type) -> bool;
+
+    ///Decoded packet received from the client. Packets consumed inside ntex-mqtt, such as MQTT 3.1.1
+    ///SUBSCRIBE/UNSUBSCRIBE and the acks it replies automatically, are not reported.
+    async fn packet_received(&self, packet: &Packet);
+
+    ///Packet about to be sent to the client
+    async fn packet_sent(&self, packet: &Packet);
+
     ///Session terminated
     async fn session_terminated(&self, r: Reason);
 
@@ -87,8 +168,8 @@ pub trait Hook: Sync + Send {
     ///publish check acl
     async fn message_publish_check_acl(&self, publish: &Publish) -> PublishAclResult;
 
-    ///Subscribe message received
-    async fn client_subscribe(&self, subscribe: &Subscribe) -> Option<TopicFilter>;
+    ///Subscribe message received, returns the adjusted topic filter and the upper limit of the granted QoS
+    async fn client_subscribe(&self, subscribe: &Subscribe) -> (Option<TopicFilter>, Option<QoS>);
 
     ///Subscription succeeded
     async fn session_subscribed(&self, subscribe: Subscribe);
@@ -123,18 +204,26 @@ pub enum Type {
     BeforeStartup,
 
     SessionCreated,
+    SessionResumed,
     SessionTerminated,
     SessionSubscribed,
     SessionUnsubscribed,
 
     ClientAuthenticate,
+    ClientQuotaCheck,
     ClientConnect,
     ClientConnack,
     ClientConnected,
     ClientDisconnected,
+    ClientKeepaliveTimeout,
+    ClientAlive,
     ClientSubscribe,
     ClientUnsubscribe,
     ClientSubscribeCheckAcl,
+    ClientProtocolViolation,
+
+    PacketReceived,
+    PacketSent,
 
     MessagePublishCheckAcl,
     MessagePublish,
@@ -156,18 +245,26 @@ impl std::convert::From<&str> for Type {
             "before_startup" => Type::BeforeStartup,
 
             "session_created" => Type::SessionCreated,
+            "session_resumed" => Type::SessionResumed,
             "session_terminated" => Type::SessionTerminated,
             "session_subscribed" => Type::SessionSubscribed,
             "session_unsubscribed" => Type::SessionUnsubscribed,
 
             "client_authenticate" => Type::ClientAuthenticate,
+            "client_quota_check" => Type::ClientQuotaCheck,
             "client_connect" => Type::ClientConnect,
             "client_connack" => Type::ClientConnack,
             "client_connected" => Type::ClientConnected,
             "client_disconnected" => Type::ClientDisconnected,
+            "client_keepalive_timeout" => Type::ClientKeepaliveTimeout,
+            "client_alive" => Type::ClientAlive,
             "client_subscribe" => Type::ClientSubscribe,
             "client_unsubscribe" => Type::ClientUnsubscribe,
             "client_subscribe_check_acl" => Type::ClientSubscribeCheckAcl,
+            "client_protocol_violation" => Type::ClientProtocolViolation,
+
+            "packet_received" => Type::PacketReceived,
+            "packet_sent" => Type::PacketSent,
 
             "message_publish_check_acl" => Type::MessagePublishCheckAcl,
             "message_publish" => Type::MessagePublish,
@@ -187,23 +284,77 @@ impl std::convert::From<&str> for Type {
     }
 }
 
+impl Type {
+    ///The name used in the configuration, such as "client_authenticate"
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Type::BeforeStartup => "before_startup",
+
+            Type::SessionCreated => "session_created",
+            Type::SessionResumed => "session_resumed",
+            Type::SessionTerminated => "session_terminated",
+            Type::SessionSubscribed => "session_subscribed",
+            Type::SessionUnsubscribed => "session_unsubscribed",
+
+            Type::ClientAuthenticate => "client_authenticate",
+            Type::ClientQuotaCheck => "client_quota_check",
+            Type::ClientConnect => "client_connect",
+            Type::ClientConnack => "client_connack",
+            Type::ClientConnected => "client_connected",
+            Type::ClientDisconnected => "client_disconnected",
+            Type::ClientKeepaliveTimeout => "client_keepalive_timeout",
+            Type::ClientAlive => "client_alive",
+            Type::ClientSubscribe => "client_subscribe",
+            Type::ClientUnsubscribe => "client_unsubscribe",
+            Type::ClientSubscribeCheckAcl => "client_subscribe_check_acl",
+            Type::ClientProtocolViolation => "client_protocol_violation",
+
+            Type::PacketReceived => "packet_received",
+            Type::PacketSent => "packet_sent",
+
+            Type::MessagePublishCheckAcl => "message_publish_check_acl",
+            Type::MessagePublish => "message_publish",
+            Type::MessageDelivered => "message_delivered",
+            Type::MessageAcked => "message_acked",
+            Type::MessageDropped => "message_dropped",
+            Type::MessageExpiryCheck => "message_expiry_check",
+            Type::MessageNonsubscribed => "message_nonsubscribed",
+
+            Type::OfflineMessage => "offline_message",
+            Type::OfflineInflightMessages => "offline_inflight_messages",
+
+            Type::GrpcMessageReceived => "grpc_message_received",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Parameter<'a> {
     BeforeStartup,
 
-    SessionCreated(&'a Session),
+    SessionCreated(&'a Session, CleanStart),
+    ///Session, restored subscriptions, queued messages
+    SessionResumed(&'a Session, usize, usize),
     SessionTerminated(&'a Session, Reason),
     SessionSubscribed(&'a Session, Subscribe),
     SessionUnsubscribed(&'a Session, Unsubscribe),
 
     ClientConnect(&'a ConnectInfo),
-    ClientConnack(&'a ConnectInfo, &'a ConnectAckReason),
+    ClientConnack(&'a ConnectInfo, &'a ConnectAckReason, SessionPresent),
     ClientAuthenticate(&'a ConnectInfo),
+    ClientQuotaCheck(&'a ConnectInfo, ClusterTotals),
     ClientConnected(&'a Session),
     ClientDisconnected(&'a Session, Reason),
+    ClientKeepaliveTimeout(&'a Session),
+    ClientAlive(&'a Session, IsPing),
     ClientSubscribe(&'a Session, &'a Subscribe),
     ClientUnsubscribe(&'a Session, &'a Unsubscribe),
     ClientSubscribeCheckAcl(&'a Session, &'a Subscribe),
+    ///Session, the raw reason reported by the codec
+    ClientProtocolViolation(&'a Session, ByteString),
+
+    PacketReceived(&'a Session, &'a Packet),
+    PacketSent(&'a Session, &'a Packet),
 
     MessagePublishCheckAcl(&'a Session, &'a Publish),
     MessagePublish(Option<&'a Session>, From, &'a Publish),
@@ -224,19 +375,27 @@ impl<'a> Parameter<'a> {
         match self {
             Parameter::BeforeStartup => Type::BeforeStartup,
 
-            Parameter::SessionCreated(_) => Type::SessionCreated,
+            Parameter::SessionCreated(_, _) => Type::SessionCreated,
+            Parameter::SessionResumed(_, _, _) => Type::SessionResumed,
             Parameter::SessionTerminated(_, _) => Type::SessionTerminated,
             Parameter::SessionSubscribed(_, _) => Type::SessionSubscribed,
             Parameter::SessionUnsubscribed(_, _) => Type::SessionUnsubscribed,
 
             Parameter::ClientAuthenticate(_) => Type::ClientAuthenticate,
+            Parameter::ClientQuotaCheck(_, _) => Type::ClientQuotaCheck,
             Parameter::ClientConnect(_) => Type::ClientConnect,
-            Parameter::ClientConnack(_, _) => Type::ClientConnack,
+            Parameter::ClientConnack(_, _, _) => Type::ClientConnack,
             Parameter::ClientConnected(_) => Type::ClientConnected,
             Parameter::ClientDisconnected(_, _) => Type::ClientDisconnected,
+            Parameter::ClientKeepaliveTimeout(_) => Type::ClientKeepaliveTimeout,
+            Parameter::ClientAlive(_, _) => Type::ClientAlive,
             Parameter::ClientSubscribe(_, _) => Type::ClientSubscribe,
             Parameter::ClientUnsubscribe(_, _) => Type::ClientUnsubscribe,
             Parameter::ClientSubscribeCheckAcl(_, _) => Type::ClientSubscribeCheckAcl,
+            Parameter::ClientProtocolViolation(_, _) => Type::ClientProtocolViolation,
+
+            Parameter::PacketReceived(_, _) => Type::PacketReceived,
+            Parameter::PacketSent(_, _) => Type::PacketSent,
 
             Parameter::MessagePublishCheckAcl(_, _) => Type::MessagePublishCheckAcl,
             Parameter::MessagePublish(_, _, _) => Type::MessagePublish,
@@ -260,15 +419,25 @@ pub enum HookResult {
     UserProperties(UserProperties),
     ///Authentication failed, for ClientAuthenticate
     AuthResult(AuthResult),
+    ///Refuse the connection with the reason, for ClientQuotaCheck
+    QuotaExceeded(String),
+    ///for ClientProtocolViolation
+    ProtocolViolationAction(ProtocolViolationAction),
     ///ConnectAckReason, for ClientConnack
     ConnectAckReason(ConnectAckReason),
+    ///Session Present flag of the CONNACK, for ClientConnack. Setting it is ignored unless the state of a
+    ///previous session is taken over without a clean start.
+    SessionPresent(bool),
     ///TopicFilters, for ClientSubscribe/ClientUnsubscribe
     TopicFilter(Option<TopicFilter>),
+    ///TopicFilter and the upper limit of the granted QoS, for ClientSubscribe
+    SubscribeQoS(Option<TopicFilter>, QoS),
     ///Subscribe AclResult, for ClientSubscribeCheckAcl
     SubscribeAclResult(SubscribeAclResult),
     ///Publish AclResult, for MessagePublishCheckAcl
     PublishAclResult(PublishAclResult),
-    ///Publish, for MessagePublish/MessageDelivered
+    ///Publish, for MessagePublish/MessageDelivered, the payload, topic, QoS or properties can be modified,
+    ///subsequent handlers receive the modified message. The QoS can not be changed by MessageDelivered
     Publish(Publish),
     ///Message Expiry
     MessageExpiry,
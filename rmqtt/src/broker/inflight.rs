@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
@@ -78,6 +79,11 @@ impl InflightMessage {
 pub struct Inflight {
     cap: usize,
     interval: TimestampMillis,
+    retry_interval: TimestampMillis,
+    expiry_interval: TimestampMillis,
+    retry_backoff: f64,
+    retry_max_interval: TimestampMillis,
+    retries: HashMap<PacketId, usize>,
     next: Arc<AtomicU16>,
     queues: Queues,
     on_push_fn: Option<Arc<dyn OnEventFn>>,
@@ -91,6 +97,11 @@ impl Inflight {
         Self {
             cap,
             interval,
+            retry_interval,
+            expiry_interval,
+            retry_backoff: 1.0,
+            retry_max_interval: 0,
+            retries: HashMap::default(),
             next: Arc::new(AtomicU16::new(1)),
             queues: Queues::default(),
             on_push_fn: None,
@@ -116,6 +127,15 @@ impl Inflight {
         self
     }
 
+    ///The retry interval is multiplied by `backoff` after every retry of a message, up to `max_interval`,
+    ///0 means no upper limit
+    #[inline]
+    pub fn retry_backoff(mut self, backoff: f64, max_interval: TimestampMillis) -> Self {
+        self.retry_backoff = backoff;
+        self.retry_max_interval = max_interval;
+        self
+    }
+
     #[inline]
     fn interval(retry_interval: TimestampMillis, expiry_interval: TimestampMillis) -> TimestampMillis {
         match (retry_interval, expiry_interval) {
@@ -126,13 +146,29 @@ impl Inflight {
         }
     }
 
+    #[inline]
+    fn message_interval(&self, packet_id: &PacketId) -> TimestampMillis {
+        let retries = self.retries.get(packet_id).copied().unwrap_or_default();
+        if retries == 0 || self.retry_interval == 0 || self.retry_backoff <= 1.0 {
+            return self.interval;
+        }
+        let backoff = self.retry_backoff.powi(retries.min(i32::MAX as usize) as i32);
+        let mut retry_interval =
+            (self.retry_interval as f64 * backoff).min(TimestampMillis::MAX as f64) as TimestampMillis;
+        if self.retry_max_interval > 0 {
+            retry_interval = retry_interval.min(self.retry_max_interval);
+        }
+        Self::interval(retry_interval, self.expiry_interval)
+    }
+
     #[inline]
     pub fn get_timeout(&self) -> Option<Duration> {
         if self.interval == 0 {
             return None;
         }
-        if let Some((_, m)) = self.queues.front() {
-            let mut t = self.interval - (chrono::Local::now().timestamp_millis() - m.update_time);
+        if let Some((packet_id, m)) = self.queues.front() {
+            let mut t =
+                self.message_interval(packet_id) - (chrono::Local::now().timestamp_millis() - m.update_time);
             if t < 1 {
                 t = 1;
             }
@@ -147,14 +183,26 @@ impl Inflight {
         if self.interval == 0 {
             return false;
         }
-        if let Some((_, m)) = self.queues.front() {
-            if m.timeout(self.interval) {
+        if let Some((packet_id, m)) = self.queues.front() {
+            if m.timeout(self.message_interval(packet_id)) {
                 return true;
             }
         }
         false
     }
 
+    ///Interval after which an unacknowledged message is sent again, in milliseconds, 0 means never
+    #[inline]
+    pub fn retry_interval(&self) -> TimestampMillis {
+        self.interval
+    }
+
+    ///Messages in the order they will be retried
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&PacketId, &InflightMessage)> {
+        self.queues.iter()
+    }
+
     #[inline]
     pub fn get(&self, packet_id: PacketId) -> Option<&InflightMessage> {
         self.queues.get(&packet_id)
@@ -186,9 +234,48 @@ impl Inflight {
         }
     }
 
+    ///Removes the messages whose retry or expiry interval has elapsed
+    #[inline]
+    pub fn pop_front_timeouts(&mut self) -> Vec<InflightMessage> {
+        let mut timeouts = Vec::new();
+        while let Some(msg) = self.pop_front_timeout() {
+            timeouts.push(msg);
+        }
+        timeouts
+    }
+
+    ///Increments and returns the number of times the message has been retried
+    #[inline]
+    pub fn retried(&mut self, packet_id: PacketId) -> usize {
+        let retries = self.retries.entry(packet_id).or_default();
+        *retries += 1;
+        *retries
+    }
+
+    ///Counts a retry of the message, true if it has been retried more than `max_retries` times,
+    ///0 means no limit
+    #[inline]
+    pub fn retries_exceeded(&mut self, packet_id: PacketId, max_retries: usize) -> bool {
+        let retries = self.retried(packet_id);
+        if max_retries > 0 && retries > max_retries {
+            self.retries_remove(&packet_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    pub fn retries_remove(&mut self, packet_id: &PacketId) {
+        self.retries.remove(packet_id);
+    }
+
     #[inline]
     pub fn push_back(&mut self, m: InflightMessage) {
         if let Some(packet_id) = m.publish.packet_id() {
+            if !m.publish.dup() {
+                self.retries.remove(&packet_id);
+            }
             if let Some(f) = self.on_push_fn.as_ref() {
                 f();
             }
@@ -205,6 +292,7 @@ impl Inflight {
 
     #[inline]
     pub fn remove(&mut self, packet_id: &PacketId) -> Option<InflightMessage> {
+        self.retries.remove(packet_id);
         if let Some(msg) = self.queues.remove(packet_id) {
             if let Some(f) = self.on_pop_fn.as_ref() {
                 f();
@@ -263,6 +351,59 @@ impl Inflight {
             //@TODO ..., check message expired
             inflight_messages.push(msg);
         }
+        self.retries.clear();
         inflight_messages
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::broker::types::{ClientId, Id, QoS};
+
+    fn timed_out(mut msg: InflightMessage) -> InflightMessage {
+        msg.update_time -= 1000;
+        msg
+    }
+
+    fn message(packet_id: PacketId) -> InflightMessage {
+        let mut publish = Publish::builder().topic("retry/test").qos(QoS::AtLeastOnce).build();
+        publish.set_packet_id(packet_id);
+        let from = From::from_system(Id::new(1, None, None, ClientId::from_static("retry"), None));
+        timed_out(InflightMessage::new(MomentStatus::UnAck, from, publish))
+    }
+
+    #[tokio::test]
+    async fn retry_timeouts() {
+        let inflight_win = Arc::new(RwLock::new(Inflight::new(16, 100, 0)));
+        inflight_win.write().await.push_back(message(1));
+
+        for retries in 1..=3 {
+            let msgs = inflight_win.write().await.pop_front_timeouts();
+            assert_eq!(msgs.len(), 1);
+            for mut msg in msgs {
+                //the retry path locks the window again, as `Session::reforward` does
+                let exceeded = tokio::time::timeout(Duration::from_secs(1), async {
+                    inflight_win.write().await.retries_exceeded(1, 2)
+                })
+                .await
+                .expect("the inflight window is still locked");
+                assert_eq!(exceeded, retries > 2);
+                if !exceeded {
+                    msg.publish.set_dup(true);
+                    inflight_win.write().await.push_back(timed_out(msg));
+                }
+            }
+        }
+        assert!(inflight_win.read().await.is_empty());
+
+        //no limit by default
+        let mut inflight = Inflight::new(16, 100, 0);
+        assert!((0..100).all(|_| !inflight.retries_exceeded(1, 0)));
+    }
+}
@@ -29,16 +29,26 @@ pub struct Metrics {
     session_resumed: AtomicUsize,
     session_terminated: AtomicUsize,
 
+    hook_handler_panic: AtomicUsize,
+
     messages_publish: AtomicUsize,
-    // messages_received: AtomicUsize,
-    // messages_received_qos0: AtomicUsize,
-    // messages_received_qos1: AtomicUsize,
-    // messages_received_qos2: AtomicUsize,
+    messages_publish_qos0: AtomicUsize,
+    messages_publish_qos1: AtomicUsize,
+    messages_publish_qos2: AtomicUsize,
     messages_delivered: AtomicUsize,
+    messages_delivered_qos0: AtomicUsize,
+    messages_delivered_qos1: AtomicUsize,
+    messages_delivered_qos2: AtomicUsize,
     // messages_forward: AtomicUsize,
     // messages_sent: AtomicUsize,
     messages_acked: AtomicUsize,
     messages_dropped: AtomicUsize,
+    messages_dropped_expired: AtomicUsize,
+    messages_dropped_queue_full: AtomicUsize,
+    messages_dropped_refused: AtomicUsize,
+
+    messages_retain_rejected: AtomicUsize,
+    messages_retain_stripped: AtomicUsize,
 
     messages_publish_custom: AtomicUsize,
     messages_delivered_custom: AtomicUsize,
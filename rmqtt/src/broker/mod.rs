@@ -8,23 +8,32 @@ use crate::broker::session::{Session, SessionOfflineInfo};
 use crate::broker::types::*;
 use crate::grpc::{GrpcClients, MessageBroadcaster, MessageReply, MESSAGE_TYPE_MESSAGE_GET};
 use crate::settings::listener::Listener;
+use crate::settings::SharedSubscriptionStrategy;
 use crate::stats::Counter;
 use crate::{grpc, MqttError, Result, Runtime};
 
 type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
 
+pub mod banned;
+pub mod compression;
+pub mod dead_letter;
 pub mod default;
+pub mod encryption;
 pub mod error;
+pub mod events;
 pub mod executor;
 pub mod fitter;
 pub mod hook;
 pub mod inflight;
 pub mod metrics;
 pub mod queue;
+pub mod rates;
+pub mod resolver;
 pub mod retain;
 pub mod session;
 pub mod stats;
 pub mod topic;
+pub mod translate;
 pub mod types;
 pub mod v3;
 pub mod v5;
@@ -32,6 +41,9 @@ pub mod v5;
 #[async_trait]
 pub trait Entry: Sync + Send {
     async fn try_lock(&self) -> Result<Box<dyn Entry>>;
+    ///Waits up to `timeout` for the entry to be unlocked, so the connects of a client id are handled
+    ///one after the other
+    async fn lock(&self, timeout: Duration) -> Result<Box<dyn Entry>>;
     fn id(&self) -> Id;
     fn id_same(&self) -> Option<bool>;
     async fn set(&mut self, session: Session, tx: Tx) -> Result<()>;
@@ -104,6 +116,28 @@ pub trait Shared: Sync + Send {
 
     async fn subscriptions_count(&self) -> usize;
 
+    ///Connected sessions of the username on this node
+    #[inline]
+    fn username_sessions(&self, username: &str) -> Vec<Id> {
+        session::username_sessions(username)
+    }
+
+    ///Connected sessions of the username on all nodes
+    #[inline]
+    async fn username_sessions_all(&self, username: &str) -> Vec<Id> {
+        self.username_sessions(username)
+    }
+
+    ///Connections and sessions of all nodes
+    #[inline]
+    async fn cluster_totals(&self) -> ClusterTotals {
+        let stats = &Runtime::instance().stats;
+        ClusterTotals {
+            connections: stats.connections.count().max(0) as usize,
+            sessions: stats.sessions.count().max(0) as usize,
+        }
+    }
+
     ///This node is not included
     #[inline]
     fn get_grpc_clients(&self) -> GrpcClients {
@@ -224,7 +258,8 @@ pub trait SharedSubscription: Sync + Send {
         listen_cfg.shared_subscription
     }
 
-    ///Shared subscription strategy, select a subscriber, default is "random"
+    ///Shared subscription strategy, select a subscriber, default is "random",
+    ///see `mqtt.shared_subscription_strategy`
     #[inline]
     async fn choice(
         &self,
@@ -246,6 +281,25 @@ pub trait SharedSubscription: Sync + Send {
             .map(|(idx, (node_id, client_id, _, _, is_online))| (idx, node_id, client_id, is_online))
             .collect::<Vec<_>>();
 
+        let strategy = Runtime::instance().settings.latest_mqtt().shared_subscription_strategy;
+        if strategy == SharedSubscriptionStrategy::Local {
+            let this_node_id = Runtime::instance().node.id();
+            let mut local_ncs =
+                tmp_ncs.iter().filter(|(_, node_id, _, _)| **node_id == this_node_id).collect::<Vec<_>>();
+            while !local_ncs.is_empty() {
+                let r_idx = if local_ncs.len() == 1 { 0 } else { rand::random::<usize>() % local_ncs.len() };
+                let (idx, node_id, client_id, is_online) = local_ncs.remove(r_idx);
+                let is_online = if let Some(is_online) = is_online {
+                    *is_online
+                } else {
+                    Runtime::instance().extends.router().await.is_online(**node_id, client_id).await
+                };
+                if is_online {
+                    return Some((*idx, true));
+                }
+            }
+        }
+
         while !tmp_ncs.is_empty() {
             let r_idx = if tmp_ncs.len() == 1 { 0 } else { rand::random::<usize>() % tmp_ncs.len() };
 
@@ -400,3 +454,66 @@ pub trait AutoSubscription: Sync + Send {
         Ok(())
     }
 }
+
+///Client metadata enrichment, resolves attributes such as the country of the client address when a
+///client is authenticated. They are added to the session attributes, attributes set by authentication
+///take precedence.
+#[async_trait]
+pub trait ClientEnricher: Sync + Send {
+    #[inline]
+    fn enable(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    async fn enrich(&self, _connect_info: &ConnectInfo) -> SessionAttrs {
+        SessionAttrs::default()
+    }
+}
+
+///Namespaced key-value store for small plugin state, see plugin::Store
+#[async_trait]
+pub trait KvStore: Sync + Send {
+    async fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>>;
+
+    async fn set(&self, namespace: &str, key: &str, val: Vec<u8>) -> Result<()>;
+
+    async fn remove(&self, namespace: &str, key: &str) -> Result<()>;
+
+    ///Whether the values are kept after the broker restarts
+    #[inline]
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+///Selects the certificate of a TLS handshake by the server name the client sent (SNI), for example to
+///look up per-tenant certificates. It is asked before the `sni_certs` of the listener, `None` falls back
+///to them. It is called synchronously during the handshake, so it must not block.
+pub trait CertResolver: Sync + Send {
+    #[inline]
+    fn enable(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn resolve(&self, _local_port: u16, _server_name: &str) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        None
+    }
+}
+
+///Loads TLS private keys that are kept in a key store, such as a PKCS#11 token, instead of a PEM file.
+///The `key` of a listener is then a URI of the store, e.g. "pkcs11:token=rmqtt;object=server", and the
+///returned key only asks the store to sign, the key material is never read.
+pub trait KeyLoader: Sync + Send {
+    #[inline]
+    fn enable(&self) -> bool {
+        false
+    }
+
+    ///`None` if the key is not a URI of this store, it is then read as a PEM file
+    #[inline]
+    fn load(&self, _key: &str) -> Option<Result<Arc<dyn rustls::sign::SigningKey>>> {
+        None
+    }
+}
@@ -1,6 +1,7 @@
 use std::num::NonZeroU32;
 use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -77,7 +78,7 @@ impl<T> Sender<T> {
             match (self.policy_fn)(&v) {
                 Policy::Current => return Err(v),
                 Policy::Early => {
-                    let removed = self.queue.pop();
+                    let removed = self.queue.pop_early();
                     if let Err(v) = self.queue.push(v) {
                         log::warn!("queue is full, queue len is {}", self.queue.len());
                         return Err(v);
@@ -152,8 +153,13 @@ impl Limiter {
 pub struct Queue<T> {
     cap: usize,
     inner: SegQueue<T>,
+    //High priority items, popped before those of `inner`
+    priority: SegQueue<T>,
+    priority_fn: Option<Arc<dyn Fn(&T) -> bool + Sync + Send>>,
     on_push_fn: Option<Arc<dyn OnEventFn>>,
     on_pop_fn: Option<Arc<dyn OnEventFn>>,
+    weigh_fn: Option<Arc<dyn Fn(&T) -> usize + Sync + Send>>,
+    bytes: AtomicUsize,
 }
 
 impl<T> Drop for Queue<T> {
@@ -166,7 +172,34 @@ impl<T> Drop for Queue<T> {
 impl<T> Queue<T> {
     #[inline]
     pub fn new(cap: usize) -> Self {
-        Self { cap, inner: SegQueue::new(), on_push_fn: None, on_pop_fn: None }
+        Self {
+            cap,
+            inner: SegQueue::new(),
+            priority: SegQueue::new(),
+            priority_fn: None,
+            on_push_fn: None,
+            on_pop_fn: None,
+            weigh_fn: None,
+            bytes: AtomicUsize::new(0),
+        }
+    }
+
+    ///Size of an item, summed up in `bytes()`
+    #[inline]
+    pub fn weigh<F>(&mut self, f: F)
+    where
+        F: Fn(&T) -> usize + Sync + Send + 'static,
+    {
+        self.weigh_fn = Some(Arc::new(f));
+    }
+
+    ///Items for which `f` returns true go to the high priority lane, the capacity is shared by both lanes
+    #[inline]
+    pub fn priority<F>(&mut self, f: F)
+    where
+        F: Fn(&T) -> bool + Sync + Send + 'static,
+    {
+        self.priority_fn = Some(Arc::new(f));
     }
 
     #[inline]
@@ -187,26 +220,48 @@ impl<T> Queue<T> {
 
     #[inline]
     pub fn push(&self, v: T) -> Result<(), T> {
-        if self.inner.len() > self.cap {
+        if self.len() > self.cap {
             return Err(v);
         }
         if let Some(f) = self.on_push_fn.as_ref() {
             f();
         }
-        self.inner.push(v);
+        if let Some(weigh) = self.weigh_fn.as_ref() {
+            self.bytes.fetch_add(weigh(&v), Ordering::Relaxed);
+        }
+        if self.priority_fn.as_ref().map(|f| f(&v)).unwrap_or_default() {
+            self.priority.push(v);
+        } else {
+            self.inner.push(v);
+        }
         Ok(())
     }
 
+    ///Pops the high priority lane first
     #[inline]
     pub fn pop(&self) -> Option<T> {
-        if let Some(v) = self.inner.pop() {
+        let v = self.priority.pop().or_else(|| self.inner.pop());
+        self.popped(v)
+    }
+
+    ///Pops the normal lane first, used to make room when the queue is full
+    #[inline]
+    pub fn pop_early(&self) -> Option<T> {
+        let v = self.inner.pop().or_else(|| self.priority.pop());
+        self.popped(v)
+    }
+
+    #[inline]
+    fn popped(&self, v: Option<T>) -> Option<T> {
+        if let Some(v) = v.as_ref() {
             if let Some(f) = self.on_pop_fn.as_ref() {
                 f();
             }
-            Some(v)
-        } else {
-            None
+            if let Some(weigh) = self.weigh_fn.as_ref() {
+                self.bytes.fetch_sub(weigh(v), Ordering::Relaxed);
+            }
         }
+        v
     }
 
     #[inline]
@@ -216,16 +271,46 @@ impl<T> Queue<T> {
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.inner.len()
+        self.inner.len() + self.priority.len()
     }
 
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    ///Number of items in the high priority lane
+    #[inline]
+    pub fn priority_len(&self) -> usize {
+        self.priority.len()
+    }
+
+    ///Total size of the queued items, 0 if no `weigh` function is set
+    #[inline]
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
 }
 
 mod test {
+    #[test]
+    fn priority() {
+        use super::Queue;
+
+        let mut q = Queue::new(10);
+        q.priority(|v: &u64| *v >= 100);
+        for v in [1, 100, 2, 101] {
+            assert!(q.push(v).is_ok());
+        }
+        assert_eq!(q.len(), 4);
+        assert_eq!(q.priority_len(), 2);
+        assert_eq!(q.pop_early(), Some(1));
+        assert_eq!(q.pop(), Some(100));
+        assert_eq!(q.pop(), Some(101));
+        assert_eq!(q.pop(), Some(2));
+        assert_eq!(q.pop(), None);
+    }
+
     #[ntex::main]
     #[test]
     async fn channel() {
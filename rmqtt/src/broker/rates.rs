@@ -0,0 +1,205 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use once_cell::sync::OnceCell;
+
+use crate::broker::types::timestamp_secs;
+
+const WINDOW_SECS: usize = 15 * 60;
+//The current second has a bucket of its own
+const BUCKETS: usize = WINDOW_SECS + 1;
+const COUNT_MASK: u64 = u32::MAX as u64;
+
+///Events per second over a sliding window of up to 15 minutes.
+///
+///There is one bucket for each second of the window, a bucket holds its second in the high 32 bits and
+///the count in the low 32 bits, so a stale bucket is reset and counted in a single compare and swap.
+///The events are also added to a cumulative total.
+pub struct Rate {
+    buckets: Box<[AtomicU64]>,
+    total: AtomicU64,
+}
+
+impl Default for Rate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rate {
+    #[inline]
+    pub fn new() -> Self {
+        Self { buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(), total: AtomicU64::new(0) }
+    }
+
+    #[inline]
+    pub fn inc(&self) {
+        self.incs(1);
+    }
+
+    #[inline]
+    pub fn incs(&self, n: usize) {
+        self.total.fetch_add(n as u64, Ordering::Relaxed);
+        let now = timestamp_secs() as u64;
+        let bucket = &self.buckets[now as usize % BUCKETS];
+        let mut curr = bucket.load(Ordering::Relaxed);
+        loop {
+            let count = if curr >> 32 == now { curr & COUNT_MASK } else { 0 };
+            let new = (now << 32) | (count + n as u64).min(COUNT_MASK);
+            match bucket.compare_exchange_weak(curr, new, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(v) => curr = v,
+            }
+        }
+    }
+
+    ///Average per second over the last `secs` complete seconds, the current second is not included
+    #[inline]
+    pub fn rate(&self, secs: usize) -> f64 {
+        let secs = secs.clamp(1, WINDOW_SECS) as u64;
+        let now = timestamp_secs() as u64;
+        let sum = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .filter(|v| {
+                let at = v >> 32;
+                at < now && at + secs >= now
+            })
+            .map(|v| v & COUNT_MASK)
+            .sum::<u64>();
+        sum as f64 / secs as f64
+    }
+
+    ///Events counted since the broker started, or since the restored total
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn total_set(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn averages(&self) -> RateAverages {
+        RateAverages { m1: self.rate(60), m5: self.rate(5 * 60), m15: self.rate(15 * 60) }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct RateAverages {
+    #[serde(rename = "1m")]
+    pub m1: f64,
+    #[serde(rename = "5m")]
+    pub m5: f64,
+    #[serde(rename = "15m")]
+    pub m15: f64,
+}
+
+impl RateAverages {
+    #[inline]
+    pub fn add(&mut self, other: &Self) {
+        self.m1 += other.m1;
+        self.m5 += other.m5;
+        self.m15 += other.m15;
+    }
+}
+
+///Connection churn and message throughput of this node, `messages_in` counts PUBLISH packets received
+///from clients and `messages_out` the messages sent to them, bytes are payload bytes.
+#[derive(Default)]
+pub struct Rates {
+    pub connects: Rate,
+    pub disconnects: Rate,
+    pub messages_in: Rate,
+    pub messages_out: Rate,
+    pub bytes_in: Rate,
+    pub bytes_out: Rate,
+}
+
+impl Rates {
+    #[inline]
+    pub fn instance() -> &'static Rates {
+        static INSTANCE: OnceCell<Rates> = OnceCell::new();
+        INSTANCE.get_or_init(Rates::default)
+    }
+
+    #[inline]
+    pub fn averages(&self) -> RatesAverages {
+        RatesAverages {
+            connects: self.connects.averages(),
+            disconnects: self.disconnects.averages(),
+            messages_in: self.messages_in.averages(),
+            messages_out: self.messages_out.averages(),
+            bytes_in: self.bytes_in.averages(),
+            bytes_out: self.bytes_out.averages(),
+        }
+    }
+
+    #[inline]
+    pub fn totals(&self) -> RatesTotals {
+        RatesTotals {
+            connects: self.connects.total(),
+            disconnects: self.disconnects.total(),
+            messages_in: self.messages_in.total(),
+            messages_out: self.messages_out.total(),
+            bytes_in: self.bytes_in.total(),
+            bytes_out: self.bytes_out.total(),
+        }
+    }
+
+    #[inline]
+    pub fn totals_set(&self, totals: &RatesTotals) {
+        self.connects.total_set(totals.connects);
+        self.disconnects.total_set(totals.disconnects);
+        self.messages_in.total_set(totals.messages_in);
+        self.messages_out.total_set(totals.messages_out);
+        self.bytes_in.total_set(totals.bytes_in);
+        self.bytes_out.total_set(totals.bytes_out);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RatesAverages {
+    pub connects: RateAverages,
+    pub disconnects: RateAverages,
+    pub messages_in: RateAverages,
+    pub messages_out: RateAverages,
+    pub bytes_in: RateAverages,
+    pub bytes_out: RateAverages,
+}
+
+impl RatesAverages {
+    #[inline]
+    pub fn add(&mut self, other: &Self) {
+        self.connects.add(&other.connects);
+        self.disconnects.add(&other.disconnects);
+        self.messages_in.add(&other.messages_in);
+        self.messages_out.add(&other.messages_out);
+        self.bytes_in.add(&other.bytes_in);
+        self.bytes_out.add(&other.bytes_out);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct RatesTotals {
+    pub connects: u64,
+    pub disconnects: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+impl RatesTotals {
+    #[inline]
+    pub fn add(&mut self, other: &Self) {
+        self.connects += other.connects;
+        self.disconnects += other.disconnects;
+        self.messages_in += other.messages_in;
+        self.messages_out += other.messages_out;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+    }
+}
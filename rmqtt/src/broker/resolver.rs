@@ -0,0 +1,176 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use hickory_resolver::TokioAsyncResolver;
+use once_cell::sync::Lazy;
+use tokio::net::TcpStream;
+
+use crate::broker::types::DashMap;
+use crate::{MqttError, Result};
+
+//Delay before the next connection attempt is started, as recommended by RFC 8305
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+//Records with a zero TTL are still cached for a moment, so a reconnect storm does not become a lookup storm
+const MIN_TTL: Duration = Duration::from_secs(1);
+
+static RESOLVER: Lazy<Resolver> = Lazy::new(Resolver::new);
+
+#[derive(Clone)]
+struct CachedAddrs {
+    addrs: Vec<IpAddr>,
+    valid_until: Instant,
+}
+
+///Asynchronous DNS resolver shared by the outgoing connections of the plug-ins, such as the bridges.
+///
+///The addresses of a host are cached until the TTL of its DNS records expires, so a changed
+///address is picked up by the next connect.
+pub struct Resolver {
+    inner: TokioAsyncResolver,
+    cache: DashMap<String, CachedAddrs>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        let inner = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|e| {
+            log::warn!("read the system DNS configuration error, use the default, {:?}", e);
+            TokioAsyncResolver::tokio(Default::default(), Default::default())
+        });
+        Self { inner, cache: DashMap::default() }
+    }
+
+    #[inline]
+    pub fn instance() -> &'static Resolver {
+        &RESOLVER
+    }
+
+    ///Looks up the IP addresses of the host. If the lookup fails, the addresses of the last successful
+    ///lookup are returned even if they have expired.
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+
+        if let Some(cached) = self.cache.get(host) {
+            if cached.valid_until > Instant::now() {
+                return Ok(cached.addrs.clone());
+            }
+        }
+
+        match self.inner.lookup_ip(host).await {
+            Ok(lookup) => {
+                let addrs = lookup.iter().collect::<Vec<_>>();
+                let valid_until = lookup.valid_until().max(Instant::now() + MIN_TTL);
+                self.cache.insert(host.into(), CachedAddrs { addrs: addrs.clone(), valid_until });
+                Ok(addrs)
+            }
+            Err(e) => {
+                if let Some(cached) = self.cache.get(host) {
+                    log::warn!("lookup {} error, use the expired addresses, {:?}", host, e);
+                    Ok(cached.addrs.clone())
+                } else {
+                    Err(MqttError::from(format!("lookup {} error, {:?}", host, e)))
+                }
+            }
+        }
+    }
+
+    ///Resolves an endpoint of the form `host:port`, IPv6 hosts are enclosed in brackets
+    pub async fn resolve(&self, endpoint: &str) -> Result<Vec<SocketAddr>> {
+        if let Ok(addr) = endpoint.parse::<SocketAddr>() {
+            return Ok(vec![addr]);
+        }
+        let invalid = || MqttError::from(format!("invalid endpoint, {}", endpoint));
+        let (host, port) = endpoint.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse::<u16>().map_err(|_| invalid())?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        Ok(self.lookup(host).await?.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+
+    ///Tries the endpoints in order, the first one is the primary and the others are failovers.
+    ///
+    ///Returns the index of the first endpoint that accepts a TCP connection and the address that was
+    ///connected with happy eyeballs. The connection is only a probe and is closed, the caller connects
+    ///to the returned address.
+    pub async fn dial(&self, endpoints: &[String], timeout: Duration) -> Result<(usize, SocketAddr)> {
+        let mut last_err = None;
+        for (idx, endpoint) in endpoints.iter().enumerate() {
+            let res = match self.resolve(endpoint).await {
+                Ok(addrs) => happy_eyeballs(addrs, timeout).await,
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok((addr, _)) => return Ok((idx, addr)),
+                Err(e) => {
+                    log::debug!("dial {} error, {}", endpoint, e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| MqttError::from("no endpoints")))
+    }
+}
+
+///Connects to one of the addresses with happy eyeballs (RFC 8305).
+///
+///IPv6 and IPv4 addresses are tried alternately, a new attempt is started every 250ms or as soon as
+///the previous one fails, and the first established connection wins.
+pub async fn happy_eyeballs(addrs: Vec<SocketAddr>, timeout: Duration) -> Result<(SocketAddr, TcpStream)> {
+    let mut addrs = interleave(addrs).into_iter();
+    let connecting = async move {
+        let mut attempts = FuturesUnordered::new();
+        let mut last_err = None;
+        loop {
+            if attempts.is_empty() {
+                match addrs.next() {
+                    Some(addr) => attempts.push(connect(addr)),
+                    None => return Err(last_err.unwrap_or_else(|| MqttError::from("no addresses"))),
+                }
+            }
+            tokio::select! {
+                Some(res) = attempts.next() => match res {
+                    Ok(conn) => return Ok(conn),
+                    Err(e) => {
+                        last_err = Some(e);
+                        if let Some(addr) = addrs.next() {
+                            attempts.push(connect(addr));
+                        }
+                    }
+                },
+                _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY) => {
+                    if let Some(addr) = addrs.next() {
+                        attempts.push(connect(addr));
+                    }
+                }
+            }
+        }
+    };
+    tokio::time::timeout(timeout, connecting).await.map_err(|_| MqttError::from("connect timeout"))?
+}
+
+async fn connect(addr: SocketAddr) -> Result<(SocketAddr, TcpStream)> {
+    TcpStream::connect(addr).await.map(|s| (addr, s)).map_err(|e| MqttError::from(format!("{}, {}", addr, e)))
+}
+
+//IPv6 first, then alternating between the address families
+#[inline]
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6());
+    itertools::interleave(v6, v4).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleave_families() {
+        let addrs = ["1.1.1.1:1883", "2.2.2.2:1883", "[::1]:1883", "3.3.3.3:1883"]
+            .iter()
+            .map(|a| a.parse::<SocketAddr>().unwrap())
+            .collect::<Vec<_>>();
+        let ordered = interleave(addrs.clone());
+        assert_eq!(ordered, vec![addrs[2], addrs[0], addrs[1], addrs[3]]);
+    }
+}
@@ -1,28 +1,146 @@
+use std::collections::VecDeque;
 use std::convert::From as _f;
 use std::fmt;
 use std::num::NonZeroU16;
 use std::ops::Deref;
 use std::rc::Rc;
-use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 #[allow(unused_imports)]
 use bitflags::Flags;
 use bytestring::ByteString;
 use futures::StreamExt;
-use tokio::sync::RwLock;
+use once_cell::sync::OnceCell;
+use tokio::sync::{Notify, RwLock};
 use tokio::time::{Duration, Instant};
 
-use ntex_mqtt::v5::codec::RetainHandling;
+use ntex_mqtt::v5::codec::{DisconnectReasonCode, PublishAckReason, RetainHandling};
 
+use crate::broker::dead_letter;
+use crate::broker::events;
 use crate::broker::hook::Hook;
 use crate::broker::inflight::{Inflight, InflightMessage, MomentStatus};
 use crate::broker::queue::{self, Limiter, Policy};
+use crate::broker::rates::Rates;
+use crate::broker::translate;
 use crate::broker::types::*;
 use crate::metrics::Metrics;
-use crate::settings::listener::Listener;
+use crate::settings::listener::{Listener, UsernameConnections, UsernameConnectionsPolicy};
+use crate::settings::RetainForbiddenAction;
 use crate::{MqttError, Result, Runtime};
 
+//Connected sessions of this node by username, kept by the online worker of each session
+#[inline]
+fn username_index() -> &'static DashMap<UserName, HashMap<ClientId, Id>> {
+    static INSTANCE: OnceCell<DashMap<UserName, HashMap<ClientId, Id>>> = OnceCell::new();
+    INSTANCE.get_or_init(DashMap::default)
+}
+
+#[inline]
+fn username_index_add(id: &Id) {
+    if let Some(username) = id.username.as_ref() {
+        username_index().entry(username.clone()).or_default().insert(id.client_id.clone(), id.clone());
+    }
+}
+
+#[inline]
+fn username_index_remove(id: &Id) {
+    if let Some(username) = id.username.as_ref() {
+        //the client may have already reconnected, only this connection is removed
+        if let Some(mut sessions) = username_index().get_mut(username) {
+            if sessions.get(&id.client_id) == Some(id) {
+                sessions.remove(&id.client_id);
+            }
+        }
+        username_index().remove_if(username, |_, sessions| sessions.is_empty());
+    }
+}
+
+///Connected sessions of the username on this node
+#[inline]
+pub fn username_sessions(username: &str) -> Vec<Id> {
+    username_index().get(username).map(|sessions| sessions.values().cloned().collect()).unwrap_or_default()
+}
+
+///Checks the `username_connections` limits for a new connection, the reason is returned when it is refused.
+///With the "kick_oldest" policy the sessions over the limits are kicked instead.
+pub(crate) async fn username_connections_check(
+    id: &Id,
+    limits: UsernameConnections,
+) -> std::result::Result<(), String> {
+    let username = match id.username.as_ref() {
+        Some(username) if limits.is_limited() => username,
+        _ => return Ok(()),
+    };
+    //a client that reconnects takes over its own session, it is not counted
+    let others = |ids: Vec<Id>| ids.into_iter().filter(|o| o.client_id != id.client_id).collect::<Vec<_>>();
+
+    let mut kicks = Vec::new();
+    if limits.max > 0 {
+        let locals = others(Runtime::instance().extends.shared().await.username_sessions(username));
+        username_connections_over(locals, limits.max, limits.policy, &mut kicks)
+            .map_err(|n| format!("username has {} connections on this node", n))?;
+    }
+    if limits.max_cluster > 0 {
+        let mut all =
+            others(Runtime::instance().extends.shared().await.username_sessions_all(username).await);
+        all.retain(|o| !kicks.contains(o));
+        username_connections_over(all, limits.max_cluster, limits.policy, &mut kicks)
+            .map_err(|n| format!("username has {} connections in the cluster", n))?;
+    }
+
+    for kick_id in kicks {
+        log::info!("{:?} kicked, too many connections of the username, new connection: {:?}", kick_id, id);
+        let mut entry = Runtime::instance().extends.shared().await.entry(kick_id.clone());
+        if let Err(e) = entry.kick(true, true, false).await {
+            log::warn!("{:?} kick error, {:?}", kick_id, e);
+        }
+    }
+    Ok(())
+}
+
+//The oldest sessions that have to be kicked to make room for one more are added to `kicks`, with the
+//"reject" policy the number of sessions is returned instead
+#[inline]
+fn username_connections_over(
+    mut ids: Vec<Id>,
+    max: usize,
+    policy: UsernameConnectionsPolicy,
+    kicks: &mut Vec<Id>,
+) -> std::result::Result<(), usize> {
+    if ids.len() < max {
+        return Ok(());
+    }
+    match policy {
+        UsernameConnectionsPolicy::Reject => Err(ids.len()),
+        UsernameConnectionsPolicy::KickOldest => {
+            ids.sort_by_key(|id| id.create_time);
+            let n = ids.len() + 1 - max;
+            kicks.extend(ids.drain(..n));
+            Ok(())
+        }
+    }
+}
+
+//The mountpoint goes after the system prefix of a topic filter, such as `$share/{group}/`, `$queue/`
+//or `$replay/{duration}/`, so that the prefix is still recognized downstream
+#[inline]
+fn mount_topic_filter(mountpoint: &str, topic_filter: &str) -> ByteString {
+    let prefix_levels = match topic_filter.split('/').next() {
+        Some("$share") | Some("$limit") | Some("$replay") => 2,
+        Some("$queue") | Some("$exclusive") => 1,
+        _ => 0,
+    };
+    let prefix_len = if prefix_levels > 0 {
+        topic_filter.match_indices('/').nth(prefix_levels - 1).map(|(idx, _)| idx + 1).unwrap_or(0)
+    } else {
+        0
+    };
+    let (prefix, filter) = topic_filter.split_at(prefix_len);
+    ByteString::from(format!("{}{}{}", prefix, mountpoint, filter))
+}
+
 #[derive(Clone)]
 pub struct SessionState {
     pub tx: Option<Tx>,
@@ -93,6 +211,8 @@ impl SessionState {
         };
         log::debug!("{:?} keep_alive_interval is {:?}", state.id, keep_alive_interval);
         let keep_alive_delay = tokio::time::sleep(keep_alive_interval);
+        let client_alive_interval = state.listen_cfg().client_alive_interval;
+        let mut client_alive_at = Instant::now();
 
         let deliver_timeout_delay = tokio::time::sleep(Duration::from_secs(60));
 
@@ -106,7 +226,9 @@ impl SessionState {
         log::debug!("{:?} there are {} offline messages ...", state.id, state.deliver_queue().len());
 
         ntex::rt::spawn(async move {
-            Runtime::instance().stats.connections.inc();
+            Runtime::instance().stats.listener_connected(state.listen_cfg().addr.port());
+            username_index_add(&state.id);
+            Rates::instance().connects.inc();
 
             let (state, deliver_queue_tx, mut deliver_queue_rx) = state.deliver_queue_channel(&limiter);
 
@@ -132,6 +254,11 @@ impl SessionState {
                         if let Err(e) = state.disconnected_reason_add(Reason::ConnectKeepaliveTimeout).await {
                             log::error!("{:?} disconnected reason add error: {:?}", state.id, e);
                         }
+                        //hook, client keepalive timeout
+                        state.hook.client_keepalive_timeout().await;
+                        if let Some(sink) = state.sink.as_ref() {
+                            sink.close_with_reason(DisconnectReasonCode::KeepAliveTimeout);
+                        }
                         break
                     },
 
@@ -142,6 +269,7 @@ impl SessionState {
                             Runtime::instance().stats.debug_session_channels.dec();
                             match msg{
                                 Message::Forward(from, p) => {
+                                    state.forwarded(&p.topic).await;
                                     if let Err((from, p)) = deliver_queue_tx.send((from, p)).await{
                                         log::warn!("{:?} deliver_dropped, from: {:?}, {:?}", state.id, from, p);
                                         //hook, message_dropped
@@ -151,7 +279,7 @@ impl SessionState {
                                 Message::Kick(sender, by_id, clean_start, is_admin) => {
                                     log::debug!("{:?} Message::Kick, send kick result, to {:?}, clean_start: {}, is_admin: {}", state.id, by_id, clean_start, is_admin);
                                     if !sender.is_closed() {
-                                        if sender.send(()).is_err() {
+                                        if sender.send(state.kick_ack().await).is_err() {
                                             log::warn!("{:?} Message::Kick, send response error, sender is closed", state.id);
                                         }
                                         flags.insert(StateFlags::Kicked);
@@ -185,12 +313,38 @@ impl SessionState {
                                     }
                                     break
                                 },
+                                Message::Shutdown => {
+                                    log::debug!("{:?} Message::Shutdown, the broker is shutting down", state.id);
+                                    if let Err(e) = state.disconnected_reason_add(Reason::ServerShuttingDown).await {
+                                        log::error!("{:?} disconnected reason add error: {:?}", state.id, e);
+                                    }
+                                    if let Some(sink) = state.sink.as_ref() {
+                                        sink.close_with_server_reference(state.server_reference());
+                                    }
+                                    break
+                                },
+                                Message::Evacuate(server_reference) => {
+                                    log::debug!("{:?} Message::Evacuate, the node is in maintenance, server reference: {:?}", state.id, server_reference);
+                                    if let Err(e) = state.disconnected_reason_add(Reason::from_static("Node maintenance")).await {
+                                        log::error!("{:?} disconnected reason add error: {:?}", state.id, e);
+                                    }
+                                    if let Some(sink) = state.sink.as_ref() {
+                                        sink.close_with_server_reference(server_reference.or_else(|| state.server_reference()));
+                                    }
+                                    break
+                                },
                                 Message::Keepalive(ping) => {
                                     log::debug!("{:?} Message::Keepalive ... ", state.id);
                                     keep_alive_delay.as_mut().reset(Instant::now() + keep_alive_interval);
+                                    state.last_received_at.store(timestamp_millis(), Ordering::Relaxed);
                                     if ping {
                                         flags.insert(StateFlags::Ping);
                                     }
+                                    if !client_alive_interval.is_zero() && client_alive_at.elapsed() >= client_alive_interval {
+                                        client_alive_at = Instant::now();
+                                        //hook, client alive
+                                        state.hook.client_alive(ping).await;
+                                    }
                                 },
                                 Message::Subscribe(sub, reply_tx) => {
                                     let sub_reply = state.subscribe(sub).await;
@@ -211,6 +365,13 @@ impl SessionState {
                                     }else{
                                         log::warn!("{:?} Message::Unsubscribe, reply sender is closed", state.id);
                                     }
+                                },
+                                Message::TriggerLastWill(reply_tx) => {
+                                    log::debug!("{:?} Message::TriggerLastWill", state.id);
+                                    let reply = state.process_last_will().await;
+                                    if let Err(e) = reply_tx.send(reply) {
+                                        log::warn!("{:?} Message::TriggerLastWill, send response error, {:?}", state.id, e);
+                                    }
                                 }
                             }
                         }else{
@@ -223,7 +384,9 @@ impl SessionState {
                     },
 
                     _ = &mut deliver_timeout_delay => {
-                        while let Some(iflt_msg) = state.inflight_win().write().await.pop_front_timeout(){
+                        //the window is unlocked before redelivering, the retry path locks it again
+                        let iflt_msgs = state.inflight_win().write().await.pop_front_timeouts();
+                        for iflt_msg in iflt_msgs {
                             log::debug!("{:?} has timeout message in inflight: {:?}", state.id, iflt_msg);
                             if let Err(e) = state.reforward(iflt_msg).await{
                                 log::error!("{:?} redeliver message error, {:?}", state.id, e);
@@ -235,7 +398,7 @@ impl SessionState {
                         log::debug!("{:?} deliver_packet: {:?}", state.id, deliver_packet);
                         match deliver_packet{
                             Some(Some((from, p))) => {
-                                if let Err(e) = state.deliver(from, p).await{
+                                if let Err(e) = state.deliver_ordered(from, p).await{
                                     log::error!("{:?} deliver message error, {:?}", state.id, e);
                                 }
                             },
@@ -271,7 +434,9 @@ impl SessionState {
                 flags.contains(StateFlags::CleanStart)
             );
 
-            Runtime::instance().stats.connections.dec();
+            Runtime::instance().stats.listener_disconnected(state.listen_cfg().addr.port());
+            username_index_remove(&state.id);
+            Rates::instance().disconnects.inc();
 
             //Setting the disconnected state
             if let Err(e) = state.disconnected_set(None, None).await {
@@ -311,6 +476,9 @@ impl SessionState {
             if flags.contains(StateFlags::Kicked) {
                 if flags.contains(StateFlags::ByAdminKick) {
                     state.clean(state.disconnected_reason_take().await.unwrap_or_default()).await;
+                } else if flags.contains(StateFlags::CleanStart) {
+                    //hook, session terminated, replaced by a clean start connection
+                    state.hook.session_terminated(Reason::SessionTakenOver).await;
                 }
             } else if clean_session {
                 state.clean(state.disconnected_reason_take().await.unwrap_or_default()).await;
@@ -335,6 +503,8 @@ impl SessionState {
                 log::debug!("{:?} offline flags: {:?}", state.id, flags);
                 if !flags.contains(StateFlags::Kicked) {
                     state.clean(Reason::SessionExpiration).await;
+                } else if !flags.contains(StateFlags::ByAdminKick) && flags.contains(StateFlags::CleanStart) {
+                    state.hook.session_terminated(Reason::SessionTakenOver).await;
                 }
             }
         });
@@ -385,7 +555,7 @@ impl SessionState {
                             Message::Kick(sender, by_id, clean_start, is_admin) => {
                                 log::debug!("{:?} offline Kicked, send kick result, to: {:?}, clean_start: {}, is_admin: {}", state.id, by_id, clean_start, is_admin);
                                 if !sender.is_closed() {
-                                    if let Err(e) = sender.send(()) {
+                                    if let Err(e) = sender.send(state.kick_ack().await) {
                                         log::warn!("{:?} offline Kick send response error, to: {:?}, clean_start: {}, is_admin: {}, {:?}", state.id, by_id, clean_start, is_admin, e);
                                     }
                                     flags.insert(StateFlags::Kicked);
@@ -495,6 +665,8 @@ impl SessionState {
 
             if !flags.contains(StateFlags::Kicked) {
                 state.clean(Reason::SessionExpiration).await;
+            } else if !flags.contains(StateFlags::ByAdminKick) && flags.contains(StateFlags::CleanStart) {
+                state.hook.session_terminated(Reason::SessionTakenOver).await;
             }
         });
 
@@ -558,6 +730,45 @@ impl SessionState {
         }
     }
 
+    ///Checks a topic name or filter sent by the client, the length limits of the listener always apply,
+    ///the UTF-8 check only in strict mode
+    fn check_topic(&self, topic: &str) -> Result<()> {
+        let listen_cfg = self.listen_cfg();
+        if listen_cfg.strict_mode && !is_strict_utf8(topic) {
+            return Err(MqttError::TopicError(format!(
+                "control characters or not normalized UTF-8 in topic, {:?}",
+                topic
+            )));
+        }
+        if listen_cfg.max_topic_len > 0 && topic.len() > listen_cfg.max_topic_len {
+            return Err(MqttError::TopicError(format!(
+                "topic is too long, {} > {}",
+                topic.len(),
+                listen_cfg.max_topic_len
+            )));
+        }
+        if listen_cfg.max_topic_levels > 0 || listen_cfg.max_topic_level_len > 0 {
+            let mut levels = 0;
+            for level in topic.split('/') {
+                levels += 1;
+                if listen_cfg.max_topic_levels > 0 && levels > listen_cfg.max_topic_levels {
+                    return Err(MqttError::TopicError(format!(
+                        "too many topic levels, the maximum is {}",
+                        listen_cfg.max_topic_levels
+                    )));
+                }
+                if listen_cfg.max_topic_level_len > 0 && level.len() > listen_cfg.max_topic_level_len {
+                    return Err(MqttError::TopicError(format!(
+                        "topic level is too long, {} > {}",
+                        level.len(),
+                        listen_cfg.max_topic_level_len
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[inline]
     fn last_will_enable(&self, flags: StateFlags, clean_session: bool) -> bool {
         let session_present =
@@ -573,13 +784,29 @@ impl SessionState {
     #[inline]
     async fn process_last_will(&self) -> Result<()> {
         if let Ok(conn_info) = self.connect_info().await {
-            if let Some(lw) = conn_info.last_will() {
-                let p = Publish::try_from(lw)?;
+            if let Some(lw) = conn_info.last_will().filter(|_| !self.last_will_cleared()) {
+                let mut p = Publish::try_from(lw)?;
+                if let Some(topic) = self.mount(&p.topic) {
+                    p.topic = topic;
+                }
                 let from = From::from_lastwill(self.id.clone());
                 //hook, message_publish
                 let p = self.hook.message_publish(from.clone(), &p).await.unwrap_or(p);
                 log::debug!("process_last_will, publish: {:?}", p);
 
+                //hook, message_publish_check_acl, permissions may have changed since the will was accepted
+                if let PublishAclResult::Rejected(_) = self.hook.message_publish_check_acl(&p).await {
+                    Metrics::instance().client_publish_auth_error_inc();
+                    //hook, Message dropped
+                    Runtime::instance()
+                        .extends
+                        .hook_mgr()
+                        .await
+                        .message_dropped(None, from, p, Reason::PublishRefused)
+                        .await;
+                    return Ok(());
+                }
+
                 let listen_cfg = self.listen_cfg();
 
                 let message_storage_available = Runtime::instance().extends.message_mgr().await.enable();
@@ -605,8 +832,72 @@ impl SessionState {
         Ok(())
     }
 
+    ///Retained messages are delivered in topic order. When they do not fit in the inflight window,
+    ///they are delivered in the background in batches of the window size, a batch is sent once the
+    ///message queue of the session is empty and the inflight window has room again. Topics that
+    ///received a live publish after the subscribe are skipped, their retained message is older.
+    #[inline]
+    async fn send_retain_messages(&self, mut retains: Vec<(TopicName, Retain)>, qos: QoS) -> Result<()> {
+        retains.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+        let batch_size = self.fitter.max_inflight().get() as usize;
+        if retains.len() <= batch_size {
+            self._send_retain_messages(retains, qos).await;
+            return Ok(());
+        }
+
+        log::debug!("{:?} {} retained messages, batch size: {}", self.id, retains.len(), batch_size);
+        let state = self.clone();
+        let subscribed_at = timestamp_millis();
+        state.deliver_waiters.waiting.fetch_add(1, Ordering::SeqCst);
+        ntex::rt::spawn(async move {
+            let mut retains = retains.into_iter();
+            loop {
+                let batch = retains.by_ref().take(batch_size).collect::<Vec<_>>();
+                if batch.is_empty() {
+                    break;
+                }
+                let batch = {
+                    let live_topics = state.live_topics.read().await;
+                    batch
+                        .into_iter()
+                        .filter(|(topic, _)| {
+                            live_topics.get(topic).map(|at| *at < subscribed_at).unwrap_or(true)
+                        })
+                        .collect::<Vec<_>>()
+                };
+                state._send_retain_messages(batch, qos).await;
+                if !state.wait_deliver_ready().await {
+                    log::debug!("{:?} disconnected, remaining retained messages are not sent", state.id);
+                    break;
+                }
+            }
+            if state.deliver_waiters.waiting.fetch_sub(1, Ordering::SeqCst) == 1 {
+                state.live_topics.write().await.clear();
+            }
+        });
+        Ok(())
+    }
+
+    //Waits until the message queue is empty and the inflight window has room,
+    //returns false if the client is disconnected
+    #[inline]
+    async fn wait_deliver_ready(&self) -> bool {
+        //a closed connection is not notified, it is checked at this interval
+        const CLOSED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+        loop {
+            let ready = self.deliver_waiters.notify.notified();
+            if self.tx.as_ref().map(|tx| tx.is_closed()).unwrap_or(true) {
+                return false;
+            }
+            if self.deliver_queue().is_empty() && self.inflight_win().read().await.has_credit() {
+                return true;
+            }
+            let _ = tokio::time::timeout(CLOSED_CHECK_INTERVAL, ready).await;
+        }
+    }
+
     #[inline]
-    async fn send_retain_messages(&self, retains: Vec<(TopicName, Retain)>, qos: QoS) -> Result<()> {
+    async fn _send_retain_messages(&self, retains: Vec<(TopicName, Retain)>, qos: QoS) {
         for (topic, mut retain) in retains {
             log::debug!("{:?} topic:{:?}, retain:{:?}", self.id, topic, retain);
 
@@ -615,6 +906,7 @@ impl SessionState {
             retain.publish.qos = retain.publish.qos.less_value(qos);
             retain.publish.topic = topic;
             retain.publish.packet_id = None;
+            retain.publish.properties = translate::forward_properties(retain.publish.properties);
             retain.publish.create_time = chrono::Local::now().timestamp_millis();
 
             log::debug!("{:?} retain.publish: {:?}", self.id, retain.publish);
@@ -635,7 +927,6 @@ impl SessionState {
                     .await;
             }
         }
-        Ok(())
     }
 
     #[inline]
@@ -745,13 +1036,35 @@ impl SessionState {
         //hook, message_delivered
         let publish = self.hook.message_delivered(from.clone(), &publish).await.unwrap_or(publish);
 
-        //send message
-        sink.publish(
-            &publish,
-            expiry_check_res.message_expiry_interval(),
-            self.server_topic_aliases.as_ref(),
-        )
-        .await?; //@TODO ... at exception, send hook and or store message
+        //send message, the mountpoint prefix is not visible to the client
+        let unmounted = self.unmount(&publish.topic).map(|topic| Publish { topic, ..publish.clone() });
+        let packet = sink
+            .publish_packet(
+                unmounted.as_ref().unwrap_or(&publish),
+                expiry_check_res.message_expiry_interval(),
+                self.server_topic_aliases.as_ref(),
+            )
+            .await;
+        //hook, packet_sent
+        self.hook.packet_sent(&packet).await;
+        if let Err(e) = sink.send(packet) {
+            //hook, message_dropped
+            Runtime::instance()
+                .extends
+                .hook_mgr()
+                .await
+                .message_dropped(
+                    Some(self.id.clone()),
+                    from,
+                    publish,
+                    Reason::MessageDeliverFailed(ByteString::from(e.to_string())),
+                )
+                .await;
+            return Err(e);
+        }
+        Rates::instance().messages_out.inc();
+        Rates::instance().bytes_out.incs(publish.payload.len());
+        self.last_delivered_at.store(timestamp_millis(), Ordering::Relaxed);
 
         //cache messages to inflight window
         let moment_status = match publish.qos() {
@@ -766,9 +1079,107 @@ impl SessionState {
         Ok(())
     }
 
+    ///Delivers a message taken from the deliver queue. A message of a `mqtt.ordered_topics` topic is held
+    ///while an earlier message of the topic waits for PUBACK or PUBREC, or other messages are held before it
+    #[inline]
+    async fn deliver_ordered(&self, from: From, publish: Publish) -> Result<()> {
+        if !Runtime::instance().settings.latest_mqtt().is_ordered(&publish.topic) {
+            return self.deliver(from, publish).await;
+        }
+        let mut ordered_held = self.ordered_held.write().await;
+        if let Some(msgs) = ordered_held.get_mut(&publish.topic) {
+            msgs.push_back((from, publish));
+            return Ok(());
+        }
+        if self.ordered_pending(&publish.topic).await {
+            ordered_held.entry(publish.topic.clone()).or_default().push_back((from, publish));
+            return Ok(());
+        }
+        drop(ordered_held);
+        self.deliver(from, publish).await
+    }
+
+    #[inline]
+    async fn ordered_pending(&self, topic: &str) -> bool {
+        self.inflight_win().read().await.iter().any(|(_, m)| {
+            matches!(m.status, MomentStatus::UnAck | MomentStatus::UnReceived) && m.publish.topic == topic
+        })
+    }
+
+    ///Delivers the messages held for a topic, until one of them waits for an acknowledgement
+    pub(crate) async fn ordered_release(&self, topic: &str) {
+        if !Runtime::instance().settings.latest_mqtt().is_ordered(topic) {
+            return;
+        }
+        //the entry is kept while a message is delivered, newer messages are held behind it
+        while !self.ordered_pending(topic).await {
+            let msg = {
+                let mut ordered_held = self.ordered_held.write().await;
+                let msg = ordered_held.get_mut(topic).and_then(|msgs| msgs.pop_front());
+                if msg.is_none() {
+                    ordered_held.remove(topic);
+                }
+                msg
+            };
+            match msg {
+                Some((from, p)) => {
+                    if let Err(e) = self.deliver(from, p).await {
+                        log::error!("{:?} deliver held message error, {:?}", self.id, e);
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    #[inline]
+    async fn retries_exceeded(&self, iflt_msg: &InflightMessage) -> bool {
+        let max_retries = self.listen_cfg().message_max_retries;
+        match iflt_msg.publish.packet_id() {
+            Some(packet_id) => self.inflight_win().write().await.retries_exceeded(packet_id, max_retries),
+            None => false,
+        }
+    }
+
     #[inline]
     pub async fn reforward(&self, mut iflt_msg: InflightMessage) -> Result<()> {
+        if matches!(iflt_msg.status, MomentStatus::UnAck | MomentStatus::UnReceived)
+            && self.retries_exceeded(&iflt_msg).await
+        {
+            log::warn!(
+                "{:?} message retries exceeded, from: {:?}, message: {:?}",
+                self.id,
+                iflt_msg.from,
+                iflt_msg.publish
+            );
+            //hook, message_dropped
+            Runtime::instance()
+                .extends
+                .hook_mgr()
+                .await
+                .message_dropped(
+                    Some(self.id.clone()),
+                    iflt_msg.from,
+                    iflt_msg.publish,
+                    Reason::MessageDeliverFailed(ByteString::from_static("max retries exceeded")),
+                )
+                .await;
+            if self.listen_cfg().message_max_retries_disconnect {
+                self.send(Message::Closed(Reason::from_static("Message max retries exceeded")))?;
+            }
+            return Ok(());
+        }
         match iflt_msg.status {
+            //messages of ordered topics are sent again at once, not behind newer messages in the queue
+            MomentStatus::UnAck | MomentStatus::UnReceived
+                if self.sink.is_some()
+                    && Runtime::instance().settings.latest_mqtt().is_ordered(&iflt_msg.publish.topic) =>
+            {
+                iflt_msg.publish.set_dup(true);
+                let topic = iflt_msg.publish.topic.clone();
+                self.deliver(iflt_msg.from, iflt_msg.publish).await?;
+                self.ordered_release(&topic).await;
+            }
             MomentStatus::UnAck => {
                 iflt_msg.publish.set_dup(true);
                 self.forward(iflt_msg.from, iflt_msg.publish).await;
@@ -788,6 +1199,18 @@ impl SessionState {
                         iflt_msg.from,
                         iflt_msg.publish
                     );
+                    //hook, message_dropped
+                    Runtime::instance()
+                        .extends
+                        .hook_mgr()
+                        .await
+                        .message_dropped(
+                            Some(self.id.clone()),
+                            iflt_msg.from,
+                            iflt_msg.publish,
+                            Reason::MessageExpiration,
+                        )
+                        .await;
                     return Ok(());
                 }
 
@@ -811,7 +1234,10 @@ impl SessionState {
                     Sink::V5(_) => iflt_msg.release_packet_v5(),
                 };
                 if let Some(release_packet) = release_packet {
+                    //hook, packet_sent
+                    self.hook.packet_sent(&release_packet).await;
                     sink.send(release_packet)?;
+                    self.last_delivered_at.store(timestamp_millis(), Ordering::Relaxed);
                     self.inflight_win().write().await.push_back(InflightMessage::new(
                         MomentStatus::UnComplete,
                         iflt_msg.from,
@@ -857,10 +1283,33 @@ impl SessionState {
             return Err(MqttError::TooManySubscriptions);
         }
 
-        if listen_cfg.max_topic_levels > 0
-            && Topic::from_str(&sub.topic_filter)?.len() > listen_cfg.max_topic_levels
-        {
-            return Err(MqttError::TooManyTopicLevels);
+        if let Err(e) = self.check_topic(&sub.topic_filter) {
+            log::info!("{:?} subscription refused, {}", self.id, e);
+            return Ok(SubscribeReturn::new_failure(SubscribeAckReason::TopicFilterInvalid));
+        }
+
+        let wildcard = &listen_cfg.wildcard_subscription;
+        if wildcard.deny_root && sub.topic_filter == "#" {
+            log::info!("{:?} subscription to the root multi-level wildcard is refused", self.id);
+            return Ok(SubscribeReturn::new_failure(SubscribeAckReason::WildcardSubscriptionsNotSupported));
+        }
+
+        if let Some(topic_filter) = self.mount_filter(&sub.topic_filter) {
+            sub.topic_filter = topic_filter;
+        }
+
+        if wildcard.max > 0 && sub.topic_filter.contains(['+', '#']) {
+            let subs = self.subscriptions().await?;
+            let resubscribe = subs.read().await.contains_key(&sub.topic_filter);
+            if !resubscribe && subs.wildcard_len().await >= wildcard.max {
+                log::info!(
+                    "{:?} too many wildcard subscriptions, max: {}, topic_filter: {}",
+                    self.id,
+                    wildcard.max,
+                    sub.topic_filter
+                );
+                return Ok(SubscribeReturn::new_failure(SubscribeAckReason::QuotaExceeded));
+            }
         }
 
         if let Some(limit) = sub.opts.limit_subs() {
@@ -890,14 +1339,22 @@ impl SessionState {
         sub.opts.set_qos(sub.opts.qos().less_value(listen_cfg.max_qos_allowed));
 
         //hook, client_subscribe
-        let topic_filter = self.hook.client_subscribe(&sub).await;
-        log::debug!("{:?} topic_filter: {:?}", self.id, topic_filter);
+        let (topic_filter, qos_cap) = self.hook.client_subscribe(&sub).await;
+        log::debug!("{:?} topic_filter: {:?}, qos_cap: {:?}", self.id, topic_filter, qos_cap);
 
         //adjust topic filter
         if let Some(topic_filter) = topic_filter {
             sub.topic_filter = topic_filter;
         }
 
+        //cap the granted QoS, whatever the client requested
+        if let Some(qos) = qos_cap {
+            sub.opts.set_qos(sub.opts.qos().less_value(qos));
+        }
+        if let Some(qos) = Runtime::instance().settings.latest_mqtt().subscribe_qos_cap(&sub.topic_filter) {
+            sub.opts.set_qos(sub.opts.qos().less_value(qos));
+        }
+
         //hook, client_subscribe_check_acl
         let acl_result = self.hook.client_subscribe_check_acl(&sub).await;
         if let Some(acl_result) = acl_result {
@@ -906,6 +1363,9 @@ impl SessionState {
             } else {
                 return Ok(acl_result);
             }
+        } else if wildcard.multi_level_acl && sub.topic_filter.contains('#') {
+            log::info!("{:?} multi-level wildcard is not granted, {}", self.id, sub.topic_filter);
+            return Ok(SubscribeReturn::new_failure(SubscribeAckReason::NotAuthorized));
         }
 
         //subscribe
@@ -962,6 +1422,9 @@ impl SessionState {
     #[inline]
     pub(crate) async fn unsubscribe(&self, mut unsub: Unsubscribe) -> Result<()> {
         log::debug!("{:?} unsubscribe: {:?}", self.id, unsub);
+        if let Some(topic_filter) = self.mount_filter(&unsub.topic_filter) {
+            unsub.topic_filter = topic_filter;
+        }
         //hook, client_unsubscribe
         let topic_filter = self.hook.client_unsubscribe(&unsub).await;
         if let Some(topic_filter) = topic_filter {
@@ -1024,18 +1487,49 @@ impl SessionState {
         if let Some(client_topic_aliases) = &self.client_topic_aliases {
             p.topic = client_topic_aliases.set_and_get(p.properties.topic_alias, p.topic).await?;
         }
+        if self.listen_cfg().response_topic_enforce {
+            if let (Some(response_topic), Some(prefix)) =
+                (p.properties.response_topic.as_ref(), self.response_information())
+            {
+                if !response_topic.starts_with(&prefix[..]) {
+                    return Err(MqttError::PublishAckReason(
+                        PublishAckReason::NotAuthorized,
+                        ByteString::from(format!(
+                            "response topic {} is not under the response information {}",
+                            response_topic, prefix
+                        )),
+                    ));
+                }
+            }
+        }
         self.publish(p).await
     }
 
     #[inline]
     async fn publish(&self, mut publish: Publish) -> Result<bool> {
         let from = From::from_custom(self.id.clone());
+        Rates::instance().messages_in.inc();
+        Rates::instance().bytes_in.incs(publish.payload.len());
 
         let listen_cfg = self.listen_cfg();
+        self.check_topic(&publish.topic).map_err(|e| {
+            MqttError::PublishAckReason(PublishAckReason::TopicNameInvalid, ByteString::from(e.to_string()))
+        })?;
+        if events::is_events_topic(&publish.topic) {
+            return Err(MqttError::PublishAckReason(
+                PublishAckReason::NotAuthorized,
+                ByteString::from(format!("{} is reserved for broker events", publish.topic)),
+            ));
+        }
+
         if self.listen_cfg().delayed_publish {
             publish = Runtime::instance().extends.delayed_sender().await.parse(publish)?;
         }
 
+        if let Some(topic) = self.mount(&publish.topic) {
+            publish.topic = topic;
+        }
+
         //hook, message_publish
         let publish = self.hook.message_publish(from.clone(), &publish).await.unwrap_or(publish);
 
@@ -1084,7 +1578,7 @@ impl SessionState {
                 )
                 .await?
             {
-                if Runtime::instance().settings.mqtt.delayed_publish_immediate {
+                if Runtime::instance().settings.latest_mqtt().delayed_publish_immediate {
                     Self::forwards(
                         f,
                         p,
@@ -1119,14 +1613,45 @@ impl SessionState {
         Ok(true)
     }
 
+    ///Applies the `mqtt.retain_forbidden` rules to a retained message, false if it is rejected and dropped
+    async fn retain_forbidden_check(from: &From, publish: &mut Publish) -> bool {
+        if !publish.retain() {
+            return true;
+        }
+        match Runtime::instance().settings.latest_mqtt().retain_forbidden(&publish.topic) {
+            None => true,
+            Some(RetainForbiddenAction::Strip) => {
+                Metrics::instance().messages_retain_stripped_inc();
+                publish.retain = false;
+                true
+            }
+            Some(RetainForbiddenAction::Reject) => {
+                log::debug!("{:?} retain is forbidden, topic: {}", from.id, publish.topic);
+                Metrics::instance().messages_retain_rejected_inc();
+                //hook, Message dropped
+                Runtime::instance()
+                    .extends
+                    .hook_mgr()
+                    .await
+                    .message_dropped(None, from.clone(), publish.clone(), Reason::PublishRefused)
+                    .await;
+                false
+            }
+        }
+    }
+
     #[inline]
     pub async fn forwards(
         from: From,
-        publish: Publish,
+        mut publish: Publish,
         retain_available: bool,
         message_storage_available: bool,
         message_expiry_interval: Option<Duration>,
     ) -> Result<()> {
+        if !Self::retain_forbidden_check(&from, &mut publish).await {
+            return Ok(());
+        }
+
         //make message id
         let msg_id = if message_storage_available {
             Some(Runtime::instance().extends.message_mgr().await.next_msg_id())
@@ -1154,9 +1679,14 @@ impl SessionState {
                 None
             };
 
+        let nonsubscribed_publish = if dead_letter::nonsubscribed_enable() { Some(publish.clone()) } else { None };
+
         let sub_cids = match Runtime::instance().extends.shared().await.forwards(from.clone(), publish).await
         {
             Ok(None) => {
+                if let Some(p) = nonsubscribed_publish {
+                    dead_letter::forward(None, &from, &p, &Reason::from_static("No subscribers"));
+                }
                 //hook, message_nonsubscribed
                 Runtime::instance().extends.hook_mgr().await.message_nonsubscribed(from).await;
                 None
@@ -1298,6 +1828,17 @@ impl SessionState {
         Ok(())
     }
 
+    ///Summary of the session state, replied to the connection that kicks the session
+    #[inline]
+    async fn kick_ack(&self) -> KickAck {
+        let disconnect = self.disconnect().await.unwrap_or(None);
+        KickAck {
+            clean_session: self.clean_session(disconnect.as_ref()).await,
+            queue_len: self.deliver_queue().len(),
+            inflight_len: self.inflight_win().read().await.len(),
+        }
+    }
+
     #[inline]
     async fn clean_session(&self, d: Option<&Disconnect>) -> bool {
         let connect_info = self.connect_info().await;
@@ -1359,6 +1900,31 @@ pub struct _Session {
     pub id: Id,
     pub fitter: FitterType,
     pub extra_attrs: RwLock<ExtraAttrs>,
+    pub attrs: RwLock<SessionAttrs>,
+    last_will_cleared: AtomicBool,
+    last_received_at: AtomicI64,
+    last_delivered_at: AtomicI64,
+    //messages of `mqtt.ordered_topics` topics waiting for an earlier message to be acknowledged
+    ordered_held: RwLock<HashMap<TopicName, VecDeque<(From, Publish)>>>,
+    //retained messages delivered in the background wait for the message queue and the inflight window
+    deliver_waiters: Arc<DeliverWaiters>,
+    //topics forwarded while retained messages are delivered in the background, with the time of the last one
+    live_topics: RwLock<HashMap<TopicName, TimestampMillis>>,
+}
+
+#[derive(Default)]
+struct DeliverWaiters {
+    waiting: AtomicUsize,
+    notify: Notify,
+}
+
+impl DeliverWaiters {
+    #[inline]
+    fn notify(&self) {
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            self.notify.notify_waiters();
+        }
+    }
 }
 
 impl Deref for _Session {
@@ -1390,6 +1956,110 @@ impl std::fmt::Debug for Session {
 }
 
 impl Session {
+    ///Topic prefix of the listener, ${clientid} and ${username} placeholders are replaced
+    #[inline]
+    fn mountpoint(&self) -> Option<String> {
+        self.listen_cfg().mountpoint.as_ref().filter(|mp| !mp.is_empty()).map(|mp| {
+            mp.replace("${clientid}", &self.id.client_id)
+                .replace("${username}", self.id.username.as_deref().unwrap_or_default())
+        })
+    }
+
+    ///Response Information returned in the CONNACK of MQTT V5 clients, ${clientid} or %c and
+    ///${username} or %u placeholders are replaced
+    #[inline]
+    pub(crate) fn response_information(&self) -> Option<ByteString> {
+        self.listen_cfg().response_information.as_ref().filter(|ri| !ri.is_empty()).map(|ri| {
+            let username = self.id.username.as_deref().unwrap_or_default();
+            ByteString::from(
+                ri.replace("${clientid}", &self.id.client_id)
+                    .replace("%c", &self.id.client_id)
+                    .replace("${username}", username)
+                    .replace("%u", username),
+            )
+        })
+    }
+
+    ///Server Reference of the listener, sent when the connection is closed by the broker
+    #[inline]
+    pub(crate) fn server_reference(&self) -> Option<ServerReference> {
+        self.listen_cfg().server_reference.as_deref().filter(|r| !r.is_empty()).map(ServerReference::from)
+    }
+
+    ///Whether the acks of failures carry a Reason String, MQTT 5.0 clients can opt out with the Request
+    ///Problem Information of the CONNECT packet
+    #[inline]
+    pub(crate) async fn problem_info(&self) -> bool {
+        self.listen_cfg().problem_information
+            && self.connect_info().await.map(|c| c.request_problem_info()).unwrap_or_default()
+    }
+
+    #[inline]
+    fn mount(&self, topic: &str) -> Option<ByteString> {
+        self.mountpoint().map(|mp| ByteString::from(format!("{}{}", mp, topic)))
+    }
+
+    #[inline]
+    fn mount_filter(&self, topic_filter: &str) -> Option<ByteString> {
+        self.mountpoint().map(|mp| mount_topic_filter(&mp, topic_filter))
+    }
+
+    #[inline]
+    fn unmount(&self, topic: &str) -> Option<ByteString> {
+        self.mountpoint()
+            .and_then(|mp| topic.strip_prefix(mp.as_str()).map(|t| ByteString::from(t.to_string())))
+    }
+
+    ///The will message as it is published, with the topic mounted, None if the client has no will
+    #[inline]
+    pub(crate) async fn last_will_publish(&self) -> Result<Option<Publish>> {
+        if self.last_will_cleared() {
+            return Ok(None);
+        }
+        let conn_info = self.connect_info().await?;
+        if let Some(lw) = conn_info.last_will() {
+            let mut p = Publish::try_from(lw)?;
+            if let Some(topic) = self.mount(&p.topic) {
+                p.topic = topic;
+            }
+            Ok(Some(p))
+        } else {
+            Ok(None)
+        }
+    }
+
+    ///The pending will message, None if the client has no will or it has been cleared
+    #[inline]
+    pub async fn last_will(&self) -> Option<serde_json::Value> {
+        if self.last_will_cleared() {
+            return None;
+        }
+        self.connect_info().await.ok()?.last_will().map(|lw| lw.to_json())
+    }
+
+    ///Discards the will message, it is no longer published when the connection is closed
+    #[inline]
+    pub fn last_will_clear(&self) {
+        self.last_will_cleared.store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn last_will_cleared(&self) -> bool {
+        self.last_will_cleared.load(Ordering::SeqCst)
+    }
+
+    ///When the last packet was received from the client, 0 if none since it connected
+    #[inline]
+    pub fn last_received_at(&self) -> TimestampMillis {
+        self.last_received_at.load(Ordering::Relaxed)
+    }
+
+    ///When the last PUBLISH or PUBREL was sent to the client, 0 if none since it connected
+    #[inline]
+    pub fn last_delivered_at(&self) -> TimestampMillis {
+        self.last_delivered_at.load(Ordering::Relaxed)
+    }
+
     #[inline]
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
@@ -1403,6 +2073,7 @@ impl Session {
         conn_info: ConnectInfoType,
         session_present: bool,
         superuser: bool,
+        attrs: SessionAttrs,
         connected: bool,
         connected_at: TimestampMillis,
 
@@ -1414,19 +2085,32 @@ impl Session {
         let max_inflight = max_inflight.get() as usize;
         let message_retry_interval = listen_cfg.message_retry_interval.as_millis() as TimestampMillis;
         let message_expiry_interval = listen_cfg.message_expiry_interval.as_millis() as TimestampMillis;
+        let message_retry_max_interval = listen_cfg.message_retry_max_interval.as_millis() as TimestampMillis;
+        let deliver_waiters = Arc::new(DeliverWaiters::default());
         let mut deliver_queue = MessageQueue::new(max_mqueue_len);
         deliver_queue.on_push(|| {
             Runtime::instance().stats.message_queues.inc();
         });
-        deliver_queue.on_pop(|| {
+        let waiters = deliver_waiters.clone();
+        deliver_queue.on_pop(move || {
             Runtime::instance().stats.message_queues.dec();
+            waiters.notify();
         });
+        deliver_queue.weigh(|(_, p): &(From, Publish)| p.payload.len());
+        if Runtime::instance().settings.latest_mqtt().priority.is_enabled() {
+            deliver_queue.priority(|(_, p): &(From, Publish)| {
+                Runtime::instance().settings.latest_mqtt().priority.is_high(p)
+            });
+        }
+        let waiters = deliver_waiters.clone();
         let out_inflight = Inflight::new(max_inflight, message_retry_interval, message_expiry_interval)
+            .retry_backoff(listen_cfg.message_retry_backoff, message_retry_max_interval)
             .on_push(|| {
                 Runtime::instance().stats.out_inflights.inc();
             })
-            .on_pop(|| {
+            .on_pop(move || {
                 Runtime::instance().stats.out_inflights.dec();
+                waiters.notify();
             });
 
         Runtime::instance().stats.sessions.inc();
@@ -1455,7 +2139,28 @@ impl Session {
                 last_id,
             )
             .await?;
-        Ok(Self(Arc::new(_Session { inner: session_like, id, fitter, extra_attrs })))
+        let attrs = RwLock::new(attrs);
+        Ok(Self(Arc::new(_Session {
+            inner: session_like,
+            id,
+            fitter,
+            extra_attrs,
+            attrs,
+            last_will_cleared: AtomicBool::new(false),
+            last_received_at: AtomicI64::new(0),
+            last_delivered_at: AtomicI64::new(0),
+            ordered_held: RwLock::new(HashMap::default()),
+            deliver_waiters,
+            live_topics: RwLock::new(HashMap::default()),
+        })))
+    }
+
+    //Records the topic of a forwarded message while retained messages are delivered in the background
+    #[inline]
+    pub(crate) async fn forwarded(&self, topic: &TopicName) {
+        if self.deliver_waiters.waiting.load(Ordering::SeqCst) > 0 {
+            self.live_topics.write().await.insert(topic.clone(), timestamp_millis());
+        }
     }
 
     #[inline]
@@ -1465,6 +2170,9 @@ impl Session {
         let subscriptions = self.subscriptions_drain().await?;
 
         let mut offline_messages = Vec::new();
+        for (_, msgs) in self.ordered_held.write().await.drain() {
+            offline_messages.extend(msgs);
+        }
         while let Some(item) = self.deliver_queue().pop() {
             //@TODO ..., check message expired
             offline_messages.push(item);
@@ -1507,6 +2215,7 @@ impl Session {
             "queues": self.deliver_queue().len(),
             "inflights": self.inflight_win().read().await.len(),
             "created_at": self.created_at().await.unwrap_or_default(),
+            "attrs": self.attrs.read().await.to_json(),
         });
         data
     }
@@ -1583,3 +2292,30 @@ pub trait SessionLike: Sync + Send {
     #[inline]
     async fn keepalive(&self, _ping: IsPing) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_topic_filter_keeps_prefix() {
+        assert_eq!(mount_topic_filter("mp/", "a/b"), "mp/a/b");
+        assert_eq!(mount_topic_filter("mp/", "$share/g1/a/b"), "$share/g1/mp/a/b");
+        assert_eq!(mount_topic_filter("mp/", "$queue/a/#"), "$queue/mp/a/#");
+        assert_eq!(mount_topic_filter("mp/", "$replay/10s/a/+"), "$replay/10s/mp/a/+");
+        assert_eq!(mount_topic_filter("mp/", "$share/g1"), "mp/$share/g1");
+    }
+
+    #[test]
+    fn mount_shared_subscription() {
+        let tf = ByteString::from("$share/g1/a/b");
+        let sub = Subscribe::from_v3(&tf, QoS::AtLeastOnce, true, false).unwrap();
+        assert!(sub.is_shared());
+        assert_eq!(sub.opts.shared_group(), Some(&SharedGroup::from("g1")));
+        assert_eq!(mount_topic_filter("tenant1/", &sub.topic_filter), "tenant1/a/b");
+
+        let unsub = Unsubscribe::from(&tf, true, false).unwrap();
+        assert!(unsub.is_shared());
+        assert_eq!(mount_topic_filter("tenant1/", &unsub.topic_filter), "tenant1/a/b");
+    }
+}
@@ -1,13 +1,19 @@
 use std::fmt;
 use std::sync::atomic::{AtomicIsize, Ordering};
+use std::time::Duration;
 
+use dashmap::DashMap;
 use ntex_mqtt::{handshakings, in_inflights};
 use once_cell::sync::OnceCell;
 
 use crate::broker::executor::{get_active_count, get_rate};
+use crate::broker::rates::{Rates, RatesAverages, RatesTotals};
+use crate::plugin::Store;
 #[cfg(feature = "debug")]
 use crate::runtime::TaskExecStats;
-use crate::{HashMap, NodeId, Runtime, StatsMergeMode};
+use crate::{HashMap, NodeId, Result, Runtime, SharedGroup, StatsMergeMode, SubRelationsMap};
+
+const STORE_NAMESPACE: &str = "rmqtt-stats";
 
 type Current = AtomicIsize;
 type Max = AtomicIsize;
@@ -110,6 +116,12 @@ impl Counter {
         self.1.fetch_min(max, Ordering::SeqCst);
     }
 
+    ///The maximum starts again from the current count
+    #[inline]
+    pub fn max_reset(&self) {
+        self.1.store(self.count(), Ordering::SeqCst);
+    }
+
     #[inline]
     pub fn count(&self) -> isize {
         self.0.load(Ordering::SeqCst)
@@ -146,6 +158,34 @@ impl Counter {
     }
 }
 
+///Messages dispatched to a shared subscription group, `local` ones went to a member connected to the
+///publishing node and `remote` ones to a member on another node
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct SharedGroupDispatches {
+    pub local: usize,
+    pub remote: usize,
+}
+
+impl SharedGroupDispatches {
+    #[inline]
+    pub fn add(&mut self, other: &Self) {
+        self.local += other.local;
+        self.remote += other.remote;
+    }
+}
+
+#[inline]
+fn shared_group_dispatches() -> &'static DashMap<SharedGroup, SharedGroupDispatches> {
+    static INSTANCE: OnceCell<DashMap<SharedGroup, SharedGroupDispatches>> = OnceCell::new();
+    INSTANCE.get_or_init(DashMap::default)
+}
+
+#[inline]
+fn listener_connections() -> &'static DashMap<u16, Counter> {
+    static INSTANCE: OnceCell<DashMap<u16, Counter>> = OnceCell::new();
+    INSTANCE.get_or_init(DashMap::default)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Stats {
     pub handshakings: Counter,
@@ -165,6 +205,10 @@ pub struct Stats {
 
     topics_map: HashMap<NodeId, Counter>,
     routes_map: HashMap<NodeId, Counter>,
+    shared_groups: HashMap<SharedGroup, SharedGroupDispatches>,
+    listeners: HashMap<u16, Counter>,
+    rates: RatesAverages,
+    totals: RatesTotals,
 
     #[cfg(feature = "debug")]
     debug_client_states_map: HashMap<NodeId, usize>,
@@ -204,6 +248,10 @@ impl Stats {
 
             topics_map: HashMap::default(),
             routes_map: HashMap::default(),
+            shared_groups: HashMap::default(),
+            listeners: HashMap::default(),
+            rates: RatesAverages::default(),
+            totals: RatesTotals::default(),
 
             #[cfg(feature = "debug")]
             debug_client_states_map: HashMap::default(),
@@ -298,6 +346,16 @@ impl Stats {
             retaineds,
             topics_map,
             routes_map,
+            shared_groups: shared_group_dispatches()
+                .iter()
+                .map(|entry| (entry.key().clone(), *entry.value()))
+                .collect(),
+            listeners: listener_connections()
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().clone()))
+                .collect(),
+            rates: Rates::instance().averages(),
+            totals: Rates::instance().totals(),
 
             #[cfg(feature = "debug")]
             debug_client_states_map,
@@ -316,6 +374,93 @@ impl Stats {
         }
     }
 
+    ///Counts a connection on the listener bound to `port`, the total is in `connections`
+    #[inline]
+    pub fn listener_connected(&self, port: u16) {
+        self.connections.inc();
+        listener_connections().entry(port).or_default().inc();
+    }
+
+    #[inline]
+    pub fn listener_disconnected(&self, port: u16) {
+        self.connections.dec();
+        if let Some(c) = listener_connections().get(&port) {
+            c.dec();
+        }
+    }
+
+    ///Counts a message dispatched to a member of the shared subscription group
+    #[inline]
+    pub fn shared_group_dispatched(&self, group: &SharedGroup, is_local: bool) {
+        let mut dispatches = shared_group_dispatches().entry(group.clone()).or_default();
+        if is_local {
+            dispatches.local += 1;
+        } else {
+            dispatches.remote += 1;
+        }
+    }
+
+    ///Counts the shared subscription members selected in the relations
+    #[inline]
+    pub fn shared_groups_dispatched(&self, relations_map: &SubRelationsMap) {
+        let this_node_id = Runtime::instance().node.id();
+        for (node_id, relations) in relations_map.iter() {
+            for (_, _, _, _, group) in relations.iter() {
+                if let Some((group, _, _)) = group {
+                    self.shared_group_dispatched(group, *node_id == this_node_id);
+                }
+            }
+        }
+    }
+
+    ///Restores the cumulative counters and the peaks saved by `persist()`, called once the storage
+    ///plugins have been started
+    pub async fn restore(&self) -> Result<()> {
+        if let Some(persisted) = store().get::<Persisted>(&store_key()).await? {
+            log::info!("restore statistics, {:?}", persisted);
+            Rates::instance().totals_set(&persisted.totals);
+            self.connections.max_max(persisted.connections_max);
+            self.sessions.max_max(persisted.sessions_max);
+            self.subscriptions.max_max(persisted.subscriptions_max);
+        }
+        Ok(())
+    }
+
+    ///Saves the cumulative counters and the peaks to the key-value store
+    pub async fn persist(&self) -> Result<()> {
+        let persisted = Persisted {
+            totals: Rates::instance().totals(),
+            connections_max: self.connections.max(),
+            sessions_max: self.sessions.max(),
+            subscriptions_max: self.subscriptions.max(),
+        };
+        store().set(&store_key(), &persisted).await
+    }
+
+    ///Saves the statistics periodically, see `node.stats_persist_interval`
+    pub(crate) fn persist_start(&'static self, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = self.persist().await {
+                    log::warn!("persist statistics error, {:?}", e);
+                }
+            }
+        });
+    }
+
+    ///Resets the cumulative counters to zero and the peaks to the current counts
+    pub async fn reset(&self) -> Result<()> {
+        Rates::instance().totals_set(&RatesTotals::default());
+        self.connections.max_reset();
+        self.sessions.max_reset();
+        self.subscriptions.max_reset();
+        if !Runtime::instance().settings.node.stats_persist_interval.is_zero() {
+            self.persist().await?;
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn add(&mut self, other: Self) {
         self.handshakings.add(&other.handshakings);
@@ -335,6 +480,14 @@ impl Stats {
 
         self.topics_map.extend(other.topics_map);
         self.routes_map.extend(other.routes_map);
+        for (group, dispatches) in other.shared_groups {
+            self.shared_groups.entry(group).or_default().add(&dispatches);
+        }
+        for (port, connections) in other.listeners {
+            self.listeners.entry(port).or_default().add(&connections);
+        }
+        self.rates.add(&other.rates);
+        self.totals.add(&other.totals);
 
         #[cfg(feature = "debug")]
         {
@@ -368,6 +521,13 @@ impl Stats {
         let router = Runtime::instance().extends.router().await;
         let topics = router.merge_topics(&self.topics_map);
         let routes = router.merge_routes(&self.routes_map);
+        let listeners = self
+            .listeners
+            .iter()
+            .map(|(port, c)| {
+                (port.to_string(), json!({ "connections.count": c.count(), "connections.max": c.max() }))
+            })
+            .collect::<serde_json::Map<_, _>>();
 
         let mut json_val = json!({
             "handshakings.count": self.handshakings.count(),
@@ -403,6 +563,11 @@ impl Stats {
             "topics.max": topics.max(),
             "routes.count": routes.count(),
             "routes.max": routes.max(),
+
+            "shared_groups": self.shared_groups,
+            "listeners": listeners,
+            "rates": self.rates,
+            "totals": self.totals,
         });
 
         #[cfg(feature = "debug")]
@@ -423,6 +588,25 @@ impl Stats {
     }
 }
 
+///The statistics that are kept across restarts
+#[derive(Serialize, Deserialize, Debug)]
+struct Persisted {
+    totals: RatesTotals,
+    connections_max: isize,
+    sessions_max: isize,
+    subscriptions_max: isize,
+}
+
+#[inline]
+fn store() -> Store {
+    Store::new(STORE_NAMESPACE)
+}
+
+#[inline]
+fn store_key() -> String {
+    format!("node-{}", Runtime::instance().node.id())
+}
+
 #[inline]
 fn stats_merge<'a>(mode: &StatsMergeMode, c: &'a Counter, o: &Counter) -> &'a Counter {
     match mode {
@@ -0,0 +1,113 @@
+//! Conversion of messages between MQTT 3.1/3.1.1 and MQTT 5.0.
+//!
+//! Messages are kept in the version neutral [`Publish`] inside the broker, they are built from the
+//! PUBLISH packet of the sender here and converted again for each subscriber, so that forwarding,
+//! bridges and retained messages apply the same rules whatever the versions of both ends are.
+
+use std::num::{NonZeroU16, NonZeroU32};
+
+use bytestring::ByteString;
+
+use crate::broker::types::{
+    v3, v5, Publish, PublishProperties, PublishPropertiesV5, TopicName, UserProperties,
+};
+
+///Builds a message from a MQTT 3.1/3.1.1 PUBLISH, the query string of the topic, e.g. "a/b?k1=v1&k2=v2",
+///is turned into user properties
+#[inline]
+pub fn from_v3(p: &v3::Publish) -> Publish {
+    let query = p.query();
+    let properties = if !query.is_empty() {
+        PublishProperties::from(query_user_properties(query.as_bytes()))
+    } else {
+        PublishProperties::default()
+    };
+
+    Publish {
+        dup: p.dup(),
+        retain: p.retain(),
+        qos: p.qos(),
+        topic: TopicName::from(p.topic().path()),
+        packet_id: p.id(),
+        payload: p.take_payload(),
+
+        properties,
+        delay_interval: None,
+        create_time: chrono::Local::now().timestamp_millis(),
+    }
+}
+
+///Builds a message from a MQTT 5.0 PUBLISH, the topic alias is kept for the session of the sender to
+///resolve it, it is replaced or dropped when the message is converted again
+#[inline]
+pub fn from_v5(p: &v5::Publish) -> Publish {
+    Publish {
+        dup: p.dup(),
+        retain: p.retain(),
+        qos: p.qos(),
+        topic: TopicName::from(p.topic().path()),
+        packet_id: p.id(),
+        payload: p.take_payload(),
+
+        properties: PublishProperties::from(p.packet().properties.clone()),
+        delay_interval: None,
+        create_time: chrono::Local::now().timestamp_millis(),
+    }
+}
+
+///User properties of the query string of a MQTT 3 topic, MQTT 3 clients use it in place of properties
+#[inline]
+pub fn query_user_properties(query: &[u8]) -> UserProperties {
+    url::form_urlencoded::parse(query)
+        .into_owned()
+        .map(|(key, val)| (ByteString::from(key), ByteString::from(val)))
+        .collect::<UserProperties>()
+}
+
+///Properties that are passed on when a message leaves the connection it was received on. The topic
+///alias belongs to a single connection and the subscription identifiers to a single subscriber,
+///both are dropped, the others are kept unchanged
+#[inline]
+pub fn forward_properties(mut props: PublishProperties) -> PublishProperties {
+    props.topic_alias = None;
+    props.subscription_ids = None;
+    props
+}
+
+///Converts a message for a MQTT 3.1/3.1.1 subscriber, MQTT 3 has no properties so all of them,
+///including the user properties, are dropped
+#[inline]
+pub fn to_v3(p: &Publish) -> v3::codec::Publish {
+    v3::codec::Publish {
+        dup: p.dup,
+        retain: p.retain,
+        qos: p.qos,
+        topic: p.topic.clone(),
+        packet_id: p.packet_id,
+        payload: p.payload.clone(),
+    }
+}
+
+///Converts a message for a MQTT 5.0 subscriber. `topic` and `topic_alias` are the ones of the connection
+///of the subscriber, the message expiry interval is the remaining lifetime of the message. Messages from
+///MQTT 3 publishers only carry the user properties of their topic query string
+#[inline]
+pub fn to_v5(
+    p: &Publish,
+    topic: TopicName,
+    topic_alias: Option<NonZeroU16>,
+    message_expiry_interval: Option<NonZeroU32>,
+) -> v5::codec::Publish {
+    let mut properties: PublishPropertiesV5 = p.properties.clone().into();
+    properties.topic_alias = topic_alias;
+    properties.message_expiry_interval = message_expiry_interval;
+    v5::codec::Publish {
+        dup: p.dup,
+        retain: p.retain,
+        qos: p.qos,
+        topic,
+        packet_id: p.packet_id,
+        payload: p.payload.clone(),
+        properties,
+    }
+}
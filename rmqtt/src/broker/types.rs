@@ -5,7 +5,7 @@ use std::fmt;
 use std::fmt::Display;
 use std::hash::Hash;
 use std::mem::{size_of, size_of_val};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::num::{NonZeroU16, NonZeroU32};
 use std::ops::Deref;
 use std::rc::Rc;
@@ -48,6 +48,7 @@ use ntex_mqtt::TopicLevel;
 use crate::broker::fitter::Fitter;
 use crate::broker::inflight::Inflight;
 use crate::broker::queue::{Queue, Sender};
+use crate::broker::translate;
 use crate::{MqttError, Result, Runtime};
 
 pub type NodeId = u64;
@@ -72,6 +73,7 @@ pub type MessageExpiry = bool;
 pub type TimestampMillis = i64;
 pub type Timestamp = i64;
 pub type IsOnline = bool;
+pub type ServerReference = bytestring::ByteString;
 pub type IsAdmin = bool;
 pub type LimiterName = u16;
 pub type CleanStart = bool;
@@ -272,14 +274,40 @@ impl ConnectInfo {
         }
     }
 
+    ///MQTT protocol level, `MQTT_LEVEL_31`, `MQTT_LEVEL_311` or `MQTT_LEVEL_5`
     #[inline]
-    pub fn proto_ver(&self) -> u8 {
+    pub fn protocol_version(&self) -> u8 {
         match self {
             ConnectInfo::V3(_, conn_info) => conn_info.protocol.level(),
             ConnectInfo::V5(_, _) => MQTT_LEVEL_5,
         }
     }
 
+    #[inline]
+    pub fn proto_ver(&self) -> u8 {
+        self.protocol_version()
+    }
+
+    ///Request Problem Information of the CONNECT packet, always false for MQTT 3.1.1
+    #[inline]
+    pub fn request_problem_info(&self) -> bool {
+        if let ConnectInfo::V5(_, connect) = self {
+            connect.request_problem_info
+        } else {
+            false
+        }
+    }
+
+    ///User properties of the CONNECT packet, None for MQTT 3.1.1
+    #[inline]
+    pub fn user_properties(&self) -> Option<&UserProperties> {
+        if let ConnectInfo::V5(_, connect) = self {
+            Some(&connect.user_properties)
+        } else {
+            None
+        }
+    }
+
     ///client max packet size, S(Max Limit) -> C
     #[inline]
     pub fn max_packet_size(&self) -> Option<NonZeroU32> {
@@ -350,7 +378,8 @@ pub enum PublishAclResult {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AuthResult {
-    Allow(Superuser),
+    ///Attributes are stored in the session when the connection is accepted
+    Allow(Superuser, Option<SessionAttrs>),
     ///User is not found
     NotFound,
     BadUsernameOrPassword,
@@ -546,6 +575,205 @@ pub fn parse_topic_filter(
     Ok((topic, shared_group, limit_subs))
 }
 
+const MAX_TOPIC_LEN: usize = u16::MAX as usize;
+
+///Topic name of a published message, non-empty, without wildcards or null characters
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "ByteString", into = "ByteString")]
+pub struct ValidTopicName(TopicName);
+
+impl ValidTopicName {
+    #[inline]
+    pub fn new<T: Into<TopicName>>(name: T) -> Result<Self> {
+        let name = name.into();
+        if name.is_empty() || name.len() > MAX_TOPIC_LEN {
+            return Err(MqttError::TopicError(format!("Illegal topic name length, {:?}", name)));
+        }
+        if name.contains(['+', '#', '\0']) {
+            return Err(MqttError::TopicError(format!("Illegal topic name, {:?}", name)));
+        }
+        Ok(Self(name))
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> TopicName {
+        self.0
+    }
+}
+
+///Topic filter of a subscription, with the `$share/{group}/` or `$limit/{n}/` prefix parsed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ValidTopicFilter {
+    filter: TopicFilter,
+    shared_group: Option<SharedGroup>,
+    limit_subs: LimitSubsCount,
+}
+
+impl ValidTopicFilter {
+    ///Filters without a prefix, shared and limit subscriptions are not parsed
+    #[inline]
+    pub fn new<T: Into<TopicFilter>>(filter: T) -> Result<Self> {
+        Self::parse(&filter.into(), false, false)
+    }
+
+    #[inline]
+    pub fn parse(filter: &TopicFilter, shared_subscription: bool, limit_subscription: bool) -> Result<Self> {
+        let (filter, shared_group, limit_subs) =
+            parse_topic_filter(filter, shared_subscription, limit_subscription)?;
+        if filter.len() > MAX_TOPIC_LEN || !is_valid_filter(&filter) {
+            return Err(MqttError::TopicError(format!("Illegal topic filter, {:?}", filter)));
+        }
+        Ok(Self { filter, shared_group, limit_subs })
+    }
+
+    ///The filter without the shared or limit subscription prefix
+    #[inline]
+    pub fn filter(&self) -> &TopicFilter {
+        &self.filter
+    }
+
+    #[inline]
+    pub fn shared_group(&self) -> Option<&SharedGroup> {
+        self.shared_group.as_ref()
+    }
+
+    #[inline]
+    pub fn limit_subs(&self) -> LimitSubsCount {
+        self.limit_subs
+    }
+
+    #[inline]
+    pub fn has_wildcard(&self) -> bool {
+        self.filter.contains(['+', '#'])
+    }
+
+    ///Whether the topic name matches this filter, names starting with `$` are not matched by
+    ///filters starting with a wildcard
+    pub fn matches(&self, name: &ValidTopicName) -> bool {
+        let mut levels = self.filter.split('/');
+        let mut name_levels = name.as_str().split('/');
+        if name.as_str().starts_with('$') && self.filter.starts_with(['+', '#']) {
+            return false;
+        }
+        loop {
+            match (levels.next(), name_levels.next()) {
+                (Some("#"), _) => return true,
+                (Some("+"), Some(_)) => {}
+                (Some(l), Some(n)) if l == n => {}
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> (TopicFilter, Option<SharedGroup>, LimitSubsCount) {
+        (self.filter, self.shared_group, self.limit_subs)
+    }
+}
+
+///Whether a client id or topic is accepted in strict mode, it must not contain U+0000 or other
+///control characters and must be in Unicode Normalization Form C
+#[inline]
+pub fn is_strict_utf8(s: &str) -> bool {
+    !s.chars().any(char::is_control) && unicode_normalization::is_nfc(s)
+}
+
+//'#' only as the last level and '+' only as a whole level
+#[inline]
+fn is_valid_filter(filter: &str) -> bool {
+    if filter.contains('\0') {
+        return false;
+    }
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let valid = match level {
+            "#" => levels.peek().is_none(),
+            "+" => true,
+            l => !l.contains(['+', '#']),
+        };
+        if !valid {
+            return false;
+        }
+    }
+    true
+}
+
+impl std::convert::TryFrom<ByteString> for ValidTopicName {
+    type Error = MqttError;
+
+    #[inline]
+    fn try_from(name: ByteString) -> std::result::Result<Self, Self::Error> {
+        Self::new(name)
+    }
+}
+
+impl std::convert::From<ValidTopicName> for ByteString {
+    #[inline]
+    fn from(name: ValidTopicName) -> Self {
+        name.0
+    }
+}
+
+impl Deref for ValidTopicName {
+    type Target = str;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ValidTopicName {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for ValidTopicName {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Display for ValidTopicFilter {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.shared_group, self.limit_subs) {
+            (Some(group), _) => write!(f, "$share/{}/{}", group, self.filter),
+            (None, Some(limit)) => write!(f, "$limit/{}/{}", limit, self.filter),
+            (None, None) => f.write_str(&self.filter),
+        }
+    }
+}
+
+impl Serialize for ValidTopicFilter {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ValidTopicFilter {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let filter = ByteString::deserialize(deserializer)?;
+        ValidTopicFilter::parse(&filter, true, true).map_err(de::Error::custom)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum SubscriptionOptions {
     V3(SubOptionsV3),
@@ -836,7 +1064,7 @@ impl Subscribe {
         limit_subscription: bool,
     ) -> Result<Self> {
         let (topic_filter, shared_group, limit_subs) =
-            parse_topic_filter(topic_filter, shared_subscription, limit_subscription)?;
+            ValidTopicFilter::parse(topic_filter, shared_subscription, limit_subscription)?.into_inner();
         let opts = (qos, shared_group, limit_subs).into();
         Ok(Subscribe { topic_filter, opts })
     }
@@ -850,7 +1078,7 @@ impl Subscribe {
         sub_id: Option<NonZeroU32>,
     ) -> Result<Self> {
         let (topic_filter, shared_group, limit_subs) =
-            parse_topic_filter(topic_filter, shared_subscription, limit_subscription)?;
+            ValidTopicFilter::parse(topic_filter, shared_subscription, limit_subscription)?.into_inner();
         let opts = (opts, shared_group, limit_subs, sub_id).into();
         Ok(Subscribe { topic_filter, opts })
     }
@@ -1008,7 +1236,7 @@ impl Unsubscribe {
         limit_subscription: bool,
     ) -> Result<Self> {
         let (topic_filter, shared_group, _) =
-            parse_topic_filter(topic_filter, shared_subscription, limit_subscription)?;
+            ValidTopicFilter::parse(topic_filter, shared_subscription, limit_subscription)?.into_inner();
         Ok(Unsubscribe { topic_filter, shared_group })
     }
 
@@ -1152,18 +1380,42 @@ impl Sink {
         }
     }
 
+    ///Close the connection, a DISCONNECT packet with the reason code is sent to the v5 client
+    #[inline]
+    pub(crate) fn close_with_reason(&self, reason_code: DisconnectReasonCode) {
+        match self {
+            Sink::V3(s) => s.close(),
+            Sink::V5(s) => s.close_with_reason(DisconnectV5 { reason_code, ..Default::default() }),
+        }
+    }
+
+    ///Close the connection, the v5 client is asked to use the referenced server if there is one
+    #[inline]
+    pub(crate) fn close_with_server_reference(&self, server_reference: Option<ServerReference>) {
+        match self {
+            Sink::V3(s) => s.close(),
+            Sink::V5(s) => {
+                let reason_code = if server_reference.is_some() {
+                    DisconnectReasonCode::UseAnotherServer
+                } else {
+                    DisconnectReasonCode::ServerShuttingDown
+                };
+                s.close_with_reason(DisconnectV5 { reason_code, server_reference, ..Default::default() })
+            }
+        }
+    }
+
     #[inline]
-    pub(crate) async fn publish(
+    pub(crate) async fn publish_packet(
         &self,
         p: &Publish,
         message_expiry_interval: Option<NonZeroU32>,
         server_topic_aliases: Option<&Rc<ServerTopicAliases>>,
-    ) -> Result<()> {
-        let pkt = match self {
+    ) -> Packet {
+        match self {
             Sink::V3(_) => p.into_v3(),
             Sink::V5(_) => p.into_v5(message_expiry_interval, server_topic_aliases).await,
-        };
-        self.send(pkt)
+        }
     }
 
     #[inline]
@@ -1282,11 +1534,7 @@ impl<'a> std::convert::TryFrom<LastWill<'a>> for Publish {
                 let (topic, user_properties) = if let Some(pos) = lw.topic.find('?') {
                     let topic = lw.topic.clone();
                     let query = lw.topic.as_bytes().slice(pos + 1..lw.topic.len());
-                    let user_props = url::form_urlencoded::parse(query.as_ref())
-                        .into_owned()
-                        .map(|(key, val)| (ByteString::from(key), ByteString::from(val)))
-                        .collect::<UserProperties>();
-                    (topic, user_props)
+                    (topic, translate::query_user_properties(query.as_ref()))
                 } else {
                     let topic = lw.topic.clone();
                     (topic, UserProperties::default())
@@ -1327,62 +1575,28 @@ impl<'a> std::convert::TryFrom<LastWill<'a>> for Publish {
 impl std::convert::From<&v3::Publish> for Publish {
     #[inline]
     fn from(p: &v3::Publish) -> Self {
-        let query = p.query();
-        let p_props = if !query.is_empty() {
-            let user_props = url::form_urlencoded::parse(query.as_bytes())
-                .into_owned()
-                .map(|(key, val)| (ByteString::from(key), ByteString::from(val)))
-                .collect::<UserProperties>();
-            PublishProperties::from(user_props)
-        } else {
-            PublishProperties::default()
-        };
-
-        Self {
-            dup: p.dup(),
-            retain: p.retain(),
-            qos: p.qos(),
-            topic: TopicName::from(p.topic().path()),
-            packet_id: p.id(),
-            payload: p.take_payload(),
-
-            properties: p_props,
-            delay_interval: None,
-            create_time: chrono::Local::now().timestamp_millis(),
-        }
+        translate::from_v3(p)
     }
 }
 
 impl std::convert::From<&v5::Publish> for Publish {
     #[inline]
     fn from(p: &v5::Publish) -> Self {
-        Self {
-            dup: p.dup(),
-            retain: p.retain(),
-            qos: p.qos(),
-            topic: TopicName::from(p.topic().path()),
-            packet_id: p.id(),
-            payload: p.take_payload(),
-
-            properties: PublishProperties::from(p.packet().properties.clone()),
-            delay_interval: None,
-            create_time: chrono::Local::now().timestamp_millis(),
-        }
+        translate::from_v5(p)
     }
 }
 
 impl Publish {
+    ///Builds a message to be injected by plugins, bridges or the API, it is converted to the protocol
+    ///of each subscriber on delivery
+    #[inline]
+    pub fn builder() -> PublishBuilder {
+        PublishBuilder::default()
+    }
+
     #[inline]
     pub fn into_v3(&self) -> Packet {
-        let p = v3::codec::Publish {
-            dup: self.dup,
-            retain: self.retain,
-            qos: self.qos,
-            topic: self.topic.clone(),
-            packet_id: self.packet_id,
-            payload: self.payload.clone(),
-        };
-        Packet::V3(v3::codec::Packet::Publish(p))
+        Packet::V3(v3::codec::Packet::Publish(translate::to_v3(self)))
     }
 
     #[inline]
@@ -1399,17 +1613,7 @@ impl Publish {
             }
         };
         log::debug!("topic: {:?}, alias: {:?}", topic, alias);
-        let mut p = v5::codec::Publish {
-            dup: self.dup,
-            retain: self.retain,
-            qos: self.qos,
-            topic: topic.unwrap_or_default(),
-            packet_id: self.packet_id,
-            payload: self.payload.clone(),
-            properties: self.properties.clone().into(),
-        };
-        p.properties.message_expiry_interval = message_expiry_interval;
-        p.properties.topic_alias = alias;
+        let p = translate::to_v5(self, topic.unwrap_or_default(), alias, message_expiry_interval);
         log::debug!("p.properties: {:?}", p.properties);
         Packet::V5(v5::codec::Packet::Publish(p))
     }
@@ -1434,6 +1638,83 @@ impl Publish {
         &mut self.topic
     }
 
+    #[inline]
+    pub fn set_topic(&mut self, topic: TopicName) {
+        self.topic = topic
+    }
+
+    #[inline]
+    pub fn set_payload(&mut self, payload: Bytes) {
+        self.payload = payload
+    }
+
+    #[inline]
+    pub fn set_qos(&mut self, qos: QoS) {
+        self.qos = qos
+    }
+
+    #[inline]
+    pub fn add_user_property(&mut self, key: ByteString, val: ByteString) {
+        self.properties.user_properties.push((key, val))
+    }
+
+    ///User properties, for MQTT 3.1.1 these are parsed from the query string of the topic
+    #[inline]
+    pub fn user_properties(&self) -> &UserProperties {
+        &self.properties.user_properties
+    }
+
+    #[inline]
+    pub fn user_properties_mut(&mut self) -> &mut UserProperties {
+        &mut self.properties.user_properties
+    }
+
+    #[inline]
+    pub fn set_user_properties(&mut self, user_properties: UserProperties) {
+        self.properties.user_properties = user_properties
+    }
+
+    ///The properties below are only carried by MQTT 5.0, they are None for MQTT 3.1.1 messages
+    #[inline]
+    pub fn content_type(&self) -> Option<&ByteString> {
+        self.properties.content_type.as_ref()
+    }
+
+    #[inline]
+    pub fn set_content_type(&mut self, content_type: Option<ByteString>) {
+        self.properties.content_type = content_type
+    }
+
+    #[inline]
+    pub fn response_topic(&self) -> Option<&TopicName> {
+        self.properties.response_topic.as_ref()
+    }
+
+    #[inline]
+    pub fn set_response_topic(&mut self, response_topic: Option<TopicName>) {
+        self.properties.response_topic = response_topic
+    }
+
+    #[inline]
+    pub fn correlation_data(&self) -> Option<&Bytes> {
+        self.properties.correlation_data.as_ref()
+    }
+
+    #[inline]
+    pub fn set_correlation_data(&mut self, correlation_data: Option<Bytes>) {
+        self.properties.correlation_data = correlation_data
+    }
+
+    #[inline]
+    pub fn message_expiry_interval(&self) -> Option<NonZeroU32> {
+        self.properties.message_expiry_interval
+    }
+
+    #[inline]
+    pub fn set_message_expiry_interval(&mut self, interval: Option<NonZeroU32>) {
+        self.properties.message_expiry_interval = interval
+    }
+
     #[inline]
     pub fn dup(&self) -> bool {
         self.dup
@@ -1480,6 +1761,116 @@ impl Publish {
     }
 }
 
+///Builder of `Publish`, QoS 0, not retained and with an empty topic and payload by default
+#[derive(Debug)]
+pub struct PublishBuilder {
+    retain: bool,
+    qos: QoS,
+    topic: TopicName,
+    payload: Bytes,
+    properties: PublishProperties,
+    delay_interval: Option<u32>,
+}
+
+impl Default for PublishBuilder {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            retain: false,
+            qos: QoS::AtMostOnce,
+            topic: TopicName::default(),
+            payload: Bytes::new(),
+            properties: PublishProperties::default(),
+            delay_interval: None,
+        }
+    }
+}
+
+impl PublishBuilder {
+    #[inline]
+    pub fn topic<T: Into<TopicName>>(mut self, topic: T) -> Self {
+        self.topic = topic.into();
+        self
+    }
+
+    #[inline]
+    pub fn qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    #[inline]
+    pub fn retain(mut self, retain: bool) -> Self {
+        self.retain = retain;
+        self
+    }
+
+    #[inline]
+    pub fn payload<P: Into<Bytes>>(mut self, payload: P) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    #[inline]
+    pub fn user_property<K: Into<ByteString>, V: Into<ByteString>>(mut self, key: K, val: V) -> Self {
+        self.properties.user_properties.push((key.into(), val.into()));
+        self
+    }
+
+    ///Replaces all properties, including the user properties added before
+    #[inline]
+    pub fn properties(mut self, properties: PublishProperties) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    #[inline]
+    pub fn content_type<C: Into<ByteString>>(mut self, content_type: C) -> Self {
+        self.properties.content_type = Some(content_type.into());
+        self
+    }
+
+    #[inline]
+    pub fn response_topic<T: Into<TopicName>>(mut self, response_topic: T) -> Self {
+        self.properties.response_topic = Some(response_topic.into());
+        self
+    }
+
+    #[inline]
+    pub fn correlation_data<D: Into<Bytes>>(mut self, correlation_data: D) -> Self {
+        self.properties.correlation_data = Some(correlation_data.into());
+        self
+    }
+
+    #[inline]
+    pub fn message_expiry_interval(mut self, interval: Option<NonZeroU32>) -> Self {
+        self.properties.message_expiry_interval = interval;
+        self
+    }
+
+    ///Delay publish interval, unit: seconds
+    #[inline]
+    pub fn delay_interval(mut self, interval: Option<u32>) -> Self {
+        self.delay_interval = interval;
+        self
+    }
+
+    #[inline]
+    pub fn build(self) -> Publish {
+        Publish {
+            dup: false,
+            retain: self.retain,
+            qos: self.qos,
+            topic: self.topic,
+            packet_id: None,
+            payload: self.payload,
+            properties: self.properties,
+            delay_interval: self.delay_interval,
+            create_time: timestamp_millis(),
+        }
+    }
+}
+
 #[derive(GetSize, Debug, Clone, Copy, Deserialize, Serialize)]
 pub enum FromType {
     Custom,
@@ -1584,6 +1975,91 @@ impl std::fmt::Debug for From {
 //pub type From = Id;
 pub type To = Id;
 
+///IPv4 clients of a dual-stack listener have IPv4-mapped IPv6 addresses (::ffff:a.b.c.d), they are
+///converted back to IPv4 addresses so that both kinds of listeners report the same address
+#[inline]
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+#[inline]
+pub fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(canonical_ip(addr.ip()), addr.port())
+}
+
+///An IP address or a CIDR block of either family, such as "10.0.0.0/8", "2001:db8::/32" or "::1".
+///
+///Addresses are compared in their canonical form, an IPv4 block also matches the IPv4-mapped
+///IPv6 addresses of the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    #[inline]
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self> {
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_len {
+            return Err(MqttError::from(format!("invalid prefix length, {}/{}", addr, prefix_len)));
+        }
+        //::ffff:a.b.c.d/96+n is the IPv4 block a.b.c.d/n
+        let (addr, prefix_len) = match addr {
+            IpAddr::V6(v6) if prefix_len >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => (IpAddr::V4(v4), prefix_len - 96),
+                None => (addr, prefix_len),
+            },
+            _ => (addr, prefix_len),
+        };
+        Ok(Self { addr, prefix_len })
+    }
+
+    #[inline]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonical_ip(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for IpCidr {
+    type Err = MqttError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid =
+            |e: &dyn Display| MqttError::from(format!("invalid IP address or CIDR block {:?}, {}", s, e));
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => {
+                let addr = addr.trim().parse::<IpAddr>().map_err(|e| invalid(&e))?;
+                let prefix_len = prefix_len.trim().parse::<u8>().map_err(|e| invalid(&e))?;
+                Self::new(addr, prefix_len)
+            }
+            None => {
+                let addr = s.trim().parse::<IpAddr>().map_err(|e| invalid(&e))?;
+                Self::new(addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        }
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
 #[derive(Clone)]
 pub struct Id(Arc<_Id>);
 
@@ -1604,8 +2080,8 @@ impl Id {
     ) -> Self {
         Self(Arc::new(_Id {
             node_id,
-            local_addr,
-            remote_addr,
+            local_addr: local_addr.map(canonical_addr),
+            remote_addr: remote_addr.map(canonical_addr),
             client_id,
             username,
             create_time: chrono::Local::now().timestamp_millis(),
@@ -1665,6 +2141,27 @@ impl Id {
         self.node_id
     }
 
+    ///Listener address the client connected to, None for messages not received from a connection
+    #[inline]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    #[inline]
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    #[inline]
+    pub fn remote_ip(&self) -> Option<IpAddr> {
+        self.remote_addr.map(|addr| addr.ip())
+    }
+
+    #[inline]
+    pub fn remote_port(&self) -> Option<u16> {
+        self.remote_addr.map(|addr| addr.port())
+    }
+
     #[inline]
     pub fn username(&self) -> UserName {
         self.username.clone().unwrap_or_else(|| UserName::from_static(UNDEFINED))
@@ -1847,15 +2344,70 @@ impl StoredMessage {
     }
 }
 
+///Reply of a kicked session, the state of the session when it was kicked
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KickAck {
+    ///The session ends with its connection, MQTT 3.1.1 Clean Session or MQTT 5.0 Session Expiry Interval 0,
+    ///its state is not taken over
+    pub clean_session: bool,
+    ///Messages in the message queue
+    pub queue_len: usize,
+    ///Messages waiting to be acknowledged by the client
+    pub inflight_len: usize,
+}
+
+///The Session Present flag of the CONNACK and how it was decided
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionPresent {
+    ///The flag sent in the CONNACK
+    pub present: bool,
+    ///Clean Start (MQTT 5.0) or Clean Session (MQTT 3.1.1) of the CONNECT packet
+    pub clean_start: bool,
+    ///The state of a previous session is taken over, the session may have been connected to another node
+    ///or restored from the storage
+    pub taken_over: bool,
+    ///The flag was changed by a ClientConnack handler
+    pub overridden: bool,
+}
+
+impl SessionPresent {
+    ///Session Present is set when the client does not request a clean start and the state of a previous
+    ///session is taken over
+    #[inline]
+    pub fn new(clean_start: bool, taken_over: bool) -> Self {
+        Self { present: !clean_start && taken_over, clean_start, taken_over, overridden: false }
+    }
+
+    ///The flag can only be set when the state of a previous session is taken over without a clean
+    ///start [MQTT-3.2.2-2], the session itself is created with the decided flag
+    #[inline]
+    pub fn override_allowed(&self, present: bool) -> bool {
+        !present || (!self.clean_start && self.taken_over)
+    }
+
+    ///An override that is not allowed is ignored
+    #[inline]
+    pub fn with_override(self, present: bool) -> Self {
+        if present == self.present || !self.override_allowed(present) {
+            self
+        } else {
+            Self { present, overridden: true, ..self }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Message {
     Forward(From, Publish),
-    Kick(oneshot::Sender<()>, Id, CleanStart, IsAdmin),
+    Kick(oneshot::Sender<KickAck>, Id, CleanStart, IsAdmin),
     Disconnect(Disconnect),
     Closed(Reason),
     Keepalive(IsPing),
     Subscribe(Subscribe, oneshot::Sender<Result<SubscribeReturn>>),
     Unsubscribe(Unsubscribe, oneshot::Sender<Result<()>>),
+    TriggerLastWill(oneshot::Sender<Result<()>>),
+    Shutdown,
+    Evacuate(Option<ServerReference>),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -1865,6 +2417,25 @@ pub struct SessionStatus {
     pub handshaking: bool,
 }
 
+///What to do with a client that violated the protocol, returned by the ClientProtocolViolation hook.
+///The connection is closed in any case, a ban only applies to this node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolViolationAction {
+    #[default]
+    Disconnect,
+    ///Refuse new connections with the client id for the duration
+    BanClientId(Duration),
+    ///Refuse new connections from the remote IP address for the duration
+    BanAddr(Duration),
+}
+
+///Current totals of all nodes, passed to the ClientQuotaCheck hook
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct ClusterTotals {
+    pub connections: usize,
+    pub sessions: usize,
+}
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct SubsSearchParams {
     #[serde(default)]
@@ -2042,6 +2613,11 @@ impl SessionSubs {
         self.subs.read().await.is_empty()
     }
 
+    #[inline]
+    pub async fn wildcard_len(&self) -> usize {
+        self.subs.read().await.iter().filter(|(tf, _)| tf.contains(['+', '#'])).count()
+    }
+
     #[inline]
     pub async fn to_topic_filters(&self) -> TopicFilters {
         self.subs.read().await.iter().map(|(key, _)| key.clone()).collect()
@@ -2193,6 +2769,184 @@ impl ExtraAttrs {
     }
 }
 
+///Value of a session attribute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttrValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl std::convert::From<bool> for AttrValue {
+    #[inline]
+    fn from(v: bool) -> Self {
+        AttrValue::Bool(v)
+    }
+}
+
+impl std::convert::From<i64> for AttrValue {
+    #[inline]
+    fn from(v: i64) -> Self {
+        AttrValue::Int(v)
+    }
+}
+
+impl std::convert::From<String> for AttrValue {
+    #[inline]
+    fn from(v: String) -> Self {
+        AttrValue::String(v)
+    }
+}
+
+impl std::convert::From<&str> for AttrValue {
+    #[inline]
+    fn from(v: &str) -> Self {
+        AttrValue::String(v.into())
+    }
+}
+
+impl std::convert::From<Vec<u8>> for AttrValue {
+    #[inline]
+    fn from(v: Vec<u8>) -> Self {
+        AttrValue::Bytes(v)
+    }
+}
+
+///Per-session attributes, such as a tenant id or device model, usually set by an authentication
+///plugin with `AuthResult::Allow` and read by the hooks that receive the session.
+///
+///Unlike `ExtraAttrs`, the values are typed and serializable, so they are also shown by the client
+///info APIs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionAttrs(HashMap<String, AttrValue>);
+
+///Session attribute that records how the client was let in, it is set by the broker, e.g. an ACL plugin
+///can deny clients with `AUTH_BYPASS` some topics
+pub const ATTR_AUTH: &str = "auth";
+///Accepted by an authentication plugin
+pub const AUTH_AUTHENTICATED: &str = "authenticated";
+///No credentials or no plugin decided, accepted because the listener allows anonymous clients
+pub const AUTH_ANONYMOUS: &str = "anonymous";
+///Connected from an `auth_bypass` address of the listener, the authentication hooks were not run
+pub const AUTH_BYPASS: &str = "bypass";
+
+///Session attributes an authentication plugin can return to override the `username_connections` limits of
+///the listener for the client, `max` and `max_cluster` are integers and `policy` is "reject" or "kick_oldest"
+pub const ATTR_USERNAME_CONNECTIONS_MAX: &str = "username_connections.max";
+pub const ATTR_USERNAME_CONNECTIONS_MAX_CLUSTER: &str = "username_connections.max_cluster";
+pub const ATTR_USERNAME_CONNECTIONS_POLICY: &str = "username_connections.policy";
+
+///Session attributes an authentication plugin can return to override the limits of the listener for the
+///client, e.g. to serve tiered service levels from one broker. `max_inflight`, `max_mqueue_len`,
+///`max_qos_allowed` are integers, `mqueue_rate_limit` is "<burst>,<interval>" like "100,10s",
+///`session_expiry_interval` and `max_session_expiry_interval` are durations like "2h"
+pub const ATTR_LIMITS_MAX_INFLIGHT: &str = "limits.max_inflight";
+pub const ATTR_LIMITS_MAX_MQUEUE_LEN: &str = "limits.max_mqueue_len";
+pub const ATTR_LIMITS_MQUEUE_RATE_LIMIT: &str = "limits.mqueue_rate_limit";
+pub const ATTR_LIMITS_SESSION_EXPIRY_INTERVAL: &str = "limits.session_expiry_interval";
+pub const ATTR_LIMITS_MAX_SESSION_EXPIRY_INTERVAL: &str = "limits.max_session_expiry_interval";
+pub const ATTR_LIMITS_MAX_QOS_ALLOWED: &str = "limits.max_qos_allowed";
+
+impl SessionAttrs {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn with<K: Into<String>, V: Into<AttrValue>>(mut self, key: K, value: V) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    #[inline]
+    pub fn insert<K: Into<String>, V: Into<AttrValue>>(&mut self, key: K, value: V) -> Option<AttrValue> {
+        self.0.insert(key.into(), value.into())
+    }
+
+    #[inline]
+    pub fn remove(&mut self, key: &str) -> Option<AttrValue> {
+        self.0.remove(key)
+    }
+
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&AttrValue> {
+        self.0.get(key)
+    }
+
+    #[inline]
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.0.get(key) {
+            Some(AttrValue::Bool(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.0.get(key) {
+            Some(AttrValue::Int(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.0.get(key) {
+            Some(AttrValue::String(v)) => Some(v.as_str()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        match self.0.get(key) {
+            Some(AttrValue::Bytes(v)) => Some(v.as_slice()),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn extend(&mut self, other: SessionAttrs) {
+        self.0.extend(other.0)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AttrValue)> {
+        self.0.iter()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///Bytes values are base64 encoded
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Value {
+        let attrs = self
+            .0
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    AttrValue::Bool(v) => json!(v),
+                    AttrValue::Int(v) => json!(v),
+                    AttrValue::String(v) => json!(v),
+                    AttrValue::Bytes(v) => json!(BASE64_STANDARD.encode(v)),
+                };
+                (k.clone(), v)
+            })
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::Value::Object(attrs)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TimedValue<V>(V, Option<Instant>);
 
@@ -2280,6 +3034,7 @@ pub enum Reason {
     ConnectKeepaliveTimeout,
     ConnectKicked(IsAdmin),
     SessionExpiration,
+    SessionTakenOver,
     SubscribeFailed(Option<ByteString>),
     UnsubscribeFailed(Option<ByteString>),
     SubscribeRefused,
@@ -2287,12 +3042,14 @@ pub enum Reason {
     DelayedPublishRefused,
     MessageExpiration,
     MessageQueueFull,
+    MessageDeliverFailed(ByteString),
     PublishFailed(ByteString),
     ProtocolError(ByteString),
     Error(ByteString),
     Reasons(Vec<Reason>),
     #[default]
     Unknown,
+    ServerShuttingDown,
 }
 
 impl Reason {
@@ -2375,6 +3132,9 @@ impl Display for Reason {
             Reason::SessionExpiration => {
                 "SessionExpiration" //session expiration
             }
+            Reason::SessionTakenOver => {
+                "SessionTakenOver" //session discarded by a clean start connection
+            }
             Reason::SubscribeFailed(r) => {
                 //subscribe failed
                 match r {
@@ -2404,6 +3164,7 @@ impl Display for Reason {
             Reason::MessageQueueFull => {
                 "MessageQueueFull" //message deliver queue is full
             }
+            Reason::MessageDeliverFailed(r) => return write!(f, "MessageDeliverFailed({})", r),
             Reason::PublishFailed(r) => return write!(f, "PublishFailed({})", r),
             Reason::Error(r) => r,
             Reason::ProtocolError(r) => return write!(f, "ProtocolError({})", r),
@@ -2415,6 +3176,9 @@ impl Display for Reason {
             Reason::Unknown => {
                 "Unknown" //unknown
             }
+            Reason::ServerShuttingDown => {
+                "ServerShuttingDown" //disconnected by the broker on shutdown
+            }
         };
         write!(f, "{}", r)
     }
@@ -6,10 +6,13 @@ use std::sync::Arc;
 use rust_box::task_exec_queue::LocalSpawnExt;
 use uuid::Uuid;
 
+use crate::broker::banned;
 use crate::broker::executor::get_handshake_exec;
+use crate::broker::hook::Type;
+use crate::broker::session::username_connections_check;
 use crate::broker::{inflight::MomentStatus, types::*};
 use crate::runtime::Runtime;
-use crate::settings::listener::Listener;
+use crate::settings::listener::{ClientIdCheck, Listener, Mqtt31Action};
 use crate::{MqttError, Result, Session, SessionState};
 
 #[inline]
@@ -23,8 +26,9 @@ async fn refused_ack<Io>(
         .extends
         .hook_mgr()
         .await
-        .client_connack(connect_info, ConnectAckReason::V3(ack_code))
-        .await;
+        .client_connack(connect_info, ConnectAckReason::V3(ack_code), SessionPresent::default())
+        .await
+        .0;
     log::warn!(
         "{:?} Connection Refused, handshake, ack_code: {:?}, new_ack_code: {:?}, reason: {}",
         connect_info.id(),
@@ -50,8 +54,29 @@ pub async fn handshake<Io: 'static>(
         listen_cfg
     );
 
+    let level = handshake.packet().protocol.level();
+    let mqtt31 = level == MQTT_LEVEL_31;
+    if !listen_cfg.protocol_version_allowed(level)
+        || (mqtt31 && listen_cfg.mqtt31.action == Mqtt31Action::Reject)
+    {
+        log::info!(
+            "{:?} Connection Refused, handshake error, reason: protocol level {} is not accepted",
+            Id::new(
+                Runtime::instance().node.id(),
+                Some(local_addr),
+                Some(remote_addr),
+                handshake.packet().client_id.clone(),
+                handshake.packet().username.clone(),
+            ),
+            level
+        );
+        return Ok(
+            ConnectAckReason::V3(ConnectAckReasonV3::UnacceptableProtocolVersion).v3_error_ack(handshake)
+        );
+    }
+
     if handshake.packet().client_id.is_empty() {
-        if handshake.packet().clean_session {
+        if handshake.packet().clean_session && !mqtt31 {
             handshake.packet_mut().client_id =
                 ClientId::from(Uuid::new_v4().as_simple().encode_lower(&mut Uuid::encode_buffer()).to_owned())
         } else {
@@ -67,6 +92,39 @@ pub async fn handshake<Io: 'static>(
             );
             return Ok(ConnectAckReason::V3(ConnectAckReasonV3::IdentifierRejected).v3_error_ack(handshake));
         }
+    } else {
+        let packet = handshake.packet();
+        let max_clientid_len = if mqtt31 {
+            listen_cfg.mqtt31.max_clientid_len(listen_cfg.max_clientid_len)
+        } else {
+            listen_cfg.max_clientid_len
+        };
+        match listen_cfg.clientid_policy.check(
+            &packet.client_id,
+            packet.username.as_deref(),
+            max_clientid_len,
+        ) {
+            ClientIdCheck::Accepted => {}
+            ClientIdCheck::Rewritten(client_id) => {
+                handshake.packet_mut().client_id = ClientId::from(client_id)
+            }
+            ClientIdCheck::Rejected(reason) => {
+                log::info!(
+                    "{:?} Connection Refused, handshake error, reason: {}",
+                    Id::new(
+                        Runtime::instance().node.id(),
+                        Some(local_addr),
+                        Some(remote_addr),
+                        packet.client_id.clone(),
+                        packet.username.clone(),
+                    ),
+                    reason
+                );
+                return Ok(
+                    ConnectAckReason::V3(ConnectAckReasonV3::IdentifierRejected).v3_error_ack(handshake)
+                );
+            }
+        }
     }
 
     let id = Id::new(
@@ -106,23 +164,51 @@ async fn _handshake<Io: 'static>(
     //hook, client connect
     let _ = Runtime::instance().extends.hook_mgr().await.client_connect(&connect_info).await;
 
-    if listen_cfg.max_clientid_len > 0 && id.client_id.len() > listen_cfg.max_clientid_len {
+    if banned::is_banned(&id) {
+        return Ok(refused_ack(
+            handshake,
+            &connect_info,
+            ConnectAckReasonV3::NotAuthorized,
+            "the client is banned".into(),
+        )
+        .await);
+    }
+
+    if Runtime::instance().node.maintenance().is_some() {
+        return Ok(refused_ack(
+            handshake,
+            &connect_info,
+            ConnectAckReasonV3::ServiceUnavailable,
+            "the node is in maintenance".into(),
+        )
+        .await);
+    }
+
+    if listen_cfg.strict_mode && !is_strict_utf8(&id.client_id) {
         return Ok(refused_ack(
             handshake,
             &connect_info,
             ConnectAckReasonV3::IdentifierRejected,
-            "client_id is too long".into(),
+            "client_id contains control characters or is not normalized".into(),
         )
         .await);
     }
 
-    //hook, client authenticate
-    let (ack, superuser) = Runtime::instance()
-        .extends
-        .hook_mgr()
-        .await
-        .client_authenticate(&connect_info, listen_cfg.allow_anonymous)
-        .await;
+    //hook, client authenticate, skipped for the auth_bypass addresses of the listener
+    let (ack, superuser, auth_attrs) = if listen_cfg.auth_bypassed(id.remote_addr) {
+        (
+            ConnectAckReason::V3(ConnectAckReasonV3::ConnectionAccepted),
+            false,
+            SessionAttrs::default().with(ATTR_AUTH, AUTH_BYPASS),
+        )
+    } else {
+        Runtime::instance()
+            .extends
+            .hook_mgr()
+            .await
+            .client_authenticate(&connect_info, listen_cfg.allow_anonymous)
+            .await
+    };
     if !ack.success() {
         if let ConnectAckReason::V3(ack) = ack {
             return Ok(refused_ack(handshake, &connect_info, ack, "Authentication failed".into()).await);
@@ -131,10 +217,41 @@ async fn _handshake<Io: 'static>(
         }
     }
 
+    let attrs = {
+        let enricher = Runtime::instance().extends.client_enricher().await;
+        if enricher.enable() {
+            let mut attrs = enricher.enrich(&connect_info).await;
+            attrs.extend(auth_attrs);
+            attrs
+        } else {
+            auth_attrs
+        }
+    };
+
+    //per-client limits returned by the authentication plugins
+    let listen_cfg = listen_cfg.with_attrs(&attrs);
+
+    let username_connections = listen_cfg.username_connections.with_attrs(&attrs);
+    if let Err(reason) = username_connections_check(&id, username_connections).await {
+        return Ok(refused_ack(handshake, &connect_info, ConnectAckReasonV3::NotAuthorized, reason).await);
+    }
+
+    let quota_exceeded = Runtime::instance().extends.hook_mgr().await.client_quota_check(&connect_info).await;
+    if let Some(reason) = quota_exceeded {
+        return Ok(
+            refused_ack(handshake, &connect_info, ConnectAckReasonV3::ServiceUnavailable, reason).await
+        );
+    }
+
     let sink = handshake.sink();
     let packet = handshake.packet_mut();
 
-    let mut entry = match { Runtime::instance().extends.shared().await.entry(id.clone()) }.try_lock().await {
+    //connects of the same client id are serialized, the previous one finishes its takeover first
+    let connect_lock_timeout = Runtime::instance().settings.latest_node().connect_lock_timeout;
+    let mut entry = match { Runtime::instance().extends.shared().await.entry(id.clone()) }
+        .lock(connect_lock_timeout)
+        .await
+    {
         Err(e) => {
             return Ok(refused_ack(
                 handshake,
@@ -148,20 +265,19 @@ async fn _handshake<Io: 'static>(
     };
 
     // Kick out the current session, if it exists
-    let (session_present, offline_info) =
-        match entry.kick(packet.clean_session, packet.clean_session, false).await {
-            Err(e) => {
-                return Ok(refused_ack(
-                    handshake,
-                    &connect_info,
-                    ConnectAckReasonV3::ServiceUnavailable,
-                    format!("{}", e),
-                )
-                .await);
-            }
-            Ok(Some(offline_info)) => (!packet.clean_session, Some(offline_info)),
-            Ok(None) => (false, None),
-        };
+    let offline_info = match entry.kick(packet.clean_session, packet.clean_session, false).await {
+        Err(e) => {
+            return Ok(refused_ack(
+                handshake,
+                &connect_info,
+                ConnectAckReasonV3::ServiceUnavailable,
+                format!("{}", e),
+            )
+            .await);
+        }
+        Ok(offline_info) => offline_info,
+    };
+    let session_present = SessionPresent::new(packet.clean_session, offline_info.is_some());
 
     let connected_at = chrono::Local::now().timestamp_millis();
 
@@ -185,8 +301,9 @@ async fn _handshake<Io: 'static>(
         max_inflight,
         created_at,
         connect_info.clone(),
-        session_present,
+        session_present.present,
         superuser,
+        attrs,
         true,
         connected_at,
         SessionSubs::new(),
@@ -222,9 +339,29 @@ async fn _handshake<Io: 'static>(
 
     let hook = Runtime::instance().extends.hook_mgr().await.hook(&session);
 
-    if offline_info.is_none() {
-        //hook, session created
-        hook.session_created().await;
+    //hook, message_publish_check_acl, the will must be publishable by the client
+    if let Ok(Some(lw)) = session.last_will_publish().await {
+        if let PublishAclResult::Rejected(_) = hook.message_publish_check_acl(&lw).await {
+            return Ok(refused_ack(
+                handshake,
+                connect_info.as_ref(),
+                ConnectAckReasonV3::NotAuthorized,
+                "Last will topic not authorized".into(),
+            )
+            .await);
+        }
+    }
+
+    match offline_info.as_ref() {
+        Some(o) if session_present.present => {
+            //hook, session resumed
+            hook.session_resumed(o.subscriptions.len(), o.inflight_messages.len() + o.offline_messages.len())
+                .await;
+        }
+        _ => {
+            //hook, session created
+            hook.session_created(packet.clean_session).await;
+        }
     }
 
     let (state, tx) = SessionState::new(session, Sink::V3(sink), hook, 0, 0).start(keep_alive).await?;
@@ -238,12 +375,16 @@ async fn _handshake<Io: 'static>(
         .await);
     }
 
-    //hook, client connack
-    let _ = Runtime::instance()
+    //hook, client connack, handlers can override the Session Present flag of the CONNACK
+    let (_, session_present) = Runtime::instance()
         .extends
         .hook_mgr()
         .await
-        .client_connack(connect_info.as_ref(), ConnectAckReason::V3(ConnectAckReasonV3::ConnectionAccepted))
+        .client_connack(
+            connect_info.as_ref(),
+            ConnectAckReason::V3(ConnectAckReasonV3::ConnectionAccepted),
+            session_present,
+        )
         .await;
 
     //hook, client connected
@@ -268,7 +409,7 @@ async fn _handshake<Io: 'static>(
         }
     }
 
-    Ok(handshake.ack(state, session_present).idle_timeout(keep_alive))
+    Ok(handshake.ack(state, session_present.present).idle_timeout(keep_alive))
 }
 
 async fn subscribes(
@@ -344,10 +485,15 @@ pub async fn control_message(
         }
         v3::ControlMessage::Ping(ping) => {
             let _ = state.send(Message::Keepalive(true));
+            //hook, packet_received, packet_sent
+            state.hook.packet_received(&Packet::V3(PacketV3::PingRequest)).await;
+            state.hook.packet_sent(&Packet::V3(PacketV3::PingResponse)).await;
             ping.ack()
         }
         v3::ControlMessage::Disconnect(disc) => {
             //let _ = state.send(Message::Keepalive(false));
+            //hook, packet_received
+            state.hook.packet_received(&Packet::V3(PacketV3::Disconnect)).await;
             state.send(Message::Disconnect(Disconnect::V3))?;
             disc.ack()
         }
@@ -370,6 +516,10 @@ pub async fn publish(state: v3::Session<SessionState>, pub_msg: v3::PublishMessa
 
     match pub_msg {
         v3::PublishMessage::Publish(publish) => {
+            //hook, packet_received
+            if state.hook.is_hooked(Type::PacketReceived) {
+                state.hook.packet_received(&Packet::V3(PacketV3::Publish(publish.packet().clone()))).await;
+            }
             let publish_fut = async move {
                 if let Err(e) = state.publish_v3(&publish).await {
                     log::warn!(
@@ -393,15 +543,27 @@ pub async fn publish(state: v3::Session<SessionState>, pub_msg: v3::PublishMessa
             }
         }
         v3::PublishMessage::PublishAck(packet_id) => {
-            if let Some(iflt_msg) = state.inflight_win().write().await.remove(&packet_id.get()) {
+            state.hook.packet_received(&Packet::V3(PacketV3::PublishAck { packet_id })).await;
+            let iflt_msg = state.inflight_win().write().await.remove(&packet_id.get());
+            if let Some(iflt_msg) = iflt_msg {
                 //hook, message_ack
                 state.hook.message_acked(iflt_msg.from, &iflt_msg.publish).await;
+                state.ordered_release(&iflt_msg.publish.topic).await;
             }
         }
         v3::PublishMessage::PublishReceived(packet_id) => {
-            state.inflight_win().write().await.update_status(&packet_id.get(), MomentStatus::UnComplete);
+            state.hook.packet_received(&Packet::V3(PacketV3::PublishReceived { packet_id })).await;
+            let topic = {
+                let mut inflight_win = state.inflight_win().write().await;
+                inflight_win.update_status(&packet_id.get(), MomentStatus::UnComplete);
+                inflight_win.get(packet_id.get()).map(|m| m.publish.topic.clone())
+            };
+            if let Some(topic) = topic {
+                state.ordered_release(&topic).await;
+            }
         }
         v3::PublishMessage::PublishComplete(packet_id) => {
+            state.hook.packet_received(&Packet::V3(PacketV3::PublishComplete { packet_id })).await;
             if let Some(iflt_msg) = state.inflight_win().write().await.remove(&packet_id.get()) {
                 //hook, message_ack
                 state.hook.message_acked(iflt_msg.from, &iflt_msg.publish).await;
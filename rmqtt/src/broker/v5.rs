@@ -3,38 +3,53 @@ use std::convert::From as _f;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use ntex_mqtt::v5::codec::{Auth, PublishAckReason};
+use ntex_mqtt::v5::codec::{Auth, PublishAckReason, UnsubscribeAckReason};
 use ntex_mqtt::v5::PublishAck;
 use ntex_mqtt::v5::PublishResult;
 use rust_box::task_exec_queue::LocalSpawnExt;
 use uuid::Uuid;
 
+use crate::broker::banned;
 use crate::broker::executor::get_handshake_exec;
+use crate::broker::hook::Type;
+use crate::broker::session::username_connections_check;
 use crate::broker::{inflight::MomentStatus, types::*};
-use crate::settings::listener::Listener;
+use crate::settings::listener::{ClientIdCheck, Listener};
 use crate::{MqttError, Result, Runtime, Session, SessionState};
 
+///The CONNACK carries `reason` as the Reason String when the listener enables problem information and
+///the client requests it, `error` is the internal cause, it is only logged.
 #[inline]
 async fn refused_ack<Io>(
     handshake: v5::Handshake<Io>,
     connect_info: &ConnectInfo,
+    problem_info: bool,
     ack_code: ConnectAckReasonV5,
-    reason: String,
+    reason: &'static str,
+    error: Option<String>,
 ) -> v5::HandshakeAck<Io, SessionState> {
     let new_ack_code = Runtime::instance()
         .extends
         .hook_mgr()
         .await
-        .client_connack(connect_info, ConnectAckReason::V5(ack_code))
-        .await;
+        .client_connack(connect_info, ConnectAckReason::V5(ack_code), SessionPresent::default())
+        .await
+        .0;
     log::warn!(
-        "{:?} Connection Refused, handshake, ack_code: {:?}, new_ack_code: {:?}, reason: {}",
+        "{:?} Connection Refused, handshake, ack_code: {:?}, new_ack_code: {:?}, reason: {}, error: {:?}",
         connect_info.id(),
         ack_code,
         new_ack_code,
         reason,
+        error,
     );
-    new_ack_code.v5_error_ack(handshake)
+    if problem_info {
+        new_ack_code
+            .v5_error_ack(handshake)
+            .with(|ack: &mut v5::codec::ConnectAck| ack.reason_string = Some(ByteString::from_static(reason)))
+    } else {
+        new_ack_code.v5_error_ack(handshake)
+    }
 }
 
 #[inline]
@@ -52,12 +67,56 @@ pub async fn handshake<Io: 'static>(
         listen_cfg
     );
 
+    if !listen_cfg.protocol_version_allowed(MQTT_LEVEL_5) {
+        log::info!(
+            "{:?} Connection Refused, handshake error, reason: protocol level {} is not accepted",
+            Id::new(
+                Runtime::instance().node.id(),
+                Some(local_addr),
+                Some(remote_addr),
+                handshake.packet().client_id.clone(),
+                handshake.packet().username.clone(),
+            ),
+            MQTT_LEVEL_5
+        );
+        return Ok(
+            ConnectAckReason::V5(ConnectAckReasonV5::UnsupportedProtocolVersion).v5_error_ack(handshake)
+        );
+    }
+
     let assigned_client_id = if handshake.packet().client_id.is_empty() {
         handshake.packet_mut().client_id =
             ClientId::from(Uuid::new_v4().as_simple().encode_lower(&mut Uuid::encode_buffer()).to_owned());
         true
     } else {
-        false
+        let packet = handshake.packet();
+        match listen_cfg.clientid_policy.check(
+            &packet.client_id,
+            packet.username.as_deref(),
+            listen_cfg.max_clientid_len,
+        ) {
+            ClientIdCheck::Accepted => false,
+            //the rewritten client id is returned to the client as the Assigned Client Identifier
+            ClientIdCheck::Rewritten(client_id) => {
+                handshake.packet_mut().client_id = ClientId::from(client_id);
+                true
+            }
+            ClientIdCheck::Rejected(reason) => {
+                log::info!(
+                    "{:?} Connection Refused, handshake error, reason: {}",
+                    Id::new(
+                        Runtime::instance().node.id(),
+                        Some(local_addr),
+                        Some(remote_addr),
+                        packet.client_id.clone(),
+                        packet.username.clone(),
+                    ),
+                    reason
+                );
+                return Ok(ConnectAckReason::V5(ConnectAckReasonV5::ClientIdentifierNotValid)
+                    .v5_error_ack(handshake));
+            }
+        }
     };
 
     let id = Id::new(
@@ -97,13 +156,44 @@ pub async fn _handshake<Io: 'static>(
     log::debug!("handshake.packet(): {:?}", handshake.packet());
     //hook, client connect
     let _user_props = Runtime::instance().extends.hook_mgr().await.client_connect(&connect_info).await;
+    let problem_info = listen_cfg.problem_information && connect_info.request_problem_info();
 
-    if listen_cfg.max_clientid_len > 0 && id.client_id.len() > listen_cfg.max_clientid_len {
+    if banned::is_banned(&id) {
         return Ok(refused_ack(
             handshake,
             &connect_info,
+            problem_info,
+            ConnectAckReasonV5::Banned,
+            "the client is banned",
+            None,
+        )
+        .await);
+    }
+
+    if let Some(m) = Runtime::instance().node.maintenance() {
+        let server_reference = m
+            .server_reference()
+            .or_else(|| listen_cfg.server_reference.as_deref().filter(|r| !r.is_empty()))
+            .map(ServerReference::from);
+        let ack_code = if server_reference.is_some() {
+            ConnectAckReasonV5::UseAnotherServer
+        } else {
+            ConnectAckReasonV5::ServerUnavailable
+        };
+        let ack =
+            refused_ack(handshake, &connect_info, problem_info, ack_code, "the node is in maintenance", None)
+                .await;
+        return Ok(ack.with(|ack: &mut v5::codec::ConnectAck| ack.server_reference = server_reference));
+    }
+
+    if listen_cfg.strict_mode && !is_strict_utf8(&id.client_id) {
+        return Ok(refused_ack(
+            handshake,
+            &connect_info,
+            problem_info,
             ConnectAckReasonV5::ClientIdentifierNotValid,
-            "client_id is too long".into(),
+            "client_id contains control characters or is not normalized",
+            None,
         )
         .await);
     }
@@ -113,37 +203,102 @@ pub async fn _handshake<Io: 'static>(
         return Ok(refused_ack(
             handshake,
             &connect_info,
+            problem_info,
             ConnectAckReasonV5::BadAuthenticationMethod,
-            "extended Auth is not supported".into(),
+            "extended Auth is not supported",
+            None,
         )
         .await);
     }
 
-    //hook, client authenticate
-    let (ack, superuser) = Runtime::instance()
-        .extends
-        .hook_mgr()
-        .await
-        .client_authenticate(&connect_info, listen_cfg.allow_anonymous)
-        .await;
+    //hook, client authenticate, skipped for the auth_bypass addresses of the listener
+    let (ack, superuser, auth_attrs) = if listen_cfg.auth_bypassed(id.remote_addr) {
+        (
+            ConnectAckReason::V5(ConnectAckReasonV5::Success),
+            false,
+            SessionAttrs::default().with(ATTR_AUTH, AUTH_BYPASS),
+        )
+    } else {
+        Runtime::instance()
+            .extends
+            .hook_mgr()
+            .await
+            .client_authenticate(&connect_info, listen_cfg.allow_anonymous)
+            .await
+    };
     if !ack.success() {
         if let ConnectAckReason::V5(ack) = ack {
-            return Ok(refused_ack(handshake, &connect_info, ack, "Authentication failed".into()).await);
+            return Ok(refused_ack(
+                handshake,
+                &connect_info,
+                problem_info,
+                ack,
+                "Authentication failed",
+                None,
+            )
+            .await);
         } else {
             unreachable!()
         }
     }
 
+    let attrs = {
+        let enricher = Runtime::instance().extends.client_enricher().await;
+        if enricher.enable() {
+            let mut attrs = enricher.enrich(&connect_info).await;
+            attrs.extend(auth_attrs);
+            attrs
+        } else {
+            auth_attrs
+        }
+    };
+
+    //per-client limits returned by the authentication plugins
+    let listen_cfg = listen_cfg.with_attrs(&attrs);
+
+    let username_connections = listen_cfg.username_connections.with_attrs(&attrs);
+    if let Err(e) = username_connections_check(&id, username_connections).await {
+        return Ok(refused_ack(
+            handshake,
+            &connect_info,
+            problem_info,
+            ConnectAckReasonV5::QuotaExceeded,
+            "too many connections of the username",
+            Some(e),
+        )
+        .await);
+    }
+
+    let quota_exceeded = Runtime::instance().extends.hook_mgr().await.client_quota_check(&connect_info).await;
+    if let Some(e) = quota_exceeded {
+        return Ok(refused_ack(
+            handshake,
+            &connect_info,
+            problem_info,
+            ConnectAckReasonV5::QuotaExceeded,
+            "quota exceeded",
+            Some(e),
+        )
+        .await);
+    }
+
     let sink = handshake.sink();
     let packet = handshake.packet_mut();
 
-    let mut entry = match { Runtime::instance().extends.shared().await.entry(id.clone()) }.try_lock().await {
+    //connects of the same client id are serialized, the previous one finishes its takeover first
+    let connect_lock_timeout = Runtime::instance().settings.latest_node().connect_lock_timeout;
+    let mut entry = match { Runtime::instance().extends.shared().await.entry(id.clone()) }
+        .lock(connect_lock_timeout)
+        .await
+    {
         Err(e) => {
             return Ok(refused_ack(
                 handshake,
                 &connect_info,
+                problem_info,
                 ConnectAckReasonV5::ServerUnavailable,
-                format!("{}", e),
+                "the server is unavailable",
+                Some(e.to_string()),
             )
             .await);
         }
@@ -151,20 +306,21 @@ pub async fn _handshake<Io: 'static>(
     };
 
     // Kick out the current session, if it exists
-    let (session_present, offline_info) =
-        match entry.kick(packet.clean_start, packet.clean_start, false).await {
-            Err(e) => {
-                return Ok(refused_ack(
-                    handshake,
-                    &connect_info,
-                    ConnectAckReasonV5::ServerUnavailable,
-                    format!("{}", e),
-                )
-                .await);
-            }
-            Ok(Some(offline_info)) => (!packet.clean_start, Some(offline_info)),
-            Ok(None) => (false, None),
-        };
+    let offline_info = match entry.kick(packet.clean_start, packet.clean_start, false).await {
+        Err(e) => {
+            return Ok(refused_ack(
+                handshake,
+                &connect_info,
+                problem_info,
+                ConnectAckReasonV5::ServerUnavailable,
+                "the server is unavailable",
+                Some(e.to_string()),
+            )
+            .await);
+        }
+        Ok(offline_info) => offline_info,
+    };
+    let session_present = SessionPresent::new(packet.clean_start, offline_info.is_some());
 
     let connected_at = chrono::Local::now().timestamp_millis();
 
@@ -188,8 +344,9 @@ pub async fn _handshake<Io: 'static>(
         max_inflight,
         created_at,
         connect_info.clone(),
-        session_present,
+        session_present.present,
         superuser,
+        attrs,
         true,
         connected_at,
         SessionSubs::new(),
@@ -203,8 +360,10 @@ pub async fn _handshake<Io: 'static>(
             return Ok(refused_ack(
                 handshake,
                 connect_info.as_ref(),
+                problem_info,
                 ConnectAckReasonV5::ServerUnavailable,
-                format!("{}", e),
+                "the server is unavailable",
+                Some(e.to_string()),
             )
             .await);
         }
@@ -216,8 +375,10 @@ pub async fn _handshake<Io: 'static>(
             return Ok(refused_ack(
                 handshake,
                 connect_info.as_ref(),
+                problem_info,
                 ConnectAckReasonV5::ServerUnavailable,
-                format!("{}", e),
+                "the server is unavailable",
+                Some(e.to_string()),
             )
             .await);
         }
@@ -225,9 +386,31 @@ pub async fn _handshake<Io: 'static>(
 
     let hook = Runtime::instance().extends.hook_mgr().await.hook(&session);
 
-    if offline_info.is_none() {
-        //hook, session created
-        hook.session_created().await;
+    //hook, message_publish_check_acl, the will must be publishable by the client
+    if let Ok(Some(lw)) = session.last_will_publish().await {
+        if let PublishAclResult::Rejected(_) = hook.message_publish_check_acl(&lw).await {
+            return Ok(refused_ack(
+                handshake,
+                connect_info.as_ref(),
+                problem_info,
+                ConnectAckReasonV5::NotAuthorized,
+                "Last will topic not authorized",
+                None,
+            )
+            .await);
+        }
+    }
+
+    match offline_info.as_ref() {
+        Some(o) if session_present.present => {
+            //hook, session resumed
+            hook.session_resumed(o.subscriptions.len(), o.inflight_messages.len() + o.offline_messages.len())
+                .await;
+        }
+        _ => {
+            //hook, session created
+            hook.session_created(packet.clean_start).await;
+        }
     }
 
     let client_topic_alias_max = session.fitter.max_client_topic_aliases();
@@ -241,18 +424,24 @@ pub async fn _handshake<Io: 'static>(
         return Ok(refused_ack(
             handshake,
             connect_info.as_ref(),
+            problem_info,
             ConnectAckReasonV5::ServerUnavailable,
-            format!("{}", e),
+            "the server is unavailable",
+            Some(e.to_string()),
         )
         .await);
     }
 
-    //hook, client connack
-    let _ = Runtime::instance()
+    //hook, client connack, handlers can override the Session Present flag of the CONNACK
+    let (_, session_present) = Runtime::instance()
         .extends
         .hook_mgr()
         .await
-        .client_connack(connect_info.as_ref(), ConnectAckReason::V5(ConnectAckReasonV5::Success))
+        .client_connack(
+            connect_info.as_ref(),
+            ConnectAckReason::V5(ConnectAckReasonV5::Success),
+            session_present,
+        )
         .await;
 
     //hook, client connected
@@ -293,8 +482,9 @@ pub async fn _handshake<Io: 'static>(
     let shared_subscription_available =
         Runtime::instance().extends.shared_subscription().await.is_supported(state.listen_cfg());
     let assigned_client_id = if is_assigned_client_id { Some(state.id.client_id.clone()) } else { None };
+    let response_info = if packet.request_response_info { state.response_information() } else { None };
     Ok(handshake.ack(state).keep_alive(keep_alive).with(|ack: &mut v5::codec::ConnectAck| {
-        ack.session_present = session_present;
+        ack.session_present = session_present.present;
         ack.server_keepalive_sec = Some(server_keepalive_sec);
         ack.session_expiry_interval_secs = session_expiry_interval_secs;
         ack.receive_max = Some(max_inflight);
@@ -302,6 +492,7 @@ pub async fn _handshake<Io: 'static>(
         ack.retain_available = Some(retain_available);
         ack.max_packet_size = Some(max_server_packet_size);
         ack.assigned_client_id = assigned_client_id;
+        ack.response_info = response_info;
         ack.topic_alias_max = client_topic_alias_max;
         ack.wildcard_subscription_available = Some(true);
         ack.subscription_identifiers_available = Some(true);
@@ -318,29 +509,73 @@ async fn subscribes(
         Runtime::instance().extends.shared_subscription().await.is_supported(state.listen_cfg());
     let limit_subscription = state.listen_cfg().limit_subscription;
     let sub_id = subs.packet().id;
+    let mut failures = Vec::new();
     for mut sub in subs.iter_mut() {
-        let s =
-            Subscribe::from_v5(sub.topic(), sub.options(), shared_subscription, limit_subscription, sub_id)?;
-        let sub_ret = state.subscribe(s).await?;
-        if let Some(qos) = sub_ret.success() {
-            sub.confirm(qos)
-        } else {
-            sub.fail(sub_ret.into_inner())
+        //a malformed topic filter only fails its own entry of the SUBACK
+        let sub_ret = match Subscribe::from_v5(
+            sub.topic(),
+            sub.options(),
+            shared_subscription,
+            limit_subscription,
+            sub_id,
+        ) {
+            Ok(s) => state.subscribe(s).await,
+            Err(e) => Err(e),
+        };
+        match sub_ret {
+            Ok(sub_ret) => {
+                if let Some(qos) = sub_ret.success() {
+                    sub.confirm(qos)
+                } else {
+                    let reason = sub_ret.into_inner();
+                    failures.push(format!("{}: {:?}", sub.topic(), reason));
+                    sub.fail(reason)
+                }
+            }
+            Err(MqttError::TopicError(e)) => {
+                log::warn!("{:?} Subscribe failed, topic filter: {:?}, reason: {}", state.id, sub.topic(), e);
+                failures.push(format!("{}: {}", sub.topic(), e));
+                sub.fail(SubscribeAckReason::TopicFilterInvalid)
+            }
+            Err(e) => return Err(e),
         }
     }
+    if !failures.is_empty() && state.problem_info().await {
+        subs = subs.ack_reason(ByteString::from(failures.join(", ")));
+    }
     Ok(subs.ack())
 }
 
 async fn unsubscribes(
     state: &v5::Session<SessionState>,
-    unsubs: v5::control::Unsubscribe,
+    mut unsubs: v5::control::Unsubscribe,
 ) -> Result<v5::ControlResult> {
     let shared_subscription =
         Runtime::instance().extends.shared_subscription().await.is_supported(state.listen_cfg());
     let limit_subscription = state.listen_cfg().limit_subscription;
-    for topic_filter in unsubs.iter() {
-        let unsub = Unsubscribe::from(topic_filter, shared_subscription, limit_subscription)?;
-        state.unsubscribe(unsub).await?;
+    let mut failures = Vec::new();
+    for mut unsub in unsubs.iter_mut() {
+        let res = match Unsubscribe::from(unsub.topic(), shared_subscription, limit_subscription) {
+            Ok(u) => state.unsubscribe(u).await,
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(()) => unsub.success(),
+            Err(MqttError::TopicError(e)) => {
+                log::warn!(
+                    "{:?} Unsubscribe failed, topic filter: {:?}, reason: {}",
+                    state.id,
+                    unsub.topic(),
+                    e
+                );
+                failures.push(format!("{}: {}", unsub.topic(), e));
+                unsub.fail(UnsubscribeAckReason::TopicFilterInvalid)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if !failures.is_empty() && state.problem_info().await {
+        unsubs = unsubs.ack_reason(ByteString::from(failures.join(", ")));
     }
     Ok(unsubs.ack())
 }
@@ -358,10 +593,17 @@ pub async fn control_message<E: std::fmt::Debug>(
         }
         v5::ControlMessage::Ping(ping) => {
             let _ = state.send(Message::Keepalive(true));
+            //hook, packet_received, packet_sent
+            state.hook.packet_received(&Packet::V5(PacketV5::PingRequest)).await;
+            state.hook.packet_sent(&Packet::V5(PacketV5::PingResponse)).await;
             ping.ack()
         }
         v5::ControlMessage::Subscribe(subs) => {
             let _ = state.send(Message::Keepalive(false));
+            //hook, packet_received
+            if state.hook.is_hooked(Type::PacketReceived) {
+                state.hook.packet_received(&Packet::V5(PacketV5::Subscribe(subs.packet().clone()))).await;
+            }
             match subscribes(&state, subs).await {
                 Err(e) => {
                     log::warn!("{:?} Subscribe failed, reason: {}", state.id, e);
@@ -377,6 +619,10 @@ pub async fn control_message<E: std::fmt::Debug>(
         }
         v5::ControlMessage::Unsubscribe(unsubs) => {
             let _ = state.send(Message::Keepalive(false));
+            //hook, packet_received
+            if state.hook.is_hooked(Type::PacketReceived) {
+                state.hook.packet_received(&Packet::V5(PacketV5::Unsubscribe(unsubs.packet().clone()))).await;
+            }
             match unsubscribes(&state, unsubs).await {
                 Err(e) => {
                     log::warn!("{:?} Unsubscribe failed, reason: {}", state.id, e);
@@ -392,6 +638,13 @@ pub async fn control_message<E: std::fmt::Debug>(
         }
         v5::ControlMessage::Disconnect(disconnect) => {
             //disconnect.packet().user_properties
+            //hook, packet_received
+            if state.hook.is_hooked(Type::PacketReceived) {
+                state
+                    .hook
+                    .packet_received(&Packet::V5(PacketV5::Disconnect(disconnect.packet().clone())))
+                    .await;
+            }
             state.send(Message::Disconnect(Disconnect::V5(disconnect.packet().clone())))?;
             disconnect.ack()
         }
@@ -410,10 +663,11 @@ pub async fn control_message<E: std::fmt::Debug>(
             err.ack(DisconnectReasonCode::ServerBusy)
         }
         v5::ControlMessage::ProtocolError(protocol_error) => {
-            if let Err(e) = state.send(Message::Closed(Reason::ProtocolError(ByteString::from(format!(
-                "{:?}",
-                protocol_error.get_ref()
-            ))))) {
+            let reason = ByteString::from(format!("{:?}", protocol_error.get_ref()));
+            log::info!("{:?} protocol violation, {}", state.id, reason);
+            //hook, client protocol violation
+            state.hook.client_protocol_violation(reason.clone()).await;
+            if let Err(e) = state.send(Message::Closed(Reason::ProtocolError(reason))) {
                 log::debug!("{:?} Closed error, reason: {:?}", state.id, e);
             }
             protocol_error.ack()
@@ -434,6 +688,12 @@ pub async fn publish(
 
     match pub_msg {
         v5::PublishMessage::Publish(publish) => {
+            //hook, packet_received
+            if state.hook.is_hooked(Type::PacketReceived) {
+                state.hook.packet_received(&Packet::V5(PacketV5::Publish(publish.packet().clone()))).await;
+            }
+            let qos = publish.qos();
+            let session = state.session.clone();
             let publish_fut = async move {
                 if let Err(e) = state.publish_v5(&publish).await {
                     log::warn!(
@@ -446,27 +706,51 @@ pub async fn publish(
                     Ok(())
                 }
             };
-            if Runtime::instance().is_busy() {
+            let res = if Runtime::instance().is_busy() {
                 Runtime::local_exec()
                     .spawn(publish_fut)
                     .result()
                     .await
-                    .map_err(|e| MqttError::from(e.to_string()))??;
+                    .map_err(|e| MqttError::from(e.to_string()))?
             } else {
-                publish_fut.await?;
-            }
-            return Ok(PublishResult::PublishAck(PublishAck::new(PublishAckReason::Success)));
+                publish_fut.await
+            };
+            return match res {
+                Ok(()) => Ok(PublishResult::PublishAck(PublishAck::new(PublishAckReason::Success))),
+                //a QoS 1 or 2 message is refused with the reason code, the connection is kept
+                Err(MqttError::PublishAckReason(reason, reason_string)) if qos != QoS::AtMostOnce => {
+                    let ack = PublishAck::new(reason);
+                    if session.problem_info().await {
+                        Ok(PublishResult::PublishAck(ack.reason(reason_string)))
+                    } else {
+                        Ok(PublishResult::PublishAck(ack))
+                    }
+                }
+                Err(e) => Err(e),
+            };
         }
         v5::PublishMessage::PublishAck(ref ack) => {
-            if let Some(iflt_msg) = state.inflight_win().write().await.remove(&ack.packet_id.get()) {
+            state.hook.packet_received(&Packet::V5(PacketV5::PublishAck(ack.clone()))).await;
+            let iflt_msg = state.inflight_win().write().await.remove(&ack.packet_id.get());
+            if let Some(iflt_msg) = iflt_msg {
                 //hook, message_ack
                 state.hook.message_acked(iflt_msg.from, &iflt_msg.publish).await;
+                state.ordered_release(&iflt_msg.publish.topic).await;
             }
         }
         v5::PublishMessage::PublishReceived(ref ack) => {
-            state.inflight_win().write().await.update_status(&ack.packet_id.get(), MomentStatus::UnComplete);
+            state.hook.packet_received(&Packet::V5(PacketV5::PublishReceived(ack.clone()))).await;
+            let topic = {
+                let mut inflight_win = state.inflight_win().write().await;
+                inflight_win.update_status(&ack.packet_id.get(), MomentStatus::UnComplete);
+                inflight_win.get(ack.packet_id.get()).map(|m| m.publish.topic.clone())
+            };
+            if let Some(topic) = topic {
+                state.ordered_release(&topic).await;
+            }
         }
         v5::PublishMessage::PublishComplete(ref ack2) => {
+            state.hook.packet_received(&Packet::V5(PacketV5::PublishComplete(ack2.clone()))).await;
             if let Some(iflt_msg) = state.inflight_win().write().await.remove(&ack2.packet_id.get()) {
                 //hook, message_ack
                 state.hook.message_acked(iflt_msg.from, &iflt_msg.publish).await;
@@ -1,15 +1,18 @@
+use std::sync::RwLock as SyncRwLock;
+
 use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use crate::broker::{
     default::{
-        DefaultAutoSubscription, DefaultDelayedSender, DefaultFitterManager, DefaultHookManager,
-        DefaultRetainStorage, DefaultRouter, DefaultSessionManager, DefaultShared, DefaultSharedSubscription,
+        DefaultAutoSubscription, DefaultCertResolver, DefaultClientEnricher, DefaultDelayedSender,
+        DefaultFitterManager, DefaultHookManager, DefaultKeyLoader, DefaultKvStore, DefaultRetainStorage,
+        DefaultRouter, DefaultSessionManager, DefaultShared, DefaultSharedSubscription,
     },
     fitter::FitterManager,
     hook::HookManager,
     session::SessionManager,
-    AutoSubscription, DefaultMessageManager, DelayedSender, MessageManager, RetainStorage, Router, Shared,
-    SharedSubscription,
+    AutoSubscription, CertResolver, ClientEnricher, DefaultMessageManager, DelayedSender, KeyLoader, KvStore,
+    MessageManager, RetainStorage, Router, Shared, SharedSubscription,
 };
 
 // Defines a struct that manages a number of lock objects to different components that are
@@ -25,6 +28,12 @@ pub struct Manager {
     message_mgr: RwLock<Box<dyn MessageManager>>,
     delayed_sender: RwLock<Box<dyn DelayedSender>>,
     auto_subscription: RwLock<Box<dyn AutoSubscription>>,
+    kv_store: RwLock<Box<dyn KvStore>>,
+    client_enricher: RwLock<Box<dyn ClientEnricher>>,
+    //Read from the TLS handshake, which can not await
+    cert_resolver: SyncRwLock<Box<dyn CertResolver>>,
+    //Read when the TLS listeners are bound, from sync code
+    key_loader: SyncRwLock<Box<dyn KeyLoader>>,
 }
 
 impl Manager {
@@ -41,6 +50,10 @@ impl Manager {
             message_mgr: RwLock::new(Box::new(DefaultMessageManager::instance())),
             delayed_sender: RwLock::new(Box::new(DefaultDelayedSender::instance())),
             auto_subscription: RwLock::new(Box::new(DefaultAutoSubscription::instance())),
+            kv_store: RwLock::new(Box::new(DefaultKvStore::instance())),
+            client_enricher: RwLock::new(Box::new(DefaultClientEnricher::instance())),
+            cert_resolver: SyncRwLock::new(Box::new(DefaultCertResolver::instance())),
+            key_loader: SyncRwLock::new(Box::new(DefaultKeyLoader::instance())),
         }
     }
 
@@ -138,4 +151,44 @@ impl Manager {
     pub async fn auto_subscription_mut(&self) -> RwLockWriteGuard<'_, Box<dyn AutoSubscription>> {
         self.auto_subscription.write().await
     }
+
+    #[inline]
+    pub async fn kv_store(&self) -> RwLockReadGuard<'_, Box<dyn KvStore>> {
+        self.kv_store.read().await
+    }
+
+    #[inline]
+    pub async fn kv_store_mut(&self) -> RwLockWriteGuard<'_, Box<dyn KvStore>> {
+        self.kv_store.write().await
+    }
+
+    #[inline]
+    pub async fn client_enricher(&self) -> RwLockReadGuard<'_, Box<dyn ClientEnricher>> {
+        self.client_enricher.read().await
+    }
+
+    #[inline]
+    pub async fn client_enricher_mut(&self) -> RwLockWriteGuard<'_, Box<dyn ClientEnricher>> {
+        self.client_enricher.write().await
+    }
+
+    #[inline]
+    pub fn cert_resolver(&self) -> std::sync::RwLockReadGuard<'_, Box<dyn CertResolver>> {
+        self.cert_resolver.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    pub fn cert_resolver_mut(&self) -> std::sync::RwLockWriteGuard<'_, Box<dyn CertResolver>> {
+        self.cert_resolver.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    pub fn key_loader(&self) -> std::sync::RwLockReadGuard<'_, Box<dyn KeyLoader>> {
+        self.key_loader.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    pub fn key_loader_mut(&self) -> std::sync::RwLockWriteGuard<'_, Box<dyn KeyLoader>> {
+        self.key_loader.write().unwrap_or_else(|e| e.into_inner())
+    }
 }
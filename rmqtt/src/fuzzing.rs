@@ -0,0 +1,195 @@
+//!Deterministic entry points for fuzzing, enabled by the `fuzzing` feature.
+//!
+//!Each function takes arbitrary bytes and drives the broker code that parses client input, without
+//!a `Runtime` and without any network. They panic when an invariant is broken, so a fuzzer reports
+//!the input as a crash. The cargo-fuzz targets are in `rmqtt/fuzz`:
+//!
+//!```text
+//!cd rmqtt && cargo +nightly fuzz run subscription_match
+//!```
+
+use std::convert::TryFrom;
+use std::num::{NonZeroU16, NonZeroU32};
+use std::str::FromStr;
+
+use bytestring::ByteString;
+use futures::executor::block_on;
+use ntex::util::Bytes;
+
+use crate::broker::retain::RetainTree;
+use crate::broker::topic::{Level, Topic, TopicTree};
+use crate::broker::types::{
+    parse_topic_filter, ClientTopicAliases, LastWill, LastWillV3, PublishProperties, PublishPropertiesV5,
+    ServerTopicAliases,
+};
+use crate::{Publish, QoS, TopicFilter, TopicName};
+
+///Topic filter parsing, including the `$share`, `$limit` and `$exclusive` prefixes.
+///
+///The input is taken as one topic filter, invalid UTF-8 is replaced.
+pub fn topic(data: &[u8]) {
+    let s = String::from_utf8_lossy(data);
+    let topic_filter = TopicFilter::from(s.as_ref());
+    for (shared, limit) in [(false, false), (true, false), (false, true), (true, true)] {
+        if let Ok((tf, group, limit_subs)) = parse_topic_filter(&topic_filter, shared, limit) {
+            assert!(!tf.is_empty());
+            assert!(shared || group.is_none());
+            assert!(limit || limit_subs.is_none());
+            let _ = Topic::from_str(&tf);
+        }
+    }
+
+    if let Ok(t) = Topic::from_str(&s) {
+        let mut tree = TopicTree::default();
+        assert!(tree.insert(&t, 0u64));
+        assert!(tree.is_match(&t) || has_wildcard(&t));
+        assert!(tree.remove(&t, &0));
+        assert_eq!(tree.nodes_size(), 0);
+    }
+}
+
+///Subscription matching against the subscription tree and the retained message tree.
+///
+///The input is a sequence of `tag, len, bytes` entries, an even tag adds a topic filter and an odd
+///tag adds a topic name.
+pub fn subscription_match(data: &[u8]) {
+    let mut input = Input(data);
+    let mut filters = Vec::new();
+    let mut topics = Vec::new();
+    while let (Some(tag), Some(s)) = (input.u8(), input.string()) {
+        match Topic::from_str(&s) {
+            Ok(t) if tag % 2 == 0 => filters.push(t),
+            Ok(t) if !has_wildcard(&t) => topics.push(t),
+            _ => {}
+        }
+    }
+
+    let mut subs = TopicTree::default();
+    for (i, tf) in filters.iter().enumerate() {
+        assert!(subs.insert(tf, i));
+    }
+    let mut retains = RetainTree::default();
+    for t in topics.iter() {
+        retains.insert(t, t.clone());
+    }
+
+    for t in topics.iter() {
+        for (_, matched) in subs.matches(t).iter() {
+            assert!(matched.iter().all(|i| **i < filters.len()));
+        }
+    }
+    for tf in filters.iter() {
+        for (t, retained) in retains.matches(tf) {
+            assert_eq!(t.levels(), retained.levels());
+        }
+    }
+
+    for (i, tf) in filters.iter().enumerate() {
+        assert!(subs.remove(tf, &i));
+    }
+    assert_eq!(subs.values_size(), 0);
+    assert_eq!(subs.nodes_size(), 0);
+}
+
+///MQTT 5.0 publish properties, topic aliases and the user properties carried in the query string of
+///a MQTT 3.1.1 will topic.
+///
+///The input starts with the maximum number of topic aliases, followed by `id, value` properties
+///encoded as in a PUBLISH packet except that strings and binary data have a one byte length. A
+///Topic Alias property is followed by the topic name it is sent with.
+pub fn v5_properties(data: &[u8]) {
+    let mut input = Input(data);
+    let max_topic_aliases = input.u8().unwrap_or_default() as usize;
+    let client_aliases = ClientTopicAliases::new(max_topic_aliases);
+    let server_aliases = ServerTopicAliases::new(max_topic_aliases);
+
+    let mut props = PublishProperties::default();
+    while let Some(id) = input.u8() {
+        match id {
+            0x01 => props.is_utf8_payload = input.u8().map(|b| b != 0),
+            0x02 => props.message_expiry_interval = input.u32().and_then(NonZeroU32::new),
+            0x03 => props.content_type = input.string().map(ByteString::from),
+            0x08 => props.response_topic = input.string().map(ByteString::from),
+            0x09 => props.correlation_data = input.bytes().map(Bytes::copy_from_slice),
+            0x0B => {
+                if let Some(sub_id) = input.u32().and_then(NonZeroU32::new) {
+                    props.subscription_ids.get_or_insert_with(Vec::new).push(sub_id);
+                }
+            }
+            0x23 => {
+                props.topic_alias = input.u16().and_then(NonZeroU16::new);
+                let topic = TopicName::from(input.string().unwrap_or_default());
+                if let Ok(topic) = block_on(client_aliases.set_and_get(props.topic_alias, topic)) {
+                    assert!(!topic.is_empty());
+                    let (topic, alias) = block_on(server_aliases.get(topic));
+                    assert!(topic.is_some() || alias.is_some());
+                    assert!(alias.map(|a| a.get() as usize <= max_topic_aliases).unwrap_or(true));
+                }
+            }
+            0x26 => match (input.string(), input.string()) {
+                (Some(k), Some(v)) => props.user_properties.push((k.into(), v.into())),
+                _ => break,
+            },
+            _ => break,
+        }
+    }
+
+    assert_eq!(PublishProperties::from(PublishPropertiesV5::from(props.clone())), props);
+    let encoded = bincode::serialize(&props).expect("serialize publish properties");
+    assert_eq!(bincode::deserialize::<PublishProperties>(&encoded).expect("deserialize"), props);
+
+    let lw = LastWillV3 {
+        qos: QoS::AtMostOnce,
+        retain: false,
+        topic: ByteString::from(input.string().unwrap_or_default()),
+        message: Bytes::new(),
+    };
+    if let Ok(p) = Publish::try_from(LastWill::V3(&lw)) {
+        assert_eq!(p.topic, lw.topic);
+    }
+}
+
+#[inline]
+fn has_wildcard(t: &Topic) -> bool {
+    t.levels().iter().any(|l| matches!(l, Level::SingleWildcard | Level::MultiWildcard))
+}
+
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    #[inline]
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    #[inline]
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    #[inline]
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    #[inline]
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    #[inline]
+    fn string(&mut self) -> Option<String> {
+        self.bytes().map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+}
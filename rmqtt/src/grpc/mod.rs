@@ -9,7 +9,7 @@ use client::NodeGrpcClient;
 use crate::broker::session::SessionOfflineInfo;
 use crate::broker::types::{
     CleanStart, ClearSubscriptions, From, Id, IsAdmin, NodeId, Publish, Retain, Route, SessionStatus,
-    SubsSearchParams, SubsSearchResult, TopicFilter, TopicName,
+    SubsSearchParams, SubsSearchResult, TopicFilter, TopicName, UserName,
 };
 use crate::{
     Addr, ClientId, MsgID, Result, SharedGroup, SubRelations, SubRelationsMap, SubscriptionClientIds,
@@ -44,6 +44,8 @@ pub enum Message {
     SessionStatus(ClientId),
     MessageGet(ClientId, TopicFilter, Option<SharedGroup>),
     Data(Vec<u8>),
+    //new variants are appended, the discriminants are part of the encoding between nodes
+    UsernameSessions(UserName),
 }
 
 impl Message {
@@ -74,6 +76,8 @@ pub enum MessageReply {
     SessionStatus(Option<SessionStatus>),
     MessageGet(Vec<(MsgID, From, Publish)>),
     Data(Vec<u8>),
+    //new variants are appended, the discriminants are part of the encoding between nodes
+    UsernameSessions(Vec<Id>),
 }
 
 impl MessageReply {
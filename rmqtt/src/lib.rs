@@ -30,6 +30,7 @@ pub use pin_project_lite;
 pub use rand;
 pub use reqwest;
 pub use rust_box;
+pub use rustls;
 pub use scc;
 pub use structopt;
 pub use tokio;
@@ -38,7 +39,7 @@ pub use tokio_tungstenite;
 pub use url;
 
 pub use crate::broker::{
-    error::MqttError,
+    error::{ErrorKind, MqttError},
     metrics,
     session::{Session, SessionState},
     stats,
@@ -50,9 +51,14 @@ pub type Result<T, E = MqttError> = anyhow::Result<T, E>;
 
 pub mod broker;
 pub mod extend;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
 pub mod grpc;
 pub mod logger;
 pub mod node;
 pub mod plugin;
 pub mod runtime;
+pub mod server;
 pub mod settings;
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -1,15 +1,72 @@
 use std::fs::{File, OpenOptions};
 use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 
+use once_cell::sync::Lazy;
+use rust_box::std_ext::RwLock;
 pub use slog::Logger;
 use slog::{o, Drain, Record};
 use slog_scope::GlobalLoggerGuard;
 use slog_term::{CountingWriter, RecordDecorator, ThreadSafeTimestampFn};
 
-use crate::{MqttError, Result, Runtime};
+use crate::{timestamp_secs, MqttError, Result, Runtime, Timestamp};
 
 use super::settings::log::{Level, To};
 
+//Minimum level of the records that are printed, can be changed at runtime
+static LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+//Level overrides set at runtime, for all targets or the targets under a module path
+static OVERRIDES: Lazy<RwLock<Vec<LevelOverride>>> = Lazy::new(|| RwLock::new(Vec::new()));
+static HAS_OVERRIDES: AtomicBool = AtomicBool::new(false);
+static OVERRIDE_ID: AtomicU64 = AtomicU64::new(0);
+
+struct LevelOverride {
+    id: u64,
+    //empty for all targets
+    target: String,
+    level: slog::Level,
+    expires_at: Option<Timestamp>,
+}
+
+impl LevelOverride {
+    #[inline]
+    fn matches(&self, module: &str) -> bool {
+        self.target.is_empty()
+            || (module.starts_with(self.target.as_str())
+                && (module.len() == self.target.len() || module[self.target.len()..].starts_with("::")))
+    }
+}
+
+///A log level change made at runtime
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogLevelOptions {
+    ///Module path, such as "rmqtt::broker::session", all targets if not set
+    #[serde(default)]
+    pub target: Option<String>,
+    ///"trace", "debug", "info", "warning", "error" or "critical"
+    pub level: String,
+    ///The change is reverted after this many seconds, 0 means it is kept until it is reset
+    #[serde(default)]
+    pub duration: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogLevels {
+    ///The configured level
+    pub level: String,
+    pub overrides: Vec<LogLevelOverride>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogLevelOverride {
+    pub target: Option<String>,
+    pub level: String,
+    pub expires_at: Option<Timestamp>,
+}
+
 /// Initializes a logger using `slog` and `slog_scope`.
 ///
 /// This function creates a `GlobalLoggerGuard` and sets the global logger to the `logger` passed
@@ -25,6 +82,106 @@ pub fn logger_init() -> Result<GlobalLoggerGuard, log::SetLoggerError> {
     Ok(guard)
 }
 
+/// Returns the current log level.
+pub fn level() -> slog::Level {
+    slog::Level::from_usize(LEVEL.load(Ordering::Relaxed)).unwrap_or(slog::Level::Debug)
+}
+
+/// Changes the log level of the running logger, used when the configuration is reloaded.
+pub fn set_level(level: slog::Level) {
+    LEVEL.store(level.as_usize(), Ordering::Relaxed);
+    update_max_level(&OVERRIDES.read());
+}
+
+/// Overrides the log level of all targets, or of the targets under `opts.target`, until `opts.duration`
+/// expires or [`reset_levels`] is called. The override of the longest matching target applies.
+pub fn set_target_level(opts: &LogLevelOptions) -> Result<LogLevels> {
+    let level = slog::Level::from_str(&opts.level)
+        .map_err(|_| MqttError::from(format!("invalid log level, {}", opts.level)))?;
+    let target = opts.target.as_deref().map(|t| t.trim().trim_end_matches("::")).unwrap_or_default();
+    let id = OVERRIDE_ID.fetch_add(1, Ordering::SeqCst);
+    let expires_at =
+        if opts.duration > 0 { Some(timestamp_secs() + opts.duration as Timestamp) } else { None };
+    {
+        let mut overrides = OVERRIDES.write();
+        overrides.retain(|o| o.target != target);
+        overrides.push(LevelOverride { id, target: target.into(), level, expires_at });
+        //longest target first
+        overrides.sort_by(|a, b| b.target.len().cmp(&a.target.len()));
+        update_max_level(&overrides);
+    }
+    log::info!("log level of {:?} set to {}, duration: {}s", opts.target, opts.level, opts.duration);
+    if opts.duration > 0 {
+        let duration = Duration::from_secs(opts.duration);
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            let reverted = {
+                let mut overrides = OVERRIDES.write();
+                let reverted = overrides.iter().position(|o| o.id == id).map(|pos| overrides.remove(pos));
+                update_max_level(&overrides);
+                reverted
+            };
+            //logged after the lock is released, the drains read the overrides
+            if let Some(o) = reverted {
+                log::info!("log level of {:?} reverted, {}", o.target, level_name(o.level));
+            }
+        });
+    }
+    Ok(levels())
+}
+
+/// Removes all log level overrides, the configured level applies again.
+pub fn reset_levels() -> LogLevels {
+    {
+        let mut overrides = OVERRIDES.write();
+        overrides.clear();
+        update_max_level(&overrides);
+    }
+    log::info!("log level overrides reset");
+    levels()
+}
+
+/// Returns the configured log level and the overrides set at runtime.
+pub fn levels() -> LogLevels {
+    LogLevels {
+        level: level_name(level()),
+        overrides: OVERRIDES
+            .read()
+            .iter()
+            .map(|o| LogLevelOverride {
+                target: if o.target.is_empty() { None } else { Some(o.target.clone()) },
+                level: level_name(o.level),
+                expires_at: o.expires_at,
+            })
+            .collect(),
+    }
+}
+
+#[inline]
+fn level_name(level: slog::Level) -> String {
+    level.as_str().to_lowercase()
+}
+
+//The `log` crate filters the records before they reach slog, so its maximum level must allow the most
+//verbose override
+fn update_max_level(overrides: &[LevelOverride]) {
+    HAS_OVERRIDES.store(!overrides.is_empty(), Ordering::SeqCst);
+    let max = overrides.iter().map(|o| o.level.as_usize()).fold(LEVEL.load(Ordering::Relaxed), usize::max);
+    let max = slog::Level::from_usize(max).unwrap_or(slog::Level::Debug);
+    log::set_max_level(slog_log_to_level(max).to_level_filter());
+}
+
+#[inline]
+fn level_enabled(record: &Record) -> bool {
+    if HAS_OVERRIDES.load(Ordering::Relaxed) {
+        let overrides = OVERRIDES.read();
+        if let Some(o) = overrides.iter().find(|o| o.matches(record.module()) || o.matches(record.tag())) {
+            return record.level().as_usize() <= o.level.as_usize();
+        }
+    }
+    record.level().as_usize() <= LEVEL.load(Ordering::Relaxed)
+}
+
 fn slog_log_to_level(level: slog::Level) -> log::Level {
     match level {
         slog::Level::Trace => log::Level::Trace,
@@ -45,6 +202,8 @@ fn slog_log_to_level(level: slog::Level) -> log::Level {
 /// creates the two `Drain`s using the provided parameters. It then combines the two `Drain`s using a
 /// `Tee` and returns the resulting `Logger`.
 pub fn config_logger(filename: String, to: To, level: Level) -> Result<slog::Logger> {
+    LEVEL.store(level.inner().as_usize(), Ordering::Relaxed);
+
     let custom_timestamp =
         |io: &mut dyn io::Write| write!(io, "{}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"));
 
@@ -83,7 +242,7 @@ pub fn config_logger(filename: String, to: To, level: Level) -> Result<slog::Log
             .use_custom_header_print(print_msg_header)
             .build()
             .fuse();
-        Some(stdout_drain.filter_level(level.inner()).fuse())
+        Some(slog::Filter::new(stdout_drain, level_enabled).fuse())
     } else {
         None
     };
@@ -104,7 +263,7 @@ pub fn config_logger(filename: String, to: To, level: Level) -> Result<slog::Log
             .build()
             .fuse();
 
-        Some(file_drain.filter_level(level.inner()).fuse())
+        Some(slog::Filter::new(file_drain, level_enabled).fuse())
     } else {
         None
     };
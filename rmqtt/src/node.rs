@@ -1,5 +1,6 @@
 use std::ops::Deref;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use once_cell::sync::Lazy;
@@ -8,7 +9,7 @@ use systemstat::Platform;
 
 use crate::grpc::client::NodeGrpcClient;
 use crate::grpc::server::Server;
-use crate::{NodeId, Result, Runtime};
+use crate::{timestamp_secs, Message, MqttError, NodeId, Result, Runtime, ServerReference, Timestamp};
 
 #[allow(dead_code)]
 mod version {
@@ -18,11 +19,12 @@ mod version {
 pub struct Node {
     pub start_time: chrono::DateTime<chrono::Local>,
     cpuload: AtomicI64,
+    maintenance: RwLock<Option<Arc<Maintenance>>>,
 }
 
 impl Node {
     pub(crate) fn new() -> Self {
-        Self { start_time: chrono::Local::now(), cpuload: AtomicI64::new(0) }
+        Self { start_time: chrono::Local::now(), cpuload: AtomicI64::new(0), maintenance: RwLock::new(None) }
     }
 
     #[inline]
@@ -64,7 +66,67 @@ impl Node {
 
     #[inline]
     pub async fn status(&self) -> NodeStatus {
-        NodeStatus::Running
+        if self.maintenance().is_some() {
+            NodeStatus::Maintenance
+        } else {
+            NodeStatus::Running
+        }
+    }
+
+    ///The maintenance in progress, new connections are refused while it is set. A scheduled maintenance
+    ///that has not started yet is not returned.
+    #[inline]
+    pub fn maintenance(&self) -> Option<Arc<Maintenance>> {
+        self.maintenance.read().clone().filter(|m| m.is_active())
+    }
+
+    ///The maintenance in progress or scheduled
+    #[inline]
+    pub fn maintenance_status(&self) -> Option<MaintenanceStatus> {
+        self.maintenance.read().as_ref().map(|m| m.status())
+    }
+
+    ///Puts the node into maintenance at `opts.start_at`, or now if it is not set. Depending on
+    ///`opts.connections`, the connected clients are disconnected in the background and their sessions are
+    ///taken over by the node they reconnect to. The node leaves maintenance after `opts.duration`, if set.
+    pub fn maintenance_start(&self, opts: MaintenanceOptions) -> Result<MaintenanceStatus> {
+        let m = {
+            let mut maintenance = self.maintenance.write();
+            if maintenance.is_some() {
+                return Err(MqttError::from("the node is already in maintenance"));
+            }
+            let m = Arc::new(Maintenance::new(opts));
+            if m.ends_at().map(|ends_at| ends_at <= timestamp_secs()).unwrap_or_default() {
+                return Err(MqttError::from("the maintenance window has already ended"));
+            }
+            maintenance.replace(m.clone());
+            m
+        };
+        log::info!("node maintenance start, {:?}", m.opts);
+        let status = m.status();
+        tokio::spawn(async move { m.run().await });
+        Ok(status)
+    }
+
+    ///Leaves maintenance, new connections are accepted again, false if the node is not in maintenance
+    pub fn maintenance_stop(&self) -> bool {
+        if let Some(m) = self.maintenance.write().take() {
+            m.stopped.store(true, Ordering::SeqCst);
+            log::info!("node maintenance stop, {:?}", m.status());
+            true
+        } else {
+            false
+        }
+    }
+
+    //Leaves the maintenance `m` when its duration is over, unless it has been replaced meanwhile
+    fn maintenance_end(&self, m: &Arc<Maintenance>) {
+        let mut maintenance = self.maintenance.write();
+        if maintenance.as_ref().map(|curr| Arc::ptr_eq(curr, m)).unwrap_or_default() {
+            maintenance.take();
+            m.stopped.store(true, Ordering::SeqCst);
+            log::info!("node maintenance end, {:?}", m.status());
+        }
     }
 
     #[inline]
@@ -133,8 +195,8 @@ impl Node {
         let loadavg = sys.load_average();
         let load1 = loadavg.as_ref().map(|l| l.one).unwrap_or_default();
 
-        load1 > Runtime::instance().settings.node.busy.loadavg
-            || cpuload > Runtime::instance().settings.node.busy.cpuloadavg
+        load1 > Runtime::instance().settings.latest_node().busy.loadavg
+            || cpuload > Runtime::instance().settings.latest_node().busy.cpuloadavg
     }
 
     #[inline]
@@ -143,7 +205,7 @@ impl Node {
         {
             let cached = CACHED.read();
             let (busy, inst) = cached.deref();
-            if inst.elapsed() < Runtime::instance().settings.node.busy.update_interval {
+            if inst.elapsed() < Runtime::instance().settings.latest_node().busy.update_interval {
                 return *busy;
             }
         }
@@ -265,6 +327,169 @@ pub enum NodeStatus {
     Running,
     Stop,
     Error(String),
+    Maintenance,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MaintenanceOptions {
+    ///Connected clients are disconnected in batches spread over this period, in seconds
+    #[serde(default)]
+    pub drain_period: u64,
+    ///MQTT 5.0 clients are asked to use this server instead, such as "node2.example.com:1883"
+    #[serde(default)]
+    pub server_reference: Option<String>,
+    ///Start time, in seconds since the epoch, the maintenance starts immediately if not set
+    #[serde(default)]
+    pub start_at: Option<Timestamp>,
+    ///The node leaves maintenance after this many seconds, 0 means it stays until it is stopped
+    #[serde(default)]
+    pub duration: u64,
+    #[serde(default)]
+    pub connections: MaintenanceConnections,
+}
+
+///What happens to the clients connected when the maintenance starts
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaintenanceConnections {
+    ///Disconnected within `drain_period`
+    #[default]
+    Drain,
+    ///Stay connected, only new connections are refused
+    Keep,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MaintenanceStatus {
+    pub opts: MaintenanceOptions,
+    pub started_at: Timestamp,
+    pub ends_at: Option<Timestamp>,
+    ///False while a scheduled maintenance has not started yet
+    pub active: bool,
+    ///Clients connected when the maintenance started
+    pub connections_total: usize,
+    pub connections_disconnected: usize,
+    pub connections_remaining: isize,
+    ///All clients have been asked to disconnect
+    pub finished: bool,
+}
+
+impl MaintenanceStatus {
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "drain_period": self.opts.drain_period,
+            "server_reference": self.opts.server_reference,
+            "connections": self.opts.connections,
+            "duration": self.opts.duration,
+            "started_at": self.started_at,
+            "ends_at": self.ends_at,
+            "active": self.active,
+            "connections_total": self.connections_total,
+            "connections_disconnected": self.connections_disconnected,
+            "connections_remaining": self.connections_remaining,
+            "finished": self.finished,
+        })
+    }
+}
+
+pub struct Maintenance {
+    opts: MaintenanceOptions,
+    started_at: Timestamp,
+    connections_total: AtomicUsize,
+    connections_disconnected: AtomicUsize,
+    finished: AtomicBool,
+    stopped: AtomicBool,
+}
+
+impl Maintenance {
+    fn new(opts: MaintenanceOptions) -> Self {
+        Self {
+            started_at: opts.start_at.unwrap_or_else(timestamp_secs),
+            opts,
+            connections_total: AtomicUsize::new(Self::connections_count()),
+            connections_disconnected: AtomicUsize::new(0),
+            finished: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    fn connections_count() -> usize {
+        Runtime::instance().stats.connections.count().max(0) as usize
+    }
+
+    #[inline]
+    pub fn server_reference(&self) -> Option<&str> {
+        self.opts.server_reference.as_deref().filter(|r| !r.is_empty())
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        timestamp_secs() >= self.started_at
+    }
+
+    #[inline]
+    pub fn ends_at(&self) -> Option<Timestamp> {
+        if self.opts.duration > 0 {
+            Some(self.started_at + self.opts.duration as Timestamp)
+        } else {
+            None
+        }
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        MaintenanceStatus {
+            opts: self.opts.clone(),
+            started_at: self.started_at,
+            ends_at: self.ends_at(),
+            active: self.is_active(),
+            connections_total: self.connections_total.load(Ordering::SeqCst),
+            connections_disconnected: self.connections_disconnected.load(Ordering::SeqCst),
+            connections_remaining: Runtime::instance().stats.connections.count(),
+            finished: self.finished.load(Ordering::SeqCst),
+        }
+    }
+
+    async fn run(self: Arc<Self>) {
+        let wait = self.started_at - timestamp_secs();
+        if wait > 0 {
+            log::info!("node maintenance scheduled, starts in {}s", wait);
+            tokio::time::sleep(Duration::from_secs(wait as u64)).await;
+            if self.stopped.load(Ordering::SeqCst) {
+                return;
+            }
+            self.connections_total.store(Self::connections_count(), Ordering::SeqCst);
+            log::info!("node maintenance, scheduled maintenance started, {:?}", self.opts);
+        }
+        match self.opts.connections {
+            MaintenanceConnections::Drain => self.evacuate().await,
+            MaintenanceConnections::Keep => self.finished.store(true, Ordering::SeqCst),
+        }
+        if let Some(ends_at) = self.ends_at() {
+            let remaining = ends_at - timestamp_secs();
+            if remaining > 0 {
+                tokio::time::sleep(Duration::from_secs(remaining as u64)).await;
+            }
+            Runtime::instance().node.maintenance_end(&self);
+        }
+    }
+
+    async fn evacuate(&self) {
+        let server_reference = self.server_reference().map(ServerReference::from);
+        crate::server::drain(Duration::from_secs(self.opts.drain_period), |tx| {
+            if self.stopped.load(Ordering::SeqCst) {
+                return false;
+            }
+            //offline sessions ignore this message
+            if tx.unbounded_send(Message::Evacuate(server_reference.clone())).is_ok() {
+                self.connections_disconnected.fetch_add(1, Ordering::SeqCst);
+            }
+            true
+        })
+        .await;
+        self.finished.store(!self.stopped.load(Ordering::SeqCst), Ordering::SeqCst);
+        log::info!("node maintenance, evacuation finished, {:?}", self.status());
+    }
 }
 
 #[inline]
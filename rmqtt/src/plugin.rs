@@ -1,12 +1,22 @@
 use core::pin::Pin;
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
 use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use dashmap::iter::Iter;
 use dashmap::mapref::one::{Ref, RefMut};
+use serde::de::DeserializeOwned;
+use tokio::sync::{broadcast, Semaphore};
 
-use crate::{MqttError, Result};
+use crate::settings::{Plugins, ReloadReport, RestartPolicy};
+use crate::{MqttError, Result, Runtime};
 
 type DashMap<K, V> = dashmap::DashMap<K, V, ahash::RandomState>;
+type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
 pub type EntryRef<'a> = Ref<'a, String, Entry>;
 pub type EntryRefMut<'a> = RefMut<'a, String, Entry>;
 pub type EntryIter<'a> = Iter<'a, String, Entry, ahash::RandomState, DashMap<String, Entry>>;
@@ -34,6 +44,83 @@ macro_rules! register {
     };
 }
 
+///Defines a plugin that only registers hook handlers. The plugin struct, the register() function and
+///the Plugin and PackageInfo implementations are generated, the handlers are added in init().
+///
+///```ignore
+///define_plugin!(CounterPlugin, [
+///    (Type::ClientConnect, Priority::MAX, CounterHandler::new()),
+///    (Type::MessagePublish, 0, CounterHandler::new()),
+///]);
+///```
+#[macro_export]
+macro_rules! define_plugin {
+    ($plugin:ident, [$(($typ:expr, $priority:expr, $handler:expr)),* $(,)?]) => {
+        #[inline]
+        pub async fn register(
+            runtime: &'static $crate::Runtime,
+            name: &'static str,
+            default_startup: bool,
+            immutable: bool,
+        ) -> $crate::Result<()> {
+            runtime
+                .plugins
+                .register(name, default_startup, immutable, move || -> $crate::plugin::DynPluginResult {
+                    Box::pin(async move {
+                        let register = runtime.extends.hook_mgr().await.register();
+                        Ok(Box::new($plugin { register }) as $crate::plugin::DynPlugin)
+                    })
+                })
+                .await
+        }
+
+        struct $plugin {
+            register: Box<dyn $crate::broker::hook::Register>,
+        }
+
+        impl $crate::plugin::PackageInfo for $plugin {
+            #[inline]
+            fn name(&self) -> &str {
+                env!("CARGO_PKG_NAME")
+            }
+
+            #[inline]
+            fn version(&self) -> &str {
+                env!("CARGO_PKG_VERSION")
+            }
+
+            #[inline]
+            fn descr(&self) -> Option<&str> {
+                option_env!("CARGO_PKG_DESCRIPTION").filter(|descr| !descr.is_empty())
+            }
+        }
+
+        #[$crate::async_trait::async_trait]
+        impl $crate::plugin::Plugin for $plugin {
+            #[inline]
+            async fn init(&mut self) -> $crate::Result<()> {
+                $crate::log::info!("{} init", $crate::plugin::PackageInfo::name(self));
+                $(self.register.add_priority($typ, $priority, Box::new($handler)).await;)*
+                Ok(())
+            }
+
+            #[inline]
+            async fn start(&mut self) -> $crate::Result<()> {
+                $crate::log::info!("{} start", $crate::plugin::PackageInfo::name(self));
+                self.register.start().await;
+                Ok(())
+            }
+
+            #[inline]
+            async fn stop(&mut self) -> $crate::Result<bool> {
+                $crate::log::info!("{} stop", $crate::plugin::PackageInfo::name(self));
+                self.register.stop().await;
+                Ok(true)
+            }
+        }
+    };
+}
+
 #[async_trait]
 pub trait Plugin: PackageInfo + Send + Sync {
     #[inline]
@@ -61,6 +148,8 @@ pub trait Plugin: PackageInfo + Send + Sync {
         Ok(true)
     }
 
+    ///Runtime attributes of the plug-in, operational metrics are reported under the "metrics" key,
+    ///see PluginMetrics::attrs
     #[inline]
     async fn attrs(&self) -> serde_json::Value {
         serde_json::Value::Null
@@ -70,6 +159,463 @@ pub trait Plugin: PackageInfo + Send + Sync {
     async fn send(&self, _msg: serde_json::Value) -> Result<serde_json::Value> {
         Ok(serde_json::Value::Null)
     }
+
+    ///Polled by the manager while the plug-in is started, see plugins.health_check_interval
+    #[inline]
+    async fn health(&self) -> Health {
+        Health::healthy()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Healthy,
+    ///Working with reduced functionality, reported but never restarted
+    Degraded,
+    Unhealthy,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Health {
+    pub status: HealthStatus,
+    pub reason: Option<String>,
+    pub details: serde_json::Value,
+}
+
+impl Health {
+    #[inline]
+    pub fn healthy() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn degraded<R: Into<String>>(reason: R) -> Self {
+        Self { status: HealthStatus::Degraded, reason: Some(reason.into()), ..Default::default() }
+    }
+
+    #[inline]
+    pub fn unhealthy<R: Into<String>>(reason: R) -> Self {
+        Self { status: HealthStatus::Unhealthy, reason: Some(reason.into()), ..Default::default() }
+    }
+
+    #[inline]
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    #[inline]
+    pub fn is_healthy(&self) -> bool {
+        self.status == HealthStatus::Healthy
+    }
+}
+
+///Published on the message bus, topic ALARM_TOPIC, when a started plug-in is not healthy
+#[derive(Debug, Clone)]
+pub struct PluginAlarm {
+    pub name: String,
+    pub health: Health,
+    pub restarted: bool,
+}
+
+///Operational metrics of a plug-in, reported in `Plugin::attrs()` under the "metrics" key and
+///aggregated over the plug-ins of the cluster by the management API
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PluginMetrics {
+    ///Messages or requests waiting to be processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_depth: Option<u64>,
+    ///Retained, stored or queued messages held by the plug-in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheMetrics>,
+    ///Latency of the requests to the backend, such as a database, an HTTP server or a message queue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<LatencyMetrics>,
+    ///Failed requests or dropped messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<u64>,
+    ///Status of the bridges of a bridge plug-in, by bridge name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bridges: Option<BTreeMap<String, BridgeMetrics>>,
+}
+
+impl PluginMetrics {
+    ///Adds the metrics to the attributes, `attrs` is replaced if it is not an object
+    #[inline]
+    pub fn attrs(&self, attrs: serde_json::Value) -> serde_json::Value {
+        let mut attrs = if attrs.is_object() { attrs } else { json!({}) };
+        if let Some(obj) = attrs.as_object_mut() {
+            obj.insert("metrics".into(), json!(self));
+        }
+        attrs
+    }
+
+    ///The metrics in the attributes returned by `Plugin::attrs()`, if any
+    #[inline]
+    pub fn from_attrs(attrs: &serde_json::Value) -> Option<Self> {
+        attrs.get("metrics").and_then(|m| serde_json::from_value(m.clone()).ok())
+    }
+
+    ///Aggregates the metrics of the same plug-in on another node
+    pub fn merge(&mut self, other: &PluginMetrics) {
+        fn add(a: &mut Option<u64>, b: Option<u64>) {
+            if let Some(b) = b {
+                *a = Some(a.unwrap_or_default() + b);
+            }
+        }
+        add(&mut self.queue_depth, other.queue_depth);
+        add(&mut self.messages, other.messages);
+        add(&mut self.errors, other.errors);
+        if let Some(other) = &other.cache {
+            self.cache.get_or_insert_with(CacheMetrics::default).merge(other);
+        }
+        if let Some(other) = &other.latency {
+            self.latency.get_or_insert_with(LatencyMetrics::default).merge(other);
+        }
+        if let Some(other) = &other.bridges {
+            let bridges = self.bridges.get_or_insert_with(BTreeMap::default);
+            for (name, other) in other {
+                bridges.entry(name.clone()).or_default().merge(other);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CacheMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    ///hits / (hits + misses), 0 if there has been no lookup
+    pub hit_rate: f64,
+}
+
+impl CacheMetrics {
+    #[inline]
+    fn merge(&mut self, other: &CacheMetrics) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.hit_rate = hit_rate(self.hits, self.misses);
+    }
+}
+
+#[inline]
+fn hit_rate(hits: u64, misses: u64) -> f64 {
+    let total = hits + misses;
+    if total == 0 {
+        0.0
+    } else {
+        hits as f64 / total as f64
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct LatencyMetrics {
+    pub count: u64,
+    ///Average, in milliseconds
+    pub avg_ms: f64,
+    ///Maximum, in milliseconds
+    pub max_ms: u64,
+}
+
+impl LatencyMetrics {
+    #[inline]
+    fn merge(&mut self, other: &LatencyMetrics) {
+        let count = self.count + other.count;
+        if count > 0 {
+            self.avg_ms =
+                (self.avg_ms * self.count as f64 + other.avg_ms * other.count as f64) / count as f64;
+        }
+        self.count = count;
+        self.max_ms = self.max_ms.max(other.max_ms);
+    }
+}
+
+///Counts the cache hits and misses of a plug-in
+#[derive(Debug, Default)]
+pub struct CacheCounter {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheCounter {
+    #[inline]
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> CacheMetrics {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        CacheMetrics { hits, misses, hit_rate: hit_rate(hits, misses) }
+    }
+}
+
+///Records the latency of the backend requests of a plug-in
+#[derive(Debug, Default)]
+pub struct LatencyRecorder {
+    count: AtomicU64,
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl LatencyRecorder {
+    #[inline]
+    pub fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    ///Records the time taken by the future
+    #[inline]
+    pub async fn time<F: Future>(&self, f: F) -> F::Output {
+        let now = std::time::Instant::now();
+        let output = f.await;
+        self.record(now.elapsed());
+        output
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> LatencyMetrics {
+        let count = self.count.load(Ordering::Relaxed);
+        let sum_us = self.sum_us.load(Ordering::Relaxed);
+        let avg_ms = if count > 0 { sum_us as f64 / count as f64 / 1000.0 } else { 0.0 };
+        LatencyMetrics { count, avg_ms, max_ms: self.max_us.load(Ordering::Relaxed) / 1000 }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct BridgeMetrics {
+    ///Clients of the bridge
+    pub clients: u64,
+    ///Clients connected to the remote system
+    pub connected: u64,
+    ///Connects after the first one, failed or not
+    pub reconnects: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    ///Forwarded messages
+    pub messages: u64,
+    ///Age of the oldest message waiting to be forwarded, in milliseconds
+    pub lag_ms: u64,
+}
+
+impl BridgeMetrics {
+    ///Aggregates the metrics of another client of the bridge, or of the bridge on another node
+    #[inline]
+    pub fn merge(&mut self, other: &BridgeMetrics) {
+        self.clients += other.clients;
+        self.connected += other.connected;
+        self.reconnects += other.reconnects;
+        self.messages += other.messages;
+        self.lag_ms = self.lag_ms.max(other.lag_ms);
+        if other.last_error.is_some() {
+            self.last_error.clone_from(&other.last_error);
+        }
+    }
+}
+
+///Records the status of one client of a bridge, the metrics of a bridge are the merged metrics of
+///its clients
+#[derive(Debug, Default)]
+pub struct BridgeRecorder {
+    connected: AtomicU64,
+    connects: AtomicU64,
+    messages: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    //enqueue time of the messages waiting to be forwarded, oldest first
+    pending: Mutex<VecDeque<Instant>>,
+}
+
+impl BridgeRecorder {
+    ///Called before each connect
+    #[inline]
+    pub fn connecting(&self) {
+        self.connects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn connected(&self) {
+        self.connected.store(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn disconnected(&self) {
+        self.connected.store(0, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn error<E: ToString>(&self, e: E) {
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(e.to_string());
+        }
+    }
+
+    ///A message is queued to be forwarded, must be followed by `dequeued()` in the same order
+    #[inline]
+    pub fn enqueued(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.push_back(Instant::now());
+        }
+    }
+
+    ///The oldest queued message is taken from the queue, sent or dropped
+    #[inline]
+    pub fn dequeued(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.pop_front();
+        }
+    }
+
+    #[inline]
+    pub fn forwarded(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///Age of the oldest message waiting to be forwarded
+    #[inline]
+    pub fn lag(&self) -> Duration {
+        self.pending.lock().ok().and_then(|pending| pending.front().map(|t| t.elapsed())).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> BridgeMetrics {
+        BridgeMetrics {
+            clients: 1,
+            connected: self.connected.load(Ordering::Relaxed),
+            reconnects: self.connects.load(Ordering::Relaxed).saturating_sub(1),
+            last_error: self.last_error.lock().ok().and_then(|e| e.clone()),
+            messages: self.messages.load(Ordering::Relaxed),
+            lag_ms: self.lag().as_millis() as u64,
+        }
+    }
+}
+
+///Coalesces the requests of a plug-in to an external backend, such as an authentication service, so that
+///a reconnect storm does not turn into as many backend requests. Concurrent calls with the same key share
+///the result of one request, and at most `max_concurrency` requests are sent at once, the other calls wait.
+pub struct RequestCoalescer<K: Eq + Hash, V> {
+    inflights: DashMap<K, broadcast::Sender<V>>,
+    limiter: Mutex<Option<Arc<Semaphore>>>,
+    coalesced: AtomicU64,
+    waiting: AtomicU64,
+}
+
+impl<K, V> RequestCoalescer<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    ///`max_concurrency` 0 means unlimited
+    #[inline]
+    pub fn new(max_concurrency: usize) -> Self {
+        Self {
+            inflights: DashMap::default(),
+            limiter: Mutex::new(Self::limiter(max_concurrency)),
+            coalesced: AtomicU64::new(0),
+            waiting: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn limiter(max_concurrency: usize) -> Option<Arc<Semaphore>> {
+        if max_concurrency > 0 {
+            Some(Arc::new(Semaphore::new(max_concurrency)))
+        } else {
+            None
+        }
+    }
+
+    ///Changes the limit after a configuration reload, requests already sent are not counted
+    #[inline]
+    pub fn set_max_concurrency(&self, max_concurrency: usize) {
+        *self.limiter.lock().unwrap() = Self::limiter(max_concurrency);
+    }
+
+    ///Calls that got the result of a request sent by another call
+    #[inline]
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    ///Calls waiting for the concurrency limit
+    #[inline]
+    pub fn waiting(&self) -> u64 {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    ///Returns the result of `f`, or the result of the request with the same key that is already in flight
+    pub async fn call<F: Future<Output = V>>(&self, key: K, f: F) -> V {
+        let rx = match self.inflights.entry(key.clone()) {
+            dashmap::mapref::entry::Entry::Occupied(e) => Some(e.get().subscribe()),
+            dashmap::mapref::entry::Entry::Vacant(e) => {
+                e.insert(broadcast::channel(1).0);
+                None
+            }
+        };
+        if let Some(mut rx) = rx {
+            if let Ok(v) = rx.recv().await {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                return v;
+            }
+            //the call that sent the request was cancelled
+            return self.request(f).await;
+        }
+
+        let inflight = InflightGuard { inflights: &self.inflights, key: Some(key) };
+        let v = self.request(f).await;
+        if let Some(tx) = inflight.remove() {
+            let _ = tx.send(v.clone());
+        }
+        v
+    }
+
+    #[inline]
+    async fn request<F: Future<Output = V>>(&self, f: F) -> V {
+        let limiter = self.limiter.lock().unwrap().clone();
+        let _permit = if let Some(limiter) = limiter {
+            self.waiting.fetch_add(1, Ordering::Relaxed);
+            let permit = limiter.acquire_owned().await;
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+            permit.ok()
+        } else {
+            None
+        };
+        f.await
+    }
+}
+
+//Removes the in-flight request when the call is done or cancelled, the waiting calls are then woken up
+struct InflightGuard<'a, K: Eq + Hash, V> {
+    inflights: &'a DashMap<K, broadcast::Sender<V>>,
+    key: Option<K>,
+}
+
+impl<'a, K: Eq + Hash, V> InflightGuard<'a, K, V> {
+    #[inline]
+    fn remove(mut self) -> Option<broadcast::Sender<V>> {
+        self.key.take().and_then(|key| self.inflights.remove(&key)).map(|(_, tx)| tx)
+    }
+}
+
+impl<'a, K: Eq + Hash, V> Drop for InflightGuard<'a, K, V> {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.inflights.remove(&key);
+        }
+    }
 }
 
 pub trait PackageInfo {
@@ -117,11 +663,26 @@ pub type DynPluginResult = BoxFuture<Result<DynPlugin>>;
 pub type DynPlugin = Box<dyn Plugin>;
 pub type DynPluginFn = Box<dyn PluginFn>;
 
+///Startup phase of a default startup plugin. While the broker is being built, the default startup
+///plugins are started phase by phase, in the order they were registered within a phase.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    ///Before the other plugins, such as the cluster and storage plugins
+    PreStorage,
+    ///After the storage is ready, before the listeners accept connections
+    #[default]
+    PostStorage,
+    ///After the listeners accept connections
+    PostListeners,
+}
+
 pub struct Entry {
     inited: bool,
     active: bool,
     //will reject start, stop, and load config operations
     immutable: bool,
+    phase: Phase,
     plugin: Option<DynPlugin>,
     plugin_f: Option<DynPluginFn>,
 }
@@ -142,12 +703,17 @@ impl Entry {
         self.immutable
     }
 
+    #[inline]
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
     #[inline]
     async fn plugin(&self) -> Result<&dyn Plugin> {
         if let Some(plugin) = &self.plugin {
             Ok(plugin.as_ref())
         } else {
-            Err(MqttError::from("the plug-in is not initialized"))
+            Err(MqttError::Plugin("the plug-in is not initialized".into()))
         }
     }
 
@@ -160,7 +726,7 @@ impl Entry {
         if let Some(plugin) = self.plugin.as_mut() {
             Ok(plugin.as_mut())
         } else {
-            Err(MqttError::from("the plug-in is not initialized"))
+            Err(MqttError::Plugin("the plug-in is not initialized".into()))
         }
     }
 
@@ -180,6 +746,7 @@ impl Entry {
                 inited: self.inited,
                 active: self.active,
                 immutable: self.immutable,
+                phase: self.phase,
                 attrs,
             })
         } else {
@@ -188,6 +755,7 @@ impl Entry {
                 inited: self.inited,
                 active: self.active,
                 immutable: self.immutable,
+                phase: self.phase,
                 ..Default::default()
             })
         }
@@ -207,6 +775,7 @@ pub struct PluginInfo {
     pub inited: bool,
     pub active: bool,
     pub immutable: bool,
+    pub phase: Phase,
     pub attrs: Vec<u8>, //json data
 }
 
@@ -230,6 +799,7 @@ impl PluginInfo {
             "inited": self.inited,
             "active": self.active,
             "immutable": self.immutable,
+            "phase": self.phase,
             "attrs": attrs,
         }))
     }
@@ -237,11 +807,104 @@ impl PluginInfo {
 
 pub struct Manager {
     plugins: DashMap<String, Entry>,
+    bus: MessageBus,
+    //names of the active plugins, in the order they were started
+    started: Mutex<Vec<String>>,
+    //phases set before the plugins are registered
+    phases: DashMap<String, Phase>,
+    //default startup plugins waiting for their phase, in the order they were registered, None when
+    //the plugins are started as they are registered
+    deferred: Mutex<Option<Vec<String>>>,
 }
 
 impl Manager {
     pub(crate) fn new() -> Self {
-        Self { plugins: DashMap::default() }
+        Self {
+            plugins: DashMap::default(),
+            bus: MessageBus::new(),
+            started: Mutex::new(Vec::new()),
+            phases: DashMap::default(),
+            deferred: Mutex::new(None),
+        }
+    }
+
+    #[inline]
+    fn started_add(&self, name: &str) {
+        let mut started = self.started.lock().unwrap();
+        started.retain(|n| n != name);
+        started.push(name.into());
+    }
+
+    #[inline]
+    fn started_remove(&self, name: &str) {
+        self.started.lock().unwrap().retain(|n| n != name);
+    }
+
+    ///Typed message bus between plug-ins
+    #[inline]
+    pub fn bus(&self) -> &MessageBus {
+        &self.bus
+    }
+
+    ///Sets the startup phase of a plugin, called before the plugin is registered. `plugins.phases`
+    ///in the configuration takes precedence.
+    #[inline]
+    pub fn set_phase<N: Into<String>>(&self, name: N, phase: Phase) {
+        self.phases.insert(name.into(), phase);
+    }
+
+    #[inline]
+    fn phase(&self, name: &str) -> Phase {
+        Runtime::instance()
+            .settings
+            .plugins
+            .phases
+            .get(name)
+            .copied()
+            .or_else(|| self.phases.get(name).map(|p| *p))
+            .unwrap_or_default()
+    }
+
+    ///The default startup plugins registered from now on are started by `start_phase`
+    #[inline]
+    pub(crate) fn defer_startups(&self) {
+        self.deferred.lock().unwrap().get_or_insert_with(Vec::new);
+    }
+
+    ///Starts the deferred plugins of the phase and of the earlier phases, in the order they were
+    ///registered. After the last phase, the plugins are started again as they are registered.
+    pub(crate) async fn start_phase(&self, phase: Phase) -> Result<()> {
+        let names = {
+            let mut deferred = self.deferred.lock().unwrap();
+            let names = if let Some(deferred) = deferred.as_mut() {
+                let (names, rest): (Vec<_>, Vec<_>) =
+                    deferred.drain(..).partition(|name| self.phase(name) <= phase);
+                *deferred = rest;
+                names
+            } else {
+                Vec::new()
+            };
+            if phase == Phase::PostListeners {
+                deferred.take();
+            }
+            names
+        };
+        for name in names {
+            log::info!("{} start the plug-in, phase: {:?}", name, phase);
+            let mut entry = if let Some(entry) = self.plugins.get_mut(&name) {
+                entry
+            } else {
+                continue;
+            };
+            let plugin = entry.plugin_mut().await?;
+            plugin.init().await?;
+            plugin.start().await?;
+            entry.inited = true;
+            entry.active = true;
+            drop(entry);
+            self.started_add(&name);
+        }
+        Ok(())
     }
 
     ///Register a Plugin
@@ -253,13 +916,27 @@ impl Manager {
         plugin_f: F,
     ) -> Result<()> {
         let name = name.into();
+        let phase = self.phase(&name);
 
         if let Some((_, mut entry)) = self.plugins.remove(&name) {
             if entry.active {
                 entry.plugin_mut().await?.stop().await?;
             }
+            self.started_remove(&name);
         }
 
+        let default_startup = if default_startup {
+            if let Some(deferred) = self.deferred.lock().unwrap().as_mut() {
+                deferred.retain(|n| n != &name);
+                deferred.push(name.clone());
+                false
+            } else {
+                true
+            }
+        } else {
+            false
+        };
+
         let (plugin, plugin_f) = if default_startup {
             let mut plugin = plugin_f().await?;
             plugin.init().await?;
@@ -270,7 +947,11 @@ impl Manager {
             (None, Some(boxed_f))
         };
 
-        let entry = Entry { inited: default_startup, active: default_startup, immutable, plugin, plugin_f };
+        if default_startup {
+            self.started_add(&name);
+        }
+        let entry =
+            Entry { inited: default_startup, active: default_startup, immutable, phase, plugin, plugin_f };
         self.plugins.insert(name, entry);
         Ok(())
     }
@@ -280,7 +961,7 @@ impl Manager {
         if let Some(entry) = self.get(name) {
             entry.plugin().await?.get_config().await
         } else {
-            Err(MqttError::from(format!("{} the plug-in does not exist", name)))
+            Err(MqttError::Plugin(format!("{} the plug-in does not exist", name)))
         }
     }
 
@@ -291,10 +972,137 @@ impl Manager {
                 entry.plugin_mut().await?.load_config().await?;
                 Ok(())
             } else {
-                Err(MqttError::from("the plug-in is not initialized"))
+                Err(MqttError::Plugin("the plug-in is not initialized".into()))
             }
         } else {
-            Err(MqttError::from(format!("{} the plug-in does not exist", name)))
+            Err(MqttError::Plugin(format!("{} the plug-in does not exist", name)))
+        }
+    }
+
+    ///Reload the configuration of the initialized plugins, immutable plugins are skipped
+    pub async fn load_configs(&self, report: &mut ReloadReport) {
+        let names = self
+            .plugins
+            .iter()
+            .filter(|entry| entry.inited && !entry.immutable)
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        for name in names {
+            match self.load_config(&name).await {
+                Ok(()) => report.applied.push(format!("plugins.{}", name)),
+                Err(e) => report.failed.push(format!("{}, {}", name, e)),
+            }
+        }
+    }
+
+    ///Watch the configuration files of the initialized plugins, the configuration of a plugin
+    ///is reloaded when its file is modified. If the new configuration is rejected by the plugin,
+    ///the running configuration is kept.
+    pub fn watch_config(&'static self, settings: &'static Plugins) {
+        if settings.watch_interval.is_zero() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut modifieds: HashMap<String, SystemTime> = HashMap::default();
+            loop {
+                tokio::time::sleep(settings.watch_interval).await;
+                let names = self
+                    .plugins
+                    .iter()
+                    .filter(|entry| entry.inited && !entry.immutable)
+                    .map(|entry| entry.key().clone())
+                    .collect::<Vec<_>>();
+                for name in names {
+                    let modified = if let Some(modified) = settings.config_modified(&name) {
+                        modified
+                    } else {
+                        continue;
+                    };
+                    match modifieds.insert(name.clone(), modified) {
+                        Some(prev) if prev != modified => {
+                            log::info!("{} the configuration file is modified, reload", name);
+                            if let Err(e) = self.load_config(&name).await {
+                                log::warn!("{} reload configuration failed, {}", name, e);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        });
+    }
+
+    ///Return Health
+    pub async fn health(&self, name: &str) -> Result<Health> {
+        if let Some(entry) = self.get(name) {
+            Ok(entry.plugin().await?.health().await)
+        } else {
+            Err(MqttError::Plugin(format!("{} the plug-in does not exist", name)))
+        }
+    }
+
+    ///Polls the health of the started plugins. An unhealthy plugin raises a PluginAlarm and, with
+    ///the on-unhealthy restart policy, is restarted with an exponential backoff between restarts.
+    pub fn watch_health(&'static self, settings: &'static Plugins) {
+        let interval = settings.health_check_interval;
+        if interval.is_zero() {
+            return;
+        }
+        tokio::spawn(async move {
+            //plugin name => (delay of the next restart, restart is not allowed before)
+            let mut backoffs: HashMap<String, (Duration, tokio::time::Instant)> = HashMap::default();
+            loop {
+                tokio::time::sleep(interval).await;
+                let names = self
+                    .plugins
+                    .iter()
+                    .filter(|entry| entry.active && !entry.immutable)
+                    .map(|entry| entry.key().clone())
+                    .collect::<Vec<_>>();
+                for name in names {
+                    let health = match self.health(&name).await {
+                        Ok(health) => health,
+                        Err(e) => Health::unhealthy(e.to_string()),
+                    };
+                    if health.is_healthy() {
+                        if backoffs.remove(&name).is_some() {
+                            log::info!("{} the plug-in is healthy again", name);
+                        }
+                        continue;
+                    }
+
+                    let now = tokio::time::Instant::now();
+                    let (backoff, not_before) = backoffs.entry(name.clone()).or_insert((interval, now));
+                    let restart = settings.restart_policy == RestartPolicy::OnUnhealthy
+                        && health.status == HealthStatus::Unhealthy
+                        && now >= *not_before;
+                    log::error!(
+                        "{} the plug-in is {:?}, reason: {:?}, restart: {}",
+                        name,
+                        health.status,
+                        health.reason,
+                        restart
+                    );
+                    let alarm = PluginAlarm { name: name.clone(), health, restarted: restart };
+                    self.bus.publish(BusMessage::new(ALARM_TOPIC, alarm));
+                    if restart {
+                        *not_before = now + *backoff;
+                        *backoff = (*backoff * 2).min(settings.restart_backoff_max);
+                        if let Err(e) = self.restart(&name).await {
+                            log::error!("{} restart failed, {}", name, e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    #[inline]
+    async fn restart(&self, name: &str) -> Result<()> {
+        if self.stop(name).await? {
+            self.start(name).await
+        } else {
+            Err(MqttError::Plugin(format!("{} the plug-in refused to stop", name)))
         }
     }
 
@@ -308,10 +1116,11 @@ impl Manager {
             if !entry.active {
                 entry.plugin_mut().await?.start().await?;
                 entry.active = true;
+                self.started_add(name);
             }
             Ok(())
         } else {
-            Err(MqttError::from(format!("{} the plug-in does not exist", name)))
+            Err(MqttError::Plugin(format!("{} the plug-in does not exist", name)))
         }
     }
 
@@ -321,15 +1130,47 @@ impl Manager {
             if entry.active {
                 let stopped = entry.plugin_mut().await?.stop().await?;
                 entry.active = !stopped;
+                if stopped {
+                    self.started_remove(name);
+                }
                 Ok(stopped)
             } else {
-                Err(MqttError::from(format!("{} the plug-in is not started", name)))
+                Err(MqttError::Plugin(format!("{} the plug-in is not started", name)))
             }
         } else {
-            Err(MqttError::from(format!("{} the plug-in does not exist", name)))
+            Err(MqttError::Plugin(format!("{} the plug-in does not exist", name)))
         }
     }
 
+    ///Stop all active plugins in the reverse order they were started, immutable plugins included.
+    ///Used when the broker shuts down.
+    pub async fn stop_all(&self) {
+        let names = self.started.lock().unwrap().clone();
+        for name in names.iter().rev() {
+            let mut entry = if let Some(entry) = self.plugins.get_mut(name) {
+                entry
+            } else {
+                continue;
+            };
+            if !entry.active {
+                continue;
+            }
+            let res = match entry.plugin_mut().await {
+                Ok(plugin) => plugin.stop().await,
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok(true) => {
+                    entry.active = false;
+                    log::info!("{} the plug-in is stopped", name);
+                }
+                Ok(false) => log::info!("{} the plug-in refused to stop", name),
+                Err(e) => log::warn!("{} stop the plug-in error, {:?}", name, e),
+            }
+        }
+        self.started.lock().unwrap().retain(|n| self.is_active(n));
+    }
+
     ///Plugin is active
     pub fn is_active(&self, name: &str) -> bool {
         if let Some(entry) = self.plugins.get(name) {
@@ -348,7 +1189,7 @@ impl Manager {
     pub fn get_mut(&self, name: &str) -> Result<Option<EntryRefMut>> {
         if let Some(entry) = self.plugins.get_mut(name) {
             if entry.immutable {
-                Err(MqttError::from("the plug-in is immutable"))
+                Err(MqttError::Plugin(format!("{} the plug-in is immutable", name)))
             } else {
                 Ok(Some(entry))
             }
@@ -362,7 +1203,7 @@ impl Manager {
         if let Some(entry) = self.plugins.get(name) {
             entry.plugin().await?.send(msg).await
         } else {
-            Err(MqttError::from(format!("{} the plug-in does not exist", name)))
+            Err(MqttError::Plugin(format!("{} the plug-in does not exist", name)))
         }
     }
 
@@ -371,3 +1212,126 @@ impl Manager {
         self.plugins.iter()
     }
 }
+
+pub type BusReceiver = broadcast::Receiver<BusMessage>;
+
+///Bus topic of PluginAlarm
+pub const ALARM_TOPIC: &str = "$plugin/alarm";
+
+///A message on the plug-in message bus, the payload is any type shared by the publisher and the
+///subscribers, it is not serialized.
+#[derive(Clone)]
+pub struct BusMessage {
+    pub topic: String,
+    payload: Arc<dyn Any + Send + Sync>,
+}
+
+impl BusMessage {
+    #[inline]
+    pub fn new<S: Into<String>, T: Any + Send + Sync>(topic: S, payload: T) -> Self {
+        Self { topic: topic.into(), payload: Arc::new(payload) }
+    }
+
+    #[inline]
+    pub fn is<T: Any>(&self) -> bool {
+        self.payload.is::<T>()
+    }
+
+    #[inline]
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.payload.downcast_ref::<T>()
+    }
+
+    ///Returns the payload, or the message itself if the payload is not of type T
+    #[inline]
+    pub fn downcast<T: Any + Send + Sync>(self) -> std::result::Result<Arc<T>, Self> {
+        let topic = self.topic;
+        self.payload.downcast::<T>().map_err(|payload| Self { topic, payload })
+    }
+}
+
+impl std::fmt::Debug for BusMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BusMessage").field("topic", &self.topic).finish_non_exhaustive()
+    }
+}
+
+///Topic-addressed publish/subscribe bus between plug-ins, complementing the JSON based Plugin::send().
+///
+///Topics are matched exactly, every subscriber of a topic receives each message published to it.
+pub struct MessageBus {
+    topics: DashMap<String, broadcast::Sender<BusMessage>>,
+}
+
+impl MessageBus {
+    const CAPACITY: usize = 1024;
+
+    #[inline]
+    fn new() -> Self {
+        Self { topics: DashMap::default() }
+    }
+
+    ///Subscribe to a topic, a subscriber that falls behind loses the oldest messages (RecvError::Lagged)
+    #[inline]
+    pub fn subscribe<S: Into<String>>(&self, topic: S) -> BusReceiver {
+        self.topics.entry(topic.into()).or_insert_with(|| broadcast::channel(Self::CAPACITY).0).subscribe()
+    }
+
+    ///Publish a message, returns the number of subscribers that received it
+    #[inline]
+    pub fn publish(&self, msg: BusMessage) -> usize {
+        let res = match self.topics.get(&msg.topic) {
+            Some(tx) => tx.send(msg),
+            None => return 0,
+        };
+        match res {
+            Ok(n) => n,
+            Err(broadcast::error::SendError(msg)) => {
+                //all subscribers are gone
+                self.topics.remove_if(&msg.topic, |_, tx| tx.receiver_count() == 0);
+                0
+            }
+        }
+    }
+
+    ///Returns the number of subscribers of the topic
+    #[inline]
+    pub fn subscribers(&self, topic: &str) -> usize {
+        self.topics.get(topic).map(|tx| tx.receiver_count()).unwrap_or_default()
+    }
+}
+
+///Typed access to a namespace of the key-value store (Runtime::extends.kv_store), values are
+///encoded with bincode. The store is in memory unless a storage plugin, such as rmqtt-kv-store,
+///is started.
+#[derive(Clone, Debug)]
+pub struct Store {
+    namespace: String,
+}
+
+impl Store {
+    #[inline]
+    pub fn new<N: Into<String>>(namespace: N) -> Self {
+        Self { namespace: namespace.into() }
+    }
+
+    #[inline]
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let val = Runtime::instance().extends.kv_store().await.get(&self.namespace, key).await?;
+        match val {
+            Some(val) => Ok(Some(bincode::deserialize(&val).map_err(anyhow::Error::new)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[inline]
+    pub async fn set<T: serde::Serialize>(&self, key: &str, val: &T) -> Result<()> {
+        let val = bincode::serialize(val).map_err(anyhow::Error::new)?;
+        Runtime::instance().extends.kv_store().await.set(&self.namespace, key, val).await
+    }
+
+    #[inline]
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        Runtime::instance().extends.kv_store().await.remove(&self.namespace, key).await
+    }
+}
@@ -18,7 +18,8 @@ use crate::{
     extend,
     node::Node,
     plugin,
-    settings::Settings,
+    server::systemd,
+    settings::{ReloadReport, Settings},
     Result,
 };
 
@@ -87,6 +88,21 @@ impl Runtime {
         get_local_stats()
     }
 
+    ///Reloads the broker configuration and the configuration of the initialized plugins
+    pub async fn reload_config(&self) -> Result<ReloadReport> {
+        systemd::reloading();
+        let res = match self.settings.reload() {
+            Ok(mut report) => {
+                self.plugins.load_configs(&mut report).await;
+                log::info!("configuration reloaded, {:?}", report);
+                Ok(report)
+            }
+            Err(e) => Err(e),
+        };
+        systemd::ready();
+        res
+    }
+
     #[inline]
     pub fn is_busy(&self) -> bool {
         if self.settings.node.busy.check_enable {
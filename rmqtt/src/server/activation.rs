@@ -0,0 +1,56 @@
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+//Listening sockets passed to this process, systemd socket activation style
+static INHERITED: Lazy<Mutex<Vec<TcpListener>>> = Lazy::new(|| Mutex::new(inherited()));
+
+///Takes the inherited listening socket bound to the address
+pub(crate) fn take(addr: SocketAddr) -> Option<TcpListener> {
+    let mut listeners = INHERITED.lock().unwrap();
+    let pos = listeners.iter().position(|l| l.local_addr().map(|a| a == addr).unwrap_or_default())?;
+    Some(listeners.remove(pos))
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code)]
+fn inherited() -> Vec<TcpListener> {
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    const LISTEN_FDS_START: i32 = 3;
+
+    //LISTEN_PID is set by the service manager, it is optional when the sockets are passed by a script
+    let pid = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    if pid.map(|pid| pid != std::process::id()).unwrap_or_default() {
+        return Vec::new();
+    }
+    let fds = std::env::var("LISTEN_FDS").ok().and_then(|n| n.parse::<i32>().ok()).unwrap_or_default();
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + fds)
+        .filter_map(|fd| {
+            //the descriptors from LISTEN_FDS_START are owned by this process, see sd_listen_fds(3)
+            let listener = unsafe { TcpListener::from_raw_fd(fd) };
+            match listener.local_addr().and_then(|addr| listener.set_nonblocking(true).map(|_| addr)) {
+                Ok(addr) => {
+                    log::info!("inherited listening socket, fd: {}, addr: {:?}", fd, addr);
+                    Some(listener)
+                }
+                Err(e) => {
+                    log::warn!("fd {} is not a listening socket, {:?}", fd, e);
+                    //not ours to close
+                    let _ = listener.into_raw_fd();
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn inherited() -> Vec<TcpListener> {
+    Vec::new()
+}
@@ -0,0 +1,490 @@
+use std::time::Duration;
+
+use futures::future::ok;
+use ntex::{
+    rt::net::TcpStream,
+    server::rustls::Acceptor,
+    server::rustls::TlsStream,
+    server::Server,
+    {fn_factory_with_config, fn_service, pipeline_factory},
+};
+use ntex_mqtt::{
+    v3::Handshake as HandshakeV3,
+    v5::Handshake as HandshakeV5,
+    {v3, v5, MqttServer},
+};
+
+use crate::broker::{
+    v3::control_message as control_message_v3, v3::handshake as handshake_v3, v3::publish as publish_v3,
+    v5::control_message as control_message_v5, v5::handshake as handshake_v5, v5::publish as publish_v5,
+};
+use crate::settings::listener::{Listener, ListenerKind};
+use crate::{MqttError, Result, Runtime, SessionState};
+
+use super::{activation, tls, ws};
+
+//Uses the inherited listening socket of the address if there is one, otherwise binds the address
+macro_rules! bind_or_inherit {
+    ($builder:expr, $name:expr, $listen_cfg:expr, $factory:expr) => {
+        match activation::take($listen_cfg.addr) {
+            Some(lst) => set_tcp_options(&socket2::SockRef::from(&lst), $listen_cfg)
+                .and_then(|_| $builder.listen($name, lst, $factory)),
+            None => bind($listen_cfg).and_then(|lst| $builder.listen($name, lst, $factory)),
+        }
+    };
+}
+
+//IPv6 sockets have IPV6_V6ONLY set explicitly, the system default is not relied on. Unless
+//`ipv6_only` is set, a listener on "[::]:port" also accepts IPv4 connections.
+fn bind(listen_cfg: &Listener) -> std::io::Result<std::net::TcpListener> {
+    use socket2::{Domain, SockAddr, Socket, Type};
+    let socket = Socket::new(Domain::for_address(listen_cfg.addr), Type::STREAM, None)?;
+    if listen_cfg.addr.is_ipv6() {
+        socket.set_only_v6(listen_cfg.ipv6_only)?;
+    }
+    if let Some(reuseaddr) = listen_cfg.reuseaddr {
+        socket.set_reuse_address(reuseaddr)?;
+    }
+    #[cfg(unix)]
+    if let Some(reuseport) = listen_cfg.reuseport {
+        socket.set_reuse_port(reuseport)?;
+    }
+    set_tcp_options(&socket, listen_cfg)?;
+    socket.bind(&SockAddr::from(listen_cfg.addr))?;
+    socket.listen(listen_cfg.backlog)?;
+    socket.set_nonblocking(true)?;
+    Ok(std::net::TcpListener::from(socket))
+}
+
+//Set on the listening socket, accepted connections inherit these options. The buffer sizes must be
+//set before listen() to take part in the TCP window scale negotiation.
+fn set_tcp_options(socket: &socket2::Socket, listen_cfg: &Listener) -> std::io::Result<()> {
+    if let Some(nodelay) = listen_cfg.tcp_nodelay {
+        socket.set_nodelay(nodelay)?;
+    }
+    if let Some(idle) = listen_cfg.tcp_keepalive {
+        let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "freebsd",
+            target_os = "windows"
+        ))]
+        let keepalive = match listen_cfg.tcp_keepalive_interval {
+            Some(interval) => keepalive.with_interval(interval),
+            None => keepalive,
+        };
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "freebsd"))]
+        let keepalive = match listen_cfg.tcp_keepalive_retries {
+            Some(retries) => keepalive.with_retries(retries),
+            None => keepalive,
+        };
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+    if let Some(size) = listen_cfg.recv_buffer_size {
+        socket.set_recv_buffer_size(size.as_usize())?;
+    }
+    if let Some(size) = listen_cfg.send_buffer_size {
+        socket.set_send_buffer_size(size.as_usize())?;
+    }
+    Ok(())
+}
+
+pub fn listen(name: String, listen_cfg: &Listener) -> Result<Server> {
+    fn _listen(name: &str, listen_cfg: &Listener) -> Result<Server> {
+        let max_inflight = listen_cfg.max_inflight.get() as usize;
+        let handshake_timeout = listen_cfg.handshake_timeout();
+        let max_size = listen_cfg.max_packet_size.as_u32();
+        let builder = Server::build()
+            .backlog(listen_cfg.backlog)
+            .reuseaddr(listen_cfg.reuseaddr)
+            .reuseport(listen_cfg.reuseport);
+        let builder = bind_or_inherit!(builder, name, listen_cfg, move || {
+            MqttServer::new()
+                .v3(v3::MqttServer::new(move |mut handshake: HandshakeV3<TcpStream>| async {
+                    let remote_addr = handshake.io().peer_addr()?;
+                    let local_addr = handshake.io().local_addr()?;
+                    let listen_cfg =
+                        Runtime::instance().settings.listeners.tcp(local_addr.port()).ok_or_else(
+                            || {
+                                log::error!(
+                                    "tcp listener config is not found, local addr is {:?}",
+                                    local_addr
+                                );
+                                MqttError::ListenerConfigError
+                            },
+                        )?;
+                    handshake_v3(listen_cfg, handshake, remote_addr, local_addr).await
+                })
+                // .v3(v3::MqttServer::new(handshake_v3)
+                .inflight(max_inflight)
+                .handshake_timeout(handshake_timeout)
+                .max_size(max_size)
+                .publish(fn_factory_with_config(|session: v3::Session<SessionState>| {
+                    ok::<_, MqttError>(fn_service(move |req| publish_v3(session.clone(), req)))
+                }))
+                .control(fn_factory_with_config(
+                    |session: v3::Session<SessionState>| {
+                        ok::<_, MqttError>(fn_service(move |req| {
+                            control_message_v3(session.clone(), req)
+                        }))
+                    },
+                )))
+                .v5(v5::MqttServer::new(move |mut handshake: HandshakeV5<TcpStream>| async {
+                    let peer_addr = handshake.io().peer_addr()?;
+                    let local_addr = handshake.io().local_addr()?;
+                    let listen_cfg =
+                        Runtime::instance().settings.listeners.tcp(local_addr.port()).ok_or_else(
+                            || {
+                                log::error!(
+                                    "tcp listener config is not found, local addr is {:?}",
+                                    local_addr
+                                );
+                                MqttError::ListenerConfigError
+                            },
+                        )?;
+                    handshake_v5(listen_cfg, handshake, peer_addr, local_addr).await
+                })
+                //v5::MqttServer::new(handshake_v5)
+                .receive_max(max_inflight as u16)
+                .handshake_timeout(handshake_timeout)
+                .max_size(max_size)
+                // .max_qos(max_qos)
+                //.max_topic_alias(max_topic_alias),
+                .publish(fn_factory_with_config(|session: v5::Session<SessionState>| {
+                    ok::<_, MqttError>(fn_service(move |req| publish_v5(session.clone(), req)))
+                }))
+                .control(fn_factory_with_config(
+                    |session: v5::Session<SessionState>| {
+                        ok::<_, MqttError>(fn_service(move |req| {
+                            control_message_v5(session.clone(), req)
+                        }))
+                    },
+                )))
+        })?;
+        Ok(builder.workers(listen_cfg.workers).maxconn(listen_cfg.max_connections / listen_cfg.workers).run())
+    }
+
+    _listen(&format!("tcp: {}", name), listen_cfg).map_err(|e| {
+        log::error!("Listen {:?} failed on {}, {:?}", name, listen_cfg.addr, e);
+        e
+    })
+}
+
+pub fn listen_tls(name: String, listen_cfg: &Listener) -> Result<Server> {
+    fn _listen_tls(name: &str, listen_cfg: &Listener) -> Result<Server> {
+        let tls_acceptor = Acceptor::new(tls::server_config(listen_cfg)?);
+
+        let max_inflight = listen_cfg.max_inflight.get() as usize;
+        let handshake_timeout = listen_cfg.handshake_timeout();
+        let max_size = listen_cfg.max_packet_size.as_u32();
+        let builder = Server::build()
+            .backlog(listen_cfg.backlog)
+            .reuseaddr(listen_cfg.reuseaddr)
+            .reuseport(listen_cfg.reuseport);
+        let builder = bind_or_inherit!(builder, name, listen_cfg, move || {
+            pipeline_factory(tls_acceptor.clone())
+                .map_err(|e| ntex_mqtt::MqttError::Service(MqttError::from(e)))
+                .and_then(
+                    MqttServer::new()
+                        .v3(v3::MqttServer::new(
+                            move |mut handshake: HandshakeV3<TlsStream<TcpStream>>| async {
+                                let (io, _) = handshake.io().get_ref();
+                                let peer_addr = io.peer_addr()?;
+                                let local_addr = io.local_addr()?;
+                                let listen_cfg = Runtime::instance()
+                                    .settings
+                                    .listeners
+                                    .tls(local_addr.port())
+                                    .ok_or_else(|| {
+                                        log::error!(
+                                            "tls listener config is not found, local addr is {:?}",
+                                            local_addr
+                                        );
+                                        MqttError::ListenerConfigError
+                                    })?;
+
+                                handshake_v3(listen_cfg, handshake, peer_addr, local_addr).await
+                            },
+                        )
+                        //.v3(v3::MqttServer::new(handshake_v3)
+                        .inflight(max_inflight)
+                        .handshake_timeout(handshake_timeout)
+                        .max_size(max_size)
+                        .publish(fn_factory_with_config(|session: v3::Session<SessionState>| {
+                            ok::<_, MqttError>(fn_service(move |req| publish_v3(session.clone(), req)))
+                        }))
+                        .control(fn_factory_with_config(
+                            |session: v3::Session<SessionState>| {
+                                ok::<_, MqttError>(fn_service(move |req| {
+                                    control_message_v3(session.clone(), req)
+                                }))
+                            },
+                        )))
+                        .v5(
+                            //v5::MqttServer::new(handshake_v5)
+                            v5::MqttServer::new(
+                                move |mut handshake: HandshakeV5<TlsStream<TcpStream>>| async {
+                                    let (io, _) = handshake.io().get_ref();
+                                    let peer_addr = io.peer_addr()?;
+                                    let local_addr = io.local_addr()?;
+                                    let listen_cfg = Runtime::instance()
+                                        .settings
+                                        .listeners
+                                        .tls(local_addr.port())
+                                        .ok_or_else(|| {
+                                            log::error!(
+                                                "tls listener config is not found, local addr is {:?}",
+                                                local_addr
+                                            );
+                                            MqttError::ListenerConfigError
+                                        })?;
+                                    handshake_v5(listen_cfg, handshake, peer_addr, local_addr).await
+                                },
+                            )
+                            .receive_max(max_inflight as u16)
+                            .handshake_timeout(handshake_timeout)
+                            .max_size(max_size)
+                            // .max_qos(max_qos)
+                            //.max_topic_alias(max_topic_alias)
+                            .publish(fn_factory_with_config(|session: v5::Session<SessionState>| {
+                                ok::<_, MqttError>(fn_service(move |req| {
+                                    publish_v5(session.clone(), req)
+                                }))
+                            }))
+                            .control(fn_factory_with_config(
+                                |session: v5::Session<SessionState>| {
+                                    ok::<_, MqttError>(fn_service(move |req| {
+                                        control_message_v5(session.clone(), req)
+                                    }))
+                                },
+                            )),
+                        ),
+                )
+        })?;
+        Ok(builder.workers(listen_cfg.workers).maxconn(listen_cfg.max_connections / listen_cfg.workers).run())
+    }
+
+    _listen_tls(&format!("tls: {}", name), listen_cfg).map_err(|e| {
+        log::error!(
+            "Listen_tls {:?} failed on {}, cert: {:?}, key: {:?}, {:?}",
+            name,
+            listen_cfg.addr,
+            listen_cfg.cert,
+            listen_cfg.key,
+            e
+        );
+        e
+    })
+}
+
+pub fn listen_ws(name: String, listen_cfg: &Listener) -> Result<Server> {
+    fn _listen_ws(name: &str, listen_cfg: &Listener) -> Result<Server> {
+        let max_inflight = listen_cfg.max_inflight.get() as usize;
+        let handshake_timeout = listen_cfg.handshake_timeout();
+        let max_size = listen_cfg.max_packet_size.as_u32();
+        let port = listen_cfg.addr.port();
+        let builder = Server::build()
+            .backlog(listen_cfg.backlog)
+            .reuseaddr(listen_cfg.reuseaddr)
+            .reuseport(listen_cfg.reuseport);
+        let builder = bind_or_inherit!(builder, name, listen_cfg, move || {
+            pipeline_factory(ws::WSServer::new(
+                Duration::from_secs(handshake_timeout as u64),
+                ListenerKind::Ws,
+                port,
+            ))
+            .and_then(
+                MqttServer::new()
+                    .v3(v3::MqttServer::new(
+                        move |mut handshake: HandshakeV3<ws::WsStream<TcpStream>>| async {
+                            let ws_io = handshake.io();
+                            let io = ws_io.get_ref();
+                            let remote_addr = io.peer_addr()?;
+                            let local_addr = io.local_addr()?;
+                            let listen_cfg = Runtime::instance()
+                                .settings
+                                .listeners
+                                .ws_route(local_addr.port(), ws_io.path())
+                                .ok_or_else(|| {
+                                    log::error!(
+                                        "ws listener config is not found, local addr is {:?}, path is {:?}",
+                                        local_addr,
+                                        ws_io.path()
+                                    );
+                                    MqttError::ListenerConfigError
+                                })?;
+                            handshake_v3(listen_cfg, handshake, remote_addr, local_addr).await
+                        },
+                    )
+                    .inflight(max_inflight)
+                    .handshake_timeout(handshake_timeout)
+                    .max_size(max_size)
+                    .publish(fn_factory_with_config(|session: v3::Session<SessionState>| {
+                        ok::<_, MqttError>(fn_service(move |req| publish_v3(session.clone(), req)))
+                    }))
+                    .control(fn_factory_with_config(
+                        |session: v3::Session<SessionState>| {
+                            ok::<_, MqttError>(fn_service(move |req| {
+                                control_message_v3(session.clone(), req)
+                            }))
+                        },
+                    )))
+                    .v5(v5::MqttServer::new(
+                        move |mut handshake: HandshakeV5<ws::WsStream<TcpStream>>| async {
+                            let ws_io = handshake.io();
+                            let io = ws_io.get_ref();
+                            let remote_addr = io.peer_addr()?;
+                            let local_addr = io.local_addr()?;
+                            let listen_cfg = Runtime::instance()
+                                .settings
+                                .listeners
+                                .ws_route(local_addr.port(), ws_io.path())
+                                .ok_or_else(|| {
+                                    log::error!(
+                                        "ws listener config is not found, local addr is {:?}, path is {:?}",
+                                        local_addr,
+                                        ws_io.path()
+                                    );
+                                    MqttError::ListenerConfigError
+                                })?;
+                            handshake_v5(listen_cfg, handshake, remote_addr, local_addr).await
+                        },
+                    )
+                    .receive_max(max_inflight as u16)
+                    .handshake_timeout(handshake_timeout)
+                    .max_size(max_size)
+                    // .max_qos(max_qos)
+                    //.max_topic_alias(max_topic_alias),
+                    .publish(fn_factory_with_config(|session: v5::Session<SessionState>| {
+                        ok::<_, MqttError>(fn_service(move |req| publish_v5(session.clone(), req)))
+                    }))
+                    .control(fn_factory_with_config(
+                        |session: v5::Session<SessionState>| {
+                            ok::<_, MqttError>(fn_service(move |req| {
+                                control_message_v5(session.clone(), req)
+                            }))
+                        },
+                    ))),
+            )
+        })?;
+        Ok(builder.workers(listen_cfg.workers).maxconn(listen_cfg.max_connections / listen_cfg.workers).run())
+    }
+
+    _listen_ws(&format!("ws: {}", name), listen_cfg).map_err(|e| {
+        log::error!("Listen {:?} failed on {}, {:?}", name, listen_cfg.addr, e);
+        e
+    })
+}
+
+pub fn listen_wss(name: String, listen_cfg: &Listener) -> Result<Server> {
+    fn _listen_wss(name: &str, listen_cfg: &Listener) -> Result<Server> {
+        let tls_acceptor = Acceptor::new(tls::server_config(listen_cfg)?);
+
+        let max_inflight = listen_cfg.max_inflight.get() as usize;
+        let handshake_timeout = listen_cfg.handshake_timeout();
+        let max_size = listen_cfg.max_packet_size.as_u32();
+        let port = listen_cfg.addr.port();
+        let builder = Server::build()
+            .backlog(listen_cfg.backlog)
+            .reuseaddr(listen_cfg.reuseaddr)
+            .reuseport(listen_cfg.reuseport);
+        let builder = bind_or_inherit!(builder, name, listen_cfg, move || {
+            pipeline_factory(tls_acceptor.clone())
+                .map_err(|e| ntex_mqtt::MqttError::Service(MqttError::from(e)))
+                .and_then(ws::WSServer::new(
+                    Duration::from_secs(handshake_timeout as u64),
+                    ListenerKind::Wss,
+                    port,
+                ))
+                .and_then(
+                    MqttServer::new()
+                        .v3(v3::MqttServer::new(
+                            move |mut handshake: HandshakeV3<ws::WsStream<TlsStream<TcpStream>>>| async {
+                                let ws_io = handshake.io();
+                                let (io, _) = ws_io.get_ref().get_ref();
+                                let peer_addr = io.peer_addr()?;
+                                let local_addr = io.local_addr()?;
+                                let listen_cfg = Runtime::instance()
+                                    .settings
+                                    .listeners
+                                    .wss_route(local_addr.port(), ws_io.path())
+                                    .ok_or_else(|| {
+                                        log::error!(
+                                            "wss listener is not found, local addr is {:?}, path is {:?}",
+                                            local_addr,
+                                            ws_io.path()
+                                        );
+                                        MqttError::ListenerConfigError
+                                    })?;
+
+                                handshake_v3(listen_cfg, handshake, peer_addr, local_addr).await
+                            },
+                        )
+                        .inflight(max_inflight)
+                        .handshake_timeout(handshake_timeout)
+                        .max_size(max_size)
+                        .publish(fn_factory_with_config(|session: v3::Session<SessionState>| {
+                            ok::<_, MqttError>(fn_service(move |req| publish_v3(session.clone(), req)))
+                        }))
+                        .control(fn_factory_with_config(
+                            |session: v3::Session<SessionState>| {
+                                ok::<_, MqttError>(fn_service(move |req| {
+                                    control_message_v3(session.clone(), req)
+                                }))
+                            },
+                        )))
+                        .v5(v5::MqttServer::new(
+                            move |mut handshake: HandshakeV5<ws::WsStream<TlsStream<TcpStream>>>| async {
+                                let ws_io = handshake.io();
+                                let (io, _) = ws_io.get_ref().get_ref();
+                                let peer_addr = io.peer_addr()?;
+                                let local_addr = io.local_addr()?;
+                                let listen_cfg = Runtime::instance()
+                                    .settings
+                                    .listeners
+                                    .wss_route(local_addr.port(), ws_io.path())
+                                    .ok_or_else(|| {
+                                        log::error!(
+                                            "wss listener is not found, local addr is {:?}, path is {:?}",
+                                            local_addr,
+                                            ws_io.path()
+                                        );
+                                        MqttError::ListenerConfigError
+                                    })?;
+                                handshake_v5(listen_cfg, handshake, peer_addr, local_addr).await
+                            },
+                        )
+                        .receive_max(max_inflight as u16)
+                        .handshake_timeout(handshake_timeout)
+                        .max_size(max_size)
+                        // .max_qos(max_qos)
+                        //.max_topic_alias(max_topic_alias)
+                        .publish(fn_factory_with_config(|session: v5::Session<SessionState>| {
+                            ok::<_, MqttError>(fn_service(move |req| publish_v5(session.clone(), req)))
+                        }))
+                        .control(fn_factory_with_config(
+                            |session: v5::Session<SessionState>| {
+                                ok::<_, MqttError>(fn_service(move |req| {
+                                    control_message_v5(session.clone(), req)
+                                }))
+                            },
+                        ))),
+                )
+        })?;
+        Ok(builder.workers(listen_cfg.workers).maxconn(listen_cfg.max_connections / listen_cfg.workers).run())
+    }
+
+    _listen_wss(&format!("wss: {}", name), listen_cfg).map_err(|e| {
+        log::error!(
+            "listen_wss {:?} failed on {}, cert: {:?}, key: {:?}, {:?}",
+            name,
+            listen_cfg.addr,
+            listen_cfg.cert,
+            listen_cfg.key,
+            e
+        );
+        e
+    })
+}
@@ -0,0 +1,284 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::LocalBoxFuture;
+use ntex::server::Server;
+use slog_scope::GlobalLoggerGuard;
+use tokio::sync::{mpsc, Notify};
+
+use crate::logger::logger_init;
+use crate::plugin::Phase;
+use crate::settings::listener::{ListenerInner, ListenerKind};
+use crate::settings::{Options, ReloadReport, Settings};
+use crate::{runtime, Message, MqttError, Result, Runtime, Tx};
+
+pub use self::listen::{listen, listen_tls, listen_ws, listen_wss};
+
+mod activation;
+mod listen;
+pub(crate) mod systemd;
+pub mod tls;
+pub mod ws;
+
+type PluginRegister = Box<dyn FnOnce(&'static Runtime) -> LocalBoxFuture<'static, Result<()>>>;
+
+///Builds an embedded broker.
+///
+///```ignore
+///let broker = Broker::builder()
+///    .config_file("rmqtt.toml")
+///    .listener(ListenerKind::Tcp, listener)
+///    .plugin(|rt| Box::pin(rmqtt_acl::register(rt, "rmqtt-acl", true, false)))
+///    .build()
+///    .await?;
+///let handle = broker.handle();
+///broker.run().await?;
+///```
+#[derive(Default)]
+pub struct BrokerBuilder {
+    opts: Options,
+    listeners: Vec<(ListenerKind, ListenerInner)>,
+    plugins: Vec<PluginRegister>,
+}
+
+impl BrokerBuilder {
+    #[inline]
+    pub fn options(mut self, opts: Options) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    ///Configuration file, loaded after the default locations
+    #[inline]
+    pub fn config_file<N: Into<String>>(mut self, name: N) -> Self {
+        self.opts.cfg_name = Some(name.into());
+        self
+    }
+
+    ///Adds a listener in addition to those in the configuration file
+    #[inline]
+    pub fn listener(mut self, kind: ListenerKind, listener: ListenerInner) -> Self {
+        self.listeners.push((kind, listener));
+        self
+    }
+
+    ///Adds a plugin register, called after the runtime is initialized
+    #[inline]
+    pub fn plugin<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&'static Runtime) -> LocalBoxFuture<'static, Result<()>> + 'static,
+    {
+        self.plugins.push(Box::new(f));
+        self
+    }
+
+    ///Initializes the settings, the runtime and the plugins. A process can only build one broker.
+    pub async fn build(self) -> Result<Broker> {
+        //init config
+        Settings::init_with(self.opts, self.listeners)?;
+
+        //init global task executor
+        let runtime = Runtime::init().await?;
+
+        //init log
+        let log_guard = logger_init().map_err(anyhow::Error::new)?;
+
+        let _ = Settings::logs();
+
+        //init scheduler
+        runtime::scheduler_init().await?;
+
+        //start gRPC server
+        runtime.node.start_grpc_server();
+
+        //register plugin, the default startup plugins are started by phase
+        runtime.plugins.defer_startups();
+        for register in self.plugins {
+            register(runtime).await?;
+        }
+        runtime.plugins.start_phase(Phase::PreStorage).await?;
+        runtime.plugins.start_phase(Phase::PostStorage).await?;
+
+        //watch plugin configuration files
+        runtime.plugins.watch_config(&runtime.settings.plugins);
+
+        //poll plugin health
+        runtime.plugins.watch_health(&runtime.settings.plugins);
+
+        //restore statistics, after the storage plugins are started
+        let stats_persist_interval = runtime.settings.node.stats_persist_interval;
+        if !stats_persist_interval.is_zero() {
+            if let Err(e) = runtime.stats.restore().await {
+                log::warn!("restore statistics error, {:?}", e);
+            }
+            runtime.stats.persist_start(stats_persist_interval);
+        }
+
+        //scheduled maintenance
+        if let Some(opts) = runtime.settings.node.maintenance.clone() {
+            if let Err(e) = runtime.node.maintenance_start(opts) {
+                log::warn!("node maintenance not started, {}", e);
+            }
+        }
+
+        //hook, before startup
+        runtime.extends.hook_mgr().await.before_startup().await;
+
+        Ok(Broker { handle: BrokerHandle { shutdown: Arc::new(Notify::new()) }, _log_guard: log_guard })
+    }
+}
+
+pub struct Broker {
+    handle: BrokerHandle,
+    _log_guard: GlobalLoggerGuard,
+}
+
+impl Broker {
+    #[inline]
+    pub fn builder() -> BrokerBuilder {
+        BrokerBuilder::default()
+    }
+
+    #[inline]
+    pub fn handle(&self) -> BrokerHandle {
+        self.handle.clone()
+    }
+
+    ///Starts all listeners, returns when the broker is shut down or a listener fails.
+    ///
+    ///On shutdown, new connections are no longer accepted, the connected clients are disconnected
+    ///within `node.shutdown_drain_period`, queued tasks are flushed and the plugins are stopped
+    ///in the reverse order they were started.
+    pub async fn run(self) -> Result<()> {
+        let listeners = &Runtime::instance().settings.listeners;
+        let mut servers = Vec::new();
+        for (_, listen_cfg) in listeners.tcps.iter() {
+            servers.push(listen(format!("{}/{:?}", &listen_cfg.name, &listen_cfg.addr), listen_cfg)?);
+        }
+        for (_, listen_cfg) in listeners.tlss.iter() {
+            servers.push(listen_tls(format!("{}/{:?}", &listen_cfg.name, &listen_cfg.addr), listen_cfg)?);
+        }
+        for (_, listen_cfg) in listeners.wss.iter() {
+            servers.push(listen_ws(format!("{}/{:?}", &listen_cfg.name, &listen_cfg.addr), listen_cfg)?);
+        }
+        for (_, listen_cfg) in listeners.wsss.iter() {
+            servers.push(listen_wss(format!("{}/{:?}", &listen_cfg.name, &listen_cfg.addr), listen_cfg)?);
+        }
+
+        let (err_tx, mut err_rx) = mpsc::unbounded_channel();
+        for server in servers.iter() {
+            let server = server.clone();
+            let err_tx = err_tx.clone();
+            ntex::rt::spawn(async move {
+                if let Err(e) = server.await {
+                    let _ = err_tx.send(e);
+                }
+            });
+        }
+        drop(err_tx);
+
+        systemd::ready();
+        if let Some(timeout) = systemd::watchdog_timeout() {
+            log::info!("systemd watchdog enabled, timeout: {:?}", timeout);
+            ntex::rt::spawn(systemd::watchdog(timeout));
+        }
+
+        let res = match Runtime::instance().plugins.start_phase(Phase::PostListeners).await {
+            Ok(()) => tokio::select! {
+                _ = self.handle.shutdown.notified() => Ok(()),
+                Some(e) = err_rx.recv() => Err(MqttError::from(e)),
+            },
+            Err(e) => {
+                log::error!("start the plug-ins failed, {}", e);
+                Err(e)
+            }
+        };
+        systemd::stopping();
+
+        for server in servers.iter() {
+            server.pause().await;
+        }
+
+        let runtime = Runtime::instance();
+        drain(runtime.settings.latest_node().shutdown_drain_period, |tx| {
+            //offline sessions ignore this message
+            let _ = tx.unbounded_send(Message::Shutdown);
+            true
+        })
+        .await;
+        if let Err(e) = runtime.exec.flush().await {
+            log::warn!("flush task queue error, {:?}", e);
+        }
+
+        for server in servers {
+            server.stop(true).await;
+        }
+
+        if !runtime.settings.node.stats_persist_interval.is_zero() {
+            if let Err(e) = runtime.stats.persist().await {
+                log::warn!("persist statistics error, {:?}", e);
+            }
+        }
+
+        runtime.plugins.stop_all().await;
+        res
+    }
+}
+
+//Disconnect the connected clients in batches spread over the period, `disconnect` sends the message
+//that closes a connection, returning false stops the draining
+pub(crate) async fn drain<F>(period: Duration, mut disconnect: F)
+where
+    F: FnMut(&Tx) -> bool,
+{
+    const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+    let txs =
+        Runtime::instance().extends.shared().await.iter().filter_map(|entry| entry.tx()).collect::<Vec<_>>();
+    if txs.is_empty() {
+        return;
+    }
+    let batches = (period.as_millis() / BATCH_INTERVAL.as_millis()).max(1) as usize;
+    let batch_size = (txs.len() + batches - 1) / batches;
+    log::info!("drain, disconnect {} clients, batch size: {}", txs.len(), batch_size);
+    for (i, batch) in txs.chunks(batch_size).enumerate() {
+        if i > 0 {
+            tokio::time::sleep(BATCH_INTERVAL).await;
+        }
+        for tx in batch {
+            if !disconnect(tx) {
+                return;
+            }
+        }
+    }
+}
+
+///Handle of a running broker, can be cloned and used from other tasks
+#[derive(Clone)]
+pub struct BrokerHandle {
+    shutdown: Arc<Notify>,
+}
+
+impl BrokerHandle {
+    ///Gracefully shuts down the broker, `Broker::run()` returns once the clients are drained
+    #[inline]
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    ///Reloads the configuration, see `Runtime::reload_config()`
+    #[inline]
+    pub async fn reload(&self) -> Result<ReloadReport> {
+        Runtime::instance().reload_config().await
+    }
+
+    #[inline]
+    pub async fn stats(&self) -> serde_json::Value {
+        Runtime::instance().stats.clone().await.to_json().await
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> serde_json::Value {
+        Runtime::instance().metrics.to_json()
+    }
+}
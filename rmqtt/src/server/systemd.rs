@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+//Service manager notifications, see sd_notify(3). Nothing is sent when NOTIFY_SOCKET is not set,
+//that is when the broker is not started by systemd as a `Type=notify` service
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return,
+    };
+    let res = UnixDatagram::unbound().and_then(|socket| {
+        //a leading '@' is a socket in the abstract namespace
+        #[cfg(target_os = "linux")]
+        if let Some(name) = path.to_str().and_then(|path| path.strip_prefix('@')) {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return socket.send_to_addr(state.as_bytes(), &addr);
+        }
+        socket.send_to(state.as_bytes(), &path)
+    });
+    if let Err(e) = res {
+        log::warn!("notify service manager {:?} error, {:?}", state, e);
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
+
+#[inline]
+pub(crate) fn ready() {
+    notify("READY=1\nSTATUS=Running");
+}
+
+#[inline]
+pub(crate) fn reloading() {
+    notify("RELOADING=1\nSTATUS=Reloading configuration");
+}
+
+#[inline]
+pub(crate) fn stopping() {
+    notify("STOPPING=1\nSTATUS=Draining connections");
+}
+
+///Watchdog timeout of the service, `WatchdogSec=` in the unit file
+pub(crate) fn watchdog_timeout() -> Option<Duration> {
+    //WATCHDOG_PID is optional, when set only that process is expected to send keepalives
+    let pid = std::env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    if pid.map(|pid| pid != std::process::id()).unwrap_or_default() {
+        return None;
+    }
+    match std::env::var("WATCHDOG_USEC").ok().and_then(|usec| usec.parse::<u64>().ok()) {
+        Some(usec) if usec > 0 => Some(Duration::from_micros(usec)),
+        _ => None,
+    }
+}
+
+///Sends a keepalive every half timeout. It runs on the runtime that spawns it, if that runtime
+///stalls the keepalives stop and systemd restarts the service.
+pub(crate) async fn watchdog(timeout: Duration) {
+    let mut keepalive = tokio::time::interval(timeout / 2);
+    loop {
+        keepalive.tick().await;
+        notify("WATCHDOG=1");
+    }
+}
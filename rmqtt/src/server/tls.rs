@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+#[cfg(not(target_os = "windows"))]
+use rustls::crypto::aws_lc_rs as provider;
+#[cfg(target_os = "windows")]
+use rustls::crypto::ring as provider;
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConfig};
+
+use crate::settings::listener::Listener;
+use crate::{MqttError, Result, Runtime};
+
+type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
+
+///Loads a PEM certificate chain and its private key, for use by a `CertResolver`. The key can also be a
+///URI of the installed `KeyLoader`.
+#[inline]
+pub fn load_certified_key(cert: &str, key: &str) -> Result<CertifiedKey> {
+    _load_certified_key(&provider::default_provider(), cert, key)
+}
+
+fn _load_certified_key(provider: &CryptoProvider, cert: &str, key: &str) -> Result<CertifiedKey> {
+    let cert_chain =
+        rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?)).collect::<Result<Vec<_>, _>>()?;
+    let loaded = {
+        let key_loader = Runtime::instance().extends.key_loader();
+        if key_loader.enable() {
+            key_loader.load(key)
+        } else {
+            None
+        }
+    };
+    let signing_key = match loaded {
+        Some(signing_key) => signing_key?,
+        None => {
+            let private_key = rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+                .ok_or_else(|| MqttError::from(format!("no private key is found in {}", key)))?;
+            provider
+                .key_provider
+                .load_private_key(private_key)
+                .map_err(|e| anyhow!(format!("bad private key {}, {}", key, e)))?
+        }
+    };
+    Ok(CertifiedKey::new(cert_chain, signing_key))
+}
+
+pub(crate) fn server_config(listen_cfg: &Listener) -> Result<ServerConfig> {
+    let cert = listen_cfg.cert.as_ref().ok_or::<MqttError>("cert is None".into())?;
+    let key = listen_cfg.key.as_ref().ok_or::<MqttError>("key is None".into())?;
+
+    let provider = Arc::new(provider::default_provider());
+    let default = _load_certified_key(&provider, cert, key)?;
+    let client_auth = if listen_cfg.cross_certificate {
+        let mut client_auth_roots = RootCertStore::empty();
+        for root in default.cert.iter().cloned() {
+            client_auth_roots.add(root).map_err(|e| anyhow!(e))?;
+        }
+        WebPkiClientVerifier::builder_with_provider(client_auth_roots.into(), provider.clone())
+            .build()
+            .map_err(|e| anyhow!(e))?
+    } else {
+        WebPkiClientVerifier::no_client_auth()
+    };
+
+    let mut certs = HashMap::default();
+    for sni in &listen_cfg.sni_certs {
+        let certified_key = _load_certified_key(&provider, &sni.cert, &sni.key)?;
+        certs.insert(sni.server_name.to_ascii_lowercase(), Arc::new(certified_key));
+    }
+    let resolver = SniResolver { local_port: listen_cfg.addr.port(), default: Arc::new(default), certs };
+
+    Ok(ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| anyhow!(e))?
+        .with_client_cert_verifier(client_auth)
+        .with_cert_resolver(Arc::new(resolver)))
+}
+
+//The registered CertResolver is asked first, then the `sni_certs` of the listener. Clients that send
+//no server name, or one that is not found, get the default certificate.
+#[derive(Debug)]
+struct SniResolver {
+    local_port: u16,
+    default: Arc<CertifiedKey>,
+    certs: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl SniResolver {
+    fn lookup(&self, server_name: &str) -> Option<Arc<CertifiedKey>> {
+        if let Some(certified_key) = self.certs.get(server_name) {
+            return Some(certified_key.clone());
+        }
+        let (_, parent) = server_name.split_once('.')?;
+        self.certs.get(&format!("*.{}", parent)).cloned()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        if let Some(server_name) = client_hello.server_name() {
+            let server_name = server_name.to_ascii_lowercase();
+            let resolver = Runtime::instance().extends.cert_resolver();
+            if resolver.enable() {
+                if let Some(certified_key) = resolver.resolve(self.local_port, &server_name) {
+                    return Some(certified_key);
+                }
+            }
+            drop(resolver);
+            if let Some(certified_key) = self.lookup(&server_name) {
+                return Some(certified_key);
+            }
+        }
+        Some(self.default.clone())
+    }
+}
@@ -1,5 +1,7 @@
+use std::cell::Cell;
 use std::future::Future;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::task::{Context, Poll};
 use std::{
     io::{self, ErrorKind},
@@ -7,35 +9,39 @@ use std::{
     time::Duration,
 };
 
-use rmqtt::futures::{ready, FutureExt, Sink, Stream};
-use rmqtt::ntex::codec::ReadBuf;
-use rmqtt::ntex::codec::{AsyncRead, AsyncWrite};
-use rmqtt::ntex::rt::time::{sleep, Sleep};
-use rmqtt::ntex::util::Ready;
-use rmqtt::ntex::{Service, ServiceFactory};
-use rmqtt::ntex_mqtt;
-use rmqtt::pin_project_lite;
-use rmqtt::tokio_tungstenite::accept_hdr_async;
-use rmqtt::tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
-use rmqtt::tokio_tungstenite::tungstenite::Error as WSError;
-use rmqtt::tokio_tungstenite::tungstenite::Message;
-use rmqtt::tokio_tungstenite::WebSocketStream;
-use rmqtt::{log, MqttError};
+use futures::{ready, FutureExt, Sink, Stream};
+use ntex::codec::ReadBuf;
+use ntex::codec::{AsyncRead, AsyncWrite};
+use ntex::rt::time::{sleep, Sleep};
+use ntex::util::Ready;
+use ntex::{Service, ServiceFactory};
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Error as WSError;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::settings::listener::ListenerKind;
+use crate::{MqttError, Runtime};
 
 pub struct WSServer<T> {
     timeout: Duration,
+    kind: ListenerKind,
+    port: u16,
     io: marker::PhantomData<T>,
 }
 
 impl<T: AsyncRead + AsyncWrite> WSServer<T> {
-    pub fn new(timeout: Duration) -> Self {
-        WSServer { timeout, io: marker::PhantomData }
+    ///`kind` and `port` select the listeners whose paths are accepted by the handshake
+    pub fn new(timeout: Duration, kind: ListenerKind, port: u16) -> Self {
+        WSServer { timeout, kind, port, io: marker::PhantomData }
     }
 }
 
 impl<T> Clone for WSServer<T> {
     fn clone(&self) -> Self {
-        Self { timeout: self.timeout, io: marker::PhantomData }
+        Self { timeout: self.timeout, kind: self.kind, port: self.port, io: marker::PhantomData }
     }
 }
 
@@ -50,13 +56,20 @@ impl<T: AsyncRead + AsyncWrite + Unpin + 'static> ServiceFactory for WSServer<T>
     type Future = Ready<Self::Service, Self::InitError>;
 
     fn new_service(&self, _: ()) -> Self::Future {
-        Ready::Ok(WSService { timeout: self.timeout, io: marker::PhantomData })
+        Ready::Ok(WSService {
+            timeout: self.timeout,
+            kind: self.kind,
+            port: self.port,
+            io: marker::PhantomData,
+        })
     }
 }
 
 pub struct WSService<T> {
     io: marker::PhantomData<T>,
     timeout: Duration,
+    kind: ListenerKind,
+    port: u16,
 }
 
 impl<T: AsyncRead + AsyncWrite + Unpin + 'static> Service for WSService<T> {
@@ -72,8 +85,15 @@ impl<T: AsyncRead + AsyncWrite + Unpin + 'static> Service for WSService<T> {
 
     #[inline]
     fn call(&self, req: Self::Request) -> Self::Future {
+        let path = Rc::new(Cell::new(String::new()));
+        let (kind, port, req_path) = (self.kind, self.port, path.clone());
+        let callback = move |req: &Request, response: Response| {
+            req_path.set(req.uri().path().to_owned());
+            on_handshake(kind, port, req, response)
+        };
         WSServiceFut {
-            fut: accept_hdr_async(req, on_handshake).boxed_local(),
+            fut: accept_hdr_async(req, callback).boxed_local(),
+            path,
             delay: if self.timeout == Duration::ZERO { None } else { Some(sleep(self.timeout)) },
         }
     }
@@ -89,6 +109,7 @@ pin_project_lite::pin_project! {
         T: Unpin,
     {
         fut: WebSocketStreamType<T>,
+        path: Rc<Cell<String>>,
         #[pin]
         delay: Option<Sleep>,
     }
@@ -107,14 +128,14 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Future for WSServiceFut<T> {
             }
         }
         match Pin::new(&mut this.fut).poll(cx) {
-            Poll::Ready(Ok(io)) => Poll::Ready(Ok(WsStream(io))),
+            Poll::Ready(Ok(io)) => Poll::Ready(Ok(WsStream(io, this.path.take()))),
             Poll::Ready(Err(e)) => Poll::Ready(Err(ntex_mqtt::MqttError::Service(MqttError::from(e)))),
             Poll::Pending => Poll::Pending,
         }
     }
 }
 
-pub struct WsStream<S>(WebSocketStream<S>);
+pub struct WsStream<S>(WebSocketStream<S>, String);
 
 impl<S> WsStream<S>
 where
@@ -124,6 +145,12 @@ where
     pub fn get_ref(&self) -> &S {
         self.0.get_ref()
     }
+
+    ///Request path of the WebSocket handshake
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.1
+    }
 }
 
 impl<S> AsyncRead for WsStream<S>
@@ -190,8 +217,23 @@ fn to_error(e: WSError) -> io::Error {
 }
 
 #[allow(clippy::result_large_err)]
-fn on_handshake(req: &Request, mut response: Response) -> std::result::Result<Response, ErrorResponse> {
+fn on_handshake(
+    kind: ListenerKind,
+    port: u16,
+    req: &Request,
+    mut response: Response,
+) -> std::result::Result<Response, ErrorResponse> {
     const PROTOCOL_ERROR: &str = "No \"Sec-WebSocket-Protocol: mqtt\" in client request";
+    let listeners = &Runtime::instance().settings.listeners;
+    let routed = match kind {
+        ListenerKind::Wss => listeners.wss_route(port, req.uri().path()),
+        _ => listeners.ws_route(port, req.uri().path()),
+    };
+    if routed.is_none() {
+        let mut resp = ErrorResponse::new(Some(format!("No listener for the path {:?}", req.uri().path())));
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Err(resp);
+    }
     let mqtt_protocol = req
         .headers()
         .get("Sec-WebSocket-Protocol")
@@ -5,13 +5,23 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use regex::Regex;
 use serde::de::{self, Deserialize, Deserializer};
 
-use crate::broker::types::QoS;
+use crate::broker::types::{
+    IpCidr, QoS, SessionAttrs, ATTR_LIMITS_MAX_INFLIGHT, ATTR_LIMITS_MAX_MQUEUE_LEN,
+    ATTR_LIMITS_MAX_QOS_ALLOWED, ATTR_LIMITS_MAX_SESSION_EXPIRY_INTERVAL, ATTR_LIMITS_MQUEUE_RATE_LIMIT,
+    ATTR_LIMITS_SESSION_EXPIRY_INTERVAL, ATTR_USERNAME_CONNECTIONS_MAX,
+    ATTR_USERNAME_CONNECTIONS_MAX_CLUSTER, ATTR_USERNAME_CONNECTIONS_POLICY, MQTT_LEVEL_31, MQTT_LEVEL_311,
+    MQTT_LEVEL_5,
+};
 
-use super::{deserialize_addr, deserialize_duration, to_duration, Bytesize};
+use super::{
+    deserialize_addr, deserialize_duration, deserialize_duration_option, to_duration, Bytesize, ReloadReport,
+};
 
 type HashMap<K, V> = std::collections::HashMap<K, V, ahash::RandomState>;
+type DashMap<K, V> = dashmap::DashMap<K, V, ahash::RandomState>;
 
 type Port = u16;
 
@@ -41,6 +51,16 @@ pub struct Listeners {
     pub wss: HashMap<Port, Listener>,
     #[serde(default, skip)]
     pub wsss: HashMap<Port, Listener>,
+
+    //all WebSocket listeners of a port, they share the socket and are selected by the request path
+    #[serde(default, skip)]
+    ws_routes: HashMap<Port, Vec<Listener>>,
+    #[serde(default, skip)]
+    wss_routes: HashMap<Port, Vec<Listener>>,
+
+    //listeners whose configuration is reloaded at runtime, by port and name
+    #[serde(default, skip)]
+    reloaded: DashMap<(Port, String), Listener>,
 }
 
 impl Listeners {
@@ -60,39 +80,77 @@ impl Listeners {
             }
         }
 
-        for (name, mut inner) in self._wss.drain() {
-            if inner.enable {
-                inner.name = name;
-                self.wss.insert(inner.addr.port(), Listener::new(inner));
-            }
-        }
+        Self::init_routes("ws", &mut self._wss, &mut self.wss, &mut self.ws_routes);
+        Self::init_routes("wss", &mut self._wsss, &mut self.wsss, &mut self.wss_routes);
+    }
 
-        for (name, mut inner) in self._wsss.drain() {
-            if inner.enable {
-                inner.name = name;
-                self.wsss.insert(inner.addr.port(), Listener::new(inner));
+    //WebSocket listeners with the same port share the socket, it is bound with the settings of the first
+    //listener by name
+    fn init_routes(
+        kind: &str,
+        cfgs: &mut HashMap<String, ListenerInner>,
+        bound: &mut HashMap<Port, Listener>,
+        routes: &mut HashMap<Port, Vec<Listener>>,
+    ) {
+        let mut cfgs = cfgs.drain().filter(|(_, inner)| inner.enable).collect::<Vec<_>>();
+        cfgs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, mut inner) in cfgs {
+            inner.name = name;
+            let port = inner.addr.port();
+            let routes = routes.entry(port).or_default();
+            if let Some(l) = routes.iter().find(|l| l.path == inner.path) {
+                log::warn!(
+                    "listener.{}.{} has the same path as listener.{}.{}, it is not used",
+                    kind,
+                    inner.name,
+                    kind,
+                    l.name
+                );
+                continue;
             }
+            let listener = Listener::new(inner);
+            bound.entry(port).or_insert_with(|| listener.clone());
+            routes.push(listener);
         }
     }
 
     #[inline]
     pub fn tcp(&self, port: u16) -> Option<Listener> {
-        self.tcps.get(&port).cloned()
+        self.tcps.get(&port).map(|l| self.latest(l))
     }
 
     #[inline]
     pub fn tls(&self, port: u16) -> Option<Listener> {
-        self.tlss.get(&port).cloned()
+        self.tlss.get(&port).map(|l| self.latest(l))
     }
 
+    ///The WebSocket listener that binds the port, use `ws_route` for the listener of a connection
     #[inline]
     pub fn ws(&self, port: u16) -> Option<Listener> {
-        self.wss.get(&port).cloned()
+        self.wss.get(&port).map(|l| self.latest(l))
     }
 
     #[inline]
     pub fn wss(&self, port: u16) -> Option<Listener> {
-        self.wsss.get(&port).cloned()
+        self.wsss.get(&port).map(|l| self.latest(l))
+    }
+
+    ///The WebSocket listener of the port for the request path, the one with the same `path` or else
+    ///the one without a path. None if the path is not served on the port.
+    #[inline]
+    pub fn ws_route(&self, port: u16, path: &str) -> Option<Listener> {
+        self.route(self.ws_routes.get(&port)?, path)
+    }
+
+    #[inline]
+    pub fn wss_route(&self, port: u16, path: &str) -> Option<Listener> {
+        self.route(self.wss_routes.get(&port)?, path)
+    }
+
+    #[inline]
+    fn route(&self, routes: &[Listener], path: &str) -> Option<Listener> {
+        let routes = || routes.iter().map(|l| self.latest(l));
+        routes().find(|l| l.path.as_deref() == Some(path)).or_else(|| routes().find(|l| l.path.is_none()))
     }
 
     #[inline]
@@ -112,6 +170,72 @@ impl Listeners {
         None
     }
 
+    #[inline]
+    fn latest(&self, listener: &Listener) -> Listener {
+        self.reloaded
+            .get(&(listener.addr.port(), listener.name.clone()))
+            .map(|l| l.value().clone())
+            .unwrap_or_else(|| listener.clone())
+    }
+
+    ///Applies the reloaded listener configurations to new connections. Changes to settings used when
+    ///the listener is bound, and added or removed listeners, require a restart.
+    pub(crate) fn reload(&self, new: &Listeners, report: &mut ReloadReport) {
+        let kinds: [(&str, Vec<&Listener>, Vec<&Listener>); 4] = [
+            ("tcp", self.tcps.values().collect(), new.tcps.values().collect()),
+            ("tls", self.tlss.values().collect(), new.tlss.values().collect()),
+            ("ws", self.ws_routes.values().flatten().collect(), new.ws_routes.values().flatten().collect()),
+            (
+                "wss",
+                self.wss_routes.values().flatten().collect(),
+                new.wss_routes.values().flatten().collect(),
+            ),
+        ];
+        let same = |a: &Listener, b: &Listener| a.addr.port() == b.addr.port() && a.name == b.name;
+        for (kind, listeners, new_listeners) in kinds {
+            for new_l in new_listeners.iter() {
+                let cur = if let Some(l) = listeners.iter().find(|l| same(l, new_l)) {
+                    self.latest(l)
+                } else {
+                    report.restart_required.push(format!("listener.{}.{}", kind, new_l.name));
+                    continue;
+                };
+                let merged = new_l.with_bound_settings(&cur);
+                if format!("{:?}", merged) != format!("{:?}", new_l.inner) {
+                    report.restart_required.push(format!("listener.{}.{}", kind, new_l.name));
+                }
+                if format!("{:?}", merged) != format!("{:?}", cur.inner) {
+                    self.reloaded.insert((cur.addr.port(), cur.name.clone()), Listener::new(merged));
+                    report.applied.push(format!("listener.{}.{}", kind, new_l.name));
+                }
+            }
+            for l in listeners.iter() {
+                if !new_listeners.iter().any(|new_l| same(l, new_l)) {
+                    report.restart_required.push(format!("listener.{}.{}", kind, l.name));
+                }
+            }
+        }
+    }
+
+    ///Adds a listener that is not declared in the configuration file
+    #[inline]
+    pub(crate) fn add(&mut self, kind: ListenerKind, inner: ListenerInner) {
+        let port = inner.addr.port();
+        let listener = Listener::new(inner);
+        match kind {
+            ListenerKind::Tcp => self.tcps.insert(port, listener),
+            ListenerKind::Tls => self.tlss.insert(port, listener),
+            ListenerKind::Ws => {
+                self.ws_routes.insert(port, vec![listener.clone()]);
+                self.wss.insert(port, listener)
+            }
+            ListenerKind::Wss => {
+                self.wss_routes.insert(port, vec![listener.clone()]);
+                self.wsss.insert(port, listener)
+            }
+        };
+    }
+
     #[inline]
     pub(crate) fn set_default(&mut self) {
         let inner = Listener::default();
@@ -119,6 +243,14 @@ impl Listeners {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerKind {
+    Tcp,
+    Tls,
+    Ws,
+    Wss,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Listener {
     inner: Arc<ListenerInner>,
@@ -129,6 +261,66 @@ impl Listener {
     fn new(inner: ListenerInner) -> Self {
         Self { inner: Arc::new(inner) }
     }
+
+    ///The listener with its limits overridden by the `limits.*` session attributes returned by an
+    ///authentication plugin, the listener itself is returned if there are none
+    pub fn with_attrs(&self, attrs: &SessionAttrs) -> Self {
+        const KEYS: [&str; 6] = [
+            ATTR_LIMITS_MAX_INFLIGHT,
+            ATTR_LIMITS_MAX_MQUEUE_LEN,
+            ATTR_LIMITS_MQUEUE_RATE_LIMIT,
+            ATTR_LIMITS_SESSION_EXPIRY_INTERVAL,
+            ATTR_LIMITS_MAX_SESSION_EXPIRY_INTERVAL,
+            ATTR_LIMITS_MAX_QOS_ALLOWED,
+        ];
+        if !KEYS.iter().any(|key| attrs.get(key).is_some()) {
+            return self.clone();
+        }
+
+        let mut inner = self.inner.as_ref().clone();
+        if let Some(max_inflight) = attrs.get_int(ATTR_LIMITS_MAX_INFLIGHT) {
+            match NonZeroU16::new(max_inflight.clamp(0, u16::MAX as i64) as u16) {
+                Some(max_inflight) => inner.max_inflight = max_inflight,
+                None => log::warn!("{}, must be greater than 0, {}", ATTR_LIMITS_MAX_INFLIGHT, max_inflight),
+            }
+        }
+        if let Some(max_mqueue_len) = attrs.get_int(ATTR_LIMITS_MAX_MQUEUE_LEN) {
+            inner.max_mqueue_len = max_mqueue_len.max(0) as usize;
+        }
+        if let Some(rate_limit) = attrs.get_str(ATTR_LIMITS_MQUEUE_RATE_LIMIT) {
+            match ListenerInner::parse_mqueue_rate_limit(rate_limit) {
+                Ok(rate_limit) => inner.mqueue_rate_limit = rate_limit,
+                Err(e) => log::warn!("{}, {}", ATTR_LIMITS_MQUEUE_RATE_LIMIT, e),
+            }
+        }
+        if let Some(interval) = Self::attr_duration(attrs, ATTR_LIMITS_SESSION_EXPIRY_INTERVAL) {
+            inner.session_expiry_interval = interval;
+        }
+        if let Some(interval) = Self::attr_duration(attrs, ATTR_LIMITS_MAX_SESSION_EXPIRY_INTERVAL) {
+            inner.max_session_expiry_interval = interval;
+        }
+        if let Some(qos) = attrs.get_int(ATTR_LIMITS_MAX_QOS_ALLOWED) {
+            match qos {
+                0 => inner.max_qos_allowed = QoS::AtMostOnce,
+                1 => inner.max_qos_allowed = QoS::AtLeastOnce,
+                2 => inner.max_qos_allowed = QoS::ExactlyOnce,
+                _ => {
+                    log::warn!("{}, only values (0,1,2) are supported, {}", ATTR_LIMITS_MAX_QOS_ALLOWED, qos)
+                }
+            }
+        }
+        Self::new(inner)
+    }
+
+    //Seconds as an integer, or a duration string like "2h"
+    #[inline]
+    fn attr_duration(attrs: &SessionAttrs, key: &str) -> Option<Duration> {
+        if let Some(secs) = attrs.get_int(key) {
+            Some(Duration::from_secs(secs.max(0) as u64))
+        } else {
+            attrs.get_str(key).map(to_duration)
+        }
+    }
 }
 
 impl Deref for Listener {
@@ -160,8 +352,24 @@ pub struct ListenerInner {
     pub reuseaddr: Option<bool>,
     #[serde(default = "ListenerInner::reuseport_default")]
     pub reuseport: Option<bool>,
+    #[serde(default)]
+    pub ipv6_only: bool,
+    #[serde(default)]
+    pub tcp_nodelay: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_duration_option")]
+    pub tcp_keepalive: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_duration_option")]
+    pub tcp_keepalive_interval: Option<Duration>,
+    #[serde(default)]
+    pub tcp_keepalive_retries: Option<u32>,
+    #[serde(default)]
+    pub recv_buffer_size: Option<Bytesize>,
+    #[serde(default)]
+    pub send_buffer_size: Option<Bytesize>,
     #[serde(default = "ListenerInner::allow_anonymous_default")]
     pub allow_anonymous: bool,
+    #[serde(default, deserialize_with = "ListenerInner::deserialize_auth_bypass")]
+    pub auth_bypass: Vec<IpCidr>,
     #[serde(default = "ListenerInner::min_keepalive_default")]
     pub min_keepalive: u16,
     #[serde(default = "ListenerInner::max_keepalive_default")]
@@ -170,6 +378,10 @@ pub struct ListenerInner {
     pub allow_zero_keepalive: bool,
     #[serde(default = "ListenerInner::keepalive_backoff_default")]
     pub keepalive_backoff: f32,
+    #[serde(default)]
+    pub keepalive_factor: Option<f32>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub client_alive_interval: Duration,
     #[serde(default = "ListenerInner::max_inflight_default")]
     pub max_inflight: NonZeroU16,
     #[serde(default = "ListenerInner::handshake_timeout_default", deserialize_with = "deserialize_duration")]
@@ -194,6 +406,26 @@ pub struct ListenerInner {
     #[serde(default = "ListenerInner::max_topic_levels_default")]
     pub max_topic_levels: usize,
 
+    #[serde(default)]
+    pub strict_mode: bool,
+    #[serde(default)]
+    pub max_topic_len: usize,
+    ///Maximum length of a topic level in bytes, 0 means unlimited
+    #[serde(default)]
+    pub max_topic_level_len: usize,
+
+    #[serde(default)]
+    pub clientid_policy: ClientIdPolicy,
+
+    #[serde(default)]
+    pub mqtt31: Mqtt31,
+
+    #[serde(default, deserialize_with = "ListenerInner::deserialize_protocol_versions")]
+    pub protocol_versions: Vec<u8>,
+
+    #[serde(default)]
+    pub username_connections: UsernameConnections,
+
     #[serde(default = "ListenerInner::retain_available_default")]
     pub retain_available: bool,
 
@@ -203,12 +435,32 @@ pub struct ListenerInner {
     )]
     pub session_expiry_interval: Duration,
 
+    ///Upper limit of the session expiry interval, including the one requested by MQTT 5.0 clients, 0 means
+    ///unlimited
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_session_expiry_interval: Duration,
+
     #[serde(
         default = "ListenerInner::message_retry_interval_default",
         deserialize_with = "deserialize_duration"
     )]
     pub message_retry_interval: Duration,
 
+    #[serde(default = "ListenerInner::message_retry_backoff_default")]
+    pub message_retry_backoff: f64,
+
+    #[serde(
+        default = "ListenerInner::message_retry_max_interval_default",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub message_retry_max_interval: Duration,
+
+    #[serde(default = "ListenerInner::message_max_retries_default")]
+    pub message_max_retries: usize,
+
+    #[serde(default = "ListenerInner::message_max_retries_disconnect_default")]
+    pub message_max_retries_disconnect: bool,
+
     #[serde(
         default = "ListenerInner::message_expiry_interval_default",
         deserialize_with = "deserialize_duration"
@@ -218,6 +470,9 @@ pub struct ListenerInner {
     #[serde(default = "ListenerInner::max_subscriptions_default")]
     pub max_subscriptions: usize,
 
+    #[serde(default)]
+    pub wildcard_subscription: WildcardSubscription,
+
     #[serde(default = "ListenerInner::shared_subscription_default")]
     pub shared_subscription: bool,
 
@@ -228,11 +483,35 @@ pub struct ListenerInner {
     pub cross_certificate: bool,
     pub cert: Option<String>,
     pub key: Option<String>,
+    #[serde(default)]
+    pub sni_certs: Vec<SniCert>,
 
     #[serde(default)]
     pub limit_subscription: bool,
     #[serde(default)]
     pub delayed_publish: bool,
+
+    #[serde(default)]
+    pub mountpoint: Option<String>,
+
+    ///Request path served by a WebSocket listener, such as "/mqtt". WebSocket listeners can share an
+    ///address with different paths, a listener without a path serves the paths of no other listener.
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default)]
+    pub response_information: Option<String>,
+    #[serde(default)]
+    pub response_topic_enforce: bool,
+    ///MQTT 5.0 clients are asked to use this server when the broker shuts down or the node is in
+    ///maintenance without its own server reference, such as "node2.example.com:1883"
+    #[serde(default)]
+    pub server_reference: Option<String>,
+
+    ///Whether the PUBACK, PUBREC, SUBACK and UNSUBACK of a failure carry the details in a Reason String,
+    ///only sent to MQTT 5.0 clients that request problem information
+    #[serde(default = "ListenerInner::problem_information_default")]
+    pub problem_information: bool,
 }
 
 impl Default for ListenerInner {
@@ -247,12 +526,22 @@ impl Default for ListenerInner {
             max_packet_size: ListenerInner::max_packet_size_default(),
             reuseaddr: ListenerInner::reuseaddr_default(),
             reuseport: ListenerInner::reuseport_default(),
+            ipv6_only: false,
+            tcp_nodelay: None,
+            tcp_keepalive: None,
+            tcp_keepalive_interval: None,
+            tcp_keepalive_retries: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
             backlog: ListenerInner::backlog_default(),
             allow_anonymous: ListenerInner::allow_anonymous_default(),
+            auth_bypass: Vec::new(),
             min_keepalive: ListenerInner::min_keepalive_default(),
             max_keepalive: ListenerInner::max_keepalive_default(),
             allow_zero_keepalive: ListenerInner::allow_zero_keepalive_default(),
             keepalive_backoff: ListenerInner::keepalive_backoff_default(),
+            keepalive_factor: None,
+            client_alive_interval: Duration::ZERO,
             max_inflight: ListenerInner::max_inflight_default(),
             handshake_timeout: ListenerInner::handshake_timeout_default(),
             max_mqueue_len: ListenerInner::max_mqueue_len_default(),
@@ -260,23 +549,70 @@ impl Default for ListenerInner {
             max_clientid_len: ListenerInner::max_clientid_len_default(),
             max_qos_allowed: ListenerInner::max_qos_allowed_default(),
             max_topic_levels: ListenerInner::max_topic_levels_default(),
+            strict_mode: false,
+            max_topic_len: 0,
+            max_topic_level_len: 0,
+            clientid_policy: ClientIdPolicy::default(),
+            mqtt31: Mqtt31::default(),
+            protocol_versions: Vec::new(),
+            username_connections: UsernameConnections::default(),
             retain_available: ListenerInner::retain_available_default(),
             session_expiry_interval: ListenerInner::session_expiry_interval_default(),
+            max_session_expiry_interval: Duration::ZERO,
             message_retry_interval: ListenerInner::message_retry_interval_default(),
+            message_retry_backoff: ListenerInner::message_retry_backoff_default(),
+            message_retry_max_interval: ListenerInner::message_retry_max_interval_default(),
+            message_max_retries: ListenerInner::message_max_retries_default(),
+            message_max_retries_disconnect: ListenerInner::message_max_retries_disconnect_default(),
             message_expiry_interval: ListenerInner::message_expiry_interval_default(),
             max_subscriptions: ListenerInner::max_subscriptions_default(),
+            wildcard_subscription: WildcardSubscription::default(),
             shared_subscription: ListenerInner::shared_subscription_default(),
             max_topic_aliases: 0,
             cross_certificate: ListenerInner::cross_certificate_default(),
             cert: None,
             key: None,
+            sni_certs: Vec::new(),
             limit_subscription: false,
             delayed_publish: false,
+            mountpoint: None,
+            path: None,
+            response_information: None,
+            response_topic_enforce: false,
+            server_reference: None,
+            problem_information: ListenerInner::problem_information_default(),
         }
     }
 }
 
 impl ListenerInner {
+    //A copy of this configuration, with the settings used when the listener is bound taken from `bound`
+    fn with_bound_settings(&self, bound: &ListenerInner) -> ListenerInner {
+        ListenerInner {
+            addr: bound.addr,
+            workers: bound.workers,
+            max_connections: bound.max_connections,
+            max_packet_size: bound.max_packet_size,
+            backlog: bound.backlog,
+            reuseaddr: bound.reuseaddr,
+            reuseport: bound.reuseport,
+            ipv6_only: bound.ipv6_only,
+            tcp_nodelay: bound.tcp_nodelay,
+            tcp_keepalive: bound.tcp_keepalive,
+            tcp_keepalive_interval: bound.tcp_keepalive_interval,
+            tcp_keepalive_retries: bound.tcp_keepalive_retries,
+            recv_buffer_size: bound.recv_buffer_size,
+            send_buffer_size: bound.send_buffer_size,
+            max_inflight: bound.max_inflight,
+            handshake_timeout: bound.handshake_timeout,
+            cross_certificate: bound.cross_certificate,
+            cert: bound.cert.clone(),
+            key: bound.key.clone(),
+            sni_certs: bound.sni_certs.clone(),
+            ..self.clone()
+        }
+    }
+
     fn enable_default() -> bool {
         true
     }
@@ -365,6 +701,10 @@ impl ListenerInner {
         0
     }
     #[inline]
+    fn problem_information_default() -> bool {
+        true
+    }
+    #[inline]
     fn retain_available_default() -> bool {
         false
     }
@@ -377,6 +717,22 @@ impl ListenerInner {
         Duration::from_secs(30)
     }
     #[inline]
+    fn message_retry_backoff_default() -> f64 {
+        1.0
+    }
+    #[inline]
+    fn message_retry_max_interval_default() -> Duration {
+        Duration::ZERO
+    }
+    #[inline]
+    fn message_max_retries_default() -> usize {
+        0
+    }
+    #[inline]
+    fn message_max_retries_disconnect_default() -> bool {
+        false
+    }
+    #[inline]
     fn message_expiry_interval_default() -> Duration {
         Duration::from_secs(300)
     }
@@ -389,6 +745,20 @@ impl ListenerInner {
         true
     }
 
+    ///Whether clients from this address skip authentication, see `auth_bypass`
+    #[inline]
+    pub fn auth_bypassed(&self, remote_addr: Option<SocketAddr>) -> bool {
+        remote_addr
+            .map(|addr| self.auth_bypass.iter().any(|cidr| cidr.contains(addr.ip())))
+            .unwrap_or_default()
+    }
+
+    ///Whether the protocol level is accepted, see `protocol_versions`
+    #[inline]
+    pub fn protocol_version_allowed(&self, level: u8) -> bool {
+        self.protocol_versions.is_empty() || self.protocol_versions.contains(&level)
+    }
+
     #[inline]
     pub fn handshake_timeout(&self) -> u16 {
         let millis = self.handshake_timeout.as_millis();
@@ -405,24 +775,26 @@ impl ListenerInner {
         D: Deserializer<'de>,
     {
         let v = String::deserialize(deserializer)?;
+        Self::parse_mqueue_rate_limit(&v).map_err(de::Error::custom)
+    }
+
+    #[inline]
+    fn parse_mqueue_rate_limit(v: &str) -> Result<(NonZeroU32, Duration), String> {
         let pair: Vec<&str> = v.split(',').collect();
         if pair.len() == 2 {
             let burst = NonZeroU32::from_str(pair[0])
-                .map_err(|e| de::Error::custom(format!("mqueue_rate_limit, burst format error, {:?}", e)))?;
+                .map_err(|e| format!("mqueue_rate_limit, burst format error, {:?}", e))?;
             let replenish_n_per = to_duration(pair[1]);
             if replenish_n_per.as_millis() == 0 {
-                return Err(de::Error::custom(format!(
-                    "mqueue_rate_limit, value format error, {}",
-                    pair.join(",")
-                )));
+                return Err(format!("mqueue_rate_limit, value format error, {}", pair.join(",")));
             }
             Ok((burst, replenish_n_per))
         } else {
-            Err(de::Error::custom(format!("mqueue_rate_limit, value format error, {}", pair.join(","))))
+            Err(format!("mqueue_rate_limit, value format error, {}", pair.join(",")))
         }
     }
     #[inline]
-    fn deserialize_max_qos_allowed<'de, D>(deserializer: D) -> Result<QoS, D::Error>
+    pub(crate) fn deserialize_max_qos_allowed<'de, D>(deserializer: D) -> Result<QoS, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -435,7 +807,247 @@ impl ListenerInner {
         Ok(qos)
     }
     #[inline]
+    fn deserialize_auth_bypass<'de, D>(deserializer: D) -> Result<Vec<IpCidr>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|cidr| cidr.parse::<IpCidr>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(de::Error::custom)
+    }
+    #[inline]
+    fn deserialize_protocol_versions<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|v| match v.trim() {
+                "3.1" => Ok(MQTT_LEVEL_31),
+                "3.1.1" => Ok(MQTT_LEVEL_311),
+                "5" | "5.0" => Ok(MQTT_LEVEL_5),
+                _ => Err(de::Error::custom(format!("invalid protocol version: {}", v))),
+            })
+            .collect()
+    }
+    #[inline]
     fn cross_certificate_default() -> bool {
         false
     }
 }
+
+///Certificate of a TLS listener for the clients that send a matching server name (SNI), `cert` and `key`
+///of the listener are used for the others
+#[derive(Debug, Clone, Deserialize)]
+pub struct SniCert {
+    ///Host name, or a wildcard such as "*.example.com" that matches a single label
+    pub server_name: String,
+    pub cert: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsernameConnectionsPolicy {
+    ///The new connection is refused
+    #[default]
+    Reject,
+    ///The longest connected sessions of the username are kicked to make room for the new connection
+    KickOldest,
+}
+
+impl FromStr for UsernameConnectionsPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(UsernameConnectionsPolicy::Reject),
+            "kick_oldest" => Ok(UsernameConnectionsPolicy::KickOldest),
+            _ => Err(format!("unknown username connections policy {:?}", s)),
+        }
+    }
+}
+
+///Limits of the simultaneous connections with the same username, 0 means unlimited. Clients without a
+///username, and a client reconnecting with the same client ID, are not counted.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct UsernameConnections {
+    ///Connections on this node
+    #[serde(default)]
+    pub max: usize,
+    ///Connections on all nodes of the cluster
+    #[serde(default)]
+    pub max_cluster: usize,
+    #[serde(default)]
+    pub policy: UsernameConnectionsPolicy,
+}
+
+impl UsernameConnections {
+    #[inline]
+    pub fn is_limited(&self) -> bool {
+        self.max > 0 || self.max_cluster > 0
+    }
+
+    ///The limits of the listener, overridden by the `username_connections.*` session attributes returned
+    ///by an authentication plugin
+    pub fn with_attrs(&self, attrs: &SessionAttrs) -> Self {
+        let mut limits = *self;
+        if let Some(max) = attrs.get_int(ATTR_USERNAME_CONNECTIONS_MAX) {
+            limits.max = max.max(0) as usize;
+        }
+        if let Some(max_cluster) = attrs.get_int(ATTR_USERNAME_CONNECTIONS_MAX_CLUSTER) {
+            limits.max_cluster = max_cluster.max(0) as usize;
+        }
+        if let Some(policy) = attrs.get_str(ATTR_USERNAME_CONNECTIONS_POLICY) {
+            match policy.parse() {
+                Ok(policy) => limits.policy = policy,
+                Err(e) => log::warn!("{}", e),
+            }
+        }
+        limits
+    }
+}
+
+///Restrictions of subscriptions with wildcards, a refused subscription fails in the SUBACK and the
+///connection is kept
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct WildcardSubscription {
+    ///Refuse the filter `#` alone, with reason code 0xA2 (Wildcard Subscriptions not supported)
+    #[serde(default)]
+    pub deny_root: bool,
+    ///Maximum number of filters with a wildcard per client, 0 means unlimited. Exceeding it fails with
+    ///reason code 0x97 (Quota exceeded)
+    #[serde(default)]
+    pub max: usize,
+    ///Filters with a multi-level wildcard must be explicitly granted by an ACL plugin, otherwise they
+    ///fail with reason code 0x87 (Not authorized)
+    #[serde(default)]
+    pub multi_level_acl: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientIdAction {
+    #[default]
+    Reject,
+    Rewrite,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mqtt31Action {
+    #[default]
+    Accept,
+    Reject,
+}
+
+///MQTT 3.1 (protocol name MQIsdp) connections of a listener
+#[derive(Debug, Clone, Deserialize)]
+pub struct Mqtt31 {
+    ///With `reject`, the connection is refused with the unacceptable protocol version return code
+    #[serde(default)]
+    pub action: Mqtt31Action,
+    ///MQTT 3.1 limits client IDs to 23 characters, 0 means only `max_clientid_len` applies
+    #[serde(default = "Mqtt31::max_clientid_len_default")]
+    pub max_clientid_len: usize,
+}
+
+impl Default for Mqtt31 {
+    fn default() -> Self {
+        Self { action: Mqtt31Action::default(), max_clientid_len: Mqtt31::max_clientid_len_default() }
+    }
+}
+
+impl Mqtt31 {
+    fn max_clientid_len_default() -> usize {
+        23
+    }
+
+    ///Maximum client ID length of a MQTT 3.1 connection
+    #[inline]
+    pub fn max_clientid_len(&self, max_clientid_len: usize) -> usize {
+        if self.max_clientid_len > 0 {
+            self.max_clientid_len.min(max_clientid_len)
+        } else {
+            max_clientid_len
+        }
+    }
+}
+
+///Client ID policy of a listener, checked before the client.connect hook
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClientIdPolicy {
+    ///Client IDs must match this regular expression
+    #[serde(default, deserialize_with = "ClientIdPolicy::deserialize_regex")]
+    pub regex: Option<Regex>,
+    ///Required prefix, ${username} is replaced with the username of the client
+    #[serde(default)]
+    pub prefix: Option<String>,
+    ///With `rewrite`, a missing prefix is prepended and a client ID longer than `max_clientid_len` is
+    ///truncated instead of being rejected
+    #[serde(default)]
+    pub action: ClientIdAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientIdCheck {
+    Accepted,
+    Rewritten(String),
+    Rejected(String),
+}
+
+impl ClientIdPolicy {
+    ///Checks the client ID, `max_len` is the `max_clientid_len` of the listener, 0 means unlimited
+    pub fn check(&self, client_id: &str, username: Option<&str>, max_len: usize) -> ClientIdCheck {
+        let rewrite = self.action == ClientIdAction::Rewrite;
+        let mut new_client_id = None;
+
+        if let Some(prefix) = self.prefix.as_ref().filter(|p| !p.is_empty()) {
+            let prefix = prefix.replace("${username}", username.unwrap_or_default());
+            if !client_id.starts_with(prefix.as_str()) {
+                if !rewrite {
+                    return ClientIdCheck::Rejected(format!("client_id does not start with {:?}", prefix));
+                }
+                new_client_id = Some(format!("{}{}", prefix, client_id));
+            }
+        }
+
+        let cid = new_client_id.as_deref().unwrap_or(client_id);
+        if max_len > 0 && cid.len() > max_len {
+            if !rewrite {
+                return ClientIdCheck::Rejected("client_id is too long".into());
+            }
+            let mut end = max_len;
+            while !cid.is_char_boundary(end) {
+                end -= 1;
+            }
+            new_client_id = Some(cid[..end].to_owned());
+        }
+
+        let cid = new_client_id.as_deref().unwrap_or(client_id);
+        if let Some(re) = &self.regex {
+            if !re.is_match(cid) {
+                return ClientIdCheck::Rejected(format!("client_id does not match {:?}", re.as_str()));
+            }
+        }
+
+        match new_client_id {
+            Some(cid) => ClientIdCheck::Rewritten(cid),
+            None => ClientIdCheck::Accepted,
+        }
+    }
+
+    #[inline]
+    fn deserialize_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .filter(|re| !re.is_empty())
+            .map(|re| Regex::new(&re))
+            .transpose()
+            .map_err(de::Error::custom)
+    }
+}
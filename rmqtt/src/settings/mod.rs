@@ -1,10 +1,11 @@
 use anyhow::anyhow;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::net::SocketAddr;
 use std::num::NonZeroU32;
 use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use chrono::LocalResult;
@@ -14,10 +15,13 @@ use serde::de::{self, Deserialize, Deserializer};
 use serde::ser::Serializer;
 use serde::Serialize;
 
-use crate::{Addr, MqttError, NodeId, Result};
+use crate::broker::hook::{Priority, Type};
+use crate::node::MaintenanceOptions;
+use crate::plugin::Phase;
+use crate::{Addr, MqttError, NodeId, Publish, QoS, QoSEx, Result};
 
 pub use self::listener::Listener;
-use self::listener::Listeners;
+use self::listener::{ListenerInner, ListenerKind, Listeners};
 use self::log::Log;
 pub use self::options::Options;
 
@@ -49,6 +53,14 @@ pub struct Inner {
     pub mqtt: Mqtt,
     #[serde(default, skip)]
     pub opts: Options,
+    //listeners added when the settings are initialized, kept for reloading
+    #[serde(default, skip)]
+    listeners_added: Vec<(ListenerKind, ListenerInner)>,
+    //the `node` and `mqtt` sections with the settings applied at runtime by reloading
+    #[serde(default, skip)]
+    node_latest: Arc<RwLock<Arc<Node>>>,
+    #[serde(default, skip)]
+    mqtt_latest: Arc<RwLock<Arc<Mqtt>>>,
 }
 
 impl Deref for Settings {
@@ -59,25 +71,43 @@ impl Deref for Settings {
 }
 
 impl Settings {
-    fn new(opts: Options) -> Result<Self> {
+    fn new(opts: Options, listeners: Vec<(ListenerKind, ListenerInner)>) -> Result<Self> {
+        //Precedence, from lowest to highest: defaults, configuration files, environment variables,
+        //command line options
         let mut builder = Config::builder()
             .add_source(File::with_name("/etc/rmqtt/rmqtt").required(false))
             .add_source(File::with_name("/etc/rmqtt").required(false))
-            .add_source(File::with_name("rmqtt").required(false))
-            .add_source(
-                config::Environment::with_prefix("rmqtt")
-                    .try_parsing(true)
-                    .list_separator(" ")
-                    .with_list_parse_key("plugins.default_startups"),
-            );
+            .add_source(File::with_name("rmqtt").required(false));
 
         if let Some(cfg) = opts.cfg_name.as_ref() {
             builder = builder.add_source(File::with_name(cfg).required(false));
         }
 
+        //RMQTT__NODE__ID=2, RMQTT__LISTENER__TCP__EXTERNAL__ADDR="0.0.0.0:1884"
+        builder = builder.add_source(
+            config::Environment::with_prefix("rmqtt")
+                .prefix_separator("__")
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(" ")
+                .with_list_parse_key("plugins.default_startups"),
+        );
+
+        //--set node.id=2 --set log.level=info
+        for item in opts.overrides.iter() {
+            let (key, val) = item.split_once('=').ok_or_else(|| {
+                MqttError::from(format!("invalid option, --set {}, expected key=value", item))
+            })?;
+            builder = builder.set_override(key.trim(), val.trim())?;
+        }
+
         let mut inner: Inner = builder.build()?.try_deserialize()?;
 
         inner.listeners.init();
+        for (kind, listener) in listeners.iter() {
+            inner.listeners.add(*kind, listener.clone());
+        }
+        inner.listeners_added = listeners;
         if inner.listeners.tcps.is_empty() && inner.listeners.tlss.is_empty() {
             //set default
             inner.listeners.set_default();
@@ -93,6 +123,8 @@ impl Settings {
             inner.plugins.default_startups.clone_from(plugins_default_startups)
         }
 
+        inner.node_latest = Arc::new(RwLock::new(Arc::new(inner.node.clone())));
+        inner.mqtt_latest = Arc::new(RwLock::new(Arc::new(inner.mqtt.clone())));
         inner.opts = opts;
         Ok(Self(Arc::new(inner)))
     }
@@ -104,10 +136,124 @@ impl Settings {
 
     #[inline]
     pub fn init(opts: Options) -> Result<&'static Self> {
-        SETTINGS.set(Settings::new(opts)?).map_err(|_| anyhow!("Settings init failed"))?;
+        Self::init_with(opts, Vec::new())
+    }
+
+    ///Initializes the settings, with listeners added in addition to those in the configuration file
+    #[inline]
+    pub fn init_with(opts: Options, listeners: Vec<(ListenerKind, ListenerInner)>) -> Result<&'static Self> {
+        SETTINGS.set(Settings::new(opts, listeners)?).map_err(|_| anyhow!("Settings init failed"))?;
         Ok(SETTINGS.get().ok_or_else(|| anyhow!("Settings init failed"))?)
     }
 
+    ///The `node` section, with the settings applied at runtime by reloading
+    #[inline]
+    pub fn latest_node(&self) -> Arc<Node> {
+        self.node_latest.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    ///The `mqtt` section, with the settings applied at runtime by reloading
+    #[inline]
+    pub fn latest_mqtt(&self) -> Arc<Mqtt> {
+        self.mqtt_latest.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    ///Reloads the configuration sources. The listener settings for new connections, the log level,
+    ///the `mqtt` section and the `node` settings not used at startup are applied at runtime, other
+    ///modified settings are reported as requiring a restart.
+    pub fn reload(&self) -> Result<ReloadReport> {
+        let new = Settings::new(self.opts.clone(), self.listeners_added.clone())?;
+        let mut report = ReloadReport::default();
+
+        self.listeners.reload(&new.listeners, &mut report);
+
+        if crate::logger::level() != new.log.level.inner() {
+            crate::logger::set_level(new.log.level.inner());
+            report.applied.push("log.level".into());
+        }
+        if format!("{:?}", (self.log.to, &self.log.dir, &self.log.file))
+            != format!("{:?}", (new.log.to, &new.log.dir, &new.log.file))
+        {
+            report.restart_required.push("log".into());
+        }
+
+        let (cur, node) = (self.latest_node(), new.node.with_startup_settings(&self.node));
+        let applied = changed_fields(
+            "node",
+            &[
+                ("busy.update_interval", &cur.busy.update_interval, &node.busy.update_interval),
+                ("busy.loadavg", &cur.busy.loadavg, &node.busy.loadavg),
+                ("busy.cpuloadavg", &cur.busy.cpuloadavg, &node.busy.cpuloadavg),
+                ("shutdown_drain_period", &cur.shutdown_drain_period, &node.shutdown_drain_period),
+                ("connect_lock_timeout", &cur.connect_lock_timeout, &node.connect_lock_timeout),
+            ],
+        );
+        if !applied.is_empty() {
+            *self.node_latest.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(node);
+            report.applied.extend(applied);
+        }
+        report.restart_required.extend(changed_fields(
+            "node",
+            &[
+                ("id", &self.node.id, &new.node.id),
+                ("cookie", &self.node.cookie, &new.node.cookie),
+                ("busy.check_enable", &self.node.busy.check_enable, &new.node.busy.check_enable),
+                ("busy.handshaking", &self.node.busy.handshaking, &new.node.busy.handshaking),
+                (
+                    "stats_persist_interval",
+                    &self.node.stats_persist_interval,
+                    &new.node.stats_persist_interval,
+                ),
+                ("maintenance", &self.node.maintenance, &new.node.maintenance),
+            ],
+        ));
+
+        let (cur, mqtt) = (self.latest_mqtt(), &new.mqtt);
+        let applied = changed_fields(
+            "mqtt",
+            &[
+                ("delayed_publish_max", &cur.delayed_publish_max, &mqtt.delayed_publish_max),
+                (
+                    "delayed_publish_immediate",
+                    &cur.delayed_publish_immediate,
+                    &mqtt.delayed_publish_immediate,
+                ),
+                ("dead_letter_topic", &cur.dead_letter_topic, &mqtt.dead_letter_topic),
+                (
+                    "dead_letter_nonsubscribed",
+                    &cur.dead_letter_nonsubscribed,
+                    &mqtt.dead_letter_nonsubscribed,
+                ),
+                (
+                    "shared_subscription_strategy",
+                    &cur.shared_subscription_strategy,
+                    &mqtt.shared_subscription_strategy,
+                ),
+                ("retain_forbidden", &cur.retain_forbidden, &mqtt.retain_forbidden),
+                ("priority", &cur.priority, &mqtt.priority),
+                ("ordered_topics", &cur.ordered_topics, &mqtt.ordered_topics),
+                ("events", &cur.events, &mqtt.events),
+                ("subscribe_qos_caps", &cur.subscribe_qos_caps, &mqtt.subscribe_qos_caps),
+            ],
+        );
+        if !applied.is_empty() {
+            *self.mqtt_latest.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(mqtt.clone());
+            report.applied.extend(applied);
+        }
+
+        let sections = [
+            ("task", format!("{:?}", self.task), format!("{:?}", new.task)),
+            ("rpc", format!("{:?}", self.rpc), format!("{:?}", new.rpc)),
+            ("plugins", format!("{:?}", self.plugins), format!("{:?}", new.plugins)),
+        ];
+        for (name, cur, new) in sections {
+            if cur != new {
+                report.restart_required.push(name.into());
+            }
+        }
+        Ok(report)
+    }
+
     #[inline]
     pub fn logs() -> Result<()> {
         let cfg = Self::instance()?;
@@ -133,6 +279,15 @@ impl Settings {
     }
 }
 
+//Names of the modified fields, the fields are compared by their debug format
+fn changed_fields(section: &str, fields: &[(&str, &dyn fmt::Debug, &dyn fmt::Debug)]) -> Vec<String> {
+    fields
+        .iter()
+        .filter(|(_, cur, new)| format!("{:?}", cur) != format!("{:?}", new))
+        .map(|(name, _, _)| format!("{}.{}", section, name))
+        .collect()
+}
+
 impl fmt::Debug for Settings {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Settings ...")?;
@@ -221,7 +376,18 @@ impl Task {
     }
 }
 
-#[derive(Default, Debug, Clone, Deserialize)]
+///Result of reloading the configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReloadReport {
+    ///Settings applied at runtime
+    pub applied: Vec<String>,
+    ///Modified settings that take effect after a restart
+    pub restart_required: Vec<String>,
+    ///Plugins that failed to reload their configuration, with the error
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Node {
     #[serde(default)]
     pub id: NodeId,
@@ -231,12 +397,61 @@ pub struct Node {
     // pub crash_dump: String,
     #[serde(default)]
     pub busy: Busy,
+    //On shutdown, the connected clients are disconnected in batches within this period
+    #[serde(default = "Node::shutdown_drain_period_default", deserialize_with = "deserialize_duration")]
+    pub shutdown_drain_period: Duration,
+    //The cumulative statistics are saved to the key-value store at this interval, 0 means not saved
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub stats_persist_interval: Duration,
+    //Scheduled maintenance, applied at startup
+    #[serde(default)]
+    pub maintenance: Option<MaintenanceOptions>,
+    //A connect waits up to this long for a connect of the same client id in progress, 0 refuses it at once
+    #[serde(default = "Node::connect_lock_timeout_default", deserialize_with = "deserialize_duration")]
+    pub connect_lock_timeout: Duration,
+}
+
+impl Default for Node {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            id: NodeId::default(),
+            cookie: Self::cookie_default(),
+            busy: Busy::default(),
+            shutdown_drain_period: Self::shutdown_drain_period_default(),
+            stats_persist_interval: Duration::ZERO,
+            maintenance: None,
+            connect_lock_timeout: Self::connect_lock_timeout_default(),
+        }
+    }
 }
 
 impl Node {
+    //A copy of this configuration, with the settings used at startup taken from `started`
+    fn with_startup_settings(&self, started: &Node) -> Node {
+        Node {
+            id: started.id,
+            cookie: started.cookie.clone(),
+            busy: Busy {
+                check_enable: started.busy.check_enable,
+                handshaking: started.busy.handshaking,
+                ..self.busy.clone()
+            },
+            stats_persist_interval: started.stats_persist_interval,
+            maintenance: started.maintenance.clone(),
+            ..self.clone()
+        }
+    }
+
     fn cookie_default() -> String {
         "rmqttsecretcookie".into()
     }
+    fn shutdown_drain_period_default() -> Duration {
+        Duration::from_secs(5)
+    }
+    fn connect_lock_timeout_default() -> Duration {
+        Duration::from_secs(5)
+    }
     // fn crash_dump_default() -> String {
     //     "/var/log/rmqtt/crash.dump".into()
     // }
@@ -360,6 +575,27 @@ pub struct Plugins {
     pub dir: String,
     #[serde(default)]
     pub default_startups: Vec<String>,
+    ///Interval for checking plugin configuration files, a modified file is reloaded, 0 is disabled
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub watch_interval: Duration,
+    ///Interval for polling the health of the started plugins, 0 is disabled
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub health_check_interval: Duration,
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    ///Upper limit of the delay between restarts of an unhealthy plugin, the delay doubles after each restart
+    #[serde(default = "Plugins::restart_backoff_max_default", deserialize_with = "deserialize_duration")]
+    pub restart_backoff_max: Duration,
+    ///Disable a hook handler after it panics, otherwise the panic is only logged and counted
+    #[serde(default)]
+    pub disable_panicked_handler: bool,
+    ///Priorities of the plugin hook handlers, overriding those set by the plugins,
+    ///plugin name -> hook type ("default" for all types) -> priority
+    #[serde(default)]
+    pub hook_priorities: BTreeMap<String, BTreeMap<String, Priority>>,
+    ///Startup phases of the default startup plugins, overriding those set when they are registered
+    #[serde(default)]
+    pub phases: BTreeMap<String, Phase>,
 }
 
 impl Plugins {
@@ -367,6 +603,27 @@ impl Plugins {
         "./plugins/".into()
     }
 
+    fn restart_backoff_max_default() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    ///The configured priority of a hook handler. The plugin is identified by the crate name of the handler
+    ///type, such as "rmqtt_auth_http::AuthHandler" for "rmqtt-auth-http".
+    pub fn hook_priority(&self, handler_name: &str, typ: Type) -> Option<Priority> {
+        if self.hook_priorities.is_empty() {
+            return None;
+        }
+        let krate = handler_name.split("::").next().unwrap_or_default().replace('_', "-");
+        let priorities = self.hook_priorities.get(&krate)?;
+        priorities.get(typ.as_str()).or_else(|| priorities.get("default")).copied()
+    }
+
+    ///Modification time of the plugin configuration file
+    pub fn config_modified(&self, name: &str) -> Option<std::time::SystemTime> {
+        let dir = self.dir.trim_end_matches(['/', '\\']);
+        std::fs::metadata(format!("{}/{}.toml", dir, name)).and_then(|m| m.modified()).ok()
+    }
+
     pub fn load_config<'de, T: serde::Deserialize<'de>>(&self, name: &str) -> Result<T> {
         let (cfg, _) = self.load_config_with_required(name, true, &[])?;
         Ok(cfg)
@@ -428,16 +685,46 @@ impl Plugins {
 
         let s = builder.build()?;
         let count = s.collect()?.len();
-        Ok((s.try_deserialize::<T>()?, count == 0))
+        //the plugin configuration type is the schema, a mismatched field rejects the whole configuration
+        let cfg = s
+            .try_deserialize::<T>()
+            .map_err(|e| MqttError::from(format!("invalid configuration of plugin '{}', {}", name, e)))?;
+        Ok((cfg, count == 0))
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    ///Unhealthy plugins are only reported
+    #[default]
+    Never,
+    ///Unhealthy plugins are stopped and started again
+    OnUnhealthy,
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct Mqtt {
     #[serde(default = "Mqtt::delayed_publish_max_default")]
     pub delayed_publish_max: usize,
     #[serde(default = "Mqtt::delayed_publish_immediate_default")]
     pub delayed_publish_immediate: bool,
+    #[serde(default)]
+    pub dead_letter_topic: Option<String>,
+    #[serde(default)]
+    pub dead_letter_nonsubscribed: bool,
+    #[serde(default)]
+    pub shared_subscription_strategy: SharedSubscriptionStrategy,
+    #[serde(default)]
+    pub retain_forbidden: Vec<RetainForbidden>,
+    #[serde(default)]
+    pub priority: MessagePriority,
+    #[serde(default)]
+    pub ordered_topics: Vec<String>,
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    #[serde(default)]
+    pub subscribe_qos_caps: Vec<SubscribeQoSCap>,
 }
 
 impl Mqtt {
@@ -448,6 +735,137 @@ impl Mqtt {
     fn delayed_publish_immediate_default() -> bool {
         true
     }
+
+    ///Whether messages of the topic are delivered strictly in order, see `ordered_topics`
+    #[inline]
+    pub fn is_ordered(&self, topic: &str) -> bool {
+        self.ordered_topics.iter().any(|prefix| topic.starts_with(prefix.as_str()))
+    }
+
+    ///The action of the first `retain_forbidden` rule whose prefix the topic starts with
+    #[inline]
+    pub fn retain_forbidden(&self, topic: &str) -> Option<RetainForbiddenAction> {
+        self.retain_forbidden.iter().find(|r| topic.starts_with(r.prefix.as_str())).map(|r| r.action)
+    }
+
+    ///The lowest QoS of the `subscribe_qos_caps` rules whose filter covers the topic filter
+    #[inline]
+    pub fn subscribe_qos_cap(&self, topic_filter: &str) -> Option<QoS> {
+        self.subscribe_qos_caps
+            .iter()
+            .filter(|c| SubscribeQoSCap::covers(&c.filter, topic_filter))
+            .map(|c| c.qos)
+            .reduce(|a, b| a.less_value(b))
+    }
+}
+
+///The QoS granted to subscriptions of topic filters covered by `filter` is at most `qos`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubscribeQoSCap {
+    pub filter: String,
+    #[serde(deserialize_with = "ListenerInner::deserialize_max_qos_allowed")]
+    pub qos: QoS,
+}
+
+impl SubscribeQoSCap {
+    ///Whether every topic matched by `topic_filter` is also matched by `filter`
+    fn covers(filter: &str, topic_filter: &str) -> bool {
+        let mut levels = topic_filter.split('/');
+        for f in filter.split('/') {
+            match (f, levels.next()) {
+                ("#", _) => return true,
+                (_, None) | (_, Some("#")) => return false,
+                ("+", Some(_)) => {}
+                (f, Some(l)) if f == l => {}
+                _ => return false,
+            }
+        }
+        levels.next().is_none()
+    }
+}
+
+///Topics starting with `prefix` may not carry the retain flag
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RetainForbidden {
+    pub prefix: String,
+    #[serde(default)]
+    pub action: RetainForbiddenAction,
+}
+
+///High priority messages are queued in a lane of the session message queue that is delivered before
+///normal messages
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MessagePriority {
+    ///Topic prefixes of high priority messages
+    #[serde(default)]
+    pub topics: Vec<String>,
+    ///MQTT 5.0 messages with this user property set to "high" are high priority
+    #[serde(default)]
+    pub user_property: Option<String>,
+}
+
+impl MessagePriority {
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        !self.topics.is_empty() || self.user_property.is_some()
+    }
+
+    #[inline]
+    pub fn is_high(&self, publish: &Publish) -> bool {
+        if self.topics.iter().any(|prefix| publish.topic.starts_with(prefix.as_str())) {
+            return true;
+        }
+        if let Some(name) = self.user_property.as_deref() {
+            return publish
+                .properties
+                .user_properties
+                .iter()
+                .any(|(k, v)| &k[..] == name && v.eq_ignore_ascii_case("high"));
+        }
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetainForbiddenAction {
+    ///The message is dropped
+    #[default]
+    Reject,
+    ///The retain flag is cleared and the message is forwarded as a normal message
+    Strip,
+}
+
+///Broker events that are published to the `$events/{event}` topics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ClientConnected,
+    ClientDisconnected,
+    SessionSubscribed,
+    SessionUnsubscribed,
+}
+
+impl EventKind {
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::ClientConnected => "client_connected",
+            EventKind::ClientDisconnected => "client_disconnected",
+            EventKind::SessionSubscribed => "session_subscribed",
+            EventKind::SessionUnsubscribed => "session_unsubscribed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SharedSubscriptionStrategy {
+    ///Any online member of the group, wherever it is connected
+    #[default]
+    Random,
+    ///Online members connected to the publishing node first, members on other nodes only when none is online
+    Local,
 }
 
 const BYTESIZE_K: usize = 1024;
@@ -634,6 +1052,32 @@ pub fn to_duration(text: &str) -> Duration {
     Duration::from_millis(ms)
 }
 
+///Like `to_duration`, but an empty or malformed duration, or one that overflows, is an error
+pub fn try_to_duration(text: &str) -> Result<Duration> {
+    let invalid = || MqttError::from(format!("invalid duration, {:?}", text));
+    let lower = text.to_lowercase().replace("ms", "Y");
+    if lower.is_empty() {
+        return Err(invalid());
+    }
+    let mut ms: u64 = 0;
+    for x in lower.split_inclusive(['s', 'm', 'h', 'd', 'w', 'f', 'Y']) {
+        let mut chars = x.chars();
+        let factor = match chars.nth_back(0) {
+            Some('Y') => 1,
+            Some('s') => 1000,
+            Some('m') => 60000,
+            Some('h') => 3600000,
+            Some('d') => 86400000,
+            Some('w') => 604800000,
+            Some('f') => 1209600000,
+            _ => return Err(invalid()),
+        };
+        let v = chars.as_str().parse::<u64>().map_err(|_| invalid())?;
+        ms = v.checked_mul(factor).and_then(|v| ms.checked_add(v)).ok_or_else(invalid)?;
+    }
+    Ok(Duration::from_millis(ms))
+}
+
 #[inline]
 pub fn deserialize_addr<'de, D>(deserializer: D) -> Result<SocketAddr, D::Error>
 where
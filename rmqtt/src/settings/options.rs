@@ -30,6 +30,14 @@ pub struct Options {
     ///will be designated as the Leader. Default value: 0
     #[structopt(name = "raft-leader-id", long)]
     pub raft_leader_id: Option<NodeId>,
+
+    ///Overrides a configuration item, can be repeated, --set node.id=2 --set log.level=info
+    #[structopt(name = "set", long, number_of_values = 1)]
+    pub overrides: Vec<String>,
+
+    ///Validates the configuration, prints the effective configuration and exits
+    #[structopt(name = "check-config", long)]
+    pub check_config: bool,
     // ///Node cookie
     // #[structopt(name = "cookie", long)]
     // pub node_cookie: Option<String>,
@@ -0,0 +1,185 @@
+//!Values for unit tests of hook handlers, none of them needs an initialized `Runtime`.
+//!
+//!Parameters that carry a `Session` can only be produced by a running broker, see `TestBroker`.
+//!
+//!```ignore
+//!let register = MockRegister::default();
+//!MyPlugin::add_handlers(&register).await;
+//!
+//!let connect_info = ConnectInfoBuilder::new("c1").username("u1").password("p1").build();
+//!let (proceed, acc) = register.exec(&Parameter::ClientAuthenticate(&connect_info), None).await;
+//!
+//!let p = publish("foo/1", "hello", QoS::AtLeastOnce);
+//!register.exec(&Parameter::MessagePublish(None, from_client("c1"), &p), None).await;
+//!```
+
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use bytestring::ByteString;
+use ntex_mqtt::v3::codec::Connect as ConnectV3;
+use ntex_mqtt::v5::codec::{Connect as ConnectV5, UserProperties};
+use tokio::sync::RwLock;
+
+use crate::broker::hook::{Handler, HookResult, Parameter, Priority, Proceed, Register, ReturnType, Type};
+use crate::broker::types::{ConnectInfo, NodeId};
+use crate::{ClientId, From, Id, Publish, QoS, TopicName, UserName};
+
+///Register that keeps the handlers instead of adding them to the hook manager
+#[derive(Default)]
+pub struct MockRegister {
+    handlers: RwLock<Vec<(Type, (Priority, usize), Box<dyn Handler>)>>,
+}
+
+impl MockRegister {
+    ///Types of the added handlers, in the order they were added
+    pub async fn types(&self) -> Vec<Type> {
+        self.handlers.read().await.iter().map(|(typ, _, _)| *typ).collect()
+    }
+
+    ///Runs the handlers of the parameter type in the same order as the hook manager, higher
+    ///priority first, and returns the final result
+    pub async fn exec(&self, param: &Parameter<'_>, mut acc: Option<HookResult>) -> ReturnType {
+        let typ = param.get_type();
+        let handlers = self.handlers.read().await;
+        let mut type_handlers = handlers.iter().filter(|(t, _, _)| *t == typ).collect::<Vec<_>>();
+        type_handlers.sort_by_key(|(_, key, _)| *key);
+        for (_, _, handler) in type_handlers.into_iter().rev() {
+            let (proceed, new_acc) = handler.hook(param, acc).await;
+            if proceed.is_stop() {
+                return (proceed, new_acc);
+            }
+            acc = new_acc;
+        }
+        (Proceed::Continue, acc)
+    }
+}
+
+#[async_trait]
+impl Register for MockRegister {
+    async fn add_priority(&self, typ: Type, priority: Priority, handler: Box<dyn Handler>) {
+        let mut handlers = self.handlers.write().await;
+        let seq = handlers.len();
+        handlers.push((typ, (priority, seq), handler));
+    }
+}
+
+///Builds the `ConnectInfo` of a client, MQTT 3.1.1 unless `v5()` is called
+pub struct ConnectInfoBuilder {
+    node_id: NodeId,
+    remote_addr: Option<SocketAddr>,
+    client_id: ClientId,
+    username: Option<UserName>,
+    password: Option<Bytes>,
+    keep_alive: u16,
+    clean_start: bool,
+    user_properties: UserProperties,
+    v5: bool,
+}
+
+impl ConnectInfoBuilder {
+    #[inline]
+    pub fn new<C: Into<ClientId>>(client_id: C) -> Self {
+        Self {
+            node_id: 1,
+            remote_addr: None,
+            client_id: client_id.into(),
+            username: None,
+            password: None,
+            keep_alive: 60,
+            clean_start: true,
+            user_properties: UserProperties::default(),
+            v5: false,
+        }
+    }
+
+    #[inline]
+    pub fn node_id(mut self, node_id: NodeId) -> Self {
+        self.node_id = node_id;
+        self
+    }
+
+    #[inline]
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.remote_addr = Some(addr);
+        self
+    }
+
+    #[inline]
+    pub fn username<U: Into<UserName>>(mut self, username: U) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    #[inline]
+    pub fn password<P: Into<Bytes>>(mut self, password: P) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    #[inline]
+    pub fn keep_alive(mut self, keep_alive: u16) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    #[inline]
+    pub fn clean_start(mut self, clean_start: bool) -> Self {
+        self.clean_start = clean_start;
+        self
+    }
+
+    ///MQTT 5.0 only
+    #[inline]
+    pub fn user_property<K: Into<ByteString>, V: Into<ByteString>>(mut self, key: K, val: V) -> Self {
+        self.user_properties.push((key.into(), val.into()));
+        self
+    }
+
+    #[inline]
+    pub fn v5(mut self) -> Self {
+        self.v5 = true;
+        self
+    }
+
+    pub fn build(self) -> ConnectInfo {
+        let id = Id::new(self.node_id, None, self.remote_addr, self.client_id.clone(), self.username.clone());
+        if self.v5 {
+            ConnectInfo::V5(
+                id,
+                Box::new(ConnectV5 {
+                    client_id: self.client_id,
+                    username: self.username,
+                    password: self.password,
+                    keep_alive: self.keep_alive,
+                    clean_start: self.clean_start,
+                    user_properties: self.user_properties,
+                    ..Default::default()
+                }),
+            )
+        } else {
+            ConnectInfo::V3(
+                id,
+                ConnectV3 {
+                    client_id: self.client_id,
+                    username: self.username,
+                    password: self.password,
+                    keep_alive: self.keep_alive,
+                    clean_session: self.clean_start,
+                    ..Default::default()
+                },
+            )
+        }
+    }
+}
+
+///Message received from a client, not retained, without properties
+pub fn publish<T: Into<TopicName>, P: Into<Bytes>>(topic: T, payload: P, qos: QoS) -> Publish {
+    Publish::builder().topic(topic).payload(payload).qos(qos).build()
+}
+
+///Sender of a message published by a client connected to node 1
+#[inline]
+pub fn from_client<C: Into<ClientId>>(client_id: C) -> From {
+    From::from_custom(Id::new(1, None, None, client_id.into(), None))
+}
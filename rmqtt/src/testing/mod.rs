@@ -0,0 +1,308 @@
+//!In-process broker for integration tests, enabled by the `testing` feature.
+//!
+//!```ignore
+//!#[tokio::test]
+//!async fn delivered() -> rmqtt::Result<()> {
+//!    let broker = TestBroker::start()?;
+//!    let recorder = HookRecorder::register(&[Type::ClientConnected, Type::MessageDelivered]).await;
+//!
+//!    //connect a subscriber and a publisher to broker.addr() with any MQTT client ...
+//!
+//!    recorder.wait_for(Type::ClientConnected, |e| e.clientid_is("sub1")).await?;
+//!    let ev = recorder.wait_delivered("sub1", "foo/1").await?;
+//!    assert_eq!(ev.payload.as_deref(), Some(&b"hello"[..]));
+//!    recorder.stop().await;
+//!    Ok(())
+//!}
+//!```
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+use tokio::sync::broadcast;
+
+use crate::broker::hook::{Handler, HookResult, Parameter, Proceed, Register, ReturnType, Type};
+use crate::plugin::PluginFn;
+use crate::server::{Broker, BrokerBuilder, BrokerHandle};
+use crate::settings::listener::{ListenerInner, ListenerKind};
+use crate::settings::Options;
+use crate::{ClientId, MqttError, Result, Runtime, TopicName};
+
+pub mod mock;
+
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+static BROKER: OnceCell<TestBroker> = OnceCell::new();
+
+///Broker running on a background thread, listening for MQTT/TCP on an ephemeral port of 127.0.0.1.
+///The listener allows anonymous clients and retained messages.
+///
+///The settings and the runtime are process wide, so a test binary runs a single broker. Tests of
+///the same binary share it and should use distinct client identifiers and topics.
+pub struct TestBroker {
+    addr: SocketAddr,
+    handle: BrokerHandle,
+}
+
+impl TestBroker {
+    ///Starts the broker, or returns the one already started
+    #[inline]
+    pub fn start() -> Result<&'static TestBroker> {
+        Self::start_with(|builder| builder)
+    }
+
+    ///Like `start()`, `f` can add listeners and plugin registers. It is ignored when the broker
+    ///is already started.
+    pub fn start_with<F>(f: F) -> Result<&'static TestBroker>
+    where
+        F: FnOnce(BrokerBuilder) -> BrokerBuilder + Send + 'static,
+    {
+        BROKER.get_or_try_init(|| Self::spawn(f))
+    }
+
+    fn spawn<F>(f: F) -> Result<TestBroker>
+    where
+        F: FnOnce(BrokerBuilder) -> BrokerBuilder + Send + 'static,
+    {
+        let addr = free_addr()?;
+        let rpc_addr = free_addr()?;
+        let opts = Options {
+            overrides: vec!["log.to=off".into(), format!("rpc.server_addr={}", rpc_addr)],
+            ..Default::default()
+        };
+        let listener = ListenerInner {
+            name: "test".into(),
+            addr,
+            allow_anonymous: true,
+            retain_available: true,
+            ..Default::default()
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::Builder::new().name("rmqtt-test-broker".into()).spawn(move || {
+            ntex::rt::System::new("rmqtt-test-broker").block_on(async move {
+                let builder = Broker::builder().options(opts).listener(ListenerKind::Tcp, listener);
+                let broker = match f(builder).build().await {
+                    Ok(broker) => broker,
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = tx.send(Ok(broker.handle()));
+                if let Err(e) = broker.run().await {
+                    log::error!("test broker run failed, {}", e);
+                }
+            })
+        })?;
+
+        let handle = rx.recv_timeout(START_TIMEOUT).map_err(|e| MqttError::from(e.to_string()))??;
+
+        //The listeners are started by Broker::run(), wait until the port accepts connections
+        let now = Instant::now();
+        while TcpStream::connect(addr).is_err() {
+            if now.elapsed() > START_TIMEOUT {
+                return Err(MqttError::from(format!("test broker is not listening on {}", addr)));
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Ok(TestBroker { addr, handle })
+    }
+
+    ///Address of the MQTT/TCP listener
+    #[inline]
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    #[inline]
+    pub fn handle(&self) -> &BrokerHandle {
+        &self.handle
+    }
+
+    ///Registers and starts a plugin, usually a mock plugin defined in the test
+    pub async fn plugin<F: PluginFn>(&self, name: &str, plugin_f: F) -> Result<()> {
+        let plugins = &Runtime::instance().plugins;
+        plugins.register(name, false, false, plugin_f).await?;
+        plugins.start(name).await
+    }
+}
+
+#[inline]
+fn free_addr() -> Result<SocketAddr> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?)
+}
+
+///Hook event recorded by `HookRecorder`
+#[derive(Debug, Clone)]
+pub struct HookEvent {
+    pub typ: Type,
+    pub clientid: Option<ClientId>,
+    ///Topic of a message, or topic filter of a subscription
+    pub topic: Option<TopicName>,
+    pub payload: Option<bytes::Bytes>,
+    pub reason: Option<String>,
+}
+
+impl HookEvent {
+    fn new(param: &Parameter) -> Self {
+        let mut ev =
+            HookEvent { typ: param.get_type(), clientid: None, topic: None, payload: None, reason: None };
+        match param {
+            Parameter::SessionCreated(s, _)
+            | Parameter::SessionResumed(s, _, _)
+            | Parameter::ClientConnected(s)
+            | Parameter::ClientKeepaliveTimeout(s)
+            | Parameter::ClientAlive(s, _)
+            | Parameter::PacketReceived(s, _)
+            | Parameter::PacketSent(s, _)
+            | Parameter::OfflineInflightMessages(s, _) => ev.clientid = Some(s.id.client_id.clone()),
+            Parameter::SessionTerminated(s, r) | Parameter::ClientDisconnected(s, r) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.reason = Some(r.to_string());
+            }
+            Parameter::ClientProtocolViolation(s, r) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.reason = Some(r.to_string());
+            }
+            Parameter::SessionSubscribed(s, sub) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.topic = Some(sub.topic_filter.clone());
+            }
+            Parameter::ClientSubscribe(s, sub) | Parameter::ClientSubscribeCheckAcl(s, sub) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.topic = Some(sub.topic_filter.clone());
+            }
+            Parameter::SessionUnsubscribed(s, unsub) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.topic = Some(unsub.topic_filter.clone());
+            }
+            Parameter::ClientUnsubscribe(s, unsub) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.topic = Some(unsub.topic_filter.clone());
+            }
+            Parameter::ClientConnect(c)
+            | Parameter::ClientConnack(c, _, _)
+            | Parameter::ClientAuthenticate(c)
+            | Parameter::ClientQuotaCheck(c, _) => ev.clientid = Some(c.client_id().clone()),
+            Parameter::MessagePublishCheckAcl(s, p)
+            | Parameter::MessageDelivered(s, _, p)
+            | Parameter::MessageAcked(s, _, p)
+            | Parameter::MessageExpiryCheck(s, _, p)
+            | Parameter::OfflineMessage(s, _, p) => {
+                ev.clientid = Some(s.id.client_id.clone());
+                ev.topic = Some(p.topic.clone());
+                ev.payload = Some(p.payload.clone());
+            }
+            Parameter::MessagePublish(_, f, p) => {
+                ev.clientid = Some(f.id.client_id.clone());
+                ev.topic = Some(p.topic.clone());
+                ev.payload = Some(p.payload.clone());
+            }
+            Parameter::MessageDropped(to, _, p, r) => {
+                ev.clientid = to.as_ref().map(|to| to.client_id.clone());
+                ev.topic = Some(p.topic.clone());
+                ev.payload = Some(p.payload.clone());
+                ev.reason = Some(r.to_string());
+            }
+            Parameter::MessageNonsubscribed(f) => ev.clientid = Some(f.id.client_id.clone()),
+            Parameter::BeforeStartup | Parameter::GrpcMessageReceived(_, _) => {}
+        }
+        ev
+    }
+
+    #[inline]
+    pub fn clientid_is(&self, clientid: &str) -> bool {
+        self.clientid.as_ref().map(|c| c.as_ref() == clientid).unwrap_or(false)
+    }
+
+    #[inline]
+    pub fn topic_is(&self, topic: &str) -> bool {
+        self.topic.as_ref().map(|t| t.as_ref() == topic).unwrap_or(false)
+    }
+}
+
+struct Events {
+    history: Mutex<Vec<HookEvent>>,
+    tx: broadcast::Sender<HookEvent>,
+}
+
+///Records the hook events of the given types, tests can wait for an event or inspect the history
+pub struct HookRecorder {
+    events: Arc<Events>,
+    register: Box<dyn Register>,
+}
+
+impl HookRecorder {
+    ///Registers a handler with the lowest priority for each type, the results of the other handlers
+    ///are passed through unchanged
+    pub async fn register(types: &[Type]) -> Self {
+        let (tx, _) = broadcast::channel(1024);
+        let events = Arc::new(Events { history: Mutex::new(Vec::new()), tx });
+        let register = Runtime::instance().extends.hook_mgr().await.register();
+        for typ in types {
+            register.add_priority(*typ, 0, Box::new(RecordHandler { events: events.clone() })).await;
+        }
+        register.start().await;
+        Self { events, register }
+    }
+
+    ///Events recorded so far, in the order they occurred
+    #[inline]
+    pub fn events(&self) -> Vec<HookEvent> {
+        self.events.history.lock().unwrap().clone()
+    }
+
+    ///Waits for an event of the type that matches `f`, including the already recorded events
+    pub async fn wait_for<F>(&self, typ: Type, f: F) -> Result<HookEvent>
+    where
+        F: Fn(&HookEvent) -> bool,
+    {
+        let matched = |ev: &HookEvent| ev.typ == typ && f(ev);
+        let mut rx = self.events.tx.subscribe();
+        if let Some(ev) = self.events.history.lock().unwrap().iter().find(|ev| matched(ev)) {
+            return Ok(ev.clone());
+        }
+        let wait = async {
+            loop {
+                match rx.recv().await {
+                    Ok(ev) if matched(&ev) => return Ok(ev),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(e) => return Err(MqttError::from(e.to_string())),
+                }
+            }
+        };
+        tokio::time::timeout(WAIT_TIMEOUT, wait)
+            .await
+            .map_err(|_| MqttError::from(format!("timeout waiting for hook event {:?}", typ)))?
+    }
+
+    ///Waits until a message of the topic is delivered to the client
+    #[inline]
+    pub async fn wait_delivered(&self, clientid: &str, topic: &str) -> Result<HookEvent> {
+        self.wait_for(Type::MessageDelivered, |ev| ev.clientid_is(clientid) && ev.topic_is(topic)).await
+    }
+
+    ///Unregisters the handlers
+    #[inline]
+    pub async fn stop(&self) {
+        self.register.stop().await;
+    }
+}
+
+struct RecordHandler {
+    events: Arc<Events>,
+}
+
+#[async_trait]
+impl Handler for RecordHandler {
+    async fn hook(&self, param: &Parameter, acc: Option<HookResult>) -> ReturnType {
+        let ev = HookEvent::new(param);
+        self.events.history.lock().unwrap().push(ev.clone());
+        let _ = self.events.tx.send(ev);
+        (Proceed::Continue, acc)
+    }
+}